@@ -0,0 +1,31 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use nectar::float_maths::{divide_pow_ten_trunc, multiply_pow_ten, truncate};
+use num::BigUint;
+
+fn bench_truncate(c: &mut Criterion) {
+    c.bench_function("truncate", |b| {
+        b.iter(|| truncate(black_box(9_123.456_789_123_456), black_box(9)))
+    });
+}
+
+fn bench_multiply_pow_ten(c: &mut Criterion) {
+    c.bench_function("multiply_pow_ten", |b| {
+        b.iter(|| multiply_pow_ten(black_box("9_123.456_789_123"), black_box(18)))
+    });
+}
+
+fn bench_divide_pow_ten_trunc(c: &mut Criterion) {
+    let uint = BigUint::from(9_123_456_789_123_456_789u64);
+
+    c.bench_function("divide_pow_ten_trunc", |b| {
+        b.iter(|| divide_pow_ten_trunc(black_box(uint.clone()), black_box(9)))
+    });
+}
+
+criterion_group!(
+    benches,
+    bench_truncate,
+    bench_multiply_pow_ten,
+    bench_divide_pow_ten_trunc
+);
+criterion_main!(benches);
@@ -0,0 +1,250 @@
+//! Notify the operator of events that require their attention.
+//!
+//! Alerts are posted to the Telegram chat, Slack webhook and/or generic
+//! webhook configured in the `[alerting]` section, which can also be named
+//! `[notifications]`. Sending is best effort: failures are logged but never
+//! interrupt trading.
+
+use crate::{
+    bitcoin,
+    config::{Alerting, MinBalance, Slack, Telegram, Webhook},
+    ethereum::{dai, ether},
+    swap::RefundCause,
+    SwapId,
+};
+use serde::Serialize;
+use std::{fmt, time::Duration};
+
+#[derive(Debug, Clone)]
+pub enum Alert {
+    SwapRefunded { swap_id: SwapId, cause: RefundCause },
+    SwapFailed { swap_id: SwapId, error: String },
+    StaleRate { error: String },
+    LowBitcoinBalance { balance: bitcoin::Amount },
+    BalanceBelowMinimum { balance: String, minimum: String },
+    LowGas { balance: ether::Amount },
+    NodeUnreachable { ledger: &'static str, error: String },
+    MainLoopStalled { stalled_for: Duration },
+    RateMovedTooFast,
+}
+
+impl fmt::Display for Alert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Alert::SwapRefunded { swap_id, cause } => {
+                write!(f, "Swap {} was refunded, {}", swap_id, cause)
+            }
+            Alert::SwapFailed { swap_id, error } => {
+                write!(f, "Swap {} failed: {}", swap_id, error)
+            }
+            Alert::StaleRate { error } => write!(
+                f,
+                "Mid-market rate is unavailable, no orders are published: {}",
+                error
+            ),
+            Alert::LowBitcoinBalance { balance } => write!(
+                f,
+                "Bitcoin balance of {} does not cover the maximum possible fee, no sell orders are published",
+                balance
+            ),
+            Alert::BalanceBelowMinimum { balance, minimum } => write!(
+                f,
+                "Balance of {} is below the minimum of {}",
+                balance, minimum
+            ),
+            Alert::LowGas { balance } => write!(
+                f,
+                "Ether balance of {} is too low to pay for the gas of another swap, no orders are published",
+                balance
+            ),
+            Alert::NodeUnreachable { ledger, error } => {
+                write!(f, "{} node is unreachable: {}", ledger, error)
+            }
+            Alert::MainLoopStalled { stalled_for } => write!(
+                f,
+                "Trade loop has not processed any event for {} seconds",
+                stalled_for.as_secs()
+            ),
+            Alert::RateMovedTooFast => write!(
+                f,
+                "Mid-market rate moved too fast, no orders are published until it calms down"
+            ),
+        }
+    }
+}
+
+impl Alert {
+    /// Identifies the kind of alert in the webhook payload.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Alert::SwapRefunded { .. } => "swap_refunded",
+            Alert::SwapFailed { .. } => "swap_failed",
+            Alert::StaleRate { .. } => "stale_rate",
+            Alert::LowBitcoinBalance { .. } => "low_bitcoin_balance",
+            Alert::BalanceBelowMinimum { .. } => "balance_below_minimum",
+            Alert::LowGas { .. } => "low_gas",
+            Alert::NodeUnreachable { .. } => "node_unreachable",
+            Alert::MainLoopStalled { .. } => "main_loop_stalled",
+            Alert::RateMovedTooFast => "rate_moved_too_fast",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Alerter {
+    client: reqwest::Client,
+    telegram: Option<Telegram>,
+    slack: Option<Slack>,
+    webhook: Option<Webhook>,
+    min_balance: MinBalance,
+}
+
+impl Alerter {
+    pub fn new(alerting: Alerting) -> Self {
+        Alerter {
+            client: reqwest::Client::new(),
+            telegram: alerting.telegram,
+            slack: alerting.slack,
+            webhook: alerting.webhook,
+            min_balance: alerting.min_balance.unwrap_or_default(),
+        }
+    }
+
+    /// Alert if the bitcoin balance fell below the configured minimum since
+    /// the `previous` balance.
+    pub fn check_bitcoin_balance(
+        &self,
+        previous: Option<bitcoin::Amount>,
+        balance: bitcoin::Amount,
+    ) {
+        if let Some(minimum) = self.min_balance.bitcoin {
+            if falls_below(previous.as_ref(), &balance, &minimum) {
+                self.notify(Alert::BalanceBelowMinimum {
+                    balance: balance.to_string(),
+                    minimum: minimum.to_string(),
+                });
+            }
+        }
+    }
+
+    /// Alert if the dai balance fell below the configured minimum since the
+    /// `previous` balance.
+    pub fn check_dai_balance(&self, previous: Option<&dai::Amount>, balance: &dai::Amount) {
+        if let Some(minimum) = &self.min_balance.dai {
+            if falls_below(previous, balance, minimum) {
+                self.notify(Alert::BalanceBelowMinimum {
+                    balance: balance.to_string(),
+                    minimum: minimum.to_string(),
+                });
+            }
+        }
+    }
+
+    /// Send the alert in the background to all configured channels.
+    pub fn notify(&self, alert: Alert) {
+        tracing::warn!("Alert: {}", alert);
+
+        if self.telegram.is_none() && self.slack.is_none() && self.webhook.is_none() {
+            return;
+        }
+
+        let alerter = self.clone();
+        let text = format!("nectar: {}", alert);
+        let kind = alert.kind();
+        tokio::spawn(async move {
+            if let Some(telegram) = &alerter.telegram {
+                if let Err(e) = alerter.send_telegram(telegram, &text).await {
+                    tracing::error!("Could not send alert to Telegram: {:#}", e);
+                }
+            }
+            if let Some(slack) = &alerter.slack {
+                if let Err(e) = alerter.send_slack(slack, &text).await {
+                    tracing::error!("Could not send alert to Slack: {:#}", e);
+                }
+            }
+            if let Some(webhook) = &alerter.webhook {
+                if let Err(e) = alerter.send_webhook(webhook, kind, &text).await {
+                    tracing::error!("Could not send alert to webhook: {:#}", e);
+                }
+            }
+        });
+    }
+
+    async fn send_telegram(&self, telegram: &Telegram, text: &str) -> anyhow::Result<()> {
+        #[derive(Serialize)]
+        struct SendMessage<'a> {
+            chat_id: &'a str,
+            text: &'a str,
+        }
+
+        self.client
+            .post(&format!(
+                "https://api.telegram.org/bot{}/sendMessage",
+                telegram.bot_token
+            ))
+            .json(&SendMessage {
+                chat_id: &telegram.chat_id,
+                text,
+            })
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+
+    async fn send_slack(&self, slack: &Slack, text: &str) -> anyhow::Result<()> {
+        #[derive(Serialize)]
+        struct Message<'a> {
+            text: &'a str,
+        }
+
+        self.client
+            .post(slack.webhook_url.clone())
+            .json(&Message { text })
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+
+    async fn send_webhook(&self, webhook: &Webhook, kind: &str, text: &str) -> anyhow::Result<()> {
+        #[derive(Serialize)]
+        struct Payload<'a> {
+            alert: &'a str,
+            text: &'a str,
+        }
+
+        self.client
+            .post(webhook.url.clone())
+            .json(&Payload { alert: kind, text })
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
+
+/// Whether `balance` is below `minimum` while `previous` was not, an unknown
+/// previous balance is not considered below.
+fn falls_below<A: PartialOrd>(previous: Option<&A>, balance: &A, minimum: &A) -> bool {
+    let was_below = previous.map_or(false, |previous| previous < minimum);
+
+    balance < minimum && !was_below
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alerts_once_when_the_balance_falls_below_the_minimum() {
+        assert!(falls_below(Some(&10), &4, &5));
+        assert!(falls_below(None, &4, &5));
+        assert!(!falls_below(Some(&4), &3, &5));
+        assert!(!falls_below(Some(&4), &5, &5));
+        assert!(!falls_below(Some(&10), &6, &5));
+    }
+}
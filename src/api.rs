@@ -0,0 +1,513 @@
+//! HTTP API exposing the state of nectar.
+//!
+//! The trade loop owns the maker, hence it pushes a snapshot of the maker's
+//! state after each event. Swaps and history are read from the database and
+//! the history file on each request, as are the balance snapshots recorded
+//! for accounting, the audit log of our published orders and the records of
+//! refunded swaps. `/metrics` serves the spread captured by trades and how
+//! swaps ended in the Prometheus text format.
+//!
+//! `/healthz` and `/readyz` are meant for liveness and readiness probes, they
+//! answer with `503 Service Unavailable` when the check fails. So does
+//! `/health`, reporting the connectivity to bitcoind and geth, the freshness
+//! of the rate feed and the writability of the database as last probed in the
+//! background, see [`crate::health`].
+//!
+//! `POST /trading/pause` and `POST /trading/resume` are forwarded to the trade
+//! loop, e.g. to stop quoting during the maintenance of a node. Swaps already
+//! in flight are executed regardless. `POST /trading/maintenance` pauses as
+//! well but takes are declined for maintenance, until resumed.
+//!
+//! `/events` is a websocket pushing the published and taken orders, the state
+//! transitions of swaps and the balance updates as JSON messages, see
+//! [`Event`].
+
+use crate::{
+    bitcoin,
+    config::HistoryFormat,
+    ethereum::{self, dai, ChainId},
+    health, history,
+    maker::Maker,
+    metrics::Metrics,
+    order::BtcDaiOrderForm,
+    swap::{Database, SwapKind},
+    Rate,
+};
+use comit::Position;
+use futures::{channel::mpsc::UnboundedSender, FutureExt, SinkExt, StreamExt};
+use libp2p::PeerId;
+use serde::Serialize;
+use std::{
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+use tokio::sync::broadcast::{self, RecvError};
+use warp::{
+    http::StatusCode,
+    reply::Response,
+    ws::{self, WebSocket},
+    Filter, Reply,
+};
+
+mod events;
+
+pub use events::{Event, Events, Message, SwapState};
+
+/// The trade loop handles rate and balance updates every 15 seconds, not
+/// having processed any event for this long means it is stuck.
+const MAIN_LOOP_STALL_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Debug)]
+pub struct State {
+    maker: Arc<RwLock<Option<MakerSnapshot>>>,
+    db: Arc<Database>,
+    history_file: PathBuf,
+    history_format: HistoryFormat,
+    metrics: Metrics,
+    peer_id: PeerId,
+    bitcoin_network: bitcoin::Network,
+    ethereum_chain: ethereum::Chain,
+    control: UnboundedSender<Control>,
+    events: Events,
+    health: health::Health,
+}
+
+/// Request to the trade loop.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Control {
+    PauseTrading,
+    EnterMaintenance,
+    ResumeTrading,
+}
+
+#[derive(Clone, Debug)]
+struct MakerSnapshot {
+    mid_market_rate: Option<Rate>,
+    btc_balance: Option<bitcoin::Amount>,
+    dai_balance: Option<dai::Amount>,
+    btc_reserved_funds: bitcoin::Amount,
+    dai_reserved_funds: dai::Amount,
+    sell_orders: Vec<BtcDaiOrderForm>,
+    buy_orders: Vec<BtcDaiOrderForm>,
+    paused: bool,
+    maintenance: bool,
+    halted: bool,
+    taken_at: Instant,
+}
+
+impl State {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        db: Arc<Database>,
+        history_file: PathBuf,
+        history_format: HistoryFormat,
+        metrics: Metrics,
+        peer_id: PeerId,
+        bitcoin_network: bitcoin::Network,
+        ethereum_chain: ethereum::Chain,
+        control: UnboundedSender<Control>,
+        events: Events,
+        health: health::Health,
+    ) -> Self {
+        State {
+            maker: Arc::new(RwLock::new(None)),
+            db,
+            history_file,
+            history_format,
+            metrics,
+            peer_id,
+            bitcoin_network,
+            ethereum_chain,
+            control,
+            events,
+            health,
+        }
+    }
+
+    /// Record the current state of the maker, the orders are the ones the
+    /// maker would publish given this state, none while trading is paused.
+    pub fn update_maker(&self, maker: &Maker) {
+        let published = |orders: anyhow::Result<Vec<BtcDaiOrderForm>>| {
+            if maker.is_paused() || maker.is_halted() {
+                Vec::new()
+            } else {
+                orders.unwrap_or_default()
+            }
+        };
+        let snapshot = MakerSnapshot {
+            mid_market_rate: maker.mid_market_rate().map(Rate::from),
+            btc_balance: maker.btc_balance(),
+            dai_balance: maker.dai_balance(),
+            btc_reserved_funds: maker.btc_reserved_funds,
+            dai_reserved_funds: maker.dai_reserved_funds.clone(),
+            sell_orders: published(maker.new_sell_orders()),
+            buy_orders: published(maker.new_buy_orders()),
+            paused: maker.is_paused(),
+            maintenance: maker.is_in_maintenance(),
+            halted: maker.is_halted(),
+            taken_at: Instant::now(),
+        };
+
+        match self.maker.write() {
+            Ok(mut maker) => *maker = Some(snapshot),
+            Err(_) => tracing::error!("Maker snapshot lock is poisoned"),
+        }
+    }
+
+    fn maker_snapshot(&self) -> anyhow::Result<MakerSnapshot> {
+        self.maker
+            .read()
+            .map_err(|_| anyhow::anyhow!("Maker snapshot lock is poisoned"))?
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("Maker is not initialised yet"))
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Status {
+    peer_id: String,
+    bitcoin_network: String,
+    ethereum_chain_id: ChainId,
+    mid_market_rate: Option<String>,
+    trading_paused: bool,
+    /// Whether trading is paused for maintenance
+    maintenance: bool,
+    /// Whether the circuit breaker halts trading
+    trading_halted: bool,
+    active_swaps: usize,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Order {
+    position: String,
+    quantity: String,
+    quote: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Swap {
+    swap_id: String,
+    protocol: &'static str,
+    taker: String,
+    bitcoin: String,
+    dai: String,
+    start_of_swap: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Balances {
+    bitcoin: Option<String>,
+    dai: Option<String>,
+    bitcoin_reserved: String,
+    dai_reserved: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Health {
+    main_loop_ticking: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct Readiness {
+    bitcoin_node_reachable: bool,
+    ethereum_node_reachable: bool,
+    rate_fresh: bool,
+    seed_loaded: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct Error {
+    error: String,
+}
+
+impl From<BtcDaiOrderForm> for Order {
+    fn from(order: BtcDaiOrderForm) -> Self {
+        Order {
+            position: match order.position {
+                Position::Buy => "buy".to_owned(),
+                Position::Sell => "sell".to_owned(),
+            },
+            quantity: bitcoin::Amount::from(order.quantity).to_string(),
+            quote: dai::Amount::from(order.quote()).to_string(),
+        }
+    }
+}
+
+impl From<SwapKind> for Swap {
+    fn from(swap: SwapKind) -> Self {
+        let protocol = match swap {
+            SwapKind::HbitHerc20(_) => "hbit-herc20",
+            SwapKind::Herc20Hbit(_) => "herc20-hbit",
+        };
+        let params = swap.params();
+
+        Swap {
+            swap_id: params.swap_id.to_string(),
+            protocol,
+            taker: params.taker.peer_id().to_string(),
+            bitcoin: bitcoin::Amount::from(params.hbit_params.shared.asset).to_string(),
+            dai: dai::Amount::from(params.herc20_params.asset).to_string(),
+            start_of_swap: params.start_of_swap.to_rfc3339(),
+        }
+    }
+}
+
+/// Serve the API on `listen` until the process stops.
+pub async fn serve(listen: SocketAddr, state: State) -> anyhow::Result<()> {
+    let state = warp::any().map(move || state.clone());
+
+    let status = warp::path!("status").and(state.clone()).map(status);
+    let orders = warp::path!("orders").and(state.clone()).map(orders);
+    let order_audit = warp::path!("orders" / "audit")
+        .and(state.clone())
+        .map(order_audit);
+    let swaps = warp::path!("swaps").and(state.clone()).map(swaps);
+    let refunds = warp::path!("swaps" / "refunds")
+        .and(state.clone())
+        .map(refunds);
+    let balances = warp::path!("balances").and(state.clone()).map(balances);
+    let balance_snapshots = warp::path!("balances" / "snapshots")
+        .and(state.clone())
+        .map(balance_snapshots);
+    let history = warp::path!("history").and(state.clone()).map(history);
+    let metrics = warp::path!("metrics").and(state.clone()).map(metrics);
+    let healthz = warp::path!("healthz").and(state.clone()).map(healthz);
+    let readyz = warp::path!("readyz").and(state.clone()).map(readyz);
+    let health = warp::path!("health").and(state.clone()).map(health);
+    let pause = warp::path!("trading" / "pause")
+        .and(state.clone())
+        .map(|state| control(state, Control::PauseTrading));
+    let resume = warp::path!("trading" / "resume")
+        .and(state.clone())
+        .map(|state| control(state, Control::ResumeTrading));
+    let maintenance = warp::path!("trading" / "maintenance")
+        .and(state.clone())
+        .map(|state| control(state, Control::EnterMaintenance));
+    let events =
+        warp::path!("events")
+            .and(warp::ws())
+            .and(state)
+            .map(|websocket: ws::Ws, state: State| {
+                let subscription = state.events.subscribe();
+                websocket.on_upgrade(move |socket| stream_events(socket, subscription))
+            });
+
+    let routes = warp::get()
+        .and(
+            status
+                .or(orders)
+                .or(order_audit)
+                .or(swaps)
+                .or(refunds)
+                .or(balances)
+                .or(balance_snapshots)
+                .or(history)
+                .or(metrics)
+                .or(healthz)
+                .or(readyz)
+                .or(health),
+        )
+        .or(warp::post().and(pause.or(resume).or(maintenance)))
+        .or(events);
+
+    let (address, server) = warp::serve(routes).try_bind_ephemeral(listen)?;
+    tracing::info!("HTTP API listening on {}", address);
+    server.await;
+
+    Ok(())
+}
+
+fn status(state: State) -> Response {
+    into_response(get_status(&state))
+}
+
+fn get_status(state: &State) -> anyhow::Result<Status> {
+    let snapshot = state.maker_snapshot()?;
+
+    Ok(Status {
+        peer_id: state.peer_id.to_string(),
+        bitcoin_network: state.bitcoin_network.to_string(),
+        ethereum_chain_id: state.ethereum_chain.chain_id(),
+        mid_market_rate: snapshot.mid_market_rate.map(|rate| rate.to_string()),
+        trading_paused: snapshot.paused,
+        maintenance: snapshot.maintenance,
+        trading_halted: snapshot.halted,
+        active_swaps: state.db.all_swaps()?.len(),
+    })
+}
+
+fn orders(state: State) -> Response {
+    into_response(state.maker_snapshot().map(|snapshot| {
+        snapshot
+            .sell_orders
+            .into_iter()
+            .chain(snapshot.buy_orders)
+            .map(Order::from)
+            .collect::<Vec<_>>()
+    }))
+}
+
+fn order_audit(state: State) -> Response {
+    into_response(state.db.order_audit_entries())
+}
+
+fn swaps(state: State) -> Response {
+    into_response(
+        state
+            .db
+            .all_swaps()
+            .map(|swaps| swaps.into_iter().map(Swap::from).collect::<Vec<_>>()),
+    )
+}
+
+fn refunds(state: State) -> Response {
+    into_response(state.db.refunds())
+}
+
+fn balances(state: State) -> Response {
+    into_response(state.maker_snapshot().map(|snapshot| Balances {
+        bitcoin: snapshot.btc_balance.map(|balance| balance.to_string()),
+        dai: snapshot.dai_balance.map(|balance| balance.to_string()),
+        bitcoin_reserved: snapshot.btc_reserved_funds.to_string(),
+        dai_reserved: snapshot.dai_reserved_funds.to_string(),
+    }))
+}
+
+fn balance_snapshots(state: State) -> Response {
+    into_response(state.db.balance_snapshots())
+}
+
+fn history(state: State) -> Response {
+    into_response(history::read_records(
+        &state.history_file,
+        state.history_format,
+    ))
+}
+
+fn metrics(state: State) -> Response {
+    match state
+        .db
+        .swap_outcomes()
+        .and_then(|swap_outcomes| state.metrics.render(&swap_outcomes))
+    {
+        Ok(metrics) => {
+            warp::reply::with_header(metrics, "content-type", "text/plain; version=0.0.4")
+                .into_response()
+        }
+        Err(e) => into_response::<()>(Err(e)),
+    }
+}
+
+fn healthz(state: State) -> Response {
+    let main_loop_ticking = state
+        .maker_snapshot()
+        .map(|snapshot| snapshot.taken_at.elapsed() < MAIN_LOOP_STALL_TIMEOUT)
+        .unwrap_or(false);
+
+    into_probe_response(main_loop_ticking, Health { main_loop_ticking })
+}
+
+fn readyz(state: State) -> Response {
+    // Balances and rate are invalidated by the trade loop when fetching them
+    // fails. The seed is loaded before the API is started.
+    let readiness = match state.maker_snapshot() {
+        Ok(snapshot) => Readiness {
+            bitcoin_node_reachable: snapshot.btc_balance.is_some(),
+            ethereum_node_reachable: snapshot.dai_balance.is_some(),
+            rate_fresh: snapshot.mid_market_rate.is_some(),
+            seed_loaded: true,
+        },
+        Err(_) => Readiness {
+            bitcoin_node_reachable: false,
+            ethereum_node_reachable: false,
+            rate_fresh: false,
+            seed_loaded: true,
+        },
+    };
+    let ready = readiness.bitcoin_node_reachable
+        && readiness.ethereum_node_reachable
+        && readiness.rate_fresh
+        && readiness.seed_loaded;
+
+    into_probe_response(ready, readiness)
+}
+
+fn health(state: State) -> Response {
+    let report = state.health.report();
+
+    into_probe_response(report.healthy(), report)
+}
+
+fn control(state: State, control: Control) -> Response {
+    match state.control.unbounded_send(control) {
+        Ok(()) => StatusCode::ACCEPTED.into_response(),
+        Err(_) => into_response::<()>(Err(anyhow::anyhow!("Trade loop is not running"))),
+    }
+}
+
+/// Forward the events to the subscriber until it disconnects. Incoming
+/// messages are only read to notice the disconnection.
+async fn stream_events(socket: WebSocket, mut subscription: broadcast::Receiver<Message>) {
+    let (mut outgoing, mut incoming) = socket.split();
+
+    loop {
+        let message = futures::select! {
+            message = subscription.recv().fuse() => Some(message),
+            incoming = incoming.next().fuse() => match incoming {
+                Some(Ok(_)) => None,
+                Some(Err(_)) | None => return,
+            },
+        };
+        let message = match message {
+            Some(Ok(message)) => message,
+            None => continue,
+            Some(Err(RecvError::Lagged(skipped))) => {
+                tracing::warn!(
+                    "Events subscriber lagged behind, {} events dropped",
+                    skipped
+                );
+                continue;
+            }
+            Some(Err(RecvError::Closed)) => return,
+        };
+
+        let text = match serde_json::to_string(&message) {
+            Ok(text) => text,
+            Err(e) => {
+                tracing::error!("Could not serialize event: {:#}", e);
+                continue;
+            }
+        };
+        if outgoing.send(ws::Message::text(text)).await.is_err() {
+            return;
+        }
+    }
+}
+
+fn into_probe_response<T: Serialize>(ok: bool, body: T) -> Response {
+    let status = if ok {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    warp::reply::with_status(warp::reply::json(&body), status).into_response()
+}
+
+fn into_response<T: Serialize>(result: anyhow::Result<T>) -> Response {
+    match result {
+        Ok(body) => warp::reply::json(&body).into_response(),
+        Err(e) => {
+            tracing::warn!("HTTP API request failed: {:#}", e);
+            warp::reply::with_status(
+                warp::reply::json(&Error {
+                    error: format!("{:#}", e),
+                }),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+            .into_response()
+        }
+    }
+}
@@ -0,0 +1,171 @@
+//! Real-time events of the trade loop, streamed to the subscribers of the
+//! `/events` websocket as JSON messages.
+
+use super::{Balances, Order};
+use crate::{history, maker::Maker, SwapId};
+use chrono::{DateTime, Utc};
+use libp2p::PeerId;
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+/// Events not yet sent to a subscriber when this many more are published are
+/// dropped for that subscriber.
+const EVENT_BUFFER: usize = 256;
+
+/// Publishes events to the current subscribers, cheap to clone.
+#[derive(Clone, Debug)]
+pub struct Events(broadcast::Sender<Message>);
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Message {
+    timestamp: DateTime<Utc>,
+    #[serde(flatten)]
+    event: Event,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    OrderPublished(Order),
+    OrderTaken {
+        swap_id: String,
+        taker: String,
+        #[serde(flatten)]
+        order: Order,
+    },
+    SwapStateChanged {
+        swap_id: String,
+        state: SwapState,
+    },
+    BalanceUpdated(Balances),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SwapState {
+    /// Waiting for one of the concurrent swap slots
+    Queued,
+    Executing,
+    Redeemed,
+    Refunded,
+    Failed,
+}
+
+impl Events {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUFFER);
+        Events(sender)
+    }
+
+    pub fn publish(&self, event: Event) {
+        // Fails if nobody is subscribed, which is fine
+        let _ = self.0.send(Message {
+            timestamp: Utc::now(),
+            event,
+        });
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Message> {
+        self.0.subscribe()
+    }
+}
+
+impl Default for Events {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Event {
+    pub fn order_taken(swap_id: SwapId, taker: &PeerId, order: Order) -> Self {
+        Event::OrderTaken {
+            swap_id: swap_id.to_string(),
+            taker: taker.to_string(),
+            order,
+        }
+    }
+
+    pub fn swap_state_changed(swap_id: SwapId, state: SwapState) -> Self {
+        Event::SwapStateChanged {
+            swap_id: swap_id.to_string(),
+            state,
+        }
+    }
+
+    /// The balances and reserved funds as currently known by the maker.
+    pub fn balance_updated(maker: &Maker) -> Self {
+        Event::BalanceUpdated(Balances {
+            bitcoin: maker.btc_balance().map(|balance| balance.to_string()),
+            dai: maker.dai_balance().map(|balance| balance.to_string()),
+            bitcoin_reserved: maker.btc_reserved_funds.to_string(),
+            dai_reserved: maker.dai_reserved_funds.to_string(),
+        })
+    }
+}
+
+impl From<history::Outcome> for SwapState {
+    fn from(outcome: history::Outcome) -> Self {
+        match outcome {
+            history::Outcome::Redeemed => SwapState::Redeemed,
+            history::Outcome::Refunded => SwapState::Refunded,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn events_are_tagged_and_flattened() {
+        let message = Message {
+            timestamp: DateTime::parse_from_rfc3339("2020-07-10T08:00:00Z")
+                .unwrap()
+                .with_timezone(&Utc),
+            event: Event::OrderTaken {
+                swap_id: "ad2652ca-ecf2-4cc6-b35c-b4351ac28a34".to_owned(),
+                taker: "QmUJF1AzhjUfDU1ifzkyuHy26SCnNHbPaVHpX1WYxYYgZg".to_owned(),
+                order: Order {
+                    position: "sell".to_owned(),
+                    quantity: "0.01 BTC".to_owned(),
+                    quote: "99 DAI".to_owned(),
+                },
+            },
+        };
+
+        assert_eq!(
+            serde_json::to_value(&message).unwrap(),
+            json!({
+                "timestamp": "2020-07-10T08:00:00Z",
+                "event": "order_taken",
+                "swap_id": "ad2652ca-ecf2-4cc6-b35c-b4351ac28a34",
+                "taker": "QmUJF1AzhjUfDU1ifzkyuHy26SCnNHbPaVHpX1WYxYYgZg",
+                "position": "sell",
+                "quantity": "0.01 BTC",
+                "quote": "99 DAI"
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn subscribers_receive_the_events_published_after_subscribing() {
+        let events = Events::new();
+        events.publish(Event::swap_state_changed(
+            SwapId::default(),
+            SwapState::Queued,
+        ));
+
+        let mut subscriber = events.subscribe();
+        events.publish(Event::swap_state_changed(
+            SwapId::default(),
+            SwapState::Failed,
+        ));
+
+        let message = subscriber.recv().await.unwrap();
+        assert!(matches!(message.event, Event::SwapStateChanged {
+            state: SwapState::Failed,
+            ..
+        }));
+    }
+}
@@ -1,5 +1,7 @@
 pub mod amount;
 mod bitcoind;
+pub mod coin_selection;
+pub mod fee;
 mod wallet;
 
 pub use ::bitcoin::{Address, Network, Txid};
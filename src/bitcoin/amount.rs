@@ -55,6 +55,26 @@ impl Amount {
     pub fn checked_add(self, rhs: Amount) -> Option<Amount> {
         self.0.checked_add(rhs.0).map(Amount)
     }
+
+    /// `None` if `rhs` is greater than `self`.
+    pub fn checked_sub(self, rhs: Amount) -> Option<Amount> {
+        self.0.checked_sub(rhs.0).map(Amount)
+    }
+
+    /// Zero if `rhs` is greater than `self`.
+    pub fn saturating_sub(self, rhs: Amount) -> Amount {
+        self.checked_sub(rhs).unwrap_or_default()
+    }
+
+    /// Rounded down to `decimals` decimal places of bitcoin, unchanged for 8
+    /// or more.
+    pub fn round_down(self, decimals: u8) -> Amount {
+        let unit = 10u64.pow(u32::from(
+            SATS_IN_BITCOIN_EXP.saturating_sub(u16::from(decimals)),
+        ));
+
+        Amount::from_sat(self.as_sat() - self.as_sat() % unit)
+    }
 }
 
 impl std::ops::Add for Amount {
@@ -64,6 +84,7 @@ impl std::ops::Add for Amount {
     }
 }
 
+/// Panics if `rhs` is greater than `self`, see `Amount::checked_sub`.
 impl std::ops::Sub for Amount {
     type Output = Amount;
 
@@ -131,6 +152,13 @@ mod tests {
     use proptest::prelude::*;
     use std::convert::TryFrom;
 
+    #[test]
+    fn checked_sub_fails_on_underflow() {
+        assert_eq!(btc(2.0).checked_sub(btc(0.5)), Some(btc(1.5)));
+        assert_eq!(btc(0.5).checked_sub(btc(2.0)), None);
+        assert_eq!(btc(0.5).saturating_sub(btc(2.0)), Amount::default());
+    }
+
     #[test]
     fn worth_in_1() {
         let btc = Amount::from_btc(1.0).unwrap();
@@ -1,10 +1,15 @@
 use crate::{
     bitcoin::{Address, Amount, Network},
-    jsonrpc,
+    config::{self, NodeAuth},
+    jsonrpc::{self, BasicAuth},
+};
+use ::bitcoin::{
+    consensus::encode::{deserialize, serialize_hex},
+    hashes::hex::FromHex,
+    OutPoint, Transaction, Txid,
 };
-use ::bitcoin::{consensus::encode::serialize_hex, hashes::hex::FromHex, Transaction, Txid};
 use anyhow::Context;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 pub const JSONRPC_VERSION: &str = "1.0";
 
@@ -20,6 +25,24 @@ impl Client {
         }
     }
 
+    pub fn with_auth(self, auth: &NodeAuth) -> anyhow::Result<Self> {
+        Ok(Client {
+            rpc_client: self.rpc_client.with_auth(auth)?,
+        })
+    }
+
+    pub fn with_rpc_config(self, rpc: config::Rpc) -> Self {
+        Client {
+            rpc_client: self.rpc_client.with_rpc_config(rpc),
+        }
+    }
+
+    pub fn with_basic_auth(self, basic_auth: BasicAuth) -> Self {
+        Client {
+            rpc_client: self.rpc_client.with_basic_auth(basic_auth),
+        }
+    }
+
     pub async fn network(&self) -> anyhow::Result<Network> {
         let blockchain_info = self
             .rpc_client
@@ -59,6 +82,29 @@ impl Client {
         Ok(response)
     }
 
+    /// Creates a blank descriptor wallet, only supported by bitcoind 0.21 or
+    /// later.
+    pub async fn create_descriptor_wallet(
+        &self,
+        wallet_name: &str,
+    ) -> anyhow::Result<CreateWalletResponse> {
+        self.rpc_client
+            .send(jsonrpc::Request::new(
+                "createwallet",
+                vec![
+                    jsonrpc::serialize(wallet_name)?,
+                    jsonrpc::serialize(false)?,
+                    jsonrpc::serialize(true)?,
+                    jsonrpc::serialize("")?,
+                    jsonrpc::serialize(false)?,
+                    jsonrpc::serialize(true)?,
+                ],
+                JSONRPC_VERSION.into(),
+            ))
+            .await
+            .context("failed to create descriptor wallet")
+    }
+
     pub async fn rescan(&self, wallet_name: &str) -> anyhow::Result<RescanResponse> {
         let response = self
             .rpc_client
@@ -167,6 +213,9 @@ impl Client {
         wallet_name: &str,
         address: Address,
         amount: Amount,
+        subtract_fee_from_amount: bool,
+        replaceable: bool,
+        conf_target: u16,
     ) -> anyhow::Result<Txid> {
         let txid: String = self
             .rpc_client
@@ -177,6 +226,11 @@ impl Client {
                     vec![
                         jsonrpc::serialize(address)?,
                         jsonrpc::serialize(amount.as_btc())?,
+                        jsonrpc::serialize(Option::<String>::None)?,
+                        jsonrpc::serialize(Option::<String>::None)?,
+                        jsonrpc::serialize(subtract_fee_from_amount)?,
+                        jsonrpc::serialize(replaceable)?,
+                        jsonrpc::serialize(conf_target)?,
                     ],
                     JSONRPC_VERSION.into(),
                 ),
@@ -188,6 +242,175 @@ impl Client {
         Ok(txid)
     }
 
+    /// Funds, without signing nor broadcasting, a transaction sending
+    /// `amount` to `address`. Used to learn the fee of a transaction.
+    pub async fn wallet_create_funded_psbt(
+        &self,
+        wallet_name: &str,
+        address: Address,
+        amount: Amount,
+        subtract_fee_from_amount: bool,
+        conf_target: u16,
+    ) -> anyhow::Result<WalletCreateFundedPsbtResponse> {
+        let subtract_fee_from_outputs = if subtract_fee_from_amount {
+            vec![0]
+        } else {
+            vec![]
+        };
+
+        self.rpc_client
+            .send_with_path(
+                format!("/wallet/{}", wallet_name),
+                jsonrpc::Request::new(
+                    "walletcreatefundedpsbt",
+                    vec![
+                        jsonrpc::serialize(Vec::<serde_json::Value>::new())?,
+                        serde_json::json!([{ address.to_string(): amount.as_btc() }]),
+                        jsonrpc::serialize(0u32)?,
+                        serde_json::json!({
+                            "subtractFeeFromOutputs": subtract_fee_from_outputs,
+                            "conf_target": conf_target,
+                        }),
+                    ],
+                    JSONRPC_VERSION.into(),
+                ),
+            )
+            .await
+            .context("failed to create funded psbt")
+    }
+
+    pub async fn list_unspent(
+        &self,
+        wallet_name: &str,
+        min_conf: u32,
+    ) -> anyhow::Result<Vec<ListUnspentResponse>> {
+        self.rpc_client
+            .send_with_path(
+                format!("/wallet/{}", wallet_name),
+                jsonrpc::Request::new(
+                    "listunspent",
+                    vec![jsonrpc::serialize(min_conf)?],
+                    JSONRPC_VERSION.into(),
+                ),
+            )
+            .await
+            .context("failed to list unspent outputs")
+    }
+
+    pub async fn get_raw_change_address(&self, wallet_name: &str) -> anyhow::Result<Address> {
+        self.rpc_client
+            .send_with_path::<Vec<()>, _>(
+                format!("/wallet/{}", wallet_name),
+                jsonrpc::Request::new("getrawchangeaddress", vec![], JSONRPC_VERSION.into()),
+            )
+            .await
+            .context("failed to get change address")
+    }
+
+    /// Creates, without signing it, a transaction spending `inputs` to
+    /// `outputs`. Returns the transaction in hex.
+    pub async fn create_raw_transaction(
+        &self,
+        inputs: &[OutPoint],
+        outputs: &[(Address, Amount)],
+        replaceable: bool,
+    ) -> anyhow::Result<String> {
+        let inputs = inputs
+            .iter()
+            .map(|input| serde_json::json!({ "txid": input.txid.to_string(), "vout": input.vout }))
+            .collect::<Vec<_>>();
+        let outputs = outputs
+            .iter()
+            .map(|(address, amount)| serde_json::json!({ address.to_string(): amount.as_btc() }))
+            .collect::<Vec<_>>();
+
+        self.rpc_client
+            .send(jsonrpc::Request::new(
+                "createrawtransaction",
+                vec![
+                    serde_json::Value::from(inputs),
+                    serde_json::Value::from(outputs),
+                    serde_json::Value::from(0u32),
+                    serde_json::Value::from(replaceable),
+                ],
+                JSONRPC_VERSION.into(),
+            ))
+            .await
+            .context("failed to create raw transaction")
+    }
+
+    pub async fn sign_raw_transaction_with_wallet(
+        &self,
+        wallet_name: &str,
+        transaction_hex: String,
+    ) -> anyhow::Result<Transaction> {
+        let response: SignRawTransactionResponse = self
+            .rpc_client
+            .send_with_path(
+                format!("/wallet/{}", wallet_name),
+                jsonrpc::Request::new(
+                    "signrawtransactionwithwallet",
+                    vec![transaction_hex],
+                    JSONRPC_VERSION.into(),
+                ),
+            )
+            .await
+            .context("failed to sign raw transaction")?;
+        if !response.complete {
+            anyhow::bail!("the wallet could not sign all the inputs of the transaction");
+        }
+
+        Ok(deserialize(&Vec::<u8>::from_hex(&response.hex)?)?)
+    }
+
+    pub async fn get_transaction(
+        &self,
+        wallet_name: &str,
+        txid: Txid,
+    ) -> anyhow::Result<GetTransactionResponse> {
+        self.rpc_client
+            .send_with_path(
+                format!("/wallet/{}", wallet_name),
+                jsonrpc::Request::new(
+                    "gettransaction",
+                    vec![txid.to_string()],
+                    JSONRPC_VERSION.into(),
+                ),
+            )
+            .await
+            .context("failed to get transaction")
+    }
+
+    /// Replaces an unconfirmed transaction of the wallet signalling
+    /// replace-by-fee by one paying a fee for confirmation within
+    /// `conf_target` blocks, deducted from its change. Returns the id of the
+    /// replacement.
+    pub async fn bump_fee(
+        &self,
+        wallet_name: &str,
+        txid: Txid,
+        conf_target: u16,
+    ) -> anyhow::Result<Txid> {
+        let response: BumpFeeResponse = self
+            .rpc_client
+            .send_with_path(
+                format!("/wallet/{}", wallet_name),
+                jsonrpc::Request::new(
+                    "bumpfee",
+                    vec![
+                        serde_json::Value::from(txid.to_string()),
+                        serde_json::json!({ "conf_target": conf_target }),
+                    ],
+                    JSONRPC_VERSION.into(),
+                ),
+            )
+            .await
+            .context("failed to bump fee")?;
+        let txid = Txid::from_hex(&response.txid)?;
+
+        Ok(txid)
+    }
+
     pub async fn send_raw_transaction(
         &self,
         wallet_name: &str,
@@ -209,6 +432,20 @@ impl Client {
         Ok(txid)
     }
 
+    pub async fn estimate_smart_fee(
+        &self,
+        conf_target: u16,
+    ) -> anyhow::Result<EstimateSmartFeeResponse> {
+        self.rpc_client
+            .send(jsonrpc::Request::new(
+                "estimatesmartfee",
+                vec![jsonrpc::serialize(conf_target)?],
+                JSONRPC_VERSION.into(),
+            ))
+            .await
+            .context("failed to estimate smart fee")
+    }
+
     #[cfg(test)]
     pub async fn dump_wallet(
         &self,
@@ -244,7 +481,6 @@ impl Client {
         Ok(wallets)
     }
 
-    #[allow(dead_code)]
     pub async fn derive_addresses(
         &self,
         descriptor: &str,
@@ -262,6 +498,64 @@ impl Client {
         Ok(addresses)
     }
 
+    pub async fn get_address_info(
+        &self,
+        wallet_name: &str,
+        address: &Address,
+    ) -> anyhow::Result<GetAddressInfoResponse> {
+        self.rpc_client
+            .send_with_path(
+                format!("/wallet/{}", wallet_name),
+                jsonrpc::Request::new(
+                    "getaddressinfo",
+                    vec![jsonrpc::serialize(address)?],
+                    JSONRPC_VERSION.into(),
+                ),
+            )
+            .await
+            .context("failed to get address info")
+    }
+
+    /// Imports the descriptors into the wallet, bitcoind rescans the chain
+    /// from the earliest timestamp of the requests before answering.
+    pub async fn import_multi(
+        &self,
+        wallet_name: &str,
+        requests: Vec<ImportMultiRequest>,
+    ) -> anyhow::Result<Vec<ImportMultiResponse>> {
+        self.rpc_client
+            .send_with_path(
+                format!("/wallet/{}", wallet_name),
+                jsonrpc::Request::new(
+                    "importmulti",
+                    vec![jsonrpc::serialize(requests)?],
+                    JSONRPC_VERSION.into(),
+                ),
+            )
+            .await
+            .context("failed to import descriptors")
+    }
+
+    /// Imports the descriptors into a descriptor wallet, bitcoind rescans the
+    /// chain from the earliest timestamp of the requests before answering.
+    pub async fn import_descriptors(
+        &self,
+        wallet_name: &str,
+        requests: Vec<ImportDescriptorsRequest>,
+    ) -> anyhow::Result<Vec<ImportMultiResponse>> {
+        self.rpc_client
+            .send_with_path(
+                format!("/wallet/{}", wallet_name),
+                jsonrpc::Request::new(
+                    "importdescriptors",
+                    vec![jsonrpc::serialize(requests)?],
+                    JSONRPC_VERSION.into(),
+                ),
+            )
+            .await
+            .context("failed to import descriptors")
+    }
+
     pub async fn get_descriptor_info(
         &self,
         descriptor: &str,
@@ -359,6 +653,100 @@ pub struct GetDescriptorInfoResponse {
     pub has_private_keys: bool,
 }
 
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct EstimateSmartFeeResponse {
+    /// Estimated fee rate in BTC/kvB, absent if there is not enough data
+    #[serde(rename = "feerate")]
+    pub fee_rate: Option<f64>,
+    pub errors: Option<Vec<String>>,
+    pub blocks: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct WalletCreateFundedPsbtResponse {
+    pub psbt: String,
+    /// Fee of the funded transaction in BTC
+    pub fee: f64,
+    #[serde(rename = "changepos")]
+    pub change_position: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ListUnspentResponse {
+    pub txid: String,
+    pub vout: u32,
+    /// Amount of the output in BTC
+    pub amount: f64,
+    pub confirmations: u32,
+    /// Whether bitcoind considers the output safe to spend, i.e. not an
+    /// unconfirmed output of a transaction from another wallet.
+    pub safe: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+struct SignRawTransactionResponse {
+    hex: String,
+    complete: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct GetTransactionResponse {
+    /// Negative if the transaction conflicts with one included that many
+    /// blocks ago.
+    pub confirmations: i64,
+    pub hex: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+struct BumpFeeResponse {
+    txid: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct GetAddressInfoResponse {
+    #[serde(rename = "ismine")]
+    pub is_mine: bool,
+    #[serde(rename = "iswatchonly")]
+    pub is_watch_only: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ImportMultiRequest {
+    /// The descriptor, with its checksum
+    #[serde(rename = "desc")]
+    pub descriptor: String,
+    /// Block time from which the chain is rescanned for transactions
+    pub timestamp: u64,
+    #[serde(rename = "watchonly")]
+    pub watch_only: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ImportDescriptorsRequest {
+    /// The descriptor, with its checksum
+    #[serde(rename = "desc")]
+    pub descriptor: String,
+    /// Block time from which the chain is rescanned for transactions
+    pub timestamp: u64,
+    /// Whether new addresses, or the change addresses if `internal`, are
+    /// derived from the descriptor
+    pub active: bool,
+    pub internal: bool,
+}
+
+/// Also the response of `importdescriptors`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ImportMultiResponse {
+    pub success: bool,
+    pub error: Option<ImportMultiError>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ImportMultiError {
+    pub code: i32,
+    pub message: String,
+}
+
 #[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
 #[serde(untagged)]
 pub enum ScanProgress {
@@ -421,4 +809,38 @@ mod test {
             scanning: ScanProgress::Bool(false)
         })
     }
+
+    #[test]
+    fn encode_import_multi_request() {
+        let request = ImportMultiRequest {
+            descriptor: "addr(bcrt1qxyz)#checksum".to_owned(),
+            timestamp: 0,
+            watch_only: true,
+        };
+
+        assert_eq!(
+            serde_json::to_value(&request).unwrap(),
+            serde_json::json!({
+                "desc": "addr(bcrt1qxyz)#checksum",
+                "timestamp": 0,
+                "watchonly": true
+            })
+        );
+    }
+
+    #[test]
+    fn decode_estimate_smart_fee() {
+        let json = r#"{
+        "errors":["Insufficient data or no feerate found"],
+        "blocks":2
+        }"#;
+
+        let estimate: EstimateSmartFeeResponse = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(estimate, EstimateSmartFeeResponse {
+            fee_rate: None,
+            errors: Some(vec!["Insufficient data or no feerate found".into()]),
+            blocks: 2
+        })
+    }
 }
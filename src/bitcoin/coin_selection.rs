@@ -0,0 +1,289 @@
+//! Selection of the unspent outputs funding our transactions, instead of
+//! deferring to bitcoind, so that the fee is known before sending them.
+//!
+//! The sizes assume P2WPKH inputs and outputs, the addresses of our wallets.
+
+use crate::{bitcoin::Amount, config};
+use ::bitcoin::OutPoint;
+
+/// Virtual size of a transaction without inputs nor outputs.
+const OVERHEAD_VSIZE: u64 = 11;
+const INPUT_VSIZE: u64 = 68;
+const OUTPUT_VSIZE: u64 = 31;
+
+/// Smallest change output if none is configured, smaller change is added to
+/// the fee.
+const DEFAULT_MIN_CHANGE_SAT: u64 = 546;
+
+/// Branch and bound gives up after this many steps and falls back to largest
+/// first.
+const BRANCH_AND_BOUND_MAX_TRIES: usize = 100_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Utxo {
+    pub outpoint: OutPoint,
+    pub amount: Amount,
+    pub confirmations: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selection {
+    pub inputs: Vec<Utxo>,
+    pub fee: Amount,
+    /// No change output if `None`, the excess is added to the fee.
+    pub change: Option<Amount>,
+}
+
+/// Selects the outputs spent to send `target` at `fee_rate` per kvB.
+pub fn select(
+    utxos: &[Utxo],
+    target: Amount,
+    fee_rate: Amount,
+    coin_selection: &config::CoinSelection,
+) -> anyhow::Result<Selection> {
+    let params = Params {
+        target: target.as_sat(),
+        fee_rate: fee_rate.as_sat(),
+        min_change: coin_selection
+            .min_change
+            .map_or(DEFAULT_MIN_CHANGE_SAT, Amount::as_sat),
+    };
+
+    let mut candidates: Vec<Utxo> = utxos
+        .iter()
+        .copied()
+        .filter(|utxo| params.effective_value(utxo) > 0)
+        .collect();
+    candidates.sort_by(|lhs, rhs| rhs.amount.cmp(&lhs.amount));
+
+    let inputs = match coin_selection.strategy {
+        config::CoinSelectionStrategy::BranchAndBound => branch_and_bound(&candidates, &params)
+            .unwrap_or_else(|| largest_first(&candidates, &params)),
+        config::CoinSelectionStrategy::LargestFirst => largest_first(&candidates, &params),
+    };
+
+    params.selection(inputs).ok_or_else(|| {
+        anyhow::anyhow!(
+            "Insufficient funds to send {} BTC at {} sat per kvB",
+            target.as_btc(),
+            fee_rate.as_sat()
+        )
+    })
+}
+
+struct Params {
+    target: u64,
+    fee_rate: u64,
+    min_change: u64,
+}
+
+impl Params {
+    fn fee(&self, vsize: u64) -> u64 {
+        self.fee_rate.saturating_mul(vsize).saturating_add(999) / 1000
+    }
+
+    /// The amount of the output minus the fee of spending it.
+    fn effective_value(&self, utxo: &Utxo) -> i64 {
+        utxo.amount.as_sat() as i64 - self.fee(INPUT_VSIZE) as i64
+    }
+
+    /// What the effective values of the inputs must cover without change.
+    fn target_without_change(&self) -> u64 {
+        self.target + self.fee(OVERHEAD_VSIZE + OUTPUT_VSIZE)
+    }
+
+    /// What the effective values of the inputs must cover with a change
+    /// output of at least the minimum.
+    fn target_with_change(&self) -> u64 {
+        self.target + self.fee(OVERHEAD_VSIZE + 2 * OUTPUT_VSIZE) + self.min_change
+    }
+
+    fn selection(&self, inputs: Vec<Utxo>) -> Option<Selection> {
+        let effective_value: i64 = inputs.iter().map(|utxo| self.effective_value(utxo)).sum();
+        if effective_value < self.target_without_change() as i64 {
+            return None;
+        }
+
+        let total: u64 = inputs.iter().map(|utxo| utxo.amount.as_sat()).sum();
+        let change = if effective_value >= self.target_with_change() as i64 {
+            let fee =
+                self.fee(OVERHEAD_VSIZE + 2 * OUTPUT_VSIZE + INPUT_VSIZE * inputs.len() as u64);
+            Some(total - self.target - fee)
+        } else {
+            None
+        };
+        let fee = total - self.target - change.unwrap_or(0);
+
+        Some(Selection {
+            inputs,
+            fee: Amount::from_sat(fee),
+            change: change.map(Amount::from_sat),
+        })
+    }
+}
+
+/// Spends the largest outputs until the target is covered.
+fn largest_first(candidates: &[Utxo], params: &Params) -> Vec<Utxo> {
+    let target = params.target_without_change() as i64;
+    let mut selected = Vec::new();
+    let mut effective_value = 0;
+
+    for utxo in candidates {
+        if effective_value >= target {
+            break;
+        }
+        effective_value += params.effective_value(utxo);
+        selected.push(*utxo);
+    }
+
+    selected
+}
+
+/// Searches, depth first, the outputs covering the target without change and
+/// with less excess than a change output would cost. `candidates` are sorted
+/// by decreasing amount.
+fn branch_and_bound(candidates: &[Utxo], params: &Params) -> Option<Vec<Utxo>> {
+    let target = params.target_without_change() as i64;
+    let upper_bound = target + (params.fee(OUTPUT_VSIZE) + params.min_change) as i64;
+    let values: Vec<i64> = candidates
+        .iter()
+        .map(|utxo| params.effective_value(utxo))
+        .collect();
+
+    // The effective value of the candidates from each index on.
+    let mut remaining = vec![0; values.len() + 1];
+    for index in (0..values.len()).rev() {
+        remaining[index] = remaining[index + 1] + values[index];
+    }
+
+    let mut included = Vec::with_capacity(values.len());
+    let mut sum = 0;
+    for _ in 0..BRANCH_AND_BOUND_MAX_TRIES {
+        let index = included.len();
+        let backtrack = sum > upper_bound || sum + remaining[index] < target;
+
+        if !backtrack && sum >= target {
+            let inputs = included
+                .iter()
+                .zip(candidates)
+                .filter(|(included, _)| **included)
+                .map(|(_, utxo)| *utxo)
+                .collect();
+            return Some(inputs);
+        }
+
+        if backtrack || index == values.len() {
+            // Exclude the last included candidate and try without it
+            loop {
+                match included.pop() {
+                    Some(true) => {
+                        sum -= values[included.len()];
+                        included.push(false);
+                        break;
+                    }
+                    Some(false) => continue,
+                    None => return None,
+                }
+            }
+        } else {
+            sum += values[index];
+            included.push(true);
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::bitcoin::{hashes::Hash, Txid};
+
+    fn utxo(vout: u32, sat: u64) -> Utxo {
+        Utxo {
+            outpoint: OutPoint {
+                txid: Txid::from_inner([0u8; 32]),
+                vout,
+            },
+            amount: Amount::from_sat(sat),
+            confirmations: 1,
+        }
+    }
+
+    fn coin_selection(strategy: config::CoinSelectionStrategy) -> config::CoinSelection {
+        config::CoinSelection {
+            strategy,
+            min_change: None,
+        }
+    }
+
+    /// One satoshi per vbyte.
+    fn fee_rate() -> Amount {
+        Amount::from_sat(1_000)
+    }
+
+    #[test]
+    fn largest_first_spends_the_largest_outputs_with_change() {
+        let utxos = [utxo(0, 10_000), utxo(1, 50_000), utxo(2, 30_000)];
+
+        let selection = select(
+            &utxos,
+            Amount::from_sat(60_000),
+            fee_rate(),
+            &coin_selection(config::CoinSelectionStrategy::LargestFirst),
+        )
+        .unwrap();
+
+        assert_eq!(selection.inputs, vec![utxos[1], utxos[2]]);
+        // 11 + 2 * 31 + 2 * 68 vbytes at 1 sat per vbyte
+        assert_eq!(selection.fee, Amount::from_sat(209));
+        assert_eq!(selection.change, Some(Amount::from_sat(19_791)));
+    }
+
+    #[test]
+    fn branch_and_bound_finds_a_selection_without_change() {
+        let utxos = [utxo(0, 50_000), utxo(1, 30_000), utxo(2, 20_200)];
+
+        let selection = select(
+            &utxos,
+            Amount::from_sat(50_000),
+            fee_rate(),
+            &coin_selection(config::CoinSelectionStrategy::BranchAndBound),
+        )
+        .unwrap();
+
+        assert_eq!(selection.inputs, vec![utxos[1], utxos[2]]);
+        assert_eq!(selection.change, None);
+        assert_eq!(selection.fee, Amount::from_sat(200));
+    }
+
+    #[test]
+    fn branch_and_bound_falls_back_to_largest_first() {
+        let utxos = [utxo(0, 100_000)];
+
+        let selection = select(
+            &utxos,
+            Amount::from_sat(50_000),
+            fee_rate(),
+            &coin_selection(config::CoinSelectionStrategy::BranchAndBound),
+        )
+        .unwrap();
+
+        assert_eq!(selection.inputs, vec![utxos[0]]);
+        assert!(selection.change.is_some());
+    }
+
+    #[test]
+    fn insufficient_funds_are_an_error() {
+        let utxos = [utxo(0, 10_000)];
+
+        let selection = select(
+            &utxos,
+            Amount::from_sat(10_000),
+            fee_rate(),
+            &coin_selection(config::CoinSelectionStrategy::LargestFirst),
+        );
+
+        assert!(selection.is_err());
+    }
+}
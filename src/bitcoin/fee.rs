@@ -0,0 +1,61 @@
+//! Estimation of the fee Nectar pays for the Bitcoin transactions of a swap.
+
+use crate::bitcoin::{Amount, Wallet};
+
+/// Number of blocks we want our transactions to be confirmed within.
+pub const CONFIRMATION_TARGET: u16 = 6;
+
+/// Number of blocks the replacement of a transaction that was not confirmed
+/// in time should be confirmed within.
+pub const FEE_BUMP_CONFIRMATION_TARGET: u16 = 2;
+
+/// Upper bound of the virtual size of the transactions we pay for when we fund
+/// a swap: the funding transaction and, if the swap fails, the refund.
+const SWAP_TRANSACTIONS_VSIZE: u64 = 400;
+
+/// Estimate the fee to reserve for a swap given the current network
+/// conditions, never more than `maximum`.
+pub async fn estimate_swap_fee(wallet: &Wallet, maximum: Amount) -> anyhow::Result<Amount> {
+    let fee_rate = wallet.fee_rate(CONFIRMATION_TARGET).await?;
+
+    Ok(swap_fee(fee_rate, maximum))
+}
+
+/// Fee of the transactions of a swap at `fee_rate` per kvB, capped at
+/// `maximum`.
+pub fn swap_fee(fee_rate: Amount, maximum: Amount) -> Amount {
+    let fee = fee_rate
+        .as_sat()
+        .saturating_mul(SWAP_TRANSACTIONS_VSIZE)
+        .saturating_add(999)
+        / 1000;
+
+    Amount::from_sat(fee).min(maximum)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitcoin::amount::btc;
+
+    #[test]
+    fn swap_fee_is_computed_from_the_fee_rate() {
+        let fee = swap_fee(Amount::from_sat(10_000), btc(0.01));
+
+        assert_eq!(fee, Amount::from_sat(4_000));
+    }
+
+    #[test]
+    fn swap_fee_is_rounded_up() {
+        let fee = swap_fee(Amount::from_sat(1_001), btc(0.01));
+
+        assert_eq!(fee, Amount::from_sat(401));
+    }
+
+    #[test]
+    fn swap_fee_is_capped_at_maximum() {
+        let fee = swap_fee(btc(1.0), btc(0.01));
+
+        assert_eq!(fee, btc(0.01));
+    }
+}
@@ -1,15 +1,23 @@
 use crate::{
-    bitcoin::{Address, Amount, Client, Network, WalletInfoResponse},
+    bitcoin::{
+        coin_selection::{self, Selection, Utxo},
+        fee::{CONFIRMATION_TARGET, FEE_BUMP_CONFIRMATION_TARGET},
+        Address, Amount, Client, ImportDescriptorsRequest, ImportMultiRequest, Network,
+        WalletInfoResponse,
+    },
+    config,
     seed::Seed,
 };
 use ::bitcoin::{
-    hashes::{sha256, Hash, HashEngine},
+    consensus::encode::deserialize,
+    hashes::{hex::FromHex, sha256, Hash, HashEngine},
     secp256k1::SecretKey,
     util::bip32::{ChainCode, ChildNumber, ExtendedPrivKey},
-    PrivateKey, Transaction, Txid,
+    OutPoint, PrivateKey, Transaction, Txid,
 };
+use anyhow::Context;
 use bitcoin::util::bip32::DerivationPath;
-use std::str::FromStr;
+use std::{convert::TryFrom, str::FromStr};
 use url::Url;
 
 const BITCOIND_DEFAULT_EXTERNAL_DERIVATION_PATH: &str = "/0h/0h/*h";
@@ -20,17 +28,56 @@ const TRANSIENT_DERIVATION_PATH: &str = "m/0'/9939'";
 #[derivative(Debug)]
 pub struct Wallet {
     /// The wallet is named `nectar_x` with `x` being the first 4 bytes of the
-    /// hash of the seed
+    /// hash of the seed, suffixed with `_bip44_` and the account for the
+    /// BIP-44 derivation, unless an external wallet is configured
     name: String,
     bitcoind_client: Client,
     root_key: ExtendedPrivKey,
+    derivation: config::Derivation,
+    /// Outputs are selected by bitcoind if `None`.
+    coin_selection: Option<config::CoinSelection>,
+    /// Our HTLC funding is never replaced if `None`.
+    fee_bumping: Option<config::FeeBumping>,
+    /// Held from selecting our outputs until the transaction spending them is
+    /// broadcast, so that concurrent sends do not select the same ones.
+    selecting_outputs: tokio::sync::Mutex<()>,
     pub network: Network,
 }
 
 impl Wallet {
     pub async fn new(seed: Seed, url: Url, network: Network) -> anyhow::Result<Wallet> {
-        let name = Wallet::gen_name(seed);
-        let bitcoind_client = Client::new(url);
+        Self::new_with_auth(
+            seed,
+            &config::Bitcoind::new(url),
+            &config::BitcoinWallet::default(),
+            config::Derivation::default(),
+            config::Rpc::default(),
+            network,
+        )
+        .await
+    }
+
+    pub async fn new_with_auth(
+        seed: Seed,
+        bitcoind: &config::Bitcoind,
+        wallet_config: &config::BitcoinWallet,
+        derivation: config::Derivation,
+        rpc: config::Rpc,
+        network: Network,
+    ) -> anyhow::Result<Wallet> {
+        let name = match (wallet_config, derivation) {
+            (config::BitcoinWallet::Seed, config::Derivation::Legacy) => Wallet::gen_name(seed),
+            (config::BitcoinWallet::Seed, config::Derivation::Bip44 { account, .. }) => {
+                format!("{}_bip44_{}", Wallet::gen_name(seed), account)
+            }
+            (config::BitcoinWallet::External { name, .. }, _) => name.clone(),
+        };
+        let mut bitcoind_client = Client::new(bitcoind.node_url.clone())
+            .with_auth(&bitcoind.auth)?
+            .with_rpc_config(rpc);
+        if let Some(basic_auth) = bitcoind.basic_auth() {
+            bitcoind_client = bitcoind_client.with_basic_auth(basic_auth);
+        }
 
         let root_key = Self::root_extended_private_key_from_seed(&seed, network);
 
@@ -38,14 +85,44 @@ impl Wallet {
             name,
             bitcoind_client,
             root_key,
+            derivation,
+            coin_selection: None,
+            fee_bumping: None,
+            selecting_outputs: tokio::sync::Mutex::new(()),
             network,
         };
 
-        wallet.init(seed).await?;
+        match wallet_config {
+            config::BitcoinWallet::Seed if derivation == config::Derivation::Legacy => {
+                wallet.init(seed).await?
+            }
+            config::BitcoinWallet::Seed => wallet.init_bip44().await?,
+            config::BitcoinWallet::External { descriptors, .. } => {
+                wallet.init_external(descriptors).await?
+            }
+        }
 
         Ok(wallet)
     }
 
+    pub fn with_coin_selection(self, coin_selection: Option<config::CoinSelection>) -> Self {
+        Self {
+            coin_selection,
+            ..self
+        }
+    }
+
+    pub fn with_fee_bumping(self, fee_bumping: Option<config::FeeBumping>) -> Self {
+        Self {
+            fee_bumping,
+            ..self
+        }
+    }
+
+    pub fn fee_bumping(&self) -> Option<config::FeeBumping> {
+        self.fee_bumping
+    }
+
     async fn init(&self, seed: Seed) -> anyhow::Result<()> {
         let info = self.info().await;
 
@@ -87,6 +164,113 @@ impl Wallet {
         }
     }
 
+    /// Creates a descriptor wallet deriving its addresses along the BIP-44
+    /// paths if it does not exist yet. Descriptor wallets require bitcoind 0.21
+    /// or later.
+    async fn init_bip44(&self) -> anyhow::Result<()> {
+        if self.info().await.is_ok() {
+            return Ok(());
+        }
+
+        self.bitcoind_client
+            .create_descriptor_wallet(&self.name)
+            .await
+            .context("The BIP-44 derivation requires bitcoind 0.21 or later")?;
+
+        let requests = self
+            .descriptors_with_checksums()
+            .await?
+            .into_iter()
+            .zip(vec![false, true])
+            .map(|(descriptor, internal)| ImportDescriptorsRequest {
+                descriptor,
+                timestamp: 0,
+                active: true,
+                internal,
+            })
+            .collect();
+
+        tracing::info!(
+            "Importing the BIP-44 descriptors into the bitcoind wallet {}, rescanning the chain",
+            self.name
+        );
+        let responses = self
+            .bitcoind_client
+            .import_descriptors(&self.name, requests)
+            .await?;
+        for response in responses {
+            if let Some(error) = response.error {
+                anyhow::bail!("Could not import descriptor: {}", error.message);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the external wallet is loaded and imports the descriptors
+    /// it does not watch yet, the chain is rescanned for their transactions.
+    async fn init_external(&self, descriptors: &[String]) -> anyhow::Result<()> {
+        self.info().await.with_context(|| {
+            format!(
+                "Could not find the bitcoind wallet {}, it must be loaded",
+                self.name
+            )
+        })?;
+
+        let mut requests = Vec::new();
+        for descriptor in descriptors {
+            let info = self.bitcoind_client.get_descriptor_info(descriptor).await?;
+            let descriptor = if descriptor.contains('#') {
+                descriptor.clone()
+            } else {
+                format!("{}#{}", descriptor, info.checksum)
+            };
+            let range = if info.is_range { Some([0, 0]) } else { None };
+
+            // The descriptor is known if its first address is
+            let address = self
+                .bitcoind_client
+                .derive_addresses(&descriptor, range)
+                .await?
+                .into_iter()
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("Descriptor {} has no address", descriptor))?;
+            let address_info = self
+                .bitcoind_client
+                .get_address_info(&self.name, &address)
+                .await?;
+
+            if !address_info.is_mine && !address_info.is_watch_only {
+                requests.push(ImportMultiRequest {
+                    descriptor,
+                    timestamp: 0,
+                    watch_only: true,
+                });
+            }
+        }
+
+        if requests.is_empty() {
+            return Ok(());
+        }
+
+        tracing::info!(
+            "Importing {} descriptors into the bitcoind wallet {}, rescanning the chain",
+            requests.len(),
+            self.name
+        );
+        let responses = self
+            .bitcoind_client
+            .import_multi(&self.name, requests)
+            .await?;
+        for response in responses {
+            if let Some(error) = response.error {
+                anyhow::bail!("Could not import descriptor: {}", error.message);
+            }
+        }
+
+        Ok(())
+    }
+
     /// Derive a new key under transient derivation path
     pub fn derive_transient_sk(&self, index: u32) -> anyhow::Result<SecretKey> {
         let index = ChildNumber::from_hardened_idx(index)?;
@@ -119,6 +303,30 @@ impl Wallet {
             .await
     }
 
+    /// Balance of the outputs with at least `confirmations` confirmations.
+    pub async fn confirmed_balance(&self, confirmations: u32) -> anyhow::Result<Amount> {
+        self.assert_network(self.network).await?;
+
+        self.bitcoind_client
+            .get_balance(&self.name, Some(confirmations), None, None)
+            .await
+    }
+
+    /// Fee rate per kvB for a transaction to confirm within `conf_target`
+    /// blocks, as estimated by bitcoind.
+    pub async fn fee_rate(&self, conf_target: u16) -> anyhow::Result<Amount> {
+        self.assert_network(self.network).await?;
+
+        let estimate = self.bitcoind_client.estimate_smart_fee(conf_target).await?;
+        match estimate.fee_rate {
+            Some(fee_rate) => Amount::from_btc(fee_rate),
+            None => anyhow::bail!(
+                "bitcoind could not estimate the fee rate: {}",
+                estimate.errors.unwrap_or_default().join(", ")
+            ),
+        }
+    }
+
     /// Returns the seed in wif format, this allows the user to import the
     /// wallet in a different bitcoind using `sethdseed`.
     /// It seems relevant that access to bitcoind must not be needed to complete
@@ -162,15 +370,19 @@ impl Wallet {
 
     /// Wallet descriptors as specified in https://github.com/bitcoin/bitcoin/blob/master/doc/descriptors.md
     pub fn descriptors(&self) -> Vec<String> {
-        Self::hd_paths()
+        Self::hd_paths(self.derivation, self.network)
             .iter()
             .map(|path| format!("wpkh({}{})", self.root_key, path))
             .collect()
     }
 
-    pub fn descriptors_from_seed(seed: &Seed, network: Network) -> Vec<String> {
+    pub fn descriptors_from_seed(
+        seed: &Seed,
+        network: Network,
+        derivation: config::Derivation,
+    ) -> Vec<String> {
         let ext_priv_key = Self::root_extended_private_key_from_seed(seed, network);
-        Self::hd_paths()
+        Self::hd_paths(derivation, network)
             .iter()
             .map(|path| format!("wpkh({}{})", ext_priv_key, path))
             .collect()
@@ -201,11 +413,26 @@ impl Wallet {
     /// "m/iH/1/k corresponds to the k'th keypair of the internal chain of
     /// account number i of the HDW derived from master m." ie, the
     /// addresses to send change.
-    fn hd_paths() -> Vec<&'static str> {
-        vec![
-            BITCOIND_DEFAULT_EXTERNAL_DERIVATION_PATH,
-            BITCOIND_DEFAULT_INTERNAL_DERIVATION_PATH,
-        ]
+    /// With the BIP-44 derivation, those are the chains of the configured
+    /// account, m/44'/coin_type'/account'/0/k and
+    /// m/44'/coin_type'/account'/1/k.
+    fn hd_paths(derivation: config::Derivation, network: Network) -> Vec<String> {
+        match derivation {
+            config::Derivation::Legacy => vec![
+                BITCOIND_DEFAULT_EXTERNAL_DERIVATION_PATH.to_owned(),
+                BITCOIND_DEFAULT_INTERNAL_DERIVATION_PATH.to_owned(),
+            ],
+            config::Derivation::Bip44 { account, .. } => {
+                let coin_type = match network {
+                    Network::Bitcoin => 0,
+                    Network::Testnet | Network::Regtest => 1,
+                };
+                vec![
+                    format!("/44h/{}h/{}h/0/*", coin_type, account),
+                    format!("/44h/{}h/{}h/1/*", coin_type, account),
+                ]
+            }
+        }
     }
 
     pub async fn send_to_address(
@@ -213,16 +440,203 @@ impl Wallet {
         address: Address,
         amount: Amount,
         network: Network,
+    ) -> anyhow::Result<Txid> {
+        self.send(address, amount, network, false).await
+    }
+
+    /// Like `send_to_address` but the transaction signals replace-by-fee, for
+    /// `bump_fee` to replace it while unconfirmed.
+    pub async fn send_replaceable_to_address(
+        &self,
+        address: Address,
+        amount: Amount,
+        network: Network,
+    ) -> anyhow::Result<Txid> {
+        self.send(address, amount, network, true).await
+    }
+
+    async fn send(
+        &self,
+        address: Address,
+        amount: Amount,
+        network: Network,
+        replaceable: bool,
     ) -> anyhow::Result<Txid> {
         self.assert_network(network).await?;
 
+        if let Some(coin_selection) = self.coin_selection {
+            return self
+                .send_selected_outputs(address, amount, &coin_selection, replaceable)
+                .await;
+        }
+
         let txid = self
             .bitcoind_client
-            .send_to_address(&self.name, address, amount)
+            .send_to_address(
+                &self.name,
+                address,
+                amount,
+                false,
+                replaceable,
+                CONFIRMATION_TARGET,
+            )
             .await?;
         Ok(txid)
     }
 
+    /// Confirmations of a transaction of the wallet, 0 while unconfirmed or
+    /// replaced.
+    pub async fn confirmations(&self, txid: Txid) -> anyhow::Result<u32> {
+        let transaction = self
+            .bitcoind_client
+            .get_transaction(&self.name, txid)
+            .await?;
+
+        Ok(u32::try_from(transaction.confirmations).unwrap_or(0))
+    }
+
+    /// Replaces an unconfirmed transaction sent with
+    /// `send_replaceable_to_address` by one paying a higher fee, deducted
+    /// from its change. Returns the replacement.
+    pub async fn bump_fee(&self, txid: Txid) -> anyhow::Result<Transaction> {
+        let txid = self
+            .bitcoind_client
+            .bump_fee(&self.name, txid, FEE_BUMP_CONFIRMATION_TARGET)
+            .await?;
+        let replacement = self
+            .bitcoind_client
+            .get_transaction(&self.name, txid)
+            .await?;
+
+        Ok(deserialize(&Vec::<u8>::from_hex(&replacement.hex)?)?)
+    }
+
+    /// The outputs of the wallet bitcoind considers safe to spend, including
+    /// our own unconfirmed change.
+    pub async fn utxos(&self) -> anyhow::Result<Vec<Utxo>> {
+        self.bitcoind_client
+            .list_unspent(&self.name, 0)
+            .await?
+            .into_iter()
+            .filter(|unspent| unspent.safe)
+            .map(|unspent| {
+                Ok(Utxo {
+                    outpoint: OutPoint {
+                        txid: Txid::from_hex(&unspent.txid)?,
+                        vout: unspent.vout,
+                    },
+                    amount: Amount::from_btc(unspent.amount)?,
+                    confirmations: unspent.confirmations,
+                })
+            })
+            .collect()
+    }
+
+    async fn select_outputs(
+        &self,
+        amount: Amount,
+        coin_selection: &config::CoinSelection,
+    ) -> anyhow::Result<Selection> {
+        let utxos = self.utxos().await?;
+        let fee_rate = self.fee_rate(CONFIRMATION_TARGET).await?;
+
+        coin_selection::select(&utxos, amount, fee_rate, coin_selection)
+    }
+
+    async fn send_selected_outputs(
+        &self,
+        address: Address,
+        amount: Amount,
+        coin_selection: &config::CoinSelection,
+        replaceable: bool,
+    ) -> anyhow::Result<Txid> {
+        // Unlike `sendtoaddress`, selecting and spending are separate calls
+        let _guard = self.selecting_outputs.lock().await;
+        let selection = self.select_outputs(amount, coin_selection).await?;
+
+        let mut outputs = vec![(address, amount)];
+        if let Some(change) = selection.change {
+            let change_address = self
+                .bitcoind_client
+                .get_raw_change_address(&self.name)
+                .await?;
+            outputs.push((change_address, change));
+        }
+        let inputs: Vec<OutPoint> = selection.inputs.iter().map(|utxo| utxo.outpoint).collect();
+
+        let unsigned = self
+            .bitcoind_client
+            .create_raw_transaction(&inputs, &outputs, replaceable)
+            .await?;
+        let transaction = self
+            .bitcoind_client
+            .sign_raw_transaction_with_wallet(&self.name, unsigned)
+            .await?;
+        tracing::debug!(
+            "Sending {} BTC spending {} outputs with a fee of {} BTC",
+            amount.as_btc(),
+            inputs.len(),
+            selection.fee.as_btc()
+        );
+
+        self.bitcoind_client
+            .send_raw_transaction(&self.name, transaction)
+            .await
+    }
+
+    /// Sends the whole balance of the wallet to `address`, the fee being
+    /// deducted from the amount sent.
+    pub async fn send_all_to_address(
+        &self,
+        address: Address,
+        network: Network,
+    ) -> anyhow::Result<Txid> {
+        self.assert_network(network).await?;
+
+        let balance = self.balance().await?;
+        let txid = self
+            .bitcoind_client
+            .send_to_address(
+                &self.name,
+                address,
+                balance,
+                true,
+                false,
+                CONFIRMATION_TARGET,
+            )
+            .await?;
+        Ok(txid)
+    }
+
+    /// Fee of a transaction sending `amount` to `address`, without sending it.
+    /// If `subtract_fee_from_amount` is set, the fee is deducted from `amount`
+    /// instead of being paid on top.
+    pub async fn estimate_send_fee(
+        &self,
+        address: Address,
+        amount: Amount,
+        subtract_fee_from_amount: bool,
+    ) -> anyhow::Result<Amount> {
+        self.assert_network(self.network).await?;
+
+        if let (Some(coin_selection), false) = (self.coin_selection, subtract_fee_from_amount) {
+            let selection = self.select_outputs(amount, &coin_selection).await?;
+            return Ok(selection.fee);
+        }
+
+        let funded = self
+            .bitcoind_client
+            .wallet_create_funded_psbt(
+                &self.name,
+                address,
+                amount,
+                subtract_fee_from_amount,
+                CONFIRMATION_TARGET,
+            )
+            .await?;
+        Amount::from_btc(funded.fee)
+    }
+
     pub async fn send_raw_transaction(
         &self,
         transaction: Transaction,
@@ -267,6 +681,25 @@ impl Wallet {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bip44_descriptors_derive_the_chains_of_the_account() {
+        let seed = Seed::from([1u8; 32]);
+        let derivation = config::Derivation::Bip44 {
+            account: 2,
+            index: 0,
+        };
+
+        let descriptors = Wallet::descriptors_from_seed(&seed, Network::Testnet, derivation);
+
+        assert!(descriptors[0].ends_with("/44h/1h/2h/0/*)"));
+        assert!(descriptors[1].ends_with("/44h/1h/2h/1/*)"));
+    }
+}
+
 #[cfg(all(test, feature = "test-docker"))]
 mod docker_tests {
     use super::*;
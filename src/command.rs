@@ -1,29 +1,53 @@
 use std::path::PathBuf;
 use structopt::StructOpt;
 
+mod backtest;
 mod balance;
 mod deposit;
+mod history_export;
+mod id;
+mod migrate_wallet;
+mod rebalance;
+mod report;
 mod resume_only;
+mod seed;
+mod swaps;
+mod takers;
 mod trade;
+mod utxos;
 mod wallet_info;
 mod withdraw;
 
 use crate::{
+    alert::{Alert, Alerter},
     bitcoin,
     config::{File, Settings},
     ethereum::{self, dai, ether},
     history,
     network::ActivePeer,
-    swap::SwapKind,
+    swap::{
+        hbit, CounterpartyNeverFunded, Database, PeerOutcome, RefundCause, RefundRecord,
+        Settlement, SwapKind, SwapOutcome,
+    },
 };
 use chrono::{DateTime, Utc};
 use num::BigUint;
 use std::str::FromStr;
 
-pub use balance::balance;
-pub use deposit::deposit;
-pub use resume_only::resume_only;
+pub use backtest::{backtest, Backtest};
+pub use balance::{balance, Balance};
+pub use deposit::{deposit, watch_deposit, Deposit};
+pub use history_export::{export_history, History};
+pub use id::id;
+pub use migrate_wallet::migrate_wallet;
+pub use rebalance::{rebalance, Rebalance};
+pub use report::{report, Report};
+pub use resume_only::{resume_only, ResumeOnly, SwapSelection};
+pub use seed::{seed, Seed};
+pub use swaps::{swaps, Swaps};
+pub use takers::{takers, Takers};
 pub use trade::trade;
+pub use utxos::utxos;
 pub use wallet_info::wallet_info;
 pub use withdraw::withdraw;
 
@@ -49,16 +73,43 @@ pub enum Command {
     Trade,
     /// Print all wallets information for backup or export purposes
     WalletInfo,
-    /// Print the actual balance on all assets
-    Balance,
-    /// Print wallet addresses to deposit assets
-    Deposit,
+    /// Print the balances, the funds reserved for ongoing swaps and the
+    /// addresses of the wallets
+    Balance(Balance),
+    /// Assess the share of the inventory held in each asset and recommend
+    /// how to rebalance it as per `[maker.rebalance]`
+    Rebalance(Rebalance),
+    /// Print wallet addresses to deposit assets and optionally wait for the
+    /// deposit to confirm
+    Deposit(Deposit),
     /// Dump the current configuration
     DumpConfig,
     /// Withdraw assets
     Withdraw(Withdraw),
-    /// Only resume ongoing swaps, do not publish or accept new orders
-    ResumeOnly,
+    /// Only resume ongoing swaps, do not publish or accept new orders, or
+    /// only refund them to unwind after an outage
+    ResumeOnly(ResumeOnly),
+    /// Summarize the trade history per day, week or month
+    Report(Report),
+    /// Export the trades, optionally filtered by date or taker, with their
+    /// realised P&L
+    History(History),
+    /// Replay recorded rates through the maker as configured and report the
+    /// hypothetical fills and P&L
+    Backtest(Backtest),
+    /// Ban or allow takers
+    Takers(Takers),
+    /// List the ongoing swaps or show the state of one of them
+    Swaps(Swaps),
+    /// Export the seed as a BIP-39 phrase or import it from one
+    Seed(Seed),
+    /// Print the peer id of nectar, derived from the seed
+    Id,
+    /// List the unspent outputs of the Bitcoin wallet
+    Utxos,
+    /// Move the bitcoin of the wallet derived along the legacy paths to the
+    /// wallet derived along the BIP-44 paths
+    MigrateWallet,
 }
 
 pub fn dump_config(settings: Settings) -> anyhow::Result<()> {
@@ -73,22 +124,64 @@ pub fn dump_config(settings: Settings) -> anyhow::Result<()> {
 #[derive(StructOpt, Debug, Clone)]
 pub enum Withdraw {
     Btc {
-        #[structopt(parse(try_from_str = parse_bitcoin))]
-        amount: bitcoin::Amount,
+        /// Amount to withdraw or `all` to sweep the wallet
+        #[structopt(parse(try_from_str = parse_bitcoin_or_all))]
+        amount: WithdrawAmount<bitcoin::Amount>,
         to_address: bitcoin::Address,
+        /// Only estimate the fee, do not send the transaction
+        #[structopt(long)]
+        dry_run: bool,
     },
     Dai {
-        #[structopt(parse(try_from_str = parse_dai))]
-        amount: dai::Amount,
+        /// Amount to withdraw or `all` to sweep the wallet
+        #[structopt(parse(try_from_str = parse_dai_or_all))]
+        amount: WithdrawAmount<dai::Amount>,
         to_address: ethereum::Address,
+        /// Only estimate the fee, do not send the transaction
+        #[structopt(long)]
+        dry_run: bool,
     },
     Eth {
-        #[structopt(parse(try_from_str = parse_ether))]
-        amount: ether::Amount,
+        /// Amount to withdraw or `all` to sweep the wallet
+        #[structopt(parse(try_from_str = parse_ether_or_all))]
+        amount: WithdrawAmount<ether::Amount>,
         to_address: ethereum::Address,
+        /// Only estimate the fee, do not send the transaction
+        #[structopt(long)]
+        dry_run: bool,
     },
 }
 
+/// Either a given amount or everything the wallet holds.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WithdrawAmount<A> {
+    All,
+    Exactly(A),
+}
+
+fn parse_all_or<A>(
+    str: &str,
+    parse: impl Fn(&str) -> anyhow::Result<A>,
+) -> anyhow::Result<WithdrawAmount<A>> {
+    if str == "all" {
+        return Ok(WithdrawAmount::All);
+    }
+
+    parse(str).map(WithdrawAmount::Exactly)
+}
+
+fn parse_bitcoin_or_all(str: &str) -> anyhow::Result<WithdrawAmount<bitcoin::Amount>> {
+    parse_all_or(str, parse_bitcoin)
+}
+
+fn parse_dai_or_all(str: &str) -> anyhow::Result<WithdrawAmount<dai::Amount>> {
+    parse_all_or(str, parse_dai)
+}
+
+fn parse_ether_or_all(str: &str) -> anyhow::Result<WithdrawAmount<ether::Amount>> {
+    parse_all_or(str, parse_ether)
+}
+
 fn parse_bitcoin(str: &str) -> anyhow::Result<bitcoin::Amount> {
     // TODO: In addition to providing an interface to withdraw satoshi, we could use
     // string instead of float here
@@ -110,14 +203,15 @@ fn parse_ether(str: &str) -> anyhow::Result<ether::Amount> {
 pub fn into_history_trade(
     peer_id: libp2p::PeerId,
     swap: SwapKind,
+    outcome: history::Outcome,
+    swap_outcome: SwapOutcome,
+    settlement: Settlement,
     #[cfg(not(test))] final_timestamp: DateTime<Utc>,
 ) -> history::Trade {
     use crate::history::*;
 
-    let (swap, position) = match swap {
-        SwapKind::HbitHerc20(swap) => (swap, history::Position::Sell),
-        SwapKind::Herc20Hbit(swap) => (swap, history::Position::Buy),
-    };
+    let position = swap.position();
+    let swap = swap.params();
 
     #[cfg(not(test))]
     let final_timestamp = final_timestamp.into();
@@ -127,17 +221,160 @@ pub fn into_history_trade(
         .unwrap()
         .into();
 
+    let base_precise_amount = BigUint::from(swap.hbit_params.shared.asset.as_sat());
+    let quote_precise_amount = BigUint::from_str(&swap.herc20_params.asset.quantity.to_wei_dec())
+        .expect("number to number conversion");
+
     Trade {
         utc_start_timestamp: history::UtcDateTime::from(swap.start_of_swap),
         utc_final_timestamp: final_timestamp,
         base_symbol: Symbol::Btc,
         quote_symbol: Symbol::Dai,
         position,
-        base_precise_amount: swap.hbit_params.shared.asset.as_sat().into(),
-        quote_precise_amount: BigUint::from_str(&swap.herc20_params.asset.quantity.to_wei_dec())
-            .expect("number to number conversion")
-            .into(),
+        mid_market_rate: swap
+            .mid_market_rate
+            .map(|rate| Float::from(rate.to_string())),
+        executed_rate: history::executed_rate(&base_precise_amount, &quote_precise_amount),
+        // Nothing was traded if we got our asset back
+        realized_pnl_dai: swap
+            .mid_market_rate
+            .filter(|_| outcome == Outcome::Redeemed)
+            .map(|rate| {
+                history::realized_pnl_dai(
+                    position,
+                    &base_precise_amount,
+                    &quote_precise_amount,
+                    rate,
+                )
+            }),
+        captured_spread: swap
+            .mid_market_rate
+            .filter(|_| outcome == Outcome::Redeemed)
+            .map(|rate| {
+                history::captured_spread(
+                    position,
+                    &base_precise_amount,
+                    &quote_precise_amount,
+                    rate,
+                )
+            }),
+        base_precise_amount: base_precise_amount.into(),
+        quote_precise_amount: quote_precise_amount.into(),
         peer: peer_id.into(),
+        swap_id: swap.swap_id,
+        outcome,
+        swap_outcome,
+        bitcoin_fund_txid: settlement.bitcoin_fund.map(Into::into),
+        bitcoin_redeem_txid: settlement.bitcoin_redeem.map(Into::into),
+        bitcoin_refund_txid: settlement.bitcoin_refund.map(Into::into),
+        ethereum_deploy_txid: settlement.ethereum_deploy.map(Into::into),
+        ethereum_fund_txid: settlement.ethereum_fund.map(Into::into),
+        ethereum_redeem_txid: settlement.ethereum_redeem.map(Into::into),
+        ethereum_refund_txid: settlement.ethereum_refund.map(Into::into),
+        bitcoin_fee_sat: settlement.bitcoin_fee.map(|fee| fee.as_sat().into()),
+    }
+}
+
+/// Alert the operator of the failure of the swap, the error is recorded as it
+/// may be the cause of a later refund. The swap ends as aborted, or as failed
+/// if we locked funds in it, until it is resumed.
+pub async fn report_swap_failure(
+    db: &Database,
+    alerter: &Alerter,
+    swap: &SwapKind,
+    error: &anyhow::Error,
+) {
+    let swap_id = swap.swap_id();
+    if error.is::<CounterpartyNeverFunded>() || error.is::<hbit::IncorrectlyFunded>() {
+        record_peer_outcome(db, swap, PeerOutcome::Aborted).await;
+    }
+
+    record_swap_outcome(db, swap, &swap.failed(db, error)).await;
+
+    let error = format!("{:#}", error);
+
+    if let Err(e) = db.insert_swap_failure(&swap_id, &error).await {
+        tracing::error!("Could not record the failure of the swap: {:#}", e);
+    }
+
+    alerter.notify(Alert::SwapFailed { swap_id, error });
+}
+
+/// Record how the executed swap ended and whether it was redeemed or refunded,
+/// the operator is alerted of a refund.
+pub async fn swap_outcome(
+    db: &Database,
+    alerter: &Alerter,
+    swap: &SwapKind,
+    outcome: &SwapOutcome,
+) -> history::Outcome {
+    record_swap_outcome(db, swap, outcome).await;
+
+    match outcome {
+        SwapOutcome::RefundedAfter(cause) => {
+            report_refund(db, alerter, swap, cause.clone()).await;
+            history::Outcome::Refunded
+        }
+        SwapOutcome::Completed => {
+            record_peer_outcome(db, swap, PeerOutcome::Completed).await;
+            history::Outcome::Redeemed
+        }
+        // Errors are returned instead by the execution, see `SwapKind::execute`
+        SwapOutcome::AbortedBeforeFund { .. } | SwapOutcome::Failed { .. } => {
+            tracing::error!("Swap did not finish: {}", outcome);
+            history::Outcome::Redeemed
+        }
+    }
+}
+
+async fn record_swap_outcome(db: &Database, swap: &SwapKind, outcome: &SwapOutcome) {
+    if let Err(e) = db.insert_swap_outcome(&swap.swap_id(), outcome).await {
+        tracing::error!("Could not record the outcome of the swap: {:#}", e);
+    }
+}
+
+async fn report_refund(db: &Database, alerter: &Alerter, swap: &SwapKind, cause: RefundCause) {
+    let swap_id = swap.swap_id();
+    let bitcoin_fee_sat = swap
+        .settlement(db)
+        .map(|settlement| settlement.bitcoin_fee.map(|fee| fee.as_sat()))
+        .unwrap_or_else(|e| {
+            tracing::error!("Could not load the settlement of the swap: {:#}", e);
+            None
+        });
+
+    let refund = RefundRecord {
+        swap_id,
+        peer_id: swap.params().taker.peer_id().to_string(),
+        recorded_at: Utc::now(),
+        cause: cause.clone(),
+        bitcoin_fee_sat,
+    };
+    if let Err(e) = db.insert_refund(&refund).await {
+        tracing::error!("Could not record the refund of the swap: {:#}", e);
+    }
+
+    match cause {
+        RefundCause::CounterpartyNeverFunded => {
+            record_peer_outcome(db, swap, PeerOutcome::Aborted).await
+        }
+        RefundCause::CounterpartyNeverRedeemed => {
+            record_peer_outcome(db, swap, PeerOutcome::RefundCaused).await
+        }
+        RefundCause::ExecutionFailed { .. } => {}
+    }
+
+    alerter.notify(Alert::SwapRefunded { swap_id, cause });
+}
+
+async fn record_peer_outcome(db: &Database, swap: &SwapKind, outcome: PeerOutcome) {
+    let peer_id = swap.params().taker.peer_id();
+    if let Err(e) = db.record_peer_outcome(&peer_id, outcome).await {
+        tracing::error!(
+            "Could not record the outcome of the swap for {}: {:#}",
+            peer_id,
+            e
+        );
     }
 }
 
@@ -146,14 +383,75 @@ pub struct FinishedSwap {
     pub swap: SwapKind,
     pub peer: ActivePeer,
     pub final_timestamp: DateTime<Utc>,
+    pub outcome: history::Outcome,
+    pub swap_outcome: SwapOutcome,
 }
 
 impl FinishedSwap {
-    pub fn new(swap: SwapKind, taker: ActivePeer, final_timestamp: DateTime<Utc>) -> Self {
+    pub fn new(
+        swap: SwapKind,
+        taker: ActivePeer,
+        final_timestamp: DateTime<Utc>,
+        outcome: history::Outcome,
+        swap_outcome: SwapOutcome,
+    ) -> Self {
         Self {
             swap,
             peer: taker,
             final_timestamp,
+            outcome,
+            swap_outcome,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn withdraw_amount_parses_all() {
+        let amount = parse_bitcoin_or_all("all").unwrap();
+
+        assert_eq!(amount, WithdrawAmount::All);
+    }
+
+    #[test]
+    fn withdraw_amount_parses_nominal_amount() {
+        let amount = parse_dai_or_all("3.2").unwrap();
+
+        assert_eq!(
+            amount,
+            WithdrawAmount::Exactly(dai::Amount::from_dai_trunc(3.2).unwrap())
+        );
+    }
+
+    #[test]
+    fn withdraw_amount_rejects_garbage() {
+        let amount = parse_ether_or_all("everything");
+
+        assert!(amount.is_err());
+    }
+
+    #[test]
+    fn bob_hbit_herc20_trade_is_a_buy() {
+        use crate::{swap::SwapParams, StaticStub};
+
+        // Bob in a HbitHerc20 swap locks 4 DAI for 0.12345678 BTC
+        let swap = SwapKind::HbitHerc20(SwapParams {
+            mid_market_rate: Some(crate::rate::rate(10_000.0)),
+            ..SwapParams::static_stub()
+        });
+
+        let trade = into_history_trade(
+            libp2p::PeerId::random(),
+            swap,
+            history::Outcome::Redeemed,
+            SwapOutcome::Completed,
+            Settlement::default(),
+        );
+
+        assert!(matches!(trade.position, history::Position::Buy));
+        assert_eq!(trade.realized_pnl_dai.unwrap().to_string(), "1230.5678");
+    }
+}
@@ -0,0 +1,391 @@
+//! Replay recorded mid-market rates through the maker as configured, to see
+//! how the spread and the limits would have fared.
+//!
+//! At each recorded rate the orders published for the previous rate are taken
+//! if the market moved past them, as a taker would, and the swaps are settled
+//! right away. Gas and the time swaps take are not accounted for, the bitcoin
+//! fee of a swap is assumed to be the maximum possible fee.
+
+use super::{parse_bitcoin, parse_dai, report::signed_int_to_float};
+use crate::{
+    bitcoin,
+    config::Settings,
+    ethereum::dai,
+    history::{self, Float},
+    maker::TakeRequestDecision,
+    order::BtcDaiOrderForm,
+    service::new_maker,
+    Maker, MidMarketRate, Rate, Spread,
+};
+use chrono::{DateTime, Utc};
+use comit::Position;
+use libp2p::PeerId;
+use num::{BigInt, BigUint};
+use serde::{Deserialize, Serialize};
+use std::{convert::TryFrom, fmt, path::PathBuf};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug, Clone)]
+pub struct Backtest {
+    /// CSV file with `timestamp` and `rate` columns, or JSON array of objects
+    /// with these fields if ending in `.json`. The rate is in dai per bitcoin
+    #[structopt(parse(from_os_str))]
+    pub rates: PathBuf,
+    /// Bitcoin held at the start
+    #[structopt(long, parse(try_from_str = parse_bitcoin))]
+    pub bitcoin: bitcoin::Amount,
+    /// Dai held at the start
+    #[structopt(long, parse(try_from_str = parse_dai))]
+    pub dai: dai::Amount,
+    /// Spread in permyriad instead of the configured one
+    #[structopt(long)]
+    pub spread: Option<u16>,
+    /// Maximum amount of bitcoin to sell per order instead of the configured
+    /// one
+    #[structopt(long, parse(try_from_str = parse_bitcoin))]
+    pub max_sell_bitcoin: Option<bitcoin::Amount>,
+    /// Maximum amount of dai to sell per order instead of the configured one
+    #[structopt(long, parse(try_from_str = parse_dai))]
+    pub max_sell_dai: Option<dai::Amount>,
+    /// Bitcoin fee per swap instead of the configured maximum possible fee
+    #[structopt(long, parse(try_from_str = parse_bitcoin))]
+    pub bitcoin_fee: Option<bitcoin::Amount>,
+    /// Print the results as a table or as JSON
+    #[structopt(long, default_value = "table")]
+    pub format: BacktestFormat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, strum_macros::EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum BacktestFormat {
+    Table,
+    Json,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct RecordedRate {
+    timestamp: DateTime<Utc>,
+    rate: f64,
+}
+
+/// An order taken during the replay.
+#[derive(Debug, Clone, Serialize)]
+struct Fill {
+    timestamp: DateTime<Utc>,
+    position: String,
+    bitcoin: String,
+    dai: String,
+    executed_rate: Float,
+}
+
+/// The outcome of the replay. The P&L compares the final inventory to the
+/// initial one, both valued at the last rate.
+#[derive(Debug, Clone, Serialize)]
+struct BacktestReport {
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    rates: usize,
+    buys: usize,
+    sells: usize,
+    initial_bitcoin: String,
+    initial_dai: String,
+    final_bitcoin: String,
+    final_dai: String,
+    bitcoin_drift: String,
+    bitcoin_fees: String,
+    pnl_dai: String,
+    fills: Vec<Fill>,
+}
+
+pub fn backtest(settings: &Settings, arguments: Backtest) -> anyhow::Result<String> {
+    let rates = read_rates(&arguments.rates)?;
+
+    let mut settings = settings.clone();
+    if let Some(spread) = arguments.spread {
+        settings.maker.spread = Spread::new(spread)?;
+    }
+    if let Some(max_sell_bitcoin) = arguments.max_sell_bitcoin {
+        settings.maker.max_sell.bitcoin = Some(max_sell_bitcoin);
+    }
+    if let Some(max_sell_dai) = arguments.max_sell_dai {
+        settings.maker.max_sell.dai = Some(max_sell_dai);
+    }
+    if let Some(bitcoin_fee) = arguments.bitcoin_fee {
+        settings.maker.maximum_possible_fee.bitcoin = bitcoin_fee;
+    }
+
+    let report = replay(&settings, arguments.bitcoin, arguments.dai, &rates)?;
+
+    match arguments.format {
+        BacktestFormat::Json => Ok(serde_json::to_string_pretty(&report)?),
+        BacktestFormat::Table => Ok(report.to_string()),
+    }
+}
+
+fn read_rates(path: &PathBuf) -> anyhow::Result<Vec<RecordedRate>> {
+    let mut rates = if path
+        .extension()
+        .map_or(false, |extension| extension == "json")
+    {
+        serde_json::from_reader(std::fs::File::open(path)?)?
+    } else {
+        csv::Reader::from_path(path)?
+            .deserialize()
+            .collect::<Result<Vec<RecordedRate>, _>>()?
+    };
+    rates.sort_by_key(|rate| rate.timestamp);
+
+    Ok(rates)
+}
+
+/// The maker together with the wallets it reports the balances of, and the
+/// orders it last published.
+struct Replay {
+    maker: Maker,
+    btc_wallet: bitcoin::Amount,
+    dai_wallet: dai::Amount,
+    sell_orders: Vec<BtcDaiOrderForm>,
+    buy_orders: Vec<BtcDaiOrderForm>,
+    fills: Vec<Fill>,
+    bitcoin_fees: bitcoin::Amount,
+}
+
+fn replay(
+    settings: &Settings,
+    initial_bitcoin: bitcoin::Amount,
+    initial_dai: dai::Amount,
+    rates: &[RecordedRate],
+) -> anyhow::Result<BacktestReport> {
+    let (first, last) = match (rates.first(), rates.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => anyhow::bail!("No rates to replay"),
+    };
+
+    // The recorded moves happened over a longer time than the replay takes,
+    // they are not fed to the circuit breaker.
+    let maker = new_maker(
+        settings,
+        initial_bitcoin,
+        initial_dai.clone(),
+        mid_market_rate(first.rate)?,
+    )
+    .with_circuit_breaker(Default::default());
+    let mut replay = Replay {
+        sell_orders: maker.new_sell_orders().unwrap_or_default(),
+        buy_orders: maker.new_buy_orders().unwrap_or_default(),
+        maker,
+        btc_wallet: initial_bitcoin,
+        dai_wallet: initial_dai.clone(),
+        fills: Vec::new(),
+        bitcoin_fees: bitcoin::Amount::ZERO,
+    };
+
+    for recorded in &rates[1..] {
+        replay.step(recorded)?;
+    }
+
+    let last_rate = Rate::try_from(last.rate)?;
+    let initial_value = value_in_attodai(initial_bitcoin, &initial_dai, last_rate);
+    let final_value = value_in_attodai(replay.btc_wallet, &replay.dai_wallet, last_rate);
+    let drift = BigInt::from(replay.btc_wallet.as_sat()) - BigInt::from(initial_bitcoin.as_sat());
+
+    Ok(BacktestReport {
+        from: first.timestamp,
+        to: last.timestamp,
+        rates: rates.len(),
+        buys: replay.count(Position::Buy),
+        sells: replay.count(Position::Sell),
+        initial_bitcoin: initial_bitcoin.to_string(),
+        initial_dai: initial_dai.to_string(),
+        final_bitcoin: replay.btc_wallet.to_string(),
+        final_dai: replay.dai_wallet.to_string(),
+        bitcoin_drift: signed_int_to_float(&drift, 8),
+        bitcoin_fees: replay.bitcoin_fees.to_string(),
+        pnl_dai: signed_int_to_float(&(final_value - initial_value), 18),
+        fills: replay.fills,
+    })
+}
+
+impl Replay {
+    /// Orders are taken before the maker learns of the new rate, as when a
+    /// taker is faster than our rate source.
+    fn step(&mut self, recorded: &RecordedRate) -> anyhow::Result<()> {
+        let rate = Rate::try_from(recorded.rate)?;
+
+        let taken = self
+            .sell_orders
+            .iter()
+            .chain(self.buy_orders.iter())
+            .filter(|order| {
+                order
+                    .is_as_profitable_as(rate)
+                    .map_or(false, |as_good| !as_good)
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        for order in taken {
+            self.take(order, recorded.timestamp)?;
+        }
+
+        if let Some(orders) = self.maker.update_rate(MidMarketRate::new(rate))? {
+            self.sell_orders = orders.new_sell_orders;
+            self.buy_orders = orders.new_buy_orders;
+        }
+
+        Ok(())
+    }
+
+    fn take(&mut self, order: BtcDaiOrderForm, timestamp: DateTime<Utc>) -> anyhow::Result<()> {
+        let decision = self
+            .maker
+            .process_taken_order(&PeerId::random(), order.clone())?;
+        if decision != TakeRequestDecision::GoForSwap {
+            return Ok(());
+        }
+
+        let base = bitcoin::Amount::from(order.quantity);
+        let quote = dai::Amount::from(order.quote());
+        match order.position {
+            Position::Sell => {
+                self.btc_wallet = self.btc_wallet - base - self.maker.btc_fee;
+                self.dai_wallet = self.dai_wallet.clone() + quote.clone();
+                self.bitcoin_fees = self.bitcoin_fees + self.maker.btc_fee;
+                self.maker.free_funds(None, Some(base))?;
+            }
+            Position::Buy => {
+                self.btc_wallet = self.btc_wallet + base;
+                self.dai_wallet = self.dai_wallet.clone() - quote.clone();
+                self.maker.free_funds(Some(quote.clone()), None)?;
+            }
+        }
+
+        self.fills.push(Fill {
+            timestamp,
+            position: format!("{:?}", order.position),
+            bitcoin: base.to_string(),
+            dai: quote.to_string(),
+            executed_rate: history::executed_rate(&BigUint::from(base.as_sat()), &quote.as_atto()),
+        });
+
+        if let Some(orders) = self.maker.update_bitcoin_balance(self.btc_wallet)? {
+            self.sell_orders = orders;
+        }
+        if let Some(orders) = self.maker.update_dai_balance(self.dai_wallet.clone())? {
+            self.buy_orders = orders;
+        }
+
+        Ok(())
+    }
+
+    fn count(&self, position: Position) -> usize {
+        let position = format!("{:?}", position);
+        self.fills
+            .iter()
+            .filter(|fill| fill.position == position)
+            .count()
+    }
+}
+
+fn mid_market_rate(rate: f64) -> anyhow::Result<MidMarketRate> {
+    Ok(MidMarketRate::new(Rate::try_from(rate)?))
+}
+
+/// A rate integer is the number of attodai per satoshi.
+fn value_in_attodai(bitcoin: bitcoin::Amount, dai: &dai::Amount, rate: Rate) -> BigInt {
+    BigInt::from(BigUint::from(bitcoin.as_sat()) * rate.integer() + dai.as_atto())
+}
+
+impl fmt::Display for BacktestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<25} {:<8} {:>14} {:>14} {:>14}",
+            "timestamp", "position", "bitcoin", "dai", "executed_rate"
+        )?;
+        for fill in &self.fills {
+            writeln!(
+                f,
+                "{:<25} {:<8} {:>14} {:>14} {:>14}",
+                fill.timestamp.to_rfc3339(),
+                fill.position,
+                fill.bitcoin,
+                fill.dai,
+                fill.executed_rate.to_string()
+            )?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "Rates: {} from {} to {}", self.rates, self.from, self.to)?;
+        writeln!(f, "Buys: {}", self.buys)?;
+        writeln!(f, "Sells: {}", self.sells)?;
+        writeln!(
+            f,
+            "Bitcoin: {} -> {}",
+            self.initial_bitcoin, self.final_bitcoin
+        )?;
+        writeln!(f, "Dai: {} -> {}", self.initial_dai, self.final_dai)?;
+        writeln!(f, "Bitcoin drift: {}", self.bitcoin_drift)?;
+        writeln!(f, "Bitcoin fees: {}", self.bitcoin_fees)?;
+        write!(f, "P&L: {} DAI", self.pnl_dai)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bitcoin::amount::btc, config::File, ethereum::dai::dai};
+    use std::str::FromStr;
+
+    fn settings() -> Settings {
+        let mut settings = Settings::from_config_file_and_defaults(File::default()).unwrap();
+        settings.maker.spread = Spread::new(500).unwrap();
+        settings
+    }
+
+    fn rates(rates: &[f64]) -> Vec<RecordedRate> {
+        let start = DateTime::<Utc>::from_str("2020-07-10T08:00:00Z").unwrap();
+
+        rates
+            .iter()
+            .enumerate()
+            .map(|(minutes, rate)| RecordedRate {
+                timestamp: start + chrono::Duration::minutes(i64::try_from(minutes).unwrap()),
+                rate: *rate,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sell_order_is_filled_when_the_rate_rises_past_it() {
+        let report = replay(
+            &settings(),
+            btc(1.0),
+            dai(10_000.0),
+            &rates(&[10_000.0, 11_000.0]),
+        )
+        .unwrap();
+
+        assert_eq!(report.sells, 1);
+        assert_eq!(report.buys, 0);
+        assert!(report.bitcoin_drift.starts_with('-'));
+    }
+
+    #[test]
+    fn nothing_is_filled_while_the_rate_stays_within_the_spread() {
+        let report = replay(
+            &settings(),
+            btc(1.0),
+            dai(10_000.0),
+            &rates(&[10_000.0, 10_400.0, 9_600.0, 10_000.0]),
+        )
+        .unwrap();
+
+        assert!(report.fills.is_empty());
+        assert_eq!(report.pnl_dai, "0");
+    }
+
+    #[test]
+    fn replay_needs_rates() {
+        assert!(replay(&settings(), btc(1.0), dai(10_000.0), &[]).is_err());
+    }
+}
@@ -1,8 +1,50 @@
-use crate::{bitcoin, ethereum};
+use crate::{
+    bitcoin,
+    config::Settings,
+    ethereum::{self, dai},
+    swap::{Database, SwapParams},
+};
+use serde::Serialize;
+use std::fmt;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug, Clone, Copy)]
+pub struct Balance {
+    /// Print the balances as JSON
+    #[structopt(long)]
+    pub json: bool,
+}
+
+/// Funds, addresses and the funds reserved for the ongoing swaps. A value that
+/// could not be retrieved is replaced by the problem encountered.
+#[derive(Debug, Serialize)]
+struct Balances {
+    bitcoin: String,
+    dai: String,
+    ether: String,
+    bitcoin_reserved: String,
+    dai_reserved: String,
+    bitcoin_address: String,
+    ethereum_address: String,
+}
+
+impl fmt::Display for Balances {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Bitcoin: {}", self.bitcoin)?;
+        writeln!(f, "Dai: {}", self.dai)?;
+        writeln!(f, "Ether: {}", self.ether)?;
+        writeln!(f, "Bitcoin reserved: {}", self.bitcoin_reserved)?;
+        writeln!(f, "Dai reserved: {}", self.dai_reserved)?;
+        writeln!(f, "Bitcoin address: {}", self.bitcoin_address)?;
+        write!(f, "Dai/Ether address: {}", self.ethereum_address)
+    }
+}
 
 pub async fn balance(
     ethereum_wallet: ethereum::Wallet,
     bitcoin_wallet: bitcoin::Wallet,
+    settings: &Settings,
+    arguments: Balance,
 ) -> anyhow::Result<String> {
     let bitcoin_balance = bitcoin_wallet
         .balance()
@@ -20,22 +62,124 @@ pub async fn balance(
         .map(|amount| amount.to_string())
         .unwrap_or_else(|e| format!("Problem encountered: {:?}", e));
 
-    Ok(format!(
-        "Bitcoin: {}\nDai: {}\nEther: {}",
-        bitcoin_balance, dai_balance, ether_balance
-    ))
+    // The database is locked while nectar is trading
+    #[cfg(not(test))]
+    let db = Database::new(&settings.data.dir.join("database"));
+    #[cfg(test)]
+    let db = Database::new_test();
+    let (bitcoin_reserved, dai_reserved) =
+        match db.and_then(|db| reserved_funds(&db, settings.maker.maximum_possible_fee.bitcoin)) {
+            Ok((bitcoin_reserved, dai_reserved)) => {
+                (bitcoin_reserved.to_string(), dai_reserved.to_string())
+            }
+            Err(e) => {
+                let problem = format!("Problem encountered: {:?}", e);
+                (problem.clone(), problem)
+            }
+        };
+
+    let bitcoin_address = bitcoin_wallet
+        .new_address()
+        .await
+        .map(|address| address.to_string())
+        .unwrap_or_else(|e| format!("Problem encountered: {:?}", e));
+
+    let balances = Balances {
+        bitcoin: bitcoin_balance,
+        dai: dai_balance,
+        ether: ether_balance,
+        bitcoin_reserved,
+        dai_reserved,
+        bitcoin_address,
+        ethereum_address: ethereum_wallet.account().to_string(),
+    };
+
+    if arguments.json {
+        Ok(serde_json::to_string_pretty(&balances)?)
+    } else {
+        Ok(balances.to_string())
+    }
+}
+
+/// The funds reserved for the ongoing swaps, as reserved by the maker upon
+/// resuming them.
+fn reserved_funds(
+    db: &Database,
+    btc_fee: bitcoin::Amount,
+) -> anyhow::Result<(bitcoin::Amount, dai::Amount)> {
+    let reserved = db.all_swaps()?.into_iter().fold(
+        (bitcoin::Amount::ZERO, dai::Amount::zero()),
+        |(bitcoin_reserved, dai_reserved), swap| {
+            let SwapParams {
+                hbit_params,
+                herc20_params,
+                ..
+            } = swap.params();
+
+            if swap.locks_bitcoin() {
+                (
+                    bitcoin_reserved + bitcoin::Amount::from(hbit_params.shared.asset) + btc_fee,
+                    dai_reserved,
+                )
+            } else {
+                (
+                    bitcoin_reserved,
+                    dai_reserved + dai::Amount::from(herc20_params.asset),
+                )
+            }
+        },
+    );
+
+    Ok(reserved)
 }
 
-#[cfg(all(test, feature = "test-docker"))]
+#[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{ethereum, test_harness, Seed};
-    use comit::ethereum::ChainId;
+    use crate::{config::SwapRole, swap::SwapKind, StaticStub};
+
+    #[tokio::test]
+    async fn funds_of_ongoing_swaps_are_reserved() {
+        let db = Database::new_test().unwrap();
+        db.insert_swap(SwapKind::HbitHerc20(SwapParams::static_stub()))
+            .await
+            .unwrap();
+        db.insert_swap(SwapKind::Herc20Hbit(SwapParams::static_stub()))
+            .await
+            .unwrap();
+
+        let (bitcoin_reserved, dai_reserved) =
+            reserved_funds(&db, bitcoin::Amount::from_sat(1_000)).unwrap();
+
+        assert_eq!(bitcoin_reserved, bitcoin::Amount::from_sat(12_346_678));
+        assert_eq!(dai_reserved, dai::Amount::from_dai_trunc(4.0).unwrap());
+    }
+
+    #[tokio::test]
+    async fn funds_locked_as_alice_are_reserved() {
+        let db = Database::new_test().unwrap();
+        db.insert_swap(SwapKind::HbitHerc20(SwapParams {
+            role: SwapRole::Alice,
+            ..SwapParams::static_stub()
+        }))
+        .await
+        .unwrap();
+
+        let (bitcoin_reserved, dai_reserved) =
+            reserved_funds(&db, bitcoin::Amount::from_sat(1_000)).unwrap();
+
+        assert_eq!(bitcoin_reserved, bitcoin::Amount::from_sat(12_346_678));
+        assert_eq!(dai_reserved, dai::Amount::zero());
+    }
 
     // Run cargo test with `--ignored --nocapture` to see the `println output`
+    #[cfg(feature = "test-docker")]
     #[ignore]
     #[tokio::test]
     async fn balance_command() {
+        use crate::{config::File, test_harness, Seed};
+        use comit::ethereum::ChainId;
+
         let client = testcontainers::clients::Cli::default();
         let seed = Seed::random().unwrap();
 
@@ -61,7 +205,13 @@ mod tests {
         .await
         .unwrap();
 
-        let stdout = balance(ethereum_wallet, bitcoin_wallet).await.unwrap();
+        let settings = Settings::from_config_file_and_defaults(File::default()).unwrap();
+
+        let stdout = balance(ethereum_wallet, bitcoin_wallet, &settings, Balance {
+            json: false,
+        })
+        .await
+        .unwrap();
         println!("{}", stdout);
     }
 }
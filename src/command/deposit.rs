@@ -1,25 +1,175 @@
-use crate::{bitcoin, ethereum};
+use crate::{
+    bitcoin,
+    ethereum::{self, dai, ether},
+};
+use futures_timer::Delay;
+use num256::Uint256;
+use qrcode::{render::unicode::Dense1x2, QrCode};
+use std::time::Duration;
+use structopt::StructOpt;
+
+const WATCH_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(StructOpt, Debug, Clone, Copy)]
+pub struct Deposit {
+    /// Also print the addresses as QR codes
+    #[structopt(long)]
+    pub qr: bool,
+    /// Wait until a deposit is confirmed
+    #[structopt(long)]
+    pub watch: bool,
+    /// Number of confirmations a Bitcoin deposit needs when watching
+    #[structopt(long, default_value = "1")]
+    pub confirmations: u32,
+}
 
 pub async fn deposit(
-    ethereum_wallet: ethereum::Wallet,
-    bitcoin_wallet: bitcoin::Wallet,
+    ethereum_wallet: &ethereum::Wallet,
+    bitcoin_wallet: &bitcoin::Wallet,
+    arguments: Deposit,
 ) -> anyhow::Result<String> {
     let bitcoin_address = bitcoin_wallet
         .new_address()
         .await
-        .map(|address| address.to_string())
-        .unwrap_or_else(|e| format!("Problem encountered: {:?}", e));
-    let ethereum_address = ethereum_wallet.account();
+        .map(|address| address.to_string());
+    let ethereum_address = ethereum_wallet.account().to_string();
 
-    Ok(format!(
-        "Bitcoin: {}\nDai/Ether: {}",
-        bitcoin_address, ethereum_address
-    ))
+    let bitcoin = match (bitcoin_address, arguments.qr) {
+        (Ok(address), true) => format!("{}\n{}", address, qr_code(&address)?),
+        (Ok(address), false) => address,
+        (Err(e), _) => format!("Problem encountered: {:?}", e),
+    };
+    let ethereum = if arguments.qr {
+        format!("{}\n{}", ethereum_address, qr_code(&ethereum_address)?)
+    } else {
+        ethereum_address
+    };
+
+    Ok(format!("Bitcoin: {}\nDai/Ether: {}", bitcoin, ethereum))
 }
 
-#[cfg(all(test, feature = "test-docker"))]
+/// Polls the wallets until one of the balances increases, a Bitcoin deposit
+/// only being taken into account once it has `confirmations` confirmations.
+pub async fn watch_deposit(
+    ethereum_wallet: &ethereum::Wallet,
+    bitcoin_wallet: &bitcoin::Wallet,
+    confirmations: u32,
+) -> anyhow::Result<String> {
+    let initial = Balances::fetch(ethereum_wallet, bitcoin_wallet, confirmations).await?;
+
+    loop {
+        Delay::new(WATCH_INTERVAL).await;
+
+        match Balances::fetch(ethereum_wallet, bitcoin_wallet, confirmations).await {
+            Ok(current) => {
+                if let Some(deposit) = current.deposited_since(&initial) {
+                    return Ok(deposit);
+                }
+            }
+            Err(e) => tracing::warn!("Could not fetch balances: {:#}", e),
+        }
+    }
+}
+
+fn qr_code(data: &str) -> anyhow::Result<String> {
+    let code = QrCode::new(data.as_bytes())?;
+
+    Ok(code
+        .render::<Dense1x2>()
+        .dark_color(Dense1x2::Light)
+        .light_color(Dense1x2::Dark)
+        .build())
+}
+
+#[derive(Debug, Clone)]
+struct Balances {
+    bitcoin: bitcoin::Amount,
+    dai: dai::Amount,
+    ether: ether::Amount,
+}
+
+impl Balances {
+    async fn fetch(
+        ethereum_wallet: &ethereum::Wallet,
+        bitcoin_wallet: &bitcoin::Wallet,
+        confirmations: u32,
+    ) -> anyhow::Result<Self> {
+        let (bitcoin, dai, ether) = futures::try_join!(
+            bitcoin_wallet.confirmed_balance(confirmations),
+            ethereum_wallet.dai_balance(),
+            ethereum_wallet.ether_balance()
+        )?;
+
+        Ok(Balances {
+            bitcoin,
+            dai,
+            ether,
+        })
+    }
+
+    /// Describes the balances that increased since `initial`, if any.
+    fn deposited_since(&self, initial: &Balances) -> Option<String> {
+        let mut deposits = Vec::new();
+
+        if self.bitcoin > initial.bitcoin {
+            deposits.push(format!("Bitcoin balance: {}", self.bitcoin));
+        }
+        if self.dai > initial.dai {
+            deposits.push(format!("Dai balance: {}", self.dai));
+        }
+        if Uint256::from(self.ether.clone()) > Uint256::from(initial.ether.clone()) {
+            deposits.push(format!("Ether balance: {}", self.ether));
+        }
+
+        if deposits.is_empty() {
+            return None;
+        }
+
+        Some(format!("Deposit confirmed\n{}", deposits.join("\n")))
+    }
+}
+
+#[cfg(test)]
 mod tests {
     use super::*;
+
+    fn balances(bitcoin: u64, dai: u64, ether: u64) -> Balances {
+        Balances {
+            bitcoin: bitcoin::Amount::from_sat(bitcoin),
+            dai: dai::Amount::from_atto(dai.into()),
+            ether: ether::Amount::from(ether),
+        }
+    }
+
+    #[test]
+    fn no_deposit_when_balances_did_not_increase() {
+        let initial = balances(10, 10, 10);
+
+        assert_eq!(balances(10, 5, 10).deposited_since(&initial), None);
+    }
+
+    #[test]
+    fn deposit_reports_increased_balances() {
+        let initial = balances(10, 10, 10);
+
+        let deposit = balances(20, 10, 11).deposited_since(&initial).unwrap();
+
+        assert!(deposit.contains("Bitcoin balance"));
+        assert!(!deposit.contains("Dai balance"));
+        assert!(deposit.contains("Ether balance"));
+    }
+
+    #[test]
+    fn qr_code_is_rendered() {
+        let qr_code = qr_code("bcrt1qk60fmayw8xrtqd4ru2ut8kgv08wyqpdzqkj55h").unwrap();
+
+        assert!(qr_code.lines().count() > 10);
+    }
+}
+
+#[cfg(all(test, feature = "test-docker"))]
+mod docker_tests {
+    use super::*;
     use crate::{test_harness, Seed};
     use comit::ethereum::ChainId;
 
@@ -52,7 +202,13 @@ mod tests {
         .await
         .unwrap();
 
-        let stdout = deposit(ethereum_wallet, bitcoin_wallet).await.unwrap();
+        let stdout = deposit(&ethereum_wallet, &bitcoin_wallet, Deposit {
+            qr: true,
+            watch: false,
+            confirmations: 1,
+        })
+        .await
+        .unwrap();
         println!("{}", stdout);
     }
 }
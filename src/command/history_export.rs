@@ -0,0 +1,304 @@
+//! Export the trade history, including its archives, filtered by date or
+//! taker.
+
+use crate::{
+    config::Settings,
+    float_maths::multiply_pow_ten,
+    history::{self, Float},
+    Rate,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use libp2p::PeerId;
+use num::{BigUint, ToPrimitive};
+use serde::Serialize;
+use serde_json::Value;
+use std::str::FromStr;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug, Clone)]
+pub struct History {
+    /// Print the trades as CSV, as a JSON array or as one JSON object per
+    /// line
+    #[structopt(long, default_value = "csv")]
+    pub format: ExportFormat,
+    /// Only the trades finished on or after this date, e.g. 2020-07-01
+    #[structopt(long)]
+    pub from: Option<NaiveDate>,
+    /// Only the trades finished on or before this date, e.g. 2020-07-31
+    #[structopt(long)]
+    pub to: Option<NaiveDate>,
+    /// Only the trades with this taker, can be repeated
+    #[structopt(long = "taker")]
+    pub takers: Vec<PeerId>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, strum_macros::EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Json,
+    Ndjson,
+}
+
+/// A trade of the history with its realised P&L computed from the recorded
+/// mid-market rate, amounts and rates are written with a `.` whatever the
+/// decimal separator of the history.
+#[derive(Debug, Clone, Serialize)]
+struct ExportedTrade {
+    swap_id: String,
+    utc_start_timestamp: String,
+    utc_final_timestamp: String,
+    #[serde(skip)]
+    final_timestamp: DateTime<Utc>,
+    position: String,
+    outcome: String,
+    peer: String,
+    base_precise_amount: String,
+    quote_precise_amount: String,
+    mid_market_rate: Option<String>,
+    executed_rate: Option<String>,
+    /// Only for redeemed trades of which the mid-market rate was recorded
+    realized_pnl_dai: Option<Float>,
+    bitcoin_fee_sat: Option<String>,
+}
+
+pub fn export_history(settings: &Settings, arguments: History) -> anyhow::Result<String> {
+    let records = history::read_all_records(&settings.data.dir, &settings.history)?;
+
+    let separator = settings.history.decimal_separator.unwrap_or('.');
+    let mut trades = records
+        .iter()
+        .map(|record| ExportedTrade::from_record(record, separator))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    trades.retain(|trade| arguments.includes(trade));
+    trades.sort_by_key(|trade| trade.final_timestamp);
+
+    match arguments.format {
+        ExportFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            for trade in &trades {
+                writer.serialize(trade)?;
+            }
+            Ok(String::from_utf8(writer.into_inner()?)?)
+        }
+        ExportFormat::Json => Ok(serde_json::to_string_pretty(&trades)?),
+        ExportFormat::Ndjson => Ok(trades
+            .iter()
+            .map(serde_json::to_string)
+            .collect::<Result<Vec<_>, _>>()?
+            .join("\n")),
+    }
+}
+
+impl History {
+    fn includes(&self, trade: &ExportedTrade) -> bool {
+        let date = trade.final_timestamp.date().naive_utc();
+
+        self.from.map_or(true, |from| date >= from)
+            && self.to.map_or(true, |to| date <= to)
+            && (self.takers.is_empty()
+                || self
+                    .takers
+                    .iter()
+                    .any(|taker| taker.to_string() == trade.peer))
+    }
+}
+
+impl ExportedTrade {
+    /// Records of the CSV history are flat, the legs of the JSON Lines
+    /// history are nested. Columns added over time are optional.
+    fn from_record(record: &Value, decimal_separator: char) -> anyhow::Result<Self> {
+        let field = |column: &str, pointer: &str| {
+            record
+                .get(column)
+                .or_else(|| record.pointer(pointer))
+                .and_then(Value::as_str)
+                .filter(|value| !value.is_empty())
+        };
+        let required = |column: &str, pointer: &str| {
+            field(column, pointer)
+                .ok_or_else(|| anyhow::anyhow!("History record without {}", column))
+        };
+        let decimal = |column: &str, pointer: &str| {
+            field(column, pointer).map(|decimal| decimal.replace(decimal_separator, "."))
+        };
+
+        let utc_final_timestamp = required("utc_final_timestamp", "/utc_final_timestamp")?;
+        let position = match required("position", "/position")? {
+            "Buy" => history::Position::Buy,
+            "Sell" => history::Position::Sell,
+            position => anyhow::bail!("Unknown position {}", position),
+        };
+        let outcome = required("outcome", "/outcome")?;
+        let base_precise_amount = required("base_precise_amount", "/base/precise_amount")?;
+        let quote_precise_amount = required("quote_precise_amount", "/quote/precise_amount")?;
+        let mid_market_rate = decimal("mid_market_rate", "/mid_market_rate");
+
+        let realized_pnl_dai = match &mid_market_rate {
+            Some(rate) if outcome == "Redeemed" => Some(history::realized_pnl_dai(
+                position,
+                &BigUint::from_str(base_precise_amount)?,
+                &BigUint::from_str(quote_precise_amount)?,
+                parse_rate(rate)?,
+            )),
+            _ => None,
+        };
+
+        Ok(ExportedTrade {
+            swap_id: required("swap_id", "/swap_id")?.to_owned(),
+            utc_start_timestamp: required("utc_start_timestamp", "/utc_start_timestamp")?
+                .to_owned(),
+            utc_final_timestamp: utc_final_timestamp.to_owned(),
+            final_timestamp: DateTime::parse_from_rfc3339(utc_final_timestamp)?.with_timezone(&Utc),
+            position: format!("{:?}", position),
+            outcome: outcome.to_owned(),
+            peer: required("peer", "/peer")?.to_owned(),
+            base_precise_amount: base_precise_amount.to_owned(),
+            quote_precise_amount: quote_precise_amount.to_owned(),
+            mid_market_rate,
+            executed_rate: decimal("executed_rate", "/executed_rate"),
+            realized_pnl_dai,
+            bitcoin_fee_sat: field("bitcoin_fee_sat", "/base/fee_sat").map(ToOwned::to_owned),
+        })
+    }
+}
+
+/// A rate as recorded in the history, truncated to the precision of [`Rate`].
+fn parse_rate(rate: &str) -> anyhow::Result<Rate> {
+    let (int, frac) = match rate.find('.') {
+        Some(index) => (&rate[..index], &rate[index + 1..]),
+        None => (rate, ""),
+    };
+    let frac = frac
+        .chars()
+        .take(usize::from(Rate::PRECISION))
+        .collect::<String>();
+
+    multiply_pow_ten(&format!("{}.{}", int, frac), Rate::PRECISION)?
+        .to_u64()
+        .map(Rate::new)
+        .ok_or_else(|| anyhow::anyhow!("Rate {} is unexpectedly large", rate))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const TAKER: &str = "QmUJF1AzhjUfDU1ifzkyuHy26SCnNHbPaVHpX1WYxYYgZg";
+
+    fn records() -> Vec<Value> {
+        vec![
+            json!({
+                "swap_id": "ad2652ca-ecf2-4cc6-b35c-b4351ac28a34",
+                "utc_start_timestamp": "2020-07-10T08:40:00+00:00",
+                "utc_final_timestamp": "2020-07-10T08:48:26.456+00:00",
+                "position": "Buy",
+                "outcome": "Redeemed",
+                "peer": TAKER,
+                "base_precise_amount": "1000000",
+                "quote_precise_amount": "99000000000000000000",
+                "mid_market_rate": "10000",
+                "executed_rate": "9900",
+                "realized_pnl_dai": "",
+                "bitcoin_fee_sat": ""
+            }),
+            json!({
+                "swap_id": "2e41cc6a-0a5e-4f1c-9d3c-ad0a9a5bd5f4",
+                "utc_start_timestamp": "2020-07-20T00:00:00+00:00",
+                "utc_final_timestamp": "2020-07-20T00:10:00+00:00",
+                "position": "Sell",
+                "outcome": "Redeemed",
+                "peer": "QmPjxWTvW7ZuzHTuF2ccKDfUKmasyLcpGfuU8gjbyYAtAB",
+                "mid_market_rate": "10000",
+                "executed_rate": "9950",
+                "base": { "precise_amount": "1000000", "fee_sat": "1234" },
+                "quote": { "precise_amount": "99500000000000000000" }
+            }),
+            json!({
+                "swap_id": "e9a1d2b8-4c5e-4a3b-8f6d-7c9b0a1e2f34",
+                "utc_start_timestamp": "2020-08-01T00:00:00+00:00",
+                "utc_final_timestamp": "2020-08-01T01:00:00+00:00",
+                "position": "Sell",
+                "outcome": "Refunded",
+                "peer": TAKER,
+                "base_precise_amount": "20000000",
+                "quote_precise_amount": "2012340000000000000000",
+                "mid_market_rate": "10000,5",
+                "executed_rate": "10061,7"
+            }),
+        ]
+    }
+
+    fn exported(arguments: &History) -> Vec<ExportedTrade> {
+        records()
+            .iter()
+            .map(|record| ExportedTrade::from_record(record, ','))
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap()
+            .into_iter()
+            .filter(|trade| arguments.includes(trade))
+            .collect()
+    }
+
+    fn arguments() -> History {
+        History {
+            format: ExportFormat::Csv,
+            from: None,
+            to: None,
+            takers: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn pnl_is_computed_from_the_recorded_rate_for_redeemed_trades() {
+        let trades = exported(&arguments());
+
+        let pnls = trades
+            .iter()
+            .map(|trade| {
+                trade
+                    .realized_pnl_dai
+                    .as_ref()
+                    .and_then(|pnl| serde_json::to_value(pnl).ok())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(pnls, vec![Some(json!("1")), Some(json!("-0.5")), None]);
+        assert_eq!(trades[2].mid_market_rate, Some("10000.5".to_owned()));
+    }
+
+    #[test]
+    fn trades_are_filtered_by_final_date() {
+        let arguments = History {
+            from: Some(NaiveDate::from_ymd(2020, 7, 20)),
+            to: Some(NaiveDate::from_ymd(2020, 7, 31)),
+            ..arguments()
+        };
+
+        let trades = exported(&arguments);
+
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].swap_id, "2e41cc6a-0a5e-4f1c-9d3c-ad0a9a5bd5f4");
+    }
+
+    #[test]
+    fn trades_are_filtered_by_taker() {
+        let arguments = History {
+            takers: vec![PeerId::from_str(TAKER).unwrap()],
+            ..arguments()
+        };
+
+        let trades = exported(&arguments);
+
+        assert_eq!(trades.len(), 2);
+        assert!(trades.iter().all(|trade| trade.peer == TAKER));
+    }
+
+    #[test]
+    fn rate_is_truncated_to_the_rate_precision() {
+        assert_eq!(
+            parse_rate("9123.123456789012").unwrap(),
+            Rate::new(91_231_234_567_890)
+        );
+    }
+}
@@ -0,0 +1,31 @@
+//! The peer id of nectar on the network is derived from the seed, it stays the
+//! same across restarts for takers who only trade with known makers.
+
+use crate::{config, config::Settings, network, Seed};
+use libp2p::PeerId;
+
+pub fn id(settings: &Settings) -> anyhow::Result<PeerId> {
+    let seed = config::Seed::from_data_dir(&settings.data.dir)?;
+
+    Ok(peer_id(&seed.into()))
+}
+
+fn peer_id(seed: &Seed) -> PeerId {
+    let identity = network::Seed::new(seed.bytes()).derive_libp2p_identity();
+
+    PeerId::from(identity.public())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn peer_id_only_depends_on_the_seed() {
+        let seed: Seed = config::Seed::random().unwrap().into();
+        let other_seed: Seed = config::Seed::random().unwrap().into();
+
+        assert_eq!(peer_id(&seed), peer_id(&seed));
+        assert_ne!(peer_id(&seed), peer_id(&other_seed));
+    }
+}
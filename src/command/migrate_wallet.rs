@@ -0,0 +1,50 @@
+//! Move the bitcoin of the wallet derived along the legacy paths to the one
+//! derived along the BIP-44 paths, once the BIP-44 derivation is configured.
+//! The ethereum account of the legacy derivation is the first BIP-44 account.
+
+use crate::{
+    bitcoin,
+    config::{self, Settings},
+    Seed,
+};
+
+pub async fn migrate_wallet(
+    seed: Seed,
+    settings: &Settings,
+    bitcoin_wallet: bitcoin::Wallet,
+) -> anyhow::Result<String> {
+    match (&settings.bitcoin.wallet, settings.derivation) {
+        (config::BitcoinWallet::External { .. }, _) => {
+            anyhow::bail!("The external bitcoind wallet is not derived from the seed")
+        }
+        (_, config::Derivation::Legacy) => {
+            anyhow::bail!("The bip44 derivation must be configured to migrate to it")
+        }
+        _ => {}
+    }
+
+    let legacy_wallet = bitcoin::Wallet::new_with_auth(
+        seed,
+        &settings.bitcoin.bitcoind,
+        &settings.bitcoin.wallet,
+        config::Derivation::Legacy,
+        settings.rpc,
+        settings.bitcoin.network,
+    )
+    .await?;
+
+    let balance = legacy_wallet.balance().await?;
+    if balance == bitcoin::Amount::ZERO {
+        return Ok("The legacy bitcoin wallet is empty, nothing to migrate".to_owned());
+    }
+
+    let address = bitcoin_wallet.new_address().await?;
+    let tx_id = legacy_wallet
+        .send_all_to_address(address.clone(), settings.bitcoin.network)
+        .await?;
+
+    Ok(format!(
+        "Sent the {} of the legacy bitcoin wallet, minus the fee, to {} in transaction {}",
+        balance, address, tx_id
+    ))
+}
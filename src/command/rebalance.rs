@@ -0,0 +1,206 @@
+//! Recommend rebalancing the inventory as per `[maker.rebalance]`. The drift
+//! is measured over the balance snapshots recorded while trading, if enabled in
+//! `[accounting]`.
+
+use crate::{
+    bitcoin,
+    config::Settings,
+    ethereum::{self, dai},
+    maker::{Assessment, RebalanceAdvisor},
+    mid_market_rate::{Aggregate, RateSource},
+    swap::{BalanceSnapshot, Database},
+    Rate,
+};
+use chrono::{DateTime, Utc};
+use num::BigUint;
+use serde::Serialize;
+use std::{fmt, str::FromStr};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug, Clone, Copy)]
+pub struct Rebalance {
+    /// Print the assessment as JSON
+    #[structopt(long)]
+    pub json: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct Advice {
+    bitcoin_share_permyriad: u32,
+    target_bitcoin_share_permyriad: u32,
+    /// Absent without balance snapshot over the last 24 hours.
+    drift_permyriad: Option<i64>,
+    recommendation: Option<String>,
+}
+
+impl fmt::Display for Advice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Bitcoin share: {} (target {})",
+            percent(i64::from(self.bitcoin_share_permyriad)),
+            percent(i64::from(self.target_bitcoin_share_permyriad))
+        )?;
+        match self.drift_permyriad {
+            Some(drift) => writeln!(f, "Drift over 24 hours: {}", percent(drift))?,
+            None => writeln!(f, "Drift over 24 hours: unknown, no balance snapshot")?,
+        }
+        match &self.recommendation {
+            Some(recommendation) => write!(f, "Recommendation: {}", recommendation),
+            None => write!(f, "Recommendation: none"),
+        }
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn percent(permyriad: i64) -> String {
+    format!("{:.2}%", permyriad as f64 / 100.0)
+}
+
+pub async fn rebalance(
+    ethereum_wallet: ethereum::Wallet,
+    bitcoin_wallet: bitcoin::Wallet,
+    settings: &Settings,
+    arguments: Rebalance,
+) -> anyhow::Result<String> {
+    let config = settings
+        .maker
+        .rebalance
+        .ok_or_else(|| anyhow::anyhow!("No rebalance configured, see [maker.rebalance]"))?;
+    let rate_source = Aggregate::from(settings.rate.clone());
+    let (bitcoin, dai, mid_market_rate) = futures::try_join!(
+        bitcoin_wallet.balance(),
+        ethereum_wallet.dai_balance(),
+        rate_source.mid_market_rate()
+    )?;
+
+    // The database is locked while nectar is trading
+    #[cfg(not(test))]
+    let db = Database::new(&settings.data.dir.join("database"));
+    #[cfg(test)]
+    let db = Database::new_test();
+    let snapshots = match db.and_then(|db| db.balance_snapshots()) {
+        Ok(snapshots) => snapshots,
+        Err(e) => {
+            tracing::warn!("Could not read the balance snapshots: {:#}", e);
+            Vec::new()
+        }
+    };
+
+    let mut advisor = RebalanceAdvisor::new(Some(config));
+    let Assessment {
+        bitcoin_share,
+        target_bitcoin_share,
+        drift,
+        recommendation,
+    } = assess(
+        &mut advisor,
+        &snapshots,
+        Utc::now(),
+        bitcoin,
+        &dai,
+        mid_market_rate.into(),
+    )
+    .ok_or_else(|| anyhow::anyhow!("The inventory is empty"))?;
+
+    let advice = Advice {
+        bitcoin_share_permyriad: bitcoin_share,
+        target_bitcoin_share_permyriad: target_bitcoin_share,
+        drift_permyriad: drift,
+        recommendation: recommendation.map(|recommendation| recommendation.to_string()),
+    };
+
+    if arguments.json {
+        Ok(serde_json::to_string_pretty(&advice)?)
+    } else {
+        Ok(advice.to_string())
+    }
+}
+
+/// Assess the current inventory after sampling the snapshots of the drift
+/// window, in chronological order.
+fn assess(
+    advisor: &mut RebalanceAdvisor,
+    snapshots: &[BalanceSnapshot],
+    now: DateTime<Utc>,
+    bitcoin: bitcoin::Amount,
+    dai: &dai::Amount,
+    mid_market_rate: Rate,
+) -> Option<Assessment> {
+    let window_start = now - RebalanceAdvisor::drift_window();
+    for snapshot in snapshots
+        .iter()
+        .filter(|snapshot| snapshot.taken_at >= window_start)
+    {
+        if let BalanceSnapshot {
+            taken_at,
+            bitcoin_sat: Some(bitcoin_sat),
+            dai_attodai: Some(dai_attodai),
+            mid_market_rate: Some(mid_market_rate),
+            ..
+        } = snapshot
+        {
+            let dai = match BigUint::from_str(dai_attodai) {
+                Ok(attodai) => dai::Amount::from_atto(attodai),
+                Err(_) => continue,
+            };
+            let _ = advisor.assess(
+                *taken_at,
+                bitcoin::Amount::from_sat(*bitcoin_sat),
+                &dai,
+                *mid_market_rate,
+            );
+        }
+    }
+
+    advisor.assess(now, bitcoin, dai, mid_market_rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bitcoin::amount::btc, config, ethereum::dai::dai, rate::rate};
+    use chrono::Duration;
+
+    fn snapshot(taken_at: DateTime<Utc>, bitcoin: f64, dai_amount: f64) -> BalanceSnapshot {
+        BalanceSnapshot {
+            taken_at,
+            bitcoin_sat: Some(btc(bitcoin).as_sat()),
+            dai_attodai: Some(dai(dai_amount).as_atto().to_string()),
+            ether_wei: None,
+            mid_market_rate: Some(rate(1000.0)),
+        }
+    }
+
+    #[test]
+    fn drift_is_measured_from_the_snapshots_of_the_last_24_hours() {
+        let mut advisor = RebalanceAdvisor::new(Some(config::Rebalance {
+            target_bitcoin_ratio: 0.5,
+            threshold_percent: 50,
+            adjust_max_sell: false,
+        }));
+        let now = Utc::now();
+        let snapshots = vec![
+            snapshot(now - Duration::hours(30), 0.9, 100.0),
+            snapshot(now - Duration::hours(20), 0.5, 500.0),
+            BalanceSnapshot {
+                bitcoin_sat: None,
+                ..snapshot(now - Duration::hours(10), 0.1, 900.0)
+            },
+        ];
+
+        let assessment = assess(
+            &mut advisor,
+            &snapshots,
+            now,
+            btc(0.2),
+            &dai(800.0),
+            rate(1000.0),
+        )
+        .unwrap();
+
+        assert_eq!(assessment.bitcoin_share, 2_000);
+        assert_eq!(assessment.drift, Some(-3_000));
+        assert!(assessment.recommendation.is_some());
+    }
+}
@@ -0,0 +1,363 @@
+//! Aggregate the trade history, including its archives, per period.
+
+use crate::{
+    config::Settings,
+    float_maths::{multiply_pow_ten, string_int_to_float},
+    history,
+};
+use chrono::{DateTime, Datelike, Utc};
+use num::{BigInt, BigUint, Zero};
+use serde::Serialize;
+use serde_json::Value;
+use std::{collections::BTreeMap, fmt, str::FromStr};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug, Clone, Copy)]
+pub struct Report {
+    /// Aggregate the trades per day, week or month
+    #[structopt(long, default_value = "month")]
+    pub period: Period,
+    /// Print the report as a table or as JSON
+    #[structopt(long, default_value = "table")]
+    pub format: ReportFormat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, strum_macros::EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum Period {
+    Day,
+    Week,
+    Month,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, strum_macros::EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum ReportFormat {
+    Table,
+    Json,
+}
+
+impl Period {
+    fn key(self, date_time: DateTime<Utc>) -> String {
+        match self {
+            Period::Day => date_time.format("%Y-%m-%d").to_string(),
+            Period::Week => {
+                let week = date_time.iso_week();
+                format!("{}-W{:02}", week.year(), week.week())
+            }
+            Period::Month => date_time.format("%Y-%m").to_string(),
+        }
+    }
+}
+
+/// The totals of the trades finished in a period. Refunded trades only count
+/// towards the refunds and fees.
+#[derive(Debug, Default, Clone, PartialEq)]
+struct Totals {
+    trades: u64,
+    refunds: u64,
+    bitcoin_bought_sat: BigUint,
+    dai_sold_attodai: BigUint,
+    bitcoin_sold_sat: BigUint,
+    dai_bought_attodai: BigUint,
+    bitcoin_fees_sat: BigUint,
+    net_pnl_attodai: BigInt,
+}
+
+#[derive(Debug, Serialize)]
+struct PeriodReport {
+    period: String,
+    trades: u64,
+    refunds: u64,
+    bitcoin_bought: String,
+    dai_sold: String,
+    bitcoin_sold: String,
+    dai_bought: String,
+    bitcoin_fees: String,
+    net_pnl_dai: String,
+}
+
+impl PeriodReport {
+    fn new(period: String, totals: Totals) -> Self {
+        PeriodReport {
+            period,
+            trades: totals.trades,
+            refunds: totals.refunds,
+            bitcoin_bought: string_int_to_float(totals.bitcoin_bought_sat.to_string(), 8),
+            dai_sold: string_int_to_float(totals.dai_sold_attodai.to_string(), 18),
+            bitcoin_sold: string_int_to_float(totals.bitcoin_sold_sat.to_string(), 8),
+            dai_bought: string_int_to_float(totals.dai_bought_attodai.to_string(), 18),
+            bitcoin_fees: string_int_to_float(totals.bitcoin_fees_sat.to_string(), 8),
+            net_pnl_dai: signed_int_to_float(&totals.net_pnl_attodai, 18),
+        }
+    }
+}
+
+/// The columns of a history record needed for the report.
+#[derive(Debug)]
+struct ReportedTrade {
+    final_timestamp: DateTime<Utc>,
+    buy: bool,
+    refunded: bool,
+    base_sat: BigUint,
+    quote_attodai: BigUint,
+    bitcoin_fee_sat: Option<BigUint>,
+    pnl_attodai: Option<BigInt>,
+}
+
+pub fn report(settings: &Settings, arguments: Report) -> anyhow::Result<String> {
+    let records = history::read_all_records(&settings.data.dir, &settings.history)?;
+
+    let separator = settings.history.decimal_separator.unwrap_or('.');
+    let trades = records
+        .iter()
+        .map(|record| ReportedTrade::from_record(record, separator))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let reports = aggregate(&trades, arguments.period)
+        .into_iter()
+        .map(|(period, totals)| PeriodReport::new(period, totals))
+        .collect::<Vec<_>>();
+
+    match arguments.format {
+        ReportFormat::Json => Ok(serde_json::to_string_pretty(&reports)?),
+        ReportFormat::Table => Ok(Table(&reports).to_string()),
+    }
+}
+
+fn aggregate(trades: &[ReportedTrade], period: Period) -> BTreeMap<String, Totals> {
+    let mut totals = BTreeMap::<String, Totals>::new();
+
+    for trade in trades {
+        let totals = totals.entry(period.key(trade.final_timestamp)).or_default();
+
+        if let Some(fee) = &trade.bitcoin_fee_sat {
+            totals.bitcoin_fees_sat += fee;
+        }
+        if trade.refunded {
+            totals.refunds += 1;
+            continue;
+        }
+
+        totals.trades += 1;
+        if trade.buy {
+            totals.bitcoin_bought_sat += &trade.base_sat;
+            totals.dai_sold_attodai += &trade.quote_attodai;
+        } else {
+            totals.bitcoin_sold_sat += &trade.base_sat;
+            totals.dai_bought_attodai += &trade.quote_attodai;
+        }
+        if let Some(pnl) = &trade.pnl_attodai {
+            totals.net_pnl_attodai += pnl;
+        }
+    }
+
+    totals
+}
+
+impl ReportedTrade {
+    /// Records of the CSV history are flat, the legs of the JSON Lines
+    /// history are nested. Columns added over time are optional.
+    fn from_record(record: &Value, decimal_separator: char) -> anyhow::Result<Self> {
+        let field = |column: &str, pointer: &str| {
+            record
+                .get(column)
+                .or_else(|| record.pointer(pointer))
+                .and_then(Value::as_str)
+                .filter(|value| !value.is_empty())
+        };
+        let required = |column: &str, pointer: &str| {
+            field(column, pointer)
+                .ok_or_else(|| anyhow::anyhow!("History record without {}", column))
+        };
+
+        let final_timestamp =
+            DateTime::parse_from_rfc3339(required("utc_final_timestamp", "/utc_final_timestamp")?)?
+                .with_timezone(&Utc);
+        let buy = match required("position", "/position")? {
+            "Buy" => true,
+            "Sell" => false,
+            position => anyhow::bail!("Unknown position {}", position),
+        };
+
+        Ok(ReportedTrade {
+            final_timestamp,
+            buy,
+            refunded: field("outcome", "/outcome") == Some("Refunded"),
+            base_sat: BigUint::from_str(required("base_precise_amount", "/base/precise_amount")?)?,
+            quote_attodai: BigUint::from_str(required(
+                "quote_precise_amount",
+                "/quote/precise_amount",
+            )?)?,
+            bitcoin_fee_sat: field("bitcoin_fee_sat", "/base/fee_sat")
+                .map(BigUint::from_str)
+                .transpose()?,
+            pnl_attodai: field("realized_pnl_dai", "/realized_pnl_dai")
+                .map(|pnl| parse_signed_float(&pnl.replace(decimal_separator, "."), 18))
+                .transpose()?,
+        })
+    }
+}
+
+fn parse_signed_float(float: &str, pow: u16) -> anyhow::Result<BigInt> {
+    match float.strip_prefix('-') {
+        Some(float) => Ok(-BigInt::from(multiply_pow_ten(float, pow)?)),
+        None => Ok(BigInt::from(multiply_pow_ten(float, pow)?)),
+    }
+}
+
+pub(super) fn signed_int_to_float(int: &BigInt, precision: usize) -> String {
+    let float = string_int_to_float(int.magnitude().to_string(), precision);
+
+    if int < &BigInt::zero() {
+        format!("-{}", float)
+    } else {
+        float
+    }
+}
+
+struct Table<'a>(&'a [PeriodReport]);
+
+impl fmt::Display for Table<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<10} {:>6} {:>7} {:>14} {:>14} {:>14} {:>14} {:>12} {:>14}",
+            "period",
+            "trades",
+            "refunds",
+            "btc_bought",
+            "dai_sold",
+            "btc_sold",
+            "dai_bought",
+            "btc_fees",
+            "net_pnl_dai"
+        )?;
+
+        for report in self.0 {
+            writeln!(
+                f,
+                "{:<10} {:>6} {:>7} {:>14} {:>14} {:>14} {:>14} {:>12} {:>14}",
+                report.period,
+                report.trades,
+                report.refunds,
+                report.bitcoin_bought,
+                report.dai_sold,
+                report.bitcoin_sold,
+                report.dai_bought,
+                report.bitcoin_fees,
+                report.net_pnl_dai
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn aggregate_csv_and_json_records_per_month() {
+        let records = vec![
+            json!({
+                "utc_final_timestamp": "2020-07-10T08:48:26.456+00:00",
+                "position": "Buy",
+                "base_precise_amount": "1000000",
+                "quote_precise_amount": "99000000000000000000",
+                "realized_pnl_dai": "1",
+                "outcome": "Redeemed",
+                "bitcoin_fee_sat": ""
+            }),
+            json!({
+                "utc_final_timestamp": "2020-07-20T00:00:00+00:00",
+                "position": "Sell",
+                "outcome": "Redeemed",
+                "realized_pnl_dai": "-0.5",
+                "base": { "precise_amount": "1000000", "fee_sat": null },
+                "quote": { "precise_amount": "99500000000000000000" }
+            }),
+            json!({
+                "utc_final_timestamp": "2020-08-01T00:00:00+00:00",
+                "position": "Sell",
+                "base_precise_amount": "20000000",
+                "quote_precise_amount": "2012340000000000000000",
+                "outcome": "Refunded",
+                "bitcoin_fee_sat": "1234"
+            }),
+        ];
+        let trades = records
+            .iter()
+            .map(|record| ReportedTrade::from_record(record, '.'))
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+
+        let totals = aggregate(&trades, Period::Month);
+
+        let july = PeriodReport::new("2020-07".to_owned(), totals["2020-07"].clone());
+        assert_eq!(july.trades, 2);
+        assert_eq!(july.refunds, 0);
+        assert_eq!(july.bitcoin_bought, "0.01");
+        assert_eq!(july.dai_sold, "99");
+        assert_eq!(july.bitcoin_sold, "0.01");
+        assert_eq!(july.dai_bought, "99.5");
+        assert_eq!(july.net_pnl_dai, "0.5");
+
+        let august = PeriodReport::new("2020-08".to_owned(), totals["2020-08"].clone());
+        assert_eq!(august.trades, 0);
+        assert_eq!(august.refunds, 1);
+        assert_eq!(august.bitcoin_sold, "0");
+        assert_eq!(august.bitcoin_fees, "0.00001234");
+    }
+
+    #[test]
+    fn bob_hbit_herc20_trade_is_reported_as_bitcoin_bought() {
+        use crate::{
+            command::into_history_trade,
+            swap::{Settlement, SwapKind, SwapOutcome, SwapParams},
+            StaticStub,
+        };
+
+        // Bob in a HbitHerc20 swap locks 4 DAI for 0.12345678 BTC
+        let trade = into_history_trade(
+            libp2p::PeerId::random(),
+            SwapKind::HbitHerc20(SwapParams {
+                mid_market_rate: Some(crate::rate::rate(10_000.0)),
+                ..SwapParams::static_stub()
+            }),
+            history::Outcome::Redeemed,
+            SwapOutcome::Completed,
+            Settlement::default(),
+        );
+        let record = serde_json::to_value(&trade).unwrap();
+        let trade = ReportedTrade::from_record(&record, '.').unwrap();
+
+        let totals = aggregate(&[trade], Period::Month);
+
+        let july = PeriodReport::new("2020-07".to_owned(), totals["2020-07"].clone());
+        assert_eq!(july.trades, 1);
+        assert_eq!(july.bitcoin_bought, "0.12345678");
+        assert_eq!(july.dai_sold, "4");
+        assert_eq!(july.bitcoin_sold, "0");
+        assert_eq!(july.dai_bought, "0");
+        assert_eq!(july.net_pnl_dai, "1230.5678");
+    }
+
+    #[test]
+    fn weeks_are_iso_weeks() {
+        let date_time = DateTime::parse_from_rfc3339("2021-01-01T12:00:00+00:00")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        assert_eq!(Period::Week.key(date_time), "2020-W53");
+        assert_eq!(Period::Day.key(date_time), "2021-01-01");
+    }
+
+    #[test]
+    fn negative_pnl_is_formatted_with_its_sign() {
+        let pnl = parse_signed_float("-0.25", 18).unwrap();
+
+        assert_eq!(signed_int_to_float(&pnl, 18), "-0.25");
+    }
+}
@@ -1,20 +1,68 @@
+//! Resume the swaps recorded in the database without trading, or only refund
+//! them to unwind after a prolonged outage.
+
 use crate::{
+    alert::Alerter,
     bitcoin,
-    command::{into_history_trade, FinishedSwap},
-    config::Settings,
+    command::{into_history_trade, report_swap_failure, swap_outcome, FinishedSwap},
+    config::{BitcoinConfirmations, Settings},
     ethereum,
     history::History,
-    swap::{Database, SwapKind},
+    network,
+    swap::{Database, SwapKind, SwapOutcome},
+    Seed, SwapId,
 };
 use chrono::Utc;
 use comit::btsieve::{bitcoin::BitcoindConnector, ethereum::Web3Connector};
 use futures::future::{join_all, TryFutureExt};
-use std::sync::{Arc, Mutex};
+use std::{
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use structopt::StructOpt;
+use tokio::sync::Semaphore;
+
+#[derive(StructOpt, Debug, Clone)]
+pub struct ResumeOnly {
+    /// Do not execute the swap with this id, or `all` swaps, only refund the
+    /// asset we locked once our HTLC expired
+    #[structopt(long, parse(try_from_str = parse_swap_selection))]
+    pub refund_only: Option<SwapSelection>,
+}
+
+/// Either a given swap or every swap of the database.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SwapSelection {
+    All,
+    Exactly(SwapId),
+}
+
+impl SwapSelection {
+    fn includes(&self, swap: &SwapKind) -> bool {
+        match self {
+            SwapSelection::All => true,
+            SwapSelection::Exactly(swap_id) => swap.swap_id() == *swap_id,
+        }
+    }
+}
+
+fn parse_swap_selection(str: &str) -> anyhow::Result<SwapSelection> {
+    if str == "all" {
+        return Ok(SwapSelection::All);
+    }
 
+    Ok(SwapSelection::Exactly(SwapId::from_str(str)?))
+}
+
+/// Execute the swaps of the database, or only refund the selected ones if
+/// `refund_only` is given.
 pub async fn resume_only(
+    seed: &Seed,
     settings: Settings,
     bitcoin_wallet: bitcoin::Wallet,
     ethereum_wallet: ethereum::Wallet,
+    refund_only: Option<SwapSelection>,
 ) -> anyhow::Result<()> {
     let bitcoin_wallet = Arc::new(bitcoin_wallet);
     let ethereum_wallet = Arc::new(ethereum_wallet);
@@ -25,44 +73,87 @@ pub async fn resume_only(
     let db = Arc::new(Database::new_test()?);
 
     let history = Arc::new(Mutex::new(History::new(
-        settings.data.dir.join("history.csv").as_path(),
+        settings.history.file_path(&settings.data.dir).as_path(),
+        settings.history.clone(),
     )?));
 
-    let bitcoin_connector = Arc::new(BitcoindConnector::new(settings.bitcoin.bitcoind.node_url)?);
-    let ethereum_connector = Arc::new(Web3Connector::new(settings.ethereum.node_url));
+    let bitcoin_connector = Arc::new(BitcoindConnector::new(
+        settings.bitcoin.bitcoind.url_with_credentials()?,
+    )?);
+    let ethereum_connector = Arc::new(Web3Connector::new(
+        settings.ethereum.url_with_credentials()?,
+    ));
 
     respawn_swaps(
         Arc::clone(&db),
+        network::Seed::new(seed.bytes()),
         Arc::clone(&bitcoin_wallet),
         Arc::clone(&ethereum_wallet),
         Arc::clone(&bitcoin_connector),
         Arc::clone(&ethereum_connector),
+        settings.maker.bitcoin_confirmations.clone(),
+        settings
+            .watchdog
+            .swap_stall_timeout_secs
+            .map(Duration::from_secs),
+        settings
+            .maker
+            .max_concurrent_swaps
+            .map(|max| Arc::new(Semaphore::new(max))),
+        Alerter::new(settings.alerting.clone()),
         history,
+        refund_only,
     )
     .await?;
 
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn respawn_swaps(
     db: Arc<Database>,
+    seed: network::Seed,
     bitcoin_wallet: Arc<bitcoin::Wallet>,
     ethereum_wallet: Arc<ethereum::Wallet>,
     bitcoin_connector: Arc<comit::btsieve::bitcoin::BitcoindConnector>,
     ethereum_connector: Arc<comit::btsieve::ethereum::Web3Connector>,
+    bitcoin_confirmations: Vec<BitcoinConfirmations>,
+    swap_stall_timeout: Option<Duration>,
+    swap_slots: Option<Arc<Semaphore>>,
+    alerter: Alerter,
     history: Arc<Mutex<History>>,
+    refund_only: Option<SwapSelection>,
 ) -> anyhow::Result<()> {
-    let futures = db.all_swaps()?.into_iter().map(|swap| {
+    let swaps = db
+        .all_swaps()?
+        .into_iter()
+        .filter(|swap| refund_only.map_or(true, |selection| selection.includes(swap)))
+        .map(|swap| swap.with_secret(&seed))
+        .collect::<Vec<_>>();
+    if let Some(SwapSelection::Exactly(swap_id)) = refund_only {
+        if swaps.is_empty() {
+            anyhow::bail!("No swap {} in the database", swap_id);
+        }
+    }
+
+    let futures = swaps.into_iter().map(|swap| {
         execute_swap(
             Arc::clone(&db),
             Arc::clone(&bitcoin_wallet),
             Arc::clone(&ethereum_wallet),
             Arc::clone(&bitcoin_connector),
             Arc::clone(&ethereum_connector),
+            bitcoin_confirmations.clone(),
+            swap_stall_timeout,
+            swap_slots.clone(),
+            alerter.clone(),
             swap,
+            refund_only.is_some(),
         )
         .and_then(|finished_swap| async {
-            handle_finished_swap(finished_swap, Arc::clone(&db), Arc::clone(&history));
+            if let Some(finished_swap) = finished_swap {
+                handle_finished_swap(finished_swap, Arc::clone(&db), Arc::clone(&history));
+            }
             Ok(())
         })
     });
@@ -79,22 +170,80 @@ async fn execute_swap(
     ethereum_wallet: Arc<ethereum::Wallet>,
     bitcoin_connector: Arc<comit::btsieve::bitcoin::BitcoindConnector>,
     ethereum_connector: Arc<comit::btsieve::ethereum::Web3Connector>,
+    bitcoin_confirmations: Vec<BitcoinConfirmations>,
+    swap_stall_timeout: Option<Duration>,
+    swap_slots: Option<Arc<Semaphore>>,
+    alerter: Alerter,
     swap: SwapKind,
-) -> anyhow::Result<FinishedSwap> {
-    swap.execute(
-        Arc::clone(&db),
-        Arc::clone(&bitcoin_wallet),
-        Arc::clone(&ethereum_wallet),
-        Arc::clone(&bitcoin_connector),
-        Arc::clone(&ethereum_connector),
-    )
-    .await?;
+    refund_only: bool,
+) -> anyhow::Result<Option<FinishedSwap>> {
+    let _permit = match &swap_slots {
+        Some(swap_slots) => Some(swap_slots.acquire().await),
+        None => None,
+    };
+
+    let result = if refund_only {
+        swap.refund(
+            Arc::clone(&db),
+            Arc::clone(&bitcoin_wallet),
+            Arc::clone(&ethereum_wallet),
+            Arc::clone(&bitcoin_connector),
+            Arc::clone(&ethereum_connector),
+        )
+        .await
+        .and_then(|refunded| {
+            if refunded {
+                swap.refund_cause(&db)
+                    .map(|cause| Some(SwapOutcome::RefundedAfter(cause)))
+            } else {
+                Ok(None)
+            }
+        })
+    } else {
+        swap.execute(
+            Arc::clone(&db),
+            Arc::clone(&bitcoin_wallet),
+            Arc::clone(&ethereum_wallet),
+            Arc::clone(&bitcoin_connector),
+            Arc::clone(&ethereum_connector),
+            &bitcoin_confirmations,
+            swap_stall_timeout,
+            None,
+        )
+        .await
+        .map(Some)
+    };
+    if let Err(e) = &result {
+        report_swap_failure(&db, &alerter, &swap, e).await;
+    }
+
+    // Nothing was locked, there is nothing to record in the history
+    let finished = match result? {
+        Some(finished) => finished,
+        None => {
+            remove_unlocked_swap(&db, &swap).await;
+            return Ok(None);
+        }
+    };
 
-    Ok(FinishedSwap::new(
+    let outcome = swap_outcome(&db, &alerter, &swap, &finished).await;
+
+    Ok(Some(FinishedSwap::new(
         swap.clone(),
         swap.params().taker,
         Utc::now(),
-    ))
+        outcome,
+        finished,
+    )))
+}
+
+async fn remove_unlocked_swap(db: &Database, swap: &SwapKind) {
+    if let Err(error) = db.remove_active_peer(&swap.params().taker).await {
+        tracing::error!("Unable to remove from active peers: {:#}", error);
+    }
+    if let Err(error) = db.remove_swap(&swap.swap_id()).await {
+        tracing::error!("Unable to delete swap from db: {:#}", error);
+    }
 }
 
 fn handle_finished_swap(
@@ -103,9 +252,16 @@ fn handle_finished_swap(
     history: Arc<Mutex<History>>,
 ) {
     {
+        let settlement = finished_swap.swap.settlement(&db).unwrap_or_else(|error| {
+            tracing::error!("Unable to load the settlement of the swap: {:#}", error);
+            Default::default()
+        });
         let trade = into_history_trade(
             finished_swap.peer.peer_id(),
             finished_swap.swap.clone(),
+            finished_swap.outcome,
+            finished_swap.swap_outcome.clone(),
+            settlement,
             #[cfg(not(test))]
             finished_swap.final_timestamp,
         );
@@ -132,3 +288,30 @@ fn handle_finished_swap(
         .remove_swap(&swap_id)
         .map_err(|error| tracing::error!("Unable to delete swap from db: {}", error));
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn swap_selection_parses_all() {
+        assert_eq!(parse_swap_selection("all").unwrap(), SwapSelection::All);
+    }
+
+    #[test]
+    fn swap_selection_parses_swap_id() {
+        let selection = parse_swap_selection("ad2652ca-ecf2-4cc6-b35c-b4351ac28a34").unwrap();
+
+        assert_eq!(
+            selection,
+            SwapSelection::Exactly(
+                SwapId::from_str("ad2652ca-ecf2-4cc6-b35c-b4351ac28a34").unwrap()
+            )
+        );
+    }
+
+    #[test]
+    fn swap_selection_rejects_garbage() {
+        assert!(parse_swap_selection("everything").is_err());
+    }
+}
@@ -0,0 +1,39 @@
+//! Back up the seed as a BIP-39 phrase and restore it. The seed derives the
+//! maker identity and the keys of both wallets.
+
+use crate::config::{self, Settings};
+use std::io::{self, BufRead};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug, Clone)]
+pub enum Seed {
+    /// Print the seed as a phrase of 24 words, keep it secret
+    Export,
+    /// Restore the seed from a phrase of 24 words read from the standard input
+    Import {
+        /// Overwrite the seed already present in the data directory
+        #[structopt(long)]
+        force: bool,
+    },
+}
+
+pub fn seed(settings: &Settings, command: Seed) -> anyhow::Result<String> {
+    match command {
+        Seed::Export => {
+            let seed = config::Seed::from_data_dir(&settings.data.dir)?;
+            Ok(seed.to_mnemonic())
+        }
+        Seed::Import { force } => {
+            let mut phrase = String::new();
+            io::stdin().lock().read_line(&mut phrase)?;
+
+            let seed = config::Seed::from_mnemonic(&phrase)?;
+            seed.write_to_data_dir(&settings.data.dir, force)?;
+
+            Ok(format!(
+                "Imported the seed into {}",
+                settings.data.dir.display()
+            ))
+        }
+    }
+}
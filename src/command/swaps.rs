@@ -0,0 +1,446 @@
+//! Inspect the swaps recorded in the database, e.g. to find out where a swap
+//! is stuck. The database is locked while nectar is trading.
+
+use crate::{
+    config::Settings,
+    swap::{Database, Settlement, SwapKind},
+    SwapId,
+};
+use chrono::{DateTime, TimeZone, Utc};
+use serde::Serialize;
+use std::fmt;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug, Clone)]
+pub enum Swaps {
+    /// List the ongoing swaps with their next expected action
+    List {
+        /// Print the swaps as a table or as JSON
+        #[structopt(long, default_value = "table")]
+        format: SwapsFormat,
+    },
+    /// Print the parameters, the recorded transactions and the next expected
+    /// action of a swap
+    Show {
+        swap_id: SwapId,
+        /// Print the swap as a table or as JSON
+        #[structopt(long, default_value = "table")]
+        format: SwapsFormat,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, strum_macros::EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum SwapsFormat {
+    Table,
+    Json,
+}
+
+/// A swap as recorded in the database, transactions not recorded yet are
+/// `None`.
+#[derive(Debug, Clone, Serialize)]
+struct SwapState {
+    swap_id: String,
+    taker: String,
+    position: String,
+    bitcoin: String,
+    dai: String,
+    start_of_swap: DateTime<Utc>,
+    bitcoin_expiry: DateTime<Utc>,
+    dai_expiry: DateTime<Utc>,
+    bitcoin_fund_txid: Option<String>,
+    bitcoin_redeem_txid: Option<String>,
+    bitcoin_refund_txid: Option<String>,
+    ethereum_deploy_txid: Option<String>,
+    ethereum_fund_txid: Option<String>,
+    ethereum_redeem_txid: Option<String>,
+    ethereum_refund_txid: Option<String>,
+    next_action: NextAction,
+}
+
+/// What the execution of the swap waits for or does next, from the
+/// transactions we recorded. Those of the taker are not recorded.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, strum_macros::Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+enum NextAction {
+    /// The taker locks their asset, then we lock ours or, if we are Alice and
+    /// locked ours first, redeem theirs
+    WaitForTakerFund,
+    DeployDai,
+    FundDai,
+    FundBitcoin,
+    /// The taker redeems our HTLC, then we redeem theirs
+    WaitForTakerRedeem,
+    /// As Alice, we redeem the HTLC of the taker
+    RedeemDai,
+    RedeemBitcoin,
+    RefundDai,
+    RefundBitcoin,
+    /// Our HTLC expired before we locked our asset
+    Abort,
+    /// We redeemed or refunded, the swap is removed once recorded in the
+    /// history
+    Finished,
+}
+
+pub fn swaps(settings: &Settings, command: Swaps) -> anyhow::Result<String> {
+    #[cfg(not(test))]
+    let db = Database::new(&settings.data.dir.join("database"))?;
+    #[cfg(test)]
+    let db = Database::new_test()?;
+
+    execute(&db, command, Utc::now())
+}
+
+fn execute(db: &Database, command: Swaps, now: DateTime<Utc>) -> anyhow::Result<String> {
+    match command {
+        Swaps::List { format } => {
+            let mut states = db
+                .all_swaps()?
+                .iter()
+                .map(|swap| SwapState::new(db, swap, now))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            states.sort_by_key(|state| state.start_of_swap);
+
+            match format {
+                SwapsFormat::Json => Ok(serde_json::to_string_pretty(&states)?),
+                SwapsFormat::Table => Ok(Table(&states).to_string()),
+            }
+        }
+        Swaps::Show { swap_id, format } => {
+            let swap = db
+                .all_swaps()?
+                .into_iter()
+                .find(|swap| swap.swap_id() == swap_id)
+                .ok_or_else(|| anyhow::anyhow!("No swap {} in the database", swap_id))?;
+            let state = SwapState::new(db, &swap, now)?;
+
+            match format {
+                SwapsFormat::Json => Ok(serde_json::to_string_pretty(&state)?),
+                SwapsFormat::Table => Ok(state.to_string()),
+            }
+        }
+    }
+}
+
+impl SwapState {
+    fn new(db: &Database, swap: &SwapKind, now: DateTime<Utc>) -> anyhow::Result<Self> {
+        let settlement = swap.settlement(db)?;
+        let params = swap.params();
+        let position = swap.position();
+        let bitcoin_expiry = expiry(params.hbit_params.shared.expiry);
+        let dai_expiry = expiry(params.herc20_params.expiry);
+
+        Ok(SwapState {
+            swap_id: params.swap_id.to_string(),
+            taker: params.taker.peer_id().to_string(),
+            position: format!("{:?}", position),
+            bitcoin: crate::bitcoin::Amount::from(params.hbit_params.shared.asset).to_string(),
+            dai: crate::ethereum::dai::Amount::from(params.herc20_params.asset).to_string(),
+            start_of_swap: params.start_of_swap,
+            bitcoin_expiry,
+            dai_expiry,
+            next_action: next_action(swap, &settlement, bitcoin_expiry, dai_expiry, now),
+            bitcoin_fund_txid: settlement.bitcoin_fund.map(|txid| txid.to_string()),
+            bitcoin_redeem_txid: settlement.bitcoin_redeem.map(|txid| txid.to_string()),
+            bitcoin_refund_txid: settlement.bitcoin_refund.map(|txid| txid.to_string()),
+            ethereum_deploy_txid: settlement.ethereum_deploy.map(|hash| hash.to_string()),
+            ethereum_fund_txid: settlement.ethereum_fund.map(|hash| hash.to_string()),
+            ethereum_redeem_txid: settlement.ethereum_redeem.map(|hash| hash.to_string()),
+            ethereum_refund_txid: settlement.ethereum_refund.map(|hash| hash.to_string()),
+        })
+    }
+}
+
+fn expiry(expiry: comit::Timestamp) -> DateTime<Utc> {
+    Utc.timestamp(i64::from(u32::from(expiry)), 0)
+}
+
+/// The expiries are compared to our clock, the ledgers may lag slightly
+/// behind it.
+fn next_action(
+    swap: &SwapKind,
+    settlement: &Settlement,
+    bitcoin_expiry: DateTime<Utc>,
+    dai_expiry: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> NextAction {
+    if let comit::Role::Alice = swap.role() {
+        return next_action_as_alice(swap, settlement, bitcoin_expiry, dai_expiry, now);
+    }
+
+    match swap {
+        // We lock dai once the taker locked bitcoin
+        SwapKind::HbitHerc20(_) => {
+            if settlement.ethereum_refund.is_some() || settlement.bitcoin_redeem.is_some() {
+                NextAction::Finished
+            } else if settlement.ethereum_fund.is_some() && dai_expiry <= now {
+                NextAction::RefundDai
+            } else if settlement.ethereum_fund.is_some() {
+                NextAction::WaitForTakerRedeem
+            } else if dai_expiry <= now {
+                NextAction::Abort
+            } else if settlement.ethereum_deploy.is_some() {
+                NextAction::FundDai
+            } else if settlement.bitcoin_fund.is_some() {
+                NextAction::DeployDai
+            } else {
+                NextAction::WaitForTakerFund
+            }
+        }
+        // We lock bitcoin once the taker locked dai
+        SwapKind::Herc20Hbit(_) => {
+            if settlement.bitcoin_refund.is_some() || settlement.ethereum_redeem.is_some() {
+                NextAction::Finished
+            } else if settlement.bitcoin_fund.is_some() && bitcoin_expiry <= now {
+                NextAction::RefundBitcoin
+            } else if settlement.bitcoin_fund.is_some() {
+                NextAction::WaitForTakerRedeem
+            } else if bitcoin_expiry <= now {
+                NextAction::Abort
+            } else if settlement.ethereum_fund.is_some() {
+                NextAction::FundBitcoin
+            } else {
+                NextAction::WaitForTakerFund
+            }
+        }
+    }
+}
+
+/// As Alice we lock our asset first, and redeem the HTLC of the taker once
+/// they locked theirs.
+fn next_action_as_alice(
+    swap: &SwapKind,
+    settlement: &Settlement,
+    bitcoin_expiry: DateTime<Utc>,
+    dai_expiry: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> NextAction {
+    match swap {
+        // We lock bitcoin, then redeem the dai of the taker
+        SwapKind::HbitHerc20(_) => {
+            if settlement.bitcoin_refund.is_some() || settlement.ethereum_redeem.is_some() {
+                NextAction::Finished
+            } else if settlement.bitcoin_fund.is_some() && bitcoin_expiry <= now {
+                NextAction::RefundBitcoin
+            } else if settlement.bitcoin_fund.is_some() && settlement.ethereum_fund.is_some() {
+                NextAction::RedeemDai
+            } else if settlement.bitcoin_fund.is_some() {
+                NextAction::WaitForTakerFund
+            } else if bitcoin_expiry <= now {
+                NextAction::Abort
+            } else {
+                NextAction::FundBitcoin
+            }
+        }
+        // We lock dai, then redeem the bitcoin of the taker
+        SwapKind::Herc20Hbit(_) => {
+            if settlement.ethereum_refund.is_some() || settlement.bitcoin_redeem.is_some() {
+                NextAction::Finished
+            } else if settlement.ethereum_fund.is_some() && dai_expiry <= now {
+                NextAction::RefundDai
+            } else if settlement.ethereum_fund.is_some() && settlement.bitcoin_fund.is_some() {
+                NextAction::RedeemBitcoin
+            } else if settlement.ethereum_fund.is_some() {
+                NextAction::WaitForTakerFund
+            } else if dai_expiry <= now {
+                NextAction::Abort
+            } else if settlement.ethereum_deploy.is_some() {
+                NextAction::FundDai
+            } else {
+                NextAction::DeployDai
+            }
+        }
+    }
+}
+
+fn or_dash(value: &Option<String>) -> &str {
+    value.as_deref().unwrap_or("-")
+}
+
+impl fmt::Display for SwapState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Swap id: {}", self.swap_id)?;
+        writeln!(f, "Taker: {}", self.taker)?;
+        writeln!(f, "Position: {}", self.position)?;
+        writeln!(f, "Bitcoin: {}", self.bitcoin)?;
+        writeln!(f, "Dai: {}", self.dai)?;
+        writeln!(f, "Start of swap: {}", self.start_of_swap)?;
+        writeln!(f, "Bitcoin expiry: {}", self.bitcoin_expiry)?;
+        writeln!(f, "Dai expiry: {}", self.dai_expiry)?;
+        writeln!(f, "Bitcoin fund: {}", or_dash(&self.bitcoin_fund_txid))?;
+        writeln!(f, "Bitcoin redeem: {}", or_dash(&self.bitcoin_redeem_txid))?;
+        writeln!(f, "Bitcoin refund: {}", or_dash(&self.bitcoin_refund_txid))?;
+        writeln!(
+            f,
+            "Ethereum deploy: {}",
+            or_dash(&self.ethereum_deploy_txid)
+        )?;
+        writeln!(f, "Ethereum fund: {}", or_dash(&self.ethereum_fund_txid))?;
+        writeln!(
+            f,
+            "Ethereum redeem: {}",
+            or_dash(&self.ethereum_redeem_txid)
+        )?;
+        writeln!(
+            f,
+            "Ethereum refund: {}",
+            or_dash(&self.ethereum_refund_txid)
+        )?;
+        write!(f, "Next action: {}", self.next_action)
+    }
+}
+
+struct Table<'a>(&'a [SwapState]);
+
+impl fmt::Display for Table<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<36} {:<8} {:>14} {:>14} {:<25} {:<25} {:<20}",
+            "swap_id", "position", "bitcoin", "dai", "bitcoin_expiry", "dai_expiry", "next_action"
+        )?;
+
+        for state in self.0 {
+            writeln!(
+                f,
+                "{:<36} {:<8} {:>14} {:>14} {:<25} {:<25} {:<20}",
+                state.swap_id,
+                state.position,
+                state.bitcoin,
+                state.dai,
+                state.bitcoin_expiry.to_rfc3339(),
+                state.dai_expiry.to_rfc3339(),
+                state.next_action.to_string()
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::SwapRole, swap::SwapParams, StaticStub};
+    use std::str::FromStr;
+
+    fn now() -> DateTime<Utc> {
+        DateTime::from_str("2020-07-10T08:00:00Z").unwrap()
+    }
+
+    fn txid() -> Option<::bitcoin::Txid> {
+        Some(
+            ::bitcoin::Txid::from_str(
+                "3ea0b7ad1da1ef5ba1ee6ae2e86e1e7c7b0c0c4e0d9c1ab3ec4d70d6c0cbeb8f",
+            )
+            .unwrap(),
+        )
+    }
+
+    #[test]
+    fn selling_bitcoin_waits_for_the_taker_to_redeem_until_our_expiry() {
+        let swap = SwapKind::Herc20Hbit(SwapParams::static_stub());
+        let settlement = Settlement {
+            bitcoin_fund: txid(),
+            ..Settlement::default()
+        };
+        let expiry = now() + chrono::Duration::hours(1);
+
+        assert_eq!(
+            next_action(&swap, &settlement, expiry, expiry, now()),
+            NextAction::WaitForTakerRedeem
+        );
+        assert_eq!(
+            next_action(&swap, &settlement, now(), expiry, now()),
+            NextAction::RefundBitcoin
+        );
+    }
+
+    #[test]
+    fn buying_bitcoin_is_finished_once_we_redeemed() {
+        let swap = SwapKind::HbitHerc20(SwapParams::static_stub());
+        let settlement = Settlement {
+            bitcoin_fund: txid(),
+            bitcoin_redeem: txid(),
+            ..Settlement::default()
+        };
+
+        assert_eq!(
+            next_action(&swap, &settlement, now(), now(), now()),
+            NextAction::Finished
+        );
+    }
+
+    #[test]
+    fn unlocked_swap_is_aborted_once_our_htlc_expired() {
+        let swap = SwapKind::HbitHerc20(SwapParams::static_stub());
+        let settlement = Settlement {
+            bitcoin_fund: txid(),
+            ..Settlement::default()
+        };
+
+        assert_eq!(
+            next_action(&swap, &settlement, now(), now(), now()),
+            NextAction::Abort
+        );
+    }
+
+    #[test]
+    fn selling_bitcoin_as_alice_funds_before_the_taker() {
+        let swap = SwapKind::HbitHerc20(SwapParams {
+            role: SwapRole::Alice,
+            ..SwapParams::static_stub()
+        });
+        let expiry = now() + chrono::Duration::hours(1);
+
+        assert_eq!(
+            next_action(&swap, &Settlement::default(), expiry, expiry, now()),
+            NextAction::FundBitcoin
+        );
+        assert_eq!(
+            next_action(
+                &swap,
+                &Settlement {
+                    bitcoin_fund: txid(),
+                    ..Settlement::default()
+                },
+                expiry,
+                expiry,
+                now()
+            ),
+            NextAction::WaitForTakerFund
+        );
+    }
+
+    #[tokio::test]
+    async fn bob_hbit_herc20_swap_is_shown_as_a_buy() {
+        let db = Database::new_test().unwrap();
+        let swap = SwapKind::HbitHerc20(SwapParams::static_stub());
+        db.insert_swap(swap.clone()).await.unwrap();
+
+        let state = SwapState::new(&db, &swap, now()).unwrap();
+
+        assert_eq!(state.position, "Buy");
+    }
+
+    #[tokio::test]
+    async fn show_unknown_swap_fails() {
+        let db = Database::new_test().unwrap();
+        db.insert_swap(SwapKind::HbitHerc20(SwapParams::static_stub()))
+            .await
+            .unwrap();
+
+        let result = execute(
+            &db,
+            Swaps::Show {
+                swap_id: SwapId::from_str("ad2652ca-ecf2-4cc6-b35c-b4351ac28a34").unwrap(),
+                format: SwapsFormat::Json,
+            },
+            now(),
+        );
+
+        assert!(result.is_err());
+    }
+}
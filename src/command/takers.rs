@@ -0,0 +1,132 @@
+//! Ban or allow takers. Changes apply the next time nectar starts trading.
+
+use crate::{
+    config::{self, Settings},
+    swap::{Database, TakerListing},
+};
+use libp2p::PeerId;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug, Clone)]
+pub enum Takers {
+    /// Refuse to trade with the taker
+    Ban { peer_id: PeerId },
+    /// Allow the taker, once any taker is allowed only those are traded with
+    Allow { peer_id: PeerId },
+    /// Remove the taker from the banned or allowed takers
+    Remove { peer_id: PeerId },
+    /// List the banned and allowed takers, including those of the config file
+    List,
+}
+
+pub async fn takers(settings: &Settings, command: Takers) -> anyhow::Result<String> {
+    #[cfg(not(test))]
+    let db = Database::new(&settings.data.dir.join("database"))?;
+    #[cfg(test)]
+    let db = Database::new_test()?;
+
+    execute(&db, &settings.takers, command).await
+}
+
+async fn execute(
+    db: &Database,
+    configured: &config::Takers,
+    command: Takers,
+) -> anyhow::Result<String> {
+    match command {
+        Takers::Ban { peer_id } => {
+            db.insert_taker_listing(&peer_id, TakerListing::Banned)
+                .await?;
+            Ok(format!("Banned {}", peer_id))
+        }
+        Takers::Allow { peer_id } => {
+            db.insert_taker_listing(&peer_id, TakerListing::Allowed)
+                .await?;
+            Ok(format!("Allowed {}", peer_id))
+        }
+        Takers::Remove { peer_id } => {
+            if db.remove_taker_listing(&peer_id).await? {
+                Ok(format!("Removed {}", peer_id))
+            } else if configured.banned.contains(&peer_id) || configured.allowed.contains(&peer_id)
+            {
+                anyhow::bail!("{} is listed in the config file, remove it there", peer_id)
+            } else {
+                anyhow::bail!("{} is neither banned nor allowed", peer_id)
+            }
+        }
+        Takers::List => {
+            let configured = configured
+                .banned
+                .iter()
+                .map(|peer_id| (peer_id.clone(), TakerListing::Banned, "config file"))
+                .chain(
+                    configured
+                        .allowed
+                        .iter()
+                        .map(|peer_id| (peer_id.clone(), TakerListing::Allowed, "config file")),
+                );
+            let listed = db
+                .taker_listings()?
+                .into_iter()
+                .map(|(peer_id, listing)| (peer_id, listing, "database"));
+
+            Ok(configured
+                .chain(listed)
+                .map(|(peer_id, listing, source)| {
+                    let listing = match listing {
+                        TakerListing::Banned => "banned",
+                        TakerListing::Allowed => "allowed",
+                    };
+                    format!("{} {} ({})", listing, peer_id, source)
+                })
+                .collect::<Vec<_>>()
+                .join("\n"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn listed_takers_include_the_config_file() {
+        let db = Database::new_test().unwrap();
+        let configured = config::Takers {
+            banned: vec![PeerId::random()],
+            allowed: vec![],
+        };
+        let allowed = PeerId::random();
+
+        execute(&db, &configured, Takers::Allow {
+            peer_id: allowed.clone(),
+        })
+        .await
+        .unwrap();
+        let list = execute(&db, &configured, Takers::List).await.unwrap();
+
+        assert_eq!(
+            list,
+            format!(
+                "banned {} (config file)\nallowed {} (database)",
+                configured.banned[0], allowed
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn taker_of_the_config_file_cannot_be_removed() {
+        let db = Database::new_test().unwrap();
+        let configured = config::Takers {
+            banned: vec![PeerId::random()],
+            allowed: vec![],
+        };
+
+        let result = execute(&db, &configured, Takers::Remove {
+            peer_id: configured.banned[0].clone(),
+        })
+        .await;
+
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,67 @@
+//! The unspent outputs of the Bitcoin wallet, those the coin selection spends
+//! from.
+
+use crate::bitcoin::{self, coin_selection::Utxo, Amount};
+use std::fmt::Write;
+
+pub async fn utxos(bitcoin_wallet: bitcoin::Wallet) -> anyhow::Result<String> {
+    let utxos = bitcoin_wallet.utxos().await?;
+
+    format_utxos(utxos)
+}
+
+/// One line per output, largest first, and the total.
+fn format_utxos(mut utxos: Vec<Utxo>) -> anyhow::Result<String> {
+    utxos.sort_by(|lhs, rhs| rhs.amount.cmp(&lhs.amount));
+
+    let mut out = String::new();
+    for utxo in utxos.iter() {
+        writeln!(
+            out,
+            "{} {} BTC ({} confirmations)",
+            utxo.outpoint,
+            utxo.amount.as_btc(),
+            utxo.confirmations
+        )?;
+    }
+    let total = utxos
+        .iter()
+        .fold(Amount::ZERO, |total, utxo| total + utxo.amount);
+    write!(
+        out,
+        "Total: {} BTC in {} outputs",
+        total.as_btc(),
+        utxos.len()
+    )?;
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::bitcoin::{hashes::Hash, OutPoint, Txid};
+
+    #[test]
+    fn outputs_are_listed_largest_first_with_the_total() {
+        let utxo = |vout, sat, confirmations| Utxo {
+            outpoint: OutPoint {
+                txid: Txid::from_inner([0u8; 32]),
+                vout,
+            },
+            amount: Amount::from_sat(sat),
+            confirmations,
+        };
+
+        let out = format_utxos(vec![utxo(0, 10_000, 0), utxo(1, 50_000, 3)]).unwrap();
+
+        let txid = "0000000000000000000000000000000000000000000000000000000000000000";
+        assert_eq!(
+            out,
+            format!(
+                "{}:1 0.0005 BTC (3 confirmations)\n{}:0 0.0001 BTC (0 confirmations)\nTotal: 0.0006 BTC in 2 outputs",
+                txid, txid
+            )
+        );
+    }
+}
@@ -1,13 +1,14 @@
-use crate::{bitcoin, ethereum, Seed};
+use crate::{bitcoin, config, ethereum, Seed};
 
 pub async fn wallet_info(
     ethereum_wallet: Option<ethereum::Wallet>,
     bitcoin_wallet: Option<bitcoin::Wallet>,
     seed: &Seed,
     bitcoin_network: bitcoin::Network,
+    derivation: config::Derivation,
 ) -> anyhow::Result<String> {
-    let bitcoin_info = bitcoin_info(bitcoin_wallet, &seed, bitcoin_network).await;
-    let ethereum_info = ethereum_info(ethereum_wallet, &seed);
+    let bitcoin_info = bitcoin_info(bitcoin_wallet, &seed, bitcoin_network, derivation).await;
+    let ethereum_info = ethereum_info(ethereum_wallet, &seed, derivation);
 
     Ok(format!(
         "Bitcoin wallet descriptors:\n{}\nEthereum private key:\n{}",
@@ -19,6 +20,7 @@ async fn bitcoin_info(
     bitcoin_wallet: Option<bitcoin::Wallet>,
     seed: &Seed,
     network: bitcoin::Network,
+    derivation: config::Derivation,
 ) -> String {
     let descriptors = match bitcoin_wallet {
         Some(bitcoin_wallet) => bitcoin_wallet.descriptors_with_checksums().await.ok(),
@@ -28,16 +30,26 @@ async fn bitcoin_info(
     match descriptors {
         Some(descriptors) => descriptors.join("\n"),
         None => {
-            let descriptors = bitcoin::Wallet::descriptors_from_seed(&seed, network);
+            let descriptors = bitcoin::Wallet::descriptors_from_seed(&seed, network, derivation);
             format!("(could not reach bitcoind)\n{}", descriptors.join("\n"))
         }
     }
 }
 
-fn ethereum_info(ethereum_wallet: Option<ethereum::Wallet>, seed: &Seed) -> String {
+fn ethereum_info(
+    ethereum_wallet: Option<ethereum::Wallet>,
+    seed: &Seed,
+    derivation: config::Derivation,
+) -> String {
     match ethereum_wallet {
-        Some(ethereum_wallet) => ethereum_wallet.private_key().to_string(),
-        None => ethereum::Wallet::private_key_from_seed(seed)
+        Some(ethereum_wallet) => match ethereum_wallet.private_key() {
+            Some(private_key) => private_key.to_string(),
+            None => format!(
+                "(signed by an external signer)\n{}",
+                ethereum_wallet.account()
+            ),
+        },
+        None => ethereum::Wallet::private_key_from_seed(seed, derivation)
             .expect("Derive private key from seed")
             .to_string(),
     }
@@ -81,6 +93,7 @@ mod tests {
             Some(bitcoin_wallet),
             &seed,
             bitcoin::Network::Regtest,
+            config::Derivation::default(),
         )
         .await?;
         println!("{}", stdout);
@@ -93,7 +106,14 @@ mod tests {
     async fn wallet_info_command_no_nodes() -> anyhow::Result<()> {
         let seed = Seed::random().unwrap();
 
-        let stdout = wallet_info(None, None, &seed, bitcoin::Network::Regtest).await?;
+        let stdout = wallet_info(
+            None,
+            None,
+            &seed,
+            bitcoin::Network::Regtest,
+            config::Derivation::default(),
+        )
+        .await?;
         println!("{}", stdout);
         Ok(())
     }
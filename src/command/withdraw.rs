@@ -1,5 +1,11 @@
-use crate::{bitcoin, command::Withdraw, ethereum, ethereum::STANDARD_ETH_TRANSFER_GAS_LIMIT};
-use std::borrow::Borrow;
+use crate::{
+    bitcoin,
+    command::{Withdraw, WithdrawAmount},
+    ethereum::{self, ether, DAI_TRANSFER_GAS_LIMIT, STANDARD_ETH_TRANSFER_GAS_LIMIT},
+};
+use num::BigUint;
+use num256::Uint256;
+use std::{borrow::Borrow, convert::TryFrom, fmt::Display, str::FromStr};
 
 pub async fn withdraw(
     ethereum_wallet: ethereum::Wallet,
@@ -7,26 +13,77 @@ pub async fn withdraw(
     arguments: Withdraw,
 ) -> anyhow::Result<String> {
     match arguments {
-        Withdraw::Btc { amount, to_address } => {
+        Withdraw::Btc {
+            amount,
+            to_address,
+            dry_run,
+        } => {
             let bitcoin_wallet = bitcoin_wallet.borrow();
-            let tx_id = bitcoin_wallet
-                .send_to_address(to_address.clone(), amount, bitcoin_wallet.network)
+            let (amount, sweep) = match amount {
+                WithdrawAmount::All => (bitcoin_wallet.balance().await?, true),
+                WithdrawAmount::Exactly(amount) => (amount, false),
+            };
+            let fee = bitcoin_wallet
+                .estimate_send_fee(to_address.clone(), amount, sweep)
                 .await?;
-            Ok(format!(
-                "{} transferred to {}\nTransaction id: {}",
-                amount, to_address, tx_id
-            ))
+            let sent = if sweep { amount - fee } else { amount };
+
+            if dry_run {
+                return Ok(dry_run_report(sent, &to_address, fee));
+            }
+
+            let tx_id = if sweep {
+                bitcoin_wallet
+                    .send_all_to_address(to_address.clone(), bitcoin_wallet.network)
+                    .await?
+            } else {
+                bitcoin_wallet
+                    .send_to_address(to_address.clone(), amount, bitcoin_wallet.network)
+                    .await?
+            };
+            Ok(report(sent, &to_address, fee, tx_id))
         }
-        Withdraw::Dai { amount, to_address } => {
+        Withdraw::Dai {
+            amount,
+            to_address,
+            dry_run,
+        } => {
+            let amount = match amount {
+                WithdrawAmount::All => ethereum_wallet.dai_balance().await?,
+                WithdrawAmount::Exactly(amount) => amount,
+            };
+            let fee = transaction_fee(&ethereum_wallet, DAI_TRANSFER_GAS_LIMIT).await?;
+
+            if dry_run {
+                return Ok(dry_run_report(&amount, &to_address, wei_to_ether(fee)?));
+            }
+
             let tx_id = ethereum_wallet
                 .transfer_dai(to_address, amount.clone(), ethereum_wallet.chain_id())
                 .await?;
-            Ok(format!(
-                "{} transferred to {}\nTransaction id: {}",
-                amount, to_address, tx_id
-            ))
+            Ok(report(&amount, &to_address, wei_to_ether(fee)?, tx_id))
         }
-        Withdraw::Eth { amount, to_address } => {
+        Withdraw::Eth {
+            amount,
+            to_address,
+            dry_run,
+        } => {
+            let fee = transaction_fee(&ethereum_wallet, STANDARD_ETH_TRANSFER_GAS_LIMIT).await?;
+            let amount = match amount {
+                WithdrawAmount::All => {
+                    let balance = Uint256::from(ethereum_wallet.ether_balance().await?);
+                    if balance <= fee {
+                        anyhow::bail!("Ether balance does not cover the transaction fee")
+                    }
+                    wei_to_ether(balance - fee.clone())?
+                }
+                WithdrawAmount::Exactly(amount) => amount,
+            };
+
+            if dry_run {
+                return Ok(dry_run_report(&amount, &to_address, wei_to_ether(fee)?));
+            }
+
             let tx_id = ethereum_wallet
                 .send_transaction(
                     to_address,
@@ -36,14 +93,44 @@ pub async fn withdraw(
                     ethereum_wallet.chain_id(),
                 )
                 .await?;
-            Ok(format!(
-                "{} transferred to {}\nTransaction id: {}",
-                amount, to_address, tx_id
-            ))
+            Ok(report(&amount, &to_address, wei_to_ether(fee)?, tx_id))
         }
     }
 }
 
+/// Most fee in wei of a transaction using up to `gas_limit` at the current
+/// fees.
+async fn transaction_fee(wallet: &ethereum::Wallet, gas_limit: u64) -> anyhow::Result<Uint256> {
+    let fees = wallet.fees().await?;
+
+    Ok(fees.max_gas_price().clone() * Uint256::from(gas_limit))
+}
+
+fn wei_to_ether(wei: Uint256) -> anyhow::Result<ether::Amount> {
+    let wei = BigUint::from_str(&wei.to_string())?;
+
+    ether::Amount::try_from(wei)
+}
+
+fn dry_run_report(amount: impl Display, to_address: impl Display, fee: impl Display) -> String {
+    format!(
+        "{} would be transferred to {}\nEstimated fee: {}",
+        amount, to_address, fee
+    )
+}
+
+fn report(
+    amount: impl Display,
+    to_address: impl Display,
+    fee: impl Display,
+    tx_id: impl Display,
+) -> String {
+    format!(
+        "{} transferred to {}\nEstimated fee: {}\nTransaction id: {}",
+        amount, to_address, fee, tx_id
+    )
+}
+
 #[cfg(all(test, feature = "test-docker"))]
 mod tests {
     use super::*;
@@ -113,9 +200,10 @@ mod tests {
             .unwrap();
 
         let bitcoin_withdraw = Withdraw::Btc {
-            amount: bitcoin::Amount::from_btc(0.3).unwrap(),
+            amount: WithdrawAmount::Exactly(bitcoin::Amount::from_btc(0.3).unwrap()),
             to_address: bitcoin::Address::from_str("bcrt1qk60fmayw8xrtqd4ru2ut8kgv08wyqpdzqkj55h")
                 .unwrap(),
+            dry_run: false,
         };
         let stdout = withdraw(
             ethereum_wallet.clone(),
@@ -127,8 +215,9 @@ mod tests {
         println!("{}", stdout);
 
         let ether_withdraw = Withdraw::Eth {
-            amount: ether::Amount::from_ether_str("2.4").unwrap(),
+            amount: WithdrawAmount::Exactly(ether::Amount::from_ether_str("2.4").unwrap()),
             to_address: ethereum::Address::random(),
+            dry_run: false,
         };
         let stdout = withdraw(
             ethereum_wallet.clone(),
@@ -140,12 +229,47 @@ mod tests {
         println!("{}", stdout);
 
         let dai_withdraw = Withdraw::Dai {
-            amount: dai::Amount::from_dai_trunc(3.2).unwrap(),
+            amount: WithdrawAmount::Exactly(dai::Amount::from_dai_trunc(3.2).unwrap()),
             to_address: ethereum::Address::random(),
+            dry_run: false,
         };
-        let stdout = withdraw(ethereum_wallet, bitcoin_wallet, dai_withdraw)
+        let stdout = withdraw(
+            ethereum_wallet.clone(),
+            bitcoin_wallet.clone(),
+            dai_withdraw,
+        )
+        .await
+        .unwrap();
+        println!("{}", stdout);
+
+        let bitcoin_sweep_dry_run = Withdraw::Btc {
+            amount: WithdrawAmount::All,
+            to_address: bitcoin::Address::from_str("bcrt1qk60fmayw8xrtqd4ru2ut8kgv08wyqpdzqkj55h")
+                .unwrap(),
+            dry_run: true,
+        };
+        let stdout = withdraw(
+            ethereum_wallet.clone(),
+            bitcoin_wallet.clone(),
+            bitcoin_sweep_dry_run,
+        )
+        .await
+        .unwrap();
+        println!("{}", stdout);
+
+        let dai_sweep = Withdraw::Dai {
+            amount: WithdrawAmount::All,
+            to_address: ethereum::Address::random(),
+            dry_run: false,
+        };
+        let stdout = withdraw(ethereum_wallet.clone(), bitcoin_wallet, dai_sweep)
             .await
             .unwrap();
         println!("{}", stdout);
+
+        assert_eq!(
+            ethereum_wallet.dai_balance().await.unwrap(),
+            dai::Amount::zero()
+        );
     }
 }
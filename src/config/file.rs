@@ -1,13 +1,22 @@
 use crate::{
     bitcoin,
-    config::{Bitcoind, Data, MaxSell, Network},
+    config::{
+        Accounting, Alerting, Api, BitcoinConfirmations, BitcoinWallet, Bitcoind, CircuitBreaker,
+        CoinSelection, Data, Derivation, ErrorReporting, EthereumSigner, Expiries, FeeBumping,
+        GasPrice, History, InventorySkew, MaxSell, MaxVolume, MinBalance, MinSell, Network,
+        NodeAuth, OrderLevel, OrderRounding, Rate, RateHysteresis, Rebalance, ReputationPolicy,
+        Rpc, SwapRole, Takers, Telemetry, Watchdog,
+    },
     Spread,
 };
 use comit::ethereum::ChainId;
 use config as config_rs;
 use log::LevelFilter;
 use serde::{Deserialize, Serialize};
-use std::{ffi::OsStr, path::Path};
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
 use url::Url;
 
 /// This struct aims to represent the configuration file as it appears on disk.
@@ -23,13 +32,44 @@ pub struct File {
     pub logging: Option<Logging>,
     pub bitcoin: Option<Bitcoin>,
     pub ethereum: Option<Ethereum>,
+    pub api: Option<Api>,
+    #[serde(alias = "notifications")]
+    pub alerting: Option<Alerting>,
+    pub history: Option<History>,
+    pub telemetry: Option<Telemetry>,
+    pub error_reporting: Option<ErrorReporting>,
+    pub accounting: Option<Accounting>,
+    pub watchdog: Option<Watchdog>,
+    pub rate: Option<Rate>,
+    pub rpc: Option<Rpc>,
+    pub takers: Option<Takers>,
+    pub derivation: Option<Derivation>,
 }
 
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct Maker {
     pub spread: Option<Spread>,
+    pub sell_spread: Option<Spread>,
+    pub buy_spread: Option<Spread>,
     pub max_sell: Option<MaxSell>,
     pub maximum_possible_fee: Option<Fees>,
+    pub max_concurrent_swaps: Option<usize>,
+    pub max_volume_per_24h: Option<MaxVolume>,
+    pub republish_interval_secs: Option<u64>,
+    pub levels: Option<Vec<OrderLevel>>,
+    pub inventory_skew: Option<InventorySkew>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rebalance: Option<Rebalance>,
+    pub circuit_breaker: Option<CircuitBreaker>,
+    pub rate_hysteresis: Option<RateHysteresis>,
+    pub reputation: Option<ReputationPolicy>,
+    pub expiries: Option<Expiries>,
+    pub bitcoin_confirmations: Option<Vec<BitcoinConfirmations>>,
+    pub min_balance: Option<MinBalance>,
+    pub min_sell: Option<MinSell>,
+    pub order_rounding: Option<OrderRounding>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<SwapRole>,
 }
 
 #[derive(Copy, Clone, Debug, Deserialize, PartialEq, Serialize)]
@@ -44,15 +84,27 @@ pub struct Bitcoin {
     #[serde(with = "crate::config::serde::bitcoin_network")]
     pub network: bitcoin::Network,
     pub bitcoind: Option<Bitcoind>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wallet: Option<BitcoinWallet>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coin_selection: Option<CoinSelection>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fee_bumping: Option<FeeBumping>,
 }
 
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct Ethereum {
     pub chain_id: ChainId,
     pub node_url: Option<Url>,
+    #[serde(flatten)]
+    pub auth: NodeAuth,
     #[serde(default)]
     #[serde(with = "crate::config::serde::ethereum_address")]
     pub local_dai_contract_address: Option<comit::ethereum::Address>,
+    #[serde(default)]
+    pub gas_price: Option<GasPrice>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signer: Option<EthereumSigner>,
 }
 
 impl File {
@@ -64,6 +116,17 @@ impl File {
             logging: None,
             bitcoin: None,
             ethereum: None,
+            api: None,
+            alerting: None,
+            history: None,
+            telemetry: None,
+            error_reporting: None,
+            accounting: None,
+            watchdog: None,
+            rate: None,
+            rpc: None,
+            takers: None,
+            derivation: None,
         }
     }
 
@@ -74,14 +137,66 @@ impl File {
         let config_file = Path::new(&config_file);
 
         let mut config = config_rs::Config::new();
-        config.merge(config_rs::File::from(config_file))?;
+        merge_with_includes(&mut config, config_file, &mut Vec::new())?;
         config.try_into()
     }
 }
 
+/// Merge the file at `path` into `config`, preceded by the files listed in its
+/// `include` array.
+///
+/// Included files are merged in the order they are listed, each one
+/// overriding the values of the previous ones. The including file is merged
+/// last and therefore overrides any value it includes. Relative paths are
+/// resolved against the directory of the including file.
+fn merge_with_includes(
+    config: &mut config_rs::Config,
+    path: &Path,
+    ancestors: &mut Vec<PathBuf>,
+) -> Result<(), config_rs::ConfigError> {
+    // Canonical so that e.g. `../conf/a.toml` is recognized as an ancestor
+    let path = &std::fs::canonicalize(path).map_err(|e| {
+        config_rs::ConfigError::Message(format!(
+            "unable to resolve config file {}: {}",
+            path.display(),
+            e
+        ))
+    })?;
+    if ancestors.iter().any(|ancestor| ancestor == path) {
+        return Err(config_rs::ConfigError::Message(format!(
+            "config file {} includes itself",
+            path.display()
+        )));
+    }
+
+    let mut own = config_rs::Config::new();
+    own.merge(config_rs::File::from(path))?;
+
+    let includes = match own.get::<Vec<PathBuf>>("include") {
+        Ok(includes) => includes,
+        Err(config_rs::ConfigError::NotFound(_)) => vec![],
+        Err(e) => return Err(e),
+    };
+
+    ancestors.push(path.to_path_buf());
+    for include in includes {
+        let include = match path.parent() {
+            Some(dir) => dir.join(include),
+            None => include,
+        };
+        merge_with_includes(config, &include, ancestors)?;
+    }
+    ancestors.pop();
+
+    config.merge(config_rs::File::from(path))?;
+
+    Ok(())
+}
+
 #[derive(Clone, Copy, Debug, serde::Serialize, serde::Deserialize, PartialEq)]
 pub struct Logging {
     pub level: Option<Level>,
+    pub format: Option<Format>,
 }
 
 #[derive(Clone, Copy, Debug, serde::Serialize, serde::Deserialize, PartialEq)]
@@ -93,6 +208,14 @@ pub enum Level {
     Trace,
 }
 
+#[derive(Clone, Copy, Debug, serde::Serialize, serde::Deserialize, PartialEq)]
+pub enum Format {
+    /// Human readable lines
+    Text,
+    /// One JSON object per line, suited for log aggregators
+    Json,
+}
+
 impl From<LevelFilter> for Level {
     fn from(level: LevelFilter) -> Self {
         match level {
@@ -143,7 +266,7 @@ mod tests {
     use super::*;
     use crate::{
         bitcoin,
-        config::{Bitcoind, Settings},
+        config::{Bitcoind, Exchange, Settings, Webhook},
         ethereum::dai,
     };
     use spectral::prelude::*;
@@ -167,6 +290,12 @@ maximum_possible_fee = { bitcoin = 0.01 }
 bitcoin = 1.23456
 dai = 9876.54321
 
+[maker.max_volume_per_24h]
+bitcoin = 2.5
+
+[maker.min_balance]
+bitcoin = 0.05
+
 [network]
 listen = ["/ip4/0.0.0.0/tcp/9939"]
 
@@ -186,6 +315,20 @@ node_url = "http://localhost:18443/"
 chain_id = 1337
 node_url = "http://localhost:8545/"
 local_dai_contract_address = "0x6A9865aDE2B6207dAAC49f8bCba9705dEB0B0e6D"
+
+[ethereum.gas_price]
+max_gwei = 200
+
+[rate]
+exchanges = ["kraken", "coinbase"]
+refresh_interval_secs = 30
+
+[takers]
+banned = ["QmUJF1AzhjUfDU1ifzkyuHy26SCnNHbPaVHpX1WYxYYgZg"]
+
+[derivation]
+scheme = "bip44"
+account = 1
 "#;
         let expected = File {
             maker: Some(Maker {
@@ -194,33 +337,92 @@ local_dai_contract_address = "0x6A9865aDE2B6207dAAC49f8bCba9705dEB0B0e6D"
                     dai: Some(dai::Amount::from_dai_trunc(9876.54321).unwrap()),
                 }),
                 spread: Some(Spread::new(1000).unwrap()),
+                sell_spread: None,
+                buy_spread: None,
                 maximum_possible_fee: Some(Fees {
                     bitcoin: Some(bitcoin::Amount::from_btc(0.01).unwrap()),
                 }),
+                max_concurrent_swaps: None,
+                max_volume_per_24h: Some(MaxVolume {
+                    bitcoin: Some(bitcoin::Amount::from_btc(2.5).unwrap()),
+                    dai: None,
+                }),
+                republish_interval_secs: None,
+                levels: None,
+                inventory_skew: None,
+                rebalance: None,
+                circuit_breaker: None,
+                rate_hysteresis: None,
+                reputation: None,
+                expiries: None,
+                bitcoin_confirmations: None,
+                min_balance: Some(MinBalance {
+                    bitcoin: Some(bitcoin::Amount::from_btc(0.05).unwrap()),
+                    dai: None,
+                }),
+                min_sell: None,
+                order_rounding: None,
+                role: None,
             }),
             network: Some(Network {
                 listen: vec!["/ip4/0.0.0.0/tcp/9939".parse().unwrap()],
+                external_addresses: vec![],
+                peers: vec![],
+                max_isolation_secs: None,
             }),
             data: Some(Data {
                 dir: PathBuf::from("/tmp/nectar/"),
             }),
             logging: Some(Logging {
                 level: Some(Level::Debug),
+                format: None,
             }),
             bitcoin: Some(Bitcoin {
                 network: bitcoin::Network::Regtest,
-                bitcoind: Some(Bitcoind {
-                    node_url: "http://localhost:18443".parse().unwrap(),
-                }),
+                bitcoind: Some(Bitcoind::new("http://localhost:18443".parse().unwrap())),
+                wallet: None,
+                coin_selection: None,
+                fee_bumping: None,
             }),
             ethereum: Some(Ethereum {
                 chain_id: ChainId::GETH_DEV,
                 node_url: Some("http://localhost:8545".parse().unwrap()),
+                auth: NodeAuth::default(),
                 local_dai_contract_address: Some(
                     "0x6A9865aDE2B6207dAAC49f8bCba9705dEB0B0e6D"
                         .parse()
                         .unwrap(),
                 ),
+                gas_price: Some(GasPrice {
+                    oracle_url: None,
+                    max_gwei: Some(200),
+                }),
+                signer: None,
+            }),
+            api: None,
+            alerting: None,
+            history: None,
+            telemetry: None,
+            error_reporting: None,
+            accounting: None,
+            watchdog: None,
+            rate: Some(Rate {
+                exchanges: vec![Exchange::Kraken, Exchange::Coinbase],
+                request_timeout_secs: 10,
+                refresh_interval_secs: 30,
+                max_age_secs: 60,
+                failover: None,
+            }),
+            rpc: None,
+            takers: Some(Takers {
+                banned: vec!["QmUJF1AzhjUfDU1ifzkyuHy26SCnNHbPaVHpX1WYxYYgZg"
+                    .parse()
+                    .unwrap()],
+                allowed: vec![],
+            }),
+            derivation: Some(Derivation::Bip44 {
+                account: 1,
+                index: 0,
             }),
         };
 
@@ -235,6 +437,91 @@ local_dai_contract_address = "0x6A9865aDE2B6207dAAC49f8bCba9705dEB0B0e6D"
         assert_that(&file).is_ok().is_equal_to(expected);
     }
 
+    #[test]
+    fn included_files_are_merged_and_overridden_by_including_file() {
+        let tmp_dir = TempDir::new("nectar_test").unwrap();
+
+        let network_path = tmp_dir.path().join("network.toml");
+        let mut network_file = std::fs::File::create(&network_path).unwrap();
+        network_file
+            .write_all(
+                br#"
+[network]
+listen = ["/ip4/0.0.0.0/tcp/9939"]
+
+[logging]
+level = "Trace"
+"#,
+            )
+            .unwrap();
+
+        let main_path = tmp_dir.path().join("config.toml");
+        let mut main_file = std::fs::File::create(&main_path).unwrap();
+        main_file
+            .write_all(
+                br#"
+include = ["network.toml"]
+
+[logging]
+level = "Debug"
+"#,
+            )
+            .unwrap();
+
+        let file = File::read(&main_path).unwrap();
+
+        assert_eq!(
+            file.network,
+            Some(Network {
+                listen: vec!["/ip4/0.0.0.0/tcp/9939".parse().unwrap()],
+                external_addresses: vec![],
+                peers: vec![],
+                max_isolation_secs: None,
+            })
+        );
+        assert_eq!(
+            file.logging,
+            Some(Logging {
+                level: Some(Level::Debug),
+                format: None,
+            })
+        );
+    }
+
+    #[test]
+    fn circular_includes_are_rejected() {
+        let tmp_dir = TempDir::new("nectar_test").unwrap();
+
+        let a_path = tmp_dir.path().join("a.toml");
+        let mut a_file = std::fs::File::create(&a_path).unwrap();
+        a_file.write_all(br#"include = ["b.toml"]"#).unwrap();
+
+        let b_path = tmp_dir.path().join("b.toml");
+        let mut b_file = std::fs::File::create(&b_path).unwrap();
+        b_file.write_all(br#"include = ["a.toml"]"#).unwrap();
+
+        let file = File::read(&a_path);
+
+        assert!(file.is_err());
+    }
+
+    #[test]
+    fn file_including_itself_through_parent_dir_is_rejected() {
+        let tmp_dir = TempDir::new("nectar_test").unwrap();
+        let conf_dir = tmp_dir.path().join("conf");
+        std::fs::create_dir(&conf_dir).unwrap();
+
+        let a_path = conf_dir.join("a.toml");
+        let mut a_file = std::fs::File::create(&a_path).unwrap();
+        a_file
+            .write_all(br#"include = ["../conf/a.toml"]"#)
+            .unwrap();
+
+        let file = File::read(&a_path);
+
+        assert!(file.is_err());
+    }
+
     #[test]
     fn full_config_serializes_correctly() {
         let file = File {
@@ -244,34 +531,73 @@ local_dai_contract_address = "0x6A9865aDE2B6207dAAC49f8bCba9705dEB0B0e6D"
                     dai: Some(dai::Amount::from_dai_trunc(9876.54321).unwrap()),
                 }),
                 spread: Some(Spread::new(1000).unwrap()),
+                sell_spread: None,
+                buy_spread: None,
                 maximum_possible_fee: Some(Fees {
                     bitcoin: Some(bitcoin::Amount::from_btc(0.01).unwrap()),
                 }),
+                max_concurrent_swaps: None,
+                max_volume_per_24h: Some(MaxVolume {
+                    bitcoin: Some(bitcoin::Amount::from_btc(2.5).unwrap()),
+                    dai: None,
+                }),
+                republish_interval_secs: None,
+                levels: None,
+                inventory_skew: None,
+                rebalance: None,
+                circuit_breaker: None,
+                rate_hysteresis: None,
+                reputation: None,
+                expiries: None,
+                bitcoin_confirmations: None,
+                min_balance: None,
+                min_sell: None,
+                order_rounding: None,
+                role: None,
             }),
             network: Some(Network {
                 listen: vec!["/ip4/0.0.0.0/tcp/9939".parse().unwrap()],
+                external_addresses: vec![],
+                peers: vec![],
+                max_isolation_secs: None,
             }),
             data: Some(Data {
                 dir: PathBuf::from("/tmp/nectar/"),
             }),
             logging: Some(Logging {
                 level: Some(Level::Debug),
+                format: None,
             }),
             bitcoin: Some(Bitcoin {
                 network: bitcoin::Network::Regtest,
-                bitcoind: Some(Bitcoind {
-                    node_url: "http://localhost:18443".parse().unwrap(),
-                }),
+                bitcoind: Some(Bitcoind::new("http://localhost:18443".parse().unwrap())),
+                wallet: None,
+                coin_selection: None,
+                fee_bumping: None,
             }),
             ethereum: Some(Ethereum {
                 chain_id: ChainId::GETH_DEV,
                 node_url: Some("http://localhost:8545".parse().unwrap()),
+                auth: NodeAuth::default(),
                 local_dai_contract_address: Some(
                     "0x6A9865aDE2B6207dAAC49f8bCba9705dEB0B0e6D"
                         .parse()
                         .unwrap(),
                 ),
+                gas_price: None,
+                signer: None,
             }),
+            api: None,
+            alerting: None,
+            history: None,
+            telemetry: None,
+            error_reporting: None,
+            accounting: None,
+            watchdog: None,
+            rate: None,
+            rpc: None,
+            takers: None,
+            derivation: None,
         };
 
         let expected = r#"[maker]
@@ -284,6 +610,9 @@ dai = 9876.54
 [maker.maximum_possible_fee]
 bitcoin = 0.01
 
+[maker.max_volume_per_24h]
+bitcoin = 2.5
+
 [network]
 listen = ["/ip4/0.0.0.0/tcp/9939"]
 
@@ -352,21 +681,30 @@ local_dai_contract_address = "0x6a9865ade2b6207daac49f8bcba9705deb0b0e6d"
         let expected = vec![
             Bitcoin {
                 network: bitcoin::Network::Bitcoin,
-                bitcoind: Some(Bitcoind {
-                    node_url: Url::parse("http://example.com:8332").unwrap(),
-                }),
+                bitcoind: Some(Bitcoind::new(
+                    Url::parse("http://example.com:8332").unwrap(),
+                )),
+                wallet: None,
+                coin_selection: None,
+                fee_bumping: None,
             },
             Bitcoin {
                 network: bitcoin::Network::Testnet,
-                bitcoind: Some(Bitcoind {
-                    node_url: Url::parse("http://example.com:18332").unwrap(),
-                }),
+                bitcoind: Some(Bitcoind::new(
+                    Url::parse("http://example.com:18332").unwrap(),
+                )),
+                wallet: None,
+                coin_selection: None,
+                fee_bumping: None,
             },
             Bitcoin {
                 network: bitcoin::Network::Regtest,
-                bitcoind: Some(Bitcoind {
-                    node_url: Url::parse("http://example.com:18443").unwrap(),
-                }),
+                bitcoind: Some(Bitcoind::new(
+                    Url::parse("http://example.com:18443").unwrap(),
+                )),
+                wallet: None,
+                coin_selection: None,
+                fee_bumping: None,
             },
         ];
 
@@ -379,6 +717,27 @@ local_dai_contract_address = "0x6a9865ade2b6207daac49f8bcba9705deb0b0e6d"
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn bitcoin_wallet_can_be_external() {
+        let contents = r#"
+            network = "mainnet"
+            [wallet]
+            mode = "external"
+            name = "operator"
+            descriptors = ["wpkh(xpub6CUGRUonZSQ4TWtTMmzXdrXDtypWKiKrhko4egpiMZbpiaQL2jkwSB1icqYh2cfDfVxdx4df189oLKnC5fSwqPfgyP3hooxujYzAu3fDVmz/0/*)"]
+            "#;
+
+        let bitcoin = toml::from_str::<Bitcoin>(contents).unwrap();
+
+        assert_eq!(
+            bitcoin.wallet,
+            Some(BitcoinWallet::External {
+                name: "operator".to_owned(),
+                descriptors: vec!["wpkh(xpub6CUGRUonZSQ4TWtTMmzXdrXDtypWKiKrhko4egpiMZbpiaQL2jkwSB1icqYh2cfDfVxdx4df189oLKnC5fSwqPfgyP3hooxujYzAu3fDVmz/0/*)".to_owned()],
+            })
+        );
+    }
+
     #[test]
     fn ethereum_deserializes_correctly() {
         let file_contents = vec![
@@ -390,32 +749,70 @@ local_dai_contract_address = "0x6a9865ade2b6207daac49f8bcba9705deb0b0e6d"
             r#"
             chain_id = 3
             node_url = "http://example.com:8545"
+            bearer_token = "secret"
+
+            [headers]
+            X-Api-Key = "key"
             "#,
             r#"
             chain_id = 1
             node_url = "http://example.com:8545"
             "#,
+            r#"
+            chain_id = 1
+            node_url = "https://mainnet.infura.io/v3/project"
+            username = ""
+            password = "project-secret"
+            "#,
         ];
 
         let expected = vec![
             Ethereum {
                 chain_id: ChainId::GETH_DEV,
                 node_url: Some(Url::parse("http://example.com:8545").unwrap()),
+                auth: NodeAuth::default(),
                 local_dai_contract_address: Some(
                     "0x31F42841c2db5173425b5223809CF3A38FEde360"
                         .parse()
                         .unwrap(),
                 ),
+                gas_price: None,
+                signer: None,
             },
             Ethereum {
                 chain_id: ChainId::ROPSTEN,
                 node_url: Some(Url::parse("http://example.com:8545").unwrap()),
+                auth: NodeAuth {
+                    username: None,
+                    password: None,
+                    bearer_token: Some("secret".to_owned()),
+                    headers: vec![("X-Api-Key".to_owned(), "key".to_owned())]
+                        .into_iter()
+                        .collect(),
+                },
                 local_dai_contract_address: None,
+                gas_price: None,
+                signer: None,
             },
             Ethereum {
                 chain_id: ChainId::MAINNET,
                 node_url: Some(Url::parse("http://example.com:8545").unwrap()),
+                auth: NodeAuth::default(),
                 local_dai_contract_address: None,
+                gas_price: None,
+                signer: None,
+            },
+            Ethereum {
+                chain_id: ChainId::MAINNET,
+                node_url: Some(Url::parse("https://mainnet.infura.io/v3/project").unwrap()),
+                auth: NodeAuth {
+                    username: Some("".to_owned()),
+                    password: Some("project-secret".to_owned()),
+                    ..NodeAuth::default()
+                },
+                local_dai_contract_address: None,
+                gas_price: None,
+                signer: None,
             },
         ];
 
@@ -488,4 +885,42 @@ local_dai_contract_address = "0x6a9865ade2b6207daac49f8bcba9705deb0b0e6d"
 
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn alerting_can_be_configured_as_notifications() {
+        let file_contents = r#"
+            [notifications]
+            webhook = { url = "https://alerts.example.com/nectar" }
+            min_balance = { bitcoin = 0.5, dai = 1000 }
+            "#;
+
+        let file = toml::from_str::<File>(file_contents).unwrap();
+
+        assert_eq!(
+            file.alerting,
+            Some(Alerting {
+                telegram: None,
+                slack: None,
+                webhook: Some(Webhook {
+                    url: Url::parse("https://alerts.example.com/nectar").unwrap(),
+                }),
+                min_balance: Some(MinBalance {
+                    bitcoin: Some(bitcoin::Amount::from_btc(0.5).unwrap()),
+                    dai: Some(dai::Amount::from_dai_trunc(1000.0).unwrap()),
+                }),
+            })
+        );
+    }
+
+    #[test]
+    fn maker_can_take_the_role_of_alice() {
+        let file_contents = r#"
+            [maker]
+            role = "alice"
+            "#;
+
+        let file = toml::from_str::<File>(file_contents).unwrap();
+
+        assert_eq!(file.maker.unwrap().role, Some(SwapRole::Alice));
+    }
 }
@@ -9,6 +9,8 @@ use std::{
     path::{Path, PathBuf},
 };
 
+const SEED_FILE: &str = "seed.pem";
+
 #[derive(Clone, Copy, PartialEq)]
 pub struct Seed(seed::Seed);
 
@@ -18,7 +20,7 @@ impl Seed {
     }
 
     pub fn from_file_or_generate(data_dir: &PathBuf) -> Result<Self, Error> {
-        let file_path_buf = data_dir.join("seed.pem");
+        let file_path_buf = data_dir.join(SEED_FILE);
         let file_path = Path::new(&file_path_buf);
 
         if file_path.exists() {
@@ -33,6 +35,35 @@ impl Seed {
         Ok(random_seed)
     }
 
+    /// Read the seed of the data directory, without generating one.
+    pub fn from_data_dir(data_dir: &PathBuf) -> Result<Self, Error> {
+        let file_path = data_dir.join(SEED_FILE);
+        if !file_path.exists() {
+            return Err(Error::NotFound(file_path));
+        }
+
+        Self::from_file(&file_path)
+    }
+
+    /// Write the seed to the data directory, an existing seed is only
+    /// overwritten if `force` is set.
+    pub fn write_to_data_dir(&self, data_dir: &PathBuf, force: bool) -> Result<(), Error> {
+        let file_path = data_dir.join(SEED_FILE);
+        if file_path.exists() && !force {
+            return Err(Error::AlreadyExists(file_path));
+        }
+
+        self.write_to(file_path)
+    }
+
+    pub fn from_mnemonic(phrase: &str) -> Result<Self, Error> {
+        Ok(Seed(seed::Seed::from_mnemonic(phrase)?))
+    }
+
+    pub fn to_mnemonic(&self) -> String {
+        self.0.to_mnemonic()
+    }
+
     fn from_file<D>(seed_file: D) -> Result<Self, Error>
     where
         D: AsRef<OsStr>,
@@ -115,12 +146,17 @@ pub enum Error {
     Rand(#[from] rand::Error),
     #[error("no default path")]
     NoDefaultPath,
+    #[error("no seed file at {0}")]
+    NotFound(PathBuf),
+    #[error("seed file {0} already exists")]
+    AlreadyExists(PathBuf),
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::env::temp_dir;
+    use tempdir::TempDir;
 
     #[test]
     fn seed_byte_string_must_be_32_bytes_long() {
@@ -193,4 +229,23 @@ mbKANv2qKGmNVg1qtquj6Hx1pFPelpqOfE2JaJJAMEg1FlFhNRNlFlE=
         let rinsed = Seed::from_file(tmpfile).expect("Read from temp file");
         assert_eq!(seed.0, rinsed.0);
     }
+
+    #[test]
+    fn existing_seed_is_not_overwritten_unless_forced() {
+        let data_dir = TempDir::new("nectar_seed").unwrap();
+        let data_dir = data_dir.path().to_path_buf();
+
+        let seed = Seed::random().unwrap();
+        seed.write_to_data_dir(&data_dir, false).unwrap();
+
+        let other = Seed::random().unwrap();
+        assert!(matches!(
+            other.write_to_data_dir(&data_dir, false),
+            Err(Error::AlreadyExists(_))
+        ));
+        assert_eq!(Seed::from_data_dir(&data_dir).unwrap(), seed);
+
+        other.write_to_data_dir(&data_dir, true).unwrap();
+        assert_eq!(Seed::from_data_dir(&data_dir).unwrap(), other);
+    }
 }
@@ -2,3 +2,4 @@ pub mod bitcoin_amount;
 pub mod bitcoin_network;
 pub mod dai_amount;
 pub mod ethereum_address;
+pub mod peer_ids;
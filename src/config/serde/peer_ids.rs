@@ -0,0 +1,27 @@
+use libp2p::PeerId;
+use serde::{Deserialize, Deserializer, Serializer};
+use std::str::FromStr;
+
+pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<PeerId>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|peer_id| {
+            PeerId::from_str(peer_id).map_err(|error| {
+                serde::de::Error::custom(format!(
+                    "Could not deserialize peer id {}: {:?}",
+                    peer_id, error
+                ))
+            })
+        })
+        .collect()
+}
+
+pub fn serialize<S>(value: &[PeerId], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.collect_seq(value.iter().map(PeerId::to_string))
+}
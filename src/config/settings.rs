@@ -1,6 +1,12 @@
 use crate::{
     bitcoin,
-    config::{file, Bitcoind, Data, File, MaxSell, Network},
+    config::{
+        file, url_with_credentials, Accounting, Alerting, Api, BitcoinConfirmations, BitcoinWallet,
+        Bitcoind, CircuitBreaker, CoinSelection, Data, Derivation, ErrorReporting, EthereumSigner,
+        Expiries, FeeBumping, File, GasPrice, History, InventorySkew, MaxSell, MaxVolume,
+        MinBalance, MinSell, Network, NodeAuth, OrderLevel, OrderRounding, Rate, RateHysteresis,
+        Rebalance, ReputationPolicy, Rpc, SwapRole, Takers, Telemetry, Watchdog,
+    },
     ethereum, Spread,
 };
 use anyhow::Context;
@@ -8,6 +14,9 @@ use log::LevelFilter;
 use std::convert::{TryFrom, TryInto};
 use url::Url;
 
+/// BIP-32 child indexes from this one on are hardened.
+const FIRST_HARDENED_INDEX: u32 = 1 << 31;
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct Settings {
     pub maker: Maker,
@@ -16,22 +25,40 @@ pub struct Settings {
     pub logging: Logging,
     pub bitcoin: Bitcoin,
     pub ethereum: Ethereum,
+    pub api: Api,
+    pub alerting: Alerting,
+    pub history: History,
+    pub telemetry: Option<Telemetry>,
+    pub error_reporting: Option<ErrorReporting>,
+    pub accounting: Option<Accounting>,
+    pub watchdog: Watchdog,
+    pub rate: Rate,
+    pub rpc: Rpc,
+    pub takers: Takers,
+    pub derivation: Derivation,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Bitcoin {
     pub network: bitcoin::Network,
     pub bitcoind: Bitcoind,
+    pub wallet: BitcoinWallet,
+    /// Outputs are selected by bitcoind if `None`.
+    pub coin_selection: Option<CoinSelection>,
+    /// Our Bitcoin HTLC funding is never replaced if `None`.
+    pub fee_bumping: Option<FeeBumping>,
 }
 
 impl Default for Bitcoin {
     fn default() -> Self {
         Self {
             network: bitcoin::Network::Regtest,
-            bitcoind: Bitcoind {
-                node_url: Url::parse("http://localhost:18443")
-                    .expect("static string to be a valid url"),
-            },
+            bitcoind: Bitcoind::new(
+                Url::parse("http://localhost:18443").expect("static string to be a valid url"),
+            ),
+            wallet: BitcoinWallet::default(),
+            coin_selection: None,
+            fee_bumping: None,
         }
     }
 }
@@ -41,6 +68,9 @@ impl From<Bitcoin> for file::Bitcoin {
         file::Bitcoin {
             network: bitcoin.network,
             bitcoind: Some(bitcoin.bitcoind),
+            wallet: Some(bitcoin.wallet).filter(|wallet| *wallet != BitcoinWallet::default()),
+            coin_selection: bitcoin.coin_selection,
+            fee_bumping: bitcoin.fee_bumping,
         }
     }
 }
@@ -48,11 +78,26 @@ impl From<Bitcoin> for file::Bitcoin {
 #[derive(Clone, Debug, PartialEq)]
 pub struct Ethereum {
     pub node_url: Url,
+    pub auth: NodeAuth,
     pub chain: ethereum::Chain,
+    pub gas_price: GasPrice,
+    pub signer: EthereumSigner,
+}
+
+impl Ethereum {
+    /// `node_url` with the basic authentication credentials, for the
+    /// connectors which only support credentials in the URL.
+    pub fn url_with_credentials(&self) -> anyhow::Result<Url> {
+        url_with_credentials(&self.node_url, self.auth.basic_auth())
+    }
 }
 
 impl From<Ethereum> for file::Ethereum {
     fn from(ethereum: Ethereum) -> Self {
+        let gas_price =
+            Some(ethereum.gas_price).filter(|gas_price| *gas_price != GasPrice::default());
+        let signer = Some(ethereum.signer).filter(|signer| *signer != EthereumSigner::default());
+
         match ethereum.chain {
             ethereum::Chain::Local {
                 chain_id,
@@ -60,12 +105,18 @@ impl From<Ethereum> for file::Ethereum {
             } => file::Ethereum {
                 chain_id: chain_id.into(),
                 node_url: Some(ethereum.node_url),
+                auth: ethereum.auth,
                 local_dai_contract_address: Some(dai_contract_address),
+                gas_price,
+                signer,
             },
             _ => file::Ethereum {
                 chain_id: ethereum.chain.chain_id(),
                 node_url: Some(ethereum.node_url),
+                auth: ethereum.auth,
                 local_dai_contract_address: None,
+                gas_price,
+                signer,
             },
         }
     }
@@ -96,7 +147,24 @@ impl TryFrom<Option<file::Ethereum>> for Ethereum {
                     (chain_id, None) => ethereum::Chain::from_public_chain_id(chain_id)?,
                 };
 
-                Ok(Ethereum { node_url, chain })
+                let gas_price = file_ethereum.gas_price.unwrap_or_default();
+                if gas_price.max_gwei == Some(0) {
+                    anyhow::bail!("Maximum gas price must be greater than 0")
+                }
+
+                if file_ethereum.auth.bearer_token.is_some()
+                    && file_ethereum.auth.basic_auth().is_some()
+                {
+                    anyhow::bail!("bearer_token cannot be set along with username and password")
+                }
+
+                Ok(Ethereum {
+                    node_url,
+                    auth: file_ethereum.auth,
+                    chain,
+                    gas_price,
+                    signer: file_ethereum.signer.unwrap_or_default(),
+                })
             }
         }
     }
@@ -106,7 +174,10 @@ impl Default for Ethereum {
     fn default() -> Self {
         Self {
             node_url: Url::parse("http://localhost:8545").expect("static string to be a valid url"),
+            auth: NodeAuth::default(),
             chain: ethereum::Chain::Mainnet,
+            gas_price: GasPrice::default(),
+            signer: EthereumSigner::default(),
         }
     }
 }
@@ -118,10 +189,52 @@ pub struct Maker {
     /// Spread to apply to the mid-market rate, format is permyriad. E.g. 5.20
     /// is 5.2% spread
     pub spread: Spread,
+    /// Spread of the orders selling bitcoin, `spread` if `None`.
+    pub sell_spread: Option<Spread>,
+    /// Spread of the orders buying bitcoin, `spread` if `None`.
+    pub buy_spread: Option<Spread>,
     /// Maximum possible network fee to consider when calculating the available
     /// balance. Fees are in the nominal native currency and per
     /// transaction.
     pub maximum_possible_fee: Fees,
+    /// Maximum number of swaps executed simultaneously, further swaps are
+    /// queued until a running one finishes. Unbounded if `None`.
+    pub max_concurrent_swaps: Option<usize>,
+    /// Maximum volume to sell over any 24 hours, takes that would exceed it
+    /// are declined.
+    pub max_volume_per_24h: MaxVolume,
+    /// Orders are withdrawn and published again this often even if nothing
+    /// changed.
+    pub republish_interval_secs: u64,
+    /// Ladder of orders published per position, a single order at `spread`
+    /// and capped by `max_sell` if empty.
+    pub levels: Vec<OrderLevel>,
+    /// Skew of the spreads by the inventory, static spreads if `None`.
+    pub inventory_skew: Option<InventorySkew>,
+    /// Rebalance recommendations are not made if `None`.
+    pub rebalance: Option<Rebalance>,
+    /// Halts trading when the rate moves too fast. Disabled if `None`.
+    pub circuit_breaker: Option<CircuitBreaker>,
+    /// Orders are republished upon any change of the rate if `None`.
+    pub rate_hysteresis: Option<RateHysteresis>,
+    /// Takes are accepted regardless of the track record of the taker if
+    /// `None`.
+    pub reputation: Option<ReputationPolicy>,
+    /// Expiry offsets of the HTLCs, the defaults of the swap protocol if
+    /// `None`.
+    pub expiries: Option<Expiries>,
+    /// Confirmations of the Bitcoin HTLC funded by the taker required by
+    /// swap amount, a single one if empty.
+    pub bitcoin_confirmations: Vec<BitcoinConfirmations>,
+    /// Orders selling an asset are withdrawn while its balance is below this
+    /// amount, and published again once replenished.
+    pub min_balance: MinBalance,
+    /// Minimum amount to sell per order, smaller orders are not published.
+    pub min_sell: MinSell,
+    /// Amounts of the published orders are not rounded if empty.
+    pub order_rounding: OrderRounding,
+    /// The role we take in the swaps of our orders.
+    pub role: SwapRole,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -144,29 +257,88 @@ impl Default for Fees {
 pub struct Logging {
     #[derivative(Default(value = "LevelFilter::Info"))]
     pub level: LevelFilter,
+    #[derivative(Default(value = "file::Format::Text"))]
+    pub format: file::Format,
+}
+
+/// Levels must have strictly increasing spreads and only the last one may
+/// take all that is left.
+fn validate_levels(levels: &[OrderLevel]) -> anyhow::Result<()> {
+    for pair in levels.windows(2) {
+        if pair[0].spread.permyriad() >= pair[1].spread.permyriad() {
+            anyhow::bail!("maker levels must be ordered by strictly increasing spread");
+        }
+        if pair[0].bitcoin.is_none() {
+            anyhow::bail!("only the last maker level can omit its bitcoin amount");
+        }
+    }
+
+    if levels
+        .iter()
+        .any(|level| level.bitcoin == Some(bitcoin::Amount::ZERO))
+    {
+        anyhow::bail!("maker levels must have a bitcoin amount greater than 0");
+    }
+
+    Ok(())
+}
+
+/// Confirmations must be ordered by strictly increasing amount, only the
+/// first one may apply to any amount, and at least one block is required.
+fn validate_bitcoin_confirmations(confirmations: &[BitcoinConfirmations]) -> anyhow::Result<()> {
+    for pair in confirmations.windows(2) {
+        match (pair[0].bitcoin, pair[1].bitcoin) {
+            (_, None) => {
+                anyhow::bail!("only the first bitcoin confirmations can omit its bitcoin amount")
+            }
+            (Some(lower), Some(higher)) if lower >= higher => anyhow::bail!(
+                "bitcoin confirmations must be ordered by strictly increasing bitcoin amount"
+            ),
+            _ => {}
+        }
+    }
+
+    if confirmations.iter().any(|tier| tier.blocks == 0) {
+        anyhow::bail!("bitcoin confirmations must require at least 1 block");
+    }
+
+    Ok(())
+}
+
+fn exceeds<T: PartialOrd>(min: &Option<T>, max: &Option<T>) -> bool {
+    match (min, max) {
+        (Some(min), Some(max)) => min > max,
+        _ => false,
+    }
 }
 
 fn derive_url_bitcoin(bitcoin: Option<file::Bitcoin>) -> Bitcoin {
     match bitcoin {
         None => Bitcoin::default(),
         Some(bitcoin) => {
-            let node_url = match bitcoin.bitcoind {
-                Some(bitcoind) => bitcoind.node_url,
-                None => match bitcoin.network {
-                    bitcoin::Network::Bitcoin => "http://localhost:8332"
-                        .parse()
-                        .expect("to be valid static string"),
-                    bitcoin::Network::Testnet => "http://localhost:18332"
-                        .parse()
-                        .expect("to be valid static string"),
-                    bitcoin::Network::Regtest => "http://localhost:18443"
-                        .parse()
-                        .expect("to be valid static string"),
-                },
+            let bitcoind = match bitcoin.bitcoind {
+                Some(bitcoind) => bitcoind,
+                None => {
+                    let node_url = match bitcoin.network {
+                        bitcoin::Network::Bitcoin => "http://localhost:8332"
+                            .parse()
+                            .expect("to be valid static string"),
+                        bitcoin::Network::Testnet => "http://localhost:18332"
+                            .parse()
+                            .expect("to be valid static string"),
+                        bitcoin::Network::Regtest => "http://localhost:18443"
+                            .parse()
+                            .expect("to be valid static string"),
+                    };
+                    Bitcoind::new(node_url)
+                }
             };
             Bitcoin {
                 network: bitcoin.network,
-                bitcoind: Bitcoind { node_url },
+                bitcoind,
+                wallet: bitcoin.wallet.unwrap_or_default(),
+                coin_selection: bitcoin.coin_selection,
+                fee_bumping: bitcoin.fee_bumping,
             }
         }
     }
@@ -178,9 +350,20 @@ impl From<Settings> for File {
             maker,
             network,
             data,
-            logging: Logging { level },
+            logging: Logging { level, format },
             bitcoin,
             ethereum,
+            api,
+            alerting,
+            history,
+            telemetry,
+            error_reporting,
+            accounting,
+            watchdog,
+            rate,
+            rpc,
+            takers,
+            derivation,
         } = settings;
 
         File {
@@ -189,9 +372,21 @@ impl From<Settings> for File {
             data: Some(data),
             logging: Some(file::Logging {
                 level: Some(level.into()),
+                format: Some(format),
             }),
             bitcoin: Some(bitcoin.into()),
             ethereum: Some(ethereum.into()),
+            api: Some(api),
+            alerting: Some(alerting).filter(|alerting| *alerting != Alerting::default()),
+            history: Some(history).filter(|history| *history != History::default()),
+            telemetry,
+            error_reporting,
+            accounting,
+            watchdog: Some(watchdog).filter(|watchdog| *watchdog != Watchdog::default()),
+            rate: Some(rate).filter(|rate| *rate != Rate::default()),
+            rpc: Some(rpc).filter(|rpc| *rpc != Rpc::default()),
+            takers: Some(takers).filter(|takers| *takers != Takers::default()),
+            derivation: Some(derivation).filter(|derivation| *derivation != Derivation::default()),
         }
     }
 }
@@ -207,9 +402,30 @@ impl From<Maker> for file::Maker {
                 max_sell => Some(max_sell),
             },
             spread: Some(maker.spread),
+            sell_spread: maker.sell_spread,
+            buy_spread: maker.buy_spread,
             maximum_possible_fee: Some(file::Fees {
                 bitcoin: Some(maker.maximum_possible_fee.bitcoin),
             }),
+            max_concurrent_swaps: maker.max_concurrent_swaps,
+            max_volume_per_24h: Some(maker.max_volume_per_24h)
+                .filter(|max_volume| *max_volume != MaxVolume::default()),
+            republish_interval_secs: Some(maker.republish_interval_secs),
+            levels: Some(maker.levels).filter(|levels| !levels.is_empty()),
+            inventory_skew: maker.inventory_skew,
+            rebalance: maker.rebalance,
+            circuit_breaker: maker.circuit_breaker,
+            rate_hysteresis: maker.rate_hysteresis,
+            reputation: maker.reputation,
+            expiries: maker.expiries,
+            bitcoin_confirmations: Some(maker.bitcoin_confirmations)
+                .filter(|confirmations| !confirmations.is_empty()),
+            min_balance: Some(maker.min_balance)
+                .filter(|min_balance| *min_balance != MinBalance::default()),
+            min_sell: Some(maker.min_sell).filter(|min_sell| *min_sell != MinSell::default()),
+            order_rounding: Some(maker.order_rounding)
+                .filter(|order_rounding| *order_rounding != OrderRounding::default()),
+            role: Some(maker.role).filter(|role| *role != SwapRole::default()),
         }
     }
 }
@@ -223,6 +439,17 @@ impl Settings {
             logging,
             bitcoin,
             ethereum,
+            api,
+            alerting,
+            history,
+            telemetry,
+            error_reporting,
+            accounting,
+            watchdog,
+            rate,
+            rpc,
+            takers,
+            derivation,
         } = config_file;
 
         Ok(Self {
@@ -246,6 +473,8 @@ impl Settings {
                     }) => spread,
                     _ => Spread::new(500).expect("500 is a valid spread value"),
                 },
+                sell_spread: maker.as_ref().and_then(|maker| maker.sell_spread),
+                buy_spread: maker.as_ref().and_then(|maker| maker.buy_spread),
                 maximum_possible_fee: {
                     if let Some(file::Maker {
                         maximum_possible_fee:
@@ -260,16 +489,188 @@ impl Settings {
                         Fees::default()
                     }
                 },
+                max_concurrent_swaps: match maker {
+                    Some(file::Maker {
+                        max_concurrent_swaps: Some(0),
+                        ..
+                    }) => anyhow::bail!("max_concurrent_swaps must be greater than 0"),
+                    Some(file::Maker {
+                        max_concurrent_swaps,
+                        ..
+                    }) => max_concurrent_swaps,
+                    None => None,
+                },
+                max_volume_per_24h: match maker {
+                    Some(file::Maker {
+                        max_volume_per_24h: Some(ref max_volume),
+                        ..
+                    }) => max_volume.clone(),
+                    _ => MaxVolume::default(),
+                },
+                republish_interval_secs: match maker {
+                    Some(file::Maker {
+                        republish_interval_secs: Some(0),
+                        ..
+                    }) => anyhow::bail!("republish_interval_secs must be greater than 0"),
+                    Some(file::Maker {
+                        republish_interval_secs: Some(republish_interval_secs),
+                        ..
+                    }) => republish_interval_secs,
+                    _ => 300,
+                },
+                levels: match maker {
+                    Some(file::Maker {
+                        levels: Some(ref levels),
+                        ..
+                    }) => {
+                        validate_levels(levels)?;
+                        levels.clone()
+                    }
+                    _ => Vec::new(),
+                },
+                inventory_skew: match maker {
+                    Some(file::Maker {
+                        inventory_skew: Some(inventory_skew),
+                        ..
+                    }) if !(inventory_skew.target_bitcoin_ratio > 0.0
+                        && inventory_skew.target_bitcoin_ratio < 1.0) =>
+                    {
+                        anyhow::bail!("target_bitcoin_ratio must be between 0 and 1")
+                    }
+                    Some(file::Maker { inventory_skew, .. }) => inventory_skew,
+                    None => None,
+                },
+                rebalance: match maker {
+                    Some(file::Maker {
+                        rebalance: Some(rebalance),
+                        ..
+                    }) if !(rebalance.target_bitcoin_ratio > 0.0
+                        && rebalance.target_bitcoin_ratio < 1.0) =>
+                    {
+                        anyhow::bail!("rebalance target_bitcoin_ratio must be between 0 and 1")
+                    }
+                    Some(file::Maker {
+                        rebalance: Some(rebalance),
+                        ..
+                    }) if rebalance.threshold_percent == 0 || rebalance.threshold_percent > 100 => {
+                        anyhow::bail!("rebalance threshold_percent must be between 1 and 100")
+                    }
+                    Some(file::Maker { rebalance, .. }) => rebalance,
+                    None => None,
+                },
+                circuit_breaker: match maker {
+                    Some(file::Maker {
+                        circuit_breaker: Some(circuit_breaker),
+                        ..
+                    }) if circuit_breaker.max_move_permyriad == 0
+                        || circuit_breaker.window_secs == 0 =>
+                    {
+                        anyhow::bail!("circuit breaker move and window must be greater than 0")
+                    }
+                    Some(file::Maker {
+                        circuit_breaker, ..
+                    }) => circuit_breaker,
+                    None => None,
+                },
+                rate_hysteresis: match maker {
+                    Some(file::Maker {
+                        rate_hysteresis, ..
+                    }) => rate_hysteresis,
+                    None => None,
+                },
+                reputation: match maker {
+                    Some(file::Maker { reputation, .. }) => reputation,
+                    None => None,
+                },
+                expiries: match maker {
+                    Some(file::Maker {
+                        expiries: Some(expiries),
+                        ..
+                    }) if expiries.alpha_offset_mins <= expiries.beta_offset_mins => {
+                        anyhow::bail!("alpha_offset_mins must be greater than beta_offset_mins")
+                    }
+                    Some(file::Maker { expiries, .. }) => expiries,
+                    None => None,
+                },
+                bitcoin_confirmations: match maker {
+                    Some(file::Maker {
+                        bitcoin_confirmations: Some(ref confirmations),
+                        ..
+                    }) => {
+                        validate_bitcoin_confirmations(confirmations)?;
+                        confirmations.clone()
+                    }
+                    _ => Vec::new(),
+                },
+                min_balance: match maker {
+                    Some(file::Maker {
+                        min_balance: Some(ref min_balance),
+                        ..
+                    }) => min_balance.clone(),
+                    _ => MinBalance::default(),
+                },
+                min_sell: match maker {
+                    Some(file::Maker {
+                        min_sell: Some(ref min_sell),
+                        max_sell: Some(ref max_sell),
+                        ..
+                    }) if exceeds(&min_sell.bitcoin, &max_sell.bitcoin)
+                        || exceeds(&min_sell.dai, &max_sell.dai) =>
+                    {
+                        anyhow::bail!("min_sell must not exceed max_sell")
+                    }
+                    Some(file::Maker {
+                        min_sell: Some(ref min_sell),
+                        ..
+                    }) => min_sell.clone(),
+                    _ => MinSell::default(),
+                },
+                order_rounding: match maker {
+                    Some(file::Maker {
+                        order_rounding: Some(order_rounding),
+                        ..
+                    }) if order_rounding.bitcoin_decimals > Some(8)
+                        || order_rounding.dai_decimals > Some(18) =>
+                    {
+                        anyhow::bail!(
+                            "order_rounding allows at most 8 bitcoin decimals and 18 dai decimals"
+                        )
+                    }
+                    Some(file::Maker {
+                        order_rounding: Some(order_rounding),
+                        ..
+                    }) => order_rounding,
+                    _ => OrderRounding::default(),
+                },
+                role: match maker {
+                    Some(file::Maker {
+                        role: Some(role), ..
+                    }) => role,
+                    _ => SwapRole::default(),
+                },
             },
-            network: network.unwrap_or_else(|| {
-                let default_socket = "/ip4/0.0.0.0/tcp/9939"
-                    .parse()
-                    .expect("cnd listen address could not be parsed");
+            network: match network {
+                Some(Network { ref peers, .. })
+                    if peers
+                        .iter()
+                        .any(|peer| crate::network::peer_id_of(peer).is_none()) =>
+                {
+                    anyhow::bail!("peer addresses must end with /p2p/<peer id>")
+                }
+                Some(network) => network,
+                None => {
+                    let default_socket = "/ip4/0.0.0.0/tcp/9939"
+                        .parse()
+                        .expect("cnd listen address could not be parsed");
 
-                Network {
-                    listen: vec![default_socket],
+                    Network {
+                        listen: vec![default_socket],
+                        external_addresses: vec![],
+                        peers: vec![],
+                        max_isolation_secs: None,
+                    }
                 }
-            }),
+            },
             data: {
                 let default_data_dir =
                     crate::fs::data_dir().context("unable to determine default data path")?;
@@ -281,16 +682,150 @@ impl Settings {
             logging: {
                 match logging {
                     None => Logging::default(),
-                    Some(inner) => match inner {
-                        file::Logging { level: None } => Logging::default(),
-                        file::Logging { level: Some(level) } => Logging {
-                            level: level.into(),
-                        },
-                    },
+                    Some(file::Logging { level, format }) => {
+                        let default = Logging::default();
+                        Logging {
+                            level: level.map_or(default.level, LevelFilter::from),
+                            format: format.unwrap_or(default.format),
+                        }
+                    }
+                }
+            },
+            bitcoin: match bitcoin {
+                Some(file::Bitcoin {
+                    bitcoind: Some(bitcoind),
+                    ..
+                }) if bitcoind.rpc_user.is_some() != bitcoind.rpc_password.is_some() => {
+                    anyhow::bail!("rpc_user and rpc_password must be set together")
+                }
+                Some(file::Bitcoin {
+                    bitcoind:
+                        Some(Bitcoind {
+                            rpc_user: Some(_),
+                            cookie_file: Some(_),
+                            ..
+                        }),
+                    ..
+                }) => {
+                    anyhow::bail!("cookie_file cannot be set along with rpc_user and rpc_password")
                 }
+                Some(file::Bitcoin {
+                    wallet: Some(BitcoinWallet::External { ref name, .. }),
+                    ..
+                }) if name.is_empty() => {
+                    anyhow::bail!("the name of the external bitcoin wallet must not be empty")
+                }
+                Some(file::Bitcoin {
+                    fee_bumping: Some(fee_bumping),
+                    ..
+                }) if !(fee_bumping.expiry_fraction > 0.0 && fee_bumping.expiry_fraction < 1.0) => {
+                    anyhow::bail!("fee bumping expiry_fraction must be between 0 and 1")
+                }
+                bitcoin => derive_url_bitcoin(bitcoin),
             },
-            bitcoin: derive_url_bitcoin(bitcoin),
             ethereum: ethereum.try_into()?,
+            api: api.unwrap_or_else(|| Api {
+                listen: "127.0.0.1:9940"
+                    .parse()
+                    .expect("api listen address could not be parsed"),
+            }),
+            alerting: alerting.unwrap_or_default(),
+            history: match history {
+                Some(History {
+                    decimal_separator: Some(separator),
+                    ..
+                }) if separator.is_ascii_digit() || separator == '-' => {
+                    anyhow::bail!("decimal_separator cannot be a digit or a minus sign")
+                }
+                history => history.unwrap_or_default(),
+            },
+            telemetry,
+            error_reporting,
+            accounting: match accounting {
+                Some(Accounting {
+                    balance_snapshot_interval_secs: 0,
+                }) => anyhow::bail!("balance_snapshot_interval_secs must be greater than 0"),
+                accounting => accounting,
+            },
+            watchdog: match watchdog {
+                Some(Watchdog {
+                    stall_timeout_secs: 0,
+                    ..
+                }) => anyhow::bail!("stall_timeout_secs must be greater than 0"),
+                Some(Watchdog {
+                    swap_stall_timeout_secs: Some(0),
+                    ..
+                }) => anyhow::bail!("swap_stall_timeout_secs must be greater than 0"),
+                Some(Watchdog {
+                    active_peer_ttl_secs: Some(0),
+                    ..
+                }) => anyhow::bail!("active_peer_ttl_secs must be greater than 0"),
+                Some(Watchdog {
+                    swap_setup_timeout_secs: Some(0),
+                    ..
+                }) => anyhow::bail!("swap_setup_timeout_secs must be greater than 0"),
+                watchdog => watchdog.unwrap_or_default(),
+            },
+            rate: match rate {
+                Some(Rate { exchanges, .. }) if exchanges.is_empty() => {
+                    anyhow::bail!("at least one exchange is required to fetch the rate")
+                }
+                Some(Rate {
+                    request_timeout_secs: 0,
+                    ..
+                }) => anyhow::bail!("request_timeout_secs must be greater than 0"),
+                Some(Rate {
+                    refresh_interval_secs: 0,
+                    ..
+                }) => anyhow::bail!("refresh_interval_secs must be greater than 0"),
+                Some(Rate {
+                    refresh_interval_secs,
+                    max_age_secs,
+                    ..
+                }) if max_age_secs < refresh_interval_secs => {
+                    anyhow::bail!("max_age_secs must not be lower than refresh_interval_secs")
+                }
+                Some(Rate {
+                    max_age_secs,
+                    failover: Some(failover),
+                    ..
+                }) if failover.grace_period_secs > max_age_secs => {
+                    anyhow::bail!("rate failover grace_period_secs must not exceed max_age_secs")
+                }
+                Some(Rate {
+                    failover: Some(failover),
+                    ..
+                }) if failover.spread_widening_permyriad > 10_000 => {
+                    anyhow::bail!("rate failover spread_widening_permyriad must not exceed 10000")
+                }
+                rate => rate.unwrap_or_default(),
+            },
+            rpc: match rpc {
+                Some(Rpc {
+                    request_timeout_secs: 0,
+                    ..
+                }) => anyhow::bail!("rpc request_timeout_secs must be greater than 0"),
+                Some(Rpc {
+                    max_attempts: 0, ..
+                }) => anyhow::bail!("rpc max_attempts must be greater than 0"),
+                rpc => rpc.unwrap_or_default(),
+            },
+            takers: match takers {
+                Some(Takers { banned, allowed })
+                    if banned.iter().any(|peer_id| allowed.contains(peer_id)) =>
+                {
+                    anyhow::bail!("a taker cannot be both banned and allowed")
+                }
+                takers => takers.unwrap_or_default(),
+            },
+            derivation: match derivation {
+                Some(Derivation::Bip44 { account, index })
+                    if account >= FIRST_HARDENED_INDEX || index >= FIRST_HARDENED_INDEX =>
+                {
+                    anyhow::bail!("derivation account and index must be lower than 2^31")
+                }
+                derivation => derivation.unwrap_or_default(),
+            },
         })
     }
 }
@@ -300,6 +835,7 @@ mod tests {
 
     use super::*;
     use crate::config::file;
+    use comit::ethereum::ChainId;
     use spectral::prelude::*;
 
     #[test]
@@ -316,6 +852,7 @@ mod tests {
             .map(|settings| &settings.logging)
             .is_equal_to(Logging {
                 level: LevelFilter::Info,
+                format: file::Format::Text,
             })
     }
 
@@ -333,6 +870,26 @@ mod tests {
             .map(|settings| &settings.network)
             .is_equal_to(Network {
                 listen: vec!["/ip4/0.0.0.0/tcp/9939".parse().unwrap()],
+                external_addresses: vec![],
+                peers: vec![],
+                max_isolation_secs: None,
+            })
+    }
+
+    #[test]
+    fn api_defaults_to_localhost() {
+        let config_file = File {
+            api: None,
+            ..File::default()
+        };
+
+        let settings = Settings::from_config_file_and_defaults(config_file);
+
+        assert_that(&settings)
+            .is_ok()
+            .map(|settings| &settings.api)
+            .is_equal_to(Api {
+                listen: "127.0.0.1:9940".parse().unwrap(),
             })
     }
 
@@ -347,9 +904,10 @@ mod tests {
             .map(|settings| &settings.bitcoin)
             .is_equal_to(Bitcoin {
                 network: ::bitcoin::Network::Regtest,
-                bitcoind: Bitcoind {
-                    node_url: "http://localhost:18443".parse().unwrap(),
-                },
+                bitcoind: Bitcoind::new("http://localhost:18443".parse().unwrap()),
+                wallet: BitcoinWallet::Seed,
+                coin_selection: None,
+                fee_bumping: None,
             })
     }
 
@@ -366,6 +924,9 @@ mod tests {
                 bitcoin: Some(file::Bitcoin {
                     network,
                     bitcoind: None,
+                    wallet: None,
+                    coin_selection: None,
+                    fee_bumping: None,
                 }),
                 ..File::default()
             };
@@ -377,13 +938,539 @@ mod tests {
                 .map(|settings| &settings.bitcoin)
                 .is_equal_to(Bitcoin {
                     network,
-                    bitcoind: Bitcoind {
-                        node_url: url.parse().unwrap(),
-                    },
+                    bitcoind: Bitcoind::new(url.parse().unwrap()),
+                    wallet: BitcoinWallet::Seed,
+                    coin_selection: None,
+                    fee_bumping: None,
                 })
         }
     }
 
+    #[test]
+    fn bitcoind_credentials_must_be_complete_and_unambiguous() {
+        let with_bitcoind = |bitcoind| File {
+            bitcoin: Some(file::Bitcoin {
+                network: ::bitcoin::Network::Regtest,
+                bitcoind: Some(bitcoind),
+                wallet: None,
+                coin_selection: None,
+                fee_bumping: None,
+            }),
+            ..File::default()
+        };
+        let bitcoind = Bitcoind::new("http://localhost:18443".parse().unwrap());
+
+        let user_only = with_bitcoind(Bitcoind {
+            rpc_user: Some("nectar".to_owned()),
+            ..bitcoind.clone()
+        });
+        let user_and_cookie = with_bitcoind(Bitcoind {
+            rpc_user: Some("nectar".to_owned()),
+            rpc_password: Some("secret".to_owned()),
+            cookie_file: Some("/home/nectar/.bitcoin/.cookie".into()),
+            ..bitcoind.clone()
+        });
+        let cookie_only = with_bitcoind(Bitcoind {
+            cookie_file: Some("/home/nectar/.bitcoin/.cookie".into()),
+            ..bitcoind
+        });
+
+        assert_that(&Settings::from_config_file_and_defaults(user_only)).is_err();
+        assert_that(&Settings::from_config_file_and_defaults(user_and_cookie)).is_err();
+        assert_that(&Settings::from_config_file_and_defaults(cookie_only)).is_ok();
+    }
+
+    #[test]
+    fn max_concurrent_swaps_of_zero_is_rejected() {
+        let config_file = File {
+            maker: Some(file::Maker {
+                spread: None,
+                sell_spread: None,
+                buy_spread: None,
+                max_sell: None,
+                maximum_possible_fee: None,
+                max_concurrent_swaps: Some(0),
+                max_volume_per_24h: None,
+                republish_interval_secs: None,
+                levels: None,
+                inventory_skew: None,
+                rebalance: None,
+                circuit_breaker: None,
+                rate_hysteresis: None,
+                reputation: None,
+                expiries: None,
+                bitcoin_confirmations: None,
+                min_balance: None,
+                min_sell: None,
+                order_rounding: None,
+                role: None,
+            }),
+            ..File::default()
+        };
+
+        let settings = Settings::from_config_file_and_defaults(config_file);
+
+        assert_that(&settings).is_err();
+    }
+
+    #[test]
+    fn republish_interval_of_zero_is_rejected() {
+        let config_file = File {
+            maker: Some(file::Maker {
+                spread: None,
+                sell_spread: None,
+                buy_spread: None,
+                max_sell: None,
+                maximum_possible_fee: None,
+                max_concurrent_swaps: None,
+                max_volume_per_24h: None,
+                republish_interval_secs: Some(0),
+                levels: None,
+                inventory_skew: None,
+                rebalance: None,
+                circuit_breaker: None,
+                rate_hysteresis: None,
+                reputation: None,
+                expiries: None,
+                bitcoin_confirmations: None,
+                min_balance: None,
+                min_sell: None,
+                order_rounding: None,
+                role: None,
+            }),
+            ..File::default()
+        };
+
+        let settings = Settings::from_config_file_and_defaults(config_file);
+
+        assert_that(&settings).is_err();
+    }
+
+    #[test]
+    fn levels_not_ordered_by_spread_are_rejected() {
+        let config_file = File {
+            maker: Some(file::Maker {
+                spread: None,
+                sell_spread: None,
+                buy_spread: None,
+                max_sell: None,
+                maximum_possible_fee: None,
+                max_concurrent_swaps: None,
+                max_volume_per_24h: None,
+                republish_interval_secs: None,
+                levels: Some(vec![
+                    OrderLevel {
+                        bitcoin: Some(bitcoin::Amount::from_btc(0.5).unwrap()),
+                        spread: Spread::new(200).unwrap(),
+                    },
+                    OrderLevel {
+                        bitcoin: None,
+                        spread: Spread::new(100).unwrap(),
+                    },
+                ]),
+                inventory_skew: None,
+                rebalance: None,
+                circuit_breaker: None,
+                rate_hysteresis: None,
+                reputation: None,
+                expiries: None,
+                bitcoin_confirmations: None,
+                min_balance: None,
+                min_sell: None,
+                order_rounding: None,
+                role: None,
+            }),
+            ..File::default()
+        };
+
+        let settings = Settings::from_config_file_and_defaults(config_file);
+
+        assert_that(&settings).is_err();
+    }
+
+    #[test]
+    fn inventory_skew_target_outside_of_0_and_1_is_rejected() {
+        let config_file = File {
+            maker: Some(file::Maker {
+                spread: None,
+                sell_spread: None,
+                buy_spread: None,
+                max_sell: None,
+                maximum_possible_fee: None,
+                max_concurrent_swaps: None,
+                max_volume_per_24h: None,
+                republish_interval_secs: None,
+                levels: None,
+                inventory_skew: Some(InventorySkew {
+                    target_bitcoin_ratio: 1.0,
+                    max_skew: Spread::new(100).unwrap(),
+                }),
+                rebalance: None,
+                circuit_breaker: None,
+                rate_hysteresis: None,
+                reputation: None,
+                expiries: None,
+                bitcoin_confirmations: None,
+                min_balance: None,
+                min_sell: None,
+                order_rounding: None,
+                role: None,
+            }),
+            ..File::default()
+        };
+
+        let settings = Settings::from_config_file_and_defaults(config_file);
+
+        assert_that(&settings).is_err();
+    }
+
+    #[test]
+    fn rebalance_threshold_above_100_percent_is_rejected() {
+        let config_file = File {
+            maker: Some(file::Maker {
+                spread: None,
+                sell_spread: None,
+                buy_spread: None,
+                max_sell: None,
+                maximum_possible_fee: None,
+                max_concurrent_swaps: None,
+                max_volume_per_24h: None,
+                republish_interval_secs: None,
+                levels: None,
+                inventory_skew: None,
+                rebalance: Some(Rebalance {
+                    target_bitcoin_ratio: 0.5,
+                    threshold_percent: 101,
+                    adjust_max_sell: false,
+                }),
+                circuit_breaker: None,
+                rate_hysteresis: None,
+                reputation: None,
+                expiries: None,
+                bitcoin_confirmations: None,
+                min_balance: None,
+                min_sell: None,
+                order_rounding: None,
+                role: None,
+            }),
+            ..File::default()
+        };
+
+        let settings = Settings::from_config_file_and_defaults(config_file);
+
+        assert_that(&settings).is_err();
+    }
+
+    #[test]
+    fn min_sell_exceeding_max_sell_is_rejected() {
+        let config_file = File {
+            maker: Some(file::Maker {
+                spread: None,
+                sell_spread: None,
+                buy_spread: None,
+                max_sell: Some(MaxSell {
+                    bitcoin: Some(bitcoin::Amount::from_btc(0.1).unwrap()),
+                    dai: None,
+                }),
+                maximum_possible_fee: None,
+                max_concurrent_swaps: None,
+                max_volume_per_24h: None,
+                republish_interval_secs: None,
+                levels: None,
+                inventory_skew: None,
+                rebalance: None,
+                circuit_breaker: None,
+                rate_hysteresis: None,
+                reputation: None,
+                expiries: None,
+                bitcoin_confirmations: None,
+                min_balance: None,
+                min_sell: Some(MinSell {
+                    bitcoin: Some(bitcoin::Amount::from_btc(0.2).unwrap()),
+                    dai: None,
+                }),
+                order_rounding: None,
+                role: None,
+            }),
+            ..File::default()
+        };
+
+        let settings = Settings::from_config_file_and_defaults(config_file);
+
+        assert_that(&settings).is_err();
+    }
+
+    #[test]
+    fn order_rounding_beyond_the_precision_is_rejected() {
+        let config_file = File {
+            maker: Some(file::Maker {
+                spread: None,
+                sell_spread: None,
+                buy_spread: None,
+                max_sell: None,
+                maximum_possible_fee: None,
+                max_concurrent_swaps: None,
+                max_volume_per_24h: None,
+                republish_interval_secs: None,
+                levels: None,
+                inventory_skew: None,
+                rebalance: None,
+                circuit_breaker: None,
+                rate_hysteresis: None,
+                reputation: None,
+                expiries: None,
+                bitcoin_confirmations: None,
+                min_balance: None,
+                min_sell: None,
+                order_rounding: Some(OrderRounding {
+                    bitcoin_decimals: Some(9),
+                    dai_decimals: None,
+                }),
+            }),
+            ..File::default()
+        };
+
+        let settings = Settings::from_config_file_and_defaults(config_file);
+
+        assert_that(&settings).is_err();
+    }
+
+    #[test]
+    fn bitcoin_confirmations_not_ordered_by_amount_are_rejected() {
+        let config_file = File {
+            maker: Some(file::Maker {
+                spread: None,
+                sell_spread: None,
+                buy_spread: None,
+                max_sell: None,
+                maximum_possible_fee: None,
+                max_concurrent_swaps: None,
+                max_volume_per_24h: None,
+                republish_interval_secs: None,
+                levels: None,
+                inventory_skew: None,
+                rebalance: None,
+                circuit_breaker: None,
+                rate_hysteresis: None,
+                reputation: None,
+                expiries: None,
+                bitcoin_confirmations: Some(vec![
+                    BitcoinConfirmations {
+                        bitcoin: Some(bitcoin::Amount::from_btc(1.0).unwrap()),
+                        blocks: 6,
+                    },
+                    BitcoinConfirmations {
+                        bitcoin: Some(bitcoin::Amount::from_btc(0.1).unwrap()),
+                        blocks: 3,
+                    },
+                ]),
+                min_balance: None,
+                min_sell: None,
+                order_rounding: None,
+                role: None,
+            }),
+            ..File::default()
+        };
+
+        let settings = Settings::from_config_file_and_defaults(config_file);
+
+        assert_that(&settings).is_err();
+    }
+
+    #[test]
+    fn alpha_expiry_not_after_beta_expiry_is_rejected() {
+        let config_file = File {
+            maker: Some(file::Maker {
+                spread: None,
+                sell_spread: None,
+                buy_spread: None,
+                max_sell: None,
+                maximum_possible_fee: None,
+                max_concurrent_swaps: None,
+                max_volume_per_24h: None,
+                republish_interval_secs: None,
+                levels: None,
+                inventory_skew: None,
+                rebalance: None,
+                circuit_breaker: None,
+                rate_hysteresis: None,
+                reputation: None,
+                expiries: Some(Expiries {
+                    alpha_offset_mins: 720,
+                    beta_offset_mins: 720,
+                }),
+                bitcoin_confirmations: None,
+                min_balance: None,
+                min_sell: None,
+                order_rounding: None,
+                role: None,
+            }),
+            ..File::default()
+        };
+
+        let settings = Settings::from_config_file_and_defaults(config_file);
+
+        assert_that(&settings).is_err();
+    }
+
+    #[test]
+    fn balance_snapshot_interval_of_zero_is_rejected() {
+        let config_file = File {
+            accounting: Some(Accounting {
+                balance_snapshot_interval_secs: 0,
+            }),
+            ..File::default()
+        };
+
+        let settings = Settings::from_config_file_and_defaults(config_file);
+
+        assert_that(&settings).is_err();
+    }
+
+    #[test]
+    fn swap_stall_timeout_of_zero_is_rejected() {
+        let config_file = File {
+            watchdog: Some(Watchdog {
+                swap_stall_timeout_secs: Some(0),
+                ..Watchdog::default()
+            }),
+            ..File::default()
+        };
+
+        let settings = Settings::from_config_file_and_defaults(config_file);
+
+        assert_that(&settings).is_err();
+    }
+
+    #[test]
+    fn active_peer_ttl_of_zero_is_rejected() {
+        let config_file = File {
+            watchdog: Some(Watchdog {
+                active_peer_ttl_secs: Some(0),
+                ..Watchdog::default()
+            }),
+            ..File::default()
+        };
+
+        let settings = Settings::from_config_file_and_defaults(config_file);
+
+        assert_that(&settings).is_err();
+    }
+
+    #[test]
+    fn swap_setup_timeout_of_zero_is_rejected() {
+        let config_file = File {
+            watchdog: Some(Watchdog {
+                swap_setup_timeout_secs: Some(0),
+                ..Watchdog::default()
+            }),
+            ..File::default()
+        };
+
+        let settings = Settings::from_config_file_and_defaults(config_file);
+
+        assert_that(&settings).is_err();
+    }
+
+    #[test]
+    fn rate_without_exchange_is_rejected() {
+        let config_file = File {
+            rate: Some(Rate {
+                exchanges: vec![],
+                ..Rate::default()
+            }),
+            ..File::default()
+        };
+
+        let settings = Settings::from_config_file_and_defaults(config_file);
+
+        assert_that(&settings).is_err();
+    }
+
+    #[test]
+    fn rate_max_age_lower_than_refresh_interval_is_rejected() {
+        let config_file = File {
+            rate: Some(Rate {
+                refresh_interval_secs: 30,
+                max_age_secs: 10,
+                ..Rate::default()
+            }),
+            ..File::default()
+        };
+
+        let settings = Settings::from_config_file_and_defaults(config_file);
+
+        assert_that(&settings).is_err();
+    }
+
+    #[test]
+    fn rate_failover_grace_period_exceeding_max_age_is_rejected() {
+        let config_file = File {
+            rate: Some(Rate {
+                max_age_secs: 60,
+                failover: Some(crate::config::RateFailover {
+                    grace_period_secs: 120,
+                    max_consecutive_failures: 3,
+                    spread_widening_permyriad: 50,
+                }),
+                ..Rate::default()
+            }),
+            ..File::default()
+        };
+
+        let settings = Settings::from_config_file_and_defaults(config_file);
+
+        assert_that(&settings).is_err();
+    }
+
+    #[test]
+    fn rpc_needs_at_least_one_attempt() {
+        let config_file = File {
+            rpc: Some(Rpc {
+                max_attempts: 0,
+                ..Rpc::default()
+            }),
+            ..File::default()
+        };
+
+        let settings = Settings::from_config_file_and_defaults(config_file);
+
+        assert_that(&settings).is_err();
+    }
+
+    #[test]
+    fn taker_both_banned_and_allowed_is_rejected() {
+        let peer_id: libp2p::PeerId = "QmUJF1AzhjUfDU1ifzkyuHy26SCnNHbPaVHpX1WYxYYgZg"
+            .parse()
+            .unwrap();
+        let config_file = File {
+            takers: Some(Takers {
+                banned: vec![peer_id.clone()],
+                allowed: vec![peer_id],
+            }),
+            ..File::default()
+        };
+
+        let settings = Settings::from_config_file_and_defaults(config_file);
+
+        assert_that(&settings).is_err();
+    }
+
+    #[test]
+    fn derivation_account_must_not_be_hardened() {
+        let config_file = File {
+            derivation: Some(Derivation::Bip44 {
+                account: 1 << 31,
+                index: 0,
+            }),
+            ..File::default()
+        };
+
+        let settings = Settings::from_config_file_and_defaults(config_file);
+
+        assert_that(&settings).is_err();
+    }
+
     #[test]
     fn ethereum_defaults() {
         let config_file = File { ..File::default() };
@@ -395,7 +1482,50 @@ mod tests {
             .map(|settings| &settings.ethereum)
             .is_equal_to(Ethereum {
                 node_url: "http://localhost:8545".parse().unwrap(),
+                auth: NodeAuth::default(),
                 chain: ethereum::Chain::Mainnet,
+                gas_price: GasPrice::default(),
+                signer: EthereumSigner::Seed,
             })
     }
+
+    #[test]
+    fn ethereum_node_cannot_have_both_a_bearer_token_and_credentials() {
+        let config_file = File {
+            ethereum: Some(file::Ethereum {
+                chain_id: ChainId::MAINNET,
+                node_url: Some("https://mainnet.infura.io/v3/project".parse().unwrap()),
+                auth: NodeAuth {
+                    password: Some("project-secret".to_owned()),
+                    bearer_token: Some("token".to_owned()),
+                    ..NodeAuth::default()
+                },
+                local_dai_contract_address: None,
+                gas_price: None,
+                signer: None,
+            }),
+            ..File::default()
+        };
+
+        let settings = Settings::from_config_file_and_defaults(config_file);
+
+        assert_that(&settings).is_err();
+    }
+
+    #[test]
+    fn ethereum_credentials_are_added_to_the_url_of_the_connectors() {
+        let ethereum = Ethereum {
+            node_url: "https://mainnet.infura.io/v3/project".parse().unwrap(),
+            auth: NodeAuth {
+                password: Some("project-secret".to_owned()),
+                ..NodeAuth::default()
+            },
+            ..Ethereum::default()
+        };
+
+        assert_eq!(
+            ethereum.url_with_credentials().unwrap().as_str(),
+            "https://:project-secret@mainnet.infura.io/v3/project"
+        );
+    }
 }
@@ -1,11 +1,14 @@
+use crate::{bitcoin, ethereum};
 use async_trait::async_trait;
 use comit::{
     btsieve::{bitcoin::BitcoindConnector, ethereum::Web3Connector},
     ethereum::ChainId,
     ledger,
+    order::SwapProtocol,
 };
 use std::fmt::Debug;
 use thiserror::Error;
+use time::Duration;
 
 #[derive(Error, Debug)]
 #[error("Connected network does not match network specified in settings (expected {connected_network:?}, got {specified_network:?})")]
@@ -40,3 +43,156 @@ impl FetchNetworkId<ChainId> for Web3Connector {
         Ok(chain_id)
     }
 }
+
+#[derive(Error, Debug, Copy, Clone, PartialEq)]
+pub enum UnsafeExpiries {
+    #[error("alpha expiry offset ({} minutes) must exceed beta expiry offset ({} minutes) by at least {} minutes", .alpha_offset.whole_minutes(), .beta_offset.whole_minutes(), .minimum_margin.whole_minutes())]
+    InsufficientMargin {
+        alpha_offset: Duration,
+        beta_offset: Duration,
+        minimum_margin: Duration,
+    },
+    #[error("{ledger} expiry offset ({} minutes) is shorter than the expected confirmation time ({} minutes)", .offset.whole_minutes(), .confirmation_time.whole_minutes())]
+    ShorterThanConfirmationTime {
+        ledger: &'static str,
+        offset: Duration,
+        confirmation_time: Duration,
+    },
+}
+
+/// Refuse expiries that do not leave enough time to safely execute a swap on
+/// the configured networks.
+pub fn validate_expiries(
+    swap_protocol: SwapProtocol,
+    bitcoin_network: bitcoin::Network,
+    ethereum_chain: ethereum::Chain,
+) -> Result<(), UnsafeExpiries> {
+    let bitcoin = ("Bitcoin", bitcoin_confirmation_time(bitcoin_network));
+    let ethereum = ("Ethereum", ethereum_confirmation_time(ethereum_chain));
+
+    match swap_protocol {
+        SwapProtocol::HbitHerc20 {
+            hbit_expiry_offset,
+            herc20_expiry_offset,
+        } => validate_expiry_offsets(
+            (bitcoin, Duration::from(hbit_expiry_offset)),
+            (ethereum, Duration::from(herc20_expiry_offset)),
+        ),
+        SwapProtocol::Herc20Hbit {
+            herc20_expiry_offset,
+            hbit_expiry_offset,
+        } => validate_expiry_offsets(
+            (ethereum, Duration::from(herc20_expiry_offset)),
+            (bitcoin, Duration::from(hbit_expiry_offset)),
+        ),
+    }
+}
+
+/// Both offsets must exceed the confirmation time of their ledger and the
+/// alpha offset must exceed the beta offset by the time needed to get a
+/// transaction confirmed on both ledgers: once the secret is revealed on the
+/// beta ledger, the alpha ledger must still accept the redeem transaction.
+fn validate_expiry_offsets(
+    ((alpha_ledger, alpha_confirmation_time), alpha_offset): ((&'static str, Duration), Duration),
+    ((beta_ledger, beta_confirmation_time), beta_offset): ((&'static str, Duration), Duration),
+) -> Result<(), UnsafeExpiries> {
+    for (ledger, offset, confirmation_time) in &[
+        (alpha_ledger, alpha_offset, alpha_confirmation_time),
+        (beta_ledger, beta_offset, beta_confirmation_time),
+    ] {
+        if offset <= confirmation_time {
+            return Err(UnsafeExpiries::ShorterThanConfirmationTime {
+                ledger: *ledger,
+                offset: *offset,
+                confirmation_time: *confirmation_time,
+            });
+        }
+    }
+
+    let minimum_margin = alpha_confirmation_time + beta_confirmation_time;
+    if alpha_offset - beta_offset < minimum_margin {
+        return Err(UnsafeExpiries::InsufficientMargin {
+            alpha_offset,
+            beta_offset,
+            minimum_margin,
+        });
+    }
+
+    Ok(())
+}
+
+/// 6 blocks of 10 minutes on public networks.
+fn bitcoin_confirmation_time(network: bitcoin::Network) -> Duration {
+    match network {
+        bitcoin::Network::Bitcoin | bitcoin::Network::Testnet => Duration::minutes(60),
+        bitcoin::Network::Regtest => Duration::zero(),
+    }
+}
+
+/// 12 blocks of 15 seconds on public chains.
+fn ethereum_confirmation_time(chain: ethereum::Chain) -> Duration {
+    match chain {
+        ethereum::Chain::Local { .. } => Duration::zero(),
+        _ => Duration::minutes(3),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BITCOIN: (&str, Duration) = ("Bitcoin", Duration::minutes(60));
+    const ETHEREUM: (&str, Duration) = ("Ethereum", Duration::minutes(3));
+
+    #[test]
+    fn given_alpha_expiry_well_after_beta_expiry_then_expiries_are_safe() {
+        let res = validate_expiry_offsets(
+            (BITCOIN, Duration::hours(24)),
+            (ETHEREUM, Duration::hours(12)),
+        );
+
+        assert_eq!(res, Ok(()));
+    }
+
+    #[test]
+    fn given_alpha_expiry_before_beta_expiry_then_expiries_are_unsafe() {
+        let res = validate_expiry_offsets(
+            (ETHEREUM, Duration::hours(12)),
+            (BITCOIN, Duration::hours(24)),
+        );
+
+        assert!(matches!(
+            res,
+            Err(UnsafeExpiries::InsufficientMargin { .. })
+        ));
+    }
+
+    #[test]
+    fn given_margin_shorter_than_confirmation_times_then_expiries_are_unsafe() {
+        let res = validate_expiry_offsets(
+            (BITCOIN, Duration::minutes(150)),
+            (ETHEREUM, Duration::minutes(100)),
+        );
+
+        assert!(matches!(
+            res,
+            Err(UnsafeExpiries::InsufficientMargin { .. })
+        ));
+    }
+
+    #[test]
+    fn given_offset_shorter_than_confirmation_time_then_expiries_are_unsafe() {
+        let res = validate_expiry_offsets(
+            (BITCOIN, Duration::minutes(30)),
+            (ETHEREUM, Duration::minutes(10)),
+        );
+
+        assert!(matches!(
+            res,
+            Err(UnsafeExpiries::ShorterThanConfirmationTime {
+                ledger: "Bitcoin",
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,163 @@
+//! Report panics and error events to a webhook.
+//!
+//! Error events are posted as a JSON object holding the message, the fields
+//! of the event and the fields of the spans it was emitted in, hence the
+//! `swap_id` and `peer_id` of the swap it relates to. Panics are turned into
+//! error events so they are reported the same way.
+
+use crate::config::ErrorReporting;
+use serde::Serialize;
+use std::{collections::BTreeMap, fmt};
+use tracing::{
+    field::{Field, Visit},
+    span, Event, Level, Subscriber,
+};
+use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
+
+/// A `tracing` layer reporting error events, it does nothing if error
+/// reporting is not configured.
+#[derive(Debug, Clone)]
+pub struct ErrorReportLayer {
+    reporter: Option<Reporter>,
+}
+
+#[derive(Debug, Clone)]
+struct Reporter {
+    client: reqwest::Client,
+    webhook_url: url::Url,
+}
+
+#[derive(Debug, Serialize)]
+struct Report {
+    level: String,
+    target: String,
+    message: String,
+    fields: BTreeMap<String, String>,
+    context: BTreeMap<String, String>,
+}
+
+/// The fields recorded on a span or an event.
+#[derive(Debug, Default)]
+struct Fields(BTreeMap<String, String>);
+
+impl Visit for Fields {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().to_owned(), format!("{:?}", value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_owned(), value.to_owned());
+    }
+}
+
+impl ErrorReportLayer {
+    pub fn new(error_reporting: Option<&ErrorReporting>) -> Self {
+        ErrorReportLayer {
+            reporter: error_reporting.map(|error_reporting| Reporter {
+                client: reqwest::Client::new(),
+                webhook_url: error_reporting.webhook_url.clone(),
+            }),
+        }
+    }
+}
+
+impl<S> Layer<S> for ErrorReportLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        if self.reporter.is_none() {
+            return;
+        }
+
+        if let Some(span) = ctx.span(id) {
+            let mut fields = Fields::default();
+            attrs.record(&mut fields);
+            span.extensions_mut().insert(fields);
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let reporter = match &self.reporter {
+            Some(reporter) if *event.metadata().level() == Level::ERROR => reporter,
+            _ => return,
+        };
+
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let message = fields.0.remove("message").unwrap_or_default();
+
+        let mut context = BTreeMap::new();
+        if let Some(span) = ctx.lookup_current() {
+            for span in std::iter::once(span.clone()).chain(span.parents()) {
+                if let Some(Fields(span_fields)) = span.extensions().get::<Fields>() {
+                    for (name, value) in span_fields {
+                        // The innermost span wins
+                        context.entry(name.clone()).or_insert_with(|| value.clone());
+                    }
+                }
+            }
+        }
+
+        reporter.send(Report {
+            level: event.metadata().level().to_string(),
+            target: event.metadata().target().to_owned(),
+            message,
+            fields: fields.0,
+            context,
+        });
+    }
+}
+
+impl Reporter {
+    fn send(&self, report: Report) {
+        // Events can be emitted outside of the runtime, e.g. while panicking
+        // in a thread of the runtime's blocking pool.
+        let handle = match tokio::runtime::Handle::try_current() {
+            Ok(handle) => handle,
+            Err(_) => {
+                eprintln!("Could not report error, no runtime: {}", report.message);
+                return;
+            }
+        };
+
+        let reporter = self.clone();
+        handle.spawn(async move {
+            let result = reporter
+                .client
+                .post(reporter.webhook_url.clone())
+                .json(&report)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+            // Not logged at error level, that would report the failure to report
+            if let Err(e) = result {
+                tracing::warn!("Could not send error report: {:#}", e);
+            }
+        });
+    }
+}
+
+/// Emit panics as error events so they are reported, the previous panic hook
+/// is still called.
+pub fn install_panic_hook() {
+    let previous = std::panic::take_hook();
+
+    std::panic::set_hook(Box::new(move |info| {
+        let location = info
+            .location()
+            .map(|location| location.to_string())
+            .unwrap_or_default();
+        let payload = info
+            .payload()
+            .downcast_ref::<&str>()
+            .map(|payload| (*payload).to_owned())
+            .or_else(|| info.payload().downcast_ref::<String>().cloned())
+            .unwrap_or_default();
+
+        tracing::error!(%location, "Panicked: {}", payload);
+
+        previous(info);
+    }));
+}
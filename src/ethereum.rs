@@ -1,13 +1,26 @@
 pub mod dai;
+mod eip1559;
+mod gas_price;
 mod geth;
+mod nonce;
+mod signer;
 mod wallet;
 
 pub use comit::ethereum::{Address, ChainId, Hash};
-pub use geth::Client;
+pub use gas_price::{Fees, GasPrice};
+pub use geth::{Client, IncludedIn};
+pub use nonce::Nonces;
+pub use signer::Signer;
 pub use wallet::Wallet;
 
 pub const STANDARD_ETH_TRANSFER_GAS_LIMIT: u64 = 21_000;
 pub const DAI_TRANSFER_GAS_LIMIT: u64 = 100_000;
+/// Gas of our transactions in a Herc20 swap with some margin: deploying and
+/// funding the HTLC when we buy bitcoin, redeeming it when we sell bitcoin.
+pub const HERC20_SWAP_GAS_LIMIT: u64 = 400_000;
+/// Blocks after which a transaction on a public chain is not expected to be
+/// reorganised out of the chain anymore.
+pub const REORG_SAFE_CONFIRMATIONS: u64 = 12;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Chain {
@@ -47,6 +60,15 @@ impl Chain {
         }
     }
 
+    /// Confirmations an observed transaction needs before being relied on, a
+    /// local chain does not reorganise.
+    pub fn required_confirmations(&self) -> u64 {
+        match self {
+            Chain::Local { .. } => 1,
+            _ => REORG_SAFE_CONFIRMATIONS,
+        }
+    }
+
     pub fn dai_contract_address(&self) -> Address {
         dai::token_contract_address(*self)
     }
@@ -9,7 +9,7 @@ use comit::{
     ethereum::Address,
 };
 use conquer_once::Lazy;
-use num::{BigUint, CheckedAdd, Integer, ToPrimitive, Zero};
+use num::{BigUint, CheckedAdd, CheckedSub, Integer, ToPrimitive, Zero};
 use std::str::FromStr;
 
 pub const ATTOS_IN_DAI_EXP: u16 = 18;
@@ -144,6 +144,26 @@ impl Amount {
         self.0.checked_add(&rhs.0).map(Amount)
     }
 
+    /// `None` if `rhs` is greater than `self`.
+    pub fn checked_sub(&self, rhs: &Amount) -> Option<Amount> {
+        self.0.checked_sub(&rhs.0).map(Amount)
+    }
+
+    /// Zero if `rhs` is greater than `self`.
+    pub fn saturating_sub(&self, rhs: &Amount) -> Amount {
+        self.checked_sub(rhs).unwrap_or_default()
+    }
+
+    /// Rounded down to `decimals` decimal places of dai, unchanged for 18 or
+    /// more.
+    pub fn round_down(&self, decimals: u8) -> Amount {
+        let unit = BigUint::from(10u64.pow(u32::from(
+            ATTOS_IN_DAI_EXP.saturating_sub(u16::from(decimals)),
+        )));
+
+        Amount(&self.0 - &self.0 % unit)
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         self.0.to_bytes_le()
     }
@@ -201,6 +221,7 @@ impl std::ops::Add for Amount {
     }
 }
 
+/// Panics if `rhs` is greater than `self`, see `Amount::checked_sub`.
 impl std::ops::Sub for Amount {
     type Output = Amount;
 
@@ -266,6 +287,13 @@ mod tests {
         assert_eq!(res, btc);
     }
 
+    #[test]
+    fn checked_sub_fails_on_underflow() {
+        assert_eq!(dai(2.0).checked_sub(&dai(0.5)), Some(dai(1.5)));
+        assert_eq!(dai(0.5).checked_sub(&dai(2.0)), None);
+        assert_eq!(dai(0.5).saturating_sub(&dai(2.0)), Amount::default());
+    }
+
     #[test]
     fn worth_in_result_truncated_1() {
         let dai = Amount::from_dai_trunc(112.648125).unwrap();
@@ -0,0 +1,158 @@
+//! EIP-1559 (type 2) transactions, paying a base fee burnt by the chain and a
+//! priority fee to the miner instead of a single gas price.
+
+use num256::Uint256;
+use sha3::{Digest, Keccak256};
+
+const TRANSACTION_TYPE: u8 = 2;
+
+/// Signs `transaction` as a type 2 transaction, its gas price is ignored.
+/// Returns the signed transaction, hex encoded.
+pub fn sign(
+    transaction: &clarity::Transaction,
+    max_fee_per_gas: &Uint256,
+    max_priority_fee_per_gas: &Uint256,
+    chain_id: u32,
+    private_key: &clarity::PrivateKey,
+) -> String {
+    let mut fields = vec![
+        encode_uint(&Uint256::from(chain_id)),
+        encode_uint(&transaction.nonce),
+        encode_uint(max_priority_fee_per_gas),
+        encode_uint(max_fee_per_gas),
+        encode_uint(&transaction.gas_limit),
+        // The zero address stands for a contract deployment
+        if transaction.to == clarity::Address::default() {
+            encode_bytes(&[])
+        } else {
+            encode_bytes(transaction.to.as_bytes())
+        },
+        encode_uint(&transaction.value),
+        encode_bytes(&transaction.data),
+        // Empty access list
+        encode_list(&[]),
+    ];
+
+    let hash = Keccak256::digest(&typed(encode_list(&fields)));
+    let signature = private_key.sign_hash(hash.as_slice());
+    // The recovery id is offset by 27 for legacy transactions
+    let y_parity = if signature.v >= Uint256::from(27u32) {
+        signature.v - Uint256::from(27u32)
+    } else {
+        signature.v
+    };
+
+    fields.push(encode_uint(&y_parity));
+    fields.push(encode_uint(&signature.r));
+    fields.push(encode_uint(&signature.s));
+
+    format!("0x{}", hex::encode(typed(encode_list(&fields))))
+}
+
+fn typed(payload: Vec<u8>) -> Vec<u8> {
+    let mut bytes = vec![TRANSACTION_TYPE];
+    bytes.extend(payload);
+    bytes
+}
+
+/// RLP encoding of an integer: big endian without leading zeros.
+fn encode_uint(value: &Uint256) -> Vec<u8> {
+    let mut hex = value.to_str_radix(16);
+    if hex == "0" {
+        return encode_bytes(&[]);
+    }
+    if hex.len() % 2 == 1 {
+        hex.insert(0, '0');
+    }
+
+    encode_bytes(&hex::decode(hex).expect("valid hex"))
+}
+
+fn encode_bytes(bytes: &[u8]) -> Vec<u8> {
+    match bytes {
+        [byte] if *byte < 0x80 => vec![*byte],
+        _ => {
+            let mut encoded = encode_length(bytes.len(), 0x80);
+            encoded.extend_from_slice(bytes);
+            encoded
+        }
+    }
+}
+
+fn encode_list(items: &[Vec<u8>]) -> Vec<u8> {
+    let payload = items.concat();
+
+    let mut encoded = encode_length(payload.len(), 0xc0);
+    encoded.extend(payload);
+    encoded
+}
+
+fn encode_length(length: usize, offset: u8) -> Vec<u8> {
+    if length < 56 {
+        return vec![offset + length as u8];
+    }
+
+    let length = length.to_be_bytes();
+    let length = match length.iter().position(|byte| *byte != 0) {
+        Some(first) => &length[first..],
+        None => &length[length.len() - 1..],
+    };
+
+    let mut encoded = vec![offset + 55 + length.len() as u8];
+    encoded.extend_from_slice(length);
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rlp_encodes_integers_without_leading_zeros() {
+        assert_eq!(encode_uint(&Uint256::from(0u32)), vec![0x80]);
+        assert_eq!(encode_uint(&Uint256::from(15u32)), vec![0x0f]);
+        assert_eq!(encode_uint(&Uint256::from(1024u32)), vec![0x82, 0x04, 0x00]);
+    }
+
+    #[test]
+    fn rlp_encodes_bytes_and_lists() {
+        assert_eq!(encode_bytes(b"dog"), vec![0x83, b'd', b'o', b'g']);
+        assert_eq!(
+            encode_list(&[encode_bytes(b"cat"), encode_bytes(b"dog")]),
+            vec![0xc8, 0x83, b'c', b'a', b't', 0x83, b'd', b'o', b'g']
+        );
+        assert_eq!(encode_list(&[]), vec![0xc0]);
+    }
+
+    #[test]
+    fn rlp_encodes_long_strings_with_their_length() {
+        let encoded = encode_bytes(&[0xaa; 60]);
+
+        assert_eq!(&encoded[..2], &[0xb8, 60]);
+        assert_eq!(encoded.len(), 62);
+    }
+
+    #[test]
+    fn signed_transaction_is_typed() {
+        let private_key = clarity::PrivateKey::from_slice(&[1u8; 32]).unwrap();
+        let transaction = clarity::Transaction {
+            nonce: 0u32.into(),
+            gas_price: 0u32.into(),
+            gas_limit: 21_000u32.into(),
+            to: clarity::Address::default(),
+            value: 1u32.into(),
+            data: vec![],
+            signature: None,
+        };
+
+        let signed = sign(
+            &transaction,
+            &Uint256::from(2_000_000_000u64),
+            &Uint256::from(1_000_000_000u64),
+            1,
+            &private_key,
+        );
+
+        assert!(signed.starts_with("0x02"));
+    }
+}
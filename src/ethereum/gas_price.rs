@@ -0,0 +1,367 @@
+//! Gas price of the transactions we send, from the node or an external
+//! oracle, capped by the configured maximum. EIP-1559 fees are derived from
+//! the fee history of the node if the chain supports them.
+
+use crate::{
+    config,
+    ethereum::geth::{Client, FeeHistory},
+    float_maths::multiply_pow_ten,
+};
+use anyhow::Context;
+use num::BigUint;
+use num256::Uint256;
+use serde::Deserialize;
+use url::Url;
+
+const WEI_IN_GWEI: u64 = 1_000_000_000;
+/// ETH Gas Station prices are in tenths of gwei.
+const WEI_IN_ETH_GAS_STATION_UNIT_EXP: u16 = 8;
+/// Geth only replaces a pending transaction by one paying at least 10% more
+/// gas, with some margin.
+const REPLACEMENT_BUMP_PERCENT: u64 = 12;
+/// Recent blocks whose priority fees we match.
+const FEE_HISTORY_BLOCKS: u64 = 10;
+const PRIORITY_FEE_PERCENTILE: f64 = 50.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fees {
+    Legacy {
+        gas_price: Uint256,
+    },
+    /// EIP-1559 fees, the base fee is burnt and the priority fee goes to the
+    /// miner.
+    Dynamic {
+        max_fee_per_gas: Uint256,
+        max_priority_fee_per_gas: Uint256,
+    },
+}
+
+impl Fees {
+    /// The most we pay per gas.
+    pub fn max_gas_price(&self) -> &Uint256 {
+        match self {
+            Fees::Legacy { gas_price } => gas_price,
+            Fees::Dynamic {
+                max_fee_per_gas, ..
+            } => max_fee_per_gas,
+        }
+    }
+
+    fn priority_fee_per_gas(&self) -> &Uint256 {
+        match self {
+            Fees::Legacy { gas_price } => gas_price,
+            Fees::Dynamic {
+                max_priority_fee_per_gas,
+                ..
+            } => max_priority_fee_per_gas,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GasPrice {
+    oracle_url: Option<Url>,
+    max: Option<Uint256>,
+    http_client: reqwest::Client,
+}
+
+impl GasPrice {
+    pub fn new(config: config::GasPrice) -> Self {
+        GasPrice {
+            oracle_url: config.oracle_url,
+            max: config
+                .max_gwei
+                .map(|gwei| biguint_to_uint256(BigUint::from(gwei) * WEI_IN_GWEI)),
+            http_client: reqwest::Client::new(),
+        }
+    }
+
+    pub async fn gas_price(&self, geth_client: &Client) -> anyhow::Result<Uint256> {
+        let gas_price = match &self.oracle_url {
+            Some(oracle_url) => match self.oracle_gas_price(oracle_url).await {
+                Ok(gas_price) => gas_price,
+                Err(e) => {
+                    tracing::warn!(
+                        "Could not get gas price from oracle, using the node's: {:#}",
+                        e
+                    );
+                    geth_client.gas_price().await?
+                }
+            },
+            None => geth_client.gas_price().await?,
+        };
+
+        Ok(self.cap(gas_price))
+    }
+
+    /// EIP-1559 fees if the chain supports them, unless a gas price oracle is
+    /// configured, the legacy gas price otherwise.
+    pub async fn fees(&self, geth_client: &Client) -> anyhow::Result<Fees> {
+        if self.oracle_url.is_none() {
+            match geth_client
+                .fee_history(FEE_HISTORY_BLOCKS, PRIORITY_FEE_PERCENTILE)
+                .await
+            {
+                Ok(history) => {
+                    if let Some(fees) = self.dynamic_fees(&history) {
+                        return Ok(fees);
+                    }
+                }
+                Err(e) => tracing::debug!("No fee history, sending legacy transactions: {:#}", e),
+            }
+        }
+
+        Ok(Fees::Legacy {
+            gas_price: self.gas_price(geth_client).await?,
+        })
+    }
+
+    /// Twice the base fee of the next block, leaving room for it to rise
+    /// over several full blocks, plus the median priority fee of the recent
+    /// blocks. `None` if the chain has no base fee.
+    fn dynamic_fees(&self, history: &FeeHistory) -> Option<Fees> {
+        let base_fee = history
+            .base_fee_per_gas
+            .last()
+            .filter(|base_fee| **base_fee > Uint256::from(0u32))?;
+
+        let mut priority_fees = history.priority_fees.clone();
+        priority_fees.sort();
+        let priority_fee = priority_fees
+            .get(priority_fees.len() / 2)
+            .cloned()
+            .unwrap_or_else(|| Uint256::from(WEI_IN_GWEI));
+
+        let max_fee_per_gas =
+            self.cap(base_fee.clone() * Uint256::from(2u32) + priority_fee.clone());
+        let max_priority_fee_per_gas = if priority_fee > max_fee_per_gas {
+            max_fee_per_gas.clone()
+        } else {
+            priority_fee
+        };
+
+        Some(Fees::Dynamic {
+            max_fee_per_gas,
+            max_priority_fee_per_gas,
+        })
+    }
+
+    async fn oracle_gas_price(&self, oracle_url: &Url) -> anyhow::Result<Uint256> {
+        let response = self
+            .http_client
+            .get(oracle_url.clone())
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<EthGasStationResponse>()
+            .await
+            .context("failed to deserialize gas price oracle response")?;
+
+        response.fast_in_wei()
+    }
+
+    /// The fees replacing a stuck transaction that paid `previous`: the
+    /// `current` ones, at least enough more than `previous` for the node to
+    /// accept the replacement. `None` if the maximum does not allow it.
+    pub fn replacement(&self, previous: &Fees, current: Fees) -> Option<Fees> {
+        match previous {
+            Fees::Legacy { gas_price } => Some(Fees::Legacy {
+                gas_price: self.replacement_price(gas_price, current.max_gas_price().clone())?,
+            }),
+            Fees::Dynamic {
+                max_fee_per_gas,
+                max_priority_fee_per_gas,
+            } => {
+                let max_fee_per_gas =
+                    self.replacement_price(max_fee_per_gas, current.max_gas_price().clone())?;
+                let max_priority_fee_per_gas = replacement_price(
+                    max_priority_fee_per_gas,
+                    current.priority_fee_per_gas().clone(),
+                    &max_fee_per_gas,
+                )?;
+
+                Some(Fees::Dynamic {
+                    max_fee_per_gas,
+                    max_priority_fee_per_gas,
+                })
+            }
+        }
+    }
+
+    fn replacement_price(&self, previous: &Uint256, current: Uint256) -> Option<Uint256> {
+        match &self.max {
+            Some(max) => replacement_price(previous, current, max),
+            None => Some(max_of(bumped(previous), current)),
+        }
+    }
+
+    fn cap(&self, gas_price: Uint256) -> Uint256 {
+        match &self.max {
+            Some(max) if gas_price > *max => {
+                tracing::debug!("Gas price {} capped at {}", gas_price, max);
+                max.clone()
+            }
+            _ => gas_price,
+        }
+    }
+}
+
+impl Default for GasPrice {
+    fn default() -> Self {
+        GasPrice::new(config::GasPrice::default())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct EthGasStationResponse {
+    fast: f64,
+}
+
+impl EthGasStationResponse {
+    fn fast_in_wei(&self) -> anyhow::Result<Uint256> {
+        let wei = multiply_pow_ten(&self.fast.to_string(), WEI_IN_ETH_GAS_STATION_UNIT_EXP)
+            .context("invalid gas price from oracle")?;
+
+        Ok(biguint_to_uint256(wei))
+    }
+}
+
+/// `current`, at least `previous` bumped enough to replace it, not above
+/// `max`.
+fn replacement_price(previous: &Uint256, current: Uint256, max: &Uint256) -> Option<Uint256> {
+    let bumped = bumped(previous);
+    if bumped > *max {
+        return None;
+    }
+
+    let price = max_of(bumped, current);
+    Some(if price > *max { max.clone() } else { price })
+}
+
+fn bumped(price: &Uint256) -> Uint256 {
+    price.clone() * Uint256::from(100 + REPLACEMENT_BUMP_PERCENT) / Uint256::from(100u64)
+}
+
+fn max_of(lhs: Uint256, rhs: Uint256) -> Uint256 {
+    if lhs > rhs {
+        lhs
+    } else {
+        rhs
+    }
+}
+
+fn biguint_to_uint256(int: BigUint) -> Uint256 {
+    Uint256::from_bytes_le(&int.to_bytes_le())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gwei(gwei: u64) -> Uint256 {
+        biguint_to_uint256(BigUint::from(gwei) * WEI_IN_GWEI)
+    }
+
+    #[test]
+    fn gas_price_above_max_is_capped() {
+        let gas_price = GasPrice::new(config::GasPrice {
+            oracle_url: None,
+            max_gwei: Some(100),
+        });
+
+        assert_eq!(gas_price.cap(gwei(150)), gwei(100));
+        assert_eq!(gas_price.cap(gwei(50)), gwei(50));
+    }
+
+    #[test]
+    fn gas_price_is_not_capped_without_max() {
+        let gas_price = GasPrice::default();
+
+        assert_eq!(gas_price.cap(gwei(10_000)), gwei(10_000));
+    }
+
+    fn legacy(gas_price: u64) -> Fees {
+        Fees::Legacy {
+            gas_price: gwei(gas_price),
+        }
+    }
+
+    fn dynamic(max_fee_per_gas: u64, max_priority_fee_per_gas: u64) -> Fees {
+        Fees::Dynamic {
+            max_fee_per_gas: gwei(max_fee_per_gas),
+            max_priority_fee_per_gas: gwei(max_priority_fee_per_gas),
+        }
+    }
+
+    #[test]
+    fn replacement_gas_price_is_bumped_over_the_previous_one() {
+        let gas_price = GasPrice::default();
+
+        assert_eq!(
+            gas_price.replacement(&legacy(100), legacy(50)),
+            Some(legacy(112))
+        );
+        assert_eq!(
+            gas_price.replacement(&legacy(100), legacy(150)),
+            Some(legacy(150))
+        );
+    }
+
+    #[test]
+    fn no_replacement_gas_price_above_max() {
+        let gas_price = GasPrice::new(config::GasPrice {
+            oracle_url: None,
+            max_gwei: Some(110),
+        });
+
+        assert_eq!(gas_price.replacement(&legacy(100), legacy(150)), None);
+        assert_eq!(
+            gas_price.replacement(&legacy(90), legacy(150)),
+            Some(legacy(110))
+        );
+    }
+
+    #[test]
+    fn replacement_bumps_both_dynamic_fees() {
+        let gas_price = GasPrice::default();
+
+        assert_eq!(
+            gas_price.replacement(&dynamic(100, 50), dynamic(50, 5)),
+            Some(dynamic(112, 56))
+        );
+    }
+
+    #[test]
+    fn dynamic_fees_are_twice_the_base_fee_plus_the_median_priority_fee() {
+        let history = FeeHistory {
+            base_fee_per_gas: vec![gwei(90), gwei(100)],
+            priority_fees: vec![gwei(3), gwei(1), gwei(2)],
+        };
+
+        let fees = GasPrice::default().dynamic_fees(&history);
+
+        assert_eq!(fees, Some(dynamic(202, 2)));
+    }
+
+    #[test]
+    fn no_dynamic_fees_without_base_fee() {
+        let history = FeeHistory {
+            base_fee_per_gas: vec![gwei(0)],
+            priority_fees: vec![],
+        };
+
+        assert_eq!(GasPrice::default().dynamic_fees(&history), None);
+    }
+
+    #[test]
+    fn eth_gas_station_response_is_in_tenths_of_gwei() {
+        let json = r#"{"fast": 1235.0, "fastest": 1500.0, "safeLow": 1000.0, "average": 1100.0}"#;
+
+        let response: EthGasStationResponse = serde_json::from_str(json).unwrap();
+
+        assert_eq!(
+            response.fast_in_wei().unwrap(),
+            Uint256::from(123_500_000_000u64)
+        );
+    }
+}
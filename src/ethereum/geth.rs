@@ -1,4 +1,5 @@
 use crate::{
+    config::{self, NodeAuth},
     ethereum::{ether, Address},
     jsonrpc,
 };
@@ -27,6 +28,18 @@ impl Client {
         }
     }
 
+    pub fn with_auth(self, auth: &NodeAuth) -> anyhow::Result<Self> {
+        Ok(Client {
+            rpc_client: self.rpc_client.with_auth(auth)?,
+        })
+    }
+
+    pub fn with_rpc_config(self, rpc: config::Rpc) -> Self {
+        Client {
+            rpc_client: self.rpc_client.with_rpc_config(rpc),
+        }
+    }
+
     pub async fn chain_id(&self) -> anyhow::Result<ChainId> {
         let chain_id = self
             .rpc_client
@@ -91,12 +104,47 @@ impl Client {
         Ok(receipt)
     }
 
+    /// The block a transaction was included in, `None` if it is not part of
+    /// the canonical chain (anymore).
+    pub async fn get_transaction_block(
+        &self,
+        transaction_hash: Hash,
+    ) -> anyhow::Result<Option<IncludedIn>> {
+        let included_in = self
+            .rpc_client
+            .send(jsonrpc::Request::new(
+                "eth_getTransactionReceipt",
+                vec![jsonrpc::serialize(transaction_hash)?],
+                JSONRPC_VERSION.into(),
+            ))
+            .await
+            .context("failed to get transaction block")?;
+
+        Ok(included_in)
+    }
+
+    pub async fn block_number(&self) -> anyhow::Result<u64> {
+        let number: String = self
+            .rpc_client
+            .send::<Vec<()>, String>(jsonrpc::Request::new(
+                "eth_blockNumber",
+                vec![],
+                JSONRPC_VERSION.into(),
+            ))
+            .await
+            .context("failed to get block number")?;
+
+        let number = u64::from_str_radix(&number[2..], 16)?;
+        Ok(number)
+    }
+
+    /// Number of transactions sent from `account`, pending ones included.
     pub async fn get_transaction_count(&self, account: Address) -> anyhow::Result<u32> {
         let count: String = self
             .rpc_client
             .send(jsonrpc::Request::new(
                 "eth_getTransactionCount",
-                vec![jsonrpc::serialize(account)?, jsonrpc::serialize("latest")?],
+                vec![jsonrpc::serialize(account)?, jsonrpc::serialize("pending")?],
                 JSONRPC_VERSION.into(),
             ))
             .await
@@ -174,6 +222,62 @@ impl Client {
         Ok(amount)
     }
 
+    /// The base fees of the last `block_count` blocks and of the next one, and
+    /// the priority fees paid at `reward_percentile` in the last blocks.
+    pub async fn fee_history(
+        &self,
+        block_count: u64,
+        reward_percentile: f64,
+    ) -> anyhow::Result<FeeHistory> {
+        let response: FeeHistoryResponse = self
+            .rpc_client
+            .send(jsonrpc::Request::new(
+                "eth_feeHistory",
+                vec![
+                    jsonrpc::serialize(format!("0x{:x}", block_count))?,
+                    jsonrpc::serialize("latest")?,
+                    jsonrpc::serialize(vec![reward_percentile])?,
+                ],
+                JSONRPC_VERSION.into(),
+            ))
+            .await
+            .context("failed to get fee history")?;
+
+        Ok(FeeHistory {
+            base_fee_per_gas: response
+                .base_fee_per_gas
+                .iter()
+                .map(|fee| parse_quantity(fee))
+                .collect::<anyhow::Result<_>>()?,
+            priority_fees: response
+                .reward
+                .iter()
+                .filter_map(|rewards| rewards.first())
+                .map(|fee| parse_quantity(fee))
+                .collect::<anyhow::Result<_>>()?,
+        })
+    }
+
+    /// Sign the transaction with an account of the node, or of Clef with
+    /// `account_signTransaction`. Returns the raw signed transaction.
+    pub async fn sign_transaction(
+        &self,
+        method: &str,
+        request: SignTransactionRequest,
+    ) -> anyhow::Result<String> {
+        let response: SignTransactionResponse = self
+            .rpc_client
+            .send(jsonrpc::Request::new(
+                method,
+                vec![jsonrpc::serialize(request)?],
+                JSONRPC_VERSION.into(),
+            ))
+            .await
+            .context("failed to sign transaction")?;
+
+        Ok(response.raw)
+    }
+
     pub async fn gas_limit(&self, request: EstimateGasRequest) -> anyhow::Result<num256::Uint256> {
         let gas_limit: String = self
             .rpc_client
@@ -190,6 +294,12 @@ impl Client {
     }
 }
 
+fn parse_quantity(quantity: &str) -> anyhow::Result<Uint256> {
+    let quantity = quantity.trim_start_matches("0x");
+
+    Ok(num256::Uint256::from_str_radix(quantity, 16)?)
+}
+
 fn balance_of_fn(account: Address) -> anyhow::Result<Vec<u8>> {
     let account = clarity::Address::from_slice(account.as_bytes())
         .map_err(|_| anyhow::anyhow!("Could not construct clarity::Address from slice"))?;
@@ -216,6 +326,59 @@ pub struct EstimateGasRequest {
     pub data: Option<Vec<u8>>,
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub struct FeeHistory {
+    /// Up to the next block, zero before EIP-1559 is activated.
+    pub base_fee_per_gas: Vec<Uint256>,
+    pub priority_fees: Vec<Uint256>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FeeHistoryResponse {
+    #[serde(default)]
+    base_fee_per_gas: Vec<String>,
+    #[serde(default)]
+    reward: Vec<Vec<String>>,
+}
+
+/// Quantities are hex encoded with a `0x` prefix. Legacy transactions have a
+/// gas price, EIP-1559 ones have a type and max fees instead.
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignTransactionRequest {
+    pub from: Address,
+    /// `None` to deploy a contract
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<Address>,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub transaction_type: Option<String>,
+    pub gas: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gas_price: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_fee_per_gas: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_priority_fee_per_gas: Option<String>,
+    pub value: String,
+    pub data: String,
+    pub nonce: String,
+    pub chain_id: String,
+}
+
+/// The fields of a transaction receipt locating the transaction in the chain.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IncludedIn {
+    pub block_hash: Hash,
+    pub block_number: U256,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct SignTransactionResponse {
+    raw: String,
+}
+
 #[cfg(all(test, feature = "test-docker"))]
 mod test {
     use super::*;
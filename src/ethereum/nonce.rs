@@ -0,0 +1,77 @@
+//! Nonces of the transactions of our account, handed out to one transaction at
+//! a time so that concurrent swaps never sign two with the same nonce.
+
+use std::sync::Arc;
+use tokio::sync::{Mutex, MutexGuard};
+
+#[derive(Debug, Clone, Default)]
+pub struct Nonces {
+    /// The nonce following the one of our last broadcast transaction, `None`
+    /// until we broadcast one or after a broadcast failed.
+    next: Arc<Mutex<Option<u32>>>,
+}
+
+impl Nonces {
+    /// Other transactions wait for the returned guard to be dropped, it is
+    /// held until the transaction is broadcast.
+    pub async fn lock(&self) -> NonceGuard<'_> {
+        NonceGuard {
+            next: self.next.lock().await,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct NonceGuard<'a> {
+    next: MutexGuard<'a, Option<u32>>,
+}
+
+impl NonceGuard<'_> {
+    /// The nonce of the next transaction given the number of transactions of
+    /// the account the node knows of, pending ones included. The node may not
+    /// know yet of our last broadcast transaction.
+    pub fn next(&self, transaction_count: u32) -> u32 {
+        self.next
+            .map_or(transaction_count, |next| next.max(transaction_count))
+    }
+
+    pub fn broadcast(&mut self, nonce: u32) {
+        *self.next = Some(nonce + 1);
+    }
+
+    /// Forget our last broadcast transaction and rely on the node for the
+    /// next nonce.
+    pub fn reset(&mut self) {
+        *self.next = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn nonces_follow_our_last_transaction_if_the_node_lags_behind() {
+        let nonces = Nonces::default();
+
+        let mut guard = nonces.lock().await;
+        assert_eq!(guard.next(5), 5);
+        guard.broadcast(5);
+        drop(guard);
+
+        let guard = nonces.lock().await;
+        assert_eq!(guard.next(5), 6);
+        assert_eq!(guard.next(8), 8);
+    }
+
+    #[tokio::test]
+    async fn reset_nonces_come_from_the_node() {
+        let nonces = Nonces::default();
+
+        let mut guard = nonces.lock().await;
+        guard.broadcast(5);
+        guard.reset();
+
+        assert_eq!(guard.next(3), 3);
+    }
+}
@@ -0,0 +1,161 @@
+//! What signs the transactions of the wallet: the key derived from the seed,
+//! or an external signer such as a node holding the keystore of the account
+//! or Clef.
+
+use crate::{
+    config,
+    ethereum::{
+        eip1559,
+        geth::{Client, SignTransactionRequest},
+        Address, ChainId, Fees,
+    },
+};
+use num256::Uint256;
+
+#[derive(Debug, Clone)]
+pub enum Signer {
+    PrivateKey(clarity::PrivateKey),
+    External {
+        client: Client,
+        account: Address,
+        /// `eth_signTransaction` or, for Clef, `account_signTransaction`
+        method: &'static str,
+    },
+}
+
+impl Signer {
+    /// The signer as configured, `private_key_from_seed` is only called if it
+    /// is the seed.
+    pub fn new<F>(
+        signer: &config::EthereumSigner,
+        rpc: config::Rpc,
+        private_key_from_seed: F,
+    ) -> anyhow::Result<Self>
+    where
+        F: FnOnce() -> anyhow::Result<clarity::PrivateKey>,
+    {
+        match signer {
+            config::EthereumSigner::Seed => Ok(Signer::PrivateKey(private_key_from_seed()?)),
+            config::EthereumSigner::External { url, account, clef } => Ok(Signer::External {
+                client: Client::new(url.clone()).with_rpc_config(rpc),
+                account: *account,
+                method: if *clef {
+                    "account_signTransaction"
+                } else {
+                    "eth_signTransaction"
+                },
+            }),
+        }
+    }
+
+    pub fn account(&self) -> Address {
+        match self {
+            Signer::PrivateKey(private_key) => {
+                let pk = private_key.to_public_key().expect("cannot fail");
+
+                let mut bytes = [0u8; 20];
+                bytes.copy_from_slice(pk.as_bytes());
+
+                Address::from(bytes)
+            }
+            Signer::External { account, .. } => *account,
+        }
+    }
+
+    /// The private key, unknown to us if the signer is external.
+    pub fn private_key(&self) -> Option<clarity::PrivateKey> {
+        match self {
+            Signer::PrivateKey(private_key) => Some(*private_key),
+            Signer::External { .. } => None,
+        }
+    }
+
+    /// Returns the signed transaction, hex encoded. It is an EIP-1559
+    /// transaction if `fees` are dynamic, the gas price of `transaction` is
+    /// overwritten otherwise.
+    pub async fn sign(
+        &self,
+        mut transaction: clarity::Transaction,
+        fees: &Fees,
+        chain_id: ChainId,
+    ) -> anyhow::Result<String> {
+        if let Fees::Legacy { gas_price } = fees {
+            transaction.gas_price = gas_price.clone();
+        }
+
+        match (self, fees) {
+            (
+                Signer::PrivateKey(private_key),
+                Fees::Dynamic {
+                    max_fee_per_gas,
+                    max_priority_fee_per_gas,
+                },
+            ) => Ok(eip1559::sign(
+                &transaction,
+                max_fee_per_gas,
+                max_priority_fee_per_gas,
+                u32::from(chain_id),
+                private_key,
+            )),
+            (Signer::PrivateKey(private_key), Fees::Legacy { .. }) => {
+                let signed_transaction =
+                    transaction.sign(private_key, Some(u32::from(chain_id) as u64));
+
+                Ok(format!(
+                    "0x{}",
+                    hex::encode(signed_transaction.to_bytes().map_err(|_| anyhow::anyhow!(
+                        "Failed to serialize signed transaction to bytes"
+                    ))?)
+                ))
+            }
+            (
+                Signer::External {
+                    client,
+                    account,
+                    method,
+                },
+                fees,
+            ) => {
+                // The zero address stands for a contract deployment
+                let to = if transaction.to == clarity::Address::default() {
+                    None
+                } else {
+                    Some(Address::from_slice(transaction.to.as_bytes()))
+                };
+                let (transaction_type, gas_price, max_fee_per_gas, max_priority_fee_per_gas) =
+                    match fees {
+                        Fees::Legacy { gas_price } => (None, Some(quantity(gas_price)), None, None),
+                        Fees::Dynamic {
+                            max_fee_per_gas,
+                            max_priority_fee_per_gas,
+                        } => (
+                            Some("0x2".to_owned()),
+                            None,
+                            Some(quantity(max_fee_per_gas)),
+                            Some(quantity(max_priority_fee_per_gas)),
+                        ),
+                    };
+
+                client
+                    .sign_transaction(method, SignTransactionRequest {
+                        from: *account,
+                        to,
+                        transaction_type,
+                        gas: quantity(&transaction.gas_limit),
+                        gas_price,
+                        max_fee_per_gas,
+                        max_priority_fee_per_gas,
+                        value: quantity(&transaction.value),
+                        data: format!("0x{}", hex::encode(&transaction.data)),
+                        nonce: quantity(&transaction.nonce),
+                        chain_id: format!("0x{:x}", u32::from(chain_id)),
+                    })
+                    .await
+            }
+        }
+    }
+}
+
+fn quantity(value: &Uint256) -> String {
+    format!("0x{}", value.to_str_radix(16))
+}
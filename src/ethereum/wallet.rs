@@ -1,12 +1,14 @@
 use crate::{
+    config::{self, NodeAuth},
     ethereum::{
         self, dai, ether,
         geth::{Client, EstimateGasRequest},
-        Address, ChainId, Hash, DAI_TRANSFER_GAS_LIMIT,
+        Address, ChainId, Fees, GasPrice, Hash, IncludedIn, Nonces, Signer, DAI_TRANSFER_GAS_LIMIT,
+        HERC20_SWAP_GAS_LIMIT,
     },
     Seed,
 };
-use anyhow::anyhow;
+use ::bitcoin::util::bip32::{DerivationPath, ExtendedPrivKey};
 use comit::{
     actions::ethereum::{CallContract, DeployContract},
     asset::Erc20,
@@ -14,33 +16,58 @@ use comit::{
 };
 use num::BigUint;
 use num256::Uint256;
-use std::time::Duration;
+use std::{convert::TryFrom, str::FromStr, time::Duration};
 use url::Url;
-use wagyu_ethereum::{EthereumDerivationPath, EthereumFormat, EthereumNetwork};
-use wagyu_model::{derivation_path::ChildIndex, ExtendedPrivateKey};
 
-pub use wagyu_ethereum::EthereumExtendedPrivateKey;
-
-/// Ethereum Standard - m/44'/60'/0'/0/0
-const DERIVATION_PATH: EthereumDerivationPath<wagyu_ethereum::network::Mainnet> =
-    EthereumDerivationPath::Ethereum(ChildIndex::Normal(0));
+/// A transaction not mined within this time is replaced with a higher gas
+/// price.
+const STUCK_TRANSACTION_TIMEOUT: Duration = Duration::from_secs(60);
+/// We give up on a transaction still not mined after this many replacements.
+const MAX_REPLACEMENTS: usize = 3;
 
 #[derive(Debug, Clone)]
 pub struct Wallet {
-    private_key: clarity::PrivateKey,
+    signer: Signer,
     geth_client: Client,
     chain: ethereum::Chain,
+    gas_price: GasPrice,
+    nonces: Nonces,
 }
 
 impl Wallet {
     pub async fn new(seed: Seed, url: Url, chain: ethereum::Chain) -> anyhow::Result<Self> {
-        let geth_client = Client::new(url);
+        Self::new_with_auth(
+            seed,
+            url,
+            &NodeAuth::default(),
+            &config::EthereumSigner::default(),
+            config::Derivation::default(),
+            config::Rpc::default(),
+            chain,
+        )
+        .await
+    }
 
-        let private_key = Self::private_key_from_seed(&seed)?;
+    pub async fn new_with_auth(
+        seed: Seed,
+        url: Url,
+        auth: &NodeAuth,
+        signer: &config::EthereumSigner,
+        derivation: config::Derivation,
+        rpc: config::Rpc,
+        chain: ethereum::Chain,
+    ) -> anyhow::Result<Self> {
+        let geth_client = Client::new(url).with_auth(auth)?.with_rpc_config(rpc);
+
+        let signer = Signer::new(signer, rpc, || {
+            Self::private_key_from_seed(&seed, derivation)
+        })?;
         let wallet = Self {
             geth_client,
-            private_key,
+            signer,
             chain,
+            gas_price: GasPrice::default(),
+            nonces: Nonces::default(),
         };
 
         wallet.assert_chain(chain.chain_id()).await?;
@@ -61,49 +88,46 @@ impl Wallet {
         let placeholder_dai_contract_address = Address::default();
         let chain = ethereum::Chain::new(chain_id, placeholder_dai_contract_address);
         Self {
-            private_key,
+            signer: Signer::PrivateKey(private_key),
             geth_client,
             chain,
+            gas_price: GasPrice::default(),
+            nonces: Nonces::default(),
         }
     }
 
-    pub fn private_key_from_seed(seed: &Seed) -> anyhow::Result<clarity::PrivateKey> {
-        let private_key = Self::root_extended_private_key_from_seed(seed)?
-            .derive(&DERIVATION_PATH)
-            .map_err(|err| anyhow!("Could not derive private key: {:?}", err))?
-            .to_private_key();
-        let private_key =
-            clarity::PrivateKey::from_slice(&private_key.to_secp256k1_secret_key()[..])
-                .map_err(|_| anyhow::anyhow!("Failed to derive private key from slice"))?;
-        Ok(private_key)
+    /// Use the given gas price source for the transactions we send instead of
+    /// the node's `eth_gasPrice`.
+    pub fn with_gas_price(self, gas_price: config::GasPrice) -> Self {
+        Self {
+            gas_price: GasPrice::new(gas_price),
+            ..self
+        }
     }
 
-    fn root_extended_private_key_from_seed<N>(
+    /// The key of the account along the BIP-44 path of `derivation`, the
+    /// network of the root extended private key does not matter for ethereum.
+    pub fn private_key_from_seed(
         seed: &Seed,
-    ) -> anyhow::Result<EthereumExtendedPrivateKey<N>>
-    where
-        N: EthereumNetwork,
-    {
-        Ok(
-            EthereumExtendedPrivateKey::new_master(
-                seed.bytes().as_ref(),
-                &EthereumFormat::Standard,
-            )
-            .map_err(|err| anyhow!("Could not generate extended private key: {:?}", err))?,
-        )
+        derivation: config::Derivation,
+    ) -> anyhow::Result<clarity::PrivateKey> {
+        let path = DerivationPath::from_str(&derivation.ethereum_path())?;
+        let private_key = ExtendedPrivKey::new_master(::bitcoin::Network::Bitcoin, &seed.bytes())?
+            .derive_priv(&crate::SECP, &path)?
+            .private_key;
+        let private_key = clarity::PrivateKey::from_slice(&private_key.key[..])
+            .map_err(|_| anyhow::anyhow!("Failed to derive private key from slice"))?;
+        Ok(private_key)
     }
 
     pub fn account(&self) -> Address {
-        let pk = self.private_key.to_public_key().expect("cannot fail");
-
-        let mut bytes = [0u8; 20];
-        bytes.copy_from_slice(pk.as_bytes());
-
-        Address::from(bytes)
+        self.signer.account()
     }
 
-    pub fn private_key(&self) -> clarity::PrivateKey {
-        self.private_key
+    /// The private key of the account, `None` if signing is delegated to an
+    /// external signer.
+    pub fn private_key(&self) -> Option<clarity::PrivateKey> {
+        self.signer.private_key()
     }
 
     pub fn chain_id(&self) -> ChainId {
@@ -126,26 +150,18 @@ impl Wallet {
     ) -> anyhow::Result<DeployedContract> {
         self.assert_chain(chain_id).await?;
 
-        let nonce = self.get_transaction_count().await?;
-        let gas_price = self.gas_price().await?;
-
         let transaction = clarity::Transaction {
-            nonce: nonce.into(),
-            gas_price,
+            nonce: 0u32.into(),
+            gas_price: 0u32.into(),
             gas_limit: gas_limit.into(),
             to: clarity::Address::default(),
             value: 0u64.into(),
             data,
             signature: None,
         };
-        let transaction_hex = self.sign(transaction)?;
+        let (hash, receipt) = self.send(transaction).await?;
 
-        let hash = self
-            .geth_client
-            .send_raw_transaction(transaction_hex)
-            .await?;
-
-        let contract_address = match self.wait_until_transaction_receipt(hash).await? {
+        let contract_address = match receipt {
             TransactionReceipt {
                 successful: true,
                 contract_address: Some(contract_address),
@@ -178,16 +194,13 @@ impl Wallet {
     ) -> anyhow::Result<Hash> {
         self.assert_chain(chain_id).await?;
 
-        let nonce = self.get_transaction_count().await?;
-        let gas_price = self.gas_price().await?;
-
         let gas_limit = match gas_limit {
             Some(gas_limit) => gas_limit.into(),
             None => {
                 self.gas_limit(EstimateGasRequest {
                     from: None,
                     to: Some(to),
-                    gas_price: Some(gas_price.clone()),
+                    gas_price: None,
                     value: Some(value.clone().into()),
                     data: data.clone(),
                 })
@@ -199,22 +212,15 @@ impl Wallet {
             .map_err(|_| anyhow::anyhow!("Failed to deserialize slice into clarity::Address"))?;
 
         let transaction = clarity::Transaction {
-            nonce: nonce.into(),
-            gas_price,
+            nonce: 0u32.into(),
+            gas_price: 0u32.into(),
             gas_limit,
             to,
             value: value.into(),
             data: data.unwrap_or_default(),
             signature: None,
         };
-        let transaction_hex = self.sign(transaction)?;
-
-        let hash = self
-            .geth_client
-            .send_raw_transaction(transaction_hex)
-            .await?;
-
-        let _ = self.wait_until_transaction_receipt(hash).await?;
+        let (hash, _) = self.send(transaction).await?;
 
         Ok(hash)
     }
@@ -227,9 +233,6 @@ impl Wallet {
     ) -> anyhow::Result<Hash> {
         self.assert_chain(chain_id).await?;
 
-        let nonce = self.get_transaction_count().await?;
-        let gas_price = self.gas_price().await?;
-
         let to = clarity::Address::from_slice(to.as_bytes())
             .map_err(|_| anyhow::anyhow!("Failed to deserialize slice into clarity::Address"))?;
 
@@ -244,22 +247,15 @@ impl Wallet {
         ]);
 
         let transaction = clarity::Transaction {
-            nonce: nonce.into(),
-            gas_price,
+            nonce: 0u32.into(),
+            gas_price: 0u32.into(),
             gas_limit: DAI_TRANSFER_GAS_LIMIT.into(),
             to: dai_contract_addr,
             value: 0u16.into(),
             data,
             signature: None,
         };
-        let transaction_hex = self.sign(transaction)?;
-
-        let hash = self
-            .geth_client
-            .send_raw_transaction(transaction_hex)
-            .await?;
-
-        let _ = self.wait_until_transaction_receipt(hash).await?;
+        let (hash, _) = self.send(transaction).await?;
 
         Ok(hash)
     }
@@ -276,12 +272,9 @@ impl Wallet {
     ) -> anyhow::Result<Hash> {
         self.assert_chain(chain_id).await?;
 
-        let nonce = self.get_transaction_count().await?;
-        let gas_price = self.gas_price().await?;
-
         let transaction = clarity::Transaction {
-            nonce: nonce.into(),
-            gas_price,
+            nonce: 0u32.into(),
+            gas_price: 0u32.into(),
             gas_limit: gas_limit.into(),
             to: clarity::Address::from_slice(to.as_bytes()).map_err(|_| {
                 anyhow::anyhow!("Failed to deserialize slice into clarity::Address")
@@ -290,14 +283,7 @@ impl Wallet {
             data: data.unwrap_or_default(),
             signature: None,
         };
-        let transaction_hex = self.sign(transaction)?;
-
-        let hash = self
-            .geth_client
-            .send_raw_transaction(transaction_hex)
-            .await?;
-
-        let _ = self.wait_until_transaction_receipt(hash).await?;
+        let (hash, _) = self.send(transaction).await?;
 
         Ok(hash)
     }
@@ -311,29 +297,122 @@ impl Wallet {
             .await
     }
 
-    pub async fn wait_until_transaction_receipt(
+    /// Signs `transaction` with the next nonce of the account and the current
+    /// fees, overwriting its own, and broadcasts it. Then waits for it to be
+    /// mined, replacing it with higher fees whenever it is stuck. Returns the
+    /// hash of the transaction mined, which may be a replacement.
+    async fn send(
         &self,
-        transaction_hash: Hash,
-    ) -> anyhow::Result<TransactionReceipt> {
-        let start_time = std::time::Instant::now();
-        let max_retry_time = Duration::from_millis(60_000);
+        mut transaction: clarity::Transaction,
+    ) -> anyhow::Result<(Hash, TransactionReceipt)> {
+        let mut nonces = self.nonces.lock().await;
+        let nonce = nonces.next(self.get_transaction_count().await?);
+        transaction.nonce = nonce.into();
+        let mut fees = self.fees().await?;
+
+        let hash = match self.broadcast(transaction.clone(), &fees).await {
+            Ok(hash) => hash,
+            Err(e) => {
+                nonces.reset();
+                return Err(e);
+            }
+        };
+        nonces.broadcast(nonce);
+        drop(nonces);
 
+        let mut hashes = vec![hash];
+        let mut replacements = 0;
         loop {
-            if std::time::Instant::now() > start_time + max_retry_time {
+            if let Some(mined) = self
+                .wait_until_transaction_receipt(&hashes, STUCK_TRANSACTION_TIMEOUT)
+                .await?
+            {
+                return Ok(mined);
+            }
+
+            if replacements == MAX_REPLACEMENTS {
                 anyhow::bail!(
-                    "failed to find transaction receipt for transaction {}",
-                    transaction_hash
+                    "failed to find transaction receipt for transaction {} with nonce {}",
+                    hash,
+                    nonce
                 )
             }
+            replacements += 1;
+
+            let current = self.fees().await?;
+            fees = match self.gas_price.replacement(&fees, current) {
+                Some(fees) => fees,
+                None => {
+                    tracing::warn!(
+                        "Transaction with nonce {} is stuck at the maximum gas price",
+                        nonce
+                    );
+                    continue;
+                }
+            };
+            tracing::warn!(
+                "Transaction with nonce {} is stuck, replacing it with gas price {}",
+                nonce,
+                fees.max_gas_price()
+            );
 
-            if let Some(transaction_receipt) =
-                self.get_transaction_receipt(transaction_hash).await?
-            {
-                return Ok(transaction_receipt);
+            match self.broadcast(transaction.clone(), &fees).await {
+                Ok(hash) => hashes.push(hash),
+                // The transaction may have been mined in the meantime
+                Err(e) => tracing::warn!("Could not replace stuck transaction: {:#}", e),
+            }
+        }
+    }
+
+    async fn broadcast(
+        &self,
+        transaction: clarity::Transaction,
+        fees: &Fees,
+    ) -> anyhow::Result<Hash> {
+        let transaction_hex = self.sign(transaction, fees).await?;
+
+        self.geth_client.send_raw_transaction(transaction_hex).await
+    }
+
+    /// Waits up to `timeout` for one of the transactions with the same nonce
+    /// to be mined.
+    async fn wait_until_transaction_receipt(
+        &self,
+        transaction_hashes: &[Hash],
+        timeout: Duration,
+    ) -> anyhow::Result<Option<(Hash, TransactionReceipt)>> {
+        let start_time = std::time::Instant::now();
+
+        while std::time::Instant::now() <= start_time + timeout {
+            for transaction_hash in transaction_hashes {
+                if let Some(transaction_receipt) =
+                    self.get_transaction_receipt(*transaction_hash).await?
+                {
+                    return Ok(Some((*transaction_hash, transaction_receipt)));
+                }
             }
 
             tokio::time::delay_for(Duration::from_millis(1_000)).await;
         }
+
+        Ok(None)
+    }
+
+    pub fn required_confirmations(&self) -> u64 {
+        self.chain.required_confirmations()
+    }
+
+    pub async fn get_transaction_block(
+        &self,
+        transaction_hash: Hash,
+    ) -> anyhow::Result<Option<IncludedIn>> {
+        self.geth_client
+            .get_transaction_block(transaction_hash)
+            .await
+    }
+
+    pub async fn block_number(&self) -> anyhow::Result<u64> {
+        self.geth_client.block_number().await
     }
 
     pub async fn erc20_balance(&self, token_contract: Address) -> anyhow::Result<Erc20> {
@@ -377,28 +456,33 @@ impl Wallet {
         Ok(())
     }
 
-    async fn gas_price(&self) -> anyhow::Result<num256::Uint256> {
-        self.geth_client.gas_price().await
+    pub async fn gas_price(&self) -> anyhow::Result<num256::Uint256> {
+        self.gas_price.gas_price(&self.geth_client).await
+    }
+
+    /// EIP-1559 fees if the chain supports them, the legacy gas price
+    /// otherwise.
+    pub async fn fees(&self) -> anyhow::Result<Fees> {
+        self.gas_price.fees(&self.geth_client).await
+    }
+
+    /// The ether needed for the gas of our transactions in another swap at
+    /// the current fees.
+    pub async fn swap_gas_cost(&self) -> anyhow::Result<ether::Amount> {
+        let fees = self.fees().await?;
+        let cost = fees.max_gas_price().clone() * Uint256::from(HERC20_SWAP_GAS_LIMIT);
+
+        ether::Amount::try_from(BigUint::from_str(&cost.to_string())?)
     }
 
     async fn gas_limit(&self, request: EstimateGasRequest) -> anyhow::Result<num256::Uint256> {
         self.geth_client.gas_limit(request).await
     }
 
-    fn sign(&self, transaction: clarity::Transaction) -> anyhow::Result<String> {
-        let signed_transaction = transaction.sign(
-            &self.private_key,
-            Some(u32::from(self.chain.chain_id()) as u64),
-        );
-        let transaction_hex =
-            format!(
-                "0x{}",
-                hex::encode(signed_transaction.to_bytes().map_err(|_| anyhow::anyhow!(
-                    "Failed to serialize signed transaction to bytes"
-                ))?)
-            );
-
-        Ok(transaction_hex)
+    async fn sign(&self, transaction: clarity::Transaction, fees: &Fees) -> anyhow::Result<String> {
+        self.signer
+            .sign(transaction, fees, self.chain.chain_id())
+            .await
     }
 
     #[cfg(test)]
@@ -431,6 +515,38 @@ impl From<DeployedContract> for comit::herc20::Deployed {
     }
 }
 
+#[cfg(test)]
+mod derivation_tests {
+    use super::*;
+
+    #[test]
+    fn legacy_key_is_the_one_of_the_first_bip44_account() {
+        let seed = Seed::from([1u8; 32]);
+        let key = |derivation| {
+            Wallet::private_key_from_seed(&seed, derivation)
+                .unwrap()
+                .to_string()
+        };
+
+        let legacy = key(config::Derivation::Legacy);
+
+        assert_eq!(
+            legacy,
+            key(config::Derivation::Bip44 {
+                account: 0,
+                index: 0
+            })
+        );
+        assert_ne!(
+            legacy,
+            key(config::Derivation::Bip44 {
+                account: 1,
+                index: 0
+            })
+        );
+    }
+}
+
 #[cfg(all(test, feature = "test-docker"))]
 mod tests {
     use super::*;
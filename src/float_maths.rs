@@ -1,79 +1,94 @@
-use anyhow::Context;
-use bitcoin::hashes::core::cmp::Ordering;
-use num::{BigUint, Zero};
-use std::str::FromStr;
+use num::{pow, BigUint, Zero};
+use std::convert::TryFrom;
+
+/// Largest integer up to which every integer is exactly representable as an
+/// `f64`; above it a float has no fractional digits left to truncate.
+const MAX_EXACT_INTEGER: f64 = 9_007_199_254_740_992.0;
 
 /// Truncate the float's mantissa to length `precision`.
 pub fn truncate(float: f64, precision: u16) -> f64 {
-    let mut string = float.to_string();
-    let index = string.find('.');
-
-    match index {
-        None => float,
-        Some(index) => {
-            let trunc = index + 1 + precision as usize;
-            string.truncate(trunc);
-            f64::from_str(&string).expect("This should still be a number")
-        }
+    let scale = 10f64.powi(i32::from(precision));
+    if !float.is_finite() || !scale.is_finite() {
+        return float;
+    }
+
+    let scaled = float * scale;
+    if scaled.abs() >= MAX_EXACT_INTEGER {
+        return float;
     }
+
+    // Scaling is inexact: `0.29 * 100.0` yields `28.999999999999996`, which
+    // must not be truncated to `28`.
+    let rounded = scaled.round();
+    let integer = if (scaled - rounded).abs() <= scaled.abs() * f64::EPSILON {
+        rounded
+    } else {
+        scaled.trunc()
+    };
+
+    integer / scale
 }
 
 /// Multiply float by 10e`pow`, Returns as a BigUint. No data loss.
 /// Errors if the float is negative.
 /// Errors if the result is a fraction.
 pub fn multiply_pow_ten(float: &str, pow: u16) -> anyhow::Result<BigUint> {
-    {
-        // Verify that the input is actually a number
-        let str = float.replace('.', &"");
-        let _ = BigUint::from_str(&str).context("Expecting a float")?;
-    }
-
-    let mut float = float.replace('_', &"");
-    let decimal_index = float.find('.');
-
-    match decimal_index {
-        None => {
-            let zeroes = "0".repeat(pow as usize);
-            Ok(BigUint::from_str(&format!("{}{}", float, zeroes)).expect("an integer"))
-        }
-        Some(decimal_index) => {
-            let mantissa = float.split_off(decimal_index + 1);
-            // Removes the decimal point
-            float.truncate(float.len() - 1);
-            let integer = float;
-
-            let pow = pow as usize;
-            match mantissa.len().cmp(&pow) {
-                Ordering::Less => {
-                    let remain = pow as usize - mantissa.len();
-                    let zeroes = "0".repeat(remain);
-                    Ok(
-                        BigUint::from_str(&format!("{}{}{}", integer, mantissa, zeroes))
-                            .expect("an integer"),
-                    )
-                }
-                Ordering::Equal => {
-                    Ok(BigUint::from_str(&format!("{}{}", integer, mantissa)).expect("an integer"))
+    let mut value = BigUint::zero();
+    let mut has_digits = false;
+    let mut decimals: Option<u16> = None;
+    // Trailing zeroes of the mantissa do not make the result a fraction
+    let mut trailing_zeroes = 0u16;
+
+    for c in float.chars() {
+        match (c, c.to_digit(10)) {
+            ('_', _) => continue,
+            ('.', _) if decimals.is_none() => decimals = Some(0),
+            (_, Some(digit)) => {
+                has_digits = true;
+                value = value * 10u8 + digit;
+
+                if let Some(decimals) = decimals.as_mut() {
+                    *decimals = decimals
+                        .checked_add(1)
+                        .ok_or_else(|| anyhow::anyhow!("Result is not an integer"))?;
+                    trailing_zeroes = if digit == 0 { trailing_zeroes + 1 } else { 0 };
                 }
-                Ordering::Greater => anyhow::bail!("Result is not an integer"),
             }
+            _ => anyhow::bail!("Expecting a float"),
         }
     }
+
+    if !has_digits {
+        anyhow::bail!("Expecting a float")
+    }
+
+    let decimals = decimals.unwrap_or(0);
+    if decimals - trailing_zeroes > pow {
+        anyhow::bail!("Result is not an integer")
+    }
+
+    Ok(if decimals > pow {
+        value / pow_ten(usize::from(decimals - pow))
+    } else {
+        value * pow_ten(usize::from(pow - decimals))
+    })
 }
 
 /// Divide BigUint by 10e`inv_pow`, Returns as a BigUint.
 /// Result is truncated
 pub fn divide_pow_ten_trunc(uint: BigUint, inv_pow: usize) -> BigUint {
-    let mut uint_str = uint.to_string();
-
-    match uint_str.len().cmp(&inv_pow) {
-        Ordering::Less => BigUint::zero(),
-        Ordering::Equal => BigUint::zero(),
-        Ordering::Greater => {
-            uint_str.truncate(uint_str.len() - inv_pow);
-            BigUint::from_str(&uint_str).expect("still an integer")
-        }
+    // 10^inv_pow > 2^inv_pow, hence the quotient is zero once `inv_pow` exceeds
+    // the number of bits of `uint`
+    match u64::try_from(inv_pow) {
+        Ok(inv_pow) if inv_pow < uint.bits() => (),
+        _ => return BigUint::zero(),
     }
+
+    uint / pow_ten(inv_pow)
+}
+
+fn pow_ten(exp: usize) -> BigUint {
+    pow(BigUint::from(10u8), exp)
 }
 
 pub fn string_int_to_float(int: String, precision: usize) -> String {
@@ -107,6 +122,7 @@ pub fn string_int_to_float(int: String, precision: usize) -> String {
 mod tests {
     use super::*;
     use proptest::prelude::*;
+    use std::str::FromStr;
 
     #[test]
     fn it_truncates() {
@@ -115,6 +131,22 @@ mod tests {
         assert_eq!(&truncate(float, 5).to_string(), "1.12345");
     }
 
+    #[test]
+    fn it_truncates_floats_without_exact_representation() {
+        assert_eq!(&truncate(0.29, 2).to_string(), "0.29");
+        assert_eq!(&truncate(-9_123.456_789, 3).to_string(), "-9123.456");
+    }
+
+    proptest! {
+        #[test]
+        fn truncated_float_has_at_most_precision_decimals(f in -1e6..1e6f64, p in 0..=9u16) {
+            let truncated = truncate(f, p).to_string();
+            let decimals = truncated.find('.').map_or(0, |index| truncated.len() - index - 1);
+
+            prop_assert!(decimals <= usize::from(p));
+        }
+    }
+
     proptest! {
         #[test]
         fn truncate_doesnt_panic(f in any::<f64>(), p in any::<u16>()) {
@@ -163,6 +195,22 @@ mod tests {
         assert!(multiply_pow_ten(float, pow).is_err(),)
     }
 
+    #[test]
+    fn given_mantissa_with_trailing_zeroes_then_it_multiplies() {
+        let float = "1.500";
+        let pow = 1;
+
+        assert_eq!(multiply_pow_ten(float, pow).unwrap(), BigUint::from(15u64))
+    }
+
+    #[test]
+    fn given_several_decimal_points_then_it_errors() {
+        let float = "1.2.3";
+        let pow = 6;
+
+        assert!(multiply_pow_ten(float, pow).is_err(),)
+    }
+
     #[test]
     fn given_negative_float_then_it_errors() {
         let float = "-123_456_789.0";
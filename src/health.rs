@@ -0,0 +1,159 @@
+//! Health of what nectar depends on: the bitcoind and geth nodes, the rate
+//! feed and the database.
+//!
+//! The nodes and the database are probed in the background so that `/health`
+//! answers from the last results instead of waiting on them. The rate feed is
+//! fresh if the trade loop received a rate recently.
+
+use crate::{bitcoin, ethereum, swap::Database};
+use serde::Serialize;
+use std::{
+    future::Future,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+const PROBE_INTERVAL: Duration = Duration::from_secs(15);
+/// A node not answering within this time is considered unreachable.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Debug)]
+pub struct Health {
+    probes: Arc<Mutex<Probes>>,
+    last_rate: Arc<Mutex<Option<Instant>>>,
+    rate_max_age: Duration,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Probes {
+    bitcoind_reachable: bool,
+    geth_reachable: bool,
+    database_writable: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Report {
+    pub bitcoind_reachable: bool,
+    pub geth_reachable: bool,
+    pub rate_fresh: bool,
+    pub database_writable: bool,
+}
+
+impl Report {
+    pub fn healthy(&self) -> bool {
+        self.bitcoind_reachable && self.geth_reachable && self.rate_fresh && self.database_writable
+    }
+}
+
+impl Health {
+    /// Nothing is healthy until probed, the rate until one is received.
+    pub fn new(rate_max_age: Duration) -> Self {
+        Health {
+            probes: Arc::new(Mutex::new(Probes::default())),
+            last_rate: Arc::new(Mutex::new(None)),
+            rate_max_age,
+        }
+    }
+
+    pub fn rate_received(&self) {
+        *self
+            .last_rate
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(Instant::now());
+    }
+
+    pub fn report(&self) -> Report {
+        let probes = *self.probes.lock().unwrap_or_else(PoisonError::into_inner);
+        let rate_fresh = self
+            .last_rate
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .map_or(false, |received_at| {
+                received_at.elapsed() <= self.rate_max_age
+            });
+
+        Report {
+            bitcoind_reachable: probes.bitcoind_reachable,
+            geth_reachable: probes.geth_reachable,
+            rate_fresh,
+            database_writable: probes.database_writable,
+        }
+    }
+
+    fn record(&self, probes: Probes) {
+        *self.probes.lock().unwrap_or_else(PoisonError::into_inner) = probes;
+    }
+}
+
+/// Probe the nodes and the database every 15 seconds, forever.
+pub async fn probe(
+    health: Health,
+    bitcoin_wallet: Arc<bitcoin::Wallet>,
+    ethereum_wallet: Arc<ethereum::Wallet>,
+    db: Arc<Database>,
+) {
+    loop {
+        let (bitcoind_reachable, geth_reachable, database_writable) = futures::join!(
+            succeeds("bitcoind", bitcoin_wallet.info()),
+            succeeds("geth", ethereum_wallet.block_number()),
+            succeeds("database", db.probe_write()),
+        );
+        health.record(Probes {
+            bitcoind_reachable,
+            geth_reachable,
+            database_writable,
+        });
+
+        tokio::time::delay_for(PROBE_INTERVAL).await;
+    }
+}
+
+async fn succeeds<T>(name: &str, probe: impl Future<Output = anyhow::Result<T>>) -> bool {
+    match tokio::time::timeout(PROBE_TIMEOUT, probe).await {
+        Ok(Ok(_)) => true,
+        Ok(Err(e)) => {
+            tracing::warn!("Health probe of {} failed: {:#}", name, e);
+            false
+        }
+        Err(_) => {
+            tracing::warn!("Health probe of {} timed out", name);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unprobed_health_is_unhealthy() {
+        let health = Health::new(Duration::from_secs(60));
+
+        assert!(!health.report().healthy());
+    }
+
+    #[test]
+    fn healthy_once_probed_and_rate_received() {
+        let health = Health::new(Duration::from_secs(60));
+        health.record(Probes {
+            bitcoind_reachable: true,
+            geth_reachable: true,
+            database_writable: true,
+        });
+        assert!(!health.report().rate_fresh);
+
+        health.rate_received();
+
+        assert!(health.report().healthy());
+    }
+
+    #[test]
+    fn old_rate_is_not_fresh() {
+        let health = Health::new(Duration::from_secs(0));
+        health.rate_received();
+        std::thread::sleep(Duration::from_millis(1));
+
+        assert!(!health.report().rate_fresh);
+    }
+}
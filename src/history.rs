@@ -1,14 +1,24 @@
-use crate::fs::ensure_directory_exists;
+use crate::{
+    config, float_maths::string_int_to_float, fs::ensure_directory_exists, swap::SwapOutcome, Rate,
+    SwapId,
+};
 use anyhow::Result;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Datelike, Utc};
 use csv::*;
-use num::BigUint;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use num::{BigUint, Zero};
 use serde::{Serialize, Serializer};
 use std::{
+    collections::BTreeMap,
     fs::{File, OpenOptions},
-    path::Path,
+    io::{self, BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
 };
 
+mod sinks;
+
+pub use sinks::TradeSink;
+
 #[derive(Debug, Copy, Clone, Serialize)]
 #[serde(rename_all = "UPPERCASE")]
 pub enum Symbol {
@@ -22,8 +32,16 @@ pub enum Position {
     Sell,
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Serialize)]
+pub enum Outcome {
+    /// We redeemed the asset of the counterpart
+    Redeemed,
+    /// We got back the asset we locked
+    Refunded,
+}
+
 #[derive(Debug, Clone, Serialize)]
-struct Float(String);
+pub struct Float(String);
 
 impl From<f64> for Float {
     fn from(float: f64) -> Self {
@@ -31,6 +49,24 @@ impl From<f64> for Float {
     }
 }
 
+impl From<String> for Float {
+    fn from(float: String) -> Self {
+        Float(float)
+    }
+}
+
+impl Float {
+    pub fn to_f64(&self) -> Option<f64> {
+        self.0.parse().ok()
+    }
+}
+
+impl std::fmt::Display for Float {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Integer(BigUint);
 
@@ -55,6 +91,21 @@ impl Serialize for Integer {
     }
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub struct TransactionId(String);
+
+impl From<::bitcoin::Txid> for TransactionId {
+    fn from(txid: ::bitcoin::Txid) -> Self {
+        TransactionId(txid.to_string())
+    }
+}
+
+impl From<comit::ethereum::Hash> for TransactionId {
+    fn from(hash: comit::ethereum::Hash) -> Self {
+        TransactionId(hash.to_string())
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct PeerId(libp2p::PeerId);
 
@@ -97,7 +148,7 @@ impl Serialize for UtcDateTime {
 }
 
 /// All the information to write in the CVS file per trade
-// If you change this then you need to think about versioning
+// If you change this then you need to increment `CSV_SCHEMA_VERSION`
 #[derive(Debug, Clone, Serialize)]
 pub struct Trade {
     /// When the trade was taken and accepted
@@ -120,7 +171,105 @@ pub struct Trade {
     pub quote_precise_amount: Integer,
     /// the Peer id of the counterpart/taker
     pub peer: PeerId,
-    // TODO: Add fees?
+    /// The mid-market rate when the order was matched, absent for swaps
+    /// started before it was recorded
+    pub mid_market_rate: Option<Float>,
+    /// The rate at which the trade was executed (quote per base)
+    pub executed_rate: Float,
+    /// The profit (or loss if negative) in DAI compared to trading at the
+    /// mid-market rate
+    /// Note: it does not include fees
+    pub realized_pnl_dai: Option<Float>,
+    pub swap_id: SwapId,
+    pub outcome: Outcome,
+    /// How the swap ended, e.g. why we refunded, only in the JSON Lines
+    /// history
+    #[serde(skip)]
+    pub swap_outcome: SwapOutcome,
+    pub bitcoin_fund_txid: Option<TransactionId>,
+    pub bitcoin_redeem_txid: Option<TransactionId>,
+    pub bitcoin_refund_txid: Option<TransactionId>,
+    pub ethereum_deploy_txid: Option<TransactionId>,
+    pub ethereum_fund_txid: Option<TransactionId>,
+    pub ethereum_redeem_txid: Option<TransactionId>,
+    pub ethereum_refund_txid: Option<TransactionId>,
+    /// The fee paid in Satoshi to spend the Bitcoin HTLC, if we spent it.
+    /// Note: the fees of the funding transaction and the Ethereum gas are
+    /// not included
+    pub bitcoin_fee_sat: Option<Integer>,
+    /// The spread captured compared to the mid-market rate, in permyriad like
+    /// the configured spread (negative if the trade was worse than the
+    /// mid-market rate)
+    pub captured_spread: Option<Float>,
+}
+
+/// The rate at which `base_precise_amount` satoshis were traded for
+/// `quote_precise_amount` attodai.
+pub fn executed_rate(base_precise_amount: &BigUint, quote_precise_amount: &BigUint) -> Float {
+    if base_precise_amount.is_zero() {
+        return Float("0".to_owned());
+    }
+
+    // A rate integer is the number of attodai per satoshi
+    let rate = quote_precise_amount / base_precise_amount;
+    Float(string_int_to_float(
+        rate.to_string(),
+        Rate::PRECISION as usize,
+    ))
+}
+
+/// The DAI gained (or lost) by the trade compared to trading the same amount
+/// of bitcoin at the mid-market rate.
+pub fn realized_pnl_dai(
+    position: Position,
+    base_precise_amount: &BigUint,
+    quote_precise_amount: &BigUint,
+    mid_market_rate: Rate,
+) -> Float {
+    let mid_market_quote = base_precise_amount * mid_market_rate.integer();
+
+    let (received, paid) = match position {
+        Position::Sell => (quote_precise_amount.clone(), mid_market_quote),
+        Position::Buy => (mid_market_quote, quote_precise_amount.clone()),
+    };
+
+    if received >= paid {
+        Float(string_int_to_float((received - paid).to_string(), 18))
+    } else {
+        Float(format!(
+            "-{}",
+            string_int_to_float((paid - received).to_string(), 18)
+        ))
+    }
+}
+
+/// The spread captured by the trade in permyriad, with 2 decimals: the
+/// realized P&L relative to the value of the bitcoin at the mid-market rate.
+pub fn captured_spread(
+    position: Position,
+    base_precise_amount: &BigUint,
+    quote_precise_amount: &BigUint,
+    mid_market_rate: Rate,
+) -> Float {
+    let mid_market_quote = base_precise_amount * mid_market_rate.integer();
+    if mid_market_quote.is_zero() {
+        return Float("0".to_owned());
+    }
+
+    let (received, paid) = match position {
+        Position::Sell => (quote_precise_amount.clone(), mid_market_quote.clone()),
+        Position::Buy => (mid_market_quote.clone(), quote_precise_amount.clone()),
+    };
+
+    // Hundredths of permyriad
+    let scale = BigUint::from(1_000_000u32);
+    if received >= paid {
+        let spread = (received - paid) * scale / mid_market_quote;
+        Float(string_int_to_float(spread.to_string(), 2))
+    } else {
+        let spread = (paid - received) * scale / mid_market_quote;
+        Float(format!("-{}", string_int_to_float(spread.to_string(), 2)))
+    }
 }
 
 #[cfg(test)]
@@ -154,6 +303,29 @@ impl Trade {
             peer: libp2p::PeerId::from_str("QmUJF1AzhjUfDU1ifzkyuHy26SCnNHbPaVHpX1WYxYYgZg")
                 .unwrap()
                 .into(),
+            mid_market_rate: Some(Float("10000".to_owned())),
+            executed_rate: Float("9900".to_owned()),
+            realized_pnl_dai: Some(Float("1".to_owned())),
+            swap_id: SwapId::from_str("3d7a4c1b-5a8e-4f5a-9d3c-1e2f3a4b5c6d").unwrap(),
+            outcome: Outcome::Redeemed,
+            swap_outcome: SwapOutcome::Completed,
+            bitcoin_fund_txid: Some(TransactionId(
+                "e2b7c8a5fd1a6a2c2ed1a2f6c3b4d5e6f708192a3b4c5d6e7f8091a2b3c4d5e6".to_owned(),
+            )),
+            bitcoin_redeem_txid: None,
+            bitcoin_refund_txid: None,
+            ethereum_deploy_txid: Some(TransactionId(
+                "0x1a2b3c4d5e6f708192a3b4c5d6e7f8091a2b3c4d5e6f708192a3b4c5d6e7f809".to_owned(),
+            )),
+            ethereum_fund_txid: Some(TransactionId(
+                "0x2b3c4d5e6f708192a3b4c5d6e7f8091a2b3c4d5e6f708192a3b4c5d6e7f8091a".to_owned(),
+            )),
+            ethereum_redeem_txid: Some(TransactionId(
+                "0x3c4d5e6f708192a3b4c5d6e7f8091a2b3c4d5e6f708192a3b4c5d6e7f8091a2b".to_owned(),
+            )),
+            ethereum_refund_txid: None,
+            bitcoin_fee_sat: None,
+            captured_spread: Some(Float("100".to_owned())),
         }
     }
 
@@ -177,40 +349,503 @@ impl Trade {
             peer: libp2p::PeerId::from_str("QmccqkBDb51kDJzvC26EdXprvFhcsLPNmYQRPMwDMmEUhK")
                 .unwrap()
                 .into(),
+            mid_market_rate: None,
+            executed_rate: Float("10061.7".to_owned()),
+            realized_pnl_dai: None,
+            swap_id: SwapId::from_str("8f9e0d1c-2b3a-4c5d-8e7f-6a5b4c3d2e1f").unwrap(),
+            outcome: Outcome::Refunded,
+            swap_outcome: SwapOutcome::RefundedAfter(
+                crate::swap::RefundCause::CounterpartyNeverRedeemed,
+            ),
+            bitcoin_fund_txid: Some(TransactionId(
+                "f3c8d9b6ae2b7b3d3fe2b3a7d4c5e6f7a8192a3b4c5d6e7f8091a2b3c4d5e6f7".to_owned(),
+            )),
+            bitcoin_redeem_txid: None,
+            bitcoin_refund_txid: Some(TransactionId(
+                "a4d9e0c7bf3c8c4e4af3c4b8e5d6f7a8b9203b4c5d6e7f8091a2b3c4d5e6f708".to_owned(),
+            )),
+            ethereum_deploy_txid: None,
+            ethereum_fund_txid: None,
+            ethereum_redeem_txid: None,
+            ethereum_refund_txid: None,
+            bitcoin_fee_sat: Some(1_234u64.into()),
+            captured_spread: None,
+        }
+    }
+}
+
+/// A trade as written in the JSON Lines history, the details of each leg are
+/// nested.
+#[derive(Debug, Serialize)]
+struct JsonTrade<'a> {
+    swap_id: &'a SwapId,
+    utc_start_timestamp: &'a UtcDateTime,
+    utc_final_timestamp: &'a UtcDateTime,
+    position: &'a Position,
+    outcome: &'a Outcome,
+    swap_outcome: &'a SwapOutcome,
+    peer: &'a PeerId,
+    mid_market_rate: &'a Option<Float>,
+    executed_rate: &'a Float,
+    realized_pnl_dai: &'a Option<Float>,
+    captured_spread: &'a Option<Float>,
+    base: BitcoinLeg<'a>,
+    quote: EthereumLeg<'a>,
+}
+
+#[derive(Debug, Serialize)]
+struct BitcoinLeg<'a> {
+    symbol: &'a Symbol,
+    precise_amount: &'a Integer,
+    fund_txid: &'a Option<TransactionId>,
+    redeem_txid: &'a Option<TransactionId>,
+    refund_txid: &'a Option<TransactionId>,
+    fee_sat: &'a Option<Integer>,
+}
+
+#[derive(Debug, Serialize)]
+struct EthereumLeg<'a> {
+    symbol: &'a Symbol,
+    precise_amount: &'a Integer,
+    deploy_txid: &'a Option<TransactionId>,
+    fund_txid: &'a Option<TransactionId>,
+    redeem_txid: &'a Option<TransactionId>,
+    refund_txid: &'a Option<TransactionId>,
+}
+
+impl<'a> From<&'a Trade> for JsonTrade<'a> {
+    fn from(trade: &'a Trade) -> Self {
+        JsonTrade {
+            swap_id: &trade.swap_id,
+            utc_start_timestamp: &trade.utc_start_timestamp,
+            utc_final_timestamp: &trade.utc_final_timestamp,
+            position: &trade.position,
+            outcome: &trade.outcome,
+            swap_outcome: &trade.swap_outcome,
+            peer: &trade.peer,
+            mid_market_rate: &trade.mid_market_rate,
+            executed_rate: &trade.executed_rate,
+            realized_pnl_dai: &trade.realized_pnl_dai,
+            captured_spread: &trade.captured_spread,
+            base: BitcoinLeg {
+                symbol: &trade.base_symbol,
+                precise_amount: &trade.base_precise_amount,
+                fund_txid: &trade.bitcoin_fund_txid,
+                redeem_txid: &trade.bitcoin_redeem_txid,
+                refund_txid: &trade.bitcoin_refund_txid,
+                fee_sat: &trade.bitcoin_fee_sat,
+            },
+            quote: EthereumLeg {
+                symbol: &trade.quote_symbol,
+                precise_amount: &trade.quote_precise_amount,
+                deploy_txid: &trade.ethereum_deploy_txid,
+                fund_txid: &trade.ethereum_fund_txid,
+                redeem_txid: &trade.ethereum_redeem_txid,
+                refund_txid: &trade.ethereum_refund_txid,
+            },
+        }
+    }
+}
+
+/// The version of the columns of the CSV history, to be incremented when they
+/// change. It is written as a comment line at the top of new files.
+pub const CSV_SCHEMA_VERSION: u32 = 1;
+
+/// How the decimal numbers are written in the CSV history.
+#[derive(Debug, Clone, Copy)]
+struct DecimalFormat {
+    separator: char,
+    places: Option<u8>,
+}
+
+impl From<&config::History> for DecimalFormat {
+    fn from(config: &config::History) -> Self {
+        DecimalFormat {
+            separator: config.decimal_separator.unwrap_or('.'),
+            places: config.decimal_places,
+        }
+    }
+}
+
+impl DecimalFormat {
+    fn apply(self, trade: &Trade) -> Trade {
+        let format = |float: &Float| Float(format_decimal(&float.0, self.places, self.separator));
+
+        Trade {
+            mid_market_rate: trade.mid_market_rate.as_ref().map(format),
+            executed_rate: format(&trade.executed_rate),
+            realized_pnl_dai: trade.realized_pnl_dai.as_ref().map(format),
+            captured_spread: trade.captured_spread.as_ref().map(format),
+            ..trade.clone()
         }
     }
 }
 
+/// Round `value` half away from zero to `places` decimal places if given and
+/// write it with `separator`. Values which are not decimal numbers are
+/// returned as is.
+fn format_decimal(value: &str, places: Option<u8>, separator: char) -> String {
+    let (negative, digits) = match value.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, value),
+    };
+    let (int, frac) = match digits.find('.') {
+        Some(index) => (&digits[..index], &digits[index + 1..]),
+        None => (digits, ""),
+    };
+    if int.is_empty() || !int.chars().chain(frac.chars()).all(|c| c.is_ascii_digit()) {
+        return value.to_owned();
+    }
+
+    let (int, frac) = match places {
+        Some(places) => {
+            let places = usize::from(places);
+            let round_up = frac
+                .as_bytes()
+                .get(places)
+                .map_or(false, |digit| *digit >= b'5');
+            let mut frac = frac.chars().take(places).collect::<String>();
+            while frac.len() < places {
+                frac.push('0');
+            }
+
+            let mut scaled = match format!("{}{}", int, frac).parse::<BigUint>() {
+                Ok(scaled) => scaled,
+                Err(_) => return value.to_owned(),
+            };
+            if round_up {
+                scaled += 1u32;
+            }
+
+            let scaled = format!("{:0>width$}", scaled, width = places + 1);
+            let (int, frac) = scaled.split_at(scaled.len() - places);
+            (int.to_owned(), frac.to_owned())
+        }
+        None => (int.to_owned(), frac.to_owned()),
+    };
+
+    let is_zero = int.chars().chain(frac.chars()).all(|c| c == '0');
+    let sign = if negative && !is_zero { "-" } else { "" };
+
+    if frac.is_empty() {
+        format!("{}{}", sign, int)
+    } else {
+        format!("{}{}{}{}", sign, int, separator, frac)
+    }
+}
+
+#[derive(Debug)]
+enum Sink {
+    Csv {
+        writer: Writer<File>,
+        decimals: DecimalFormat,
+    },
+    JsonLines(File),
+}
+
+impl Sink {
+    /// Open the file to append trades to it, the schema version and headers
+    /// are only written to new CSV files.
+    fn open(path: &Path, config: &config::History) -> Result<Sink> {
+        let exists = path.exists();
+        let mut file = OpenOptions::new().append(true).create(true).open(path)?;
+
+        let sink = match config.format {
+            config::HistoryFormat::Csv => {
+                if !exists {
+                    writeln!(
+                        file,
+                        "# nectar history schema version {}",
+                        CSV_SCHEMA_VERSION
+                    )?;
+                }
+
+                Sink::Csv {
+                    writer: WriterBuilder::new().has_headers(!exists).from_writer(file),
+                    decimals: config.into(),
+                }
+            }
+            config::HistoryFormat::JsonLines => Sink::JsonLines(file),
+        };
+
+        Ok(sink)
+    }
+
+    fn write(&mut self, trade: &Trade) -> Result<()> {
+        match self {
+            Sink::Csv { writer, decimals } => {
+                writer.serialize(decimals.apply(trade))?;
+                writer.flush()?;
+            }
+            Sink::JsonLines(file) => {
+                let mut line = serde_json::to_vec(&JsonTrade::from(trade))?;
+                line.push(b'\n');
+                file.write_all(&line)?;
+                file.flush()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        match self {
+            Sink::Csv { writer, .. } => writer.flush()?,
+            Sink::JsonLines(file) => file.flush()?,
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Debug)]
 pub struct History {
-    writer: Writer<File>,
+    sink: Sink,
+    /// Written to after the history file, see `config::HistorySink`.
+    sinks: Vec<Box<dyn TradeSink>>,
+    path: PathBuf,
+    config: config::History,
+    /// When the active file was last written to, `None` if it has no trades.
+    last_written: Option<DateTime<Utc>>,
 }
 
 impl History {
-    pub fn new(path: &Path) -> Result<History> {
+    pub fn new(path: &Path, config: config::History) -> Result<History> {
         ensure_directory_exists(&path)?;
 
-        let writer = if path.exists() {
-            let file = OpenOptions::new().append(true).open(path)?;
-            WriterBuilder::new().has_headers(false).from_writer(file)
+        let last_written = if path.exists() {
+            let last_written: DateTime<Utc> = path.metadata()?.modified()?.into();
+            Some(last_written)
         } else {
-            Writer::from_path(path)?
+            None
         };
+        let sink = Sink::open(path, &config)?;
+        let sinks = config
+            .sinks
+            .iter()
+            .map(sinks::open)
+            .collect::<Result<Vec<_>>>()?;
 
-        Ok(History { writer })
+        Ok(History {
+            sink,
+            sinks,
+            path: path.to_path_buf(),
+            config,
+            last_written,
+        })
     }
 
     pub fn write(&mut self, trade: Trade) -> anyhow::Result<()> {
-        self.writer.serialize(trade)?;
-        self.writer.flush()?;
+        let written_at = trade.utc_final_timestamp.inner;
+
+        if self.needs_rotation(written_at)? {
+            self.rotate()?;
+        }
+
+        self.sink.write(&trade)?;
+        self.last_written = Some(written_at);
+
+        for sink in &mut self.sinks {
+            if let Err(e) = sink.write(&trade) {
+                tracing::warn!(
+                    "Unable to write trade {} to the {} history sink: {:#}",
+                    trade.swap_id,
+                    sink.name(),
+                    e
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    fn needs_rotation(&self, written_at: DateTime<Utc>) -> anyhow::Result<bool> {
+        let last_written = match self.last_written {
+            Some(last_written) => last_written,
+            None => return Ok(false),
+        };
+
+        let new_month =
+            (last_written.year(), last_written.month()) != (written_at.year(), written_at.month());
+        if self.config.rotate_monthly && new_month {
+            return Ok(true);
+        }
+
+        match self.config.max_size_bytes {
+            Some(max_size_bytes) => Ok(self.path.metadata()?.len() >= max_size_bytes),
+            None => Ok(false),
+        }
+    }
+
+    /// Move the active file to a timestamped archive next to it, compressed if
+    /// configured, and start a new active file.
+    fn rotate(&mut self) -> anyhow::Result<()> {
+        self.sink.flush()?;
+
+        let archive = archive_path(&self.path, Utc::now());
+        std::fs::rename(&self.path, &archive)?;
+        let archive = if self.config.compress_archives {
+            compress(&archive)?
+        } else {
+            archive
+        };
+        tracing::info!("Archived trade history to {}", archive.display());
+
+        self.sink = Sink::open(&self.path, &self.config)?;
+        self.last_written = None;
         Ok(())
     }
 }
 
+/// `history.csv` is archived as `history-20200710T074826.123Z.csv`.
+fn archive_path(path: &Path, archived_at: DateTime<Utc>) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("history");
+    let file_name = match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) => format!(
+            "{}-{}.{}",
+            stem,
+            archived_at.format("%Y%m%dT%H%M%S%.3fZ"),
+            extension
+        ),
+        None => format!("{}-{}", stem, archived_at.format("%Y%m%dT%H%M%S%.3fZ")),
+    };
+
+    path.with_file_name(file_name)
+}
+
+/// Replace the file at `path` with its gzipped version, `history.csv` is
+/// compressed to `history.csv.gz`.
+fn compress(path: &Path) -> anyhow::Result<PathBuf> {
+    let mut compressed = path.as_os_str().to_owned();
+    compressed.push(GZIP_EXTENSION);
+    let compressed = PathBuf::from(compressed);
+
+    let mut encoder = GzEncoder::new(File::create(&compressed)?, Compression::default());
+    io::copy(&mut File::open(path)?, &mut encoder)?;
+    encoder.finish()?.sync_all()?;
+    std::fs::remove_file(path)?;
+
+    Ok(compressed)
+}
+
+const GZIP_EXTENSION: &str = ".gz";
+
+/// Open the history file at `path`, decompressing it if it is gzipped.
+fn open(path: &Path) -> anyhow::Result<Box<dyn Read>> {
+    let file = File::open(path)?;
+    let is_gzipped = path
+        .to_str()
+        .map_or(false, |path| path.ends_with(GZIP_EXTENSION));
+
+    Ok(if is_gzipped {
+        Box::new(GzDecoder::new(file))
+    } else {
+        Box::new(file)
+    })
+}
+
+/// The archives of the history file `path`, compressed or not, in
+/// chronological order.
+pub fn archives(path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let (directory, stem, extension) = match (
+        path.parent(),
+        path.file_stem().and_then(|stem| stem.to_str()),
+        path.extension().and_then(|extension| extension.to_str()),
+    ) {
+        (Some(directory), Some(stem), Some(extension)) => (directory, stem, extension),
+        _ => return Ok(Vec::new()),
+    };
+    if !directory.exists() {
+        return Ok(Vec::new());
+    }
+
+    let prefix = format!("{}-", stem);
+    let suffix = format!(".{}", extension);
+    let compressed_suffix = format!("{}{}", suffix, GZIP_EXTENSION);
+    let mut archives = Vec::new();
+    for entry in std::fs::read_dir(directory)? {
+        let path = entry?.path();
+        let is_archive = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map_or(false, |name| {
+                name.starts_with(&prefix)
+                    && (name.ends_with(&suffix) || name.ends_with(&compressed_suffix))
+            });
+        if is_archive {
+            archives.push(path);
+        }
+    }
+    // The timestamps in the names sort chronologically
+    archives.sort();
+
+    Ok(archives)
+}
+
+/// Read all the records of the history file, gzipped or not, CSV records are
+/// returned as column name/value pairs.
+///
+/// An absent history file means no trade happened yet. Records written before
+/// columns were added to [`Trade`] are returned without these columns.
+pub fn read_records(
+    path: &Path,
+    format: config::HistoryFormat,
+) -> anyhow::Result<Vec<serde_json::Value>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let records = match format {
+        config::HistoryFormat::Csv => {
+            let mut reader = ReaderBuilder::new()
+                .flexible(true)
+                .comment(Some(b'#'))
+                .from_reader(open(path)?);
+            reader
+                .deserialize::<BTreeMap<String, String>>()
+                .map(|record| Ok(serde_json::to_value(record?)?))
+                .collect::<anyhow::Result<Vec<_>>>()?
+        }
+        config::HistoryFormat::JsonLines => BufReader::new(open(path)?)
+            .lines()
+            .filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()))
+            .map(|line| Ok(serde_json::from_str(&line?)?))
+            .collect::<anyhow::Result<Vec<_>>>()?,
+    };
+
+    Ok(records)
+}
+
+/// Read the records of the history files in the data directory and of their
+/// archives, in both formats as the format may have changed over time.
+pub fn read_all_records(
+    data_dir: &Path,
+    config: &config::History,
+) -> anyhow::Result<Vec<serde_json::Value>> {
+    let mut records = Vec::new();
+    for format in &[config::HistoryFormat::Csv, config::HistoryFormat::JsonLines] {
+        let path = config::History {
+            format: *format,
+            ..config.clone()
+        }
+        .file_path(data_dir);
+
+        for archive in archives(&path)? {
+            records.extend(read_records(&archive, *format)?);
+        }
+        records.extend(read_records(&path, *format)?);
+    }
+
+    Ok(records)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::io::Read;
+    use std::{convert::TryFrom, io::Read, str::FromStr};
     use tempdir::TempDir;
 
     #[test]
@@ -221,7 +856,7 @@ mod tests {
             .join("history.csv");
         let trade_1 = Trade::new_1();
         let trade_2 = Trade::new_2();
-        let mut history = History::new(&temp_file).unwrap();
+        let mut history = History::new(&temp_file, Default::default()).unwrap();
 
         history.write(trade_1).unwrap();
         history.write(trade_2).unwrap();
@@ -230,14 +865,33 @@ mod tests {
         let mut contents = String::new();
         file.read_to_string(&mut contents).unwrap();
 
-        let expected_contents = "utc_start_timestamp,utc_final_timestamp,base_symbol,quote_symbol,position,base_precise_amount,quote_precise_amount,peer
-2020-07-10T07:48:26.123+00:00,2020-07-10T08:48:26.456+00:00,BTC,DAI,Buy,1000000,99000000000000000000,QmUJF1AzhjUfDU1ifzkyuHy26SCnNHbPaVHpX1WYxYYgZg
-2020-07-11T02:00:00.789+00:00,2020-07-11T03:00:00+00:00,BTC,DAI,Sell,20000000,2012340000000000000000,QmccqkBDb51kDJzvC26EdXprvFhcsLPNmYQRPMwDMmEUhK
+        let expected_contents = "# nectar history schema version 1
+utc_start_timestamp,utc_final_timestamp,base_symbol,quote_symbol,position,base_precise_amount,quote_precise_amount,peer,mid_market_rate,executed_rate,realized_pnl_dai,swap_id,outcome,bitcoin_fund_txid,bitcoin_redeem_txid,bitcoin_refund_txid,ethereum_deploy_txid,ethereum_fund_txid,ethereum_redeem_txid,ethereum_refund_txid,bitcoin_fee_sat,captured_spread
+2020-07-10T07:48:26.123+00:00,2020-07-10T08:48:26.456+00:00,BTC,DAI,Buy,1000000,99000000000000000000,QmUJF1AzhjUfDU1ifzkyuHy26SCnNHbPaVHpX1WYxYYgZg,10000,9900,1,3d7a4c1b-5a8e-4f5a-9d3c-1e2f3a4b5c6d,Redeemed,e2b7c8a5fd1a6a2c2ed1a2f6c3b4d5e6f708192a3b4c5d6e7f8091a2b3c4d5e6,,,0x1a2b3c4d5e6f708192a3b4c5d6e7f8091a2b3c4d5e6f708192a3b4c5d6e7f809,0x2b3c4d5e6f708192a3b4c5d6e7f8091a2b3c4d5e6f708192a3b4c5d6e7f8091a,0x3c4d5e6f708192a3b4c5d6e7f8091a2b3c4d5e6f708192a3b4c5d6e7f8091a2b,,,100
+2020-07-11T02:00:00.789+00:00,2020-07-11T03:00:00+00:00,BTC,DAI,Sell,20000000,2012340000000000000000,QmccqkBDb51kDJzvC26EdXprvFhcsLPNmYQRPMwDMmEUhK,,10061.7,,8f9e0d1c-2b3a-4c5d-8e7f-6a5b4c3d2e1f,Refunded,f3c8d9b6ae2b7b3d3fe2b3a7d4c5e6f7a8192a3b4c5d6e7f8091a2b3c4d5e6f7,,a4d9e0c7bf3c8c4e4af3c4b8e5d6f7a8b9203b4c5d6e7f8091a2b3c4d5e6f708,,,,,1234,
 ";
 
         assert_eq!(contents, expected_contents);
     }
 
+    #[test]
+    fn read_written_trades_as_records() {
+        let temp_file = TempDir::new("nectar_test")
+            .unwrap()
+            .path()
+            .join("history.csv");
+        let mut history = History::new(&temp_file, Default::default()).unwrap();
+
+        history.write(Trade::new_1()).unwrap();
+        history.write(Trade::new_2()).unwrap();
+
+        let records = read_records(&temp_file, config::HistoryFormat::Csv).unwrap();
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["position"], "Buy");
+        assert_eq!(records[1]["base_precise_amount"], "20000000");
+    }
+
     #[test]
     fn re_use_existing_file_without_losing_data_or_re_writing_headers() {
         let temp_file = TempDir::new("nectar_test")
@@ -246,12 +900,12 @@ mod tests {
             .join("history.csv");
         let trade_1 = Trade::new_1();
         let trade_2 = Trade::new_2();
-        let mut history = History::new(&temp_file).unwrap();
+        let mut history = History::new(&temp_file, Default::default()).unwrap();
 
         history.write(trade_1).unwrap();
 
         // Re-instantiate history to test re-usage of an existing file
-        let mut history = History::new(&temp_file).unwrap();
+        let mut history = History::new(&temp_file, Default::default()).unwrap();
 
         history.write(trade_2).unwrap();
 
@@ -259,11 +913,262 @@ mod tests {
         let mut contents = String::new();
         file.read_to_string(&mut contents).unwrap();
 
-        let expected_contents = "utc_start_timestamp,utc_final_timestamp,base_symbol,quote_symbol,position,base_precise_amount,quote_precise_amount,peer
-2020-07-10T07:48:26.123+00:00,2020-07-10T08:48:26.456+00:00,BTC,DAI,Buy,1000000,99000000000000000000,QmUJF1AzhjUfDU1ifzkyuHy26SCnNHbPaVHpX1WYxYYgZg
-2020-07-11T02:00:00.789+00:00,2020-07-11T03:00:00+00:00,BTC,DAI,Sell,20000000,2012340000000000000000,QmccqkBDb51kDJzvC26EdXprvFhcsLPNmYQRPMwDMmEUhK
+        let expected_contents = "# nectar history schema version 1
+utc_start_timestamp,utc_final_timestamp,base_symbol,quote_symbol,position,base_precise_amount,quote_precise_amount,peer,mid_market_rate,executed_rate,realized_pnl_dai,swap_id,outcome,bitcoin_fund_txid,bitcoin_redeem_txid,bitcoin_refund_txid,ethereum_deploy_txid,ethereum_fund_txid,ethereum_redeem_txid,ethereum_refund_txid,bitcoin_fee_sat,captured_spread
+2020-07-10T07:48:26.123+00:00,2020-07-10T08:48:26.456+00:00,BTC,DAI,Buy,1000000,99000000000000000000,QmUJF1AzhjUfDU1ifzkyuHy26SCnNHbPaVHpX1WYxYYgZg,10000,9900,1,3d7a4c1b-5a8e-4f5a-9d3c-1e2f3a4b5c6d,Redeemed,e2b7c8a5fd1a6a2c2ed1a2f6c3b4d5e6f708192a3b4c5d6e7f8091a2b3c4d5e6,,,0x1a2b3c4d5e6f708192a3b4c5d6e7f8091a2b3c4d5e6f708192a3b4c5d6e7f809,0x2b3c4d5e6f708192a3b4c5d6e7f8091a2b3c4d5e6f708192a3b4c5d6e7f8091a,0x3c4d5e6f708192a3b4c5d6e7f8091a2b3c4d5e6f708192a3b4c5d6e7f8091a2b,,,100
+2020-07-11T02:00:00.789+00:00,2020-07-11T03:00:00+00:00,BTC,DAI,Sell,20000000,2012340000000000000000,QmccqkBDb51kDJzvC26EdXprvFhcsLPNmYQRPMwDMmEUhK,,10061.7,,8f9e0d1c-2b3a-4c5d-8e7f-6a5b4c3d2e1f,Refunded,f3c8d9b6ae2b7b3d3fe2b3a7d4c5e6f7a8192a3b4c5d6e7f8091a2b3c4d5e6f7,,a4d9e0c7bf3c8c4e4af3c4b8e5d6f7a8b9203b4c5d6e7f8091a2b3c4d5e6f708,,,,,1234,
 ";
 
         assert_eq!(contents, expected_contents);
     }
+
+    #[test]
+    fn executed_rate_is_quote_per_base() {
+        let rate = executed_rate(
+            &BigUint::from(20_000_000u64),
+            &BigUint::from_str("2_012_340_000_000_000_000_000").unwrap(),
+        );
+
+        assert_eq!(rate.0, "10061.7");
+    }
+
+    #[test]
+    fn buying_below_mid_market_rate_is_a_profit() {
+        let pnl = realized_pnl_dai(
+            Position::Buy,
+            &BigUint::from(1_000_000u64),
+            &BigUint::from_str("99_000_000_000_000_000_000").unwrap(),
+            Rate::try_from(10_000.0).unwrap(),
+        );
+
+        assert_eq!(pnl.0, "1");
+    }
+
+    #[test]
+    fn selling_below_mid_market_rate_is_a_loss() {
+        let pnl = realized_pnl_dai(
+            Position::Sell,
+            &BigUint::from(1_000_000u64),
+            &BigUint::from_str("99_500_000_000_000_000_000").unwrap(),
+            Rate::try_from(10_000.0).unwrap(),
+        );
+
+        assert_eq!(pnl.0, "-0.5");
+    }
+
+    #[test]
+    fn buying_below_mid_market_rate_captures_a_spread() {
+        let spread = captured_spread(
+            Position::Buy,
+            &BigUint::from(1_000_000u64),
+            &BigUint::from_str("99_000_000_000_000_000_000").unwrap(),
+            Rate::try_from(10_000.0).unwrap(),
+        );
+
+        assert_eq!(spread.0, "100");
+    }
+
+    #[test]
+    fn selling_below_mid_market_rate_captures_a_negative_spread() {
+        let spread = captured_spread(
+            Position::Sell,
+            &BigUint::from(1_000_000u64),
+            &BigUint::from_str("99_500_000_000_000_000_000").unwrap(),
+            Rate::try_from(10_000.0).unwrap(),
+        );
+
+        assert_eq!(spread.0, "-50");
+    }
+
+    #[test]
+    fn rotate_history_exceeding_max_size() {
+        let temp_dir = TempDir::new("nectar_test").unwrap();
+        let temp_file = temp_dir.path().join("history.csv");
+        let mut history = History::new(&temp_file, config::History {
+            max_size_bytes: Some(1),
+            rotate_monthly: false,
+            ..Default::default()
+        })
+        .unwrap();
+
+        history.write(Trade::new_1()).unwrap();
+        history.write(Trade::new_2()).unwrap();
+
+        let active = read_records(&temp_file, config::HistoryFormat::Csv).unwrap();
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0]["position"], "Sell");
+
+        let archives = archives(&temp_dir);
+        assert_eq!(archives.len(), 1);
+        let archived = read_records(&archives[0], config::HistoryFormat::Csv).unwrap();
+        assert_eq!(archived.len(), 1);
+        assert_eq!(archived[0]["position"], "Buy");
+    }
+
+    #[test]
+    fn rotate_history_on_new_month_only() {
+        let temp_dir = TempDir::new("nectar_test").unwrap();
+        let temp_file = temp_dir.path().join("history.csv");
+        let mut history = History::new(&temp_file, config::History {
+            max_size_bytes: None,
+            rotate_monthly: true,
+            ..Default::default()
+        })
+        .unwrap();
+        let mut next_month_trade = Trade::new_2();
+        next_month_trade.utc_final_timestamp = DateTime::from_str("2020-08-01T00:00:00+00:00")
+            .unwrap()
+            .into();
+
+        history.write(Trade::new_1()).unwrap();
+        history.write(Trade::new_2()).unwrap();
+        assert!(archives(&temp_dir).is_empty());
+
+        history.write(next_month_trade).unwrap();
+
+        assert_eq!(
+            read_records(&temp_file, config::HistoryFormat::Csv)
+                .unwrap()
+                .len(),
+            1
+        );
+        let archives = archives(&temp_dir);
+        assert_eq!(archives.len(), 1);
+        assert_eq!(
+            read_records(&archives[0], config::HistoryFormat::Csv)
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn compress_archives_and_read_them_back() {
+        let temp_dir = TempDir::new("nectar_test").unwrap();
+        let temp_file = temp_dir.path().join("history.csv");
+        let config = config::History {
+            max_size_bytes: Some(1),
+            rotate_monthly: false,
+            compress_archives: true,
+            ..Default::default()
+        };
+        let mut history = History::new(&temp_file, config.clone()).unwrap();
+
+        history.write(Trade::new_1()).unwrap();
+        history.write(Trade::new_2()).unwrap();
+
+        let archives = super::archives(&temp_file).unwrap();
+        assert_eq!(archives.len(), 1);
+        assert!(archives[0].to_str().unwrap().ends_with(".csv.gz"));
+        let archived = read_records(&archives[0], config::HistoryFormat::Csv).unwrap();
+        assert_eq!(archived.len(), 1);
+        assert_eq!(archived[0]["position"], "Buy");
+
+        let records = read_all_records(temp_dir.path(), &config).unwrap();
+        assert_eq!(records.len(), 2);
+    }
+
+    #[test]
+    fn write_trades_as_json_lines_with_nested_legs() {
+        let temp_file = TempDir::new("nectar_test")
+            .unwrap()
+            .path()
+            .join("history.jsonl");
+        let mut history = History::new(&temp_file, config::History {
+            format: config::HistoryFormat::JsonLines,
+            ..Default::default()
+        })
+        .unwrap();
+
+        history.write(Trade::new_1()).unwrap();
+        history.write(Trade::new_2()).unwrap();
+
+        let records = read_records(&temp_file, config::HistoryFormat::JsonLines).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["position"], "Buy");
+        assert_eq!(records[0]["base"]["symbol"], "BTC");
+        assert_eq!(records[0]["base"]["precise_amount"], "1000000");
+        assert_eq!(records[0]["quote"]["symbol"], "DAI");
+        assert_eq!(
+            records[0]["quote"]["precise_amount"],
+            "99000000000000000000"
+        );
+        assert_eq!(records[1]["position"], "Sell");
+        assert_eq!(records[0]["swap_outcome"], "Completed");
+        assert_eq!(
+            records[1]["swap_outcome"]["RefundedAfter"],
+            "CounterpartyNeverRedeemed"
+        );
+    }
+
+    #[test]
+    fn format_decimal_rounds_half_away_from_zero() {
+        assert_eq!(format_decimal("10061.745", Some(2), ','), "10061,75");
+        assert_eq!(format_decimal("-0.5", Some(0), '.'), "-1");
+        assert_eq!(format_decimal("-0.004", Some(2), '.'), "0.00");
+        assert_eq!(format_decimal("9.999", Some(2), '.'), "10.00");
+        assert_eq!(format_decimal("1", Some(2), '.'), "1.00");
+        assert_eq!(format_decimal("0.123456", None, ','), "0,123456");
+    }
+
+    #[test]
+    fn write_decimals_with_configured_separator_and_places() {
+        let temp_file = TempDir::new("nectar_test")
+            .unwrap()
+            .path()
+            .join("history.csv");
+        let mut history = History::new(&temp_file, config::History {
+            decimal_separator: Some(','),
+            decimal_places: Some(2),
+            ..Default::default()
+        })
+        .unwrap();
+
+        history.write(Trade::new_2()).unwrap();
+
+        let records = read_records(&temp_file, config::HistoryFormat::Csv).unwrap();
+        assert_eq!(records[0]["executed_rate"], "10061,70");
+        assert_eq!(records[0]["base_precise_amount"], "20000000");
+    }
+
+    #[test]
+    fn list_archives_of_the_history_file_only() {
+        let temp_dir = TempDir::new("nectar_test").unwrap();
+        let temp_file = temp_dir.path().join("history.csv");
+        let later = archive_path(&temp_file, Utc::now());
+        let earlier = archive_path(
+            &temp_file,
+            DateTime::from_str("2020-07-10T07:48:26Z").unwrap(),
+        );
+        for path in &[
+            &temp_file,
+            &later,
+            &earlier,
+            &temp_dir.path().join("history-1.jsonl"),
+        ] {
+            File::create(path).unwrap();
+        }
+
+        assert_eq!(super::archives(&temp_file).unwrap(), vec![earlier, later]);
+    }
+
+    #[test]
+    fn archive_path_is_timestamped() {
+        let archived_at = DateTime::from_str("2020-07-10T07:48:26.123+00:00").unwrap();
+
+        let archive = archive_path(Path::new("/data/history.csv"), archived_at);
+
+        assert_eq!(
+            archive,
+            PathBuf::from("/data/history-20200710T074826.123Z.csv")
+        );
+    }
+
+    fn archives(temp_dir: &TempDir) -> Vec<PathBuf> {
+        std::fs::read_dir(temp_dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.file_name() != Some(std::ffi::OsStr::new("history.csv")))
+            .collect()
+    }
 }
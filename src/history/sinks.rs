@@ -0,0 +1,151 @@
+//! Additional destinations of the trade history, written to after the history
+//! file, see `[[history.sinks]]` in the config.
+
+use super::{JsonTrade, Trade};
+use crate::config;
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection};
+use std::{fmt::Debug, path::Path};
+use url::Url;
+
+pub trait TradeSink: Debug + Send {
+    /// Used to identify the sink in logs.
+    fn name(&self) -> &'static str;
+
+    fn write(&mut self, trade: &Trade) -> Result<()>;
+}
+
+pub fn open(config: &config::HistorySink) -> Result<Box<dyn TradeSink>> {
+    let sink: Box<dyn TradeSink> = match config {
+        config::HistorySink::Sqlite { path } => Box::new(Sqlite::open(path)?),
+        config::HistorySink::Http { url } => Box::new(Http::new(url.clone())),
+    };
+
+    Ok(sink)
+}
+
+/// Stores the trades, as the JSON lines of the history file, in a `trades`
+/// table keyed by swap id.
+#[derive(Debug)]
+pub struct Sqlite(Connection);
+
+impl Sqlite {
+    pub fn open(path: &Path) -> Result<Sqlite> {
+        crate::fs::ensure_directory_exists(path)?;
+        let connection = Connection::open(path)
+            .with_context(|| format!("Unable to open SQLite database {}", path.display()))?;
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS trades (
+                swap_id TEXT PRIMARY KEY,
+                utc_final_timestamp TEXT NOT NULL,
+                trade TEXT NOT NULL
+            )",
+            params![],
+        )?;
+
+        Ok(Sqlite(connection))
+    }
+}
+
+impl TradeSink for Sqlite {
+    fn name(&self) -> &'static str {
+        "sqlite"
+    }
+
+    fn write(&mut self, trade: &Trade) -> Result<()> {
+        let json = serde_json::to_string(&JsonTrade::from(trade))?;
+        self.0.execute(
+            "INSERT OR REPLACE INTO trades (swap_id, utc_final_timestamp, trade) VALUES (?1, ?2, ?3)",
+            params![
+                trade.swap_id.to_string(),
+                trade.utc_final_timestamp.inner.to_rfc3339(),
+                json
+            ],
+        )?;
+
+        Ok(())
+    }
+}
+
+/// POSTs each trade as JSON to the configured url, in the background so that a
+/// slow endpoint does not hold up the trade loop.
+#[derive(Debug)]
+pub struct Http {
+    client: reqwest::Client,
+    url: Url,
+}
+
+impl Http {
+    pub fn new(url: Url) -> Http {
+        Http {
+            client: reqwest::Client::new(),
+            url,
+        }
+    }
+}
+
+impl TradeSink for Http {
+    fn name(&self) -> &'static str {
+        "http"
+    }
+
+    fn write(&mut self, trade: &Trade) -> Result<()> {
+        let body = serde_json::to_vec(&JsonTrade::from(trade))?;
+        let runtime = tokio::runtime::Handle::try_current()
+            .context("Unable to post the trade outside of a runtime")?;
+
+        let request = self
+            .client
+            .post(self.url.clone())
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body);
+        let swap_id = trade.swap_id;
+        let _ = runtime.spawn(async move {
+            let response = request.send().await.and_then(|res| res.error_for_status());
+            if let Err(e) = response {
+                tracing::warn!(
+                    "Unable to post trade {} to the history sink: {}",
+                    swap_id,
+                    e
+                );
+            }
+        });
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn write_trades_to_sqlite_once_per_swap() {
+        let path = TempDir::new("nectar_test")
+            .unwrap()
+            .into_path()
+            .join("history.sqlite");
+        let mut sink = Sqlite::open(&path).unwrap();
+
+        sink.write(&Trade::new_1()).unwrap();
+        sink.write(&Trade::new_1()).unwrap();
+        sink.write(&Trade::new_2()).unwrap();
+
+        let connection = Connection::open(&path).unwrap();
+        let count: i64 = connection
+            .query_row("SELECT COUNT(*) FROM trades", params![], |row| row.get(0))
+            .unwrap();
+        let trade: String = connection
+            .query_row(
+                "SELECT trade FROM trades WHERE swap_id = ?1",
+                params![Trade::new_1().swap_id.to_string()],
+                |row| row.get(0),
+            )
+            .unwrap();
+        let trade: serde_json::Value = serde_json::from_str(&trade).unwrap();
+
+        assert_eq!(count, 2);
+        assert_eq!(trade["position"], "Buy");
+    }
+}
@@ -0,0 +1,73 @@
+//! The market making engine of nectar, usable by other binaries.
+//!
+//! `Maker` holds the state of the market maker and decides which orders to
+//! publish. It does not fetch anything itself: the rate and balances are fed
+//! to it with `Maker::update_rate`, `Maker::update_bitcoin_balance` and
+//! `Maker::update_dai_balance`, hence it can be driven by any event source.
+//! `network::new_swarm` builds the libp2p swarm publishing the orders and
+//! setting up swaps with takers, which are executed with
+//! `swap::SwapKind::execute`. `MakerBuilder` wires these together into the
+//! `MakerService` run by the `trade` command, which can be embedded as is.
+
+#![warn(
+    unused_extern_crates,
+    missing_debug_implementations,
+    missing_copy_implementations,
+    rust_2018_idioms,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::fallible_impl_from,
+    clippy::cast_precision_loss,
+    clippy::cast_possible_wrap,
+    clippy::dbg_macro
+)]
+#![cfg_attr(not(test), warn(clippy::unwrap_used))]
+#![forbid(unsafe_code)]
+#![recursion_limit = "256"]
+#![type_length_limit = "1944624"]
+
+pub mod alert;
+mod api;
+pub mod bitcoin;
+pub mod command;
+pub mod config;
+mod error_report;
+pub mod ethereum;
+pub mod float_maths;
+pub mod fs;
+mod health;
+pub mod history;
+mod jsonrpc;
+pub mod maker;
+mod metrics;
+pub mod mid_market_rate;
+pub mod network;
+pub mod order;
+pub mod rate;
+mod seed;
+pub mod service;
+pub mod swap;
+mod swap_id;
+pub mod trace;
+mod watchdog;
+
+#[cfg(test)]
+mod test_harness;
+
+#[cfg(test)]
+mod arbitrary;
+
+use conquer_once::Lazy;
+
+pub use maker::Maker;
+pub use mid_market_rate::MidMarketRate;
+pub use rate::{Rate, Spread};
+pub use seed::Seed;
+pub use service::{MakerBuilder, MakerService};
+pub use swap_id::SwapId;
+
+#[cfg(test)]
+pub use test_harness::StaticStub;
+
+pub static SECP: Lazy<::bitcoin::secp256k1::Secp256k1<::bitcoin::secp256k1::All>> =
+    Lazy::new(::bitcoin::secp256k1::Secp256k1::new);
@@ -15,50 +15,18 @@
 #![recursion_limit = "256"]
 #![type_length_limit = "1944624"]
 
-mod bitcoin;
-mod command;
-mod config;
-mod ethereum;
-mod float_maths;
-mod fs;
-mod history;
-mod jsonrpc;
-mod maker;
-mod mid_market_rate;
-mod network;
-mod order;
-mod rate;
-mod seed;
-mod swap;
-mod swap_id;
-mod trace;
-
-#[cfg(test)]
-mod test_harness;
-
-#[cfg(test)]
-mod arbitrary;
-
-use crate::{
+use nectar::{
+    bitcoin,
     command::{
-        balance, deposit, dump_config, resume_only, trade, wallet_info, withdraw, Command, Options,
+        backtest, balance, deposit, dump_config, export_history, id, migrate_wallet, rebalance,
+        report, resume_only, seed, swaps, takers, trade, utxos, wallet_info, watch_deposit,
+        withdraw, Command, Options,
     },
-    config::{read_config, Settings},
+    config::{self, read_config, Settings},
+    ethereum,
     fs::default_config_path,
+    trace,
 };
-use conquer_once::Lazy;
-
-pub use maker::Maker;
-pub use mid_market_rate::MidMarketRate;
-pub use rate::{Rate, Spread};
-pub use seed::Seed;
-pub use swap_id::SwapId;
-
-#[cfg(test)]
-pub use test_harness::StaticStub;
-
-pub static SECP: Lazy<::bitcoin::secp256k1::Secp256k1<::bitcoin::secp256k1::All>> =
-    Lazy::new(::bitcoin::secp256k1::Secp256k1::new);
 
 #[tokio::main]
 async fn main() {
@@ -73,25 +41,88 @@ async fn main() {
         std::process::exit(0);
     }
 
-    trace::init_tracing(settings.logging.level).expect("initialize tracing");
+    if let Command::Report(arguments) = options.cmd {
+        let report = report(&settings, arguments).expect("summarize the trade history");
+        println!("{}", report);
+        std::process::exit(0);
+    }
+
+    if let Command::History(arguments) = options.cmd {
+        let history = export_history(&settings, arguments).expect("export the trade history");
+        println!("{}", history);
+        std::process::exit(0);
+    }
+
+    if let Command::Backtest(arguments) = options.cmd {
+        let backtest = backtest(&settings, arguments).expect("backtest the configuration");
+        println!("{}", backtest);
+        std::process::exit(0);
+    }
+
+    if let Command::Takers(arguments) = options.cmd {
+        let takers = takers(&settings, arguments)
+            .await
+            .expect("update the listed takers");
+        println!("{}", takers);
+        std::process::exit(0);
+    }
+
+    if let Command::Swaps(arguments) = options.cmd {
+        let swaps = swaps(&settings, arguments).expect("inspect the swaps");
+        println!("{}", swaps);
+        std::process::exit(0);
+    }
+
+    if let Command::Seed(arguments) = options.cmd {
+        let seed = seed(&settings, arguments).expect("export or import the seed");
+        println!("{}", seed);
+        std::process::exit(0);
+    }
+
+    if let Command::Id = options.cmd {
+        let peer_id = id(&settings).expect("derive the peer id");
+        println!("{}", peer_id);
+        std::process::exit(0);
+    }
+
+    let _tracing_guard = trace::init_tracing(
+        settings.logging.level,
+        settings.logging.format,
+        settings.telemetry.as_ref(),
+        settings.error_reporting.as_ref(),
+    )
+    .expect("initialize tracing");
 
     let seed = config::Seed::from_file_or_generate(&settings.data.dir)
         .expect("Could not retrieve/initialize seed")
         .into();
 
-    let bitcoin_wallet = bitcoin::Wallet::new(
+    let bitcoin_wallet = bitcoin::Wallet::new_with_auth(
         seed,
-        settings.bitcoin.bitcoind.node_url.clone(),
+        &settings.bitcoin.bitcoind,
+        &settings.bitcoin.wallet,
+        settings.derivation,
+        settings.rpc,
         settings.bitcoin.network,
     )
-    .await;
+    .await
+    .map(|wallet| {
+        wallet
+            .with_coin_selection(settings.bitcoin.coin_selection)
+            .with_fee_bumping(settings.bitcoin.fee_bumping)
+    });
 
-    let ethereum_wallet = ethereum::Wallet::new(
+    let ethereum_wallet = ethereum::Wallet::new_with_auth(
         seed,
         settings.ethereum.node_url.clone(),
+        &settings.ethereum.auth,
+        &settings.ethereum.signer,
+        settings.derivation,
+        settings.rpc,
         settings.ethereum.chain,
     )
-    .await;
+    .await
+    .map(|wallet| wallet.with_gas_price(settings.ethereum.gas_price.clone()));
 
     match options.cmd {
         Command::Trade => trade(
@@ -108,44 +139,91 @@ async fn main() {
                 bitcoin_wallet.ok(),
                 &seed,
                 settings.bitcoin.network,
+                settings.derivation,
             )
             .await
             .expect("get wallet info");
             println!("{}", wallet_info);
         }
-        Command::Balance => {
+        Command::Balance(arguments) => {
             let balance = balance(
                 ethereum_wallet.expect("could not initialise ethereum wallet"),
                 bitcoin_wallet.expect("could not initialise bitcoin wallet"),
+                &settings,
+                arguments,
             )
             .await
             .expect("get wallet balances");
             println!("{}", balance);
         }
-        Command::Deposit => {
-            let deposit = deposit(
+        Command::Rebalance(arguments) => {
+            let advice = rebalance(
                 ethereum_wallet.expect("could not initialise ethereum wallet"),
                 bitcoin_wallet.expect("could not initialise bitcoin wallet"),
+                &settings,
+                arguments,
             )
             .await
-            .expect("get wallet addresses");
+            .expect("assess the inventory");
+            println!("{}", advice);
+        }
+        Command::Utxos => {
+            let utxos = utxos(bitcoin_wallet.expect("could not initialise bitcoin wallet"))
+                .await
+                .expect("list unspent outputs");
+            println!("{}", utxos);
+        }
+        Command::Deposit(arguments) => {
+            let ethereum_wallet = ethereum_wallet.expect("could not initialise ethereum wallet");
+            let bitcoin_wallet = bitcoin_wallet.expect("could not initialise bitcoin wallet");
+
+            let deposit = deposit(&ethereum_wallet, &bitcoin_wallet, arguments)
+                .await
+                .expect("get wallet addresses");
             println!("{}", deposit);
+
+            if arguments.watch {
+                let deposited =
+                    watch_deposit(&ethereum_wallet, &bitcoin_wallet, arguments.confirmations)
+                        .await
+                        .expect("watch deposit");
+                println!("{}", deposited);
+            }
         }
         Command::Withdraw(arguments) => {
-            let tx_id = withdraw(
+            let withdraw = withdraw(
                 ethereum_wallet.expect("could not initialise ethereum wallet"),
                 bitcoin_wallet.expect("could not initialise bitcoin wallet"),
                 arguments,
             )
             .await
             .expect("Withdraw assets");
-            println!("Withdraw successful. Transaction Id: {}", tx_id);
+            println!("{}", withdraw);
+        }
+        Command::MigrateWallet => {
+            let migrated = migrate_wallet(
+                seed,
+                &settings,
+                bitcoin_wallet.expect("could not initialise bitcoin wallet"),
+            )
+            .await
+            .expect("migrate the bitcoin wallet");
+            println!("{}", migrated);
         }
         Command::DumpConfig => unreachable!(),
-        Command::ResumeOnly => resume_only(
+        Command::Report(_) => unreachable!(),
+        Command::History(_) => unreachable!(),
+        Command::Backtest(_) => unreachable!(),
+        Command::Takers(_) => unreachable!(),
+        Command::Swaps(_) => unreachable!(),
+        Command::Seed(_) => unreachable!(),
+        Command::Id => unreachable!(),
+        Command::ResumeOnly(arguments) => resume_only(
+            &seed,
             settings,
             bitcoin_wallet.expect("could not initialise bitcoin wallet"),
             ethereum_wallet.expect("could not initialise ethereum wallet"),
+            arguments.refund_only,
         )
         .await
         .expect("Wrapping up"),
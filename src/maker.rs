@@ -1,27 +1,98 @@
 use crate::{
-    bitcoin,
-    ethereum::{self, dai},
+    bitcoin, config,
+    ethereum::{self, dai, ether},
     order::{BtcDaiOrderForm, Symbol},
     rate::Spread,
-    MidMarketRate,
+    swap::{Reputation, TakerListing},
+    MidMarketRate, Rate,
 };
+use chrono::{DateTime, Duration, Utc};
 use comit::{order::SwapProtocol, Position, Role};
+use libp2p::PeerId;
+use num256::Uint256;
+use std::{cmp::min, collections::HashSet};
+
+mod circuit_breaker;
+mod rebalance;
+mod reputation;
+#[cfg(test)]
+mod simulation;
+mod strategy;
+mod volume;
+
+pub use circuit_breaker::CircuitBreaker;
+pub use rebalance::{Assessment, RebalanceAdvisor, Recommendation};
+pub use reputation::Reputations;
+pub use strategy::SpreadStrategy;
+pub use volume::{Sale, VolumeLimits};
 
 // Bundles the state of the application
 #[derive(Debug)]
 pub struct Maker {
     btc_balance: Option<bitcoin::Amount>,
     dai_balance: Option<dai::Amount>,
+    ether_balance: Option<ether::Amount>,
+    /// Ether needed for the gas of our transactions in another swap at the
+    /// current gas price.
+    swap_gas_cost: Option<ether::Amount>,
     pub btc_fee: bitcoin::Amount,
     pub btc_reserved_funds: bitcoin::Amount,
     pub dai_reserved_funds: dai::Amount,
+    /// Number of ongoing swaps for which `btc_fee` is part of the reserved
+    /// funds, needed to adjust the reservation when the fee changes.
+    btc_fee_reservations: u64,
     btc_max_sell_amount: Option<bitcoin::Amount>,
     dai_max_sell_amount: Option<dai::Amount>,
+    /// Orders smaller than this are not published, see `BtcDaiOrderForm`.
+    min_sell: config::MinSell,
+    /// Amounts of the published orders are rounded down to these decimals.
+    order_rounding: config::OrderRounding,
     mid_market_rate: Option<MidMarketRate>,
+    /// When the rate was last fetched, whether it changed or not.
+    rate_fetched_at: DateTime<Utc>,
+    /// A rate fetched longer ago is not acted upon. Unbounded if `None`.
+    rate_max_age: Option<Duration>,
+    /// The rate is invalidated upon the first failed fetch if `None`.
+    rate_failover: Option<config::RateFailover>,
+    /// Failed fetches since the rate was last fetched, the spread is widened
+    /// as per the failover while there are some.
+    rate_fetch_failures: u32,
+    /// Orders are replaced upon any change of the rate if `None`.
+    rate_hysteresis: Option<config::RateHysteresis>,
+    /// The rate the orders were last replaced at upon a rate update, and when.
+    published_rate: Option<(Rate, DateTime<Utc>)>,
     spread: Spread,
+    /// Override `spread` for the orders selling, respectively buying, bitcoin.
+    sell_spread: Option<Spread>,
+    buy_spread: Option<Spread>,
+    /// Ladder of orders published per position instead of a single order at
+    /// `spread`, ordered by increasing spread.
+    levels: Vec<config::OrderLevel>,
+    spread_strategy: SpreadStrategy,
     bitcoin_network: bitcoin::Network,
     ethereum_chain: ethereum::Chain,
     role: Role,
+    /// While paused the rate and balances are kept up to date but no orders
+    /// are published and takes are declined.
+    paused: bool,
+    /// Paused for the maintenance of a node, takes are declined as such.
+    maintenance: bool,
+    /// Connected to no peer for too long, orders are withdrawn as they could
+    /// not be honored.
+    isolated: bool,
+    /// While tripped orders are withdrawn and takes declined, like when
+    /// paused.
+    circuit_breaker: CircuitBreaker,
+    taker_filter: TakerFilter,
+    reputations: Reputations,
+    volume_limits: VolumeLimits,
+    /// The orders selling an asset are withdrawn and takes of them declined
+    /// while its balance is below the minimum.
+    min_balance: config::MinBalance,
+    /// Overrides the expiry offsets of the swap protocol.
+    expiries: Option<config::Expiries>,
+    /// May scale down the maximum sell amount of the depleted asset.
+    rebalance: RebalanceAdvisor,
 }
 
 impl Maker {
@@ -41,16 +112,148 @@ impl Maker {
         Maker {
             btc_balance: Some(btc_balance),
             dai_balance: Some(dai_balance),
+            ether_balance: None,
+            swap_gas_cost: None,
             btc_fee,
             btc_reserved_funds: Default::default(),
             dai_reserved_funds: Default::default(),
+            btc_fee_reservations: 0,
             btc_max_sell_amount,
             dai_max_sell_amount,
+            min_sell: config::MinSell::default(),
+            order_rounding: config::OrderRounding::default(),
             mid_market_rate: Some(mid_market_rate),
+            rate_fetched_at: Utc::now(),
+            rate_max_age: None,
+            rate_failover: None,
+            rate_fetch_failures: 0,
+            rate_hysteresis: None,
+            published_rate: None,
             spread,
+            sell_spread: None,
+            buy_spread: None,
+            levels: Vec::new(),
+            spread_strategy: SpreadStrategy::default(),
             bitcoin_network,
             ethereum_chain: dai_chain,
             role,
+            paused: false,
+            maintenance: false,
+            isolated: false,
+            circuit_breaker: CircuitBreaker::default(),
+            taker_filter: TakerFilter::default(),
+            reputations: Reputations::default(),
+            volume_limits: VolumeLimits::default(),
+            min_balance: config::MinBalance::default(),
+            expiries: None,
+            rebalance: RebalanceAdvisor::default(),
+        }
+    }
+
+    pub fn with_rate_max_age(self, rate_max_age: std::time::Duration) -> Self {
+        Self {
+            rate_max_age: Duration::from_std(rate_max_age).ok(),
+            ..self
+        }
+    }
+
+    pub fn with_rate_failover(self, rate_failover: Option<config::RateFailover>) -> Self {
+        Self {
+            rate_failover,
+            ..self
+        }
+    }
+
+    pub fn with_rate_hysteresis(self, rate_hysteresis: Option<config::RateHysteresis>) -> Self {
+        Self {
+            rate_hysteresis,
+            ..self
+        }
+    }
+
+    pub fn with_min_sell(self, min_sell: config::MinSell) -> Self {
+        Self { min_sell, ..self }
+    }
+
+    pub fn with_order_rounding(self, order_rounding: config::OrderRounding) -> Self {
+        Self {
+            order_rounding,
+            ..self
+        }
+    }
+
+    pub fn with_position_spreads(
+        self,
+        sell_spread: Option<Spread>,
+        buy_spread: Option<Spread>,
+    ) -> Self {
+        Self {
+            sell_spread,
+            buy_spread,
+            ..self
+        }
+    }
+
+    pub fn with_levels(self, levels: Vec<config::OrderLevel>) -> Self {
+        Self { levels, ..self }
+    }
+
+    pub fn with_spread_strategy(self, spread_strategy: SpreadStrategy) -> Self {
+        Self {
+            spread_strategy,
+            ..self
+        }
+    }
+
+    pub fn with_circuit_breaker(self, circuit_breaker: CircuitBreaker) -> Self {
+        Self {
+            circuit_breaker,
+            ..self
+        }
+    }
+
+    pub fn with_volume_limits(self, volume_limits: VolumeLimits) -> Self {
+        Self {
+            volume_limits,
+            ..self
+        }
+    }
+
+    /// Replace the sales counted against the volume limit, e.g. after the
+    /// sale of a swap that was never set up was rolled back.
+    pub fn replace_sales(&mut self, sales: Vec<Sale>) {
+        self.volume_limits.replace_sales(sales);
+    }
+
+    pub fn with_taker_filter(self, taker_filter: TakerFilter) -> Self {
+        Self {
+            taker_filter,
+            ..self
+        }
+    }
+
+    pub fn with_reputation_policy(self, policy: Option<config::ReputationPolicy>) -> Self {
+        Self {
+            reputations: Reputations::new(policy),
+            ..self
+        }
+    }
+
+    pub fn with_min_balance(self, min_balance: config::MinBalance) -> Self {
+        Self {
+            min_balance,
+            ..self
+        }
+    }
+
+    pub fn with_expiries(self, expiries: Option<config::Expiries>) -> Self {
+        Self { expiries, ..self }
+    }
+
+    pub fn with_rebalance(self, rebalance: Option<config::Rebalance>) -> Self {
+        Self {
+            rebalance: RebalanceAdvisor::new(rebalance),
+            ..self
         }
     }
 
@@ -58,29 +261,117 @@ impl Maker {
         &mut self,
         mid_market_rate: MidMarketRate,
     ) -> anyhow::Result<Option<PublishOrders>> {
+        let now = Utc::now();
+        self.rate_fetched_at = now;
+        // The orders were published with a widened spread
+        let recovered = self.rate_fetch_failures > 0;
+        self.rate_fetch_failures = 0;
+
         match self.mid_market_rate {
-            Some(previous_mid_market_rate) if previous_mid_market_rate == mid_market_rate => {
+            Some(previous_mid_market_rate)
+                if previous_mid_market_rate == mid_market_rate && !recovered =>
+            {
                 Ok(None)
             }
             _ => {
                 self.mid_market_rate = Some(mid_market_rate);
+                self.circuit_breaker.record(mid_market_rate.into(), now);
+
+                if !self.is_quoting() {
+                    self.published_rate = None;
+                    return Ok(None);
+                }
+
+                let rate = mid_market_rate.into();
+                if !recovered && !self.rate_moved_enough(rate, now) {
+                    return Ok(None);
+                }
+                self.published_rate = Some((rate, now));
 
                 Ok(Some(PublishOrders {
-                    new_sell_order: self.new_sell_order()?,
-                    new_buy_order: self.new_buy_order()?,
+                    new_sell_orders: self.new_sell_orders()?,
+                    new_buy_orders: self.new_buy_orders()?,
                 }))
             }
         }
     }
 
+    /// Records a failed fetch of the rate. The last known rate is kept, with
+    /// a widened spread, unless the failover does not tolerate the failure.
+    pub fn rate_fetch_failed(&mut self, now: DateTime<Utc>) -> anyhow::Result<RateFetchFailure> {
+        self.rate_fetch_failures = self.rate_fetch_failures.saturating_add(1);
+
+        let tolerated = match (self.rate_failover, self.mid_market_rate) {
+            (Some(failover), Some(_)) => {
+                self.rate_fetch_failures < failover.max_consecutive_failures
+                    && now - self.rate_fetched_at
+                        <= circuit_breaker::seconds(failover.grace_period_secs)
+            }
+            _ => false,
+        };
+        if !tolerated {
+            self.invalidate_rate();
+            return Ok(RateFetchFailure::RateInvalidated);
+        }
+
+        // The spread is only widened upon the first failure
+        if self.rate_fetch_failures > 1 || !self.is_quoting() {
+            return Ok(RateFetchFailure::Tolerated(None));
+        }
+
+        Ok(RateFetchFailure::Tolerated(Some(PublishOrders {
+            new_sell_orders: self.new_sell_orders()?,
+            new_buy_orders: self.new_buy_orders()?,
+        })))
+    }
+
     pub fn invalidate_rate(&mut self) {
         self.mid_market_rate = None;
+        self.published_rate = None;
+    }
+
+    /// Whether the orders published upon the last rate update are to be
+    /// replaced for `rate`, as per the rate hysteresis.
+    fn rate_moved_enough(&self, rate: Rate, now: DateTime<Utc>) -> bool {
+        match (self.rate_hysteresis, self.published_rate) {
+            (Some(hysteresis), Some((published_rate, published_at))) => {
+                now - published_at >= circuit_breaker::seconds(hysteresis.min_interval_secs)
+                    && circuit_breaker::moved_more_than(
+                        published_rate,
+                        rate,
+                        hysteresis.threshold_permyriad,
+                    )
+            }
+            _ => true,
+        }
+    }
+
+    /// Invalidates the rate if it was fetched longer than the maximum age
+    /// before `now`, returns whether it was invalidated.
+    pub fn expire_stale_rate(&mut self, now: DateTime<Utc>) -> bool {
+        if self.mid_market_rate.is_some() && self.is_rate_stale(now) {
+            self.invalidate_rate();
+            return true;
+        }
+
+        false
+    }
+
+    fn is_rate_stale(&self, now: DateTime<Utc>) -> bool {
+        self.rate_max_age
+            .map_or(false, |max_age| now - self.rate_fetched_at > max_age)
+    }
+
+    /// The rate, unless it is too old to be acted upon.
+    fn fresh_mid_market_rate(&self) -> Option<MidMarketRate> {
+        self.mid_market_rate
+            .filter(|_| !self.is_rate_stale(Utc::now()))
     }
 
     pub fn update_bitcoin_balance(
         &mut self,
         balance: bitcoin::Amount,
-    ) -> anyhow::Result<Option<BtcDaiOrderForm>> {
+    ) -> anyhow::Result<Option<Vec<BtcDaiOrderForm>>> {
         // if we had a balance and the balance did not change => no new orders
         if let Some(previous_balance) = self.btc_balance {
             if previous_balance == balance {
@@ -89,8 +380,12 @@ impl Maker {
         }
 
         self.btc_balance = Some(balance);
-        let order = self.new_sell_order()?;
-        Ok(Some(order))
+        if !self.is_quoting() {
+            return Ok(None);
+        }
+
+        let orders = self.new_sell_orders()?;
+        Ok(Some(orders))
     }
 
     pub fn invalidate_bitcoin_balance(&mut self) {
@@ -100,7 +395,7 @@ impl Maker {
     pub fn update_dai_balance(
         &mut self,
         balance: dai::Amount,
-    ) -> anyhow::Result<Option<BtcDaiOrderForm>> {
+    ) -> anyhow::Result<Option<Vec<BtcDaiOrderForm>>> {
         // if we had a balance and the balance did not change => no new orders
         if let Some(previous_balance) = self.dai_balance.clone() {
             if previous_balance == balance {
@@ -109,27 +404,267 @@ impl Maker {
         }
 
         self.dai_balance = Some(balance);
-        let order = self.new_buy_order()?;
-        Ok(Some(order))
+        if !self.is_quoting() {
+            return Ok(None);
+        }
+
+        let orders = self.new_buy_orders()?;
+        Ok(Some(orders))
     }
 
     pub fn invalidate_dai_balance(&mut self) {
         self.dai_balance = None;
     }
 
+    /// Update the ether balance and the gas cost of another swap, returns the
+    /// orders to publish if the balance started or stopped covering it.
+    pub fn update_ether_balance(
+        &mut self,
+        balance: ether::Amount,
+        swap_gas_cost: ether::Amount,
+    ) -> anyhow::Result<Option<PublishOrders>> {
+        let was_lacking_gas = self.is_lacking_gas();
+
+        self.ether_balance = Some(balance);
+        self.swap_gas_cost = Some(swap_gas_cost);
+        if self.is_lacking_gas() == was_lacking_gas {
+            return Ok(None);
+        }
+
+        self.republish()
+    }
+
+    pub fn invalidate_ether_balance(&mut self) {
+        self.ether_balance = None;
+    }
+
+    /// Update the fee reserved for the Bitcoin transactions of a swap, the
+    /// funds reserved for ongoing swaps are adjusted accordingly.
+    pub fn update_btc_fee(
+        &mut self,
+        btc_fee: bitcoin::Amount,
+    ) -> anyhow::Result<Option<Vec<BtcDaiOrderForm>>> {
+        if self.btc_fee == btc_fee {
+            return Ok(None);
+        }
+
+        let reservations = self.btc_fee_reservations;
+        let previous_fees = bitcoin::Amount::from_sat(self.btc_fee.as_sat() * reservations);
+        let fees = bitcoin::Amount::from_sat(btc_fee.as_sat() * reservations);
+        self.btc_reserved_funds = self
+            .btc_reserved_funds
+            .checked_sub(previous_fees)
+            .ok_or(ReservedFundsUnderflow(Symbol::Btc))?
+            + fees;
+        self.btc_fee = btc_fee;
+
+        if !self.is_quoting() {
+            return Ok(None);
+        }
+
+        let orders = self.new_sell_orders()?;
+        Ok(Some(orders))
+    }
+
+    /// Sample the inventory at `now` for the rebalance recommendations, the
+    /// orders are to be published again if the maximum sell amounts were
+    /// adjusted as a result. `None` if no rebalance is configured or the
+    /// balances or rate are not known.
+    pub fn assess_inventory(
+        &mut self,
+        now: DateTime<Utc>,
+    ) -> anyhow::Result<Option<InventoryAssessment>> {
+        let (btc_balance, dai_balance, mid_market_rate) = match (
+            self.btc_balance,
+            &self.dai_balance,
+            self.fresh_mid_market_rate(),
+        ) {
+            (Some(btc_balance), Some(dai_balance), Some(mid_market_rate)) => {
+                (btc_balance, dai_balance.clone(), mid_market_rate)
+            }
+            _ => return Ok(None),
+        };
+
+        let (btc_max_sell, dai_max_sell) = (self.btc_max_sell_amount(), self.dai_max_sell_amount());
+        let assessment =
+            match self
+                .rebalance
+                .assess(now, btc_balance, &dai_balance, mid_market_rate.into())
+            {
+                Some(assessment) => assessment,
+                None => return Ok(None),
+            };
+
+        let publish_orders = if (btc_max_sell, dai_max_sell)
+            != (self.btc_max_sell_amount(), self.dai_max_sell_amount())
+        {
+            self.republish()?
+        } else {
+            None
+        };
+
+        Ok(Some(InventoryAssessment {
+            assessment,
+            publish_orders,
+        }))
+    }
+
+    /// The asset depleted as of the last inventory assessment.
+    pub fn depleted_asset(&self) -> Option<Symbol> {
+        self.rebalance.depleted()
+    }
+
+    /// Stop publishing orders and accepting takes, ongoing swaps are not
+    /// affected.
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    /// Pause for the maintenance of a node, e.g. to upgrade it without
+    /// killing the ongoing swaps. Ends upon `resume`.
+    pub fn enter_maintenance(&mut self) {
+        self.paused = true;
+        self.maintenance = true;
+    }
+
+    /// Resume trading, returns the orders to publish given the current
+    /// state, none while the circuit breaker is tripped.
+    pub fn resume(&mut self) -> anyhow::Result<Option<PublishOrders>> {
+        self.paused = false;
+        self.maintenance = false;
+
+        self.republish()
+    }
+
+    /// The orders to publish again given the current state, none while
+    /// paused.
+    pub fn republish(&self) -> anyhow::Result<Option<PublishOrders>> {
+        if !self.is_quoting() {
+            return Ok(None);
+        }
+
+        Ok(Some(PublishOrders {
+            new_sell_orders: self.new_sell_orders()?,
+            new_buy_orders: self.new_buy_orders()?,
+        }))
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn is_in_maintenance(&self) -> bool {
+        self.maintenance
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.circuit_breaker.is_tripped()
+    }
+
+    /// Stop publishing orders until connected to a peer again.
+    pub fn isolate(&mut self) {
+        self.isolated = true;
+    }
+
+    /// Returns the orders to publish again given the current state.
+    pub fn end_isolation(&mut self) -> anyhow::Result<Option<PublishOrders>> {
+        self.isolated = false;
+
+        self.republish()
+    }
+
+    fn is_quoting(&self) -> bool {
+        !self.paused && !self.is_halted() && !self.isolated
+    }
+
+    /// Resets the circuit breaker once the rate has been calm for the
+    /// cool-down, returns the orders to publish then unless paused.
+    pub fn end_cool_down(&mut self, now: DateTime<Utc>) -> anyhow::Result<Option<PublishOrders>> {
+        if !self.circuit_breaker.reset_if_calm(now) {
+            return Ok(None);
+        }
+
+        self.republish()
+    }
+
+    pub fn btc_balance(&self) -> Option<bitcoin::Amount> {
+        self.btc_balance
+    }
+
+    pub fn dai_balance(&self) -> Option<dai::Amount> {
+        self.dai_balance.clone()
+    }
+
+    pub fn ether_balance(&self) -> Option<ether::Amount> {
+        self.ether_balance.clone()
+    }
+
+    pub fn mid_market_rate(&self) -> Option<MidMarketRate> {
+        self.mid_market_rate
+    }
+
+    /// Whether the bitcoin balance is known to be below the minimum, sell
+    /// orders are withdrawn then.
+    pub fn is_btc_balance_below_minimum(&self) -> bool {
+        match (self.btc_balance, self.min_balance.bitcoin) {
+            (Some(balance), Some(minimum)) => balance < minimum,
+            _ => false,
+        }
+    }
+
+    /// Whether the dai balance is known to be below the minimum, buy orders
+    /// are withdrawn then.
+    pub fn is_dai_balance_below_minimum(&self) -> bool {
+        match (&self.dai_balance, &self.min_balance.dai) {
+            (Some(balance), Some(minimum)) => balance < minimum,
+            _ => false,
+        }
+    }
+
+    /// Whether the ether balance is known not to cover the gas of another
+    /// swap, orders are withdrawn then as both positions need gas.
+    pub fn is_lacking_gas(&self) -> bool {
+        match (&self.ether_balance, &self.swap_gas_cost) {
+            (Some(balance), Some(cost)) => {
+                Uint256::from(balance.clone()) < Uint256::from(cost.clone())
+            }
+            _ => false,
+        }
+    }
+
+    /// The swap protocol of our orders, the expiry offsets of the HTLCs
+    /// published along with them are the ones agreed on when they are taken.
     pub fn swap_protocol(&self, position: Position) -> SwapProtocol {
-        SwapProtocol::new(self.role, position)
+        let expiries = match self.expiries {
+            Some(expiries) => expiries,
+            None => return SwapProtocol::new(self.role, position),
+        };
+        let alpha_offset = time::Duration::minutes(i64::from(expiries.alpha_offset_mins));
+        let beta_offset = time::Duration::minutes(i64::from(expiries.beta_offset_mins));
+
+        match SwapProtocol::new(self.role, position) {
+            SwapProtocol::HbitHerc20 { .. } => SwapProtocol::HbitHerc20 {
+                hbit_expiry_offset: alpha_offset.into(),
+                herc20_expiry_offset: beta_offset.into(),
+            },
+            SwapProtocol::Herc20Hbit { .. } => SwapProtocol::Herc20Hbit {
+                herc20_expiry_offset: alpha_offset.into(),
+                hbit_expiry_offset: beta_offset.into(),
+            },
+        }
     }
 
     pub fn new_sell_order(&self) -> anyhow::Result<BtcDaiOrderForm> {
-        match (self.mid_market_rate, self.btc_balance) {
+        match (self.fresh_mid_market_rate(), self.btc_balance) {
             (Some(mid_market_rate), Some(btc_balance)) => BtcDaiOrderForm::new_sell(
                 btc_balance,
                 self.btc_fee,
                 self.btc_reserved_funds,
-                self.btc_max_sell_amount,
+                self.btc_max_sell_amount(),
+                self.min_sell.bitcoin,
+                self.order_rounding,
                 mid_market_rate.into(),
-                self.spread,
+                self.strategy_spread(self.position_spread(Position::Sell), Position::Sell),
             ),
             (None, _) => anyhow::bail!(RateNotAvailable(Position::Sell)),
             (_, None) => anyhow::bail!(BalanceNotAvailable(Symbol::Btc)),
@@ -137,19 +672,204 @@ impl Maker {
     }
 
     pub fn new_buy_order(&self) -> anyhow::Result<BtcDaiOrderForm> {
-        match (self.mid_market_rate, self.dai_balance.clone()) {
+        match (self.fresh_mid_market_rate(), self.dai_balance.clone()) {
             (Some(mid_market_rate), Some(dai_balance)) => BtcDaiOrderForm::new_buy(
                 dai_balance,
                 self.dai_reserved_funds.clone(),
-                self.dai_max_sell_amount.clone(),
+                self.dai_max_sell_amount(),
+                self.min_sell.dai.clone(),
+                self.order_rounding,
                 mid_market_rate.into(),
-                self.spread,
+                self.strategy_spread(self.position_spread(Position::Buy), Position::Buy),
             ),
             (None, _) => anyhow::bail!(RateNotAvailable(Position::Buy)),
             (_, None) => anyhow::bail!(BalanceNotAvailable(Symbol::Dai)),
         }
     }
 
+    /// The ladder of orders to publish for the Sell position, a single order
+    /// if no levels are configured. Levels the funds do not cover are left
+    /// out, all of them while the bitcoin balance is below the minimum or
+    /// while lacking gas.
+    pub fn new_sell_orders(&self) -> anyhow::Result<Vec<BtcDaiOrderForm>> {
+        if self.is_btc_balance_below_minimum() || self.is_lacking_gas() {
+            return Ok(Vec::new());
+        }
+
+        if self.levels.is_empty() {
+            return Ok(vec![self.new_sell_order()?]);
+        }
+
+        let (mid_market_rate, btc_balance) = match (self.fresh_mid_market_rate(), self.btc_balance)
+        {
+            (Some(mid_market_rate), Some(btc_balance)) => (mid_market_rate, btc_balance),
+            (None, _) => anyhow::bail!(RateNotAvailable(Position::Sell)),
+            (_, None) => anyhow::bail!(BalanceNotAvailable(Symbol::Btc)),
+        };
+
+        // Each order is funded as if the tighter ones were taken
+        let mut reserved_funds = self.btc_reserved_funds;
+        let mut orders = Vec::new();
+        for level in &self.levels {
+            let spread = self.strategy_spread(level.spread, Position::Sell);
+            let max_amount = min_limit(
+                level.bitcoin.map(|amount| amount + self.btc_fee),
+                self.btc_max_sell_amount(),
+            );
+
+            match BtcDaiOrderForm::new_sell(
+                btc_balance,
+                self.btc_fee,
+                reserved_funds,
+                max_amount,
+                self.min_sell.bitcoin,
+                self.order_rounding,
+                mid_market_rate.into(),
+                spread,
+            ) {
+                Ok(order) => {
+                    reserved_funds =
+                        reserved_funds + bitcoin::Amount::from(order.quantity) + self.btc_fee;
+                    orders.push(order);
+                }
+                Err(_) if !orders.is_empty() => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(orders)
+    }
+
+    /// The ladder of orders to publish for the Buy position, a single order
+    /// if no levels are configured. Levels the funds do not cover are left
+    /// out, all of them while the dai balance is below the minimum or while
+    /// lacking gas.
+    pub fn new_buy_orders(&self) -> anyhow::Result<Vec<BtcDaiOrderForm>> {
+        if self.is_dai_balance_below_minimum() || self.is_lacking_gas() {
+            return Ok(Vec::new());
+        }
+
+        if self.levels.is_empty() {
+            return Ok(vec![self.new_buy_order()?]);
+        }
+
+        let (mid_market_rate, dai_balance) =
+            match (self.fresh_mid_market_rate(), self.dai_balance.clone()) {
+                (Some(mid_market_rate), Some(dai_balance)) => (mid_market_rate, dai_balance),
+                (None, _) => anyhow::bail!(RateNotAvailable(Position::Buy)),
+                (_, None) => anyhow::bail!(BalanceNotAvailable(Symbol::Dai)),
+            };
+
+        let mut reserved_funds = self.dai_reserved_funds.clone();
+        let mut orders = Vec::new();
+        for level in &self.levels {
+            let spread = self.strategy_spread(level.spread, Position::Buy);
+            let rate = spread.apply(mid_market_rate.into(), Position::Buy)?;
+            let max_amount = min_limit(
+                level.bitcoin.map(|amount| amount.worth_in(rate)),
+                self.dai_max_sell_amount(),
+            );
+
+            match BtcDaiOrderForm::new_buy(
+                dai_balance.clone(),
+                reserved_funds.clone(),
+                max_amount,
+                self.min_sell.dai.clone(),
+                self.order_rounding,
+                mid_market_rate.into(),
+                spread,
+            ) {
+                Ok(order) => {
+                    reserved_funds = reserved_funds + dai::Amount::from(order.quote());
+                    orders.push(order);
+                }
+                Err(_) if !orders.is_empty() => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(orders)
+    }
+
+    /// The spread a taken order must be as profitable as: that of the
+    /// tightest level it fits in, the widest one if it fits in none.
+    fn spread_for(&self, order: &BtcDaiOrderForm) -> Spread {
+        let quantity = bitcoin::Amount::from(order.quantity);
+
+        let spread = self
+            .levels
+            .iter()
+            .filter(|level| level.bitcoin.map_or(true, |amount| quantity <= amount))
+            .map(|level| level.spread)
+            .min_by_key(|spread| spread.permyriad())
+            .or_else(|| {
+                self.levels
+                    .iter()
+                    .map(|level| level.spread)
+                    .max_by_key(|spread| spread.permyriad())
+            })
+            .unwrap_or_else(|| self.position_spread(order.position));
+
+        self.strategy_spread(spread, order.position)
+    }
+
+    /// The maximum amount of bitcoin to sell per order, scaled down while
+    /// bitcoin is depleted if so configured.
+    fn btc_max_sell_amount(&self) -> Option<bitcoin::Amount> {
+        let rebalanced = self
+            .btc_balance
+            .and_then(|balance| self.rebalance.max_sell_bitcoin(balance));
+
+        min_limit(self.btc_max_sell_amount, rebalanced)
+    }
+
+    /// The maximum amount of dai to sell per order, scaled down while dai is
+    /// depleted if so configured.
+    fn dai_max_sell_amount(&self) -> Option<dai::Amount> {
+        let rebalanced = self
+            .dai_balance
+            .as_ref()
+            .and_then(|balance| self.rebalance.max_sell_dai(balance));
+
+        min_limit(self.dai_max_sell_amount.clone(), rebalanced)
+    }
+
+    /// The spread of the single order published for `position`.
+    fn position_spread(&self, position: Position) -> Spread {
+        match position {
+            Position::Sell => self.sell_spread,
+            Position::Buy => self.buy_spread,
+        }
+        .unwrap_or(self.spread)
+    }
+
+    /// The configured spread as adjusted by the spread strategy for the
+    /// current balances.
+    fn strategy_spread(&self, spread: Spread, position: Position) -> Spread {
+        let spread = match (self.mid_market_rate, self.btc_balance, &self.dai_balance) {
+            (Some(mid_market_rate), Some(btc_balance), Some(dai_balance)) => {
+                self.spread_strategy.spread(
+                    spread,
+                    position,
+                    btc_balance,
+                    dai_balance,
+                    mid_market_rate.into(),
+                )
+            }
+            _ => spread,
+        };
+
+        match self.rate_failover {
+            Some(failover) if self.rate_fetch_failures > 0 => {
+                let widened = spread
+                    .permyriad()
+                    .saturating_add(failover.spread_widening_permyriad);
+                Spread::new(widened.min(10_000)).expect("spread of at most 100%")
+            }
+            _ => spread,
+        }
+    }
+
     pub fn new_order(&self, position: Position) -> anyhow::Result<BtcDaiOrderForm> {
         match position {
             Position::Buy => self.new_buy_order(),
@@ -162,18 +882,52 @@ impl Maker {
     /// Re & take & reserve
     pub fn process_taken_order(
         &mut self,
+        taker: &PeerId,
         order: BtcDaiOrderForm,
     ) -> anyhow::Result<TakeRequestDecision> {
-        match self.mid_market_rate {
+        if self.maintenance {
+            return Ok(TakeRequestDecision::Maintenance);
+        }
+
+        if self.paused {
+            return Ok(TakeRequestDecision::Paused);
+        }
+
+        if self.is_halted() {
+            return Ok(TakeRequestDecision::Halted);
+        }
+
+        if !self.taker_filter.can_trade_with(taker) {
+            return Ok(TakeRequestDecision::CannotTradeWithTaker);
+        }
+
+        if let Some(decision) = self.reputations.decline(taker, &order) {
+            return Ok(decision);
+        }
+
+        match self.fresh_mid_market_rate() {
             Some(current_mid_market_rate) => {
                 let current_profitable_rate = self
-                    .spread
+                    .spread_for(&order)
                     .apply(current_mid_market_rate.into(), order.position)?;
 
                 if !order.is_as_profitable_as(current_profitable_rate)? {
                     return Ok(TakeRequestDecision::RateNotProfitable);
                 }
 
+                let sale = Sale::of(&order, Utc::now());
+                if !self.volume_limits.allow(&sale) {
+                    return Ok(TakeRequestDecision::VolumeLimitReached);
+                }
+
+                let below_minimum = match order.position {
+                    Position::Buy => self.is_dai_balance_below_minimum(),
+                    Position::Sell => self.is_btc_balance_below_minimum(),
+                };
+                if below_minimum || self.is_lacking_gas() {
+                    return Ok(TakeRequestDecision::InsufficientFunds);
+                }
+
                 match order.position {
                     Position::Buy => match self.dai_balance {
                         Some(ref dai_balance) => {
@@ -197,10 +951,12 @@ impl Maker {
                             }
 
                             self.btc_reserved_funds = updated_btc_reserved_funds;
+                            self.btc_fee_reservations += 1;
                         }
                         None => anyhow::bail!(BalanceNotAvailable(Symbol::Btc)),
                     },
                 };
+                self.volume_limits.record(sale);
 
                 Ok(TakeRequestDecision::GoForSwap)
             }
@@ -208,14 +964,46 @@ impl Maker {
         }
     }
 
-    pub fn free_funds(&mut self, dai: Option<dai::Amount>, bitcoin: Option<bitcoin::Amount>) {
-        if let Some(amount) = dai {
-            self.dai_reserved_funds = self.dai_reserved_funds.clone() - amount;
-        }
+    pub fn update_reputation(&mut self, taker: PeerId, reputation: Reputation) {
+        self.reputations.update(taker, reputation);
+    }
+
+    /// Reserve the funds of a swap we are the Bitcoin funder of, including
+    /// the fee.
+    pub fn reserve_btc(&mut self, amount: bitcoin::Amount) {
+        self.btc_reserved_funds = self.btc_reserved_funds + amount + self.btc_fee;
+        self.btc_fee_reservations += 1;
+    }
+
+    /// Free the funds reserved for a finished swap. Nothing is freed if it
+    /// would be more than the reserved funds.
+    pub fn free_funds(
+        &mut self,
+        dai: Option<dai::Amount>,
+        bitcoin: Option<bitcoin::Amount>,
+    ) -> anyhow::Result<()> {
+        let dai_reserved_funds = match dai {
+            Some(amount) => self
+                .dai_reserved_funds
+                .checked_sub(&amount)
+                .ok_or(ReservedFundsUnderflow(Symbol::Dai))?,
+            None => self.dai_reserved_funds.clone(),
+        };
+        let btc_reserved_funds = match bitcoin {
+            Some(amount) => amount
+                .checked_add(self.btc_fee)
+                .and_then(|freed| self.btc_reserved_funds.checked_sub(freed))
+                .ok_or(ReservedFundsUnderflow(Symbol::Btc))?,
+            None => self.btc_reserved_funds,
+        };
 
-        if let Some(amount) = bitcoin {
-            self.btc_reserved_funds = self.btc_reserved_funds - (amount + self.btc_fee);
+        self.dai_reserved_funds = dai_reserved_funds;
+        self.btc_reserved_funds = btc_reserved_funds;
+        if bitcoin.is_some() {
+            self.btc_fee_reservations = self.btc_fee_reservations.saturating_sub(1);
         }
+
+        Ok(())
     }
 }
 
@@ -224,12 +1012,74 @@ pub enum TakeRequestDecision {
     GoForSwap,
     RateNotProfitable,
     InsufficientFunds,
+    Paused,
+    Maintenance,
+    CannotTradeWithTaker,
+    PoorReputation,
+    FirstTradeTooLarge,
+    VolumeLimitReached,
+    Halted,
+}
+
+/// The takers we refuse to trade with and, if any, the only takers we trade
+/// with. A banned taker is refused even if also allowed.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TakerFilter {
+    banned: HashSet<PeerId>,
+    allowed: HashSet<PeerId>,
+}
+
+impl TakerFilter {
+    /// Combines the takers of the configuration file with those listed in the
+    /// database.
+    pub fn new(takers: &config::Takers, listings: Vec<(PeerId, TakerListing)>) -> Self {
+        let mut filter = TakerFilter {
+            banned: takers.banned.iter().cloned().collect(),
+            allowed: takers.allowed.iter().cloned().collect(),
+        };
+
+        for (peer_id, listing) in listings {
+            match listing {
+                TakerListing::Banned => filter.banned.insert(peer_id),
+                TakerListing::Allowed => filter.allowed.insert(peer_id),
+            };
+        }
+
+        filter
+    }
+
+    fn can_trade_with(&self, taker: &PeerId) -> bool {
+        !self.banned.contains(taker) && (self.allowed.is_empty() || self.allowed.contains(taker))
+    }
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct PublishOrders {
-    pub new_sell_order: BtcDaiOrderForm,
-    pub new_buy_order: BtcDaiOrderForm,
+    pub new_sell_orders: Vec<BtcDaiOrderForm>,
+    pub new_buy_orders: Vec<BtcDaiOrderForm>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InventoryAssessment {
+    pub assessment: Assessment,
+    /// The maximum sell amounts were adjusted, the orders are to be replaced.
+    pub publish_orders: Option<PublishOrders>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum RateFetchFailure {
+    /// The last known rate is kept, with the orders to publish at the
+    /// widened spread upon the first failure.
+    Tolerated(Option<PublishOrders>),
+    RateInvalidated,
+}
+
+/// The lower of two limits, `None` being unbounded.
+fn min_limit<T: Ord>(lhs: Option<T>, rhs: Option<T>) -> Option<T> {
+    match (lhs, rhs) {
+        (Some(lhs), Some(rhs)) => Some(min(lhs, rhs)),
+        (lhs, rhs) => lhs.or(rhs),
+    }
 }
 
 #[derive(Debug, Copy, Clone, thiserror::Error)]
@@ -240,6 +1090,10 @@ pub struct RateNotAvailable(Position);
 #[error("{0} balance not available.")]
 pub struct BalanceNotAvailable(Symbol);
 
+#[derive(Debug, Copy, Clone, thiserror::Error)]
+#[error("Freeing more {0} than reserved.")]
+pub struct ReservedFundsUnderflow(Symbol);
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -258,16 +1112,41 @@ mod tests {
             Self {
                 btc_balance: Some(bitcoin::Amount::default()),
                 dai_balance: Some(dai::Amount::default()),
+                ether_balance: None,
+                swap_gas_cost: None,
                 btc_fee: bitcoin::Amount::default(),
                 btc_reserved_funds: bitcoin::Amount::default(),
                 dai_reserved_funds: dai::Amount::default(),
+                btc_fee_reservations: 0,
                 btc_max_sell_amount: None,
                 dai_max_sell_amount: None,
+                min_sell: config::MinSell::default(),
+                order_rounding: config::OrderRounding::default(),
                 mid_market_rate: Some(MidMarketRate::static_stub()),
+                rate_fetched_at: Utc::now(),
+                rate_max_age: None,
+                rate_failover: None,
+                rate_fetch_failures: 0,
                 spread: Spread::default(),
+                sell_spread: None,
+                buy_spread: None,
+                levels: Vec::new(),
+                spread_strategy: SpreadStrategy::default(),
                 bitcoin_network: bitcoin::Network::Bitcoin,
                 ethereum_chain: ethereum::Chain::static_stub(),
                 role: Role::Bob,
+                paused: false,
+                maintenance: false,
+                isolated: false,
+                circuit_breaker: CircuitBreaker::default(),
+                taker_filter: TakerFilter::default(),
+                reputations: Reputations::default(),
+                volume_limits: VolumeLimits::default(),
+                min_balance: config::MinBalance::default(),
+                expiries: None,
+                rate_hysteresis: None,
+                published_rate: None,
+                rebalance: RebalanceAdvisor::default(),
             }
         }
     }
@@ -290,7 +1169,9 @@ mod tests {
 
         let taken_order = btc_dai_order_form(Position::Sell, btc(1.5), rate(0.0));
 
-        let event = maker.process_taken_order(taken_order).unwrap();
+        let event = maker
+            .process_taken_order(&PeerId::random(), taken_order)
+            .unwrap();
 
         assert_eq!(event, TakeRequestDecision::GoForSwap);
         assert_eq!(maker.btc_reserved_funds, btc(1.5))
@@ -306,7 +1187,9 @@ mod tests {
 
         let taken_order = btc_dai_order_form(Position::Sell, btc(1.5), rate(0.0));
 
-        let event = maker.process_taken_order(taken_order).unwrap();
+        let event = maker
+            .process_taken_order(&PeerId::random(), taken_order)
+            .unwrap();
 
         assert_eq!(event, TakeRequestDecision::GoForSwap);
         assert_eq!(maker.btc_reserved_funds, btc(2.5))
@@ -321,7 +1204,9 @@ mod tests {
 
         let taken_order = btc_dai_order_form(Position::Buy, btc(1.0), rate(1.5));
 
-        let result = maker.process_taken_order(taken_order).unwrap();
+        let result = maker
+            .process_taken_order(&PeerId::random(), taken_order)
+            .unwrap();
 
         assert_eq!(result, TakeRequestDecision::GoForSwap);
         assert_eq!(maker.dai_reserved_funds, dai(1.5))
@@ -337,7 +1222,9 @@ mod tests {
 
         let taken_order = btc_dai_order_form(Position::Buy, btc(1.0), rate(1.5));
 
-        let result = maker.process_taken_order(taken_order).unwrap();
+        let result = maker
+            .process_taken_order(&PeerId::random(), taken_order)
+            .unwrap();
 
         assert_eq!(result, TakeRequestDecision::GoForSwap);
         assert_eq!(maker.dai_reserved_funds, dai(1.5))
@@ -352,7 +1239,9 @@ mod tests {
 
         let taken_order = btc_dai_order_form(Position::Sell, btc(1.5), rate(0.0));
 
-        let result = maker.process_taken_order(taken_order).unwrap();
+        let result = maker
+            .process_taken_order(&PeerId::random(), taken_order)
+            .unwrap();
 
         assert_eq!(result, TakeRequestDecision::InsufficientFunds);
     }
@@ -367,7 +1256,9 @@ mod tests {
 
         let taken_order = btc_dai_order_form(Position::Buy, btc(1.0), rate(1.5));
 
-        let result = maker.process_taken_order(taken_order).unwrap();
+        let result = maker
+            .process_taken_order(&PeerId::random(), taken_order)
+            .unwrap();
 
         assert_eq!(result, TakeRequestDecision::InsufficientFunds);
     }
@@ -382,7 +1273,9 @@ mod tests {
 
         let taken_order = btc_dai_order_form(Position::Sell, btc(1.0), rate(0.0));
 
-        let result = maker.process_taken_order(taken_order).unwrap();
+        let result = maker
+            .process_taken_order(&PeerId::random(), taken_order)
+            .unwrap();
 
         assert_eq!(result, TakeRequestDecision::InsufficientFunds);
     }
@@ -398,7 +1291,7 @@ mod tests {
             ..StaticStub::static_stub()
         };
 
-        let result = maker.process_taken_order(taken_order);
+        let result = maker.process_taken_order(&PeerId::random(), taken_order);
         assert!(result.is_err());
 
         let result = maker.new_buy_order();
@@ -417,7 +1310,9 @@ mod tests {
 
         let taken_order = btc_dai_order_form(Position::Sell, btc(1.0), rate(9000.0));
 
-        let result = maker.process_taken_order(taken_order).unwrap();
+        let result = maker
+            .process_taken_order(&PeerId::random(), taken_order)
+            .unwrap();
 
         assert_eq!(result, TakeRequestDecision::RateNotProfitable);
     }
@@ -431,7 +1326,9 @@ mod tests {
 
         let taken_order = btc_dai_order_form(Position::Buy, btc(1.0), rate(11000.0));
 
-        let result = maker.process_taken_order(taken_order).unwrap();
+        let result = maker
+            .process_taken_order(&PeerId::random(), taken_order)
+            .unwrap();
 
         assert_eq!(result, TakeRequestDecision::RateNotProfitable);
     }
@@ -468,7 +1365,475 @@ mod tests {
     }
 
     #[test]
-    fn free_funds_when_processing_finished_swap() {
+    fn orders_replaced_only_once_the_rate_moved_past_the_hysteresis() {
+        let mut maker = Maker {
+            btc_balance: some_btc(10.0),
+            dai_balance: some_dai(10000.0),
+            mid_market_rate: some_rate(1000.0),
+            ..StaticStub::static_stub()
+        }
+        .with_rate_hysteresis(Some(config::RateHysteresis {
+            threshold_permyriad: 100,
+            min_interval_secs: 0,
+        }));
+
+        let orders = maker.update_rate(MidMarketRate::new(rate(1001.0))).unwrap();
+        assert!(orders.is_some(), "nothing published upon a rate update yet");
+
+        let orders = maker.update_rate(MidMarketRate::new(rate(1005.0))).unwrap();
+        assert!(orders.is_none());
+        assert_eq!(maker.mid_market_rate, some_rate(1005.0));
+
+        let orders = maker.update_rate(MidMarketRate::new(rate(1020.0))).unwrap();
+        assert!(orders.is_some());
+    }
+
+    #[test]
+    fn orders_not_replaced_before_the_hysteresis_interval() {
+        let mut maker = Maker {
+            btc_balance: some_btc(10.0),
+            dai_balance: some_dai(10000.0),
+            mid_market_rate: some_rate(1000.0),
+            ..StaticStub::static_stub()
+        }
+        .with_rate_hysteresis(Some(config::RateHysteresis {
+            threshold_permyriad: 100,
+            min_interval_secs: 60,
+        }));
+        maker.published_rate = Some((rate(1000.0), Utc::now()));
+
+        let orders = maker.update_rate(MidMarketRate::new(rate(1100.0))).unwrap();
+        assert!(orders.is_none());
+
+        maker.published_rate = Some((rate(1000.0), Utc::now() - Duration::seconds(61)));
+        let orders = maker.update_rate(MidMarketRate::new(rate(1200.0))).unwrap();
+        assert!(orders.is_some());
+    }
+
+    #[test]
+    fn no_orders_published_while_paused() {
+        let mut maker = Maker {
+            btc_balance: some_btc(10.0),
+            dai_balance: some_dai(10.0),
+            mid_market_rate: some_rate(1.0),
+            ..StaticStub::static_stub()
+        };
+        maker.pause();
+
+        let new_mid_market_rate = MidMarketRate::new(Rate::try_from(2.0).unwrap());
+
+        assert!(maker.update_rate(new_mid_market_rate).unwrap().is_none());
+        assert!(maker.update_bitcoin_balance(btc(5.0)).unwrap().is_none());
+        assert!(maker.update_dai_balance(dai(5.0)).unwrap().is_none());
+        assert_eq!(maker.mid_market_rate, Some(new_mid_market_rate));
+        assert_eq!(maker.btc_balance, some_btc(5.0));
+        assert_eq!(maker.dai_balance, some_dai(5.0));
+        assert!(maker.republish().unwrap().is_none());
+    }
+
+    #[test]
+    fn republished_orders_match_the_current_state() {
+        let maker = Maker {
+            btc_balance: some_btc(3.0),
+            dai_balance: some_dai(1000.0),
+            mid_market_rate: some_rate(1.0),
+            ..StaticStub::static_stub()
+        };
+
+        let orders = maker.republish().unwrap().unwrap();
+
+        assert_eq!(orders.new_sell_orders, vec![maker
+            .new_sell_order()
+            .unwrap()]);
+        assert_eq!(orders.new_buy_orders, vec![maker.new_buy_order().unwrap()]);
+    }
+
+    #[test]
+    fn takes_declined_while_paused_and_accepted_once_resumed() {
+        let mut maker = Maker {
+            btc_balance: some_btc(3.0),
+            btc_fee: bitcoin::Amount::ZERO,
+            ..StaticStub::static_stub()
+        };
+        maker.pause();
+
+        let taken_order = btc_dai_order_form(Position::Sell, btc(1.5), rate(0.0));
+
+        let event = maker
+            .process_taken_order(&PeerId::random(), taken_order.clone())
+            .unwrap();
+        assert_eq!(event, TakeRequestDecision::Paused);
+        assert_eq!(maker.btc_reserved_funds, bitcoin::Amount::ZERO);
+
+        let orders = maker.resume().unwrap().unwrap();
+        assert_eq!(orders.new_sell_orders, vec![maker
+            .new_sell_order()
+            .unwrap()]);
+
+        let event = maker
+            .process_taken_order(&PeerId::random(), taken_order)
+            .unwrap();
+        assert_eq!(event, TakeRequestDecision::GoForSwap);
+    }
+
+    #[test]
+    fn no_orders_are_published_while_isolated() {
+        let mut maker = Maker {
+            btc_balance: some_btc(3.0),
+            ..StaticStub::static_stub()
+        };
+        maker.isolate();
+
+        assert!(maker.republish().unwrap().is_none());
+        assert!(maker.end_isolation().unwrap().is_some());
+    }
+
+    #[test]
+    fn takes_declined_during_maintenance_until_resumed() {
+        let mut maker = Maker {
+            btc_balance: some_btc(3.0),
+            btc_fee: bitcoin::Amount::ZERO,
+            ..StaticStub::static_stub()
+        };
+        maker.enter_maintenance();
+        assert!(maker.is_paused());
+
+        let taken_order = btc_dai_order_form(Position::Sell, btc(1.5), rate(0.0));
+
+        let event = maker
+            .process_taken_order(&PeerId::random(), taken_order.clone())
+            .unwrap();
+        assert_eq!(event, TakeRequestDecision::Maintenance);
+
+        let _ = maker.resume().unwrap();
+        assert!(!maker.is_in_maintenance());
+
+        let event = maker
+            .process_taken_order(&PeerId::random(), taken_order)
+            .unwrap();
+        assert_eq!(event, TakeRequestDecision::GoForSwap);
+    }
+
+    #[test]
+    fn banned_taker_is_refused() {
+        let banned = PeerId::random();
+        let mut maker = Maker {
+            btc_balance: some_btc(3.0),
+            taker_filter: TakerFilter::new(
+                &config::Takers {
+                    banned: vec![banned.clone()],
+                    allowed: vec![],
+                },
+                vec![],
+            ),
+            ..StaticStub::static_stub()
+        };
+
+        let taken_order = btc_dai_order_form(Position::Sell, btc(1.5), rate(0.0));
+
+        let event = maker
+            .process_taken_order(&banned, taken_order.clone())
+            .unwrap();
+        assert_eq!(event, TakeRequestDecision::CannotTradeWithTaker);
+        assert_eq!(maker.btc_reserved_funds, bitcoin::Amount::ZERO);
+
+        let event = maker
+            .process_taken_order(&PeerId::random(), taken_order)
+            .unwrap();
+        assert_eq!(event, TakeRequestDecision::GoForSwap);
+    }
+
+    #[test]
+    fn only_allowed_takers_are_accepted_once_any_is_allowed() {
+        let allowed = PeerId::random();
+        let mut maker = Maker {
+            btc_balance: some_btc(3.0),
+            taker_filter: TakerFilter::new(&config::Takers::default(), vec![(
+                allowed.clone(),
+                TakerListing::Allowed,
+            )]),
+            ..StaticStub::static_stub()
+        };
+
+        let taken_order = btc_dai_order_form(Position::Sell, btc(1.5), rate(0.0));
+
+        let event = maker
+            .process_taken_order(&PeerId::random(), taken_order.clone())
+            .unwrap();
+        assert_eq!(event, TakeRequestDecision::CannotTradeWithTaker);
+
+        let event = maker.process_taken_order(&allowed, taken_order).unwrap();
+        assert_eq!(event, TakeRequestDecision::GoForSwap);
+    }
+
+    #[test]
+    fn taker_with_repeated_aborts_is_refused() {
+        let taker = PeerId::random();
+        let mut maker = Maker {
+            btc_balance: some_btc(3.0),
+            reputations: Reputations::new(Some(config::ReputationPolicy {
+                first_trade_max_bitcoin: None,
+                max_aborts: Some(1),
+                max_refunds_caused: None,
+            })),
+            ..StaticStub::static_stub()
+        };
+        let taken_order = btc_dai_order_form(Position::Sell, btc(1.5), rate(0.0));
+
+        maker.update_reputation(taker.clone(), Reputation {
+            aborted: 1,
+            ..Reputation::default()
+        });
+        let event = maker
+            .process_taken_order(&taker, taken_order.clone())
+            .unwrap();
+        assert_eq!(event, TakeRequestDecision::GoForSwap);
+
+        maker.update_reputation(taker.clone(), Reputation {
+            aborted: 2,
+            ..Reputation::default()
+        });
+        let event = maker.process_taken_order(&taker, taken_order).unwrap();
+        assert_eq!(event, TakeRequestDecision::PoorReputation);
+    }
+
+    #[test]
+    fn first_trade_of_unknown_taker_is_capped() {
+        let taker = PeerId::random();
+        let mut maker = Maker {
+            btc_balance: some_btc(3.0),
+            reputations: Reputations::new(Some(config::ReputationPolicy {
+                first_trade_max_bitcoin: Some(btc(1.0)),
+                max_aborts: None,
+                max_refunds_caused: None,
+            })),
+            ..StaticStub::static_stub()
+        };
+        let taken_order = btc_dai_order_form(Position::Sell, btc(1.5), rate(0.0));
+
+        let event = maker
+            .process_taken_order(&taker, taken_order.clone())
+            .unwrap();
+        assert_eq!(event, TakeRequestDecision::FirstTradeTooLarge);
+
+        maker.update_reputation(taker.clone(), Reputation {
+            completed: 1,
+            ..Reputation::default()
+        });
+        let event = maker.process_taken_order(&taker, taken_order).unwrap();
+        assert_eq!(event, TakeRequestDecision::GoForSwap);
+    }
+
+    #[test]
+    fn stale_rate_is_not_acted_upon() {
+        let mut maker = Maker {
+            btc_balance: some_btc(3.0),
+            rate_fetched_at: Utc::now() - Duration::seconds(120),
+            rate_max_age: Some(Duration::seconds(60)),
+            ..StaticStub::static_stub()
+        };
+
+        let taken_order = btc_dai_order_form(Position::Sell, btc(1.5), rate(0.0));
+
+        assert!(maker.new_sell_order().is_err());
+        assert!(maker
+            .process_taken_order(&PeerId::random(), taken_order)
+            .is_err());
+        assert!(maker.expire_stale_rate(Utc::now()));
+        assert!(maker.mid_market_rate().is_none());
+        assert!(!maker.expire_stale_rate(Utc::now()));
+    }
+
+    #[test]
+    fn rate_update_refreshes_the_rate_age() {
+        let mut maker = Maker {
+            btc_balance: some_btc(3.0),
+            rate_fetched_at: Utc::now() - Duration::seconds(120),
+            rate_max_age: Some(Duration::seconds(60)),
+            ..StaticStub::static_stub()
+        };
+
+        let rate = maker.mid_market_rate().unwrap();
+        let _ = maker.update_rate(rate).unwrap();
+
+        assert!(!maker.expire_stale_rate(Utc::now()));
+        assert!(maker.new_sell_order().is_ok());
+    }
+
+    #[test]
+    fn failed_rate_fetch_within_failover_keeps_the_rate_with_widened_spread() {
+        let mut maker = Maker {
+            btc_balance: some_btc(10.0),
+            dai_balance: some_dai(10.0),
+            mid_market_rate: some_rate(1.0),
+            rate_failover: Some(config::RateFailover {
+                grace_period_secs: 45,
+                max_consecutive_failures: 2,
+                spread_widening_permyriad: 50,
+            }),
+            ..StaticStub::static_stub()
+        };
+
+        let failure = maker.rate_fetch_failed(Utc::now()).unwrap();
+
+        assert!(matches!(failure, RateFetchFailure::Tolerated(Some(_))));
+        assert!(maker.mid_market_rate().is_some());
+        assert_eq!(
+            maker.strategy_spread(spread(100), Position::Sell),
+            spread(150)
+        );
+
+        let failure = maker.rate_fetch_failed(Utc::now()).unwrap();
+
+        assert_eq!(failure, RateFetchFailure::RateInvalidated);
+        assert!(maker.mid_market_rate().is_none());
+    }
+
+    #[test]
+    fn rate_fetch_success_restores_the_spread() {
+        let mut maker = Maker {
+            btc_balance: some_btc(10.0),
+            dai_balance: some_dai(10.0),
+            mid_market_rate: some_rate(1.0),
+            rate_failover: Some(config::RateFailover {
+                grace_period_secs: 45,
+                max_consecutive_failures: 3,
+                spread_widening_permyriad: 50,
+            }),
+            ..StaticStub::static_stub()
+        };
+        let rate = maker.mid_market_rate().unwrap();
+        let _ = maker.rate_fetch_failed(Utc::now()).unwrap();
+
+        let publish = maker.update_rate(rate).unwrap();
+
+        assert!(publish.is_some());
+        assert_eq!(
+            maker.strategy_spread(spread(100), Position::Sell),
+            spread(100)
+        );
+    }
+
+    #[test]
+    fn failed_rate_fetch_past_the_grace_period_invalidates_the_rate() {
+        let mut maker = Maker {
+            rate_fetched_at: Utc::now() - Duration::seconds(60),
+            rate_failover: Some(config::RateFailover {
+                grace_period_secs: 45,
+                max_consecutive_failures: 3,
+                spread_widening_permyriad: 50,
+            }),
+            ..StaticStub::static_stub()
+        };
+
+        let failure = maker.rate_fetch_failed(Utc::now()).unwrap();
+
+        assert_eq!(failure, RateFetchFailure::RateInvalidated);
+    }
+
+    #[test]
+    fn take_exceeding_volume_limit_is_declined() {
+        let mut maker = Maker {
+            btc_balance: some_btc(3.0),
+            volume_limits: VolumeLimits::new(
+                config::MaxVolume {
+                    bitcoin: some_btc(2.0),
+                    dai: None,
+                },
+                vec![],
+            ),
+            ..StaticStub::static_stub()
+        };
+
+        let taken_order = btc_dai_order_form(Position::Sell, btc(1.5), rate(0.0));
+
+        let event = maker
+            .process_taken_order(&PeerId::random(), taken_order.clone())
+            .unwrap();
+        assert_eq!(event, TakeRequestDecision::GoForSwap);
+
+        let event = maker
+            .process_taken_order(&PeerId::random(), taken_order)
+            .unwrap();
+        assert_eq!(event, TakeRequestDecision::VolumeLimitReached);
+        assert_eq!(maker.btc_reserved_funds, btc(1.5));
+    }
+
+    #[test]
+    fn sell_orders_withdrawn_while_btc_balance_below_minimum() {
+        let mut maker = Maker {
+            btc_balance: some_btc(1.0),
+            btc_max_sell_amount: None,
+            mid_market_rate: some_rate(1.0),
+            spread: spread(0),
+            min_balance: config::MinBalance {
+                bitcoin: some_btc(0.5),
+                dai: None,
+            },
+            ..StaticStub::static_stub()
+        };
+
+        let new_sell_orders = maker.update_bitcoin_balance(btc(0.4)).unwrap().unwrap();
+        assert!(new_sell_orders.is_empty());
+        assert!(maker.is_btc_balance_below_minimum());
+
+        let new_sell_orders = maker.update_bitcoin_balance(btc(0.6)).unwrap().unwrap();
+        assert_eq!(new_sell_orders.len(), 1);
+        assert!(!maker.is_btc_balance_below_minimum());
+    }
+
+    #[test]
+    fn take_declined_while_dai_balance_below_minimum() {
+        let mut maker = Maker {
+            dai_balance: some_dai(100.0),
+            min_balance: config::MinBalance {
+                bitcoin: None,
+                dai: some_dai(1000.0),
+            },
+            ..StaticStub::static_stub()
+        };
+
+        let taken_order = btc_dai_order_form(Position::Buy, btc(0.01), rate(0.0));
+
+        let event = maker
+            .process_taken_order(&PeerId::random(), taken_order)
+            .unwrap();
+        assert_eq!(event, TakeRequestDecision::InsufficientFunds);
+    }
+
+    #[test]
+    fn orders_withdrawn_and_takes_declined_while_lacking_gas() {
+        let mut maker = Maker {
+            btc_balance: some_btc(1.0),
+            dai_balance: some_dai(1000.0),
+            mid_market_rate: some_rate(1.0),
+            spread: spread(0),
+            ..StaticStub::static_stub()
+        };
+
+        let orders = maker
+            .update_ether_balance(ether::Amount::from(999), ether::Amount::from(1000))
+            .unwrap()
+            .unwrap();
+        assert!(orders.new_sell_orders.is_empty());
+        assert!(orders.new_buy_orders.is_empty());
+
+        let taken_order = btc_dai_order_form(Position::Sell, btc(0.1), rate(0.0));
+        let event = maker
+            .process_taken_order(&PeerId::random(), taken_order)
+            .unwrap();
+        assert_eq!(event, TakeRequestDecision::InsufficientFunds);
+
+        let orders = maker
+            .update_ether_balance(ether::Amount::from(1000), ether::Amount::from(1000))
+            .unwrap()
+            .unwrap();
+        assert_eq!(orders.new_sell_orders.len(), 1);
+        assert_eq!(orders.new_buy_orders.len(), 1);
+    }
+
+    #[test]
+    fn free_funds_when_processing_finished_swap() {
         let mut maker = Maker {
             btc_reserved_funds: btc(1.1),
             dai_reserved_funds: dai(1.0),
@@ -477,14 +1842,67 @@ mod tests {
         };
 
         let free_btc = Some(btc(0.5));
-        maker.free_funds(None, free_btc);
+        maker.free_funds(None, free_btc).unwrap();
         assert_eq!(maker.btc_reserved_funds, btc(0.5));
 
         let free_dai = Some(dai(0.5));
-        maker.free_funds(free_dai, None);
+        maker.free_funds(free_dai, None).unwrap();
         assert_eq!(maker.dai_reserved_funds, dai(0.5));
     }
 
+    #[test]
+    fn freeing_more_than_reserved_fails_without_freeing() {
+        let mut maker = Maker {
+            btc_reserved_funds: btc(1.1),
+            dai_reserved_funds: dai(1.0),
+            btc_fee: btc(0.1),
+            ..StaticStub::static_stub()
+        };
+
+        assert!(maker.free_funds(Some(dai(0.5)), Some(btc(2.0))).is_err());
+        assert!(maker.free_funds(Some(dai(2.0)), None).is_err());
+
+        assert_eq!(maker.btc_reserved_funds, btc(1.1));
+        assert_eq!(maker.dai_reserved_funds, dai(1.0));
+    }
+
+    #[test]
+    fn fee_update_adjusts_funds_reserved_for_ongoing_swaps() {
+        let mut maker = Maker {
+            btc_balance: some_btc(3.0),
+            btc_fee: btc(0.1),
+            ..StaticStub::static_stub()
+        };
+
+        let taken_order = btc_dai_order_form(Position::Sell, btc(1.0), rate(0.0));
+        maker
+            .process_taken_order(&PeerId::random(), taken_order)
+            .unwrap();
+        maker.reserve_btc(btc(0.5));
+        assert_eq!(maker.btc_reserved_funds, btc(1.7));
+
+        let order = maker.update_btc_fee(btc(0.2)).unwrap();
+        assert!(order.is_some());
+        assert_eq!(maker.btc_reserved_funds, btc(1.9));
+
+        maker.free_funds(None, Some(btc(1.0))).unwrap();
+        assert_eq!(maker.btc_reserved_funds, btc(0.7));
+
+        maker.update_btc_fee(btc(0.05)).unwrap();
+        assert_eq!(maker.btc_reserved_funds, btc(0.55));
+    }
+
+    #[test]
+    fn no_new_sell_order_if_no_btc_fee_change() {
+        let mut maker = Maker {
+            btc_fee: btc(0.1),
+            ..StaticStub::static_stub()
+        };
+
+        let result = maker.update_btc_fee(btc(0.1)).unwrap();
+        assert!(result.is_none());
+    }
+
     #[test]
     fn no_new_sell_order_if_no_btc_balance_change() {
         let mut maker = Maker {
@@ -519,8 +1937,9 @@ mod tests {
         };
         let new_balance = btc(0.5);
 
-        let new_sell_order = maker.update_bitcoin_balance(new_balance).unwrap().unwrap();
-        assert_eq!(new_sell_order.position, Position::Sell);
+        let new_sell_orders = maker.update_bitcoin_balance(new_balance).unwrap().unwrap();
+        assert_eq!(new_sell_orders.len(), 1);
+        assert_eq!(new_sell_orders[0].position, Position::Sell);
         assert_eq!(maker.btc_balance, Some(new_balance))
     }
 
@@ -535,11 +1954,12 @@ mod tests {
         };
         let new_balance = dai(0.5);
 
-        let new_buy_order = maker
+        let new_buy_orders = maker
             .update_dai_balance(new_balance.clone())
             .unwrap()
             .unwrap();
-        assert_eq!(new_buy_order.position, Position::Buy);
+        assert_eq!(new_buy_orders.len(), 1);
+        assert_eq!(new_buy_orders[0].position, Position::Buy);
         assert_eq!(maker.dai_balance, Some(new_balance))
     }
 
@@ -556,7 +1976,9 @@ mod tests {
         let new_sell_order = maker.new_sell_order().unwrap();
         assert_eq!(new_sell_order.quantity.sats(), btc(1.0).as_sat());
 
-        let result = maker.process_taken_order(new_sell_order).unwrap();
+        let result = maker
+            .process_taken_order(&PeerId::random(), new_sell_order)
+            .unwrap();
 
         assert_eq!(result, TakeRequestDecision::GoForSwap);
         assert_eq!(maker.btc_reserved_funds, btc(1.0))
@@ -574,7 +1996,9 @@ mod tests {
         let new_buy_order = maker.new_buy_order().unwrap();
         assert_eq!(dai::Amount::from(new_buy_order.quote()), dai(1.0));
 
-        let result = maker.process_taken_order(new_buy_order).unwrap();
+        let result = maker
+            .process_taken_order(&PeerId::random(), new_buy_order)
+            .unwrap();
 
         assert_eq!(result, TakeRequestDecision::GoForSwap);
         assert_eq!(maker.dai_reserved_funds, dai(1.0))
@@ -594,4 +2018,263 @@ mod tests {
         assert_eq!(bitcoin::Amount::from(new_buy_order.quantity), btc(0.002));
         assert_eq!(dai::Amount::from(new_buy_order.quote()), dai(18.0));
     }
+
+    #[test]
+    fn position_spreads_override_the_spread() {
+        let maker = Maker {
+            btc_balance: some_btc(1.0),
+            dai_balance: some_dai(1000.0),
+            mid_market_rate: some_rate(1000.0),
+            spread: spread(100),
+            sell_spread: Some(spread(150)),
+            buy_spread: Some(spread(50)),
+            ..StaticStub::static_stub()
+        };
+
+        let new_sell_order = maker.new_sell_order().unwrap();
+        assert!(new_sell_order.is_as_profitable_as(rate(1014.0)).unwrap());
+        assert!(!new_sell_order.is_as_profitable_as(rate(1016.0)).unwrap());
+
+        let new_buy_order = maker.new_buy_order().unwrap();
+        assert!(new_buy_order.is_as_profitable_as(rate(996.0)).unwrap());
+        assert!(!new_buy_order.is_as_profitable_as(rate(994.0)).unwrap());
+    }
+
+    fn levels() -> Vec<config::OrderLevel> {
+        vec![
+            config::OrderLevel {
+                bitcoin: some_btc(0.1),
+                spread: spread(100),
+            },
+            config::OrderLevel {
+                bitcoin: some_btc(0.5),
+                spread: spread(200),
+            },
+            config::OrderLevel {
+                bitcoin: None,
+                spread: spread(300),
+            },
+        ]
+    }
+
+    #[test]
+    fn sell_ladder_covers_levels_in_order_of_spread() {
+        let maker = Maker {
+            btc_balance: some_btc(1.0),
+            mid_market_rate: some_rate(1000.0),
+            levels: levels(),
+            ..StaticStub::static_stub()
+        };
+
+        let quantities = maker
+            .new_sell_orders()
+            .unwrap()
+            .into_iter()
+            .map(|order| bitcoin::Amount::from(order.quantity))
+            .collect::<Vec<_>>();
+
+        assert_eq!(quantities, vec![btc(0.1), btc(0.5), btc(0.4)]);
+    }
+
+    #[test]
+    fn levels_not_covered_by_the_funds_are_left_out() {
+        let maker = Maker {
+            btc_balance: some_btc(0.3),
+            mid_market_rate: some_rate(1000.0),
+            levels: levels(),
+            ..StaticStub::static_stub()
+        };
+
+        let quantities = maker
+            .new_sell_orders()
+            .unwrap()
+            .into_iter()
+            .map(|order| bitcoin::Amount::from(order.quantity))
+            .collect::<Vec<_>>();
+
+        assert_eq!(quantities, vec![btc(0.1), btc(0.2)]);
+    }
+
+    #[test]
+    fn buy_ladder_levels_are_sized_in_bitcoin() {
+        let maker = Maker {
+            dai_balance: some_dai(1000.0),
+            mid_market_rate: some_rate(1000.0),
+            levels: vec![
+                config::OrderLevel {
+                    bitcoin: some_btc(0.1),
+                    spread: spread(0),
+                },
+                config::OrderLevel {
+                    bitcoin: None,
+                    spread: spread(1000),
+                },
+            ],
+            ..StaticStub::static_stub()
+        };
+
+        let orders = maker.new_buy_orders().unwrap();
+
+        assert_eq!(orders.len(), 2);
+        assert_eq!(bitcoin::Amount::from(orders[0].quantity), btc(0.1));
+        assert_eq!(dai::Amount::from(orders[0].quote()), dai(100.0));
+        assert_eq!(bitcoin::Amount::from(orders[1].quantity), btc(1.0));
+        assert_eq!(dai::Amount::from(orders[1].quote()), dai(900.0));
+    }
+
+    #[test]
+    fn take_larger_than_its_level_is_declined() {
+        let mut maker = Maker {
+            btc_balance: some_btc(1.0),
+            mid_market_rate: some_rate(1000.0),
+            levels: levels(),
+            ..StaticStub::static_stub()
+        };
+        let first_level_rate = rate(1010.0);
+
+        let too_large = btc_dai_order_form(Position::Sell, btc(0.5), first_level_rate);
+        let decision = maker
+            .process_taken_order(&PeerId::random(), too_large)
+            .unwrap();
+        assert_eq!(decision, TakeRequestDecision::RateNotProfitable);
+
+        let within_level = btc_dai_order_form(Position::Sell, btc(0.1), first_level_rate);
+        let decision = maker
+            .process_taken_order(&PeerId::random(), within_level)
+            .unwrap();
+        assert_eq!(decision, TakeRequestDecision::GoForSwap);
+    }
+
+    #[test]
+    fn skewed_sell_order_can_be_taken() {
+        let mut maker = Maker {
+            btc_balance: some_btc(1.0),
+            dai_balance: some_dai(0.0),
+            mid_market_rate: some_rate(1000.0),
+            spread: spread(200),
+            spread_strategy: SpreadStrategy::InventorySkew(config::InventorySkew {
+                target_bitcoin_ratio: 0.5,
+                max_skew: spread(100),
+            }),
+            ..StaticStub::static_stub()
+        };
+
+        let new_sell_order = maker.new_sell_order().unwrap();
+        assert!(new_sell_order.is_as_profitable_as(rate(1010.0)).unwrap());
+        assert!(!new_sell_order.is_as_profitable_as(rate(1020.0)).unwrap());
+
+        let decision = maker
+            .process_taken_order(&PeerId::random(), new_sell_order)
+            .unwrap();
+        assert_eq!(decision, TakeRequestDecision::GoForSwap);
+    }
+
+    #[test]
+    fn rate_moving_too_fast_halts_trading_until_the_cool_down_ends() {
+        let mut maker = Maker {
+            btc_balance: some_btc(1.0),
+            dai_balance: some_dai(1000.0),
+            mid_market_rate: some_rate(1000.0),
+            ..StaticStub::static_stub()
+        }
+        .with_circuit_breaker(CircuitBreaker::new(config::CircuitBreaker {
+            max_move_permyriad: 500,
+            window_secs: 60,
+            cool_down_secs: 600,
+        }));
+
+        let orders = maker.update_rate(MidMarketRate::new(rate(1010.0))).unwrap();
+        assert!(orders.is_some());
+
+        let orders = maker.update_rate(MidMarketRate::new(rate(900.0))).unwrap();
+        assert!(orders.is_none());
+        assert!(maker.is_halted());
+
+        let taken_order = btc_dai_order_form(Position::Sell, btc(0.1), rate(0.0));
+        let decision = maker
+            .process_taken_order(&PeerId::random(), taken_order)
+            .unwrap();
+        assert_eq!(decision, TakeRequestDecision::Halted);
+
+        assert!(maker.end_cool_down(Utc::now()).unwrap().is_none());
+        assert!(maker.is_halted());
+
+        let orders = maker
+            .end_cool_down(Utc::now() + Duration::seconds(601))
+            .unwrap();
+        assert!(orders.is_some());
+        assert!(!maker.is_halted());
+    }
+
+    #[test]
+    fn max_sell_of_depleted_bitcoin_is_scaled_down_once_assessed() {
+        let mut maker = Maker {
+            btc_balance: some_btc(0.2),
+            dai_balance: some_dai(800.0),
+            btc_fee: bitcoin::Amount::ZERO,
+            mid_market_rate: some_rate(1000.0),
+            spread: spread(0),
+            ..StaticStub::static_stub()
+        }
+        .with_rebalance(Some(config::Rebalance {
+            target_bitcoin_ratio: 0.5,
+            threshold_percent: 50,
+            adjust_max_sell: true,
+        }));
+        assert_eq!(
+            maker.new_sell_order().unwrap().quantity.sats(),
+            btc(0.2).as_sat()
+        );
+
+        let InventoryAssessment {
+            assessment,
+            publish_orders,
+        } = maker.assess_inventory(Utc::now()).unwrap().unwrap();
+
+        assert_eq!(
+            assessment.recommendation,
+            Some(Recommendation::BuyBitcoin(dai(300.0)))
+        );
+        let publish_orders = publish_orders.unwrap();
+        assert_eq!(
+            publish_orders.new_sell_orders[0].quantity.sats(),
+            btc(0.08).as_sat()
+        );
+        assert!(maker
+            .assess_inventory(Utc::now())
+            .unwrap()
+            .unwrap()
+            .publish_orders
+            .is_none());
+    }
+
+    #[test]
+    fn configured_expiries_apply_to_the_alpha_and_beta_htlcs() {
+        let maker = Maker::static_stub().with_expiries(Some(config::Expiries {
+            alpha_offset_mins: 1440,
+            beta_offset_mins: 720,
+        }));
+
+        for position in &[Position::Buy, Position::Sell] {
+            let (alpha_offset, beta_offset) = match maker.swap_protocol(*position) {
+                SwapProtocol::HbitHerc20 {
+                    hbit_expiry_offset,
+                    herc20_expiry_offset,
+                } => (
+                    time::Duration::from(hbit_expiry_offset),
+                    time::Duration::from(herc20_expiry_offset),
+                ),
+                SwapProtocol::Herc20Hbit {
+                    herc20_expiry_offset,
+                    hbit_expiry_offset,
+                } => (
+                    time::Duration::from(herc20_expiry_offset),
+                    time::Duration::from(hbit_expiry_offset),
+                ),
+            };
+
+            assert_eq!(alpha_offset, time::Duration::hours(24));
+            assert_eq!(beta_offset, time::Duration::hours(12));
+        }
+    }
 }
@@ -0,0 +1,160 @@
+//! Halts trading when the mid-market rate moves too fast, e.g. during a
+//! crash, until it has been calm for a cool-down period.
+
+use crate::{config, Rate};
+use chrono::{DateTime, Duration, Utc};
+use num::BigUint;
+use std::{collections::VecDeque, convert::TryFrom};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CircuitBreaker {
+    /// Never trips if `None`.
+    config: Option<config::CircuitBreaker>,
+    /// The rates recorded within the window, oldest first.
+    rates: VecDeque<(DateTime<Utc>, Rate)>,
+    /// Trading is halted until then, and past it until `reset_if_calm`.
+    tripped_until: Option<DateTime<Utc>>,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: config::CircuitBreaker) -> Self {
+        CircuitBreaker {
+            config: Some(config),
+            ..Self::default()
+        }
+    }
+
+    /// Record a new rate, trips the breaker or extends its cool-down if the
+    /// rate moved too much within the window.
+    pub fn record(&mut self, rate: Rate, now: DateTime<Utc>) {
+        let config = match self.config {
+            Some(config) => config,
+            None => return,
+        };
+
+        let window_start = now - seconds(config.window_secs);
+        while self
+            .rates
+            .front()
+            .map_or(false, |(recorded_at, _)| *recorded_at < window_start)
+        {
+            self.rates.pop_front();
+        }
+
+        if self
+            .rates
+            .iter()
+            .any(|(_, previous)| moved_more_than(*previous, rate, config.max_move_permyriad))
+        {
+            self.tripped_until = Some(now + seconds(config.cool_down_secs));
+        }
+
+        self.rates.push_back((now, rate));
+    }
+
+    pub fn is_tripped(&self) -> bool {
+        self.tripped_until.is_some()
+    }
+
+    /// Resets the breaker if its cool-down elapsed, returns whether it was
+    /// reset.
+    pub fn reset_if_calm(&mut self, now: DateTime<Utc>) -> bool {
+        match self.tripped_until {
+            Some(tripped_until) if tripped_until <= now => {
+                self.tripped_until = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+pub(super) fn moved_more_than(previous: Rate, rate: Rate, max_move_permyriad: u16) -> bool {
+    let (previous, rate) = (previous.integer(), rate.integer());
+    let difference = if rate > previous {
+        &rate - &previous
+    } else {
+        &previous - &rate
+    };
+
+    difference * BigUint::from(10_000u16) > previous * BigUint::from(max_move_permyriad)
+}
+
+pub(super) fn seconds(seconds: u64) -> Duration {
+    Duration::seconds(i64::try_from(seconds).unwrap_or(i64::MAX))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rate::rate;
+    use std::str::FromStr;
+
+    fn now() -> DateTime<Utc> {
+        DateTime::from_str("2020-07-10T08:00:00Z").unwrap()
+    }
+
+    fn circuit_breaker() -> CircuitBreaker {
+        CircuitBreaker::new(config::CircuitBreaker {
+            max_move_permyriad: 500,
+            window_secs: 60,
+            cool_down_secs: 600,
+        })
+    }
+
+    #[test]
+    fn small_moves_do_not_trip() {
+        let mut circuit_breaker = circuit_breaker();
+
+        circuit_breaker.record(rate(10_000.0), now());
+        circuit_breaker.record(rate(10_200.0), now() + Duration::seconds(10));
+        circuit_breaker.record(rate(10_400.0), now() + Duration::seconds(20));
+
+        assert!(!circuit_breaker.is_tripped());
+    }
+
+    #[test]
+    fn move_within_the_window_trips_until_calm() {
+        let mut circuit_breaker = circuit_breaker();
+
+        circuit_breaker.record(rate(10_000.0), now());
+        circuit_breaker.record(rate(9_000.0), now() + Duration::seconds(30));
+        assert!(circuit_breaker.is_tripped());
+
+        assert!(!circuit_breaker.reset_if_calm(now() + Duration::seconds(300)));
+        assert!(circuit_breaker.reset_if_calm(now() + Duration::seconds(630)));
+        assert!(!circuit_breaker.is_tripped());
+    }
+
+    #[test]
+    fn moves_spread_over_more_than_the_window_do_not_trip() {
+        let mut circuit_breaker = circuit_breaker();
+
+        circuit_breaker.record(rate(10_000.0), now());
+        circuit_breaker.record(rate(9_000.0), now() + Duration::seconds(90));
+
+        assert!(!circuit_breaker.is_tripped());
+    }
+
+    #[test]
+    fn move_during_the_cool_down_extends_it() {
+        let mut circuit_breaker = circuit_breaker();
+
+        circuit_breaker.record(rate(10_000.0), now());
+        circuit_breaker.record(rate(9_000.0), now() + Duration::seconds(30));
+        circuit_breaker.record(rate(10_000.0), now() + Duration::seconds(50));
+
+        assert!(!circuit_breaker.reset_if_calm(now() + Duration::seconds(640)));
+        assert!(circuit_breaker.reset_if_calm(now() + Duration::seconds(1000)));
+    }
+
+    #[test]
+    fn disabled_breaker_never_trips() {
+        let mut circuit_breaker = CircuitBreaker::default();
+
+        circuit_breaker.record(rate(10_000.0), now());
+        circuit_breaker.record(rate(1_000.0), now());
+
+        assert!(!circuit_breaker.is_tripped());
+    }
+}
@@ -0,0 +1,285 @@
+//! Rebalance recommendations: the share of the inventory held in bitcoin,
+//! valued at the mid-market rate, is sampled over time and moving funds to an
+//! asset is recommended once its share falls below the configured percentage
+//! of its target share.
+//!
+//! If so configured, the maximum amount sold per order of the depleted asset
+//! is also scaled down by how far its share is below the target, so that the
+//! little of it left is not sold off at once.
+
+use crate::{bitcoin, config, ethereum::dai, order::Symbol, Rate};
+use chrono::{DateTime, Duration, Utc};
+use num::{BigUint, ToPrimitive, Zero};
+use std::{collections::VecDeque, fmt};
+
+const PERMYRIAD: u32 = 10_000;
+
+#[derive(Debug, Clone, Default)]
+pub struct RebalanceAdvisor {
+    config: Option<config::Rebalance>,
+    /// The bitcoin shares of the inventory sampled over the drift window,
+    /// oldest first.
+    samples: VecDeque<(DateTime<Utc>, u32)>,
+    /// The asset depleted as of the last assessment.
+    depleted: Option<Symbol>,
+}
+
+/// The state of the inventory upon a sample.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assessment {
+    /// Share of the inventory held in bitcoin, in permyriad.
+    pub bitcoin_share: u32,
+    /// The configured target share of bitcoin, in permyriad.
+    pub target_bitcoin_share: u32,
+    /// Change of `bitcoin_share` over the drift window, `None` if there is no
+    /// earlier sample within it.
+    pub drift: Option<i64>,
+    pub recommendation: Option<Recommendation>,
+}
+
+/// The funds to move to restore the target shares, e.g. by trading them on an
+/// exchange.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Recommendation {
+    /// Bitcoin is depleted, buy bitcoin with this much dai.
+    BuyBitcoin(dai::Amount),
+    /// Dai is depleted, sell this much bitcoin for dai.
+    SellBitcoin(bitcoin::Amount),
+}
+
+impl Recommendation {
+    pub fn depleted(&self) -> Symbol {
+        match self {
+            Recommendation::BuyBitcoin(_) => Symbol::Btc,
+            Recommendation::SellBitcoin(_) => Symbol::Dai,
+        }
+    }
+}
+
+impl fmt::Display for Recommendation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Recommendation::BuyBitcoin(dai) => write!(f, "buy bitcoin with {}", dai),
+            Recommendation::SellBitcoin(bitcoin) => write!(f, "sell {} for dai", bitcoin),
+        }
+    }
+}
+
+impl RebalanceAdvisor {
+    pub fn new(config: Option<config::Rebalance>) -> Self {
+        RebalanceAdvisor {
+            config,
+            ..Default::default()
+        }
+    }
+
+    /// The drift of the inventory is measured over the window ending at the
+    /// last sample.
+    pub fn drift_window() -> Duration {
+        Duration::hours(24)
+    }
+
+    /// Sample the inventory at `now` and assess it. `None` if no rebalance is
+    /// configured or the inventory is empty.
+    pub fn assess(
+        &mut self,
+        now: DateTime<Utc>,
+        bitcoin: bitcoin::Amount,
+        dai: &dai::Amount,
+        mid_market_rate: Rate,
+    ) -> Option<Assessment> {
+        let config = self.config?;
+        let bitcoin_value = bitcoin.worth_in(mid_market_rate).as_atto();
+        let total_value = &bitcoin_value + dai.as_atto();
+        if total_value.is_zero() {
+            return None;
+        }
+        let bitcoin_share = (&bitcoin_value * PERMYRIAD / &total_value)
+            .to_u32()
+            .unwrap_or(PERMYRIAD);
+
+        let window_start = now - Self::drift_window();
+        while matches!(self.samples.front(), Some((sampled_at, _)) if *sampled_at < window_start) {
+            self.samples.pop_front();
+        }
+        let drift = self
+            .samples
+            .front()
+            .map(|(_, share)| i64::from(bitcoin_share) - i64::from(*share));
+        self.samples.push_back((now, bitcoin_share));
+
+        let target_share = target_bitcoin_share(&config);
+        let threshold =
+            |target_share: u32| target_share * u32::from(config.threshold_percent) / 100;
+        let target_value = dai::Amount::from_atto(&total_value * target_share / PERMYRIAD);
+        let bitcoin_value = dai::Amount::from_atto(bitcoin_value);
+
+        let recommendation = if bitcoin_share < threshold(target_share) {
+            Some(Recommendation::BuyBitcoin(
+                target_value.saturating_sub(&bitcoin_value),
+            ))
+        } else if PERMYRIAD - bitcoin_share < threshold(PERMYRIAD - target_share) {
+            let excess = bitcoin_value.saturating_sub(&target_value);
+            Some(Recommendation::SellBitcoin(
+                excess
+                    .worth_in(mid_market_rate)
+                    .unwrap_or(bitcoin::Amount::ZERO),
+            ))
+        } else {
+            None
+        };
+        self.depleted = recommendation.as_ref().map(Recommendation::depleted);
+
+        Some(Assessment {
+            bitcoin_share,
+            target_bitcoin_share: target_share,
+            drift,
+            recommendation,
+        })
+    }
+
+    /// The asset depleted as of the last assessment.
+    pub fn depleted(&self) -> Option<Symbol> {
+        self.depleted
+    }
+
+    /// The maximum amount of bitcoin to sell per order when holding `balance`,
+    /// `None` if not adjusted.
+    pub fn max_sell_bitcoin(&self, balance: bitcoin::Amount) -> Option<bitcoin::Amount> {
+        let scale = self.max_sell_scale(Symbol::Btc)?;
+        let sat = BigUint::from(balance.as_sat()) * scale / PERMYRIAD;
+
+        sat.to_u64().map(bitcoin::Amount::from_sat)
+    }
+
+    /// The maximum amount of dai to sell per order when holding `balance`,
+    /// `None` if not adjusted.
+    pub fn max_sell_dai(&self, balance: &dai::Amount) -> Option<dai::Amount> {
+        let scale = self.max_sell_scale(Symbol::Dai)?;
+
+        Some(dai::Amount::from_atto(
+            balance.as_atto() * scale / PERMYRIAD,
+        ))
+    }
+
+    /// The share of its target held in `symbol`, in permyriad, if it is
+    /// depleted and the maximum sell amounts are to be adjusted.
+    fn max_sell_scale(&self, symbol: Symbol) -> Option<u32> {
+        let config = self.config.filter(|config| config.adjust_max_sell)?;
+        if self.depleted != Some(symbol) {
+            return None;
+        }
+        let (_, bitcoin_share) = self.samples.back()?;
+        let target_share = target_bitcoin_share(&config);
+
+        match symbol {
+            Symbol::Btc => Some(bitcoin_share * PERMYRIAD / target_share),
+            Symbol::Dai => {
+                Some((PERMYRIAD - bitcoin_share) * PERMYRIAD / (PERMYRIAD - target_share))
+            }
+        }
+    }
+}
+
+/// The target share of bitcoin, in permyriad, strictly between 0 and 10000 as
+/// the ratio is validated.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn target_bitcoin_share(config: &config::Rebalance) -> u32 {
+    ((config.target_bitcoin_ratio * f64::from(PERMYRIAD)).round() as u32)
+        .max(1)
+        .min(PERMYRIAD - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bitcoin::amount::btc, ethereum::dai::dai, rate::rate};
+
+    fn advisor(adjust_max_sell: bool) -> RebalanceAdvisor {
+        RebalanceAdvisor::new(Some(config::Rebalance {
+            target_bitcoin_ratio: 0.5,
+            threshold_percent: 50,
+            adjust_max_sell,
+        }))
+    }
+
+    fn at(hours: i64) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2020-07-10T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc)
+            + Duration::hours(hours)
+    }
+
+    #[test]
+    fn inventory_within_threshold_is_not_rebalanced() {
+        let assessment = advisor(false)
+            .assess(at(0), btc(0.3), &dai(700.0), rate(1000.0))
+            .unwrap();
+
+        assert_eq!(assessment.bitcoin_share, 3_000);
+        assert_eq!(assessment.drift, None);
+        assert_eq!(assessment.recommendation, None);
+    }
+
+    #[test]
+    fn depleted_bitcoin_is_bought_back_to_the_target() {
+        let assessment = advisor(false)
+            .assess(at(0), btc(0.2), &dai(800.0), rate(1000.0))
+            .unwrap();
+
+        assert_eq!(
+            assessment.recommendation,
+            Some(Recommendation::BuyBitcoin(dai(300.0)))
+        );
+    }
+
+    #[test]
+    fn depleted_dai_is_bought_back_to_the_target() {
+        let assessment = advisor(false)
+            .assess(at(0), btc(0.8), &dai(200.0), rate(1000.0))
+            .unwrap();
+
+        assert_eq!(
+            assessment.recommendation,
+            Some(Recommendation::SellBitcoin(btc(0.3)))
+        );
+    }
+
+    #[test]
+    fn drift_is_measured_over_the_window() {
+        let mut advisor = advisor(false);
+
+        advisor.assess(at(0), btc(0.5), &dai(500.0), rate(1000.0));
+        advisor.assess(at(12), btc(0.4), &dai(600.0), rate(1000.0));
+        let assessment = advisor
+            .assess(at(30), btc(0.3), &dai(700.0), rate(1000.0))
+            .unwrap();
+
+        assert_eq!(assessment.drift, Some(-1_000));
+    }
+
+    #[test]
+    fn max_sell_of_the_depleted_asset_is_scaled_down_if_configured() {
+        let mut advisor = advisor(true);
+        advisor.assess(at(0), btc(0.2), &dai(800.0), rate(1000.0));
+
+        assert_eq!(advisor.max_sell_bitcoin(btc(0.2)), Some(btc(0.08)));
+        assert_eq!(advisor.max_sell_dai(&dai(800.0)), None);
+    }
+
+    #[test]
+    fn max_sell_is_not_scaled_down_unless_configured() {
+        let mut advisor = advisor(false);
+        advisor.assess(at(0), btc(0.2), &dai(800.0), rate(1000.0));
+
+        assert_eq!(advisor.max_sell_bitcoin(btc(0.2)), None);
+    }
+
+    #[test]
+    fn empty_inventory_is_not_assessed() {
+        assert_eq!(
+            advisor(false).assess(at(0), btc(0.0), &dai(0.0), rate(1000.0)),
+            None
+        );
+    }
+}
@@ -0,0 +1,49 @@
+//! Declines the takes of takers with a poor track record, as recorded in the
+//! database at the end of their swaps.
+
+use crate::{
+    bitcoin, config, maker::TakeRequestDecision, order::BtcDaiOrderForm, swap::Reputation,
+};
+use libp2p::PeerId;
+use std::collections::HashMap;
+
+/// The reputations are refreshed from the database before each take, a
+/// taker not refreshed yet is unknown.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Reputations {
+    policy: Option<config::ReputationPolicy>,
+    peers: HashMap<PeerId, Reputation>,
+}
+
+impl Reputations {
+    pub fn new(policy: Option<config::ReputationPolicy>) -> Self {
+        Reputations {
+            policy,
+            peers: HashMap::new(),
+        }
+    }
+
+    pub fn update(&mut self, taker: PeerId, reputation: Reputation) {
+        self.peers.insert(taker, reputation);
+    }
+
+    /// Why the take is declined, `None` if the policy allows it.
+    pub fn decline(&self, taker: &PeerId, order: &BtcDaiOrderForm) -> Option<TakeRequestDecision> {
+        let policy = self.policy.as_ref()?;
+        let reputation = self.peers.get(taker).copied().unwrap_or_default();
+
+        let exceeds = |max: Option<u32>, count: u32| max.map_or(false, |max| count > max);
+        if exceeds(policy.max_aborts, reputation.aborted)
+            || exceeds(policy.max_refunds_caused, reputation.refunds_caused)
+        {
+            return Some(TakeRequestDecision::PoorReputation);
+        }
+
+        match policy.first_trade_max_bitcoin {
+            Some(max) if reputation.is_unknown() && bitcoin::Amount::from(order.quantity) > max => {
+                Some(TakeRequestDecision::FirstTradeTooLarge)
+            }
+            _ => None,
+        }
+    }
+}
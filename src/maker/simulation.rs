@@ -0,0 +1,251 @@
+//! Simulation of the trade event loop: random sequences of rate updates,
+//! balance changes, takes and finished swaps are fed to the `Maker` the way
+//! `trade` does and its accounting is checked after every event.
+
+use super::*;
+use crate::{Rate, StaticStub};
+use num::BigUint;
+use proptest::prelude::*;
+use std::convert::TryFrom;
+
+#[derive(Debug, Clone)]
+enum Event {
+    RateUpdate(u32),
+    DepositBtc(u64),
+    DepositDai(u32),
+    /// Withdraw a percentage of the Bitcoin that is not reserved
+    WithdrawBtc(u8),
+    /// Withdraw a percentage of the Dai that is not reserved
+    WithdrawDai(u8),
+    /// Take the order last published for this position
+    Take(Position),
+    /// Finish one of the ongoing swaps
+    SwapFinished {
+        index: usize,
+        redeemed: bool,
+    },
+}
+
+fn position() -> impl Strategy<Value = Position> {
+    prop_oneof![Just(Position::Buy), Just(Position::Sell)]
+}
+
+fn event() -> impl Strategy<Value = Event> {
+    prop_oneof![
+        2 => (100..100_000u32).prop_map(Event::RateUpdate),
+        1 => (0..100_000_000u64).prop_map(Event::DepositBtc),
+        1 => (0..1_000_000u32).prop_map(Event::DepositDai),
+        1 => (0..=100u8).prop_map(Event::WithdrawBtc),
+        1 => (0..=100u8).prop_map(Event::WithdrawDai),
+        4 => position().prop_map(Event::Take),
+        2 => (any::<usize>(), any::<bool>())
+            .prop_map(|(index, redeemed)| Event::SwapFinished { index, redeemed }),
+    ]
+}
+
+/// The maker together with the wallets it reports the balances of and the
+/// takers it trades with.
+struct Simulation {
+    maker: Maker,
+    btc_wallet: bitcoin::Amount,
+    dai_wallet: dai::Amount,
+    published_sell_order: Option<BtcDaiOrderForm>,
+    published_buy_order: Option<BtcDaiOrderForm>,
+    ongoing_swaps: Vec<BtcDaiOrderForm>,
+}
+
+impl Simulation {
+    fn new(maker: Maker) -> Self {
+        let mut simulation = Simulation {
+            btc_wallet: maker.btc_balance().unwrap(),
+            dai_wallet: maker.dai_balance().unwrap(),
+            maker,
+            published_sell_order: None,
+            published_buy_order: None,
+            ongoing_swaps: Vec::new(),
+        };
+
+        if let Ok(orders) = simulation.maker.new_sell_orders() {
+            simulation.publish(orders);
+        }
+        if let Ok(orders) = simulation.maker.new_buy_orders() {
+            simulation.publish(orders);
+        }
+
+        simulation
+    }
+
+    fn process(&mut self, event: Event) {
+        match event {
+            Event::RateUpdate(rate) => {
+                let rate = MidMarketRate::new(Rate::try_from(f64::from(rate)).unwrap());
+
+                if let Ok(Some(orders)) = self.maker.update_rate(rate) {
+                    self.publish(orders.new_sell_orders);
+                    self.publish(orders.new_buy_orders);
+                }
+            }
+            Event::DepositBtc(sat) => {
+                self.btc_wallet = self.btc_wallet + bitcoin::Amount::from_sat(sat);
+                self.report_btc_balance();
+            }
+            Event::DepositDai(amount) => {
+                self.dai_wallet = self.dai_wallet.clone()
+                    + dai::Amount::from_dai_trunc(f64::from(amount)).unwrap();
+                self.report_dai_balance();
+            }
+            Event::WithdrawBtc(percent) => {
+                let unreserved = self.btc_wallet.as_sat() - self.maker.btc_reserved_funds.as_sat();
+                let withdrawn = unreserved / 100 * u64::from(percent);
+
+                self.btc_wallet = self.btc_wallet - bitcoin::Amount::from_sat(withdrawn);
+                self.report_btc_balance();
+            }
+            Event::WithdrawDai(percent) => {
+                let unreserved =
+                    self.dai_wallet.as_atto() - self.maker.dai_reserved_funds.as_atto();
+                let withdrawn = unreserved * BigUint::from(percent) / BigUint::from(100u8);
+
+                self.dai_wallet = self.dai_wallet.clone() - dai::Amount::from_atto(withdrawn);
+                self.report_dai_balance();
+            }
+            Event::Take(position) => {
+                let order = match position {
+                    Position::Sell => self.published_sell_order.clone(),
+                    Position::Buy => self.published_buy_order.clone(),
+                };
+
+                if let Some(order) = order {
+                    let decision = self
+                        .maker
+                        .process_taken_order(&PeerId::random(), order.clone())
+                        .unwrap();
+
+                    if decision == TakeRequestDecision::GoForSwap {
+                        assert_respects_spread(&self.maker, &order);
+                        self.ongoing_swaps.push(order);
+                    }
+                }
+            }
+            Event::SwapFinished { index, redeemed } => {
+                if self.ongoing_swaps.is_empty() {
+                    return;
+                }
+                let swap = self.ongoing_swaps.remove(index % self.ongoing_swaps.len());
+                let base = bitcoin::Amount::from(swap.quantity);
+                let quote = dai::Amount::from(swap.quote());
+
+                match (swap.position, redeemed) {
+                    (Position::Sell, true) => {
+                        self.btc_wallet = self.btc_wallet - base - self.maker.btc_fee;
+                        self.dai_wallet = self.dai_wallet.clone() + quote;
+                    }
+                    (Position::Sell, false) => {
+                        self.btc_wallet = self.btc_wallet - self.maker.btc_fee;
+                    }
+                    (Position::Buy, true) => {
+                        self.btc_wallet = self.btc_wallet + base;
+                        self.dai_wallet = self.dai_wallet.clone() - quote;
+                    }
+                    (Position::Buy, false) => {}
+                }
+
+                let freed = match swap.position {
+                    Position::Sell => self.maker.free_funds(None, Some(base)),
+                    Position::Buy => self.maker.free_funds(Some(quote), None),
+                };
+                freed.expect("the funds of the swap were reserved");
+                self.report_btc_balance();
+                self.report_dai_balance();
+            }
+        }
+    }
+
+    fn report_btc_balance(&mut self) {
+        if let Ok(Some(orders)) = self.maker.update_bitcoin_balance(self.btc_wallet) {
+            self.publish(orders);
+        }
+    }
+
+    fn report_dai_balance(&mut self) {
+        if let Ok(Some(orders)) = self.maker.update_dai_balance(self.dai_wallet.clone()) {
+            self.publish(orders);
+        }
+    }
+
+    fn publish(&mut self, orders: Vec<BtcDaiOrderForm>) {
+        for order in orders {
+            assert_respects_spread(&self.maker, &order);
+
+            match order.position {
+                Position::Sell => self.published_sell_order = Some(order),
+                Position::Buy => self.published_buy_order = Some(order),
+            }
+        }
+    }
+
+    fn assert_invariants(&self) {
+        assert_eq!(self.maker.btc_balance(), Some(self.btc_wallet));
+        assert_eq!(self.maker.dai_balance(), Some(self.dai_wallet.clone()));
+
+        assert!(self.maker.btc_reserved_funds <= self.btc_wallet);
+        assert!(self.maker.dai_reserved_funds <= self.dai_wallet);
+
+        let (btc_reserved, dai_reserved) = self.ongoing_swaps.iter().fold(
+            (bitcoin::Amount::default(), dai::Amount::default()),
+            |(btc_reserved, dai_reserved), swap| match swap.position {
+                Position::Sell => (
+                    btc_reserved + bitcoin::Amount::from(swap.quantity) + self.maker.btc_fee,
+                    dai_reserved,
+                ),
+                Position::Buy => (btc_reserved, dai_reserved + dai::Amount::from(swap.quote())),
+            },
+        );
+        assert_eq!(self.maker.btc_reserved_funds, btc_reserved);
+        assert_eq!(self.maker.dai_reserved_funds, dai_reserved);
+    }
+}
+
+fn assert_respects_spread(maker: &Maker, order: &BtcDaiOrderForm) {
+    let mid_market_rate = maker.mid_market_rate().unwrap();
+    let profitable_rate = maker
+        .spread
+        .apply(mid_market_rate.into(), order.position)
+        .unwrap();
+
+    assert!(order.is_as_profitable_as(profitable_rate).unwrap());
+}
+
+proptest! {
+    #[test]
+    fn maker_accounting_holds_for_any_sequence_of_events(
+        btc_balance in 0..1_000_000_000u64,
+        dai_balance in 0..10_000_000u32,
+        btc_fee in 0..100_000u64,
+        btc_max_sell_amount in proptest::option::of(100_000..1_000_000_000u64),
+        dai_max_sell_amount in proptest::option::of(0..10_000_000u32),
+        rate in 100..100_000u32,
+        spread in 0..1_000u16,
+        events in proptest::collection::vec(event(), 0..100),
+    ) {
+        let maker = Maker::new(
+            bitcoin::Amount::from_sat(btc_balance),
+            dai::Amount::from_dai_trunc(f64::from(dai_balance)).unwrap(),
+            bitcoin::Amount::from_sat(btc_fee),
+            btc_max_sell_amount.map(bitcoin::Amount::from_sat),
+            dai_max_sell_amount.map(|amount| dai::Amount::from_dai_trunc(f64::from(amount)).unwrap()),
+            MidMarketRate::new(Rate::try_from(f64::from(rate)).unwrap()),
+            Spread::new(spread).unwrap(),
+            bitcoin::Network::Regtest,
+            ethereum::Chain::static_stub(),
+            Role::Bob,
+        );
+        let mut simulation = Simulation::new(maker);
+        simulation.assert_invariants();
+
+        for event in events {
+            simulation.process(event);
+            simulation.assert_invariants();
+        }
+    }
+}
@@ -0,0 +1,142 @@
+//! How the spreads of our orders are derived from the configured ones.
+//!
+//! Skewing by the inventory: when holding more bitcoin than targeted the sell
+//! spread is tightened and the buy spread widened so that the inventory moves
+//! back towards the target, and the other way around when holding more dai.
+
+use crate::{bitcoin, config, ethereum::dai, Rate, Spread};
+use comit::Position;
+use num::{ToPrimitive, Zero};
+use std::convert::TryFrom;
+
+const MAX_SPREAD_PERMYRIAD: i32 = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpreadStrategy {
+    Static,
+    InventorySkew(config::InventorySkew),
+}
+
+impl Default for SpreadStrategy {
+    fn default() -> Self {
+        SpreadStrategy::Static
+    }
+}
+
+impl SpreadStrategy {
+    /// The spread to apply for `position` when holding `bitcoin` and `dai`.
+    pub fn spread(
+        &self,
+        spread: Spread,
+        position: Position,
+        bitcoin: bitcoin::Amount,
+        dai: &dai::Amount,
+        mid_market_rate: Rate,
+    ) -> Spread {
+        let skew = match self {
+            SpreadStrategy::Static => return spread,
+            SpreadStrategy::InventorySkew(inventory_skew) => {
+                skew(inventory_skew, bitcoin, dai, mid_market_rate)
+            }
+        };
+
+        let permyriad = match position {
+            Position::Sell => i32::from(spread.permyriad()) - skew,
+            Position::Buy => i32::from(spread.permyriad()) + skew,
+        };
+
+        u16::try_from(permyriad.max(0).min(MAX_SPREAD_PERMYRIAD))
+            .ok()
+            .and_then(|permyriad| Spread::new(permyriad).ok())
+            .unwrap_or(spread)
+    }
+}
+
+/// Adjustment of the spreads in permyriad, positive when holding more bitcoin
+/// than targeted.
+#[allow(clippy::cast_possible_truncation)]
+fn skew(
+    inventory_skew: &config::InventorySkew,
+    bitcoin: bitcoin::Amount,
+    dai: &dai::Amount,
+    mid_market_rate: Rate,
+) -> i32 {
+    let bitcoin_value = bitcoin.worth_in(mid_market_rate).as_atto();
+    let total_value = &bitcoin_value + dai.as_atto();
+    if total_value.is_zero() {
+        return 0;
+    }
+
+    // Valued in attodai, only the ratio in permyriad is converted to a float
+    let ratio = (bitcoin_value * 10_000u32 / total_value)
+        .to_f64()
+        .map_or(0.0, |permyriad| permyriad / 10_000.0);
+    let target = inventory_skew.target_bitcoin_ratio;
+    // -1 when holding only dai, 1 when holding only bitcoin
+    let imbalance = if ratio >= target {
+        (ratio - target) / (1.0 - target)
+    } else {
+        (ratio - target) / target
+    };
+
+    (imbalance * f64::from(inventory_skew.max_skew.permyriad())).round() as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bitcoin::amount::btc, ethereum::dai::dai, rate::rate};
+
+    fn inventory_skew() -> SpreadStrategy {
+        SpreadStrategy::InventorySkew(config::InventorySkew {
+            target_bitcoin_ratio: 0.5,
+            max_skew: Spread::new(100).unwrap(),
+        })
+    }
+
+    fn spreads(strategy: SpreadStrategy, bitcoin: f64, dai_amount: f64) -> (u16, u16) {
+        let spread = Spread::new(200).unwrap();
+        let dai = dai(dai_amount);
+        let sell = strategy.spread(spread, Position::Sell, btc(bitcoin), &dai, rate(1000.0));
+        let buy = strategy.spread(spread, Position::Buy, btc(bitcoin), &dai, rate(1000.0));
+
+        (sell.permyriad(), buy.permyriad())
+    }
+
+    #[test]
+    fn static_strategy_does_not_skew() {
+        assert_eq!(spreads(SpreadStrategy::Static, 10.0, 0.0), (200, 200));
+    }
+
+    #[test]
+    fn balanced_inventory_does_not_skew() {
+        assert_eq!(spreads(inventory_skew(), 1.0, 1000.0), (200, 200));
+    }
+
+    #[test]
+    fn bitcoin_heavy_inventory_tightens_sell_and_widens_buy_spread() {
+        assert_eq!(spreads(inventory_skew(), 3.0, 1000.0), (150, 250));
+        assert_eq!(spreads(inventory_skew(), 1.0, 0.0), (100, 300));
+    }
+
+    #[test]
+    fn dai_heavy_inventory_tightens_buy_and_widens_sell_spread() {
+        assert_eq!(spreads(inventory_skew(), 1.0, 3000.0), (250, 150));
+        assert_eq!(spreads(inventory_skew(), 0.0, 1000.0), (300, 100));
+    }
+
+    #[test]
+    fn skewed_spread_does_not_go_below_zero() {
+        let strategy = SpreadStrategy::InventorySkew(config::InventorySkew {
+            target_bitcoin_ratio: 0.5,
+            max_skew: Spread::new(500).unwrap(),
+        });
+
+        assert_eq!(spreads(strategy, 1.0, 0.0), (0, 700));
+    }
+
+    #[test]
+    fn empty_inventory_does_not_skew() {
+        assert_eq!(spreads(inventory_skew(), 0.0, 0.0), (200, 200));
+    }
+}
@@ -0,0 +1,184 @@
+//! Limits on the volume sold over a rolling 24 hours window, so that a burst
+//! of takes, e.g. while the rate moves fast, cannot sell more than the
+//! operator is willing to.
+
+use crate::{bitcoin, config, ethereum::dai, order::BtcDaiOrderForm, swap::SoldVolume, SwapId};
+use chrono::{DateTime, Duration, Utc};
+use comit::Position;
+use num::BigUint;
+use std::{collections::VecDeque, convert::TryFrom, str::FromStr};
+
+/// What we sell when taking the Sell position is bitcoin, dai otherwise.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sale {
+    pub sold_at: DateTime<Utc>,
+    pub bitcoin: bitcoin::Amount,
+    pub dai: dai::Amount,
+}
+
+impl Sale {
+    pub fn of(order: &BtcDaiOrderForm, sold_at: DateTime<Utc>) -> Self {
+        match order.position {
+            Position::Sell => Sale {
+                sold_at,
+                bitcoin: order.quantity.into(),
+                dai: dai::Amount::zero(),
+            },
+            Position::Buy => Sale {
+                sold_at,
+                bitcoin: bitcoin::Amount::ZERO,
+                dai: order.quote().into(),
+            },
+        }
+    }
+
+    pub fn into_sold_volume(self, swap_id: SwapId) -> SoldVolume {
+        SoldVolume {
+            sold_at: self.sold_at,
+            swap_id,
+            bitcoin_sat: self.bitcoin.as_sat(),
+            dai_attodai: self.dai.as_atto().to_string(),
+        }
+    }
+}
+
+impl TryFrom<SoldVolume> for Sale {
+    type Error = anyhow::Error;
+
+    fn try_from(sold_volume: SoldVolume) -> anyhow::Result<Self> {
+        Ok(Sale {
+            sold_at: sold_volume.sold_at,
+            bitcoin: bitcoin::Amount::from_sat(sold_volume.bitcoin_sat),
+            dai: dai::Amount::from_atto(BigUint::from_str(&sold_volume.dai_attodai)?),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VolumeLimits {
+    max: config::MaxVolume,
+    /// The sales of the last 24 hours, oldest first.
+    sales: VecDeque<Sale>,
+}
+
+impl VolumeLimits {
+    pub fn new(max: config::MaxVolume, mut sales: Vec<Sale>) -> Self {
+        sales.sort_by_key(|sale| sale.sold_at);
+
+        VolumeLimits {
+            max,
+            sales: sales.into(),
+        }
+    }
+
+    /// Start of the window ending at `now`.
+    pub fn window_start(now: DateTime<Utc>) -> DateTime<Utc> {
+        now - Duration::hours(24)
+    }
+
+    /// Whether the sale keeps the volume sold over the 24 hours up to it
+    /// within the limits.
+    pub fn allow(&mut self, sale: &Sale) -> bool {
+        let window_start = Self::window_start(sale.sold_at);
+        while self
+            .sales
+            .front()
+            .map_or(false, |oldest| oldest.sold_at < window_start)
+        {
+            self.sales.pop_front();
+        }
+
+        let (bitcoin, dai) = self
+            .sales
+            .iter()
+            .fold((sale.bitcoin, sale.dai.clone()), |(bitcoin, dai), sale| {
+                (bitcoin + sale.bitcoin, dai + sale.dai.clone())
+            });
+
+        let exceeds_bitcoin = self.max.bitcoin.map_or(false, |max| bitcoin > max);
+        let exceeds_dai = self.max.dai.as_ref().map_or(false, |max| dai > *max);
+
+        !exceeds_bitcoin && !exceeds_dai
+    }
+
+    pub fn record(&mut self, sale: Sale) {
+        self.sales.push_back(sale);
+    }
+
+    /// Replace the recorded sales, e.g. after one was rolled back.
+    pub fn replace_sales(&mut self, sales: Vec<Sale>) {
+        *self = VolumeLimits::new(self.max.clone(), sales);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bitcoin::amount::btc, ethereum::dai::dai};
+
+    fn sale(hours_ago: i64, bitcoin: f64) -> Sale {
+        Sale {
+            sold_at: now() - Duration::hours(hours_ago),
+            bitcoin: btc(bitcoin),
+            dai: dai::Amount::zero(),
+        }
+    }
+
+    fn now() -> DateTime<Utc> {
+        DateTime::from_str("2020-07-10T08:00:00Z").unwrap()
+    }
+
+    fn max_btc(bitcoin: f64) -> config::MaxVolume {
+        config::MaxVolume {
+            bitcoin: Some(btc(bitcoin)),
+            dai: None,
+        }
+    }
+
+    #[test]
+    fn sale_within_the_limit_is_recorded() {
+        let mut limits = VolumeLimits::new(max_btc(1.0), vec![sale(1, 0.5)]);
+
+        assert!(limits.allow(&sale(0, 0.5)));
+        limits.record(sale(0, 0.5));
+        assert!(!limits.allow(&sale(0, 0.1)));
+    }
+
+    #[test]
+    fn sales_older_than_24_hours_are_not_counted() {
+        let mut limits = VolumeLimits::new(max_btc(1.0), vec![sale(25, 1.0)]);
+
+        assert!(limits.allow(&sale(0, 1.0)));
+    }
+
+    #[test]
+    fn dai_limit_does_not_restrict_bitcoin_sales() {
+        let mut limits = VolumeLimits::new(
+            config::MaxVolume {
+                bitcoin: None,
+                dai: Some(dai(100.0)),
+            },
+            vec![],
+        );
+
+        assert!(limits.allow(&sale(0, 10.0)));
+        assert!(!limits.allow(&Sale {
+            sold_at: now(),
+            bitcoin: bitcoin::Amount::ZERO,
+            dai: dai(100.5),
+        }));
+    }
+
+    #[test]
+    fn sale_round_trips_through_the_database_record() {
+        let sale = Sale {
+            sold_at: now(),
+            bitcoin: btc(0.3),
+            dai: dai(1_234.5),
+        };
+
+        let sold_volume = sale.clone().into_sold_volume(SwapId::default());
+
+        assert_eq!(Sale::try_from(sold_volume).unwrap(), sale);
+    }
+}
@@ -0,0 +1,383 @@
+//! Metrics served in the Prometheus text format on the API's `/metrics`.
+//!
+//! The spread captured by each trade is compared to the configured spread,
+//! both are in permyriad. Besides the running sum and count per position, a
+//! mean over the last trades is exported so it can be graphed without
+//! querying over a time range.
+//!
+//! The inventory is exported as last assessed for the rebalance
+//! recommendations, if configured.
+//!
+//! The swaps are counted by how they ended as recorded in the database, so
+//! the counts carry over restarts.
+//!
+//! With the `otlp` feature, the metrics can also be pushed to an OpenTelemetry
+//! collector, see `[telemetry]`.
+
+#[cfg(feature = "otlp")]
+pub mod otlp;
+
+use crate::{
+    history::{Position, Trade},
+    maker::Assessment,
+    order::Symbol,
+    swap::SwapOutcome,
+    Spread,
+};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::Write,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+/// The number of trades the rolling mean of the captured spread is computed
+/// over.
+const ROLLING_WINDOW: usize = 20;
+
+#[derive(Clone, Debug)]
+pub struct Metrics {
+    configured_spread: Spread,
+    captured_spread: Arc<Mutex<CapturedSpread>>,
+    connected_peers: Arc<AtomicUsize>,
+    inventory: Arc<Mutex<Option<Assessment>>>,
+}
+
+#[derive(Debug, Default)]
+struct CapturedSpread {
+    buy: SpreadAggregate,
+    sell: SpreadAggregate,
+}
+
+#[derive(Debug, Default)]
+struct SpreadAggregate {
+    sum: f64,
+    count: u64,
+    recent: VecDeque<f64>,
+}
+
+impl SpreadAggregate {
+    fn record(&mut self, spread: f64) {
+        self.sum += spread;
+        self.count += 1;
+
+        if self.recent.len() == ROLLING_WINDOW {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(spread);
+    }
+
+    fn last(&self) -> Option<f64> {
+        self.recent.back().copied()
+    }
+
+    fn rolling_mean(&self) -> Option<f64> {
+        let (sum, count) = self.recent.iter().fold((0.0, 0.0), |(sum, count), spread| {
+            (sum + spread, count + 1.0)
+        });
+
+        if count > 0.0 {
+            Some(sum / count)
+        } else {
+            None
+        }
+    }
+}
+
+impl Metrics {
+    pub fn new(configured_spread: Spread) -> Self {
+        Metrics {
+            configured_spread,
+            captured_spread: Default::default(),
+            connected_peers: Default::default(),
+            inventory: Default::default(),
+        }
+    }
+
+    pub fn set_connected_peers(&self, connected_peers: usize) {
+        self.connected_peers
+            .store(connected_peers, Ordering::Relaxed);
+    }
+
+    pub fn record_inventory(&self, assessment: Assessment) {
+        match self.inventory.lock() {
+            Ok(mut inventory) => *inventory = Some(assessment),
+            Err(_) => tracing::error!("Inventory metrics lock is poisoned"),
+        }
+    }
+
+    /// Record the spread captured by the trade, trades without one (refunded
+    /// or without mid-market rate) are ignored.
+    pub fn record_trade(&self, trade: &Trade) {
+        let spread = match trade
+            .captured_spread
+            .as_ref()
+            .and_then(|spread| spread.to_f64())
+        {
+            Some(spread) => spread,
+            None => return,
+        };
+
+        match self.captured_spread.lock() {
+            Ok(mut captured_spread) => match trade.position {
+                Position::Buy => captured_spread.buy.record(spread),
+                Position::Sell => captured_spread.sell.record(spread),
+            },
+            Err(_) => tracing::error!("Captured spread metrics lock is poisoned"),
+        }
+    }
+
+    /// Render the metrics in the Prometheus text exposition format.
+    pub fn render(&self, swap_outcomes: &[SwapOutcome]) -> anyhow::Result<String> {
+        let captured_spread = self
+            .captured_spread
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Captured spread metrics lock is poisoned"))?;
+        let positions = [
+            ("buy", &captured_spread.buy),
+            ("sell", &captured_spread.sell),
+        ];
+
+        let mut out = String::new();
+
+        writeln!(
+            out,
+            "# HELP nectar_configured_spread_permyriad Spread applied to the mid-market rate when publishing orders."
+        )?;
+        writeln!(out, "# TYPE nectar_configured_spread_permyriad gauge")?;
+        writeln!(
+            out,
+            "nectar_configured_spread_permyriad {}",
+            self.configured_spread.permyriad()
+        )?;
+
+        writeln!(
+            out,
+            "# HELP nectar_captured_spread_permyriad Spread captured by trades compared to the mid-market rate when the order was matched."
+        )?;
+        writeln!(out, "# TYPE nectar_captured_spread_permyriad summary")?;
+        for (position, aggregate) in positions.iter() {
+            writeln!(
+                out,
+                "nectar_captured_spread_permyriad_sum{{position=\"{}\"}} {}",
+                position, aggregate.sum
+            )?;
+            writeln!(
+                out,
+                "nectar_captured_spread_permyriad_count{{position=\"{}\"}} {}",
+                position, aggregate.count
+            )?;
+        }
+
+        writeln!(
+            out,
+            "# HELP nectar_last_captured_spread_permyriad Spread captured by the last trade."
+        )?;
+        writeln!(out, "# TYPE nectar_last_captured_spread_permyriad gauge")?;
+        for (position, aggregate) in positions.iter() {
+            if let Some(last) = aggregate.last() {
+                writeln!(
+                    out,
+                    "nectar_last_captured_spread_permyriad{{position=\"{}\"}} {}",
+                    position, last
+                )?;
+            }
+        }
+
+        writeln!(
+            out,
+            "# HELP nectar_rolling_captured_spread_permyriad Mean spread captured by the last {} trades.",
+            ROLLING_WINDOW
+        )?;
+        writeln!(out, "# TYPE nectar_rolling_captured_spread_permyriad gauge")?;
+        for (position, aggregate) in positions.iter() {
+            if let Some(mean) = aggregate.rolling_mean() {
+                writeln!(
+                    out,
+                    "nectar_rolling_captured_spread_permyriad{{position=\"{}\"}} {}",
+                    position, mean
+                )?;
+            }
+        }
+
+        writeln!(
+            out,
+            "# HELP nectar_connected_peers Number of peers nectar is connected to."
+        )?;
+        writeln!(out, "# TYPE nectar_connected_peers gauge")?;
+        writeln!(
+            out,
+            "nectar_connected_peers {}",
+            self.connected_peers.load(Ordering::Relaxed)
+        )?;
+
+        let mut swaps: BTreeMap<&str, u64> = ["completed", "refunded", "aborted", "failed"]
+            .iter()
+            .map(|kind| (*kind, 0))
+            .collect();
+        for outcome in swap_outcomes {
+            *swaps.entry(outcome.kind()).or_default() += 1;
+        }
+        writeln!(
+            out,
+            "# HELP nectar_swaps_total Number of swaps by how they ended."
+        )?;
+        writeln!(out, "# TYPE nectar_swaps_total counter")?;
+        for (outcome, count) in swaps {
+            writeln!(
+                out,
+                "nectar_swaps_total{{outcome=\"{}\"}} {}",
+                outcome, count
+            )?;
+        }
+
+        let inventory = self
+            .inventory
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Inventory metrics lock is poisoned"))?;
+        if let Some(assessment) = inventory.as_ref() {
+            writeln!(
+                out,
+                "# HELP nectar_inventory_bitcoin_share_permyriad Share of the inventory held in bitcoin, valued at the mid-market rate."
+            )?;
+            writeln!(out, "# TYPE nectar_inventory_bitcoin_share_permyriad gauge")?;
+            writeln!(
+                out,
+                "nectar_inventory_bitcoin_share_permyriad {}",
+                assessment.bitcoin_share
+            )?;
+
+            if let Some(drift) = assessment.drift {
+                writeln!(
+                    out,
+                    "# HELP nectar_inventory_drift_permyriad Change of the bitcoin share of the inventory over the last 24 hours."
+                )?;
+                writeln!(out, "# TYPE nectar_inventory_drift_permyriad gauge")?;
+                writeln!(out, "nectar_inventory_drift_permyriad {}", drift)?;
+            }
+
+            writeln!(
+                out,
+                "# HELP nectar_rebalance_recommended Whether moving funds to the asset is recommended as it is depleted."
+            )?;
+            writeln!(out, "# TYPE nectar_rebalance_recommended gauge")?;
+            let depleted = assessment
+                .recommendation
+                .as_ref()
+                .map(|recommendation| recommendation.depleted());
+            for symbol in &[Symbol::Btc, Symbol::Dai] {
+                writeln!(
+                    out,
+                    "nectar_rebalance_recommended{{asset=\"{}\"}} {}",
+                    symbol,
+                    u8::from(depleted == Some(*symbol))
+                )?;
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ethereum::dai, maker::Recommendation};
+
+    #[test]
+    fn rolling_mean_only_covers_the_last_trades() {
+        let mut aggregate = SpreadAggregate::default();
+
+        aggregate.record(1_000.0);
+        for _ in 0..ROLLING_WINDOW {
+            aggregate.record(100.0);
+        }
+
+        assert_eq!(aggregate.count, 21);
+        assert_eq!(aggregate.sum.to_string(), "3000");
+        assert_eq!(aggregate.last(), Some(100.0));
+        assert_eq!(aggregate.rolling_mean(), Some(100.0));
+    }
+
+    #[test]
+    fn render_aggregates_per_position() {
+        let metrics = Metrics::new(Spread::new(500).unwrap());
+        metrics.captured_spread.lock().unwrap().sell.record(450.5);
+        metrics.set_connected_peers(3);
+
+        let rendered = metrics
+            .render(&[
+                SwapOutcome::Completed,
+                SwapOutcome::AbortedBeforeFund {
+                    reason: "Alice failed to fund.".to_owned(),
+                },
+                SwapOutcome::Completed,
+            ])
+            .unwrap();
+
+        assert!(rendered.contains("nectar_configured_spread_permyriad 500\n"));
+        assert!(
+            rendered.contains("nectar_captured_spread_permyriad_sum{position=\"sell\"} 450.5\n")
+        );
+        assert!(rendered.contains("nectar_captured_spread_permyriad_count{position=\"sell\"} 1\n"));
+        assert!(rendered.contains("nectar_captured_spread_permyriad_count{position=\"buy\"} 0\n"));
+        assert!(
+            rendered.contains("nectar_last_captured_spread_permyriad{position=\"sell\"} 450.5\n")
+        );
+        assert!(!rendered.contains("nectar_last_captured_spread_permyriad{position=\"buy\"}"));
+        assert!(rendered.contains("nectar_connected_peers 3\n"));
+        assert!(rendered.contains("nectar_swaps_total{outcome=\"completed\"} 2\n"));
+        assert!(rendered.contains("nectar_swaps_total{outcome=\"aborted\"} 1\n"));
+        assert!(rendered.contains("nectar_swaps_total{outcome=\"failed\"} 0\n"));
+        assert!(!rendered.contains("nectar_inventory_bitcoin_share_permyriad"));
+    }
+
+    #[test]
+    fn bob_hbit_herc20_trade_captures_a_buy_spread() {
+        use crate::{
+            command::into_history_trade,
+            history::Outcome,
+            swap::{Settlement, SwapKind, SwapParams},
+            StaticStub,
+        };
+
+        // Bob in a HbitHerc20 swap locks 4 DAI for 0.12345678 BTC
+        let trade = into_history_trade(
+            libp2p::PeerId::random(),
+            SwapKind::HbitHerc20(SwapParams {
+                mid_market_rate: Some(crate::rate::rate(10_000.0)),
+                ..SwapParams::static_stub()
+            }),
+            Outcome::Redeemed,
+            SwapOutcome::Completed,
+            Settlement::default(),
+        );
+        let metrics = Metrics::new(Spread::new(500).unwrap());
+
+        metrics.record_trade(&trade);
+
+        let captured_spread = metrics.captured_spread.lock().unwrap();
+        assert_eq!(captured_spread.buy.last(), Some(9967.59));
+        assert_eq!(captured_spread.sell.count, 0);
+    }
+
+    #[test]
+    fn render_the_last_inventory_assessment() {
+        let metrics = Metrics::new(Spread::new(500).unwrap());
+        metrics.record_inventory(Assessment {
+            bitcoin_share: 2_000,
+            target_bitcoin_share: 5_000,
+            drift: Some(-500),
+            recommendation: Some(Recommendation::BuyBitcoin(dai::Amount::zero())),
+        });
+
+        let rendered = metrics.render(&[]).unwrap();
+
+        assert!(rendered.contains("nectar_inventory_bitcoin_share_permyriad 2000\n"));
+        assert!(rendered.contains("nectar_inventory_drift_permyriad -500\n"));
+        assert!(rendered.contains("nectar_rebalance_recommended{asset=\"BTC\"} 1\n"));
+        assert!(rendered.contains("nectar_rebalance_recommended{asset=\"DAI\"} 0\n"));
+    }
+}
@@ -0,0 +1,289 @@
+//! Push of the metrics to an OpenTelemetry collector over OTLP/HTTP, encoded
+//! as JSON. The metrics are exported as rendered for `/metrics`: gauges as
+//! gauges, counters and the sums and counts of summaries as cumulative sums.
+
+use super::Metrics;
+use crate::swap::Database;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use url::Url;
+
+const EXPORT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// `AGGREGATION_TEMPORALITY_CUMULATIVE` of the OTLP metrics protocol.
+const CUMULATIVE: u8 = 2;
+
+pub async fn export(metrics: Metrics, db: Arc<Database>, endpoint: Url) {
+    let client = reqwest::Client::new();
+
+    loop {
+        tokio::time::delay_for(EXPORT_INTERVAL).await;
+
+        let request = match db
+            .swap_outcomes()
+            .and_then(|swap_outcomes| metrics.render(&swap_outcomes))
+            .and_then(|rendered| request(&rendered, Utc::now()))
+        {
+            Ok(request) => request,
+            Err(e) => {
+                tracing::warn!("Could not collect the metrics to export: {:#}", e);
+                continue;
+            }
+        };
+
+        let response = client
+            .post(endpoint.clone())
+            .json(&request)
+            .send()
+            .await
+            .and_then(|res| res.error_for_status());
+        if let Err(e) = response {
+            tracing::warn!("Could not export the metrics over OTLP: {}", e);
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportMetricsServiceRequest {
+    resource_metrics: Vec<ResourceMetrics>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ResourceMetrics {
+    resource: Resource,
+    instrumentation_library_metrics: Vec<InstrumentationLibraryMetrics>,
+}
+
+#[derive(Debug, Serialize)]
+struct Resource {
+    attributes: Vec<Attribute>,
+}
+
+#[derive(Clone, Copy, Debug, Serialize)]
+struct Attribute {
+    key: &'static str,
+    value: AnyValue,
+}
+
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AnyValue {
+    string_value: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct InstrumentationLibraryMetrics {
+    instrumentation_library: InstrumentationLibrary,
+    metrics: Vec<Metric>,
+}
+
+#[derive(Clone, Copy, Debug, Serialize)]
+struct InstrumentationLibrary {
+    name: &'static str,
+    version: &'static str,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Metric {
+    name: String,
+    description: String,
+    #[serde(flatten)]
+    data: Data,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+enum Data {
+    #[serde(rename_all = "camelCase")]
+    DoubleGauge { data_points: Vec<DataPoint> },
+    #[serde(rename_all = "camelCase")]
+    DoubleSum {
+        data_points: Vec<DataPoint>,
+        aggregation_temporality: u8,
+        is_monotonic: bool,
+    },
+}
+
+impl Data {
+    fn data_points(&mut self) -> &mut Vec<DataPoint> {
+        match self {
+            Data::DoubleGauge { data_points } | Data::DoubleSum { data_points, .. } => data_points,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DataPoint {
+    labels: Vec<Label>,
+    /// A uint64, encoded as string in JSON.
+    time_unix_nano: String,
+    value: f64,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct Label {
+    key: String,
+    value: String,
+}
+
+/// Convert the metrics rendered in the Prometheus text format to an OTLP
+/// export request.
+fn request(rendered: &str, now: DateTime<Utc>) -> anyhow::Result<ExportMetricsServiceRequest> {
+    let time_unix_nano = now.timestamp_nanos().to_string();
+    let mut descriptions = HashMap::new();
+    let mut types = HashMap::new();
+    let mut metrics: Vec<Metric> = Vec::new();
+
+    for line in rendered.lines() {
+        if let Some(help) = line.strip_prefix("# HELP ") {
+            let mut parts = help.splitn(2, ' ');
+            if let (Some(name), Some(description)) = (parts.next(), parts.next()) {
+                descriptions.insert(name, description);
+            }
+            continue;
+        }
+        if let Some(kind) = line.strip_prefix("# TYPE ") {
+            let mut parts = kind.splitn(2, ' ');
+            if let (Some(name), Some(kind)) = (parts.next(), parts.next()) {
+                types.insert(name, kind);
+            }
+            continue;
+        }
+
+        let (series, value) = match line.rfind(' ') {
+            Some(index) => (&line[..index], &line[index + 1..]),
+            None => continue,
+        };
+        let value = value
+            .parse::<f64>()
+            .map_err(|e| anyhow::anyhow!("Invalid value of {}: {}", series, e))?;
+        let (name, labels) = match series.find('{') {
+            Some(index) => (&series[..index], labels(&series[index..])?),
+            None => (series, Vec::new()),
+        };
+
+        // The sum and count of a summary are named after the metric family
+        let family = match types.get(name) {
+            Some(_) => name,
+            None => name
+                .strip_suffix("_sum")
+                .or_else(|| name.strip_suffix("_count"))
+                .unwrap_or(name),
+        };
+
+        let data_point = DataPoint {
+            labels,
+            time_unix_nano: time_unix_nano.clone(),
+            value,
+        };
+        match metrics.iter_mut().find(|metric| metric.name == name) {
+            Some(metric) => metric.data.data_points().push(data_point),
+            None => metrics.push(Metric {
+                name: name.to_owned(),
+                description: descriptions
+                    .get(family)
+                    .map_or_else(String::new, |description| (*description).to_owned()),
+                data: match types.get(family) {
+                    Some(&"gauge") => Data::DoubleGauge {
+                        data_points: vec![data_point],
+                    },
+                    _ => Data::DoubleSum {
+                        data_points: vec![data_point],
+                        aggregation_temporality: CUMULATIVE,
+                        is_monotonic: true,
+                    },
+                },
+            }),
+        }
+    }
+
+    Ok(ExportMetricsServiceRequest {
+        resource_metrics: vec![ResourceMetrics {
+            resource: Resource {
+                attributes: vec![Attribute {
+                    key: "service.name",
+                    value: AnyValue {
+                        string_value: "nectar",
+                    },
+                }],
+            },
+            instrumentation_library_metrics: vec![InstrumentationLibraryMetrics {
+                instrumentation_library: InstrumentationLibrary {
+                    name: "nectar",
+                    version: env!("CARGO_PKG_VERSION"),
+                },
+                metrics,
+            }],
+        }],
+    })
+}
+
+/// Parse labels such as `{position="buy"}`, the values of ours contain
+/// neither commas nor quotes.
+fn labels(labels: &str) -> anyhow::Result<Vec<Label>> {
+    let labels = labels
+        .strip_prefix('{')
+        .and_then(|labels| labels.strip_suffix('}'))
+        .ok_or_else(|| anyhow::anyhow!("Invalid labels {}", labels))?;
+
+    labels
+        .split(',')
+        .map(|label| {
+            let mut parts = label.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) => Ok(Label {
+                    key: key.to_owned(),
+                    value: value.trim_matches('"').to_owned(),
+                }),
+                _ => Err(anyhow::anyhow!("Invalid label {}", label)),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{swap::SwapOutcome, Spread};
+
+    #[test]
+    fn rendered_metrics_are_converted_by_type() {
+        let metrics = Metrics::new(Spread::new(500).unwrap());
+        metrics.captured_spread.lock().unwrap().sell.record(450.5);
+        let rendered = metrics.render(&[SwapOutcome::Completed]).unwrap();
+
+        let request = request(&rendered, Utc::now()).unwrap();
+        let metrics = &request.resource_metrics[0].instrumentation_library_metrics[0].metrics;
+        let metric = |name: &str| metrics.iter().find(|metric| metric.name == name).unwrap();
+
+        assert!(matches!(
+            &metric("nectar_configured_spread_permyriad").data,
+            Data::DoubleGauge { data_points } if data_points[0].value.to_string() == "500"
+        ));
+        assert!(matches!(
+            &metric("nectar_captured_spread_permyriad_sum").data,
+            Data::DoubleSum { data_points, is_monotonic: true, .. } if data_points.len() == 2
+        ));
+        assert_eq!(
+            metric("nectar_captured_spread_permyriad_count").description,
+            "Spread captured by trades compared to the mid-market rate when the order was matched."
+        );
+        assert!(matches!(
+            &metric("nectar_swaps_total").data,
+            Data::DoubleSum { data_points, .. } if data_points.iter().any(|data_point| {
+                data_point.value.to_string() == "1"
+                    && data_point.labels
+                        == vec![Label {
+                            key: "outcome".to_owned(),
+                            value: "completed".to_owned(),
+                        }]
+            })
+        ));
+    }
+}
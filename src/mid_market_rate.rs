@@ -1,12 +1,121 @@
-use crate::Rate;
-use std::convert::TryInto;
+use crate::{config, Rate};
+use futures::future::join_all;
+use num::{BigUint, ToPrimitive};
+use std::time::Duration;
 
-/// Get mid-market rate for the trading pair BTC-DAI.
+pub use bitfinex::Bitfinex;
+pub use coinbase::Coinbase;
+pub use kraken::Kraken;
+
+/// A source of the mid-market rate for the trading pair BTC-DAI.
+#[async_trait::async_trait]
+pub trait RateSource: std::fmt::Debug + Send + Sync {
+    fn name(&self) -> &'static str;
+
+    async fn mid_market_rate(&self) -> anyhow::Result<MidMarketRate>;
+}
+
+/// The median of the mid-market rates of several sources.
 ///
-/// Currently, this function only delegates to Kraken. Eventually, it
-/// could return a value based on multiple sources.
-pub async fn get_btc_dai_mid_market_rate() -> anyhow::Result<MidMarketRate> {
-    kraken::get_btc_dai_mid_market_rate().await
+/// Fails if more than one source cannot be reached within the request
+/// timeout, a single source being down is tolerated.
+#[derive(Debug)]
+pub struct Aggregate {
+    sources: Vec<Box<dyn RateSource>>,
+    request_timeout: Duration,
+}
+
+impl Aggregate {
+    pub fn new(sources: Vec<Box<dyn RateSource>>, request_timeout: Duration) -> Self {
+        Aggregate {
+            sources,
+            request_timeout,
+        }
+    }
+}
+
+impl From<config::Rate> for Aggregate {
+    fn from(rate: config::Rate) -> Self {
+        let sources = rate
+            .exchanges
+            .into_iter()
+            .map(|exchange| -> Box<dyn RateSource> {
+                match exchange {
+                    config::Exchange::Kraken => Box::new(Kraken),
+                    config::Exchange::Coinbase => Box::new(Coinbase),
+                    config::Exchange::Bitfinex => Box::new(Bitfinex),
+                }
+            })
+            .collect();
+
+        Aggregate::new(sources, Duration::from_secs(rate.request_timeout_secs))
+    }
+}
+
+impl Default for Aggregate {
+    fn default() -> Self {
+        config::Rate::default().into()
+    }
+}
+
+#[async_trait::async_trait]
+impl RateSource for Aggregate {
+    fn name(&self) -> &'static str {
+        "aggregate"
+    }
+
+    async fn mid_market_rate(&self) -> anyhow::Result<MidMarketRate> {
+        let results = join_all(self.sources.iter().map(|source| async move {
+            tokio::time::timeout(self.request_timeout, source.mid_market_rate())
+                .await
+                .map_err(|_| anyhow::anyhow!("Request timed out"))?
+        }))
+        .await;
+
+        let mut rates = Vec::with_capacity(results.len());
+        for (source, result) in self.sources.iter().zip(results) {
+            match result {
+                Ok(rate) => rates.push(rate),
+                Err(e) => tracing::warn!("Could not get rate from {}: {:#}", source.name(), e),
+            }
+        }
+
+        let required = self.sources.len().saturating_sub(1).max(1);
+        if rates.len() < required {
+            anyhow::bail!(
+                "Only {} out of {} rate sources are available",
+                rates.len(),
+                self.sources.len()
+            )
+        }
+
+        median(rates).ok_or_else(|| anyhow::anyhow!("No rate source configured"))
+    }
+}
+
+fn median(rates: Vec<MidMarketRate>) -> Option<MidMarketRate> {
+    let mut integers = rates
+        .into_iter()
+        .map(|rate| Rate::from(rate).integer())
+        .collect::<Vec<_>>();
+    integers.sort();
+
+    let middle = integers.len() / 2;
+    let median = match integers.len() {
+        0 => return None,
+        len if len % 2 == 1 => integers[middle].clone(),
+        _ => (&integers[middle - 1] + &integers[middle]) / BigUint::from(2u8),
+    };
+
+    // The median is bounded by the rates it is computed from
+    median
+        .to_u64()
+        .map(|integer| MidMarketRate::new(Rate::new(integer)))
+}
+
+/// Mid-market rate of an order book.
+fn mid_market_rate(ask: Rate, bid: Rate) -> MidMarketRate {
+    MidMarketRate::new(Rate::mean(ask, bid))
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -33,7 +142,6 @@ impl From<MidMarketRate> for Rate {
 
 mod kraken {
     use super::*;
-    use crate::float_maths::truncate;
     use serde::{de::Error, Deserialize};
     use std::convert::TryFrom;
 
@@ -42,35 +150,35 @@ mod kraken {
     /// More info here: https://www.kraken.com/features/api
     /// Rate limits: For public API a frequency of 1 call per second is
     /// acceptable, More info here: https://support.kraken.com/hc/en-us/articles/206548367-What-are-the-REST-API-rate-limits-
-    pub async fn get_btc_dai_mid_market_rate() -> anyhow::Result<MidMarketRate> {
-        let ask_and_bid = reqwest::get("https://api.kraken.com/0/public/Ticker?pair=XBTDAI")
-            .await?
-            .json::<TickerResponse>()
-            .await
-            .map(|response| response.result.xbtdai)?;
-        let rate = ask_and_bid.try_into()?;
+    #[derive(Clone, Copy, Debug, Default)]
+    pub struct Kraken;
+
+    #[async_trait::async_trait]
+    impl RateSource for Kraken {
+        fn name(&self) -> &'static str {
+            "Kraken"
+        }
 
-        Ok(rate)
+        async fn mid_market_rate(&self) -> anyhow::Result<MidMarketRate> {
+            let ask_and_bid = reqwest::get("https://api.kraken.com/0/public/Ticker?pair=XBTDAI")
+                .await?
+                .json::<TickerResponse>()
+                .await
+                .map(|response| response.result.xbtdai)?;
+            Ok(ask_and_bid.into())
+        }
     }
 
     #[derive(Clone, Copy, Debug, Deserialize)]
     #[serde(try_from = "TickerData")]
     pub struct AskAndBid {
-        pub ask: f64,
-        pub bid: f64,
+        pub ask: Rate,
+        pub bid: Rate,
     }
 
-    impl TryFrom<AskAndBid> for MidMarketRate {
-        type Error = anyhow::Error;
-
-        fn try_from(AskAndBid { ask, bid }: AskAndBid) -> anyhow::Result<Self> {
-            let value = (bid + ask) / 2f64;
-
-            // `Rate::try_from`'s maximum precision is 9 decimal places
-            let value = truncate(value, 9);
-            let value = Rate::try_from(value)?;
-
-            Ok(Self { 0: value })
+    impl From<AskAndBid> for MidMarketRate {
+        fn from(AskAndBid { ask, bid }: AskAndBid) -> Self {
+            mid_market_rate(ask, bid)
         }
     }
 
@@ -108,10 +216,10 @@ mod kraken {
 
             Ok(AskAndBid {
                 ask: ask_price
-                    .parse::<f64>()
+                    .parse::<Rate>()
                     .map_err(serde_json::Error::custom)?,
                 bid: bid_price
-                    .parse::<f64>()
+                    .parse::<Rate>()
                     .map_err(serde_json::Error::custom)?,
             })
         }
@@ -169,3 +277,232 @@ mod kraken {
         }
     }
 }
+
+mod coinbase {
+    use super::*;
+    use serde::Deserialize;
+
+    /// Fetch mid-market rate for the trading pair BTC-DAI from Coinbase Pro.
+    ///
+    /// Coinbase does not list BTC-DAI, the rate is crossed from the BTC-USD
+    /// and DAI-USD order books.
+    ///
+    /// More info here: https://docs.pro.coinbase.com/#get-product-ticker
+    /// Rate limits: 3 requests per second per IP for public endpoints.
+    #[derive(Clone, Copy, Debug, Default)]
+    pub struct Coinbase;
+
+    #[async_trait::async_trait]
+    impl RateSource for Coinbase {
+        fn name(&self) -> &'static str {
+            "Coinbase"
+        }
+
+        async fn mid_market_rate(&self) -> anyhow::Result<MidMarketRate> {
+            let (btc_usd, dai_usd) = futures::try_join!(ticker("BTC-USD"), ticker("DAI-USD"))?;
+
+            cross_rate(btc_usd, dai_usd)
+        }
+    }
+
+    async fn ticker(product: &str) -> anyhow::Result<Ticker> {
+        let ticker = reqwest::get(&format!(
+            "https://api.pro.coinbase.com/products/{}/ticker",
+            product
+        ))
+        .await?
+        .json::<Ticker>()
+        .await?;
+
+        Ok(ticker)
+    }
+
+    fn cross_rate(btc_usd: Ticker, dai_usd: Ticker) -> anyhow::Result<MidMarketRate> {
+        let btc_usd = mid_market_rate(btc_usd.ask.parse()?, btc_usd.bid.parse()?);
+        let dai_usd = mid_market_rate(dai_usd.ask.parse()?, dai_usd.bid.parse()?);
+
+        let btc_dai = Rate::from(btc_usd)
+            .checked_div(dai_usd.into())
+            .ok_or_else(|| anyhow::anyhow!("DAI-USD rate is not positive"))?;
+
+        Ok(MidMarketRate::new(btc_dai))
+    }
+
+    #[derive(Deserialize)]
+    struct Ticker {
+        ask: String,
+        bid: String,
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        const BTC_USD_TICKER_EXAMPLE: &str = r#"{
+    "trade_id": 103581457,
+    "price": "10753.77",
+    "size": "0.00410925",
+    "time": "2020-09-14T06:28:46.417082Z",
+    "bid": "10753.76",
+    "ask": "10753.78",
+    "volume": "4961.93545305"
+}"#;
+
+        const DAI_USD_TICKER_EXAMPLE: &str = r#"{
+    "trade_id": 2381926,
+    "price": "1.0049",
+    "size": "49.77064239",
+    "time": "2020-09-14T06:27:11.152Z",
+    "bid": "1.0048",
+    "ask": "1.005",
+    "volume": "1030287.15836098"
+}"#;
+
+        #[test]
+        fn given_ticker_examples_computes_cross_rate() {
+            let btc_usd = serde_json::from_str::<Ticker>(BTC_USD_TICKER_EXAMPLE).unwrap();
+            let dai_usd = serde_json::from_str::<Ticker>(DAI_USD_TICKER_EXAMPLE).unwrap();
+
+            let rate = cross_rate(btc_usd, dai_usd).unwrap();
+
+            assert_eq!(Rate::from(rate).to_string(), "10701.3334660165");
+        }
+    }
+}
+
+mod bitfinex {
+    use super::*;
+
+    /// Fetch mid-market rate for the trading pair BTC-DAI from Bitfinex.
+    ///
+    /// Bitfinex lists DAI-BTC, the rate is the inverse of its mid-market rate.
+    ///
+    /// More info here: https://docs.bitfinex.com/reference#rest-public-ticker
+    /// Rate limits: 90 requests per minute for the ticker endpoint.
+    #[derive(Clone, Copy, Debug, Default)]
+    pub struct Bitfinex;
+
+    #[async_trait::async_trait]
+    impl RateSource for Bitfinex {
+        fn name(&self) -> &'static str {
+            "Bitfinex"
+        }
+
+        async fn mid_market_rate(&self) -> anyhow::Result<MidMarketRate> {
+            let ticker = reqwest::get("https://api-pub.bitfinex.com/v2/ticker/tDAIBTC")
+                .await?
+                .json::<Vec<serde_json::Number>>()
+                .await?;
+
+            inverse_rate(&ticker)
+        }
+    }
+
+    /// The ticker is an array starting with `[BID, BID_SIZE, ASK, ...]`. The
+    /// prices are parsed from their shortest decimal representation, the one
+    /// quoted.
+    fn inverse_rate(ticker: &[serde_json::Number]) -> anyhow::Result<MidMarketRate> {
+        let (bid, ask) = match ticker {
+            [bid, _, ask, ..] => (
+                bid.to_string().parse::<Rate>()?,
+                ask.to_string().parse::<Rate>()?,
+            ),
+            _ => anyhow::bail!("Unexpected ticker: {:?}", ticker),
+        };
+
+        // Buying DAI at the ask means selling BTC for 1/ask DAI
+        match (bid.inverse(), ask.inverse()) {
+            (Some(bid), Some(ask)) => Ok(mid_market_rate(bid, ask)),
+            _ => anyhow::bail!("Unexpected ticker: {:?}", ticker),
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        const TICKER_EXAMPLE: &str =
+            "[0.00009299,12845.1,0.000093327,10341.7,-0.000000233,-0.0025,0.000093097,1093.6,0.00009361,0.000092953]";
+
+        #[test]
+        fn given_ticker_example_computes_inverse_rate() {
+            let ticker = serde_json::from_str::<Vec<serde_json::Number>>(TICKER_EXAMPLE).unwrap();
+
+            let rate = inverse_rate(&ticker).unwrap();
+
+            assert_eq!(Rate::from(rate).to_string(), "10734.4286519244");
+        }
+
+        #[test]
+        fn given_empty_ticker_errors() {
+            assert!(inverse_rate(&[]).is_err());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    #[derive(Debug)]
+    struct Fixed(Option<f64>);
+
+    #[async_trait::async_trait]
+    impl RateSource for Fixed {
+        fn name(&self) -> &'static str {
+            "fixed"
+        }
+
+        async fn mid_market_rate(&self) -> anyhow::Result<MidMarketRate> {
+            match self.0 {
+                Some(rate) => Ok(MidMarketRate::new(Rate::try_from(rate)?)),
+                None => anyhow::bail!("source is down"),
+            }
+        }
+    }
+
+    fn aggregate(rates: &[Option<f64>]) -> Aggregate {
+        Aggregate::new(
+            rates
+                .iter()
+                .map(|rate| Box::new(Fixed(*rate)) as Box<dyn RateSource>)
+                .collect(),
+            Duration::from_secs(1),
+        )
+    }
+
+    fn rate(rate: f64) -> MidMarketRate {
+        MidMarketRate::new(Rate::try_from(rate).unwrap())
+    }
+
+    #[tokio::test]
+    async fn aggregate_is_the_median_of_the_sources() {
+        let aggregate = aggregate(&[Some(10_100.0), Some(9_900.0), Some(10_000.0)]);
+
+        let aggregated = aggregate.mid_market_rate().await.unwrap();
+
+        assert_eq!(aggregated, rate(10_000.0));
+    }
+
+    #[tokio::test]
+    async fn aggregate_tolerates_one_source_being_down() {
+        let aggregate = aggregate(&[Some(10_100.0), None, Some(9_900.0)]);
+
+        let aggregated = aggregate.mid_market_rate().await.unwrap();
+
+        assert_eq!(aggregated, rate(10_000.0));
+    }
+
+    #[tokio::test]
+    async fn aggregate_fails_if_two_sources_are_down() {
+        let aggregate = aggregate(&[Some(10_100.0), None, None]);
+
+        assert!(aggregate.mid_market_rate().await.is_err());
+    }
+
+    #[test]
+    fn median_of_no_rate_is_none() {
+        assert!(median(vec![]).is_none());
+    }
+}
@@ -1,8 +1,10 @@
 use crate::{
-    bitcoin, ethereum,
+    bitcoin,
+    config::SwapRole,
+    ethereum,
     order::BtcDaiOrderForm,
     swap::{Database, SwapKind, SwapParams},
-    SwapId,
+    Rate, SwapId,
 };
 use ::bitcoin::hashes::{sha256, Hash, HashEngine};
 use chrono::{NaiveDateTime, Utc};
@@ -20,17 +22,21 @@ use comit::{
 };
 use futures::Future;
 use libp2p::{
+    core::multiaddr::Protocol,
+    identify::{Identify, IdentifyEvent},
     identity::{ed25519, Keypair},
     swarm::{NetworkBehaviourAction, PollParameters},
-    NetworkBehaviour, PeerId,
+    Multiaddr, NetworkBehaviour, PeerId,
 };
 use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
 use std::{
     collections::VecDeque,
+    fmt,
     pin::Pin,
     str::FromStr,
     sync::Arc,
     task::{Context, Poll},
+    time::Instant,
 };
 use time::{Duration, OffsetDateTime};
 
@@ -38,6 +44,8 @@ pub type Swarm = libp2p::Swarm<Nectar>;
 
 pub const SEED_LENGTH: usize = 32;
 
+const IDENTIFY_PROTOCOL_VERSION: &str = "comit/1.0.0";
+
 pub fn new_swarm(
     seed: Seed,
     settings: &crate::config::Settings,
@@ -69,6 +77,9 @@ pub fn new_swarm(
         Swarm::listen_on(&mut swarm, addr.clone())
             .with_context(|| format!("Address is not supported: {:?}", addr))?;
     }
+    for addr in settings.network.external_addresses.clone() {
+        Swarm::add_external_address(&mut swarm, addr);
+    }
 
     Ok(swarm)
 }
@@ -94,16 +105,20 @@ pub struct SetupSwapContext {
     pub swap_id: SwapId,
     pub bitcoin_transient_key_index: u32,
     pub match_ref_point: OffsetDateTime,
+    pub mid_market_rate: Option<Rate>,
 }
 
 /// A `NetworkBehaviour` that delegates to the `Orderbook` and `SetupSwap`
-/// behaviours.
+/// behaviours. `Identify` announces our listen and external addresses to the
+/// peers, and reports the addresses they observe us at, which become
+/// external addresses if we are behind NAT.
 #[derive(NetworkBehaviour)]
 #[behaviour(out_event = "Event", poll_method = "poll")]
 #[allow(missing_debug_implementations)]
 pub struct Nectar {
     pub orderbook: orderbook::Orderbook,
     pub setup_swap: setup_swap::SetupSwap<SetupSwapContext>,
+    pub identify: Identify,
     #[behaviour(ignore)]
     seed: Seed,
     #[behaviour(ignore)]
@@ -123,6 +138,17 @@ pub struct Nectar {
 }
 
 impl Nectar {
+    /// Withdraw our orders and publish `orders` instead. The gossip messages
+    /// are queued back to back, before the swarm is polled again, so that
+    /// takes are never processed in between with only part of the orders
+    /// replaced.
+    pub fn replace_orders(&mut self, orders: Vec<comit::BtcDaiOrder>) {
+        self.orderbook.clear_own_orders();
+        for order in orders {
+            self.orderbook.publish(order);
+        }
+    }
+
     fn new(
         seed: Seed,
         dai_contract_address: ethereum::Address,
@@ -136,6 +162,11 @@ impl Nectar {
         Self {
             seed,
             orderbook: comit::network::Orderbook::new(peer_id, identity.clone()),
+            identify: Identify::new(
+                IDENTIFY_PROTOCOL_VERSION.to_string(),
+                format!("nectar/{}", env!("CARGO_PKG_VERSION")),
+                identity.public(),
+            ),
             identity,
             setup_swap: Default::default(),
             events: VecDeque::new(),
@@ -155,24 +186,7 @@ impl Nectar {
     }
 
     fn derive_secret_hash(&self, swap_id: SwapId) -> SecretHash {
-        let secret: Secret = self
-            .sha256_with_seed(&[b"SECRET", swap_id.as_bytes()])
-            .into();
-        SecretHash::new(secret)
-    }
-
-    fn sha256_with_seed(&self, slices: &[&[u8]]) -> [u8; 32] {
-        let mut engine = sha256::HashEngine::default();
-
-        engine.input(&self.seed.bytes());
-        engine.input(b"TRANSIENT_KEY");
-        for slice in slices {
-            engine.input(slice);
-        }
-
-        let hash = sha256::Hash::from_engine(engine);
-
-        hash.into_inner()
+        SecretHash::new(self.seed.derive_secret(swap_id))
     }
 
     fn ethereum_chain_id(&self) -> ethereum::ChainId {
@@ -435,6 +449,11 @@ impl
                     }
                 };
 
+                let secret = match exec_swap.our_role {
+                    Role::Alice => Some(self.seed.derive_secret(swap_id)),
+                    Role::Bob => None,
+                };
+
                 let swap_kind = match (exec_swap.our_role, exec_swap.swap_protocol) {
                     // Sell
                     (Role::Alice, setup_swap::SwapProtocol::HbitHerc20) => {
@@ -445,18 +464,21 @@ impl
                             ),
                             herc20_params: crate::swap::herc20::Params {
                                 asset: exec_swap.herc20.asset.clone(),
-                                redeem_identity: exec_swap.herc20.refund_identity,
-                                refund_identity: exec_swap.herc20.redeem_identity,
+                                redeem_identity: exec_swap.herc20.redeem_identity,
+                                refund_identity: exec_swap.herc20.refund_identity,
                                 expiry: exec_swap.herc20.expiry,
                                 secret_hash: exec_swap.herc20.secret_hash,
                                 chain_id: exec_swap.herc20.chain_id,
                             },
                             secret_hash: exec_swap.hbit.secret_hash,
+                            role: SwapRole::Alice,
+                            secret,
                             start_of_swap,
                             swap_id,
                             taker: ActivePeer {
                                 peer_id: exec_swap.peer_id,
                             },
+                            mid_market_rate: exec_swap.context.mid_market_rate,
                         })
                     }
                     // Buy
@@ -475,11 +497,14 @@ impl
                                 chain_id: exec_swap.herc20.chain_id,
                             },
                             secret_hash: exec_swap.hbit.secret_hash,
+                            role: SwapRole::Bob,
+                            secret,
                             start_of_swap,
                             swap_id,
                             taker: ActivePeer {
                                 peer_id: exec_swap.peer_id,
                             },
+                            mid_market_rate: exec_swap.context.mid_market_rate,
                         })
                     }
                     // Buy
@@ -498,11 +523,14 @@ impl
                                 chain_id: exec_swap.herc20.chain_id,
                             },
                             secret_hash: exec_swap.hbit.secret_hash,
+                            role: SwapRole::Alice,
+                            secret,
                             start_of_swap,
                             swap_id,
                             taker: ActivePeer {
                                 peer_id: exec_swap.peer_id,
                             },
+                            mid_market_rate: exec_swap.context.mid_market_rate,
                         })
                     }
                     // Sell
@@ -521,11 +549,14 @@ impl
                                 chain_id: exec_swap.herc20.chain_id,
                             },
                             secret_hash: exec_swap.hbit.secret_hash,
+                            role: SwapRole::Bob,
+                            secret,
                             start_of_swap,
                             swap_id,
                             taker: ActivePeer {
                                 peer_id: exec_swap.peer_id,
                             },
+                            mid_market_rate: exec_swap.context.mid_market_rate,
                         })
                     }
                 };
@@ -538,6 +569,22 @@ impl
     }
 }
 
+impl libp2p::swarm::NetworkBehaviourEventProcess<IdentifyEvent> for Nectar {
+    fn inject_event(&mut self, event: IdentifyEvent) {
+        match event {
+            IdentifyEvent::Received {
+                peer_id,
+                observed_addr,
+                ..
+            } => tracing::debug!("{} observed us at {}", peer_id, observed_addr),
+            IdentifyEvent::Sent { .. } => {}
+            IdentifyEvent::Error { peer_id, error } => {
+                tracing::debug!("could not identify with {}: {}", peer_id, error)
+            }
+        }
+    }
+}
+
 struct TokioExecutor {
     handle: tokio::runtime::Handle,
 }
@@ -621,6 +668,166 @@ impl Seed {
             ed25519::SecretKey::from_bytes(hash.into_inner()).expect("we always pass 32 bytes");
         libp2p::identity::Keypair::Ed25519(key.into())
     }
+
+    /// The secret of a swap in which we are Alice, derived from the seed so
+    /// that it matches the secret hash sent along with the order match and
+    /// never needs to be stored.
+    pub fn derive_secret(&self, swap_id: SwapId) -> Secret {
+        let mut engine = sha256::HashEngine::default();
+
+        engine.input(&self.bytes());
+        engine.input(b"TRANSIENT_KEY");
+        engine.input(b"SECRET");
+        engine.input(swap_id.as_bytes());
+
+        let hash = sha256::Hash::from_engine(engine);
+        hash.into_inner().into()
+    }
+}
+
+impl fmt::Debug for Seed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Seed([*****])")
+    }
+}
+
+/// The peer id an address ends with, if any.
+pub fn peer_id_of(address: &Multiaddr) -> Option<PeerId> {
+    address.iter().find_map(|protocol| match protocol {
+        Protocol::P2p(hash) => PeerId::from_multihash(hash).ok(),
+        _ => None,
+    })
+}
+
+const MIN_REDIAL_BACKOFF: std::time::Duration = std::time::Duration::from_secs(5);
+const MAX_REDIAL_BACKOFF: std::time::Duration = std::time::Duration::from_secs(300);
+
+/// The peers of the configuration file, dialed on startup and redialed
+/// whenever disconnected. The backoff doubles with every dial until the peer
+/// is connected.
+#[derive(Debug, Clone)]
+pub struct StaticPeers {
+    peers: Vec<StaticPeer>,
+}
+
+#[derive(Debug, Clone)]
+struct StaticPeer {
+    address: Multiaddr,
+    peer_id: PeerId,
+    backoff: std::time::Duration,
+    next_dial: Option<Instant>,
+}
+
+impl StaticPeers {
+    /// Addresses without a peer id are ignored, they are rejected when
+    /// reading the configuration.
+    pub fn new(addresses: Vec<Multiaddr>) -> Self {
+        let peers = addresses
+            .into_iter()
+            .filter_map(|address| {
+                peer_id_of(&address).map(|peer_id| StaticPeer {
+                    address,
+                    peer_id,
+                    backoff: MIN_REDIAL_BACKOFF,
+                    next_dial: None,
+                })
+            })
+            .collect();
+
+        StaticPeers { peers }
+    }
+
+    pub fn dial_disconnected(&mut self, swarm: &mut Swarm, now: Instant) {
+        let due = self.due(|peer_id| Swarm::is_connected(&*swarm, peer_id), now);
+        for address in due {
+            tracing::debug!("Dialing {}", address);
+            if let Err(e) = Swarm::dial_addr(swarm, address.clone()) {
+                tracing::warn!("Could not dial {}: {}", address, e);
+            }
+        }
+    }
+
+    /// Dial all disconnected peers with the next check, e.g. once connected
+    /// to no peer at all.
+    pub fn reset_backoff(&mut self) {
+        for peer in self.peers.iter_mut() {
+            peer.backoff = MIN_REDIAL_BACKOFF;
+            peer.next_dial = None;
+        }
+    }
+
+    /// The addresses of the disconnected peers whose backoff elapsed.
+    fn due(&mut self, is_connected: impl Fn(&PeerId) -> bool, now: Instant) -> Vec<Multiaddr> {
+        let mut due = Vec::new();
+        for peer in self.peers.iter_mut() {
+            if is_connected(&peer.peer_id) {
+                peer.backoff = MIN_REDIAL_BACKOFF;
+                peer.next_dial = None;
+                continue;
+            }
+
+            if peer.next_dial.map_or(true, |next_dial| now >= next_dial) {
+                due.push(peer.address.clone());
+                peer.next_dial = Some(now + peer.backoff);
+                peer.backoff = std::cmp::min(peer.backoff * 2, MAX_REDIAL_BACKOFF);
+            }
+        }
+
+        due
+    }
+}
+
+/// Tracks for how long we have been connected to no peer.
+#[derive(Debug, Clone)]
+pub struct Connectivity {
+    /// Never isolated if `None`.
+    max_isolation: Option<std::time::Duration>,
+    disconnected_since: Option<Instant>,
+    isolated: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConnectivityChange {
+    /// Connected to no peer anymore.
+    Lost,
+    /// Connected to no peer for longer than the maximum isolation.
+    Isolated,
+    /// Connected to a peer again, after having been isolated or not.
+    Restored { was_isolated: bool },
+}
+
+impl Connectivity {
+    pub fn new(max_isolation: Option<std::time::Duration>) -> Self {
+        Connectivity {
+            max_isolation,
+            disconnected_since: None,
+            isolated: false,
+        }
+    }
+
+    pub fn update(&mut self, connected_peers: usize, now: Instant) -> Option<ConnectivityChange> {
+        if connected_peers > 0 {
+            self.disconnected_since.take()?;
+            let was_isolated = std::mem::replace(&mut self.isolated, false);
+            return Some(ConnectivityChange::Restored { was_isolated });
+        }
+
+        let disconnected_since = match self.disconnected_since {
+            Some(disconnected_since) => disconnected_since,
+            None => {
+                self.disconnected_since = Some(now);
+                return Some(ConnectivityChange::Lost);
+            }
+        };
+
+        match self.max_isolation {
+            Some(max_isolation) if !self.isolated && now - disconnected_since > max_isolation => {
+                self.isolated = true;
+                Some(ConnectivityChange::Isolated)
+            }
+            _ => None,
+        }
+    }
 }
 
 mod transport {
@@ -692,3 +899,69 @@ mod arbitrary {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disconnected_peer_is_redialed_with_increasing_backoff() {
+        let address: Multiaddr =
+            "/ip4/198.51.100.7/tcp/9939/p2p/QmUJF1AzhjUfDU1ifzkyuHy26SCnNHbPaVHpX1WYxYYgZg"
+                .parse()
+                .unwrap();
+        let mut peers = StaticPeers::new(vec![address.clone()]);
+        let start = Instant::now();
+        let disconnected = |_: &PeerId| false;
+
+        assert_eq!(peers.due(disconnected, start), vec![address.clone()]);
+        assert!(peers
+            .due(disconnected, start + MIN_REDIAL_BACKOFF / 2)
+            .is_empty());
+        assert_eq!(peers.due(disconnected, start + MIN_REDIAL_BACKOFF), vec![
+            address.clone()
+        ]);
+        assert!(peers
+            .due(disconnected, start + MIN_REDIAL_BACKOFF * 2)
+            .is_empty());
+
+        assert!(peers
+            .due(|_| true, start + MIN_REDIAL_BACKOFF * 2)
+            .is_empty());
+        assert_eq!(
+            peers.due(disconnected, start + MIN_REDIAL_BACKOFF * 2),
+            vec![address]
+        );
+    }
+
+    #[test]
+    fn isolated_once_disconnected_for_longer_than_the_maximum() {
+        let max_isolation = std::time::Duration::from_secs(60);
+        let mut connectivity = Connectivity::new(Some(max_isolation));
+        let start = Instant::now();
+
+        assert_eq!(connectivity.update(1, start), None);
+        assert_eq!(
+            connectivity.update(0, start),
+            Some(ConnectivityChange::Lost)
+        );
+        assert_eq!(connectivity.update(0, start + max_isolation), None);
+        assert_eq!(
+            connectivity.update(0, start + max_isolation * 2),
+            Some(ConnectivityChange::Isolated)
+        );
+        assert_eq!(connectivity.update(0, start + max_isolation * 3), None);
+        assert_eq!(
+            connectivity.update(2, start + max_isolation * 3),
+            Some(ConnectivityChange::Restored { was_isolated: true })
+        );
+        assert_eq!(connectivity.update(2, start + max_isolation * 3), None);
+    }
+
+    #[test]
+    fn address_without_peer_id_has_none() {
+        let address = "/ip4/198.51.100.7/tcp/9939".parse().unwrap();
+
+        assert!(peer_id_of(&address).is_none());
+    }
+}
@@ -1,12 +1,15 @@
-use crate::{bitcoin, ethereum::dai, Rate, Spread};
+use crate::{bitcoin, config::OrderRounding, ethereum::dai, Rate, Spread};
 use comit::{
     asset::{Bitcoin, Erc20Quantity},
     order::SwapProtocol,
     Position, Price, Quantity,
 };
-use std::cmp::min;
+use std::cmp::{max, min};
 
-#[derive(Debug, Copy, Clone, strum_macros::Display)]
+/// Outputs below this amount are non-standard and not relayed by the nodes.
+const BITCOIN_DUST_LIMIT_SAT: u64 = 546;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, strum_macros::Display)]
 #[strum(serialize_all = "UPPERCASE")]
 pub enum Symbol {
     Btc,
@@ -39,6 +42,8 @@ impl BtcDaiOrderForm {
         base_fees: bitcoin::Amount,
         base_reserved_funds: bitcoin::Amount,
         max_amount: Option<bitcoin::Amount>,
+        min_amount: Option<bitcoin::Amount>,
+        rounding: OrderRounding,
         mid_market_rate: Rate,
         spread: Spread,
     ) -> anyhow::Result<BtcDaiOrderForm> {
@@ -61,6 +66,10 @@ impl BtcDaiOrderForm {
             Some(max_amount) => min(base_balance - base_reserved_funds, max_amount) - base_fees,
             None => base_balance - base_reserved_funds - base_fees,
         };
+        let base_amount = rounding.round_bitcoin(base_amount);
+        if base_amount < min_bitcoin_amount(min_amount) {
+            anyhow::bail!(BelowMinimumAmount(Symbol::Btc))
+        }
 
         let rate = spread.apply(mid_market_rate, Position::Sell)?;
 
@@ -76,6 +85,8 @@ impl BtcDaiOrderForm {
         quote_balance: dai::Amount,
         quote_reserved_funds: dai::Amount,
         max_amount: Option<dai::Amount>,
+        min_amount: Option<dai::Amount>,
+        rounding: OrderRounding,
         mid_market_rate: Rate,
         spread: Spread,
     ) -> anyhow::Result<BtcDaiOrderForm> {
@@ -87,9 +98,18 @@ impl BtcDaiOrderForm {
             Some(max_amount) => min(quote_balance - quote_reserved_funds, max_amount),
             None => quote_balance - quote_reserved_funds,
         };
+        let quote_amount = rounding.round_dai(&quote_amount);
+        if let Some(min_amount) = min_amount {
+            if quote_amount < min_amount {
+                anyhow::bail!(BelowMinimumAmount(Symbol::Dai))
+            }
+        }
 
         let rate = spread.apply(mid_market_rate, Position::Buy)?;
-        let base_amount = quote_amount.worth_in(rate)?;
+        let base_amount = rounding.round_bitcoin(quote_amount.worth_in(rate)?);
+        if base_amount < min_bitcoin_amount(None) {
+            anyhow::bail!(BelowMinimumAmount(Symbol::Btc))
+        }
 
         Ok(BtcDaiOrderForm {
             position: Position::Buy,
@@ -129,10 +149,21 @@ pub struct InsufficientFunds(Symbol);
 #[error("The maximum amount for an order cannot be smaller than the maximum fee.")]
 pub struct MaxAmountSmallerThanMaxFee;
 
+#[derive(Debug, Copy, Clone, thiserror::Error)]
+#[error("The {0} amount of the order would be below the minimum.")]
+pub struct BelowMinimumAmount(Symbol);
+
 #[derive(Debug, Copy, Clone, thiserror::Error)]
 #[error("Amounts to large to be added.")]
 pub struct Overflow;
 
+/// The minimum amount of bitcoin of an order, never below the dust limit.
+fn min_bitcoin_amount(min_amount: Option<bitcoin::Amount>) -> bitcoin::Amount {
+    let dust_limit = bitcoin::Amount::from_sat(BITCOIN_DUST_LIMIT_SAT);
+
+    min_amount.map_or(dust_limit, |min_amount| max(min_amount, dust_limit))
+}
+
 pub trait LockedFunds {
     type Amount;
     fn locked_funds(&self) -> Self::Amount;
@@ -193,6 +224,8 @@ mod tests {
             btc(0.0),
             btc(0.0),
             Some(btc(100.0)),
+            None,
+            OrderRounding::default(),
             rate,
             Spread::new(0).unwrap(),
         )
@@ -204,6 +237,8 @@ mod tests {
             dai(10.0),
             dai(0.0),
             Some(dai(100.0)),
+            None,
+            OrderRounding::default(),
             rate,
             Spread::new(0).unwrap(),
         )
@@ -220,6 +255,8 @@ mod tests {
             btc(0.0),
             btc(2.0),
             Some(btc(100.0)),
+            None,
+            OrderRounding::default(),
             rate,
             Spread::new(0).unwrap(),
         )
@@ -227,9 +264,16 @@ mod tests {
 
         assert_eq!(bitcoin::Amount::from(order.quantity), btc(8.0));
 
-        let order =
-            BtcDaiOrderForm::new_buy(dai(10.0), dai(2.0), None, rate, Spread::new(0).unwrap())
-                .unwrap();
+        let order = BtcDaiOrderForm::new_buy(
+            dai(10.0),
+            dai(2.0),
+            None,
+            None,
+            OrderRounding::default(),
+            rate,
+            Spread::new(0).unwrap(),
+        )
+        .unwrap();
 
         assert_eq!(dai::Amount::from(order.quote()), dai(8.0));
     }
@@ -242,6 +286,8 @@ mod tests {
             btc(0.0),
             btc(2.0),
             Some(btc(2.0)),
+            None,
+            OrderRounding::default(),
             rate,
             Spread::new(0).unwrap(),
         )
@@ -253,6 +299,8 @@ mod tests {
             dai(10.0),
             dai(2.0),
             Some(dai(2.0)),
+            None,
+            OrderRounding::default(),
             rate,
             Spread::new(0).unwrap(),
         )
@@ -268,6 +316,8 @@ mod tests {
             dai(10.0),
             dai(3.0),
             Some(dai(1.0)),
+            None,
+            OrderRounding::default(),
             rate,
             Spread::new(0).unwrap(),
         )
@@ -281,8 +331,17 @@ mod tests {
         let spread = Spread::new(0).unwrap();
 
         let rate = Rate::try_from(0.1).unwrap();
-        let order = BtcDaiOrderForm::new_sell(btc(1051.0), btc(1.0), btc(50.0), None, rate, spread)
-            .unwrap();
+        let order = BtcDaiOrderForm::new_sell(
+            btc(1051.0),
+            btc(1.0),
+            btc(50.0),
+            None,
+            None,
+            OrderRounding::default(),
+            rate,
+            spread,
+        )
+        .unwrap();
 
         // 1 Sell => 0.1 Buy
         // 1000 Sell => 100 Buy
@@ -290,20 +349,47 @@ mod tests {
         assert_eq!(dai::Amount::from(order.quote()), dai(100.0));
 
         let rate = Rate::try_from(10.0).unwrap();
-        let order = BtcDaiOrderForm::new_sell(btc(1051.0), btc(1.0), btc(50.0), None, rate, spread)
-            .unwrap();
+        let order = BtcDaiOrderForm::new_sell(
+            btc(1051.0),
+            btc(1.0),
+            btc(50.0),
+            None,
+            None,
+            OrderRounding::default(),
+            rate,
+            spread,
+        )
+        .unwrap();
 
         assert_eq!(bitcoin::Amount::from(order.quantity), btc(1000.0));
         assert_eq!(dai::Amount::from(order.quote()), dai(10_000.0));
 
         let rate = Rate::try_from(0.1).unwrap();
-        let order = BtcDaiOrderForm::new_buy(dai(1050.0), dai(50.0), None, rate, spread).unwrap();
+        let order = BtcDaiOrderForm::new_buy(
+            dai(1050.0),
+            dai(50.0),
+            None,
+            None,
+            OrderRounding::default(),
+            rate,
+            spread,
+        )
+        .unwrap();
 
         assert_eq!(bitcoin::Amount::from(order.quantity), btc(10_000.0));
         assert_eq!(dai::Amount::from(order.quote()), dai(1000.0));
 
         let rate = Rate::try_from(10.0).unwrap();
-        let order = BtcDaiOrderForm::new_buy(dai(1050.0), dai(50.0), None, rate, spread).unwrap();
+        let order = BtcDaiOrderForm::new_buy(
+            dai(1050.0),
+            dai(50.0),
+            None,
+            None,
+            OrderRounding::default(),
+            rate,
+            spread,
+        )
+        .unwrap();
 
         assert_eq!(bitcoin::Amount::from(order.quantity), btc(100.0));
         assert_eq!(dai::Amount::from(order.quote()), dai(1000.0));
@@ -319,8 +405,17 @@ mod tests {
             BigUint::from(103000000000000 as u64)
         );
 
-        let order =
-            BtcDaiOrderForm::new_sell(btc(1.51), btc(0.01), btc(0.5), None, rate, spread).unwrap();
+        let order = BtcDaiOrderForm::new_sell(
+            btc(1.51),
+            btc(0.01),
+            btc(0.5),
+            None,
+            None,
+            OrderRounding::default(),
+            rate,
+            spread,
+        )
+        .unwrap();
 
         assert_eq!(bitcoin::Amount::from(order.quantity), btc(1.0));
         assert_eq!(dai::Amount::from(order.quote()), dai(10_300.0));
@@ -330,7 +425,16 @@ mod tests {
             BigUint::from(97000000000000 as u64)
         );
 
-        let order = BtcDaiOrderForm::new_buy(dai(10_051.0), dai(51.0), None, rate, spread).unwrap();
+        let order = BtcDaiOrderForm::new_buy(
+            dai(10_051.0),
+            dai(51.0),
+            None,
+            None,
+            OrderRounding::default(),
+            rate,
+            spread,
+        )
+        .unwrap();
 
         assert_eq!(bitcoin::Amount::from(order.quantity), btc(1.03092783));
         assert_eq!(dai::Amount::from(order.quote()), dai(9999.999951));
@@ -341,10 +445,27 @@ mod tests {
         let rate = Rate::try_from(1.0).unwrap();
         let spread = Spread::new(0).unwrap();
 
-        let result = BtcDaiOrderForm::new_sell(btc(1.0), btc(2.0), btc(0.0), None, rate, spread);
+        let result = BtcDaiOrderForm::new_sell(
+            btc(1.0),
+            btc(2.0),
+            btc(0.0),
+            None,
+            None,
+            OrderRounding::default(),
+            rate,
+            spread,
+        );
         assert!(result.unwrap_err().downcast::<InsufficientFunds>().is_ok());
 
-        let result = BtcDaiOrderForm::new_buy(dai(1.0), dai(2.0), None, rate, spread);
+        let result = BtcDaiOrderForm::new_buy(
+            dai(1.0),
+            dai(2.0),
+            None,
+            None,
+            OrderRounding::default(),
+            rate,
+            spread,
+        );
         assert!(result.unwrap_err().downcast::<InsufficientFunds>().is_ok());
     }
 
@@ -353,13 +474,117 @@ mod tests {
         let rate = Rate::try_from(1.0).unwrap();
         let spread = Spread::new(0).unwrap();
 
-        let result = BtcDaiOrderForm::new_sell(btc(1.0), btc(0.0), btc(2.0), None, rate, spread);
+        let result = BtcDaiOrderForm::new_sell(
+            btc(1.0),
+            btc(0.0),
+            btc(2.0),
+            None,
+            None,
+            OrderRounding::default(),
+            rate,
+            spread,
+        );
         assert!(result.unwrap_err().downcast::<InsufficientFunds>().is_ok());
 
-        let result = BtcDaiOrderForm::new_buy(dai(1.0), dai(2.0), None, rate, spread);
+        let result = BtcDaiOrderForm::new_buy(
+            dai(1.0),
+            dai(2.0),
+            None,
+            None,
+            OrderRounding::default(),
+            rate,
+            spread,
+        );
         assert!(result.unwrap_err().downcast::<InsufficientFunds>().is_ok());
     }
 
+    #[test]
+    fn given_available_funds_below_the_minimum_return_below_minimum_amount() {
+        let rate = Rate::try_from(1.0).unwrap();
+        let spread = Spread::new(0).unwrap();
+
+        let result = BtcDaiOrderForm::new_sell(
+            btc(1.0),
+            btc(0.0),
+            btc(0.5),
+            None,
+            Some(btc(0.6)),
+            OrderRounding::default(),
+            rate,
+            spread,
+        );
+        assert!(result.unwrap_err().downcast::<BelowMinimumAmount>().is_ok());
+
+        let result = BtcDaiOrderForm::new_buy(
+            dai(10.0),
+            dai(2.0),
+            None,
+            Some(dai(10.0)),
+            OrderRounding::default(),
+            rate,
+            spread,
+        );
+        assert!(result.unwrap_err().downcast::<BelowMinimumAmount>().is_ok());
+    }
+
+    #[test]
+    fn given_order_rounding_round_amounts_down() {
+        let rate = Rate::try_from(10_000.0).unwrap();
+        let spread = Spread::new(0).unwrap();
+        let rounding = OrderRounding {
+            bitcoin_decimals: Some(5),
+            dai_decimals: Some(0),
+        };
+
+        let order = BtcDaiOrderForm::new_sell(
+            btc(1.234_567_89),
+            btc(0.000_012_34),
+            btc(0.0),
+            None,
+            None,
+            rounding,
+            rate,
+            spread,
+        )
+        .unwrap();
+        assert_eq!(bitcoin::Amount::from(order.quantity), btc(1.234_55));
+
+        let order =
+            BtcDaiOrderForm::new_buy(dai(1_234.567), dai(0.0), None, None, rounding, rate, spread)
+                .unwrap();
+        assert_eq!(bitcoin::Amount::from(order.quantity), btc(0.123_4));
+    }
+
+    #[test]
+    fn given_available_funds_below_the_dust_limit_return_below_minimum_amount() {
+        let rate = Rate::try_from(10_000.0).unwrap();
+        let spread = Spread::new(0).unwrap();
+
+        let result = BtcDaiOrderForm::new_sell(
+            bitcoin::Amount::from_sat(545),
+            btc(0.0),
+            btc(0.0),
+            None,
+            None,
+            OrderRounding::default(),
+            rate,
+            spread,
+        );
+        assert!(result.unwrap_err().downcast::<BelowMinimumAmount>().is_ok());
+
+        // 0.05 DAI buys 500 satoshis
+        let result = BtcDaiOrderForm::new_buy(
+            dai(0.05),
+            dai(0.0),
+            None,
+            None,
+            OrderRounding::default(),
+            rate,
+            spread,
+        );
+        assert!(result.unwrap_err().downcast::<BelowMinimumAmount>().is_ok());
+    }
+
     #[test]
     fn sell_order_is_as_good_as_market_rate() {
         let order = btc_dai_order_form(Position::Sell, btc(1.0), rate(1.0));
@@ -435,7 +660,7 @@ mod tests {
                 let dai_reserved_funds = dai::Amount::from_atto(dai_reserved_funds);
                 let dai_max_amount = dai::Amount::from_atto(dai_max_amount);
 
-                let _: anyhow::Result<BtcDaiOrderForm> = BtcDaiOrderForm::new_buy(dai_balance, dai_reserved_funds, Some(dai_max_amount), rate, spread);
+                let _: anyhow::Result<BtcDaiOrderForm> = BtcDaiOrderForm::new_buy(dai_balance, dai_reserved_funds, Some(dai_max_amount), None, OrderRounding::default(), rate, spread);
             }
         }
     }
@@ -453,7 +678,7 @@ mod tests {
                 let dai_balance = dai::Amount::from_atto(dai_balance);
                 let dai_reserved_funds = dai::Amount::from_atto(dai_reserved_funds);
 
-                let _: anyhow::Result<BtcDaiOrderForm> = BtcDaiOrderForm::new_buy(dai_balance, dai_reserved_funds, None, rate, spread);
+                let _: anyhow::Result<BtcDaiOrderForm> = BtcDaiOrderForm::new_buy(dai_balance, dai_reserved_funds, None, None, OrderRounding::default(), rate, spread);
             }
         }
     }
@@ -470,7 +695,7 @@ mod tests {
             let spread = Spread::new(spread);
 
             if let (Ok(rate), Ok(spread)) = (rate, spread) {
-                let _: anyhow::Result<BtcDaiOrderForm> = BtcDaiOrderForm::new_sell(btc_balance, btc_fees, btc_reserved_funds, Some(btc_max_amount), rate, spread);
+                let _: anyhow::Result<BtcDaiOrderForm> = BtcDaiOrderForm::new_sell(btc_balance, btc_fees, btc_reserved_funds, Some(btc_max_amount), None, OrderRounding::default(), rate, spread);
             }
         }
     }
@@ -486,7 +711,7 @@ mod tests {
             let spread = Spread::new(spread);
 
             if let (Ok(rate), Ok(spread)) = (rate, spread) {
-                let _: anyhow::Result<BtcDaiOrderForm> = BtcDaiOrderForm::new_sell(btc_balance, btc_fees, btc_reserved_funds, None, rate, spread);
+                let _: anyhow::Result<BtcDaiOrderForm> = BtcDaiOrderForm::new_sell(btc_balance, btc_fees, btc_reserved_funds, None, None, OrderRounding::default(), rate, spread);
             }
         }
     }
@@ -1,20 +1,23 @@
+use crate::float_maths::{multiply_pow_ten, string_int_to_float};
 use anyhow::Context;
 use comit::{
     asset::{ethereum::FromWei, Erc20Quantity},
     Position, Price,
 };
-use num::{BigUint, Integer, ToPrimitive};
+use num::{BigUint, Integer, ToPrimitive, Zero};
 use serde::{Deserialize, Serialize};
-use std::{convert::TryFrom, iter::FromIterator, str::FromStr};
+use std::{cmp::min, convert::TryFrom, iter::FromIterator, str::FromStr};
 
 /// Represent a rate. Note this is designed to support Bitcoin/Dai buy and sell
 /// rates (Bitcoin being in the range of 10k-100kDai) A rate has a maximum
 /// precision of 9 digits after the decimal rate = self.0 * 10e-9
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, PartialOrd)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, PartialOrd, Serialize, Deserialize)]
 pub struct Rate(u64);
 
 impl Rate {
     pub const PRECISION: u16 = 10;
+    /// integer of a rate of 1
+    const ONE: u64 = 10_000_000_000;
 
     /// integer = rate * 10ePRECISION
     pub fn new(integer: u64) -> Self {
@@ -25,6 +28,60 @@ impl Rate {
     pub fn integer(self) -> BigUint {
         BigUint::from(self.0)
     }
+
+    /// The mean of both rates, truncated.
+    pub fn mean(lhs: Rate, rhs: Rate) -> Rate {
+        let mean = (u128::from(lhs.0) + u128::from(rhs.0)) / 2;
+        Rate(u64::try_from(mean).expect("mean of two u64 fits in a u64"))
+    }
+
+    /// `self` divided by `divisor`, truncated, e.g. BTC-USD divided by DAI-USD
+    /// is BTC-DAI. `None` if the divisor is nil or the result too large.
+    pub fn checked_div(self, divisor: Rate) -> Option<Rate> {
+        if divisor.0 == 0 {
+            return None;
+        }
+
+        let quotient = u128::from(self.0) * u128::from(Self::ONE) / u128::from(divisor.0);
+        u64::try_from(quotient).ok().map(Rate)
+    }
+
+    /// The inverse rate, e.g. BTC-DAI from DAI-BTC, truncated.
+    pub fn inverse(self) -> Option<Rate> {
+        Rate(Self::ONE).checked_div(self)
+    }
+}
+
+/// Parses a decimal, in scientific notation or not, as quoted by exchanges.
+/// Digits beyond the precision are truncated.
+impl FromStr for Rate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        if !s.is_ascii() {
+            anyhow::bail!("Expecting a decimal")
+        }
+
+        let (mantissa, exponent) = match s.find(|c| c == 'e' || c == 'E') {
+            Some(index) => (
+                &s[..index],
+                s[index + 1..].parse::<i32>().context("Invalid exponent")?,
+            ),
+            None => (s, 0),
+        };
+        let pow =
+            u16::try_from(i32::from(Self::PRECISION) + exponent).context("Rate is out of range")?;
+
+        let mantissa = match mantissa.find('.') {
+            Some(index) => &mantissa[..min(mantissa.len(), index + 1 + usize::from(pow))],
+            None => mantissa,
+        };
+        let integer = multiply_pow_ten(mantissa, pow)?
+            .to_u64()
+            .context("Rate is unexpectedly large")?;
+
+        Ok(Rate::new(integer))
+    }
 }
 
 impl TryFrom<f64> for Rate {
@@ -67,6 +124,13 @@ impl TryFrom<f64> for Rate {
     }
 }
 
+impl std::fmt::Display for Rate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let rate = string_int_to_float(self.0.to_string(), Self::PRECISION as usize);
+        write!(f, "{}", rate)
+    }
+}
+
 impl Into<Price<comit::asset::Bitcoin, comit::asset::Erc20Quantity>> for Rate {
     fn into(self) -> Price<comit::asset::Bitcoin, comit::asset::Erc20Quantity> {
         let btc_to_dai = Erc20Quantity::from_wei(self.0);
@@ -93,6 +157,10 @@ impl Spread {
         Ok(Spread(permyriad))
     }
 
+    pub fn permyriad(self) -> u16 {
+        self.0
+    }
+
     pub fn apply(self, rate: Rate, position: Position) -> anyhow::Result<Rate> {
         let ten_thousand = BigUint::from(10_000u16);
 
@@ -103,8 +171,12 @@ impl Spread {
 
         let integer = rate.integer() * (spread);
 
-        // Now divide by 10e4 because of the spread
-        let (rate, _remainder) = integer.div_rem(&ten_thousand);
+        // Now divide by 10e4 because of the spread, rounding in our favour:
+        // up when selling and down when buying
+        let (mut rate, remainder) = integer.div_rem(&ten_thousand);
+        if matches!(position, Position::Sell) && !remainder.is_zero() {
+            rate += 1u32;
+        }
         let rate = rate
             .to_u64()
             .ok_or_else(|| anyhow::anyhow!("Result is unexpectedly large"))?;
@@ -136,6 +208,49 @@ mod tests {
         assert_eq!(rate_from_f64, rate_new);
     }
 
+    #[test]
+    fn rate_displays_as_decimal() {
+        let rate = Rate::try_from(9123.456).unwrap();
+        assert_eq!(rate.to_string(), "9123.456");
+    }
+
+    #[test]
+    fn rate_parses_from_decimal() {
+        assert_eq!(
+            Rate::from_str("9489.50000").unwrap(),
+            Rate::try_from(9489.5).unwrap()
+        );
+        assert_eq!(Rate::from_str("9.299e-5").unwrap(), Rate::new(929_900));
+        // Digits beyond the precision are truncated
+        assert_eq!(
+            Rate::from_str("0.123456789012").unwrap(),
+            Rate::new(1_234_567_890)
+        );
+        assert!(Rate::from_str("-1.0").is_err());
+    }
+
+    #[test]
+    fn rates_divide_without_floats() {
+        let btc_usd = Rate::try_from(10_000.0).unwrap();
+        let dai_usd = Rate::try_from(1.25).unwrap();
+
+        assert_eq!(
+            btc_usd.checked_div(dai_usd),
+            Some(Rate::try_from(8_000.0).unwrap())
+        );
+        assert_eq!(dai_usd.inverse(), Some(Rate::try_from(0.8).unwrap()));
+        assert_eq!(btc_usd.checked_div(Rate::default()), None);
+    }
+
+    #[test]
+    fn apply_spread_rounds_in_our_favour() {
+        let spread = Spread::new(1).unwrap();
+        let rate = Rate::new(15);
+
+        assert_eq!(spread.apply(rate, Position::Sell).unwrap(), Rate::new(16));
+        assert_eq!(spread.apply(rate, Position::Buy).unwrap(), Rate::new(14));
+    }
+
     #[test]
     fn rate_error_on_negative_rate() {
         let rate = Rate::try_from(-1.0);
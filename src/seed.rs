@@ -2,6 +2,7 @@ use ::bitcoin::{
     hashes::{sha512, Hash, HashEngine, Hmac, HmacEngine},
     secp256k1::{self, constants::SECRET_KEY_SIZE, SecretKey},
 };
+use bip39::{Language, Mnemonic};
 use rand::prelude::*;
 use std::fmt;
 
@@ -21,10 +22,32 @@ impl Seed {
         Ok(Seed(bytes))
     }
 
+    /// Restore the seed from the 24 words BIP-39 phrase of `to_mnemonic`.
+    pub fn from_mnemonic(phrase: &str) -> Result<Self, Error> {
+        let mnemonic = Mnemonic::from_phrase(phrase.trim(), Language::English)
+            .map_err(|_| Error::InvalidMnemonic)?;
+
+        let mut bytes = [0u8; SEED_LENGTH];
+        if mnemonic.entropy().len() != SEED_LENGTH {
+            return Err(Error::InvalidMnemonic);
+        }
+        bytes.copy_from_slice(mnemonic.entropy());
+        let _ = SecretKey::from_slice(&bytes)?;
+
+        Ok(Seed(bytes))
+    }
+
     pub fn bytes(&self) -> [u8; SEED_LENGTH] {
         self.0
     }
 
+    /// The seed bytes as BIP-39 entropy, i.e. a phrase of 24 english words.
+    pub fn to_mnemonic(&self) -> String {
+        Mnemonic::from_entropy(&self.0, Language::English)
+            .expect("32 bytes are valid entropy")
+            .into_phrase()
+    }
+
     /// Return the private key and chain code to be used as root extended
     /// private key for a BIP32 wallet.
     pub fn root_secret_key_chain_code(&self) -> (SecretKey, Vec<u8>) {
@@ -76,6 +99,8 @@ impl From<[u8; SEED_LENGTH]> for Seed {
 pub enum Error {
     #[error("Secp256k1: ")]
     Secp256k1(#[from] secp256k1::Error),
+    #[error("expected a BIP-39 phrase of 24 english words")]
+    InvalidMnemonic,
 }
 
 #[cfg(test)]
@@ -86,4 +111,34 @@ mod tests {
     fn generate_random_seed() {
         let _ = Seed::random().unwrap();
     }
+
+    #[test]
+    fn seed_round_trips_through_mnemonic() {
+        let seed = Seed::random().unwrap();
+
+        let restored = Seed::from_mnemonic(&seed.to_mnemonic()).unwrap();
+
+        assert_eq!(restored, seed);
+    }
+
+    #[test]
+    fn mnemonic_encodes_the_seed_bytes_as_entropy() {
+        let seed = Seed::from([0x7f; SEED_LENGTH]);
+
+        assert_eq!(
+            seed.to_mnemonic(),
+            "legal winner thank year wave sausage worth useful legal winner thank year wave \
+             sausage worth useful legal winner thank year wave sausage worth title"
+        );
+    }
+
+    #[test]
+    fn mnemonic_of_12_words_is_rejected() {
+        let phrase = "legal winner thank year wave sausage worth useful legal winner thank yellow";
+
+        assert!(matches!(
+            Seed::from_mnemonic(phrase),
+            Err(Error::InvalidMnemonic)
+        ));
+    }
 }
@@ -0,0 +1,2100 @@
+//! The trade loop of the maker, publishing orders, accepting takes and
+//! executing their swaps, as run by the `trade` command.
+//!
+//! `MakerBuilder` sets it up from the settings and the wallets, so that other
+//! programs can embed the maker with their own rate source, spread strategy or
+//! database. The resulting `MakerService` runs until the shutdown future
+//! passed to it resolves.
+
+use crate::{
+    alert::{Alert, Alerter},
+    api::{self, Control, Event, Events, SwapState},
+    bitcoin,
+    command::{into_history_trade, report_swap_failure, swap_outcome, FinishedSwap},
+    config::{validation::validate_expiries, BitcoinConfirmations, Settings},
+    ethereum::{self, dai, ether},
+    health::{self, Health},
+    history::History,
+    maker::{InventoryAssessment, PublishOrders, RateFetchFailure},
+    metrics::Metrics,
+    mid_market_rate::{Aggregate, RateSource},
+    network::{self, Swarm},
+    order::BtcDaiOrderForm,
+    swap::{
+        AuditedOrder, BalanceSnapshot, Broadcast, Database, OrderAction, OrderAuditEntry,
+        OrderUpdateReason, Reservation, SwapKind, SwapParams,
+    },
+    watchdog, Maker, MidMarketRate, Rate, Seed, Spread, SwapId,
+};
+use anyhow::Context;
+use comit::btsieve::{bitcoin::BitcoindConnector, ethereum::Web3Connector};
+use futures::{
+    channel::mpsc::{Sender, UnboundedSender},
+    future::{BoxFuture, Either},
+    Future, FutureExt, SinkExt, StreamExt, TryFutureExt,
+};
+use futures_timer::Delay;
+use num::ToPrimitive;
+
+use crate::{
+    maker::{CircuitBreaker, Sale, SpreadStrategy, TakeRequestDecision, TakerFilter, VolumeLimits},
+    network::{
+        new_swarm, ActivePeer, Connectivity, ConnectivityChange, SetupSwapContext, StaticPeers,
+    },
+};
+use comit::Position;
+use scheduler::{Fetch, Intervals, Update};
+use std::{convert::TryFrom, sync::Arc, time::Duration};
+use tokio::sync::Semaphore;
+use tracing::Instrument;
+
+mod scheduler;
+
+const ENSURED_CONSUME_ZERO_BUFFER: usize = 0;
+
+/// How often the age of the rate is checked, so that orders are withdrawn soon
+/// after it becomes stale.
+const RATE_AGE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often the circuit breaker is checked for the end of its cool-down.
+const COOL_DOWN_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often the connected peers are counted and the peers of the
+/// configuration file redialed if disconnected, the first check dials them
+/// all.
+const CONNECTIVITY_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How often the inventory is sampled for the rebalance recommendations.
+const REBALANCE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How long a taker is considered active after taking an order, unless set in
+/// `[watchdog]`.
+const DEFAULT_ACTIVE_PEER_TTL: Duration = Duration::from_secs(48 * 60 * 60);
+
+/// How long the setup of a swap may take after its order was taken, unless set
+/// in `[watchdog]`.
+const DEFAULT_SWAP_SETUP_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// How often the active peers are checked for expired records.
+const ACTIVE_PEER_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How long to wait upon shutdown for the swaps in progress to finish. Those
+/// still in progress then are resumed from the database on the next start.
+const SHUTDOWN_SWAP_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Sets up the maker: the wallets are required, the rate source, spread
+/// strategy and database default to the ones configured in the settings.
+#[derive(Debug)]
+pub struct MakerBuilder {
+    seed: Seed,
+    settings: Settings,
+    bitcoin_wallet: Arc<bitcoin::Wallet>,
+    ethereum_wallet: Arc<ethereum::Wallet>,
+    rate_source: Option<Arc<dyn RateSource>>,
+    spread_strategy: Option<SpreadStrategy>,
+    database: Option<Arc<Database>>,
+}
+
+impl MakerBuilder {
+    pub fn new(
+        seed: &Seed,
+        settings: Settings,
+        bitcoin_wallet: bitcoin::Wallet,
+        ethereum_wallet: ethereum::Wallet,
+    ) -> Self {
+        MakerBuilder {
+            seed: *seed,
+            settings,
+            bitcoin_wallet: Arc::new(bitcoin_wallet),
+            ethereum_wallet: Arc::new(ethereum_wallet),
+            rate_source: None,
+            spread_strategy: None,
+            database: None,
+        }
+    }
+
+    /// Instead of aggregating the exchanges of the `[rate]` settings.
+    pub fn with_rate_source(self, rate_source: Arc<dyn RateSource>) -> Self {
+        Self {
+            rate_source: Some(rate_source),
+            ..self
+        }
+    }
+
+    /// Instead of the `inventory_skew` of the maker settings.
+    pub fn with_spread_strategy(self, spread_strategy: SpreadStrategy) -> Self {
+        Self {
+            spread_strategy: Some(spread_strategy),
+            ..self
+        }
+    }
+
+    /// Instead of the database in the data directory.
+    pub fn with_database(self, database: Arc<Database>) -> Self {
+        Self {
+            database: Some(database),
+            ..self
+        }
+    }
+
+    /// Fetch the initial balances and rate, open the database and start
+    /// listening on the network. Nothing is published before the service
+    /// runs.
+    pub async fn build(self) -> anyhow::Result<MakerService> {
+        let MakerBuilder {
+            seed,
+            settings,
+            bitcoin_wallet,
+            ethereum_wallet,
+            rate_source,
+            spread_strategy,
+            database,
+        } = self;
+
+        let rate_source =
+            rate_source.unwrap_or_else(|| Arc::new(Aggregate::from(settings.rate.clone())));
+
+        let mut maker = init_maker(
+            Arc::clone(&bitcoin_wallet),
+            Arc::clone(&ethereum_wallet),
+            rate_source.as_ref(),
+            settings.clone(),
+        )
+        .await
+        .context("Could not initialise Maker")?;
+        if let Some(spread_strategy) = spread_strategy {
+            maker = maker.with_spread_strategy(spread_strategy);
+        }
+
+        for position in &[Position::Buy, Position::Sell] {
+            validate_expiries(
+                maker.swap_protocol(*position),
+                settings.bitcoin.network,
+                settings.ethereum.chain,
+            )
+            .context("Refusing to trade with unsafe expiries")?;
+        }
+
+        let db = match database {
+            Some(database) => database,
+            #[cfg(not(test))]
+            None => Arc::new(Database::new(&settings.data.dir.join("database"))?),
+            #[cfg(test)]
+            None => Arc::new(Database::new_test()?),
+        };
+
+        maker = maker.with_taker_filter(TakerFilter::new(
+            &settings.takers,
+            db.taker_listings()
+                .context("Could not load the listed takers")?,
+        ));
+        maker = maker.with_volume_limits(VolumeLimits::new(
+            settings.maker.max_volume_per_24h.clone(),
+            recent_sales(&db).context("Could not load the volumes sold")?,
+        ));
+
+        let network_seed = network::Seed::new(seed.bytes());
+        let swarm = new_swarm(
+            network_seed,
+            &settings,
+            Arc::clone(&bitcoin_wallet),
+            Arc::clone(&ethereum_wallet),
+            Arc::clone(&db),
+        )?;
+
+        Ok(MakerService {
+            settings,
+            maker,
+            swarm,
+            db,
+            bitcoin_wallet,
+            ethereum_wallet,
+            rate_source,
+        })
+    }
+}
+
+/// The maker ready to trade, see `MakerService::run`.
+#[allow(missing_debug_implementations)]
+pub struct MakerService {
+    settings: Settings,
+    maker: Maker,
+    swarm: Swarm,
+    db: Arc<Database>,
+    bitcoin_wallet: Arc<bitcoin::Wallet>,
+    ethereum_wallet: Arc<ethereum::Wallet>,
+    rate_source: Arc<dyn RateSource>,
+}
+
+impl MakerService {
+    pub fn maker(&self) -> &Maker {
+        &self.maker
+    }
+
+    /// Publish orders, execute the swaps of the takes and serve the HTTP API
+    /// until `shutdown` resolves. The swaps still in progress then are given
+    /// some time to finish before the database is flushed.
+    pub async fn run(
+        self,
+        shutdown: impl Future<Output = anyhow::Result<()>> + Send,
+    ) -> anyhow::Result<()> {
+        let MakerService {
+            settings,
+            mut maker,
+            mut swarm,
+            db,
+            bitcoin_wallet,
+            ethereum_wallet,
+            rate_source,
+        } = self;
+        let alerter = Alerter::new(settings.alerting.clone());
+        let metrics = Metrics::new(settings.maker.spread);
+        let (control_sender, mut control_receiver) = futures::channel::mpsc::unbounded::<Control>();
+        let events = Events::new();
+        let health = Health::new(Duration::from_secs(settings.rate.max_age_secs));
+        tokio::spawn(health::probe(
+            health.clone(),
+            Arc::clone(&bitcoin_wallet),
+            Arc::clone(&ethereum_wallet),
+            Arc::clone(&db),
+        ));
+        let api_state = api::State::new(
+            Arc::clone(&db),
+            settings.history.file_path(&settings.data.dir),
+            settings.history.format,
+            metrics.clone(),
+            *Swarm::local_peer_id(&swarm),
+            settings.bitcoin.network,
+            settings.ethereum.chain,
+            control_sender,
+            events.clone(),
+            health.clone(),
+        );
+        api_state.update_maker(&maker);
+        export_metrics(&settings, &metrics, &db);
+        tokio::spawn(
+            api::serve(settings.api.listen, api_state.clone())
+                .map_err(|e| tracing::error!("HTTP API stopped: {:#}", e)),
+        );
+
+        let initial_sell_orders = maker
+            .new_sell_orders()
+            .context("Could not generate sell orders")?;
+
+        let initial_buy_orders = maker
+            .new_buy_orders()
+            .context("Could not generate buy orders")?;
+
+        publish_orders(
+            &mut swarm,
+            &db,
+            &events,
+            &maker,
+            initial_sell_orders,
+            Position::Buy,
+            OrderUpdateReason::Startup,
+        );
+        publish_orders(
+            &mut swarm,
+            &db,
+            &events,
+            &maker,
+            initial_buy_orders,
+            Position::Sell,
+            OrderUpdateReason::Startup,
+        );
+
+        let update_interval = Duration::from_secs(15u64);
+        let intervals = Intervals {
+            rate: Duration::from_secs(settings.rate.refresh_interval_secs),
+            balances: update_interval,
+            // Fee estimates only change with new blocks
+            bitcoin_fee: Duration::from_secs(60u64),
+        };
+        let maximum_btc_fee = settings.maker.maximum_possible_fee.bitcoin;
+
+        let (scheduler_future, mut update_receiver, fetch_trigger) = scheduler::init(intervals, {
+            let rate_source = Arc::clone(&rate_source);
+            let bitcoin_wallet = Arc::clone(&bitcoin_wallet);
+            let ethereum_wallet = Arc::clone(&ethereum_wallet);
+            move |kind| {
+                fetch(
+                    kind,
+                    Arc::clone(&rate_source),
+                    Arc::clone(&bitcoin_wallet),
+                    Arc::clone(&ethereum_wallet),
+                    maximum_btc_fee,
+                )
+            }
+        });
+
+        tokio::spawn(scheduler_future);
+        if let Some(accounting) = settings.accounting {
+            tokio::spawn(init_balance_snapshots(
+                Duration::from_secs(accounting.balance_snapshot_interval_secs),
+                Arc::clone(&rate_source),
+                Arc::clone(&bitcoin_wallet),
+                Arc::clone(&ethereum_wallet),
+                Arc::clone(&db),
+            ));
+        }
+
+        let (swap_execution_finished_sender, mut swap_execution_finished_receiver) =
+            futures::channel::mpsc::channel::<FinishedSwap>(ENSURED_CONSUME_ZERO_BUFFER);
+
+        // Refresh the affected balance as soon as we broadcast a transaction
+        // instead of waiting for the next poll
+        let (broadcast_sender, mut broadcast_receiver) =
+            futures::channel::mpsc::unbounded::<Broadcast>();
+
+        let mut history = History::new(
+            settings.history.file_path(&settings.data.dir).as_path(),
+            settings.history.clone(),
+        )?;
+
+        let bitcoin_connector = Arc::new(BitcoindConnector::new(
+            settings.bitcoin.bitcoind.url_with_credentials()?,
+        )?);
+        let ethereum_connector = Arc::new(Web3Connector::new(
+            settings.ethereum.url_with_credentials()?,
+        ));
+
+        let swap_slots = settings
+            .maker
+            .max_concurrent_swaps
+            .map(|max| Arc::new(Semaphore::new(max)));
+        let swap_stall_timeout = settings
+            .watchdog
+            .swap_stall_timeout_secs
+            .map(Duration::from_secs);
+        let active_peer_ttl = settings
+            .watchdog
+            .active_peer_ttl_secs
+            .map_or(DEFAULT_ACTIVE_PEER_TTL, Duration::from_secs);
+        let swap_setup_timeout = settings
+            .watchdog
+            .swap_setup_timeout_secs
+            .map_or(DEFAULT_SWAP_SETUP_TIMEOUT, Duration::from_secs);
+
+        respawn_swaps(
+            Arc::clone(&db),
+            &network_seed,
+            &mut maker,
+            Arc::clone(&bitcoin_wallet),
+            Arc::clone(&ethereum_wallet),
+            Arc::clone(&bitcoin_connector),
+            Arc::clone(&ethereum_connector),
+            settings.maker.bitcoin_confirmations.clone(),
+            swap_stall_timeout,
+            active_peer_ttl,
+            swap_slots.clone(),
+            alerter.clone(),
+            swap_execution_finished_sender.clone(),
+            broadcast_sender.clone(),
+            events.clone(),
+        )
+        .await
+        .context("Could not respawn swaps")?;
+
+        let heartbeat = watchdog::spawn(settings.watchdog, alerter.clone())
+            .context("Could not start the watchdog")?;
+
+        let mut rate_age_check = tokio::time::interval(RATE_AGE_CHECK_INTERVAL);
+        let mut cool_down_check = tokio::time::interval(COOL_DOWN_CHECK_INTERVAL);
+        let mut static_peers = StaticPeers::new(settings.network.peers.clone());
+        let mut connectivity =
+            Connectivity::new(settings.network.max_isolation_secs.map(Duration::from_secs));
+        let mut connectivity_check = tokio::time::interval(CONNECTIVITY_CHECK_INTERVAL);
+        let mut rebalance_check = tokio::time::interval(REBALANCE_CHECK_INTERVAL);
+        let mut active_peer_sweep = tokio::time::interval(ACTIVE_PEER_SWEEP_INTERVAL);
+        let republish_interval = Duration::from_secs(settings.maker.republish_interval_secs);
+        let mut republication = tokio::time::interval_at(
+            tokio::time::Instant::now() + republish_interval,
+            republish_interval,
+        );
+        let mut shutdown = Box::pin(shutdown.fuse());
+
+        loop {
+            futures::select! {
+                finished_swap = swap_execution_finished_receiver.next().fuse() => {
+                    if let Some(finished_swap) = finished_swap {
+                        handle_finished_swap(finished_swap, &mut maker, &db, &mut history, &metrics, &mut swarm).await;
+                        fetch_trigger.fetch(Fetch::BitcoinBalance);
+                        fetch_trigger.fetch(Fetch::DaiBalance);
+                        fetch_trigger.fetch(Fetch::EtherBalance);
+                    }
+                },
+                network_event = swarm.next().fuse() => {
+                    handle_network_event(
+                        network_event,
+                        &mut maker,
+                        &mut swarm,
+                        Arc::clone(&db),
+                        Arc::clone(&bitcoin_wallet),
+                        Arc::clone(&ethereum_wallet),
+                        Arc::clone(&bitcoin_connector),
+                        Arc::clone(&ethereum_connector),
+                        settings.maker.bitcoin_confirmations.clone(),
+                        swap_stall_timeout,
+                        active_peer_ttl,
+                        swap_setup_timeout,
+                        swap_slots.clone(),
+                        alerter.clone(),
+                        swap_execution_finished_sender.clone(),
+                        broadcast_sender.clone(),
+                        events.clone(),
+                    ).await;
+                },
+                control = control_receiver.next().fuse() => {
+                    if let Some(control) = control {
+                        handle_control(control, &mut maker, &mut swarm, &db, &events);
+                    }
+                },
+                broadcast = broadcast_receiver.next().fuse() => {
+                    match broadcast {
+                        Some(Broadcast::Bitcoin) => fetch_trigger.fetch(Fetch::BitcoinBalance),
+                        Some(Broadcast::Ethereum) => {
+                            fetch_trigger.fetch(Fetch::DaiBalance);
+                            fetch_trigger.fetch(Fetch::EtherBalance);
+                        }
+                        None => (),
+                    }
+                },
+                _ = rate_age_check.tick().fuse() => handle_rate_age_check(&mut maker, &mut swarm, &db, &alerter),
+                _ = cool_down_check.tick().fuse() => handle_cool_down_check(&mut maker, &mut swarm, &db, &events),
+                _ = connectivity_check.tick().fuse() => handle_connectivity_check(&mut connectivity, &mut static_peers, &mut maker, &mut swarm, &db, &events, &metrics),
+                _ = republication.tick().fuse() => handle_republication(&maker, &mut swarm, &db, &events),
+                _ = rebalance_check.tick().fuse() => handle_rebalance_check(&mut maker, &mut swarm, &db, &events, &metrics),
+                _ = active_peer_sweep.tick().fuse() => handle_active_peer_sweep(&mut maker, &mut swarm, &db, &events).await,
+                update = update_receiver.next().fuse() => {
+                    match update.context("Update stream terminated")? {
+                        Update::Rate(rate_update) => {
+                            if rate_update.is_ok() {
+                                health.rate_received();
+                            }
+                            handle_rate_update(rate_update, &mut maker, &mut swarm, &db, &events, &alerter)
+                        }
+                        Update::BitcoinBalance(btc_balance_update) => handle_btc_balance_update(btc_balance_update, &mut maker, &mut swarm, &db, &events, &alerter),
+                        Update::DaiBalance(dai_balance_update) => handle_dai_balance_update(dai_balance_update, &mut maker, &mut swarm, &db, &events, &alerter),
+                        Update::EtherBalance(ether_balance_update) => handle_ether_balance_update(ether_balance_update, &mut maker, &mut swarm, &db, &events, &alerter),
+                        Update::BitcoinFee(btc_fee_update) => handle_btc_fee_update(btc_fee_update, &mut maker, &mut swarm, &db, &events),
+                    }
+                },
+                signal = shutdown => {
+                    signal.context("Could not listen for the shutdown")?;
+                    tracing::info!("Shutting down");
+                    break;
+                }
+            }
+
+            heartbeat.tick();
+            api_state.update_maker(&maker);
+        }
+
+        // The swarm is not polled anymore so no new take is accepted
+        clear_orders(&mut swarm, &db, &maker, OrderUpdateReason::Shutdown);
+
+        // The receiver ends once the executions holding a sender are all over
+        drop(swap_execution_finished_sender);
+        let mut timeout = Delay::new(SHUTDOWN_SWAP_TIMEOUT).fuse();
+        loop {
+            futures::select! {
+                finished_swap = swap_execution_finished_receiver.next().fuse() => match finished_swap {
+                    Some(finished_swap) => handle_finished_swap(finished_swap, &mut maker, &db, &mut history, &metrics, &mut swarm).await,
+                    None => break,
+                },
+                _ = timeout => {
+                    tracing::warn!("Swaps still in progress are resumed on the next start");
+                    break;
+                }
+            }
+        }
+
+        db.flush().await.context("Could not flush the database")?;
+        tracing::info!("Shut down");
+
+        Ok(())
+    }
+}
+
+async fn init_maker(
+    bitcoin_wallet: Arc<bitcoin::Wallet>,
+    ethereum_wallet: Arc<ethereum::Wallet>,
+    rate_source: &dyn RateSource,
+    settings: Settings,
+) -> anyhow::Result<Maker> {
+    let initial_btc_balance = bitcoin_wallet
+        .balance()
+        .await
+        .context("Could not get Bitcoin balance")?;
+
+    let initial_dai_balance = ethereum_wallet
+        .dai_balance()
+        .await
+        .context("Could not get Dai balance")?;
+
+    let initial_rate = rate_source
+        .mid_market_rate()
+        .await
+        .context("Could not get rate")?;
+
+    Ok(new_maker(
+        &settings,
+        initial_btc_balance,
+        initial_dai_balance,
+        initial_rate,
+    ))
+}
+
+/// The maker quoting as configured, also used to backtest the configuration.
+pub fn new_maker(
+    settings: &Settings,
+    btc_balance: bitcoin::Amount,
+    dai_balance: dai::Amount,
+    mid_market_rate: MidMarketRate,
+) -> Maker {
+    let btc_max_sell = settings.maker.max_sell.bitcoin;
+    let dai_max_sell = settings.maker.max_sell.dai.clone();
+    let btc_fee_reserve = settings.maker.maximum_possible_fee.bitcoin;
+
+    let spread: Spread = settings.maker.spread;
+
+    Maker::new(
+        btc_balance,
+        dai_balance,
+        btc_fee_reserve,
+        btc_max_sell,
+        dai_max_sell,
+        mid_market_rate,
+        spread,
+        settings.bitcoin.network,
+        settings.ethereum.chain,
+        settings.maker.role.into(),
+    )
+    .with_position_spreads(settings.maker.sell_spread, settings.maker.buy_spread)
+    .with_levels(settings.maker.levels.clone())
+    .with_circuit_breaker(
+        settings
+            .maker
+            .circuit_breaker
+            .map_or_else(CircuitBreaker::default, CircuitBreaker::new),
+    )
+    .with_spread_strategy(
+        settings
+            .maker
+            .inventory_skew
+            .map_or(SpreadStrategy::Static, SpreadStrategy::InventorySkew),
+    )
+    .with_rate_max_age(Duration::from_secs(settings.rate.max_age_secs))
+    .with_rate_failover(settings.rate.failover)
+    .with_rate_hysteresis(settings.maker.rate_hysteresis)
+    .with_reputation_policy(settings.maker.reputation)
+    .with_min_balance(settings.maker.min_balance.clone())
+    .with_min_sell(settings.maker.min_sell.clone())
+    .with_order_rounding(settings.maker.order_rounding)
+    .with_expiries(settings.maker.expiries)
+    .with_rebalance(settings.maker.rebalance)
+}
+
+fn fetch(
+    fetch: Fetch,
+    rate_source: Arc<dyn RateSource>,
+    bitcoin_wallet: Arc<bitcoin::Wallet>,
+    ethereum_wallet: Arc<ethereum::Wallet>,
+    maximum_btc_fee: bitcoin::Amount,
+) -> BoxFuture<'static, Update> {
+    match fetch {
+        Fetch::Rate => async move { Update::Rate(rate_source.mid_market_rate().await) }.boxed(),
+        Fetch::BitcoinBalance => {
+            async move { Update::BitcoinBalance(bitcoin_wallet.balance().await) }.boxed()
+        }
+        Fetch::DaiBalance => {
+            async move { Update::DaiBalance(ethereum_wallet.dai_balance().await) }.boxed()
+        }
+        Fetch::EtherBalance => async move {
+            Update::EtherBalance(futures::try_join!(
+                ethereum_wallet.ether_balance(),
+                ethereum_wallet.swap_gas_cost()
+            ))
+        }
+        .boxed(),
+        Fetch::BitcoinFee => async move {
+            Update::BitcoinFee(
+                bitcoin::fee::estimate_swap_fee(&bitcoin_wallet, maximum_btc_fee).await,
+            )
+        }
+        .boxed(),
+    }
+}
+
+/// Record the balances and the mid-market rate for accounting purposes.
+async fn init_balance_snapshots(
+    interval: Duration,
+    rate_source: Arc<dyn RateSource>,
+    bitcoin_wallet: Arc<bitcoin::Wallet>,
+    ethereum_wallet: Arc<ethereum::Wallet>,
+    db: Arc<Database>,
+) -> comit::Never {
+    loop {
+        let (bitcoin, dai, ether, rate) = futures::join!(
+            bitcoin_wallet.balance(),
+            ethereum_wallet.dai_balance(),
+            ethereum_wallet.ether_balance(),
+            rate_source.mid_market_rate()
+        );
+
+        let snapshot = BalanceSnapshot {
+            taken_at: chrono::Utc::now(),
+            bitcoin_sat: bitcoin.map(|amount| amount.as_sat()).ok(),
+            dai_attodai: dai.map(|amount| amount.as_atto().to_string()).ok(),
+            ether_wei: ether
+                .map(|amount| num256::Uint256::from(amount).to_string())
+                .ok(),
+            mid_market_rate: rate.map(Rate::from).ok(),
+        };
+
+        if let Err(e) = db.insert_balance_snapshot(&snapshot).await {
+            tracing::error!("Could not record balance snapshot: {:#}", e);
+        }
+
+        Delay::new(interval).await;
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn execute_swap(
+    db: Arc<Database>,
+    bitcoin_wallet: Arc<bitcoin::Wallet>,
+    ethereum_wallet: Arc<ethereum::Wallet>,
+    bitcoin_connector: Arc<comit::btsieve::bitcoin::BitcoindConnector>,
+    ethereum_connector: Arc<comit::btsieve::ethereum::Web3Connector>,
+    bitcoin_confirmations: Vec<BitcoinConfirmations>,
+    swap_stall_timeout: Option<Duration>,
+    active_peer_ttl: Duration,
+    swap_slots: Option<Arc<Semaphore>>,
+    alerter: Alerter,
+    mut finished_swap_sender: Sender<FinishedSwap>,
+    broadcast_sender: UnboundedSender<Broadcast>,
+    events: Events,
+    swap: SwapKind,
+) -> anyhow::Result<()> {
+    let execution = async {
+        let _permit = match &swap_slots {
+            Some(swap_slots) => {
+                if swap_slots.available_permits() == 0 {
+                    tracing::info!(
+                        "Maximum number of concurrent swaps reached, swap {} is queued",
+                        swap.swap_id()
+                    );
+                    events.publish(Event::swap_state_changed(swap.swap_id(), SwapState::Queued));
+                }
+                Some(swap_slots.acquire().await)
+            }
+            None => None,
+        };
+        events.publish(Event::swap_state_changed(
+            swap.swap_id(),
+            SwapState::Executing,
+        ));
+
+        swap.execute(
+            Arc::clone(&db),
+            Arc::clone(&bitcoin_wallet),
+            Arc::clone(&ethereum_wallet),
+            Arc::clone(&bitcoin_connector),
+            Arc::clone(&ethereum_connector),
+            &bitcoin_confirmations,
+            swap_stall_timeout,
+            Some(broadcast_sender),
+        )
+        .await
+    };
+    // However long the swap is queued or executed, the funds reserved for it
+    // must not be freed while they may be locked in an HTLC
+    let result = renewing_active_peer(&db, &swap.params().taker, active_peer_ttl, execution).await;
+    if let Err(e) = &result {
+        report_swap_failure(&db, &alerter, &swap, e).await;
+        events.publish(Event::swap_state_changed(swap.swap_id(), SwapState::Failed));
+    }
+
+    let finished = result?;
+    let outcome = swap_outcome(&db, &alerter, &swap, &finished).await;
+    events.publish(Event::swap_state_changed(
+        swap.swap_id(),
+        SwapState::from(outcome),
+    ));
+
+    let _ = finished_swap_sender
+        .send(FinishedSwap::new(
+            swap.clone(),
+            swap.params().taker,
+            chrono::Utc::now(),
+            outcome,
+            finished,
+        ))
+        .await
+        .map_err(|_| {
+            tracing::trace!("Error when sending execution finished from sender to receiver.")
+        });
+
+    Ok(())
+}
+
+/// Run `future` while renewing the record of `peer` every half `ttl`.
+async fn renewing_active_peer<T>(
+    db: &Database,
+    peer: &ActivePeer,
+    ttl: Duration,
+    future: impl Future<Output = T>,
+) -> T {
+    let renewals = async {
+        loop {
+            Delay::new(ttl / 2).await;
+            match db.renew_active_peer(peer, active_peer_expiry(ttl)).await {
+                Ok(true) => (),
+                Ok(false) => tracing::warn!(
+                    "Record of taker {} expired while its swap is in progress",
+                    peer.peer_id()
+                ),
+                Err(e) => tracing::error!(
+                    "Could not renew the record of taker {}: {:#}",
+                    peer.peer_id(),
+                    e
+                ),
+            }
+        }
+    };
+
+    futures::pin_mut!(future);
+    futures::pin_mut!(renewals);
+    match futures::future::select(future, renewals).await {
+        Either::Left((output, _)) => output,
+        Either::Right(((), _)) => unreachable!("renewals never end"),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn respawn_swaps(
+    db: Arc<Database>,
+    seed: &network::Seed,
+    maker: &mut Maker,
+    bitcoin_wallet: Arc<bitcoin::Wallet>,
+    ethereum_wallet: Arc<ethereum::Wallet>,
+    bitcoin_connector: Arc<comit::btsieve::bitcoin::BitcoindConnector>,
+    ethereum_connector: Arc<comit::btsieve::ethereum::Web3Connector>,
+    bitcoin_confirmations: Vec<BitcoinConfirmations>,
+    swap_stall_timeout: Option<Duration>,
+    active_peer_ttl: Duration,
+    swap_slots: Option<Arc<Semaphore>>,
+    alerter: Alerter,
+    finished_swap_sender: Sender<FinishedSwap>,
+    broadcast_sender: UnboundedSender<Broadcast>,
+    events: Events,
+) -> anyhow::Result<()> {
+    for swap in db.all_swaps()?.into_iter() {
+        let swap = swap.with_secret(seed);
+
+        // Reserve funds
+        let SwapParams {
+            hbit_params,
+            herc20_params,
+            ..
+        } = swap.params();
+        if swap.locks_bitcoin() {
+            maker.reserve_btc(hbit_params.shared.asset.into());
+        } else {
+            let fund_amount = herc20_params.asset.into();
+            maker.dai_reserved_funds = maker.dai_reserved_funds.clone() + fund_amount;
+        }
+        db.insert_active_peer(
+            swap.params().taker,
+            Reservation::of_swap(&swap),
+            active_peer_expiry(active_peer_ttl),
+        )
+        .await?;
+
+        // Already stored, hence resumed without inserting it again
+        let swap_id = swap.swap_id();
+        tokio::spawn(
+            execute_swap(
+                Arc::clone(&db),
+                Arc::clone(&bitcoin_wallet),
+                Arc::clone(&ethereum_wallet),
+                Arc::clone(&bitcoin_connector),
+                Arc::clone(&ethereum_connector),
+                bitcoin_confirmations.clone(),
+                swap_stall_timeout,
+                active_peer_ttl,
+                swap_slots.clone(),
+                alerter.clone(),
+                finished_swap_sender.clone(),
+                broadcast_sender.clone(),
+                events.clone(),
+                swap,
+            )
+            .map_err(move |e| tracing::error!("Execution failed for swap {}: {:?}", swap_id, e)),
+        );
+    }
+
+    Ok(())
+}
+
+fn handle_control(
+    control: Control,
+    maker: &mut Maker,
+    swarm: &mut Swarm,
+    db: &Database,
+    events: &Events,
+) {
+    match control {
+        Control::PauseTrading if !maker.is_paused() => {
+            maker.pause();
+            clear_orders(swarm, db, maker, OrderUpdateReason::TradingPaused);
+            tracing::info!("Trading paused");
+        }
+        Control::EnterMaintenance if !maker.is_in_maintenance() => {
+            maker.enter_maintenance();
+            clear_orders(swarm, db, maker, OrderUpdateReason::Maintenance);
+            tracing::info!("Maintenance mode entered, ongoing swaps are still executed");
+        }
+        Control::ResumeTrading if maker.is_paused() => {
+            match maker.resume() {
+                Ok(Some(PublishOrders {
+                    new_sell_orders,
+                    new_buy_orders,
+                })) => {
+                    let reason = OrderUpdateReason::TradingResumed;
+                    publish_orders(
+                        swarm,
+                        db,
+                        events,
+                        maker,
+                        new_sell_orders,
+                        Position::Sell,
+                        reason,
+                    );
+                    publish_orders(
+                        swarm,
+                        db,
+                        events,
+                        maker,
+                        new_buy_orders,
+                        Position::Buy,
+                        reason,
+                    );
+                }
+                // Orders are published once the circuit breaker cool-down ends
+                Ok(None) => (),
+                // Orders are published again with the next rate or balance update
+                Err(e) => tracing::warn!("Could not publish orders upon resuming: {}", e),
+            }
+            tracing::info!("Trading resumed");
+        }
+        _ => tracing::debug!("Ignoring {:?}, already in that state", control),
+    }
+}
+
+fn handle_rate_update(
+    rate_update: anyhow::Result<MidMarketRate>,
+    maker: &mut Maker,
+    swarm: &mut Swarm,
+    db: &Database,
+    events: &Events,
+    alerter: &Alerter,
+) {
+    match rate_update {
+        Ok(new_rate) => {
+            let was_halted = maker.is_halted();
+            let result = maker.update_rate(new_rate);
+            match result {
+                Ok(Some(PublishOrders {
+                    new_sell_orders,
+                    new_buy_orders,
+                })) => {
+                    let reason = OrderUpdateReason::RateUpdate;
+                    replace_orders(
+                        swarm,
+                        db,
+                        events,
+                        maker,
+                        new_sell_orders,
+                        new_buy_orders,
+                        reason,
+                    );
+                }
+
+                Ok(None) => (),
+                Err(e) => tracing::warn!("Rate update yielded error: {}", e),
+            }
+
+            if !was_halted && maker.is_halted() {
+                alerter.notify(Alert::RateMovedTooFast);
+                clear_orders(swarm, db, maker, OrderUpdateReason::CircuitBreakerTripped);
+                tracing::warn!("Mid-market rate moved too fast, trading halted");
+            }
+        }
+        Err(e) => {
+            let had_rate = maker.mid_market_rate().is_some();
+            match maker.rate_fetch_failed(chrono::Utc::now()) {
+                Ok(RateFetchFailure::Tolerated(publish_orders)) => {
+                    tracing::warn!(
+                        "Fetching rate yielded error, using the last known rate: {:#}",
+                        e
+                    );
+                    if let Some(PublishOrders {
+                        new_sell_orders,
+                        new_buy_orders,
+                    }) = publish_orders
+                    {
+                        replace_orders(
+                            swarm,
+                            db,
+                            events,
+                            maker,
+                            new_sell_orders,
+                            new_buy_orders,
+                            OrderUpdateReason::RateFetchFailed,
+                        );
+                    }
+                }
+                Ok(RateFetchFailure::RateInvalidated) => {
+                    if had_rate {
+                        alerter.notify(Alert::StaleRate {
+                            error: format!("{:#}", e),
+                        });
+                    }
+                    tracing::error!(
+                        "Unable to fetch latest rate! Fetching rate yielded error: {}",
+                        e
+                    );
+                }
+                Err(publish_error) => {
+                    tracing::warn!("Rate fetch failure yielded error: {}", publish_error)
+                }
+            }
+        }
+    }
+}
+
+fn handle_cool_down_check(maker: &mut Maker, swarm: &mut Swarm, db: &Database, events: &Events) {
+    let was_halted = maker.is_halted();
+
+    match maker.end_cool_down(chrono::Utc::now()) {
+        Ok(Some(PublishOrders {
+            new_sell_orders,
+            new_buy_orders,
+        })) => {
+            let reason = OrderUpdateReason::CircuitBreakerReset;
+            publish_orders(
+                swarm,
+                db,
+                events,
+                maker,
+                new_sell_orders,
+                Position::Sell,
+                reason,
+            );
+            publish_orders(
+                swarm,
+                db,
+                events,
+                maker,
+                new_buy_orders,
+                Position::Buy,
+                reason,
+            );
+        }
+        Ok(None) => (),
+        // Orders are published again with the next rate or balance update
+        Err(e) => tracing::warn!("Could not publish orders after the cool-down: {}", e),
+    }
+
+    if was_halted && !maker.is_halted() {
+        tracing::info!("Mid-market rate calmed down, trading resumed");
+    }
+}
+
+fn handle_rate_age_check(maker: &mut Maker, swarm: &mut Swarm, db: &Database, alerter: &Alerter) {
+    if maker.expire_stale_rate(chrono::Utc::now()) {
+        alerter.notify(Alert::StaleRate {
+            error: "no rate was fetched within the maximum age".to_string(),
+        });
+        clear_orders(swarm, db, maker, OrderUpdateReason::StaleRate);
+        tracing::error!("Mid-market rate is stale, orders withdrawn");
+    }
+}
+
+/// Withdraws the orders once isolated for too long, as they could not be
+/// honored, and publishes them again once connected to a peer.
+fn handle_connectivity_check(
+    connectivity: &mut Connectivity,
+    static_peers: &mut StaticPeers,
+    maker: &mut Maker,
+    swarm: &mut Swarm,
+    db: &Database,
+    events: &Events,
+    metrics: &Metrics,
+) {
+    let now = std::time::Instant::now();
+    let connected_peers = Swarm::network_info(swarm).num_peers();
+    metrics.set_connected_peers(connected_peers);
+
+    match connectivity.update(connected_peers, now) {
+        Some(ConnectivityChange::Lost) => {
+            tracing::warn!("Connected to no peer, redialing the configured peers");
+            static_peers.reset_backoff();
+        }
+        Some(ConnectivityChange::Isolated) => {
+            maker.isolate();
+            clear_orders(swarm, db, maker, OrderUpdateReason::Isolated);
+            tracing::error!("Connected to no peer for too long, orders withdrawn");
+        }
+        Some(ConnectivityChange::Restored { was_isolated }) => {
+            tracing::info!("Connected to {} peers", connected_peers);
+            if was_isolated {
+                match maker.end_isolation() {
+                    Ok(Some(PublishOrders {
+                        new_sell_orders,
+                        new_buy_orders,
+                    })) => replace_orders(
+                        swarm,
+                        db,
+                        events,
+                        maker,
+                        new_sell_orders,
+                        new_buy_orders,
+                        OrderUpdateReason::Reconnected,
+                    ),
+                    Ok(None) => (),
+                    // Orders are published again with the next rate or balance update
+                    Err(e) => tracing::warn!("Could not publish orders once reconnected: {}", e),
+                }
+            }
+        }
+        None => (),
+    }
+
+    static_peers.dial_disconnected(swarm, now);
+}
+
+/// Samples the inventory, recommends rebalancing it while an asset is depleted
+/// and replaces the orders if their maximum amounts were adjusted.
+fn handle_rebalance_check(
+    maker: &mut Maker,
+    swarm: &mut Swarm,
+    db: &Database,
+    events: &Events,
+    metrics: &Metrics,
+) {
+    let was_depleted = maker.depleted_asset();
+
+    let InventoryAssessment {
+        assessment,
+        publish_orders,
+    } = match maker.assess_inventory(chrono::Utc::now()) {
+        Ok(Some(inventory_assessment)) => inventory_assessment,
+        Ok(None) => return,
+        Err(e) => {
+            tracing::warn!(
+                "Could not publish orders after assessing the inventory: {}",
+                e
+            );
+            return;
+        }
+    };
+
+    // Only logged upon change, the metrics are updated upon every check
+    match &assessment.recommendation {
+        Some(recommendation) if was_depleted != Some(recommendation.depleted()) => {
+            tracing::warn!(
+                "{} is depleted, {:.2}% of the inventory is held in bitcoin, rebalance: {}",
+                recommendation.depleted(),
+                f64::from(assessment.bitcoin_share) / 100.0,
+                recommendation
+            )
+        }
+        None if was_depleted.is_some() => tracing::info!("Inventory rebalanced"),
+        _ => (),
+    }
+    metrics.record_inventory(assessment);
+
+    if let Some(PublishOrders {
+        new_sell_orders,
+        new_buy_orders,
+    }) = publish_orders
+    {
+        replace_orders(
+            swarm,
+            db,
+            events,
+            maker,
+            new_sell_orders,
+            new_buy_orders,
+            OrderUpdateReason::Rebalance,
+        );
+    }
+}
+
+/// Withdraw our orders and publish them again so that peers do not keep acting
+/// on orders we published long ago, e.g. before a network partition.
+fn handle_republication(maker: &Maker, swarm: &mut Swarm, db: &Database, events: &Events) {
+    match maker.republish() {
+        Ok(Some(PublishOrders {
+            new_sell_orders,
+            new_buy_orders,
+        })) => {
+            let reason = OrderUpdateReason::Republication;
+            replace_orders(
+                swarm,
+                db,
+                events,
+                maker,
+                new_sell_orders,
+                new_buy_orders,
+                reason,
+            );
+        }
+        Ok(None) => (),
+        // Orders are published again with the next rate or balance update
+        Err(e) => tracing::warn!("Could not republish orders: {}", e),
+    }
+}
+
+fn handle_btc_balance_update(
+    btc_balance_update: anyhow::Result<bitcoin::Amount>,
+    maker: &mut Maker,
+    swarm: &mut Swarm,
+    db: &Database,
+    events: &Events,
+    alerter: &Alerter,
+) {
+    let previous_balance = maker.btc_balance();
+    let was_below_minimum = maker.is_btc_balance_below_minimum();
+
+    match btc_balance_update {
+        Ok(btc_balance) => {
+            let was_low = maker
+                .btc_balance()
+                .map_or(false, |balance| balance <= maker.btc_fee);
+            if btc_balance <= maker.btc_fee && !was_low {
+                alerter.notify(Alert::LowBitcoinBalance {
+                    balance: btc_balance,
+                });
+            }
+            alerter.check_bitcoin_balance(previous_balance, btc_balance);
+
+            match maker.update_bitcoin_balance(btc_balance) {
+                Ok(Some(new_sell_orders)) => {
+                    let reason = OrderUpdateReason::BitcoinBalanceUpdate;
+                    replace_orders(swarm, db, events, maker, new_sell_orders, vec![], reason);
+                }
+                Ok(None) => (),
+                Err(e) => tracing::warn!("Bitcoin balance update yielded error: {}", e),
+            }
+        }
+        Err(e) => {
+            if maker.btc_balance().is_some() {
+                alerter.notify(Alert::NodeUnreachable {
+                    ledger: "Bitcoin",
+                    error: format!("{:#}", e),
+                });
+            }
+            maker.invalidate_bitcoin_balance();
+            tracing::error!(
+                "Unable to fetch bitcoin balance! Fetching balance yielded error: {}",
+                e
+            );
+        }
+    }
+
+    match (was_below_minimum, maker.is_btc_balance_below_minimum()) {
+        (false, true) => {
+            tracing::warn!("Bitcoin balance is below the minimum, sell orders withdrawn")
+        }
+        (true, false) if maker.btc_balance().is_some() => {
+            tracing::info!("Bitcoin balance is back above the minimum, selling resumed")
+        }
+        _ => (),
+    }
+
+    if maker.btc_balance() != previous_balance {
+        events.publish(Event::balance_updated(maker));
+    }
+}
+
+fn handle_dai_balance_update(
+    dai_balance_update: anyhow::Result<dai::Amount>,
+    maker: &mut Maker,
+    swarm: &mut Swarm,
+    db: &Database,
+    events: &Events,
+    alerter: &Alerter,
+) {
+    let previous_balance = maker.dai_balance();
+    let was_below_minimum = maker.is_dai_balance_below_minimum();
+
+    match dai_balance_update {
+        Ok(dai_balance) => {
+            alerter.check_dai_balance(previous_balance.as_ref(), &dai_balance);
+
+            match maker.update_dai_balance(dai_balance) {
+                Ok(Some(new_buy_orders)) => {
+                    let reason = OrderUpdateReason::DaiBalanceUpdate;
+                    replace_orders(swarm, db, events, maker, vec![], new_buy_orders, reason);
+                }
+                Ok(None) => (),
+                Err(e) => tracing::warn!("Dai balance update yielded error: {}", e),
+            }
+        }
+        Err(e) => {
+            if maker.dai_balance().is_some() {
+                alerter.notify(Alert::NodeUnreachable {
+                    ledger: "Ethereum",
+                    error: format!("{:#}", e),
+                });
+            }
+            maker.invalidate_dai_balance();
+            tracing::error!(
+                "Unable to fetch dai balance! Fetching balance yielded error: {}",
+                e
+            );
+        }
+    }
+
+    match (was_below_minimum, maker.is_dai_balance_below_minimum()) {
+        (false, true) => tracing::warn!("Dai balance is below the minimum, buy orders withdrawn"),
+        (true, false) if maker.dai_balance().is_some() => {
+            tracing::info!("Dai balance is back above the minimum, buying resumed")
+        }
+        _ => (),
+    }
+
+    if maker.dai_balance() != previous_balance {
+        events.publish(Event::balance_updated(maker));
+    }
+}
+
+/// Withdraws the orders and alerts once the ether balance no longer covers the
+/// gas of another swap, publishes them again once it does.
+fn handle_ether_balance_update(
+    ether_balance_update: anyhow::Result<(ether::Amount, ether::Amount)>,
+    maker: &mut Maker,
+    swarm: &mut Swarm,
+    db: &Database,
+    events: &Events,
+    alerter: &Alerter,
+) {
+    let was_lacking_gas = maker.is_lacking_gas();
+
+    match ether_balance_update {
+        Ok((ether_balance, swap_gas_cost)) => {
+            match maker.update_ether_balance(ether_balance.clone(), swap_gas_cost) {
+                Ok(Some(PublishOrders {
+                    new_sell_orders,
+                    new_buy_orders,
+                })) => {
+                    let reason = OrderUpdateReason::EtherBalanceUpdate;
+                    replace_orders(
+                        swarm,
+                        db,
+                        events,
+                        maker,
+                        new_sell_orders,
+                        new_buy_orders,
+                        reason,
+                    );
+                }
+                Ok(None) => (),
+                Err(e) => tracing::warn!("Ether balance update yielded error: {}", e),
+            }
+
+            match (was_lacking_gas, maker.is_lacking_gas()) {
+                (false, true) => {
+                    alerter.notify(Alert::LowGas {
+                        balance: ether_balance,
+                    });
+                    tracing::warn!(
+                        "Ether balance does not cover the gas of another swap, orders withdrawn"
+                    )
+                }
+                (true, false) => {
+                    tracing::info!(
+                        "Ether balance covers the gas of another swap again, trading resumed"
+                    )
+                }
+                _ => (),
+            }
+        }
+        Err(e) => {
+            maker.invalidate_ether_balance();
+            tracing::error!(
+                "Unable to fetch ether balance! Fetching balance yielded error: {}",
+                e
+            );
+        }
+    }
+}
+
+fn handle_btc_fee_update(
+    btc_fee_update: anyhow::Result<bitcoin::Amount>,
+    maker: &mut Maker,
+    swarm: &mut Swarm,
+    db: &Database,
+    events: &Events,
+) {
+    match btc_fee_update {
+        Ok(btc_fee) => match maker.update_btc_fee(btc_fee) {
+            Ok(Some(new_sell_orders)) => {
+                let reason = OrderUpdateReason::BitcoinFeeUpdate;
+                replace_orders(swarm, db, events, maker, new_sell_orders, vec![], reason);
+            }
+            Ok(None) => (),
+            Err(e) => tracing::warn!("Bitcoin fee update yielded error: {}", e),
+        },
+        // The previous estimate, or the maximum possible fee, remains reserved
+        Err(e) => tracing::warn!("Unable to estimate the bitcoin fee: {:#}", e),
+    }
+}
+
+/// Publish the order with the swap protocol of `protocol_position` and record
+/// it in the order audit log.
+fn publish_order(
+    swarm: &mut Swarm,
+    db: &Database,
+    events: &Events,
+    maker: &Maker,
+    order: BtcDaiOrderForm,
+    protocol_position: Position,
+    reason: OrderUpdateReason,
+) {
+    let order = announce_order(db, events, maker, order, protocol_position, reason);
+    swarm.orderbook.publish(order);
+}
+
+/// Record the order in the audit log and notify the subscribers of the
+/// events, returns the order to publish.
+fn announce_order(
+    db: &Database,
+    events: &Events,
+    maker: &Maker,
+    order: BtcDaiOrderForm,
+    protocol_position: Position,
+    reason: OrderUpdateReason,
+) -> comit::BtcDaiOrder {
+    let quantity = bitcoin::Amount::from(order.quantity);
+    let quote = dai::Amount::from(order.quote()).as_atto();
+    let rate = if quantity.as_sat() == 0 {
+        None
+    } else {
+        (&quote / quantity.as_sat()).to_u64().map(Rate::new)
+    };
+    let audited_order = AuditedOrder {
+        position: match order.position {
+            Position::Buy => "buy".to_owned(),
+            Position::Sell => "sell".to_owned(),
+        },
+        quantity_sat: quantity.as_sat(),
+        quote_attodai: quote.to_string(),
+        rate,
+    };
+    events.publish(Event::OrderPublished(api::Order::from(order.clone())));
+    audit_orders(db, maker, reason, OrderAction::Published(audited_order));
+
+    order.to_comit_order(maker.swap_protocol(protocol_position))
+}
+
+/// Publish each order of a ladder, see `publish_order`.
+fn publish_orders(
+    swarm: &mut Swarm,
+    db: &Database,
+    events: &Events,
+    maker: &Maker,
+    orders: Vec<BtcDaiOrderForm>,
+    protocol_position: Position,
+    reason: OrderUpdateReason,
+) {
+    for order in orders {
+        publish_order(swarm, db, events, maker, order, protocol_position, reason);
+    }
+}
+
+/// Withdraw our orders and publish the new sell and buy orders in their place,
+/// see `Nectar::replace_orders`.
+fn replace_orders(
+    swarm: &mut Swarm,
+    db: &Database,
+    events: &Events,
+    maker: &Maker,
+    new_sell_orders: Vec<BtcDaiOrderForm>,
+    new_buy_orders: Vec<BtcDaiOrderForm>,
+    reason: OrderUpdateReason,
+) {
+    audit_orders(db, maker, reason, OrderAction::Cleared);
+
+    let sell_orders = new_sell_orders
+        .into_iter()
+        .map(|order| announce_order(db, events, maker, order, Position::Sell, reason));
+    let buy_orders = new_buy_orders
+        .into_iter()
+        .map(|order| announce_order(db, events, maker, order, Position::Buy, reason));
+
+    swarm.replace_orders(sell_orders.chain(buy_orders).collect());
+}
+
+/// Clear our orders from the orderbook and record it in the order audit log.
+fn clear_orders(swarm: &mut Swarm, db: &Database, maker: &Maker, reason: OrderUpdateReason) {
+    swarm.orderbook.clear_own_orders();
+    audit_orders(db, maker, reason, OrderAction::Cleared);
+}
+
+fn audit_orders(db: &Database, maker: &Maker, reason: OrderUpdateReason, action: OrderAction) {
+    let entry = OrderAuditEntry {
+        recorded_at: chrono::Utc::now(),
+        reason,
+        action,
+        mid_market_rate: maker.mid_market_rate().map(Rate::from),
+    };
+
+    if let Err(e) = db.insert_order_audit_entry(&entry) {
+        tracing::error!("Could not record order audit entry: {:#}", e);
+    }
+}
+
+async fn handle_finished_swap(
+    finished_swap: FinishedSwap,
+    maker: &mut Maker,
+    db: &Database,
+    history: &mut History,
+    metrics: &Metrics,
+    _swarm: &mut Swarm,
+) {
+    {
+        let settlement = finished_swap.swap.settlement(db).unwrap_or_else(|error| {
+            tracing::error!("Unable to load the settlement of the swap: {:#}", error);
+            Default::default()
+        });
+        let trade = into_history_trade(
+            finished_swap.peer.peer_id(),
+            finished_swap.swap.clone(),
+            finished_swap.outcome,
+            finished_swap.swap_outcome.clone(),
+            settlement,
+            #[cfg(not(test))]
+            finished_swap.final_timestamp,
+        );
+
+        metrics.record_trade(&trade);
+        let _ = history.write(trade).map_err(|error| {
+            tracing::error!(
+                "Unable to register history entry: {}; {:?}",
+                error,
+                finished_swap
+            )
+        });
+    }
+
+    let swap_id = finished_swap.swap.swap_id();
+
+    // The funds were already freed if the record of the peer expired
+    match db.remove_active_peer(&finished_swap.peer).await {
+        Ok(Some(reservation)) => free_reservation(maker, &reservation),
+        Ok(None) => tracing::warn!(
+            "Swap {} finished after its taker was no longer considered active",
+            swap_id
+        ),
+        Err(error) => tracing::error!("Unable to remove from active takers: {}", error),
+    }
+
+    let _ = db
+        .remove_swap(&swap_id)
+        .await
+        .map_err(|error| tracing::error!("Unable to delete swap from db: {}", error));
+}
+
+/// Push the metrics to the OpenTelemetry collector, if configured.
+#[cfg(feature = "otlp")]
+fn export_metrics(settings: &Settings, metrics: &Metrics, db: &Arc<Database>) {
+    if let Some(endpoint) = settings
+        .telemetry
+        .as_ref()
+        .and_then(|telemetry| telemetry.otlp_metrics_endpoint.clone())
+    {
+        tokio::spawn(crate::metrics::otlp::export(
+            metrics.clone(),
+            Arc::clone(db),
+            endpoint,
+        ));
+    }
+}
+
+#[cfg(not(feature = "otlp"))]
+fn export_metrics(_: &Settings, _: &Metrics, _: &Arc<Database>) {}
+
+/// Free the funds reserved for the swaps of the peers whose record expired,
+/// e.g. because the setup or the execution of their swap failed, and publish
+/// our orders again with them.
+async fn handle_active_peer_sweep(
+    maker: &mut Maker,
+    swarm: &mut Swarm,
+    db: &Database,
+    events: &Events,
+) {
+    if free_expired_reservations(maker, db).await {
+        republish_with_freed_funds(maker, swarm, db, events);
+    }
+}
+
+/// Whether funds were freed. The sales of the swaps that were never set up are
+/// rolled back along with their reservation.
+async fn free_expired_reservations(maker: &mut Maker, db: &Database) -> bool {
+    let expired = match db.purge_expired_active_peers(chrono::Utc::now()).await {
+        Ok(expired) => expired,
+        Err(e) => {
+            tracing::error!("Could not purge the expired active peers: {:#}", e);
+            return false;
+        }
+    };
+
+    for (peer, reservation) in &expired {
+        tracing::warn!(
+            "Taker {} is no longer considered active, freeing the funds reserved for its swap",
+            peer.peer_id()
+        );
+        free_reservation(maker, reservation);
+
+        if let Some(swap_id) = reservation.swap_id {
+            match db.contains_swap(&swap_id) {
+                Ok(true) => (),
+                Ok(false) => roll_back_sale(maker, db, swap_id).await,
+                Err(e) => tracing::error!("Could not look up swap {}: {:#}", swap_id, e),
+            }
+        }
+    }
+
+    !expired.is_empty()
+}
+
+/// Undo the sale of a swap that was never set up, so that it does not count
+/// against the volume limit.
+async fn roll_back_sale(maker: &mut Maker, db: &Database, swap_id: SwapId) {
+    let sales = match db.remove_sold_volume(&swap_id).await {
+        Ok(_) => recent_sales(db),
+        Err(e) => Err(e),
+    };
+
+    match sales {
+        Ok(sales) => maker.replace_sales(sales),
+        Err(e) => tracing::error!("Could not roll back the sale of swap {}: {:#}", swap_id, e),
+    }
+}
+
+/// The sales counted against the volume limit.
+fn recent_sales(db: &Database) -> anyhow::Result<Vec<Sale>> {
+    db.sold_volumes_since(VolumeLimits::window_start(chrono::Utc::now()))?
+        .into_iter()
+        .map(Sale::try_from)
+        .collect()
+}
+
+fn free_reservation(maker: &mut Maker, reservation: &Reservation) {
+    let freed = reservation
+        .dai()
+        .and_then(|dai| maker.free_funds(dai, reservation.bitcoin()));
+    if let Err(error) = freed {
+        tracing::error!("Unable to free the reserved funds: {}", error)
+    }
+}
+
+/// Publish our orders again now that the freed funds are available to them.
+fn republish_with_freed_funds(maker: &Maker, swarm: &mut Swarm, db: &Database, events: &Events) {
+    match maker.republish() {
+        Ok(Some(PublishOrders {
+            new_sell_orders,
+            new_buy_orders,
+        })) => replace_orders(
+            swarm,
+            db,
+            events,
+            maker,
+            new_sell_orders,
+            new_buy_orders,
+            OrderUpdateReason::ReservationFreed,
+        ),
+        Ok(None) => (),
+        // Orders are published again with the next rate or balance update
+        Err(e) => tracing::warn!("Could not publish orders with the freed funds: {}", e),
+    }
+}
+
+/// When the record of a peer that took an order expires, the TTL is capped to
+/// a century so that the expiry cannot overflow.
+fn active_peer_expiry(ttl: Duration) -> chrono::DateTime<chrono::Utc> {
+    let century = chrono::Duration::days(36_525);
+    let ttl = chrono::Duration::from_std(ttl).map_or(century, |ttl| ttl.min(century));
+
+    chrono::Utc::now() + ttl
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn handle_network_event(
+    network_event: network::Event,
+    maker: &mut Maker,
+    swarm: &mut Swarm,
+    db: Arc<Database>,
+    bitcoin_wallet: Arc<bitcoin::Wallet>,
+    ethereum_wallet: Arc<ethereum::Wallet>,
+    bitcoin_connector: Arc<comit::btsieve::bitcoin::BitcoindConnector>,
+    ethereum_connector: Arc<comit::btsieve::ethereum::Web3Connector>,
+    bitcoin_confirmations: Vec<BitcoinConfirmations>,
+    swap_stall_timeout: Option<Duration>,
+    active_peer_ttl: Duration,
+    swap_setup_timeout: Duration,
+    swap_slots: Option<Arc<Semaphore>>,
+    alerter: Alerter,
+    finished_swap_sender: Sender<FinishedSwap>,
+    broadcast_sender: UnboundedSender<Broadcast>,
+    events: Events,
+) {
+    match network_event {
+        network::Event::OrderMatch {
+            form,
+            to,
+            to_send,
+            common,
+            swap_protocol,
+            swap_id,
+            match_ref_point,
+            bitcoin_transient_key_index,
+        } => {
+            let span = tracing::info_span!("swap", %swap_id, peer_id = %to);
+            async {
+                match db.reputation(&to) {
+                    Ok(reputation) => maker.update_reputation(to.clone(), reputation),
+                    Err(e) => {
+                        tracing::error!("Could not load the reputation of the taker: {:#}", e)
+                    }
+                }
+                let result = maker.process_taken_order(&to, form.clone());
+
+                match result {
+                    Ok(TakeRequestDecision::GoForSwap) => {
+                        events.publish(Event::order_taken(
+                            swap_id,
+                            &to,
+                            api::Order::from(form.clone()),
+                        ));
+
+                        if let Err(e) = swarm.setup_swap.send(
+                            &to,
+                            to_send,
+                            common,
+                            swap_protocol,
+                            SetupSwapContext {
+                                swap_id,
+                                match_ref_point,
+                                bitcoin_transient_key_index,
+                                mid_market_rate: maker.mid_market_rate().map(Rate::from),
+                            },
+                        ) {
+                            tracing::error!("Sending setup swap message yielded error: {}", e);
+                            free_reservation(maker, &Reservation::of_order(swap_id, &form));
+                            roll_back_sale(maker, &db, swap_id).await;
+                            republish_with_freed_funds(maker, swarm, &db, &events);
+                            return;
+                        }
+
+                        // Renewed once the setup completed, the funds are
+                        // freed by the sweep if it does not complete in time
+                        let _ = db
+                            .insert_active_peer(
+                                ActivePeer { peer_id: to },
+                                Reservation::of_order(swap_id, &form),
+                                active_peer_expiry(swap_setup_timeout),
+                            )
+                            .await
+                            .map_err(|e| tracing::error!("Failed to confirm order: {}", e));
+
+                        let sold_volume =
+                            Sale::of(&form, chrono::Utc::now()).into_sold_volume(swap_id);
+                        let _ = db
+                            .insert_sold_volume(&sold_volume)
+                            .await
+                            .map_err(|e| tracing::error!("Failed to record sold volume: {}", e));
+
+                        // todo: publish new order here?
+                        // What if i publish a new order here and the does go
+                        // through?
+                    }
+                    Ok(TakeRequestDecision::InsufficientFunds) => {
+                        tracing::info!("Insufficient funds")
+                    }
+                    Ok(TakeRequestDecision::RateNotProfitable) => {
+                        tracing::info!("Rate not profitable")
+                    }
+                    Ok(TakeRequestDecision::Paused) => tracing::info!("Trading is paused"),
+                    Ok(TakeRequestDecision::Maintenance) => {
+                        tracing::info!("Declining take, maintenance mode")
+                    }
+                    Ok(TakeRequestDecision::CannotTradeWithTaker) => {
+                        tracing::info!("Taker is banned or not allowed")
+                    }
+                    Ok(TakeRequestDecision::PoorReputation) => {
+                        tracing::info!("Taker aborted or caused refunds too often")
+                    }
+                    Ok(TakeRequestDecision::FirstTradeTooLarge) => {
+                        tracing::info!("First trade with the taker is too large")
+                    }
+                    Ok(TakeRequestDecision::VolumeLimitReached) => {
+                        tracing::info!("Volume sold over 24 hours would exceed the limit")
+                    }
+                    Ok(TakeRequestDecision::Halted) => {
+                        tracing::info!("Trading is halted by the circuit breaker")
+                    }
+                    Err(e) => tracing::error!("Processing taken order yielded error: {}", e),
+                };
+            }
+            .instrument(span)
+            .await;
+        }
+        network::Event::SpawnSwap(swap) => {
+            let swap_id = swap.swap_id();
+
+            match db
+                .renew_active_peer(&swap.params().taker, active_peer_expiry(active_peer_ttl))
+                .await
+            {
+                Ok(true) => (),
+                Ok(false) => {
+                    tracing::warn!(
+                        "Setup of swap {} completed after it timed out and the funds reserved for it were freed, not executing it",
+                        swap_id
+                    );
+                    return;
+                }
+                Err(e) => tracing::error!(
+                    "Could not renew the record of the taker of swap {}: {:#}",
+                    swap_id,
+                    e
+                ),
+            }
+
+            let res = db
+                .insert_swap(swap.clone())
+                .map_err(|e| tracing::error!("Could not insert swap {}: {:?}", swap_id, e))
+                .await;
+
+            if res.is_ok() {
+                // Not awaited so that queued swaps do not hold up the event loop
+                tokio::spawn(
+                    execute_swap(
+                        Arc::clone(&db),
+                        Arc::clone(&bitcoin_wallet),
+                        Arc::clone(&ethereum_wallet),
+                        Arc::clone(&bitcoin_connector),
+                        Arc::clone(&ethereum_connector),
+                        bitcoin_confirmations,
+                        swap_stall_timeout,
+                        active_peer_ttl,
+                        swap_slots,
+                        alerter,
+                        finished_swap_sender,
+                        broadcast_sender,
+                        events,
+                        swap,
+                    )
+                    .map_err(move |e| {
+                        tracing::error!("Execution failed for swap {}: {:#}", swap_id, e)
+                    }),
+                );
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "test-docker"))]
+mod tests {
+    use super::*;
+    use crate::{
+        bitcoin::amount::btc,
+        config::{
+            file::Format, settings, Api, Data, Logging, MaxSell, MaxVolume, MinBalance, MinSell,
+            Network, OrderRounding, SwapRole,
+        },
+        order::btc_dai_order_form,
+        rate::rate,
+        swap::herc20::asset::ethereum::FromWei,
+        test_harness, Seed, StaticStub,
+    };
+    use comit::{asset, asset::Erc20Quantity, ethereum::ChainId};
+    use ethereum::ether;
+    use libp2p::PeerId;
+    use log::LevelFilter;
+    use quickcheck::{Arbitrary, StdThreadGen};
+
+    // Run cargo test with `--ignored --nocapture` to see the `println output`
+    #[ignore]
+    #[tokio::test]
+    async fn trade_command() {
+        let client = testcontainers::clients::Cli::default();
+        let seed = Seed::random().unwrap();
+
+        let bitcoin_blockchain = test_harness::bitcoin::Blockchain::new(&client).unwrap();
+        bitcoin_blockchain.init().await.unwrap();
+
+        let mut ethereum_blockchain = test_harness::ethereum::Blockchain::new(&client).unwrap();
+        ethereum_blockchain.init().await.unwrap();
+
+        let settings = Settings {
+            maker: settings::Maker {
+                max_sell: MaxSell {
+                    bitcoin: None,
+                    dai: None,
+                },
+                spread: Default::default(),
+                sell_spread: None,
+                buy_spread: None,
+                maximum_possible_fee: Default::default(),
+                max_concurrent_swaps: None,
+                max_volume_per_24h: MaxVolume::default(),
+                republish_interval_secs: 300,
+                levels: vec![],
+                inventory_skew: None,
+                rebalance: None,
+                circuit_breaker: None,
+                rate_hysteresis: None,
+                reputation: None,
+                expiries: None,
+                bitcoin_confirmations: vec![],
+                min_balance: MinBalance::default(),
+                min_sell: MinSell::default(),
+                order_rounding: OrderRounding::default(),
+                role: SwapRole::default(),
+            },
+            network: Network {
+                listen: vec!["/ip4/98.97.96.95/tcp/20500"
+                    .parse()
+                    .expect("invalid multiaddr")],
+                external_addresses: vec![],
+                peers: vec![],
+                max_isolation_secs: None,
+            },
+            data: Data {
+                dir: Default::default(),
+            },
+            logging: Logging {
+                level: LevelFilter::Trace,
+                format: Format::Text,
+            },
+            bitcoin: Default::default(),
+            ethereum: settings::Ethereum {
+                node_url: ethereum_blockchain.node_url.clone(),
+                auth: Default::default(),
+                chain: ethereum::Chain::new(
+                    ChainId::GETH_DEV,
+                    ethereum_blockchain.token_contract(),
+                ),
+                gas_price: Default::default(),
+                signer: Default::default(),
+            },
+            api: Api {
+                listen: "127.0.0.1:0".parse().expect("invalid socket address"),
+            },
+            alerting: Default::default(),
+            history: Default::default(),
+            telemetry: None,
+            error_reporting: None,
+            accounting: None,
+            watchdog: Default::default(),
+            rate: Default::default(),
+            rpc: Default::default(),
+            takers: Default::default(),
+        };
+
+        let bitcoin_wallet = bitcoin::Wallet::new(
+            seed,
+            bitcoin_blockchain.node_url.clone(),
+            ::bitcoin::Network::Regtest,
+        )
+        .await
+        .unwrap();
+
+        let ethereum_wallet = crate::ethereum::Wallet::new(
+            seed,
+            ethereum_blockchain.node_url.clone(),
+            settings.ethereum.chain,
+        )
+        .await
+        .unwrap();
+
+        bitcoin_blockchain
+            .mint(
+                bitcoin_wallet.new_address().await.unwrap(),
+                asset::Bitcoin::from_sat(1_000_000_000).into(),
+            )
+            .await
+            .unwrap();
+
+        ethereum_blockchain
+            .mint_ether(
+                ethereum_wallet.account(),
+                ether::Amount::from(1_000_000_000_000_000_000u64),
+                settings.ethereum.chain.chain_id(),
+            )
+            .await
+            .unwrap();
+        ethereum_blockchain
+            .mint_erc20_token(
+                ethereum_wallet.account(),
+                asset::Erc20::new(
+                    settings.ethereum.chain.dai_contract_address(),
+                    Erc20Quantity::from_wei(1_000_000_000_000_000_000u64),
+                ),
+                settings.ethereum.chain.chain_id(),
+            )
+            .await
+            .unwrap();
+
+        let _ = MakerBuilder::new(&seed, settings, bitcoin_wallet, ethereum_wallet)
+            .build()
+            .await
+            .unwrap()
+            .run(futures::future::pending())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn stored_swap_is_resumed() {
+        let client = testcontainers::clients::Cli::default();
+        let seed = Seed::random().unwrap();
+
+        let bitcoin_blockchain = test_harness::bitcoin::Blockchain::new(&client).unwrap();
+        bitcoin_blockchain.init().await.unwrap();
+
+        let mut ethereum_blockchain = test_harness::ethereum::Blockchain::new(&client).unwrap();
+        ethereum_blockchain.init().await.unwrap();
+
+        let bitcoin_wallet = bitcoin::Wallet::new(
+            seed,
+            bitcoin_blockchain.node_url.clone(),
+            ::bitcoin::Network::Regtest,
+        )
+        .await
+        .unwrap();
+        let ethereum_wallet = crate::ethereum::Wallet::new(
+            seed,
+            ethereum_blockchain.node_url.clone(),
+            ethereum::Chain::new(ChainId::GETH_DEV, ethereum_blockchain.token_contract()),
+        )
+        .await
+        .unwrap();
+
+        let db = Arc::new(Database::new_test().unwrap());
+        let swap = SwapKind::arbitrary(&mut StdThreadGen::new(100));
+        db.insert_swap(swap.clone()).await.unwrap();
+
+        let events = Events::new();
+        let mut subscriber = events.subscribe();
+        let (finished_swap_sender, _finished_swap_receiver) =
+            futures::channel::mpsc::channel::<FinishedSwap>(0);
+        let (broadcast_sender, _broadcast_receiver) =
+            futures::channel::mpsc::unbounded::<Broadcast>();
+
+        tokio::spawn(execute_swap(
+            Arc::clone(&db),
+            Arc::new(bitcoin_wallet),
+            Arc::new(ethereum_wallet),
+            Arc::new(BitcoindConnector::new(bitcoin_blockchain.node_url.clone()).unwrap()),
+            Arc::new(Web3Connector::new(ethereum_blockchain.node_url.clone())),
+            vec![],
+            None,
+            Duration::from_secs(60),
+            None,
+            Alerter::new(Default::default()),
+            finished_swap_sender,
+            broadcast_sender,
+            events.clone(),
+            swap.clone(),
+        ));
+
+        let message = tokio::time::timeout(Duration::from_secs(10), subscriber.recv())
+            .await
+            .expect("stored swap was not executed")
+            .unwrap();
+        let message = serde_json::to_value(&message).unwrap();
+
+        assert_eq!(message["swap_id"], swap.swap_id().to_string());
+        assert_eq!(message["state"], "executing");
+    }
+
+    #[tokio::test]
+    async fn active_peer_of_a_swap_outlasting_the_ttl_is_not_purged() {
+        let db = Database::new_test().unwrap();
+        let swap = SwapKind::arbitrary(&mut StdThreadGen::new(100));
+        let taker = swap.params().taker;
+        let ttl = Duration::from_millis(400);
+        db.insert_active_peer(
+            taker.clone(),
+            Reservation::of_swap(&swap),
+            active_peer_expiry(ttl),
+        )
+        .await
+        .unwrap();
+
+        renewing_active_peer(&db, &taker, ttl, Delay::new(ttl * 5)).await;
+        let expired = db
+            .purge_expired_active_peers(chrono::Utc::now())
+            .await
+            .unwrap();
+
+        assert!(expired.is_empty());
+    }
+
+    #[tokio::test]
+    async fn timed_out_setup_leaves_the_volume_limit_unchanged() {
+        let db = Database::new_test().unwrap();
+        let mut maker = Maker::static_stub().with_volume_limits(VolumeLimits::new(
+            MaxVolume {
+                bitcoin: Some(btc(2.0)),
+                dai: None,
+            },
+            vec![],
+        ));
+        // Only the balance matters, not the orders to publish with it
+        let _ = maker.update_bitcoin_balance(btc(3.0));
+        let taken_order = btc_dai_order_form(Position::Sell, btc(1.5), rate(0.0));
+        let taker = ActivePeer {
+            peer_id: PeerId::random(),
+        };
+        let swap_id = SwapId::default();
+
+        assert_eq!(
+            maker
+                .process_taken_order(&taker.peer_id, taken_order.clone())
+                .unwrap(),
+            TakeRequestDecision::GoForSwap
+        );
+        db.insert_active_peer(
+            taker,
+            Reservation::of_order(swap_id, &taken_order),
+            chrono::Utc::now() - chrono::Duration::seconds(1),
+        )
+        .await
+        .unwrap();
+        db.insert_sold_volume(
+            &Sale::of(&taken_order, chrono::Utc::now()).into_sold_volume(swap_id),
+        )
+        .await
+        .unwrap();
+
+        assert!(free_expired_reservations(&mut maker, &db).await);
+
+        assert!(recent_sales(&db).unwrap().is_empty());
+        assert_eq!(
+            maker
+                .process_taken_order(&PeerId::random(), taken_order)
+                .unwrap(),
+            TakeRequestDecision::GoForSwap
+        );
+    }
+}
@@ -0,0 +1,254 @@
+//! Periodic fetching of the mid-market rate, the wallet balances, including
+//! the ether paying for gas, and the Bitcoin fee estimate.
+//!
+//! All fetches are driven by a single task: they are staggered over the update
+//! interval so that they do not hit the nodes at the same time, a fetch is
+//! never started while the previous one of the same kind is still in flight
+//! and fetches can be triggered out of schedule, e.g. once a swap finished.
+
+use crate::{
+    bitcoin,
+    ethereum::{dai, ether},
+    MidMarketRate,
+};
+use futures::{
+    channel::mpsc::{Receiver, UnboundedSender},
+    future::BoxFuture,
+    stream::FuturesUnordered,
+    Future, FutureExt, SinkExt, StreamExt,
+};
+use futures_timer::Delay;
+use std::{
+    collections::HashSet,
+    time::{Duration, Instant},
+};
+
+const ENSURED_CONSUME_ZERO_BUFFER: usize = 0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Fetch {
+    Rate,
+    BitcoinBalance,
+    DaiBalance,
+    EtherBalance,
+    BitcoinFee,
+}
+
+impl Fetch {
+    const ALL: [Fetch; 5] = [
+        Fetch::Rate,
+        Fetch::BitcoinBalance,
+        Fetch::DaiBalance,
+        Fetch::EtherBalance,
+        Fetch::BitcoinFee,
+    ];
+}
+
+#[derive(Debug)]
+pub enum Update {
+    Rate(anyhow::Result<MidMarketRate>),
+    BitcoinBalance(anyhow::Result<bitcoin::Amount>),
+    DaiBalance(anyhow::Result<dai::Amount>),
+    /// The ether balance and the gas cost of another swap
+    EtherBalance(anyhow::Result<(ether::Amount, ether::Amount)>),
+    BitcoinFee(anyhow::Result<bitcoin::Amount>),
+}
+
+impl Update {
+    fn kind(&self) -> Fetch {
+        match self {
+            Update::Rate(_) => Fetch::Rate,
+            Update::BitcoinBalance(_) => Fetch::BitcoinBalance,
+            Update::DaiBalance(_) => Fetch::DaiBalance,
+            Update::EtherBalance(_) => Fetch::EtherBalance,
+            Update::BitcoinFee(_) => Fetch::BitcoinFee,
+        }
+    }
+}
+
+/// How often each kind of fetch is scheduled.
+#[derive(Debug, Clone, Copy)]
+pub struct Intervals {
+    pub rate: Duration,
+    pub balances: Duration,
+    pub bitcoin_fee: Duration,
+}
+
+impl Intervals {
+    fn of(&self, fetch: Fetch) -> Duration {
+        match fetch {
+            Fetch::Rate => self.rate,
+            Fetch::BitcoinBalance | Fetch::DaiBalance | Fetch::EtherBalance => self.balances,
+            Fetch::BitcoinFee => self.bitcoin_fee,
+        }
+    }
+}
+
+/// Handle to request fetches out of schedule.
+#[derive(Debug, Clone)]
+pub struct Trigger(UnboundedSender<Fetch>);
+
+impl Trigger {
+    /// Fetch now, unless a fetch of this kind is already in flight.
+    pub fn fetch(&self, fetch: Fetch) {
+        let _ = self.0.unbounded_send(fetch).map_err(|e| {
+            tracing::trace!("Error when triggering {:?} fetch: {}", fetch, e);
+        });
+    }
+}
+
+/// Returns the scheduler task, the stream of updates it produces and a handle
+/// to trigger fetches on demand.
+pub fn init<F>(
+    intervals: Intervals,
+    fetch: F,
+) -> (
+    impl Future<Output = comit::Never> + Send,
+    Receiver<Update>,
+    Trigger,
+)
+where
+    F: Fn(Fetch) -> BoxFuture<'static, Update> + Send + 'static,
+{
+    let (mut sender, receiver) =
+        futures::channel::mpsc::channel::<Update>(ENSURED_CONSUME_ZERO_BUFFER);
+    let (trigger_sender, mut trigger_receiver) = futures::channel::mpsc::unbounded::<Fetch>();
+
+    let future = async move {
+        #[allow(clippy::cast_possible_truncation)]
+        let stagger = intervals
+            .rate
+            .min(intervals.balances)
+            .min(intervals.bitcoin_fee)
+            / Fetch::ALL.len() as u32;
+        let start = Instant::now();
+        let mut due = Fetch::ALL
+            .iter()
+            .zip(0u32..)
+            .map(|(fetch, slot)| (*fetch, start + stagger * slot))
+            .collect::<Vec<_>>();
+        let mut in_flight = HashSet::new();
+        let mut fetches = FuturesUnordered::<BoxFuture<'static, Update>>::new();
+
+        loop {
+            let next = due
+                .iter()
+                .filter(|(fetch, _)| !in_flight.contains(fetch))
+                .min_by_key(|(_, at)| *at)
+                .copied();
+            let timer = match next {
+                Some((_, at)) => Delay::new(at.saturating_duration_since(Instant::now())).boxed(),
+                None => futures::future::pending::<()>().boxed(),
+            };
+
+            let start_fetch = futures::select! {
+                _ = timer.fuse() => next.map(|(fetch, _)| fetch),
+                fetch = trigger_receiver.select_next_some() => Some(fetch),
+                update = fetches.select_next_some() => {
+                    let kind = update.kind();
+                    in_flight.remove(&kind);
+                    reschedule(&mut due, kind, Instant::now() + intervals.of(kind));
+
+                    let _ = sender.send(update).await.map_err(|e| {
+                        tracing::trace!("Error when sending update from sender to receiver: {}", e)
+                    });
+
+                    None
+                }
+            };
+
+            if let Some(kind) = start_fetch {
+                if in_flight.insert(kind) {
+                    fetches.push(fetch(kind));
+                } else {
+                    tracing::trace!("{:?} fetch already in flight", kind);
+                }
+            }
+        }
+    };
+
+    (future, receiver, Trigger(trigger_sender))
+}
+
+fn reschedule(due: &mut [(Fetch, Instant)], fetch: Fetch, at: Instant) {
+    if let Some(entry) = due.iter_mut().find(|(kind, _)| *kind == fetch) {
+        entry.1 = at;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StaticStub;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    fn intervals(interval: Duration) -> Intervals {
+        Intervals {
+            rate: interval,
+            balances: interval,
+            bitcoin_fee: interval,
+        }
+    }
+
+    fn stub_fetch(fetch: Fetch) -> BoxFuture<'static, Update> {
+        async move {
+            Delay::new(Duration::from_millis(50)).await;
+            match fetch {
+                Fetch::Rate => Update::Rate(Ok(MidMarketRate::static_stub())),
+                Fetch::BitcoinBalance => Update::BitcoinBalance(Ok(bitcoin::Amount::ZERO)),
+                Fetch::DaiBalance => Update::DaiBalance(Ok(dai::Amount::zero())),
+                Fetch::EtherBalance => {
+                    Update::EtherBalance(Ok((ether::Amount::zero(), ether::Amount::zero())))
+                }
+                Fetch::BitcoinFee => Update::BitcoinFee(Ok(bitcoin::Amount::ZERO)),
+            }
+        }
+        .boxed()
+    }
+
+    #[tokio::test]
+    async fn fetches_are_staggered_over_the_interval() {
+        let (future, mut receiver, _trigger) =
+            init(intervals(Duration::from_millis(600)), stub_fetch);
+        tokio::spawn(future);
+
+        let mut kinds = Vec::new();
+        for _ in 0..Fetch::ALL.len() {
+            kinds.push(receiver.next().await.unwrap().kind());
+        }
+
+        assert_eq!(kinds, Fetch::ALL.to_vec());
+    }
+
+    #[tokio::test]
+    async fn triggered_fetch_is_deduplicated_while_in_flight() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let fetch = {
+            let count = Arc::clone(&count);
+            move |fetch| {
+                if fetch == Fetch::Rate {
+                    count.fetch_add(1, Ordering::SeqCst);
+                }
+                stub_fetch(fetch)
+            }
+        };
+        let (future, mut receiver, trigger) = init(intervals(Duration::from_secs(60)), fetch);
+        tokio::spawn(future);
+
+        trigger.fetch(Fetch::Rate);
+        trigger.fetch(Fetch::Rate);
+        trigger.fetch(Fetch::Rate);
+
+        let update = receiver.next().await.unwrap();
+        assert_eq!(update.kind(), Fetch::Rate);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        trigger.fetch(Fetch::Rate);
+        let update = receiver.next().await.unwrap();
+        assert_eq!(update.kind(), Fetch::Rate);
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+}
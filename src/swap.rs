@@ -1,7 +1,6 @@
 //! Execute a swap.
 
 mod action;
-#[cfg(test)]
 mod alice;
 pub mod bitcoin;
 mod bob;
@@ -9,12 +8,46 @@ mod comit;
 mod db;
 pub mod ethereum;
 
-use crate::{network::ActivePeer, swap::bob::Bob, SwapId};
-use std::sync::Arc;
+use crate::{
+    config::{BitcoinConfirmations, SwapRole},
+    history, network,
+    network::ActivePeer,
+    swap::{alice::Alice, bob::Bob},
+    Rate, SwapId,
+};
+use futures::{
+    channel::mpsc::UnboundedSender,
+    future::{self, Either},
+    Future,
+};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tracing::Instrument;
 
 pub use self::comit::{hbit, herc20};
 use chrono::{DateTime, Utc};
-pub use db::Database;
+use db::Load;
+pub use db::{
+    AuditedOrder, BalanceSnapshot, Database, IncorrectFunding, OrderAction, OrderAuditEntry,
+    OrderUpdateReason, PeerOutcome, RefundCause, RefundRecord, Reputation, Reservation, SoldVolume,
+    SwapOutcome, TakerListing,
+};
+
+/// How often the ledger time is fetched while waiting for the expiry of our
+/// HTLC alongside the execution of a swap.
+const EXPIRY_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often the progress of a swap is checked if a stall timeout is set.
+const STALL_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// A transaction we broadcast while executing a swap, by ledger.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Broadcast {
+    Bitcoin,
+    Ethereum,
+}
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum SwapKind {
@@ -33,6 +66,163 @@ impl SwapKind {
         self.params().swap_id
     }
 
+    /// Our role in the swap, only Alice knows the secret.
+    pub fn role(&self) -> comit::Role {
+        self.params().role.into()
+    }
+
+    /// Derive the secret from the seed if we are Alice, it is not stored in
+    /// the database and has to be derived again to resume the swap.
+    pub fn with_secret(self, seed: &network::Seed) -> Self {
+        let secret = match self.role() {
+            comit::Role::Alice => Some(seed.derive_secret(self.swap_id())),
+            comit::Role::Bob => None,
+        };
+
+        match self {
+            SwapKind::HbitHerc20(params) => SwapKind::HbitHerc20(SwapParams { secret, ..params }),
+            SwapKind::Herc20Hbit(params) => SwapKind::Herc20Hbit(SwapParams { secret, ..params }),
+        }
+    }
+
+    /// The secret if we are Alice, see `SwapKind::with_secret`.
+    fn secret(&self) -> anyhow::Result<Option<comit::Secret>> {
+        match (self.role(), self.params().secret) {
+            (comit::Role::Alice, None) => {
+                anyhow::bail!("The secret of the swap was not derived from the seed")
+            }
+            (_, secret) => Ok(secret),
+        }
+    }
+
+    /// Whether the asset we lock in the swap is bitcoin, i.e. our HTLC is the
+    /// Bitcoin one.
+    pub fn locks_bitcoin(&self) -> bool {
+        matches!(
+            (self, self.role()),
+            (SwapKind::HbitHerc20(_), comit::Role::Alice)
+                | (SwapKind::Herc20Hbit(_), comit::Role::Bob)
+        )
+    }
+
+    /// The position of the trade from our point of view, we sell the bitcoin
+    /// if we lock it.
+    pub fn position(&self) -> history::Position {
+        if self.locks_bitcoin() {
+            history::Position::Sell
+        } else {
+            history::Position::Buy
+        }
+    }
+
+    /// Why we had to refund the asset we locked in the swap. As Bob we only
+    /// fund our HTLC once the taker funded theirs, a refund means they did not
+    /// redeem ours unless the execution of the swap failed in between. As
+    /// Alice a refund means the taker never funded unless the execution
+    /// failed.
+    pub fn refund_cause(&self, db: &Database) -> anyhow::Result<RefundCause> {
+        let swap_id = self.swap_id();
+
+        if let Some(error) = db.swap_failure(&swap_id)? {
+            return Ok(RefundCause::ExecutionFailed { error });
+        }
+
+        let counterparty_funded = if self.locks_bitcoin() {
+            Load::<herc20::Funded>::load(db, swap_id)?.is_some()
+        } else {
+            Load::<hbit::Funded>::load(db, swap_id)?.is_some()
+        };
+
+        if counterparty_funded {
+            Ok(RefundCause::CounterpartyNeverRedeemed)
+        } else {
+            Ok(RefundCause::CounterpartyNeverFunded)
+        }
+    }
+
+    /// How the swap ended if its execution returned `error`: aborted if we
+    /// never locked funds in it, failed otherwise.
+    pub fn failed(&self, db: &Database, error: &anyhow::Error) -> SwapOutcome {
+        let reason = format!("{:#}", error);
+
+        match self.progress(db) {
+            Ok(Progress {
+                funds_locked: false,
+                ..
+            }) => SwapOutcome::AbortedBeforeFund { reason },
+            Ok(_) => SwapOutcome::Failed { reason },
+            Err(e) => {
+                tracing::error!(
+                    "Could not check whether we locked funds in the swap: {:#}",
+                    e
+                );
+                SwapOutcome::Failed { reason }
+            }
+        }
+    }
+
+    /// Load the transactions of the swap recorded in the database, as well as
+    /// the fee we paid to spend the Bitcoin HTLC.
+    pub fn settlement(&self, db: &Database) -> anyhow::Result<Settlement> {
+        let swap_id = self.swap_id();
+
+        let hbit_funded = Load::<hbit::Funded>::load(db, swap_id)?;
+        let hbit_redeemed = Load::<hbit::Redeemed>::load(db, swap_id)?;
+        let hbit_refunded = Load::<hbit::Refunded>::load(db, swap_id)?;
+        let herc20_deployed = Load::<herc20::Deployed>::load(db, swap_id)?;
+        let herc20_funded = Load::<herc20::Funded>::load(db, swap_id)?;
+        let herc20_redeemed = Load::<herc20::Redeemed>::load(db, swap_id)?;
+        let herc20_refunded = Load::<herc20::Refunded>::load(db, swap_id)?;
+
+        // We only spend the Bitcoin HTLC when redeeming it as buyer of bitcoin
+        // or when refunding it as seller of bitcoin
+        let our_htlc_spend = if self.locks_bitcoin() {
+            hbit_refunded.as_ref().map(|event| &event.transaction)
+        } else {
+            hbit_redeemed.as_ref().map(|event| &event.transaction)
+        };
+        let bitcoin_fee = match (hbit_funded, our_htlc_spend) {
+            (Some(funded), Some(transaction)) => {
+                let spent: u64 = transaction.output.iter().map(|output| output.value).sum();
+                Some(crate::bitcoin::Amount::from_sat(
+                    funded.asset.as_sat().saturating_sub(spent),
+                ))
+            }
+            _ => None,
+        };
+
+        Ok(Settlement {
+            bitcoin_fund: hbit_funded.map(|event| event.location.txid),
+            bitcoin_redeem: hbit_redeemed.map(|event| event.transaction.txid()),
+            bitcoin_refund: hbit_refunded.map(|event| event.transaction.txid()),
+            ethereum_deploy: herc20_deployed.map(|event| event.transaction.hash),
+            ethereum_fund: herc20_funded.map(|event| event.transaction.hash),
+            ethereum_redeem: herc20_redeemed.map(|event| event.transaction.hash),
+            ethereum_refund: herc20_refunded.map(|event| event.transaction.hash),
+            bitcoin_fee,
+        })
+    }
+
+    /// Execute the swap, all events emitted during the execution carry the
+    /// `swap_id` and `peer_id` of the swap.
+    ///
+    /// The expiry of our HTLC is watched alongside the execution: if the
+    /// execution fails after we locked funds, or has not finished once our
+    /// HTLC expired, we refund it rather than waiting on the execution.
+    ///
+    /// The Bitcoin HTLC funded by the taker is only considered funded once it
+    /// has the confirmations `bitcoin_confirmations` requires for the amount
+    /// of the swap.
+    ///
+    /// Each transaction we broadcast is reported to `broadcasts`, if given, as
+    /// soon as it went out.
+    ///
+    /// If `stall_timeout` is given, the execution is aborted once the swap did
+    /// not progress for that long, as long as we did not lock funds in it.
+    ///
+    /// Returns how the swap ended if it completed or we refunded, errors mean
+    /// the swap was aborted or failed, see `SwapKind::failed`.
+    #[allow(clippy::too_many_arguments)]
     pub async fn execute(
         &self,
         db: Arc<Database>,
@@ -40,7 +230,349 @@ impl SwapKind {
         ethereum_wallet: Arc<crate::ethereum::Wallet>,
         bitcoin_connector: Arc<comit::btsieve::bitcoin::BitcoindConnector>,
         ethereum_connector: Arc<comit::btsieve::ethereum::Web3Connector>,
-    ) -> anyhow::Result<()> {
+        bitcoin_confirmations: &[BitcoinConfirmations],
+        stall_timeout: Option<Duration>,
+        broadcasts: Option<UnboundedSender<Broadcast>>,
+    ) -> anyhow::Result<SwapOutcome> {
+        let params = self.params();
+        let span = tracing::info_span!(
+            "swap",
+            swap_id = %params.swap_id,
+            peer_id = %params.taker.peer_id()
+        );
+
+        async {
+            tracing::info!("Executing swap");
+            let result = self
+                .execute_or_refund(
+                    db,
+                    bitcoin_wallet,
+                    ethereum_wallet,
+                    bitcoin_connector,
+                    ethereum_connector,
+                    bitcoin_confirmations,
+                    stall_timeout,
+                    broadcasts,
+                )
+                .await;
+            match &result {
+                Ok(outcome) => tracing::info!("Swap finished: {}", outcome),
+                Err(e) => tracing::error!("Swap failed: {:#}", e),
+            }
+            result
+        }
+        .instrument(span)
+        .await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn execute_or_refund(
+        &self,
+        db: Arc<Database>,
+        bitcoin_wallet: Arc<crate::bitcoin::Wallet>,
+        ethereum_wallet: Arc<crate::ethereum::Wallet>,
+        bitcoin_connector: Arc<comit::btsieve::bitcoin::BitcoindConnector>,
+        ethereum_connector: Arc<comit::btsieve::ethereum::Web3Connector>,
+        bitcoin_confirmations: &[BitcoinConfirmations],
+        stall_timeout: Option<Duration>,
+        broadcasts: Option<UnboundedSender<Broadcast>>,
+    ) -> anyhow::Result<SwapOutcome> {
+        let execution = self.execute_in_role(
+            Arc::clone(&db),
+            Arc::clone(&bitcoin_wallet),
+            Arc::clone(&ethereum_wallet),
+            Arc::clone(&bitcoin_connector),
+            Arc::clone(&ethereum_connector),
+            bitcoin_confirmations,
+            broadcasts.clone(),
+        );
+        let stall = async {
+            match stall_timeout {
+                Some(timeout) => stalled(|| self.progress(&db), timeout).await,
+                None => future::pending().await,
+            }
+        };
+        let execution = async {
+            futures::pin_mut!(execution, stall);
+            match future::select(execution, stall).await {
+                Either::Left((result, _)) => result,
+                Either::Right((stalled, _)) => Err(anyhow::Error::from(stalled)),
+            }
+        };
+        let our_htlc_expired = self.our_htlc_expired(
+            Arc::clone(&bitcoin_connector),
+            Arc::clone(&ethereum_connector),
+        );
+        // Only polled once the execution failed or our HTLC expired
+        let refund = self.refund_in_role(
+            Arc::clone(&db),
+            bitcoin_wallet,
+            ethereum_wallet,
+            bitcoin_connector,
+            ethereum_connector,
+            broadcasts,
+        );
+        futures::pin_mut!(execution, our_htlc_expired, refund);
+
+        let execution = match future::select(execution, our_htlc_expired).await {
+            Either::Left((result, _)) => return self.refund_if_failed(&db, result, refund).await,
+            Either::Right(((), execution)) => execution,
+        };
+
+        // The execution keeps going as the taker may have redeemed our HTLC
+        // just before it expired, in which case we still have to redeem theirs
+        tracing::warn!("Our HTLC expired before the swap finished, refunding it");
+        match future::select(execution, refund).await {
+            Either::Left((result, refund)) => self.refund_if_failed(&db, result, refund).await,
+            Either::Right((Ok(true), _)) => Ok(SwapOutcome::RefundedAfter(self.refund_cause(&db)?)),
+            Either::Right((Ok(false), _)) => anyhow::bail!(CounterpartyNeverFunded),
+            Either::Right((Err(e), execution)) => {
+                tracing::error!("Could not refund our expired HTLC: {:#}", e);
+                execution.await
+            }
+        }
+    }
+
+    /// How far the execution of the swap got: the events recorded so far and
+    /// whether we locked funds in our HTLC, in which case only its refund can
+    /// end the swap early.
+    fn progress(&self, db: &Database) -> anyhow::Result<Progress> {
+        let swap_id = self.swap_id();
+
+        let events = [
+            Load::<hbit::Funded>::load(db, swap_id)?.is_some(),
+            Load::<hbit::Redeemed>::load(db, swap_id)?.is_some(),
+            Load::<hbit::Refunded>::load(db, swap_id)?.is_some(),
+            Load::<herc20::Deployed>::load(db, swap_id)?.is_some(),
+            Load::<herc20::Funded>::load(db, swap_id)?.is_some(),
+            Load::<herc20::Redeemed>::load(db, swap_id)?.is_some(),
+            Load::<herc20::Refunded>::load(db, swap_id)?.is_some(),
+        ];
+        // Same as `refund_in_role`, which refunds from the deployment of the
+        // Ethereum HTLC on
+        let funds_locked = if self.locks_bitcoin() {
+            events[0]
+        } else {
+            events[3]
+        };
+
+        Ok(Progress {
+            events: events.iter().filter(|recorded| **recorded).count(),
+            funds_locked,
+        })
+    }
+
+    /// Resolves once our HTLC expired. Errors fetching the ledger time are
+    /// retried, they must not stop us from refunding.
+    async fn our_htlc_expired(
+        &self,
+        bitcoin_connector: Arc<comit::btsieve::bitcoin::BitcoindConnector>,
+        ethereum_connector: Arc<comit::btsieve::ethereum::Web3Connector>,
+    ) {
+        let SwapParams {
+            hbit_params,
+            herc20_params,
+            ..
+        } = self.params();
+
+        if self.locks_bitcoin() {
+            wait_for_expiry(bitcoin_connector.as_ref(), hbit_params.shared.expiry).await
+        } else {
+            wait_for_expiry(ethereum_connector.as_ref(), herc20_params.expiry).await
+        }
+    }
+
+    /// If the execution failed, refund our HTLC once it expired in case we
+    /// locked funds in it. The failure is then recorded as the cause of the
+    /// refund and the swap finishes as refunded.
+    async fn refund_if_failed(
+        &self,
+        db: &Database,
+        result: anyhow::Result<SwapOutcome>,
+        refund: impl Future<Output = anyhow::Result<bool>>,
+    ) -> anyhow::Result<SwapOutcome> {
+        let error = match result {
+            Ok(outcome) => return Ok(outcome),
+            Err(error) => error,
+        };
+
+        if let Some(funded) = error.downcast_ref::<hbit::IncorrectlyFunded>() {
+            let incorrect_funding = IncorrectFunding {
+                swap_id: self.swap_id(),
+                recorded_at: Utc::now(),
+                expected_sat: funded.expected.as_sat(),
+                funded_sat: funded.funded.as_sat(),
+            };
+            if let Err(e) = db.insert_incorrect_funding(&incorrect_funding).await {
+                tracing::error!("Could not record the incorrect funding: {:#}", e);
+            }
+        }
+
+        tracing::warn!(
+            "Swap execution failed, refunding our HTLC if we locked funds in it: {:#}",
+            error
+        );
+        match refund.await {
+            Ok(true) => {
+                let error = format!("{:#}", error);
+                db.insert_swap_failure(&self.swap_id(), &error).await?;
+                Ok(SwapOutcome::RefundedAfter(RefundCause::ExecutionFailed {
+                    error,
+                }))
+            }
+            Ok(false) => Err(error),
+            Err(e) => {
+                tracing::error!("Could not refund our HTLC: {:#}", e);
+                Err(error)
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn execute_in_role(
+        &self,
+        db: Arc<Database>,
+        bitcoin_wallet: Arc<crate::bitcoin::Wallet>,
+        ethereum_wallet: Arc<crate::ethereum::Wallet>,
+        bitcoin_connector: Arc<comit::btsieve::bitcoin::BitcoindConnector>,
+        ethereum_connector: Arc<comit::btsieve::ethereum::Web3Connector>,
+        bitcoin_confirmations: &[BitcoinConfirmations],
+        broadcasts: Option<UnboundedSender<Broadcast>>,
+    ) -> anyhow::Result<SwapOutcome> {
+        match self.secret()? {
+            Some(secret) => {
+                self.execute_as_alice(
+                    db,
+                    bitcoin_wallet,
+                    ethereum_wallet,
+                    bitcoin_connector,
+                    ethereum_connector,
+                    bitcoin_confirmations,
+                    secret,
+                    broadcasts,
+                )
+                .await
+            }
+            None => {
+                self.execute_as_bob(
+                    db,
+                    bitcoin_wallet,
+                    ethereum_wallet,
+                    bitcoin_connector,
+                    ethereum_connector,
+                    bitcoin_confirmations,
+                    broadcasts,
+                )
+                .await
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn execute_as_alice(
+        &self,
+        db: Arc<Database>,
+        bitcoin_wallet: Arc<crate::bitcoin::Wallet>,
+        ethereum_wallet: Arc<crate::ethereum::Wallet>,
+        bitcoin_connector: Arc<comit::btsieve::bitcoin::BitcoindConnector>,
+        ethereum_connector: Arc<comit::btsieve::ethereum::Web3Connector>,
+        bitcoin_confirmations: &[BitcoinConfirmations],
+        secret: comit::Secret,
+        broadcasts: Option<UnboundedSender<Broadcast>>,
+    ) -> anyhow::Result<SwapOutcome> {
+        let bitcoin_wallet = bitcoin::Wallet {
+            inner: bitcoin_wallet,
+            connector: Arc::clone(&bitcoin_connector),
+        };
+        let ethereum_wallet = ethereum::Wallet {
+            inner: ethereum_wallet,
+            connector: ethereum_connector,
+        };
+
+        let outcome = match self {
+            SwapKind::HbitHerc20(SwapParams {
+                hbit_params,
+                herc20_params,
+                start_of_swap,
+                swap_id,
+                ..
+            }) => {
+                // Watches through the wallet for the taker's deployment and
+                // funding to be confirmed before we redeem
+                let ethereum_watcher = ethereum_wallet.clone();
+                let alice = Alice {
+                    alpha_wallet: bitcoin_wallet,
+                    beta_wallet: ethereum_wallet,
+                    db,
+                    swap_id: *swap_id,
+                    secret,
+                    utc_start_of_swap: *start_of_swap,
+                    beta_expiry: herc20_params.expiry,
+                    broadcasts,
+                };
+
+                comit::hbit_herc20_alice(
+                    alice,
+                    &ethereum_watcher,
+                    *hbit_params,
+                    herc20_params.clone(),
+                    secret,
+                    *start_of_swap,
+                )
+                .await?
+            }
+            SwapKind::Herc20Hbit(SwapParams {
+                hbit_params,
+                herc20_params,
+                start_of_swap,
+                swap_id,
+                ..
+            }) => {
+                let alice = Alice {
+                    alpha_wallet: ethereum_wallet,
+                    beta_wallet: bitcoin_wallet,
+                    db,
+                    swap_id: *swap_id,
+                    secret,
+                    utc_start_of_swap: *start_of_swap,
+                    beta_expiry: hbit_params.shared.expiry,
+                    broadcasts,
+                };
+
+                let bitcoin_connector = bitcoin::Confirmed {
+                    connector: bitcoin_connector,
+                    confirmations: bitcoin::required_confirmations(
+                        bitcoin_confirmations,
+                        hbit_params.shared.asset.into(),
+                    ),
+                };
+
+                comit::herc20_hbit_alice(
+                    alice,
+                    &bitcoin_connector,
+                    herc20_params.clone(),
+                    *hbit_params,
+                    secret,
+                    *start_of_swap,
+                )
+                .await?
+            }
+        };
+
+        Ok(outcome)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn execute_as_bob(
+        &self,
+        db: Arc<Database>,
+        bitcoin_wallet: Arc<crate::bitcoin::Wallet>,
+        ethereum_wallet: Arc<crate::ethereum::Wallet>,
+        bitcoin_connector: Arc<comit::btsieve::bitcoin::BitcoindConnector>,
+        ethereum_connector: Arc<comit::btsieve::ethereum::Web3Connector>,
+        bitcoin_confirmations: &[BitcoinConfirmations],
+        broadcasts: Option<UnboundedSender<Broadcast>>,
+    ) -> anyhow::Result<SwapOutcome> {
         let bitcoin_wallet = bitcoin::Wallet {
             inner: bitcoin_wallet,
             connector: Arc::clone(&bitcoin_connector),
@@ -50,7 +582,7 @@ impl SwapKind {
             connector: Arc::clone(&ethereum_connector),
         };
 
-        match self {
+        let outcome = match self {
             SwapKind::HbitHerc20(SwapParams {
                 hbit_params,
                 herc20_params,
@@ -67,11 +599,20 @@ impl SwapKind {
                     secret_hash: *secret_hash,
                     utc_start_of_swap: *start_of_swap,
                     beta_expiry: herc20_params.expiry,
+                    broadcasts: broadcasts.clone(),
+                };
+
+                let bitcoin_connector = bitcoin::Confirmed {
+                    connector: bitcoin_connector,
+                    confirmations: bitcoin::required_confirmations(
+                        bitcoin_confirmations,
+                        hbit_params.shared.asset.into(),
+                    ),
                 };
 
                 comit::hbit_herc20_bob(
                     bob,
-                    bitcoin_connector.as_ref(),
+                    &bitcoin_connector,
                     ethereum_connector.as_ref(),
                     *hbit_params,
                     herc20_params.clone(),
@@ -87,6 +628,9 @@ impl SwapKind {
                 swap_id,
                 ..
             }) => {
+                // Watches through the wallet for the taker's deployment and
+                // funding to be confirmed before we fund
+                let ethereum_watcher = ethereum_wallet.clone();
                 let bob = Bob {
                     alpha_wallet: ethereum_wallet,
                     beta_wallet: bitcoin_wallet,
@@ -95,11 +639,12 @@ impl SwapKind {
                     secret_hash: *secret_hash,
                     utc_start_of_swap: *start_of_swap,
                     beta_expiry: herc20_params.expiry,
+                    broadcasts: broadcasts.clone(),
                 };
 
                 comit::herc20_hbit_bob(
                     bob,
-                    ethereum_connector.as_ref(),
+                    &ethereum_watcher,
                     bitcoin_connector.as_ref(),
                     herc20_params.clone(),
                     *hbit_params,
@@ -109,18 +654,353 @@ impl SwapKind {
             }
         };
 
-        Ok(())
+        Ok(outcome)
+    }
+
+    /// Only refund the asset we locked in the swap once our HTLC expired,
+    /// without executing any other action of the swap. Returns whether we had
+    /// locked anything to refund.
+    pub async fn refund(
+        &self,
+        db: Arc<Database>,
+        bitcoin_wallet: Arc<crate::bitcoin::Wallet>,
+        ethereum_wallet: Arc<crate::ethereum::Wallet>,
+        bitcoin_connector: Arc<comit::btsieve::bitcoin::BitcoindConnector>,
+        ethereum_connector: Arc<comit::btsieve::ethereum::Web3Connector>,
+    ) -> anyhow::Result<bool> {
+        let params = self.params();
+        let span = tracing::info_span!(
+            "swap",
+            swap_id = %params.swap_id,
+            peer_id = %params.taker.peer_id()
+        );
+
+        async {
+            tracing::info!("Refunding swap");
+            let result = self
+                .refund_in_role(
+                    db,
+                    bitcoin_wallet,
+                    ethereum_wallet,
+                    bitcoin_connector,
+                    ethereum_connector,
+                    None,
+                )
+                .await;
+            match &result {
+                Ok(true) => tracing::info!("Swap refunded"),
+                Ok(false) => tracing::info!("Nothing was locked in the swap, nothing to refund"),
+                Err(e) => tracing::error!("Refund failed: {:#}", e),
+            }
+            result
+        }
+        .instrument(span)
+        .await
+    }
+
+    async fn refund_in_role(
+        &self,
+        db: Arc<Database>,
+        bitcoin_wallet: Arc<crate::bitcoin::Wallet>,
+        ethereum_wallet: Arc<crate::ethereum::Wallet>,
+        bitcoin_connector: Arc<comit::btsieve::bitcoin::BitcoindConnector>,
+        ethereum_connector: Arc<comit::btsieve::ethereum::Web3Connector>,
+        broadcasts: Option<UnboundedSender<Broadcast>>,
+    ) -> anyhow::Result<bool> {
+        match self.secret()? {
+            Some(secret) => {
+                self.refund_as_alice(
+                    db,
+                    bitcoin_wallet,
+                    ethereum_wallet,
+                    bitcoin_connector,
+                    ethereum_connector,
+                    secret,
+                    broadcasts,
+                )
+                .await
+            }
+            None => {
+                self.refund_as_bob(
+                    db,
+                    bitcoin_wallet,
+                    ethereum_wallet,
+                    bitcoin_connector,
+                    ethereum_connector,
+                    broadcasts,
+                )
+                .await
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn refund_as_alice(
+        &self,
+        db: Arc<Database>,
+        bitcoin_wallet: Arc<crate::bitcoin::Wallet>,
+        ethereum_wallet: Arc<crate::ethereum::Wallet>,
+        bitcoin_connector: Arc<comit::btsieve::bitcoin::BitcoindConnector>,
+        ethereum_connector: Arc<comit::btsieve::ethereum::Web3Connector>,
+        secret: comit::Secret,
+        broadcasts: Option<UnboundedSender<Broadcast>>,
+    ) -> anyhow::Result<bool> {
+        let bitcoin_wallet = bitcoin::Wallet {
+            inner: bitcoin_wallet,
+            connector: bitcoin_connector,
+        };
+        let ethereum_wallet = ethereum::Wallet {
+            inner: ethereum_wallet,
+            connector: ethereum_connector,
+        };
+
+        match self {
+            SwapKind::HbitHerc20(SwapParams {
+                hbit_params,
+                herc20_params,
+                start_of_swap,
+                swap_id,
+                ..
+            }) => {
+                let funded = match Load::<hbit::Funded>::load(db.as_ref(), *swap_id)? {
+                    Some(funded) => funded,
+                    None => return Ok(false),
+                };
+
+                tracing::info!("Waiting for the expiry of our Bitcoin HTLC");
+                wait_for_expiry(&bitcoin_wallet, hbit_params.shared.expiry).await;
+
+                let alice = Alice {
+                    alpha_wallet: bitcoin_wallet,
+                    beta_wallet: ethereum_wallet,
+                    db,
+                    swap_id: *swap_id,
+                    secret,
+                    utc_start_of_swap: *start_of_swap,
+                    beta_expiry: herc20_params.expiry,
+                    broadcasts,
+                };
+
+                hbit::ExecuteRefund::execute_refund(&alice, *hbit_params, funded).await?;
+            }
+            SwapKind::Herc20Hbit(SwapParams {
+                hbit_params,
+                herc20_params,
+                start_of_swap,
+                swap_id,
+                ..
+            }) => {
+                let deployed = match Load::<herc20::Deployed>::load(db.as_ref(), *swap_id)? {
+                    Some(deployed) => deployed,
+                    None => return Ok(false),
+                };
+
+                tracing::info!("Waiting for the expiry of our Ethereum HTLC");
+                wait_for_expiry(&ethereum_wallet, herc20_params.expiry).await;
+
+                let alice = Alice {
+                    alpha_wallet: ethereum_wallet,
+                    beta_wallet: bitcoin_wallet,
+                    db,
+                    swap_id: *swap_id,
+                    secret,
+                    utc_start_of_swap: *start_of_swap,
+                    beta_expiry: hbit_params.shared.expiry,
+                    broadcasts,
+                };
+
+                herc20::ExecuteRefund::execute_refund(
+                    &alice,
+                    herc20_params.clone(),
+                    deployed,
+                    *start_of_swap,
+                )
+                .await?;
+            }
+        };
+
+        Ok(true)
+    }
+
+    async fn refund_as_bob(
+        &self,
+        db: Arc<Database>,
+        bitcoin_wallet: Arc<crate::bitcoin::Wallet>,
+        ethereum_wallet: Arc<crate::ethereum::Wallet>,
+        bitcoin_connector: Arc<comit::btsieve::bitcoin::BitcoindConnector>,
+        ethereum_connector: Arc<comit::btsieve::ethereum::Web3Connector>,
+        broadcasts: Option<UnboundedSender<Broadcast>>,
+    ) -> anyhow::Result<bool> {
+        let bitcoin_wallet = bitcoin::Wallet {
+            inner: bitcoin_wallet,
+            connector: bitcoin_connector,
+        };
+        let ethereum_wallet = ethereum::Wallet {
+            inner: ethereum_wallet,
+            connector: ethereum_connector,
+        };
+
+        match self {
+            SwapKind::HbitHerc20(SwapParams {
+                herc20_params,
+                secret_hash,
+                start_of_swap,
+                swap_id,
+                ..
+            }) => {
+                let deployed = match Load::<herc20::Deployed>::load(db.as_ref(), *swap_id)? {
+                    Some(deployed) => deployed,
+                    None => return Ok(false),
+                };
+
+                tracing::info!("Waiting for the expiry of our Ethereum HTLC");
+                wait_for_expiry(&ethereum_wallet, herc20_params.expiry).await;
+
+                let bob = Bob {
+                    alpha_wallet: bitcoin_wallet,
+                    beta_wallet: ethereum_wallet,
+                    db,
+                    swap_id: *swap_id,
+                    secret_hash: *secret_hash,
+                    utc_start_of_swap: *start_of_swap,
+                    beta_expiry: herc20_params.expiry,
+                    broadcasts,
+                };
+
+                herc20::ExecuteRefund::execute_refund(
+                    &bob,
+                    herc20_params.clone(),
+                    deployed,
+                    *start_of_swap,
+                )
+                .await?;
+            }
+            SwapKind::Herc20Hbit(SwapParams {
+                hbit_params,
+                herc20_params,
+                secret_hash,
+                start_of_swap,
+                swap_id,
+                ..
+            }) => {
+                let funded = match Load::<hbit::Funded>::load(db.as_ref(), *swap_id)? {
+                    Some(funded) => funded,
+                    None => return Ok(false),
+                };
+
+                tracing::info!("Waiting for the expiry of our Bitcoin HTLC");
+                wait_for_expiry(&bitcoin_wallet, hbit_params.shared.expiry).await;
+
+                let bob = Bob {
+                    alpha_wallet: ethereum_wallet,
+                    beta_wallet: bitcoin_wallet,
+                    db,
+                    swap_id: *swap_id,
+                    secret_hash: *secret_hash,
+                    utc_start_of_swap: *start_of_swap,
+                    beta_expiry: herc20_params.expiry,
+                    broadcasts,
+                };
+
+                hbit::ExecuteRefund::execute_refund(&bob, *hbit_params, funded).await?;
+            }
+        };
+
+        Ok(true)
     }
 }
 
+/// The taker took our order but did not fund before our HTLC expired.
+#[derive(Debug, Copy, Clone, thiserror::Error)]
+#[error("Our HTLC expired before we locked any funds in it")]
+pub struct CounterpartyNeverFunded;
+
+/// The execution of the swap did not progress for the stall timeout, e.g.
+/// because a connector stopped returning new blocks.
+#[derive(Debug, Copy, Clone, thiserror::Error)]
+#[error("The swap did not progress for {} seconds", .0.as_secs())]
+pub struct SwapStalled(Duration);
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Progress {
+    events: usize,
+    funds_locked: bool,
+}
+
+/// Resolves once `progress` did not change for `timeout` while no funds are
+/// locked. Once funds are locked the stall is only logged, aborting would stop
+/// us from redeeming the HTLC of the taker if they redeem ours.
+async fn stalled(
+    mut progress: impl FnMut() -> anyhow::Result<Progress>,
+    timeout: Duration,
+) -> SwapStalled {
+    let poll_interval = std::cmp::min(STALL_POLL_INTERVAL, timeout / 4);
+    let mut last = None;
+    let mut progressed_at = Instant::now();
+    let mut logged = false;
+
+    loop {
+        match progress() {
+            Ok(current) if last != Some(current) => {
+                last = Some(current);
+                progressed_at = Instant::now();
+                logged = false;
+            }
+            Ok(_) => {}
+            Err(e) => tracing::warn!("Could not load the progress of the swap: {:#}", e),
+        }
+
+        if progressed_at.elapsed() >= timeout {
+            match last {
+                Some(Progress {
+                    funds_locked: true, ..
+                }) => {
+                    if !logged {
+                        tracing::warn!(
+                            "Swap did not progress for {} seconds, waiting for our HTLC to expire as we locked funds in it",
+                            timeout.as_secs()
+                        );
+                        logged = true;
+                    }
+                }
+                _ => return SwapStalled(timeout),
+            }
+        }
+
+        tokio::time::delay_for(poll_interval).await;
+    }
+}
+
+/// How a finished swap was settled on-chain.
+#[derive(Clone, Debug, Default)]
+pub struct Settlement {
+    pub bitcoin_fund: Option<::bitcoin::Txid>,
+    pub bitcoin_redeem: Option<::bitcoin::Txid>,
+    pub bitcoin_refund: Option<::bitcoin::Txid>,
+    pub ethereum_deploy: Option<ethereum::Hash>,
+    pub ethereum_fund: Option<ethereum::Hash>,
+    pub ethereum_redeem: Option<ethereum::Hash>,
+    pub ethereum_refund: Option<ethereum::Hash>,
+    /// The fee of our transaction spending the Bitcoin HTLC. The fees of the
+    /// funding transaction are paid by the bitcoind wallet and gas is paid
+    /// in ether, neither is known here.
+    pub bitcoin_fee: Option<crate::bitcoin::Amount>,
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct SwapParams {
     pub hbit_params: hbit::Params,
     pub herc20_params: herc20::Params,
     pub secret_hash: comit::SecretHash,
+    pub role: SwapRole,
+    /// The secret of `secret_hash` if we are Alice, derived from the seed
+    /// rather than stored.
+    pub secret: Option<comit::Secret>,
     pub start_of_swap: DateTime<Utc>,
     pub swap_id: SwapId,
     pub taker: ActivePeer,
+    /// The mid-market rate when the order was matched.
+    pub mid_market_rate: Option<Rate>,
 }
 
 /// Fetch the current network time for a ledger.
@@ -150,6 +1030,24 @@ where
     }
 }
 
+/// Wait until the ledger time reached `expiry`, unlike
+/// `poll_beta_has_expired` errors fetching the ledger time are logged and
+/// retried.
+async fn wait_for_expiry<C>(connector: &C, expiry: comit::Timestamp)
+where
+    C: LedgerTime,
+{
+    loop {
+        match connector.ledger_time().await {
+            Ok(ledger_time) if expiry <= ledger_time => return,
+            Ok(_) => {}
+            Err(e) => tracing::warn!("Could not fetch the ledger time, retrying: {:#}", e),
+        }
+
+        tokio::time::delay_for(EXPIRY_POLL_INTERVAL).await;
+    }
+}
+
 #[cfg(test)]
 impl crate::StaticStub for SwapParams {
     fn static_stub() -> Self {
@@ -196,9 +1094,12 @@ impl crate::StaticStub for SwapParams {
                 chain_id: 42.into(),
             },
             secret_hash: SecretHash::new(comit::Secret::from(*b"hello world, you are beautiful!!")),
+            role: SwapRole::Bob,
+            secret: None,
             start_of_swap: chrono::Utc::now(),
             swap_id: Default::default(),
             taker: ActivePeer::static_stub(),
+            mid_market_rate: None,
         }
     }
 }
@@ -242,9 +1143,17 @@ mod arbitrary {
                 hbit_params: hbit::Params::arbitrary(g),
                 herc20_params,
                 secret_hash: secret_hash(g),
+                role: if bool::arbitrary(g) {
+                    SwapRole::Alice
+                } else {
+                    SwapRole::Bob
+                },
+                // As loaded from the database, before the secret is derived
+                secret: None,
                 start_of_swap: chrono::DateTime::from_utc(naive, chrono::offset::Utc),
                 swap_id: SwapId::arbitrary(g),
                 taker: ActivePeer::arbitrary(g),
+                mid_market_rate: Option::<u64>::arbitrary(g).map(Rate::new),
             }
         }
     }
@@ -341,6 +1250,73 @@ mod tests {
         Secret::from(*bytes)
     }
 
+    #[tokio::test]
+    async fn settlement_of_swap_without_events_is_empty() {
+        let db = Database::new_test().unwrap();
+        let swap = SwapKind::Herc20Hbit(SwapParams::static_stub());
+        db.insert_swap(swap.clone()).await.unwrap();
+
+        let settlement = swap.settlement(&db).unwrap();
+
+        assert!(settlement.bitcoin_fund.is_none());
+        assert!(settlement.ethereum_deploy.is_none());
+        assert!(settlement.bitcoin_fee.is_none());
+    }
+
+    #[tokio::test]
+    async fn swap_without_progress_stalls_unless_funds_are_locked() {
+        let db = Database::new_test().unwrap();
+        let swap = SwapKind::Herc20Hbit(SwapParams::static_stub());
+        db.insert_swap(swap.clone()).await.unwrap();
+        let timeout = Duration::from_millis(100);
+
+        let progress = swap.progress(&db).unwrap();
+        assert_eq!(progress, Progress {
+            events: 0,
+            funds_locked: false
+        });
+
+        let stalled_at = Instant::now();
+        let SwapStalled(stall_timeout) = stalled(|| swap.progress(&db), timeout).await;
+        assert_eq!(stall_timeout, timeout);
+        assert!(stalled_at.elapsed() >= timeout);
+
+        let locked = stalled(
+            || {
+                Ok(Progress {
+                    events: 1,
+                    funds_locked: true,
+                })
+            },
+            timeout,
+        );
+        let still_waiting = tokio::time::timeout(timeout * 3, locked).await;
+        assert!(still_waiting.is_err());
+    }
+
+    #[tokio::test]
+    async fn refund_cause_prefers_the_failure_of_the_execution() {
+        let db = Database::new_test().unwrap();
+        let swap = SwapKind::HbitHerc20(SwapParams::static_stub());
+        db.insert_swap(swap.clone()).await.unwrap();
+
+        assert_eq!(
+            swap.refund_cause(&db).unwrap(),
+            RefundCause::CounterpartyNeverFunded
+        );
+
+        db.insert_swap_failure(&swap.swap_id(), "connection refused")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            swap.refund_cause(&db).unwrap(),
+            RefundCause::ExecutionFailed {
+                error: "connection refused".to_owned()
+            }
+        );
+    }
+
     #[tokio::test]
     async fn execute_alice_hbit_herc20_swap() -> anyhow::Result<()> {
         let client = clients::Cli::default();
@@ -489,9 +1465,12 @@ mod tests {
                 },
                 herc20_params: herc20_params.clone(),
                 secret_hash,
+                role: SwapRole::Alice,
+                secret: Some(secret),
                 start_of_swap,
                 swap_id,
                 taker: ActivePeer::static_stub(),
+                mid_market_rate: None,
             });
 
             alice_db.insert_swap(swap).await.unwrap();
@@ -505,6 +1484,7 @@ mod tests {
                 secret,
                 utc_start_of_swap: start_of_swap,
                 beta_expiry: herc20_params.expiry,
+                broadcasts: None,
             };
 
             comit::hbit_herc20_alice(
@@ -527,9 +1507,12 @@ mod tests {
                 },
                 herc20_params: herc20_params.clone(),
                 secret_hash,
+                role: SwapRole::Bob,
+                secret: None,
                 start_of_swap,
                 swap_id,
                 taker: ActivePeer::static_stub(),
+                mid_market_rate: None,
             });
 
             bob_db.insert_swap(swap).await.unwrap();
@@ -543,6 +1526,7 @@ mod tests {
                 secret_hash,
                 utc_start_of_swap: start_of_swap,
                 beta_expiry: herc20_params.expiry,
+                broadcasts: None,
             };
 
             comit::hbit_herc20_bob(
@@ -608,3 +1592,234 @@ mod tests {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod mock_tests {
+    use super::*;
+    use crate::{
+        swap::{
+            alice::Alice,
+            comit::{Secret, Timestamp},
+            db::Load,
+        },
+        test_harness::mock::{BitcoinEvent, BitcoinWallet, EthereumEvent, EthereumWallet, Ledger},
+        StaticStub,
+    };
+    use futures::StreamExt;
+    use std::time::Duration;
+
+    const TIMEOUT: Duration = Duration::from_secs(30);
+
+    fn secret() -> Secret {
+        Secret::from(*b"hello world, you are beautiful!!")
+    }
+
+    fn swap_params(genesis: Timestamp) -> SwapParams {
+        let mut params = SwapParams::static_stub();
+        params.hbit_params.shared.expiry = genesis.plus(2 * 60 * 60);
+        params.herc20_params.expiry = genesis.plus(60 * 60);
+
+        params
+    }
+
+    async fn bob(
+        params: &SwapParams,
+        bitcoin_ledger: &Ledger<BitcoinEvent>,
+        ethereum_ledger: &Ledger<EthereumEvent>,
+    ) -> Bob<BitcoinWallet, EthereumWallet> {
+        let db = Arc::new(Database::new_test().unwrap());
+        db.insert_swap(SwapKind::HbitHerc20(params.clone()))
+            .await
+            .unwrap();
+
+        Bob {
+            alpha_wallet: BitcoinWallet {
+                ledger: bitcoin_ledger.clone(),
+            },
+            beta_wallet: EthereumWallet {
+                ledger: ethereum_ledger.clone(),
+            },
+            db,
+            swap_id: params.swap_id,
+            secret_hash: params.secret_hash,
+            utc_start_of_swap: params.start_of_swap,
+            beta_expiry: params.herc20_params.expiry,
+            broadcasts: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn execute_hbit_herc20_swap_against_mock_ledgers() {
+        let genesis = Timestamp::from(1_600_000_000u32);
+        let bitcoin_ledger = Ledger::new(genesis);
+        let ethereum_ledger = Ledger::new(genesis);
+        let params = swap_params(genesis);
+
+        let alice_db = Arc::new(Database::new_test().unwrap());
+        alice_db
+            .insert_swap(SwapKind::HbitHerc20(params.clone()))
+            .await
+            .unwrap();
+        let alice = Alice {
+            alpha_wallet: BitcoinWallet {
+                ledger: bitcoin_ledger.clone(),
+            },
+            beta_wallet: EthereumWallet {
+                ledger: ethereum_ledger.clone(),
+            },
+            db: alice_db,
+            swap_id: params.swap_id,
+            secret: secret(),
+            utc_start_of_swap: params.start_of_swap,
+            beta_expiry: params.herc20_params.expiry,
+            broadcasts: None,
+        };
+        let alice_swap = comit::hbit_herc20_alice(
+            alice,
+            &ethereum_ledger,
+            params.hbit_params,
+            params.herc20_params.clone(),
+            secret(),
+            params.start_of_swap,
+        );
+
+        let mut bob = bob(&params, &bitcoin_ledger, &ethereum_ledger).await;
+        let (broadcast_sender, broadcast_receiver) = futures::channel::mpsc::unbounded();
+        bob.broadcasts = Some(broadcast_sender);
+        let bob_db = Arc::clone(&bob.db);
+        let bob_swap = comit::hbit_herc20_bob(
+            bob,
+            &bitcoin_ledger,
+            &ethereum_ledger,
+            params.hbit_params,
+            params.herc20_params.clone(),
+            params.start_of_swap,
+        );
+
+        tokio::time::timeout(TIMEOUT, futures::future::try_join(alice_swap, bob_swap))
+            .await
+            .unwrap()
+            .unwrap();
+
+        let redeemed: Option<hbit::Redeemed> = bob_db.load(params.swap_id).unwrap();
+        assert_eq!(redeemed.unwrap().secret, secret());
+        let refunded: Option<herc20::Refunded> = bob_db.load(params.swap_id).unwrap();
+        assert!(refunded.is_none());
+        assert!(matches!(bitcoin_ledger.events().as_slice(), [
+            BitcoinEvent::Funded { .. },
+            BitcoinEvent::Redeemed { .. }
+        ]));
+
+        let broadcasts = broadcast_receiver.collect::<Vec<_>>().await;
+        assert_eq!(broadcasts, vec![Broadcast::Ethereum, Broadcast::Bitcoin]);
+    }
+
+    #[tokio::test]
+    async fn bob_refunds_herc20_if_alice_never_redeems() {
+        let genesis = Timestamp::from(1_600_000_000u32);
+        let bitcoin_ledger = Ledger::new(genesis);
+        let ethereum_ledger = Ledger::new(genesis);
+        let params = swap_params(genesis);
+
+        let bob = bob(&params, &bitcoin_ledger, &ethereum_ledger).await;
+        let bob_db = Arc::clone(&bob.db);
+        let bob_swap = comit::hbit_herc20_bob(
+            bob,
+            &bitcoin_ledger,
+            &ethereum_ledger,
+            params.hbit_params,
+            params.herc20_params.clone(),
+            params.start_of_swap,
+        );
+
+        // Alice funds but walks away once Bob funded
+        let alice_walks_away = async {
+            let alice_bitcoin_wallet = BitcoinWallet {
+                ledger: bitcoin_ledger.clone(),
+            };
+            hbit::ExecuteFund::execute_fund(&alice_bitcoin_wallet, &params.hbit_params)
+                .await
+                .unwrap();
+
+            while !ethereum_ledger
+                .events()
+                .iter()
+                .any(|event| matches!(event, EthereumEvent::Funded { .. }))
+            {
+                tokio::time::delay_for(Duration::from_millis(10)).await;
+            }
+            ethereum_ledger.mine_until(params.herc20_params.expiry);
+        };
+
+        let (bob_result, ()) =
+            tokio::time::timeout(TIMEOUT, futures::future::join(bob_swap, alice_walks_away))
+                .await
+                .unwrap();
+        assert_eq!(
+            bob_result.unwrap(),
+            SwapOutcome::RefundedAfter(RefundCause::CounterpartyNeverRedeemed)
+        );
+
+        let refunded: Option<herc20::Refunded> = bob_db.load(params.swap_id).unwrap();
+        assert!(refunded.is_some());
+        let redeemed: Option<hbit::Redeemed> = bob_db.load(params.swap_id).unwrap();
+        assert!(redeemed.is_none());
+    }
+
+    #[test]
+    fn alice_locks_the_asset_of_the_alpha_ledger() {
+        let alice = SwapParams {
+            role: SwapRole::Alice,
+            ..SwapParams::static_stub()
+        };
+
+        assert!(SwapKind::HbitHerc20(alice.clone()).locks_bitcoin());
+        assert!(!SwapKind::Herc20Hbit(alice).locks_bitcoin());
+        assert!(!SwapKind::HbitHerc20(SwapParams::static_stub()).locks_bitcoin());
+        assert!(SwapKind::Herc20Hbit(SwapParams::static_stub()).locks_bitcoin());
+    }
+
+    #[test]
+    fn alice_and_bob_take_opposite_positions_in_a_swap() {
+        use crate::history::Position;
+
+        let alice = SwapParams {
+            role: SwapRole::Alice,
+            ..SwapParams::static_stub()
+        };
+
+        assert!(matches!(
+            SwapKind::HbitHerc20(alice.clone()).position(),
+            Position::Sell
+        ));
+        assert!(matches!(
+            SwapKind::Herc20Hbit(alice).position(),
+            Position::Buy
+        ));
+        assert!(matches!(
+            SwapKind::HbitHerc20(SwapParams::static_stub()).position(),
+            Position::Buy
+        ));
+        assert!(matches!(
+            SwapKind::Herc20Hbit(SwapParams::static_stub()).position(),
+            Position::Sell
+        ));
+    }
+
+    #[test]
+    fn only_the_secret_of_alice_is_derived_from_the_seed() {
+        let seed = network::Seed::new([7u8; crate::seed::SEED_LENGTH]);
+        let alice = SwapKind::HbitHerc20(SwapParams {
+            role: SwapRole::Alice,
+            ..SwapParams::static_stub()
+        });
+        let bob = SwapKind::HbitHerc20(SwapParams::static_stub());
+
+        let swap_id = alice.swap_id();
+        assert_eq!(
+            alice.with_secret(&seed).params().secret,
+            Some(seed.derive_secret(swap_id))
+        );
+        assert_eq!(bob.with_secret(&seed).params().secret, None);
+    }
+}
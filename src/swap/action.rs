@@ -5,6 +5,11 @@ use crate::{
 use anyhow::Context;
 use futures::{future::FutureExt, Future};
 
+/// The span of an action executed by one of the parties of the swap.
+pub fn action_span(chain: &'static str, action: &'static str) -> tracing::Span {
+    tracing::info_span!("action", chain, action)
+}
+
 /// Try to do an action resulting in the event `E`.
 ///
 /// If we can `Load` the event `E` corresponding to `swap_id` from the
@@ -31,9 +36,11 @@ where
     E: Clone + Send + Sync + 'static,
 {
     if let Some(event) = db.load(swap_id)? {
+        tracing::debug!("Action already executed, skipping");
         return Ok(event);
     }
 
+    tracing::info!("Executing action");
     let event = futures::select! {
         event = action.fuse() => event.context("failed to execute action")?,
         abort = poll_abortion_condition.fuse() => {
@@ -44,6 +51,7 @@ where
     };
 
     db.save(event.clone(), swap_id).await?;
+    tracing::info!("Action executed");
     Ok(event)
 }
 
@@ -1,4 +1,9 @@
-use crate::swap::{hbit, LedgerTime};
+use crate::{
+    config::BitcoinConfirmations,
+    swap::{hbit, LedgerTime},
+};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
 use comit::{
     bitcoin::median_time_past,
     btsieve::{bitcoin::BitcoindConnector, BlockByHash, LatestBlock},
@@ -7,7 +12,15 @@ use comit::{
 use std::{sync::Arc, time::Duration};
 
 pub use crate::bitcoin::Amount;
-pub use ::bitcoin::{secp256k1::SecretKey, Address, Block, BlockHash, OutPoint, Transaction};
+pub use ::bitcoin::{secp256k1::SecretKey, Address, Block, BlockHash, OutPoint, Transaction, Txid};
+
+/// How often the confirmations of the Bitcoin HTLC funded by the taker are
+/// counted while waiting for enough of them.
+const CONFIRMATION_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The timestamp of a block may be up to two hours ahead of the time it was
+/// mined at.
+const BLOCK_TIME_TOLERANCE_SECS: i64 = 2 * 60 * 60;
 
 #[derive(Debug, Clone)]
 pub struct Wallet {
@@ -22,7 +35,7 @@ impl hbit::ExecuteFund for Wallet {
 
         let txid = self
             .inner
-            .send_to_address(action.to, action.amount.into(), action.network.into())
+            .send_replaceable_to_address(action.to, action.amount.into(), action.network.into())
             .await?;
 
         // we send money to a single address, vout is always 0
@@ -33,6 +46,72 @@ impl hbit::ExecuteFund for Wallet {
     }
 }
 
+#[async_trait::async_trait]
+impl hbit::BumpFee for Wallet {
+    async fn bump_fee(
+        &self,
+        params: &hbit::Params,
+        fund_event: hbit::Funded,
+        utc_start_of_swap: DateTime<Utc>,
+    ) -> anyhow::Result<Option<hbit::Funded>> {
+        let fee_bumping = match self.inner.fee_bumping() {
+            Some(fee_bumping) => fee_bumping,
+            None => return Ok(None),
+        };
+        let deadline = fee_bump_deadline(
+            utc_start_of_swap,
+            params.shared.expiry,
+            fee_bumping.expiry_fraction,
+        );
+        let txid = fund_event.location.txid;
+
+        loop {
+            if self.inner.confirmations(txid).await? > 0 {
+                return Ok(None);
+            }
+            if deadline <= self.ledger_time().await? {
+                break;
+            }
+            tokio::time::delay_for(CONFIRMATION_POLL_INTERVAL).await;
+        }
+
+        tracing::info!(
+            "Bitcoin HTLC funding {} still unconfirmed, replacing it with a higher fee",
+            txid
+        );
+        let replacement = self.inner.bump_fee(txid).await?;
+        let htlc = params.shared.build_fund_action().to.script_pubkey();
+        let vout = replacement
+            .output
+            .iter()
+            .position(|output| output.script_pubkey == htlc)
+            .context("the replacement does not fund the Bitcoin HTLC")?;
+
+        Ok(Some(hbit::Funded {
+            asset: fund_event.asset,
+            location: OutPoint {
+                txid: replacement.txid(),
+                vout: vout as u32,
+            },
+        }))
+    }
+}
+
+/// The ledger time from which our HTLC funding is replaced if still
+/// unconfirmed: once `expiry_fraction` of the time between the start of the
+/// swap and the expiry elapsed.
+fn fee_bump_deadline(
+    utc_start_of_swap: DateTime<Utc>,
+    expiry: Timestamp,
+    expiry_fraction: f64,
+) -> Timestamp {
+    let start = utc_start_of_swap.timestamp() as f64;
+    let expiry = f64::from(u32::from(expiry));
+    let deadline = start + (expiry - start).max(0.0) * expiry_fraction;
+
+    Timestamp::from(deadline as u32)
+}
+
 #[async_trait::async_trait]
 impl hbit::ExecuteRedeem for Wallet {
     async fn execute_redeem(
@@ -133,9 +212,171 @@ impl LedgerTime for BitcoindConnector {
     }
 }
 
+#[async_trait::async_trait]
+impl hbit::WatchForFunded for BitcoindConnector {
+    async fn watch_for_funded(
+        &self,
+        params: &hbit::SharedParams,
+        utc_start_of_swap: DateTime<Utc>,
+    ) -> anyhow::Result<hbit::Funded> {
+        hbit::watch_for_funded(self, params, utc_start_of_swap).await
+    }
+}
+
+#[async_trait::async_trait]
+impl hbit::WatchForRedeemed for BitcoindConnector {
+    async fn watch_for_redeemed(
+        &self,
+        params: &hbit::SharedParams,
+        location: hbit::htlc_location::Bitcoin,
+        utc_start_of_swap: DateTime<Utc>,
+    ) -> anyhow::Result<hbit::Redeemed> {
+        hbit::watch_for_redeemed(self, params, location, utc_start_of_swap).await
+    }
+}
+
 #[async_trait::async_trait]
 impl LedgerTime for Wallet {
     async fn ledger_time(&self) -> anyhow::Result<Timestamp> {
         self.connector.as_ref().ledger_time().await
     }
 }
+
+/// The number of confirmations of the Bitcoin HTLC funded by the taker
+/// required for a swap of `amount`: the most blocks of the confirmations whose
+/// amount it reaches, at least one.
+pub fn required_confirmations(confirmations: &[BitcoinConfirmations], amount: Amount) -> u32 {
+    confirmations
+        .iter()
+        .filter(|tier| tier.bitcoin.map_or(true, |minimum| amount >= minimum))
+        .map(|tier| tier.blocks)
+        .max()
+        .unwrap_or(1)
+        .max(1)
+}
+
+/// Watches for the Bitcoin HTLC funded by the taker and only considers it
+/// funded once the funding transaction has `confirmations` confirmations,
+/// to protect larger swaps against reorganisations.
+#[derive(Debug, Clone)]
+pub struct Confirmed {
+    pub connector: Arc<BitcoindConnector>,
+    pub confirmations: u32,
+}
+
+#[async_trait::async_trait]
+impl hbit::WatchForFunded for Confirmed {
+    async fn watch_for_funded(
+        &self,
+        params: &hbit::SharedParams,
+        utc_start_of_swap: DateTime<Utc>,
+    ) -> anyhow::Result<hbit::Funded> {
+        let funded =
+            hbit::watch_for_funded(self.connector.as_ref(), params, utc_start_of_swap).await?;
+        if self.confirmations <= 1 {
+            return Ok(funded);
+        }
+
+        tracing::info!(
+            "Waiting for {} confirmations of the Bitcoin HTLC",
+            self.confirmations
+        );
+        loop {
+            match confirmations(
+                self.connector.as_ref(),
+                funded.location.txid,
+                utc_start_of_swap,
+            )
+            .await
+            {
+                Ok(confirmations) if confirmations >= self.confirmations => break,
+                Ok(confirmations) => {
+                    tracing::debug!("Bitcoin HTLC has {} confirmations", confirmations)
+                }
+                Err(e) => tracing::warn!("Could not count the confirmations, retrying: {:#}", e),
+            }
+
+            tokio::time::delay_for(CONFIRMATION_POLL_INTERVAL).await;
+        }
+
+        Ok(funded)
+    }
+}
+
+/// Count the confirmations of `txid` by looking for it in the blocks mined
+/// since `utc_start_of_swap`, from the tip down. It has none if it is not in
+/// any of them, e.g. after being reorganised out.
+async fn confirmations<C>(
+    connector: &C,
+    txid: Txid,
+    utc_start_of_swap: DateTime<Utc>,
+) -> anyhow::Result<u32>
+where
+    C: LatestBlock<Block = Block> + BlockByHash<Block = Block, BlockHash = BlockHash>,
+{
+    let oldest_block_time = utc_start_of_swap.timestamp() - BLOCK_TIME_TOLERANCE_SECS;
+
+    let mut block = connector.latest_block().await?;
+    let mut depth = 1;
+    loop {
+        if block
+            .txdata
+            .iter()
+            .any(|transaction| transaction.txid() == txid)
+        {
+            return Ok(depth);
+        }
+        if i64::from(block.header.time) < oldest_block_time {
+            return Ok(0);
+        }
+
+        block = connector.block_by_hash(block.header.prev_blockhash).await?;
+        depth += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitcoin::amount::btc;
+    use chrono::TimeZone;
+
+    fn tier(bitcoin: Option<f64>, blocks: u32) -> BitcoinConfirmations {
+        BitcoinConfirmations {
+            bitcoin: bitcoin.map(btc),
+            blocks,
+        }
+    }
+
+    #[test]
+    fn fee_bump_deadline_is_the_fraction_of_the_time_until_expiry() {
+        let utc_start_of_swap = Utc.timestamp(1_000_000, 0);
+        let expiry = Timestamp::from(1_004_000);
+
+        let deadline = fee_bump_deadline(utc_start_of_swap, expiry, 0.25);
+
+        assert_eq!(deadline, Timestamp::from(1_001_000));
+    }
+
+    #[test]
+    fn a_single_confirmation_is_required_by_default() {
+        assert_eq!(required_confirmations(&[], btc(10.0)), 1);
+    }
+
+    #[test]
+    fn confirmations_scale_with_the_amount_of_the_swap() {
+        let policy = [tier(None, 1), tier(Some(0.5), 3), tier(Some(2.0), 6)];
+
+        assert_eq!(required_confirmations(&policy, btc(0.1)), 1);
+        assert_eq!(required_confirmations(&policy, btc(0.5)), 3);
+        assert_eq!(required_confirmations(&policy, btc(1.0)), 3);
+        assert_eq!(required_confirmations(&policy, btc(5.0)), 6);
+    }
+
+    #[test]
+    fn amounts_below_every_threshold_require_a_single_confirmation() {
+        let policy = [tier(Some(1.0), 6)];
+
+        assert_eq!(required_confirmations(&policy, btc(0.5)), 1);
+    }
+}
@@ -1,17 +1,22 @@
 //! Bob's perspective of the swap.
 //!
-//! In Nectar we always take the role of Bob in the swap, so this
-//! component has to be prepared to execute actions using wallets.
+//! Unless configured to take the role of Alice, Nectar takes the role of Bob
+//! in the swap, so this component has to be prepared to execute actions using
+//! wallets.
 
 use crate::{
     swap::{
-        action::try_do_it_once, bitcoin, ethereum, hbit, herc20, poll_beta_has_expired, Database,
+        action::{action_span, try_do_it_once},
+        db::{Rollback, Save},
+        hbit, herc20, poll_beta_has_expired, Broadcast, Database, LedgerTime,
     },
     SwapId,
 };
 use chrono::{DateTime, Utc};
 use comit::{Secret, SecretHash, Timestamp};
+use futures::channel::mpsc::UnboundedSender;
 use std::sync::Arc;
+use tracing::Instrument;
 
 #[derive(Clone, Debug)]
 pub struct Bob<AW, BW> {
@@ -22,56 +27,142 @@ pub struct Bob<AW, BW> {
     pub secret_hash: SecretHash,
     pub utc_start_of_swap: DateTime<Utc>,
     pub beta_expiry: Timestamp,
+    /// Notified of every transaction we broadcast that moves our funds.
+    pub broadcasts: Option<UnboundedSender<Broadcast>>,
+}
+
+impl<AW, BW> Bob<AW, BW> {
+    fn notify_broadcast(&self, broadcast: Broadcast) {
+        if let Some(broadcasts) = &self.broadcasts {
+            let _ = broadcasts
+                .unbounded_send(broadcast)
+                .map_err(|e| tracing::trace!("Error when sending broadcast notification: {}", e));
+        }
+    }
 }
 
 #[async_trait::async_trait]
-impl<AW> herc20::ExecuteDeploy for Bob<AW, ethereum::Wallet>
+impl<AW, BW> herc20::ExecuteDeploy for Bob<AW, BW>
 where
     AW: Send + Sync,
+    BW: herc20::ExecuteDeploy
+        + herc20::WaitForConfirmations
+        + herc20::WatchForDeployed
+        + LedgerTime
+        + Send
+        + Sync,
 {
+    /// If the deployment gets orphaned the stored event is rolled back and we
+    /// watch for the transaction to be included again.
     async fn execute_deploy(&self, params: herc20::Params) -> anyhow::Result<herc20::Deployed> {
-        let action = self.beta_wallet.execute_deploy(params);
+        let action = self.beta_wallet.execute_deploy(params.clone());
         let poll_beta_has_expired = poll_beta_has_expired(&self.beta_wallet, self.beta_expiry);
 
-        try_do_it_once(
+        let event = try_do_it_once(
             self.db.as_ref(),
             self.swap_id,
             action,
             poll_beta_has_expired,
         )
-        .await
+        .instrument(action_span("ethereum", "deploy"))
+        .await?;
+
+        match self
+            .beta_wallet
+            .wait_for_confirmations(event.transaction.hash)
+            .await?
+        {
+            herc20::Inclusion::Confirmed => Ok(event),
+            herc20::Inclusion::Orphaned => {
+                tracing::warn!("Ethereum HTLC deployment was orphaned, watching for it again");
+                Rollback::<herc20::Deployed>::rollback(self.db.as_ref(), self.swap_id).await?;
+
+                let action = self
+                    .beta_wallet
+                    .watch_for_deployed(params, self.utc_start_of_swap);
+                let poll_beta_has_expired =
+                    poll_beta_has_expired(&self.beta_wallet, self.beta_expiry);
+
+                try_do_it_once(
+                    self.db.as_ref(),
+                    self.swap_id,
+                    action,
+                    poll_beta_has_expired,
+                )
+                .instrument(action_span("ethereum", "deploy"))
+                .await
+            }
+        }
     }
 }
 
 #[async_trait::async_trait]
-impl<AW> herc20::ExecuteFund for Bob<AW, ethereum::Wallet>
+impl<AW, BW> herc20::ExecuteFund for Bob<AW, BW>
 where
     AW: Send + Sync,
+    BW: herc20::ExecuteFund
+        + herc20::WaitForConfirmations
+        + herc20::WatchForFunded
+        + LedgerTime
+        + Send
+        + Sync,
 {
+    /// If the funding gets orphaned the stored event is rolled back and we
+    /// watch for the transaction to be included again.
     async fn execute_fund(
         &self,
         params: herc20::Params,
         deploy_event: herc20::Deployed,
         utc_start_of_swap: DateTime<Utc>,
     ) -> anyhow::Result<herc20::Funded> {
-        let action = self
-            .beta_wallet
-            .execute_fund(params, deploy_event, utc_start_of_swap);
+        let action =
+            self.beta_wallet
+                .execute_fund(params.clone(), deploy_event.clone(), utc_start_of_swap);
         let poll_beta_has_expired = poll_beta_has_expired(&self.beta_wallet, self.beta_expiry);
 
-        try_do_it_once(
+        let event = try_do_it_once(
             self.db.as_ref(),
             self.swap_id,
             action,
             poll_beta_has_expired,
         )
-        .await
+        .instrument(action_span("ethereum", "fund"))
+        .await?;
+        self.notify_broadcast(Broadcast::Ethereum);
+
+        match self
+            .beta_wallet
+            .wait_for_confirmations(event.transaction.hash)
+            .await?
+        {
+            herc20::Inclusion::Confirmed => Ok(event),
+            herc20::Inclusion::Orphaned => {
+                tracing::warn!("Ethereum HTLC funding was orphaned, watching for it again");
+                Rollback::<herc20::Funded>::rollback(self.db.as_ref(), self.swap_id).await?;
+
+                let action =
+                    self.beta_wallet
+                        .watch_for_funded(params, utc_start_of_swap, deploy_event);
+                let poll_beta_has_expired =
+                    poll_beta_has_expired(&self.beta_wallet, self.beta_expiry);
+
+                try_do_it_once(
+                    self.db.as_ref(),
+                    self.swap_id,
+                    action,
+                    poll_beta_has_expired,
+                )
+                .instrument(action_span("ethereum", "fund"))
+                .await
+            }
+        }
     }
 }
 
 #[async_trait::async_trait]
-impl<BW> herc20::ExecuteRedeem for Bob<ethereum::Wallet, BW>
+impl<AW, BW> herc20::ExecuteRedeem for Bob<AW, BW>
 where
+    AW: herc20::ExecuteRedeem + Send + Sync,
     BW: Send + Sync,
 {
     async fn execute_redeem(
@@ -85,20 +176,25 @@ where
             self.alpha_wallet
                 .execute_redeem(params, secret, deploy_event, utc_start_of_swap);
 
-        try_do_it_once(
+        let event = try_do_it_once(
             self.db.as_ref(),
             self.swap_id,
             action,
             futures::future::pending(),
         )
-        .await
+        .instrument(action_span("ethereum", "redeem"))
+        .await?;
+        self.notify_broadcast(Broadcast::Ethereum);
+
+        Ok(event)
     }
 }
 
 #[async_trait::async_trait]
-impl<AW> herc20::ExecuteRefund for Bob<AW, ethereum::Wallet>
+impl<AW, BW> herc20::ExecuteRefund for Bob<AW, BW>
 where
     AW: Send + Sync,
+    BW: herc20::ExecuteRefund + Send + Sync,
 {
     async fn execute_refund(
         &self,
@@ -110,38 +206,71 @@ where
             .beta_wallet
             .execute_refund(params, deploy_event, utc_start_of_swap);
 
-        try_do_it_once(
+        let event = try_do_it_once(
             self.db.as_ref(),
             self.swap_id,
             action,
             futures::future::pending(),
         )
-        .await
+        .instrument(action_span("ethereum", "refund"))
+        .await?;
+        self.notify_broadcast(Broadcast::Ethereum);
+
+        Ok(event)
     }
 }
 
 #[async_trait::async_trait]
-impl<AW> hbit::ExecuteFund for Bob<AW, bitcoin::Wallet>
+impl<AW, BW> hbit::ExecuteFund for Bob<AW, BW>
 where
     AW: Send + Sync,
+    BW: hbit::ExecuteFund + hbit::BumpFee + LedgerTime + Send + Sync,
 {
+    /// If the funding is replaced to bump its fee the stored event is
+    /// replaced as well, failing to bump the fee does not fail the swap.
     async fn execute_fund(&self, params: &hbit::Params) -> anyhow::Result<hbit::Funded> {
         let action = self.beta_wallet.execute_fund(params);
         let poll_beta_has_expired = poll_beta_has_expired(&self.beta_wallet, self.beta_expiry);
 
-        try_do_it_once(
+        let event = try_do_it_once(
             self.db.as_ref(),
             self.swap_id,
             action,
             poll_beta_has_expired,
         )
-        .await
+        .instrument(action_span("bitcoin", "fund"))
+        .await?;
+        self.notify_broadcast(Broadcast::Bitcoin);
+
+        let replacement = match self
+            .beta_wallet
+            .bump_fee(params, event, self.utc_start_of_swap)
+            .instrument(action_span("bitcoin", "bump_fee"))
+            .await
+        {
+            Ok(Some(replacement)) => replacement,
+            Ok(None) => return Ok(event),
+            Err(e) => {
+                tracing::warn!(
+                    "Could not bump the fee of the Bitcoin HTLC funding: {:#}",
+                    e
+                );
+                return Ok(event);
+            }
+        };
+
+        Rollback::<hbit::Funded>::rollback(self.db.as_ref(), self.swap_id).await?;
+        Save::<hbit::Funded>::save(self.db.as_ref(), replacement, self.swap_id).await?;
+        self.notify_broadcast(Broadcast::Bitcoin);
+
+        Ok(replacement)
     }
 }
 
 #[async_trait::async_trait]
-impl<BW> hbit::ExecuteRedeem for Bob<bitcoin::Wallet, BW>
+impl<AW, BW> hbit::ExecuteRedeem for Bob<AW, BW>
 where
+    AW: hbit::ExecuteRedeem + Send + Sync,
     BW: Send + Sync,
 {
     async fn execute_redeem(
@@ -152,20 +281,25 @@ where
     ) -> anyhow::Result<comit::hbit::Redeemed> {
         let action = self.alpha_wallet.execute_redeem(params, fund_event, secret);
 
-        try_do_it_once(
+        let event = try_do_it_once(
             self.db.as_ref(),
             self.swap_id,
             action,
             futures::future::pending(),
         )
-        .await
+        .instrument(action_span("bitcoin", "redeem"))
+        .await?;
+        self.notify_broadcast(Broadcast::Bitcoin);
+
+        Ok(event)
     }
 }
 
 #[async_trait::async_trait]
-impl<AW> hbit::ExecuteRefund for Bob<AW, bitcoin::Wallet>
+impl<AW, BW> hbit::ExecuteRefund for Bob<AW, BW>
 where
     AW: Send + Sync,
+    BW: hbit::ExecuteRefund + Send + Sync,
 {
     async fn execute_refund(
         &self,
@@ -174,12 +308,16 @@ where
     ) -> anyhow::Result<comit::hbit::Refunded> {
         let action = self.beta_wallet.execute_refund(params, fund_event);
 
-        try_do_it_once(
+        let event = try_do_it_once(
             self.db.as_ref(),
             self.swap_id,
             action,
             futures::future::pending(),
         )
-        .await
+        .instrument(action_span("bitcoin", "refund"))
+        .await?;
+        self.notify_broadcast(Broadcast::Bitcoin);
+
+        Ok(event)
     }
 }
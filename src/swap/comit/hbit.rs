@@ -46,6 +46,20 @@ pub trait ExecuteRefund {
     async fn execute_refund(&self, params: Params, fund_event: Funded) -> anyhow::Result<Refunded>;
 }
 
+/// Replace our funding of the HTLC by one paying a higher fee if it is not
+/// confirmed in time.
+#[async_trait::async_trait]
+pub trait BumpFee {
+    /// Wait for `fund_event` to be confirmed, returns the replacing funding
+    /// if it was not before the fee bumping deadline.
+    async fn bump_fee(
+        &self,
+        params: &Params,
+        fund_event: Funded,
+        utc_start_of_swap: DateTime<Utc>,
+    ) -> anyhow::Result<Option<Funded>>;
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Funded {
     pub asset: asset::Bitcoin,
@@ -64,7 +78,100 @@ where
         comit::hbit::Funded::Correctly {
             asset, location, ..
         } => Ok(Funded { asset, location }),
-        comit::hbit::Funded::Incorrectly { .. } => anyhow::bail!("Bitcoin HTLC incorrectly funded"),
+        comit::hbit::Funded::Incorrectly {
+            asset, location, ..
+        } => Ok(accept_funding(params.asset, asset, location)?),
+    }
+}
+
+/// The HTLC funded with an amount other than the agreed one is only accepted
+/// if it locks more, the whole output is then redeemed.
+fn accept_funding(
+    expected: asset::Bitcoin,
+    funded: asset::Bitcoin,
+    location: htlc_location::Bitcoin,
+) -> Result<Funded, IncorrectlyFunded> {
+    let (expected, funded) = (
+        crate::bitcoin::Amount::from(expected),
+        crate::bitcoin::Amount::from(funded),
+    );
+    if funded < expected {
+        return Err(IncorrectlyFunded {
+            expected,
+            funded,
+            location,
+        });
+    }
+
+    tracing::warn!(
+        "Bitcoin HTLC funded with {} instead of {}, accepted as it exceeds it",
+        funded,
+        expected
+    );
+    Ok(Funded {
+        asset: funded.into(),
+        location,
+    })
+}
+
+/// The counterparty funded the Bitcoin HTLC with less than the agreed amount,
+/// the swap is aborted before we lock any funds and the amounts are recorded.
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+#[error("Bitcoin HTLC {location} funded with {funded} instead of {expected}")]
+pub struct IncorrectlyFunded {
+    pub expected: crate::bitcoin::Amount,
+    pub funded: crate::bitcoin::Amount,
+    pub location: htlc_location::Bitcoin,
+}
+
+/// Watch the Bitcoin ledger for the HTLC being funded by the counterparty.
+#[async_trait::async_trait]
+pub trait WatchForFunded {
+    async fn watch_for_funded(
+        &self,
+        params: &SharedParams,
+        utc_start_of_swap: DateTime<Utc>,
+    ) -> anyhow::Result<Funded>;
+}
+
+/// Watch the Bitcoin ledger for the HTLC being redeemed by the counterparty.
+#[async_trait::async_trait]
+pub trait WatchForRedeemed {
+    async fn watch_for_redeemed(
+        &self,
+        params: &SharedParams,
+        location: htlc_location::Bitcoin,
+        utc_start_of_swap: DateTime<Utc>,
+    ) -> anyhow::Result<Redeemed>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn funding_exceeding_the_agreed_amount_is_accepted_in_full() {
+        let funded = accept_funding(
+            asset::Bitcoin::from_sat(100_000),
+            asset::Bitcoin::from_sat(100_001),
+            htlc_location::Bitcoin::default(),
+        )
+        .unwrap();
+
+        assert_eq!(funded.asset, asset::Bitcoin::from_sat(100_001));
+    }
+
+    #[test]
+    fn funding_short_of_the_agreed_amount_is_rejected() {
+        let error = accept_funding(
+            asset::Bitcoin::from_sat(100_000),
+            asset::Bitcoin::from_sat(99_999),
+            htlc_location::Bitcoin::default(),
+        )
+        .unwrap_err();
+
+        assert_eq!(error.funded, crate::bitcoin::Amount::from_sat(99_999));
+        assert_eq!(error.expected, crate::bitcoin::Amount::from_sat(100_000));
     }
 }
 
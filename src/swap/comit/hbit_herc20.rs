@@ -1,16 +1,13 @@
-use crate::swap::{hbit, herc20};
+use crate::swap::{hbit, herc20, RefundCause, SwapOutcome};
+use anyhow::Context;
 use chrono::{DateTime, Utc};
-use comit::{
-    btsieve,
-    btsieve::{BlockByHash, LatestBlock},
-    ethereum, Secret,
-};
+use comit::Secret;
 
 /// Execute a Hbit<->Herc20 swap for Alice.
 ///
 /// Delegates to `hbit_herc20_happy_alice` and handles errors by
-/// executing refund for Alice when necessary.
-#[allow(dead_code)] // This is library code
+/// executing refund for Alice when necessary, errors that leave nothing to
+/// refund are returned.
 pub async fn hbit_herc20_alice<A, EC>(
     alice: A,
     ethereum_connector: &EC,
@@ -18,12 +15,10 @@ pub async fn hbit_herc20_alice<A, EC>(
     herc20_params: herc20::Params,
     secret: Secret,
     utc_start_of_swap: DateTime<Utc>,
-) -> anyhow::Result<()>
+) -> anyhow::Result<SwapOutcome>
 where
     A: hbit::ExecuteFund + herc20::ExecuteRedeem + hbit::ExecuteRefund,
-    EC: LatestBlock<Block = ethereum::Block>
-        + BlockByHash<Block = ethereum::Block, BlockHash = ethereum::Hash>
-        + btsieve::ethereum::ReceiptByHash,
+    EC: herc20::WatchForDeployed + herc20::WatchForFunded,
 {
     let res = hbit_herc20_happy_alice(
         &alice,
@@ -36,13 +31,22 @@ where
     .await;
 
     use HbitHerc20AliceError::*;
-    if let Err(BobDeploy(hbit_funded)) | Err(BobFund(hbit_funded)) | Err(AliceRedeem(hbit_funded)) =
-        res
-    {
-        alice.execute_refund(hbit_params, hbit_funded).await?;
+    let error = match res {
+        Ok(()) => return Ok(SwapOutcome::Completed),
+        Err(error) => error,
+    };
+    let (hbit_funded, cause) = match error.downcast_ref::<HbitHerc20AliceError>() {
+        Some(BobDeploy(hbit_funded)) | Some(BobFund(hbit_funded)) => {
+            (*hbit_funded, RefundCause::CounterpartyNeverFunded)
+        }
+        Some(AliceRedeem(hbit_funded)) => (*hbit_funded, RefundCause::ExecutionFailed {
+            error: format!("{:#}", error),
+        }),
+        Some(AliceFund) | None => return Err(error),
     };
+    alice.execute_refund(hbit_params, hbit_funded).await?;
 
-    Ok(())
+    Ok(SwapOutcome::RefundedAfter(cause))
 }
 
 /// Execute the happy path of a Hbit<->Herc20 swap for Alice.
@@ -53,38 +57,33 @@ async fn hbit_herc20_happy_alice<A, EC>(
     herc20_params: herc20::Params,
     secret: Secret,
     utc_start_of_swap: DateTime<Utc>,
-) -> Result<(), HbitHerc20AliceError>
+) -> anyhow::Result<()>
 where
     A: hbit::ExecuteFund + herc20::ExecuteRedeem,
-    EC: LatestBlock<Block = ethereum::Block>
-        + BlockByHash<Block = ethereum::Block, BlockHash = ethereum::Hash>
-        + btsieve::ethereum::ReceiptByHash,
+    EC: herc20::WatchForDeployed + herc20::WatchForFunded,
 {
     use HbitHerc20AliceError::*;
 
-    let hbit_funded = alice
-        .execute_fund(&hbit_params)
-        .await
-        .map_err(|_| AliceFund)?;
+    let hbit_funded = alice.execute_fund(&hbit_params).await.context(AliceFund)?;
 
-    let herc20_deployed =
-        herc20::watch_for_deployed(ethereum_connector, herc20_params.clone(), utc_start_of_swap)
-            .await
-            .map_err(|_| BobDeploy(hbit_funded))?;
+    let herc20_deployed = ethereum_connector
+        .watch_for_deployed(herc20_params.clone(), utc_start_of_swap)
+        .await
+        .context(BobDeploy(hbit_funded))?;
 
-    let _herc20_funded = herc20::watch_for_funded(
-        ethereum_connector,
-        herc20_params.clone(),
-        utc_start_of_swap,
-        herc20_deployed.clone(),
-    )
-    .await
-    .map_err(|_| BobFund(hbit_funded))?;
+    let _herc20_funded = ethereum_connector
+        .watch_for_funded(
+            herc20_params.clone(),
+            utc_start_of_swap,
+            herc20_deployed.clone(),
+        )
+        .await
+        .context(BobFund(hbit_funded))?;
 
     let _herc20_redeemed = alice
         .execute_redeem(herc20_params, secret, herc20_deployed, utc_start_of_swap)
         .await
-        .map_err(|_| AliceRedeem(hbit_funded))?;
+        .context(AliceRedeem(hbit_funded))?;
 
     Ok(())
 }
@@ -92,7 +91,8 @@ where
 /// Execute a Hbit<->Herc20 swap for Bob.
 ///
 /// Delegates to `hbit_herc20_happy_bob` and handles errors by
-/// executing refund for Bob when necessary.
+/// executing refund for Bob when necessary, errors that leave nothing to
+/// refund are returned.
 pub async fn hbit_herc20_bob<B, BC, EC>(
     bob: B,
     bitcoin_connector: &BC,
@@ -100,14 +100,11 @@ pub async fn hbit_herc20_bob<B, BC, EC>(
     hbit_params: hbit::Params,
     herc20_params: herc20::Params,
     utc_start_of_swap: DateTime<Utc>,
-) -> anyhow::Result<()>
+) -> anyhow::Result<SwapOutcome>
 where
     B: herc20::ExecuteDeploy + herc20::ExecuteFund + hbit::ExecuteRedeem + herc20::ExecuteRefund,
-    BC: LatestBlock<Block = ::bitcoin::Block>
-        + BlockByHash<Block = ::bitcoin::Block, BlockHash = ::bitcoin::BlockHash>,
-    EC: LatestBlock<Block = ethereum::Block>
-        + BlockByHash<Block = ethereum::Block, BlockHash = ethereum::Hash>
-        + btsieve::ethereum::ReceiptByHash,
+    BC: hbit::WatchForFunded,
+    EC: herc20::WatchForRedeemed,
 {
     let res = hbit_herc20_happy_bob(
         &bob,
@@ -119,13 +116,20 @@ where
     )
     .await;
 
-    use HbitHerc20BobError::*;
-    if let Err(AliceRedeem(herc20_deployed)) = res {
-        bob.execute_refund(herc20_params, herc20_deployed, utc_start_of_swap)
-            .await?;
-    }
+    let error = match res {
+        Ok(()) => return Ok(SwapOutcome::Completed),
+        Err(error) => error,
+    };
+    let herc20_deployed = match error.downcast_ref::<HbitHerc20BobError>() {
+        Some(HbitHerc20BobError::AliceRedeem(herc20_deployed)) => herc20_deployed.clone(),
+        _ => return Err(error),
+    };
+    bob.execute_refund(herc20_params, herc20_deployed, utc_start_of_swap)
+        .await?;
 
-    Ok(())
+    Ok(SwapOutcome::RefundedAfter(
+        RefundCause::CounterpartyNeverRedeemed,
+    ))
 }
 
 /// Execute the happy path of a Hbit<->Herc20 swap for Bob.
@@ -136,26 +140,23 @@ async fn hbit_herc20_happy_bob<B, BC, EC>(
     hbit_params: hbit::Params,
     herc20_params: herc20::Params,
     utc_start_of_swap: DateTime<Utc>,
-) -> Result<(), HbitHerc20BobError>
+) -> anyhow::Result<()>
 where
     B: herc20::ExecuteDeploy + herc20::ExecuteFund + hbit::ExecuteRedeem,
-    BC: LatestBlock<Block = ::bitcoin::Block>
-        + BlockByHash<Block = ::bitcoin::Block, BlockHash = ::bitcoin::BlockHash>,
-    EC: LatestBlock<Block = ethereum::Block>
-        + BlockByHash<Block = ethereum::Block, BlockHash = ethereum::Hash>
-        + btsieve::ethereum::ReceiptByHash,
+    BC: hbit::WatchForFunded,
+    EC: herc20::WatchForRedeemed,
 {
     use HbitHerc20BobError::*;
 
-    let hbit_funded =
-        hbit::watch_for_funded(bitcoin_connector, &hbit_params.shared, utc_start_of_swap)
-            .await
-            .map_err(|_| AliceFund)?;
+    let hbit_funded = bitcoin_connector
+        .watch_for_funded(&hbit_params.shared, utc_start_of_swap)
+        .await
+        .context(AliceFund)?;
 
     let herc20_deployed = bob
         .execute_deploy(herc20_params.clone())
         .await
-        .map_err(|_| BobDeploy)?;
+        .context(BobDeploy)?;
 
     let _herc20_funded = bob
         .execute_fund(
@@ -164,20 +165,17 @@ where
             utc_start_of_swap,
         )
         .await
-        .map_err(|_| BobFund)?;
+        .context(BobFund)?;
 
-    let herc20_redeemed = herc20::watch_for_redeemed(
-        ethereum_connector,
-        utc_start_of_swap,
-        herc20_deployed.clone(),
-    )
-    .await
-    .map_err(|_| AliceRedeem(herc20_deployed))?;
+    let herc20_redeemed = ethereum_connector
+        .watch_for_redeemed(utc_start_of_swap, herc20_deployed.clone())
+        .await
+        .with_context(|| AliceRedeem(herc20_deployed))?;
 
     let _hbit_redeem = bob
         .execute_redeem(hbit_params, hbit_funded, herc20_redeemed.secret)
         .await
-        .map_err(|_| BobRedeem)?;
+        .context(BobRedeem)?;
 
     dbg!(_hbit_redeem);
 
@@ -70,6 +70,54 @@ where
     }
 }
 
+/// Watch the Ethereum ledger for the HTLC being deployed by the counterparty.
+#[async_trait::async_trait]
+pub trait WatchForDeployed {
+    async fn watch_for_deployed(
+        &self,
+        params: Params,
+        utc_start_of_swap: DateTime<Utc>,
+    ) -> anyhow::Result<Deployed>;
+}
+
+/// Watch the Ethereum ledger for the HTLC being funded by the counterparty.
+#[async_trait::async_trait]
+pub trait WatchForFunded {
+    async fn watch_for_funded(
+        &self,
+        params: Params,
+        utc_start_of_swap: DateTime<Utc>,
+        deployed: Deployed,
+    ) -> anyhow::Result<Funded>;
+}
+
+/// Watch the Ethereum ledger for the HTLC being redeemed by the counterparty.
+#[async_trait::async_trait]
+pub trait WatchForRedeemed {
+    async fn watch_for_redeemed(
+        &self,
+        utc_start_of_swap: DateTime<Utc>,
+        deployed: Deployed,
+    ) -> anyhow::Result<Redeemed>;
+}
+
+/// Whether a transaction stayed in the chain until enough blocks were mined on
+/// top of it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Inclusion {
+    Confirmed,
+    /// The block the transaction was included in was reorganised out of the
+    /// chain, the transaction may or may not be included again.
+    Orphaned,
+}
+
+/// Wait for a transaction we observed to be buried deep enough to not be
+/// reorganised out of the chain anymore.
+#[async_trait::async_trait]
+pub trait WaitForConfirmations {
+    async fn wait_for_confirmations(&self, transaction: Hash) -> anyhow::Result<Inclusion>;
+}
+
 #[cfg(test)]
 pub fn params(
     secret_hash: SecretHash,
@@ -1,16 +1,13 @@
-use crate::swap::{hbit, herc20};
+use crate::swap::{hbit, herc20, RefundCause, SwapOutcome};
+use anyhow::Context;
 use chrono::{DateTime, Utc};
-use comit::{
-    btsieve,
-    btsieve::{BlockByHash, LatestBlock},
-    ethereum, Secret,
-};
+use comit::Secret;
 
 /// Execute a Herc20<->Hbit swap for Alice.
 ///
 /// Delegates to `herc20_hbit_happy_alice` and handles errors by
-/// executing refund for Alice when necessary.
-#[allow(dead_code)] // This is library code
+/// executing refund for Alice when necessary, errors that leave nothing to
+/// refund are returned.
 pub async fn herc20_hbit_alice<A, BC>(
     alice: A,
     bitcoin_connector: &BC,
@@ -18,11 +15,10 @@ pub async fn herc20_hbit_alice<A, BC>(
     hbit_params: hbit::Params,
     secret: Secret,
     utc_start_of_swap: DateTime<Utc>,
-) -> anyhow::Result<()>
+) -> anyhow::Result<SwapOutcome>
 where
     A: herc20::ExecuteDeploy + herc20::ExecuteFund + herc20::ExecuteRefund + hbit::ExecuteRedeem,
-    BC: LatestBlock<Block = ::bitcoin::Block>
-        + BlockByHash<Block = ::bitcoin::Block, BlockHash = ::bitcoin::BlockHash>,
+    BC: hbit::WatchForFunded,
 {
     let res = herc20_hbit_happy_alice(
         &alice,
@@ -35,13 +31,27 @@ where
     .await;
 
     use Herc20HbitAliceError::*;
-    if let Err(BobFund(herc20_deployed)) | Err(AliceRedeem(herc20_deployed)) = res {
-        alice
-            .execute_refund(herc20_params, herc20_deployed, utc_start_of_swap)
-            .await?;
+    let error = match res {
+        Ok(()) => return Ok(SwapOutcome::Completed),
+        Err(error) => error,
+    };
+    let (herc20_deployed, cause) = match error.downcast_ref::<Herc20HbitAliceError>() {
+        Some(BobFund(herc20_deployed)) => (
+            herc20_deployed.clone(),
+            RefundCause::CounterpartyNeverFunded,
+        ),
+        Some(AliceRedeem(herc20_deployed)) => {
+            (herc20_deployed.clone(), RefundCause::ExecutionFailed {
+                error: format!("{:#}", error),
+            })
+        }
+        Some(AliceDeploy) | Some(AliceFund) | None => return Err(error),
     };
+    alice
+        .execute_refund(herc20_params, herc20_deployed, utc_start_of_swap)
+        .await?;
 
-    Ok(())
+    Ok(SwapOutcome::RefundedAfter(cause))
 }
 
 /// Execute the happy path of a Herc20<->Hbit swap for Alice.
@@ -52,18 +62,17 @@ async fn herc20_hbit_happy_alice<A, BC>(
     hbit_params: hbit::Params,
     secret: Secret,
     utc_start_of_swap: DateTime<Utc>,
-) -> Result<(), Herc20HbitAliceError>
+) -> anyhow::Result<()>
 where
     A: herc20::ExecuteDeploy + herc20::ExecuteFund + hbit::ExecuteRedeem,
-    BC: LatestBlock<Block = ::bitcoin::Block>
-        + BlockByHash<Block = ::bitcoin::Block, BlockHash = ::bitcoin::BlockHash>,
+    BC: hbit::WatchForFunded,
 {
     use Herc20HbitAliceError::*;
 
     let herc20_deployed = alice
         .execute_deploy(herc20_params.clone())
         .await
-        .map_err(|_| AliceDeploy)?;
+        .context(AliceDeploy)?;
 
     let _herc20_funded = alice
         .execute_fund(
@@ -72,17 +81,17 @@ where
             utc_start_of_swap,
         )
         .await
-        .map_err(|_| AliceFund)?;
+        .context(AliceFund)?;
 
-    let hbit_funded =
-        hbit::watch_for_funded(bitcoin_connector, &hbit_params.shared, utc_start_of_swap)
-            .await
-            .map_err(|_| BobFund(herc20_deployed.clone()))?;
+    let hbit_funded = bitcoin_connector
+        .watch_for_funded(&hbit_params.shared, utc_start_of_swap)
+        .await
+        .with_context(|| BobFund(herc20_deployed.clone()))?;
 
     let _hbit_redeemed = alice
         .execute_redeem(hbit_params, hbit_funded, secret)
         .await
-        .map_err(|_| AliceRedeem(herc20_deployed))?;
+        .context(AliceRedeem(herc20_deployed))?;
 
     Ok(())
 }
@@ -90,7 +99,8 @@ where
 /// Execute a Herc20<->Hbit swap for Bob.
 ///
 /// Delegates to `herc20_hbit_happy_bob` and handles errors by
-/// executing refund for Bob when necessary.
+/// executing refund for Bob when necessary, errors that leave nothing to
+/// refund are returned.
 pub async fn herc20_hbit_bob<B, EC, BC>(
     bob: B,
     ethereum_connector: &EC,
@@ -98,14 +108,11 @@ pub async fn herc20_hbit_bob<B, EC, BC>(
     herc20_params: herc20::Params,
     hbit_params: hbit::Params,
     utc_start_of_swap: DateTime<Utc>,
-) -> anyhow::Result<()>
+) -> anyhow::Result<SwapOutcome>
 where
     B: hbit::ExecuteFund + hbit::ExecuteRefund + herc20::ExecuteRedeem,
-    EC: LatestBlock<Block = ethereum::Block>
-        + BlockByHash<Block = ethereum::Block, BlockHash = ethereum::Hash>
-        + btsieve::ethereum::ReceiptByHash,
-    BC: LatestBlock<Block = ::bitcoin::Block>
-        + BlockByHash<Block = ::bitcoin::Block, BlockHash = ::bitcoin::BlockHash>,
+    EC: herc20::WatchForDeployed + herc20::WatchForFunded,
+    BC: hbit::WatchForRedeemed,
 {
     let res = herc20_hbit_happy_bob(
         &bob,
@@ -117,12 +124,19 @@ where
     )
     .await;
 
-    use Herc20HbitBobError::*;
-    if let Err(AliceRedeem(hbit_funded)) = res {
-        bob.execute_refund(hbit_params, hbit_funded).await?;
+    let error = match res {
+        Ok(()) => return Ok(SwapOutcome::Completed),
+        Err(error) => error,
+    };
+    let hbit_funded = match error.downcast_ref::<Herc20HbitBobError>() {
+        Some(Herc20HbitBobError::AliceRedeem(hbit_funded)) => *hbit_funded,
+        _ => return Err(error),
     };
+    bob.execute_refund(hbit_params, hbit_funded).await?;
 
-    Ok(())
+    Ok(SwapOutcome::RefundedAfter(
+        RefundCause::CounterpartyNeverRedeemed,
+    ))
 }
 
 /// Execute the happy path of a Herc20<->Hbit swap for Bob.
@@ -133,41 +147,34 @@ async fn herc20_hbit_happy_bob<B, EC, BC>(
     herc20_params: herc20::Params,
     hbit_params: hbit::Params,
     utc_start_of_swap: DateTime<Utc>,
-) -> Result<(), Herc20HbitBobError>
+) -> anyhow::Result<()>
 where
     B: hbit::ExecuteFund + herc20::ExecuteRedeem,
-    EC: LatestBlock<Block = ethereum::Block>
-        + BlockByHash<Block = ethereum::Block, BlockHash = ethereum::Hash>
-        + btsieve::ethereum::ReceiptByHash,
-    BC: LatestBlock<Block = ::bitcoin::Block>
-        + BlockByHash<Block = ::bitcoin::Block, BlockHash = ::bitcoin::BlockHash>,
+    EC: herc20::WatchForDeployed + herc20::WatchForFunded,
+    BC: hbit::WatchForRedeemed,
 {
     use Herc20HbitBobError::*;
 
-    let herc20_deployed =
-        herc20::watch_for_deployed(ethereum_connector, herc20_params.clone(), utc_start_of_swap)
-            .await
-            .map_err(|_| AliceDeploy)?;
+    let herc20_deployed = ethereum_connector
+        .watch_for_deployed(herc20_params.clone(), utc_start_of_swap)
+        .await
+        .context(AliceDeploy)?;
 
-    let _herc20_funded = herc20::watch_for_funded(
-        ethereum_connector,
-        herc20_params.clone(),
-        utc_start_of_swap,
-        herc20_deployed.clone(),
-    )
-    .await
-    .map_err(|_| AliceFund)?;
+    let _herc20_funded = ethereum_connector
+        .watch_for_funded(
+            herc20_params.clone(),
+            utc_start_of_swap,
+            herc20_deployed.clone(),
+        )
+        .await
+        .context(AliceFund)?;
 
-    let hbit_funded = bob.execute_fund(&hbit_params).await.map_err(|_| BobFund)?;
+    let hbit_funded = bob.execute_fund(&hbit_params).await.context(BobFund)?;
 
-    let hbit_redeemed = hbit::watch_for_redeemed(
-        bitcoin_connector,
-        &hbit_params.shared,
-        hbit_funded.location,
-        utc_start_of_swap,
-    )
-    .await
-    .map_err(|_| AliceRedeem(hbit_funded))?;
+    let hbit_redeemed = bitcoin_connector
+        .watch_for_redeemed(&hbit_params.shared, hbit_funded.location, utc_start_of_swap)
+        .await
+        .context(AliceRedeem(hbit_funded))?;
 
     let _herc20_redeem = bob
         .execute_redeem(
@@ -177,7 +184,7 @@ where
             utc_start_of_swap,
         )
         .await
-        .map_err(|_| BobRedeem)?;
+        .context(BobRedeem)?;
 
     Ok(())
 }
@@ -2,17 +2,24 @@ use self::{
     hbit::{HbitFunded, HbitRedeemed, HbitRefunded},
     herc20::{Herc20Deployed, Herc20Funded, Herc20Redeemed, Herc20Refunded},
 };
-use crate::{network, network::ActivePeer, swap, swap::SwapKind, SwapId};
+use crate::{
+    bitcoin, config::SwapRole, ethereum::dai, network, network::ActivePeer, order::BtcDaiOrderForm,
+    swap, swap::SwapKind, Rate, SwapId,
+};
 use anyhow::{anyhow, Context};
 use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
+use comit::Position;
+use libp2p::PeerId;
+use num::BigUint;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 #[cfg(test)]
 use crate::StaticStub;
-use std::{collections::HashSet, iter::FromIterator};
+use std::{fmt, str::FromStr};
 
 mod hbit;
 mod herc20;
+mod migration;
 
 pub trait Load<T>: Send + Sync + 'static {
     fn load(&self, swap_id: SwapId) -> anyhow::Result<Option<T>>;
@@ -23,6 +30,12 @@ pub trait Save<T>: Send + Sync + 'static {
     async fn save(&self, elem: T, swap_id: SwapId) -> anyhow::Result<()>;
 }
 
+/// Forget a stored event whose transaction was reorganised out of the chain.
+#[async_trait::async_trait]
+pub trait Rollback<T>: Send + Sync + 'static {
+    async fn rollback(&self, swap_id: SwapId) -> anyhow::Result<()>;
+}
+
 #[derive(Debug)]
 pub struct Database {
     db: sled::Db,
@@ -31,8 +44,8 @@ pub struct Database {
 }
 
 impl Database {
-    const ACTIVE_PEER_KEY: &'static str = "active_peer";
     const BITCOIN_TRANSIENT_KEYS_INDEX_KEY: &'static str = "bitcoin_transient_key_index";
+    const HEALTH_PROBE_KEY: &'static str = "health_probe";
 
     #[cfg(not(test))]
     pub fn new(path: &std::path::Path) -> anyhow::Result<Self> {
@@ -41,17 +54,13 @@ impl Database {
             .ok_or_else(|| anyhow!("The path is not utf-8 valid: {:?}", path))?;
         let db = sled::open(path).context(format!("Could not open the DB at {}", path))?;
 
-        if !db.contains_key(Self::ACTIVE_PEER_KEY)? {
-            let peers = Vec::<ActivePeer>::new();
-            let peers = serialize(&peers)?;
-            let _ = db.insert(serialize(&Self::ACTIVE_PEER_KEY)?, peers)?;
-        }
-
         if !db.contains_key(Self::BITCOIN_TRANSIENT_KEYS_INDEX_KEY)? {
             let index = serialize(&0u32)?;
             let _ = db.insert(serialize(&Self::BITCOIN_TRANSIENT_KEYS_INDEX_KEY)?, index)?;
         }
 
+        migration::migrate(&db)?;
+
         Ok(Database { db })
     }
 
@@ -63,13 +72,11 @@ impl Database {
             tmp_dir.path().display()
         ))?;
 
-        let peers = Vec::<ActivePeer>::new();
-        let peers = serialize(&peers)?;
-        let _ = db.insert(serialize(&Self::ACTIVE_PEER_KEY)?, peers)?;
-
         let index = serialize(&0u32)?;
         let _ = db.insert(serialize(&Self::BITCOIN_TRANSIENT_KEYS_INDEX_KEY)?, index)?;
 
+        migration::migrate(&db)?;
+
         Ok(Database { db, tmp_dir })
     }
 
@@ -103,7 +110,6 @@ impl Database {
 }
 /// Swap related functions
 impl Database {
-    // TODO: Add versioning to the data
     pub async fn insert_swap(&self, swap: SwapKind) -> anyhow::Result<()> {
         let swap_id = swap.swap_id();
 
@@ -151,14 +157,32 @@ impl Database {
             .collect()
     }
 
+    pub fn contains_swap(&self, swap_id: &SwapId) -> anyhow::Result<bool> {
+        Ok(self.db.contains_key(serialize(swap_id)?)?)
+    }
+
     pub async fn remove_swap(&self, swap_id: &SwapId) -> anyhow::Result<()> {
         let key = serialize(swap_id)?;
 
         self.db
-            .remove(key)
+            .remove(&key)
             .context(format!("Could not delete swap {}", swap_id))
             .map(|_| ())?;
+        self.db
+            .open_tree(Self::SWAP_FAILURES_TREE)?
+            .remove(key)
+            .context(format!("Could not delete failure of swap {}", swap_id))?;
+
+        self.db
+            .flush_async()
+            .await
+            .map(|_| ())
+            .context("Could not flush db")
+    }
 
+    /// Flush all the trees, including what was written without waiting for
+    /// it such as the order audit log.
+    pub async fn flush(&self) -> anyhow::Result<()> {
         self.db
             .flush_async()
             .await
@@ -166,6 +190,15 @@ impl Database {
             .context("Could not flush db")
     }
 
+    /// Write the time of the probe and flush it, failing if the database is
+    /// not writable.
+    pub async fn probe_write(&self) -> anyhow::Result<()> {
+        let key = serialize(&Self::HEALTH_PROBE_KEY)?;
+        let _ = self.db.insert(key, serialize(&Utc::now().timestamp())?)?;
+
+        self.flush().await
+    }
+
     fn get_swap(&self, swap_id: &SwapId) -> anyhow::Result<Swap> {
         let key = serialize(swap_id)?;
 
@@ -180,62 +213,779 @@ impl Database {
 
 /// These methods are used to prevent a peer from having more than one ongoing
 /// swap with nectar An active peer refers to one that has an ongoing swap with
-/// nectar.
+/// nectar. A peer stays active until its swap finished or its record expired,
+/// e.g. because the execution of the swap failed.
 impl Database {
-    pub async fn insert_active_peer(&self, peer: ActivePeer) -> anyhow::Result<()> {
-        self.modify_peers_with(|peers: &mut HashSet<ActivePeer>| peers.insert(peer.clone()))?;
+    const ACTIVE_PEERS_TREE: &'static str = "active_peers";
+
+    pub async fn insert_active_peer(
+        &self,
+        peer: ActivePeer,
+        reservation: Reservation,
+        expires_at: DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        self.insert_expiring(Self::ACTIVE_PEERS_TREE, &peer, reservation, expires_at)
+            .await
+    }
+
+    /// The funds reserved for the swap with the peer, `None` if it was not
+    /// active anymore, e.g. because its record expired.
+    pub async fn remove_active_peer(
+        &self,
+        peer: &ActivePeer,
+    ) -> anyhow::Result<Option<Reservation>> {
+        self.remove_expiring(Self::ACTIVE_PEERS_TREE, peer).await
+    }
+
+    /// Push back the expiry of the record of the peer, keeping the funds
+    /// reserved for its swap. `false` if the peer was not active anymore.
+    pub async fn renew_active_peer(
+        &self,
+        peer: &ActivePeer,
+        expires_at: DateTime<Utc>,
+    ) -> anyhow::Result<bool> {
+        self.renew_expiring::<_, Reservation>(Self::ACTIVE_PEERS_TREE, peer, expires_at)
+            .await
+    }
+
+    pub fn contains_active_peer(&self, peer: &ActivePeer) -> anyhow::Result<bool> {
+        let reservation: Option<Reservation> = self.get_expiring(Self::ACTIVE_PEERS_TREE, peer)?;
 
+        Ok(reservation.is_some())
+    }
+
+    /// Remove the peers whose record expired as of `now`, along with the funds
+    /// reserved for their swaps.
+    pub async fn purge_expired_active_peers(
+        &self,
+        now: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<(ActivePeer, Reservation)>> {
+        self.purge_expired(Self::ACTIVE_PEERS_TREE, now).await
+    }
+}
+
+/// The funds reserved for the swap with an active peer, the amounts are
+/// stored as in `BalanceSnapshot`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Reservation {
+    pub dai_attodai: Option<String>,
+    pub bitcoin_sat: Option<u64>,
+    /// Absent in the records written before it was added.
+    #[serde(default)]
+    pub swap_id: Option<SwapId>,
+}
+
+impl Reservation {
+    pub fn of_order(swap_id: SwapId, form: &BtcDaiOrderForm) -> Self {
+        match form.position {
+            Position::Buy => Reservation {
+                dai_attodai: Some(dai::Amount::from(form.quote()).as_atto().to_string()),
+                bitcoin_sat: None,
+                swap_id: Some(swap_id),
+            },
+            Position::Sell => Reservation {
+                dai_attodai: None,
+                bitcoin_sat: Some(bitcoin::Amount::from(form.quantity).as_sat()),
+                swap_id: Some(swap_id),
+            },
+        }
+    }
+
+    pub fn of_swap(swap: &SwapKind) -> Self {
+        let params = swap.params();
+
+        if swap.locks_bitcoin() {
+            Reservation {
+                dai_attodai: None,
+                bitcoin_sat: Some(bitcoin::Amount::from(params.hbit_params.shared.asset).as_sat()),
+                swap_id: Some(params.swap_id),
+            }
+        } else {
+            Reservation {
+                dai_attodai: Some(
+                    dai::Amount::from(params.herc20_params.asset)
+                        .as_atto()
+                        .to_string(),
+                ),
+                bitcoin_sat: None,
+                swap_id: Some(params.swap_id),
+            }
+        }
+    }
+
+    pub fn dai(&self) -> anyhow::Result<Option<dai::Amount>> {
+        self.dai_attodai
+            .as_deref()
+            .map(|attodai| {
+                BigUint::from_str(attodai)
+                    .map(dai::Amount::from_atto)
+                    .with_context(|| format!("Invalid reserved dai amount {}", attodai))
+            })
+            .transpose()
+    }
+
+    pub fn bitcoin(&self) -> Option<bitcoin::Amount> {
+        self.bitcoin_sat.map(bitcoin::Amount::from_sat)
+    }
+}
+
+/// A value kept until it expires.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Expiring<V> {
+    expires_at: DateTime<Utc>,
+    value: V,
+}
+
+/// Key/value pairs kept in their own tree for a limited time. Expired pairs
+/// are kept until purged, rather than ignored, so that the caller can release
+/// whatever they stand for.
+impl Database {
+    async fn insert_expiring<K, V>(
+        &self,
+        tree: &str,
+        key: &K,
+        value: V,
+        expires_at: DateTime<Utc>,
+    ) -> anyhow::Result<()>
+    where
+        K: Serialize,
+        V: Serialize,
+    {
+        let tree = self.db.open_tree(tree)?;
+
+        tree.insert(serialize(key)?, serialize(&Expiring { expires_at, value })?)
+            .context("Could not write in the DB")?;
+
+        tree.flush_async()
+            .await
+            .map(|_| ())
+            .context("Could not flush db")
+    }
+
+    fn get_expiring<K, V>(&self, tree: &str, key: &K) -> anyhow::Result<Option<V>>
+    where
+        K: Serialize,
+        V: DeserializeOwned,
+    {
         self.db
-            .flush_async()
+            .open_tree(tree)?
+            .get(serialize(key)?)?
+            .map(|value| deserialize::<Expiring<V>>(&value).map(|expiring| expiring.value))
+            .transpose()
+    }
+
+    async fn remove_expiring<K, V>(&self, tree: &str, key: &K) -> anyhow::Result<Option<V>>
+    where
+        K: Serialize,
+        V: DeserializeOwned,
+    {
+        let tree = self.db.open_tree(tree)?;
+        let removed = tree
+            .remove(serialize(key)?)
+            .context("Could not remove from the DB")?;
+
+        tree.flush_async().await.context("Could not flush db")?;
+
+        removed
+            .map(|value| deserialize::<Expiring<V>>(&value).map(|expiring| expiring.value))
+            .transpose()
+    }
+
+    /// Set the expiry of the pair, `false` if there is none.
+    async fn renew_expiring<K, V>(
+        &self,
+        tree: &str,
+        key: &K,
+        expires_at: DateTime<Utc>,
+    ) -> anyhow::Result<bool>
+    where
+        K: Serialize,
+        V: Serialize + DeserializeOwned,
+    {
+        let tree = self.db.open_tree(tree)?;
+        let key = serialize(key)?;
+
+        let old = match tree.get(&key)? {
+            Some(old) => old,
+            None => return Ok(false),
+        };
+        let Expiring { value, .. }: Expiring<V> = deserialize(&old)?;
+        let new = serialize(&Expiring { expires_at, value })?;
+
+        let renewed = tree
+            .compare_and_swap(&key, Some(&old), Some(new))
+            .context("Could not write in the DB")?;
+        if renewed.is_err() {
+            // Purged or written to in the meantime
+            return Ok(false);
+        }
+
+        tree.flush_async().await.context("Could not flush db")?;
+
+        Ok(true)
+    }
+
+    /// Remove the pairs that expired as of `now`. A pair written to in the
+    /// meantime, e.g. renewed, is left alone.
+    async fn purge_expired<K, V>(
+        &self,
+        tree: &str,
+        now: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<(K, V)>>
+    where
+        K: DeserializeOwned,
+        V: DeserializeOwned,
+    {
+        let tree = self.db.open_tree(tree)?;
+        let mut purged = Vec::new();
+
+        for item in tree.iter() {
+            let (key, value) = item.context("Could not retrieve data")?;
+            let expiring: Expiring<V> = deserialize(&value)?;
+            if expiring.expires_at > now {
+                continue;
+            }
+
+            let removed = tree
+                .compare_and_swap(&key, Some(&value), None as Option<&[u8]>)
+                .context("Could not remove from the DB")?;
+            if removed.is_ok() {
+                purged.push((deserialize(&key)?, expiring.value));
+            }
+        }
+
+        tree.flush_async().await.context("Could not flush db")?;
+
+        Ok(purged)
+    }
+}
+
+/// The balances of the wallets and the prevailing mid-market rate at a point
+/// in time. A value is absent if it could not be fetched.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BalanceSnapshot {
+    pub taken_at: DateTime<Utc>,
+    pub bitcoin_sat: Option<u64>,
+    pub dai_attodai: Option<String>,
+    pub ether_wei: Option<String>,
+    pub mid_market_rate: Option<Rate>,
+}
+
+/// Balance snapshots are kept in their own tree, keyed by timestamp so that
+/// they are iterated in chronological order.
+impl Database {
+    const BALANCE_SNAPSHOTS_TREE: &'static str = "balance_snapshots";
+
+    pub async fn insert_balance_snapshot(&self, snapshot: &BalanceSnapshot) -> anyhow::Result<()> {
+        let tree = self.db.open_tree(Self::BALANCE_SNAPSHOTS_TREE)?;
+        let key = snapshot.taken_at.timestamp_millis().to_be_bytes();
+
+        tree.insert(key, serialize(snapshot)?)
+            .context("Could not write in the DB")?;
+
+        tree.flush_async()
             .await
             .map(|_| ())
             .context("Could not flush db")
     }
 
-    pub async fn remove_active_peer(&self, peer: &ActivePeer) -> anyhow::Result<()> {
-        self.modify_peers_with(|peers: &mut HashSet<ActivePeer>| peers.remove(peer))?;
+    pub fn balance_snapshots(&self) -> anyhow::Result<Vec<BalanceSnapshot>> {
         self.db
-            .flush_async()
+            .open_tree(Self::BALANCE_SNAPSHOTS_TREE)?
+            .iter()
+            .map(|item| {
+                let (_, value) = item.context("Could not retrieve data")?;
+                deserialize(&value).context("Could not deserialize balance snapshot")
+            })
+            .collect()
+    }
+}
+
+/// What we agreed to sell when accepting a take, kept to enforce the limit on
+/// the volume sold over 24 hours across restarts.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SoldVolume {
+    pub sold_at: DateTime<Utc>,
+    pub swap_id: SwapId,
+    pub bitcoin_sat: u64,
+    pub dai_attodai: String,
+}
+
+/// Sold volumes are keyed by timestamp, then swap id, so that the most recent
+/// ones can be retrieved with a range query.
+impl Database {
+    const SOLD_VOLUMES_TREE: &'static str = "sold_volumes";
+
+    pub async fn insert_sold_volume(&self, sold_volume: &SoldVolume) -> anyhow::Result<()> {
+        let tree = self.db.open_tree(Self::SOLD_VOLUMES_TREE)?;
+        let mut key = sold_volume
+            .sold_at
+            .timestamp_millis()
+            .to_be_bytes()
+            .to_vec();
+        key.extend_from_slice(sold_volume.swap_id.as_bytes());
+
+        tree.insert(key, serialize(sold_volume)?)
+            .context("Could not write in the DB")?;
+
+        tree.flush_async()
             .await
             .map(|_| ())
             .context("Could not flush db")
     }
 
-    pub fn contains_active_peer(&self, peer: &ActivePeer) -> anyhow::Result<bool> {
-        let peers = self.peers()?;
+    /// Remove the volume sold in the swap, e.g. because its setup failed.
+    pub async fn remove_sold_volume(&self, swap_id: &SwapId) -> anyhow::Result<Option<SoldVolume>> {
+        let tree = self.db.open_tree(Self::SOLD_VOLUMES_TREE)?;
+
+        // Most recent first, the swap being usually just taken
+        let mut removed = None;
+        for item in tree.iter().rev() {
+            let (key, value) = item.context("Could not retrieve data")?;
+            if key.ends_with(&swap_id.as_bytes()[..]) {
+                tree.remove(&key).context("Could not write in the DB")?;
+                removed = Some(deserialize(&value).context("Could not deserialize sold volume")?);
+                break;
+            }
+        }
+
+        tree.flush_async()
+            .await
+            .map(|_| ())
+            .context("Could not flush db")?;
+
+        Ok(removed)
+    }
+
+    /// The volumes sold at or after `since`, in chronological order.
+    pub fn sold_volumes_since(&self, since: DateTime<Utc>) -> anyhow::Result<Vec<SoldVolume>> {
+        self.db
+            .open_tree(Self::SOLD_VOLUMES_TREE)?
+            .range(since.timestamp_millis().to_be_bytes()..)
+            .map(|item| {
+                let (_, value) = item.context("Could not retrieve data")?;
+                deserialize(&value).context("Could not deserialize sold volume")
+            })
+            .collect()
+    }
+}
 
-        Ok(peers.contains(&peer))
+/// A publication or clearance of our orders, recorded so that what was quoted
+/// at any moment can be reconstructed.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct OrderAuditEntry {
+    pub recorded_at: DateTime<Utc>,
+    pub reason: OrderUpdateReason,
+    pub action: OrderAction,
+    pub mid_market_rate: Option<Rate>,
+}
+
+/// What triggered the update of the orders.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum OrderUpdateReason {
+    Startup,
+    RateUpdate,
+    BitcoinBalanceUpdate,
+    DaiBalanceUpdate,
+    EtherBalanceUpdate,
+    BitcoinFeeUpdate,
+    TradingPaused,
+    TradingResumed,
+    StaleRate,
+    Republication,
+    CircuitBreakerTripped,
+    CircuitBreakerReset,
+    Shutdown,
+    Maintenance,
+    /// Connected to no peer for longer than the maximum isolation.
+    Isolated,
+    Reconnected,
+    /// Fetching the rate failed, the last known rate is used with a widened
+    /// spread.
+    RateFetchFailed,
+    /// The maximum sell amounts were adjusted as an asset got depleted or
+    /// rebalanced.
+    Rebalance,
+    /// The funds reserved for a swap were freed as its taker is no longer
+    /// active, e.g. because the setup of the swap timed out.
+    ReservationFreed,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum OrderAction {
+    Published(AuditedOrder),
+    /// All our orders were removed from the orderbook.
+    Cleared,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuditedOrder {
+    /// `buy` or `sell` bitcoin
+    pub position: String,
+    pub quantity_sat: u64,
+    pub quote_attodai: String,
+    /// The rate of the order, absent if the quantity is zero
+    pub rate: Option<Rate>,
+}
+
+/// Order audit entries are keyed by a monotonic id as several of them are
+/// recorded at the same instant, e.g. publishing both orders.
+impl Database {
+    const ORDER_AUDIT_TREE: &'static str = "order_audit";
+
+    /// Insert the entry without waiting for it to be flushed, it is
+    /// recorded from the trade loop which must not block.
+    pub fn insert_order_audit_entry(&self, entry: &OrderAuditEntry) -> anyhow::Result<()> {
+        let tree = self.db.open_tree(Self::ORDER_AUDIT_TREE)?;
+        let key = self.db.generate_id()?.to_be_bytes();
+
+        tree.insert(key, serialize(entry)?)
+            .context("Could not write in the DB")?;
+
+        Ok(())
+    }
+
+    pub fn order_audit_entries(&self) -> anyhow::Result<Vec<OrderAuditEntry>> {
+        self.db
+            .open_tree(Self::ORDER_AUDIT_TREE)?
+            .iter()
+            .map(|item| {
+                let (_, value) = item.context("Could not retrieve data")?;
+                deserialize(&value).context("Could not deserialize order audit entry")
+            })
+            .collect()
     }
+}
 
-    fn modify_peers_with(
+/// Recorded when we had to refund the asset we locked in a swap.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RefundRecord {
+    pub swap_id: SwapId,
+    pub peer_id: String,
+    pub recorded_at: DateTime<Utc>,
+    pub cause: RefundCause,
+    /// The fee paid to spend the Bitcoin HTLC, if we spent it.
+    pub bitcoin_fee_sat: Option<u64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum RefundCause {
+    /// The funding of the taker's HTLC was never seen.
+    CounterpartyNeverFunded,
+    /// The taker did not redeem our HTLC before it expired.
+    CounterpartyNeverRedeemed,
+    /// The execution of the swap failed before it was resumed, e.g. because
+    /// a node was unreachable, the expiry may have been missed because of it.
+    ExecutionFailed { error: String },
+}
+
+impl fmt::Display for RefundCause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RefundCause::CounterpartyNeverFunded => write!(f, "the taker never funded"),
+            RefundCause::CounterpartyNeverRedeemed => {
+                write!(f, "the taker did not redeem before expiry")
+            }
+            RefundCause::ExecutionFailed { error } => {
+                write!(f, "the execution of the swap failed: {}", error)
+            }
+        }
+    }
+}
+
+/// Recorded when the taker funded the Bitcoin HTLC with less than the agreed
+/// amount, the swap was then aborted.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct IncorrectFunding {
+    pub swap_id: SwapId,
+    pub recorded_at: DateTime<Utc>,
+    pub expected_sat: u64,
+    pub funded_sat: u64,
+}
+
+/// How the execution of a swap ended.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SwapOutcome {
+    /// We redeemed the HTLC of the taker.
+    Completed,
+    /// We got back the asset we locked.
+    RefundedAfter(RefundCause),
+    /// The swap ended before we locked any funds in it.
+    AbortedBeforeFund { reason: String },
+    /// The execution failed and we could not refund, the funds we locked may
+    /// be stuck in our HTLC.
+    Failed { reason: String },
+}
+
+impl SwapOutcome {
+    /// Used as label of the metrics.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SwapOutcome::Completed => "completed",
+            SwapOutcome::RefundedAfter(_) => "refunded",
+            SwapOutcome::AbortedBeforeFund { .. } => "aborted",
+            SwapOutcome::Failed { .. } => "failed",
+        }
+    }
+}
+
+impl fmt::Display for SwapOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SwapOutcome::Completed => write!(f, "completed"),
+            SwapOutcome::RefundedAfter(cause) => write!(f, "refunded because {}", cause),
+            SwapOutcome::AbortedBeforeFund { reason } => {
+                write!(f, "aborted before we funded: {}", reason)
+            }
+            SwapOutcome::Failed { reason } => write!(f, "failed: {}", reason),
+        }
+    }
+}
+
+/// Refunds are kept in chronological order, the last error of the execution
+/// of a swap is kept until the swap is removed to find the cause of a refund.
+/// The outcome of a swap is kept after it is removed.
+impl Database {
+    const REFUNDS_TREE: &'static str = "refunds";
+    const SWAP_FAILURES_TREE: &'static str = "swap_failures";
+    const INCORRECT_FUNDINGS_TREE: &'static str = "incorrect_fundings";
+    const SWAP_OUTCOMES_TREE: &'static str = "swap_outcomes";
+
+    pub async fn insert_refund(&self, refund: &RefundRecord) -> anyhow::Result<()> {
+        let tree = self.db.open_tree(Self::REFUNDS_TREE)?;
+        let key = self.db.generate_id()?.to_be_bytes();
+
+        tree.insert(key, serialize(refund)?)
+            .context("Could not write in the DB")?;
+
+        tree.flush_async()
+            .await
+            .map(|_| ())
+            .context("Could not flush db")
+    }
+
+    pub fn refunds(&self) -> anyhow::Result<Vec<RefundRecord>> {
+        self.db
+            .open_tree(Self::REFUNDS_TREE)?
+            .iter()
+            .map(|item| {
+                let (_, value) = item.context("Could not retrieve data")?;
+                deserialize(&value).context("Could not deserialize refund")
+            })
+            .collect()
+    }
+
+    pub async fn insert_incorrect_funding(
         &self,
-        operation_fn: impl Fn(&mut HashSet<ActivePeer>) -> bool,
+        incorrect_funding: &IncorrectFunding,
     ) -> anyhow::Result<()> {
-        let mut peers = self.peers()?;
+        let tree = self.db.open_tree(Self::INCORRECT_FUNDINGS_TREE)?;
 
-        operation_fn(&mut peers);
+        tree.insert(
+            serialize(&incorrect_funding.swap_id)?,
+            serialize(incorrect_funding)?,
+        )
+        .context("Could not write in the DB")?;
 
-        let updated_peers = Vec::<ActivePeer>::from_iter(peers);
-        let updated_peers = serialize(&updated_peers)?;
+        tree.flush_async()
+            .await
+            .map(|_| ())
+            .context("Could not flush db")
+    }
 
+    pub fn incorrect_funding(&self, swap_id: &SwapId) -> anyhow::Result<Option<IncorrectFunding>> {
         self.db
-            .insert(serialize(&Self::ACTIVE_PEER_KEY)?, updated_peers)?;
+            .open_tree(Self::INCORRECT_FUNDINGS_TREE)?
+            .get(serialize(swap_id)?)?
+            .map(|value| deserialize(&value).context("Could not deserialize incorrect funding"))
+            .transpose()
+    }
 
-        Ok(())
+    pub async fn insert_swap_failure(&self, swap_id: &SwapId, error: &str) -> anyhow::Result<()> {
+        let tree = self.db.open_tree(Self::SWAP_FAILURES_TREE)?;
+
+        tree.insert(serialize(swap_id)?, serialize(&error)?)
+            .context("Could not write in the DB")?;
+
+        tree.flush_async()
+            .await
+            .map(|_| ())
+            .context("Could not flush db")
     }
 
-    fn peers(&self) -> anyhow::Result<HashSet<ActivePeer>> {
-        let peers = self
-            .db
-            .get(serialize(&Self::ACTIVE_PEER_KEY)?)?
-            .ok_or_else(|| anyhow::anyhow!("no key \"active_peer\" in db"))?;
-        let peers: Vec<ActivePeer> = deserialize(&peers)?;
-        let peers = HashSet::<ActivePeer>::from_iter(peers);
+    pub fn swap_failure(&self, swap_id: &SwapId) -> anyhow::Result<Option<String>> {
+        self.db
+            .open_tree(Self::SWAP_FAILURES_TREE)?
+            .get(serialize(swap_id)?)?
+            .map(|value| deserialize(&value).context("Could not deserialize swap failure"))
+            .transpose()
+    }
+
+    pub async fn insert_swap_outcome(
+        &self,
+        swap_id: &SwapId,
+        outcome: &SwapOutcome,
+    ) -> anyhow::Result<()> {
+        let tree = self.db.open_tree(Self::SWAP_OUTCOMES_TREE)?;
+
+        tree.insert(serialize(swap_id)?, serialize(outcome)?)
+            .context("Could not write in the DB")?;
+
+        tree.flush_async()
+            .await
+            .map(|_| ())
+            .context("Could not flush db")
+    }
+
+    pub fn swap_outcome(&self, swap_id: &SwapId) -> anyhow::Result<Option<SwapOutcome>> {
+        self.db
+            .open_tree(Self::SWAP_OUTCOMES_TREE)?
+            .get(serialize(swap_id)?)?
+            .map(|value| deserialize(&value).context("Could not deserialize swap outcome"))
+            .transpose()
+    }
+
+    pub fn swap_outcomes(&self) -> anyhow::Result<Vec<SwapOutcome>> {
+        self.db
+            .open_tree(Self::SWAP_OUTCOMES_TREE)?
+            .iter()
+            .map(|item| {
+                let (_, value) = item.context("Could not retrieve data")?;
+                deserialize(&value).context("Could not deserialize swap outcome")
+            })
+            .collect()
+    }
+}
+
+/// Whether a taker is banned or one of the only takers we trade with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TakerListing {
+    Banned,
+    Allowed,
+}
+
+/// Takers listed with the `takers` command, on top of those of the
+/// configuration file. A taker is in at most one of the lists.
+impl Database {
+    const TAKER_LISTINGS_TREE: &'static str = "taker_listings";
 
-        Ok(peers)
+    pub async fn insert_taker_listing(
+        &self,
+        peer_id: &PeerId,
+        listing: TakerListing,
+    ) -> anyhow::Result<()> {
+        let tree = self.db.open_tree(Self::TAKER_LISTINGS_TREE)?;
+
+        tree.insert(peer_id.to_string(), serialize(&listing)?)
+            .context("Could not write in the DB")?;
+
+        tree.flush_async()
+            .await
+            .map(|_| ())
+            .context("Could not flush db")
     }
+
+    /// Returns whether the taker was listed.
+    pub async fn remove_taker_listing(&self, peer_id: &PeerId) -> anyhow::Result<bool> {
+        let tree = self.db.open_tree(Self::TAKER_LISTINGS_TREE)?;
+
+        let removed = tree
+            .remove(peer_id.to_string())
+            .context("Could not remove from the DB")?;
+
+        tree.flush_async().await.context("Could not flush db")?;
+
+        Ok(removed.is_some())
+    }
+
+    pub fn taker_listings(&self) -> anyhow::Result<Vec<(PeerId, TakerListing)>> {
+        self.db
+            .open_tree(Self::TAKER_LISTINGS_TREE)?
+            .iter()
+            .map(|item| {
+                let (key, value) = item.context("Could not retrieve data")?;
+                let peer_id = std::str::from_utf8(&key)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|peer_id| PeerId::from_str(peer_id).map_err(|e| anyhow!("{}", e)))
+                    .context("Could not deserialize peer id")?;
+                let listing = deserialize(&value).context("Could not deserialize listing")?;
+
+                Ok((peer_id, listing))
+            })
+            .collect()
+    }
+}
+
+/// How swaps with a taker ended, used to decide whether to trade with them
+/// again.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Reputation {
+    pub completed: u32,
+    /// Takes after which the taker never funded.
+    pub aborted: u32,
+    /// Swaps in which we had to refund because the taker did not redeem.
+    pub refunds_caused: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PeerOutcome {
+    Completed,
+    Aborted,
+    RefundCaused,
 }
 
+impl Reputation {
+    /// Whether we never completed a swap with this taker.
+    pub fn is_unknown(&self) -> bool {
+        self.completed == 0
+    }
+
+    fn record(&mut self, outcome: PeerOutcome) {
+        let count = match outcome {
+            PeerOutcome::Completed => &mut self.completed,
+            PeerOutcome::Aborted => &mut self.aborted,
+            PeerOutcome::RefundCaused => &mut self.refunds_caused,
+        };
+        *count = count.saturating_add(1);
+    }
+}
+
+impl Database {
+    const REPUTATIONS_TREE: &'static str = "reputations";
+
+    /// Returns the reputation of the taker including the new outcome.
+    pub async fn record_peer_outcome(
+        &self,
+        peer_id: &PeerId,
+        outcome: PeerOutcome,
+    ) -> anyhow::Result<Reputation> {
+        let tree = self.db.open_tree(Self::REPUTATIONS_TREE)?;
+
+        let mut reputation = self.reputation(peer_id)?;
+        reputation.record(outcome);
+
+        tree.insert(peer_id.to_string(), serialize(&reputation)?)
+            .context("Could not write in the DB")?;
+
+        tree.flush_async().await.context("Could not flush db")?;
+
+        Ok(reputation)
+    }
+
+    pub fn reputation(&self, peer_id: &PeerId) -> anyhow::Result<Reputation> {
+        self.db
+            .open_tree(Self::REPUTATIONS_TREE)?
+            .get(peer_id.to_string())?
+            .map(|value| deserialize(&value).context("Could not deserialize reputation"))
+            .transpose()
+            .map(Option::unwrap_or_default)
+    }
+}
+
+/// How often the update of a swap is attempted when it is written to
+/// concurrently.
+const MAX_SWAP_UPDATE_ATTEMPTS: usize = 10;
+
 pub fn serialize<T>(t: &T) -> anyhow::Result<Vec<u8>>
 where
     T: Serialize,
@@ -250,14 +1000,78 @@ where
     Ok(serde_cbor::from_slice(v)?)
 }
 
+async fn rollback(
+    db: &Database,
+    swap_id: SwapId,
+    mut remove_events: impl FnMut(&mut Swap),
+) -> anyhow::Result<()> {
+    update_swap(db, swap_id, |swap| {
+        remove_events(swap);
+        Ok(())
+    })
+    .await
+}
+
+/// Apply `update` to the stored swap and write it back. If the swap was
+/// written to in between, e.g. because events of both ledgers are saved at the
+/// same time, `update` is applied again to the swap as written then so that
+/// concurrent updates are merged rather than failing.
+async fn update_swap(
+    db: &Database,
+    swap_id: SwapId,
+    mut update: impl FnMut(&mut Swap) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let key = serialize(&swap_id)?;
+
+    for _ in 0..MAX_SWAP_UPDATE_ATTEMPTS {
+        let old_value = db
+            .db
+            .get(&key)?
+            .ok_or_else(|| anyhow!("Swap does not exists {}", swap_id))?;
+        let mut swap: Swap = deserialize(&old_value).context("Could not deserialize swap")?;
+        update(&mut swap)?;
+        let new_value = serialize(&swap).context("Could not serialize new swap value")?;
+
+        let written = db
+            .db
+            .compare_and_swap(&key, Some(old_value), Some(new_value))
+            .context("Could not write in the DB")?;
+        if written.is_ok() {
+            return db
+                .db
+                .flush_async()
+                .await
+                .map(|_| ())
+                .context("Could not flush db");
+        }
+
+        tracing::debug!(
+            "Swap {} was written to concurrently, updating it again",
+            swap_id
+        );
+    }
+
+    anyhow::bail!(
+        "Swap {} kept being written to concurrently, gave up updating it after {} attempts",
+        swap_id,
+        MAX_SWAP_UPDATE_ATTEMPTS
+    )
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 struct Swap {
     pub kind: Kind,
     pub hbit_params: hbit::Params,
     pub herc20_params: herc20::Params,
     pub secret_hash: comit::SecretHash,
+    /// Whether we are Alice, the secret is derived from the seed again when
+    /// the swap is resumed rather than stored.
+    #[serde(default)]
+    pub alice: bool,
     pub utc_start_of_swap: DateTime<Utc>,
     pub active_peer: network::ActivePeer,
+    #[serde(default)]
+    pub mid_market_rate: Option<Rate>,
     pub hbit_funded: Option<HbitFunded>,
     pub hbit_redeemed: Option<HbitRedeemed>,
     pub hbit_refunded: Option<HbitRefunded>,
@@ -288,8 +1102,10 @@ impl StaticStub for Swap {
                 )
                 .unwrap(),
             ),
+            alice: false,
             active_peer: network::ActivePeer::static_stub(),
             utc_start_of_swap: chrono::Utc::now(),
+            mid_market_rate: None,
             hbit_funded: None,
             hbit_redeemed: None,
             hbit_refunded: None,
@@ -310,8 +1126,10 @@ impl From<(Swap, SwapId)> for SwapKind {
             hbit_params,
             herc20_params,
             secret_hash,
+            alice,
             utc_start_of_swap: start_of_swap,
             active_peer: taker,
+            mid_market_rate,
             ..
         } = swap;
 
@@ -319,9 +1137,16 @@ impl From<(Swap, SwapId)> for SwapKind {
             hbit_params: hbit_params.into(),
             herc20_params: herc20_params.into(),
             secret_hash,
+            role: if alice {
+                SwapRole::Alice
+            } else {
+                SwapRole::Bob
+            },
+            secret: None,
             start_of_swap,
             swap_id,
             taker,
+            mid_market_rate,
         };
 
         match kind {
@@ -343,8 +1168,10 @@ impl From<SwapKind> for Swap {
             hbit_params: swap.hbit_params.into(),
             herc20_params: swap.herc20_params.into(),
             secret_hash: swap.secret_hash,
+            alice: swap.role == SwapRole::Alice,
             utc_start_of_swap: swap.start_of_swap,
             active_peer: swap.taker,
+            mid_market_rate: swap.mid_market_rate,
             hbit_funded: None,
             hbit_redeemed: None,
             hbit_refunded: None,
@@ -361,6 +1188,13 @@ mod tests {
     use super::*;
     use quickcheck::{Arbitrary, StdThreadGen};
 
+    #[tokio::test]
+    async fn probe_write_succeeds_on_writable_db() {
+        let db = Database::new_test().unwrap();
+
+        db.probe_write().await.unwrap();
+    }
+
     #[quickcheck_async::tokio]
     async fn save_and_retrieve_swaps(swap_1: SwapKind, swap_2: SwapKind) -> bool {
         let db = Database::new_test().unwrap();
@@ -377,6 +1211,77 @@ mod tests {
         true
     }
 
+    #[tokio::test]
+    async fn swaps_in_progress_are_kept_across_restarts() {
+        let db = Database::new_test().unwrap();
+        let swap = SwapKind::arbitrary(&mut StdThreadGen::new(100));
+
+        db.insert_swap(swap.clone()).await.unwrap();
+        db.flush().await.unwrap();
+
+        let Database { db, tmp_dir } = db;
+        drop(db);
+        let db = Database {
+            db: sled::open(tmp_dir.path()).unwrap(),
+            tmp_dir,
+        };
+
+        assert_eq!(db.all_swaps().unwrap(), vec![swap]);
+    }
+
+    #[tokio::test]
+    async fn secret_of_alice_is_not_stored() {
+        let db = Database::new_test().unwrap();
+        let secret = comit::Secret::from(*b"hello world, you are beautiful!!");
+        let swap = SwapKind::HbitHerc20(swap::SwapParams {
+            role: SwapRole::Alice,
+            secret: Some(secret),
+            ..swap::SwapParams::static_stub()
+        });
+        let swap_id = swap.swap_id();
+
+        db.insert_swap(swap).await.unwrap();
+
+        let stored = db.db.get(serialize(&swap_id).unwrap()).unwrap().unwrap();
+        match deserialize::<serde_cbor::Value>(&stored).unwrap() {
+            serde_cbor::Value::Map(swap) => {
+                assert!(!swap.contains_key(&serde_cbor::Value::Text("secret".to_owned())))
+            }
+            _ => panic!("swap is a map"),
+        }
+        let loaded = db.all_swaps().unwrap().remove(0);
+        assert!(matches!(loaded.role(), comit::Role::Alice));
+        assert_eq!(loaded.params().secret, None);
+    }
+
+    #[tokio::test]
+    async fn incorrect_funding_is_kept_across_restarts() {
+        let db = Database::new_test().unwrap();
+        let swap_id = SwapId::default();
+        let incorrect_funding = IncorrectFunding {
+            swap_id,
+            recorded_at: Utc::now(),
+            expected_sat: 100_000_000,
+            funded_sat: 99_000_000,
+        };
+
+        db.insert_incorrect_funding(&incorrect_funding)
+            .await
+            .unwrap();
+
+        let Database { db, tmp_dir } = db;
+        drop(db);
+        let db = Database {
+            db: sled::open(tmp_dir.path()).unwrap(),
+            tmp_dir,
+        };
+
+        assert_eq!(
+            db.incorrect_funding(&swap_id).unwrap(),
+            Some(incorrect_funding)
+        );
+    }
+
     #[quickcheck_async::tokio]
     async fn save_and_delete_correct_swap(swap_1: swap::SwapParams, swap_2: SwapKind) -> bool {
         let db = Database::new_test().unwrap();
@@ -400,7 +1305,10 @@ mod tests {
     async fn taker_no_longer_has_ongoing_trade_after_removal(peer: ActivePeer) -> bool {
         let db = Database::new_test().unwrap();
 
-        let _ = db.insert_active_peer(peer.clone()).await.unwrap();
+        let _ = db
+            .insert_active_peer(peer.clone(), Reservation::default(), Utc::now())
+            .await
+            .unwrap();
 
         let res = db.contains_active_peer(&peer);
         assert!(matches!(res, Ok(true)));
@@ -411,6 +1319,82 @@ mod tests {
         matches!(res, Ok(false))
     }
 
+    #[tokio::test]
+    async fn expired_active_peers_are_purged_with_their_reservation() {
+        let db = Database::new_test().unwrap();
+        let now = Utc::now();
+        let expired = ActivePeer {
+            peer_id: PeerId::random(),
+        };
+        let active = ActivePeer {
+            peer_id: PeerId::random(),
+        };
+        let reservation = Reservation {
+            dai_attodai: None,
+            bitcoin_sat: Some(100_000),
+            swap_id: None,
+        };
+        db.insert_active_peer(
+            expired.clone(),
+            reservation.clone(),
+            now - chrono::Duration::minutes(1),
+        )
+        .await
+        .unwrap();
+        db.insert_active_peer(
+            active.clone(),
+            Reservation::default(),
+            now + chrono::Duration::hours(1),
+        )
+        .await
+        .unwrap();
+
+        let purged = db.purge_expired_active_peers(now).await.unwrap();
+
+        assert_eq!(purged, vec![(expired.clone(), reservation)]);
+        assert!(!db.contains_active_peer(&expired).unwrap());
+        assert!(db.contains_active_peer(&active).unwrap());
+        assert_eq!(db.remove_active_peer(&expired).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn renewed_active_peer_is_not_purged() {
+        let db = Database::new_test().unwrap();
+        let now = Utc::now();
+        let peer = ActivePeer {
+            peer_id: PeerId::random(),
+        };
+        let reservation = Reservation {
+            dai_attodai: None,
+            bitcoin_sat: Some(100_000),
+            swap_id: None,
+        };
+        db.insert_active_peer(
+            peer.clone(),
+            reservation.clone(),
+            now - chrono::Duration::minutes(1),
+        )
+        .await
+        .unwrap();
+
+        let renewed = db
+            .renew_active_peer(&peer, now + chrono::Duration::hours(1))
+            .await
+            .unwrap();
+        let purged = db.purge_expired_active_peers(now).await.unwrap();
+
+        assert!(renewed);
+        assert!(purged.is_empty());
+        assert_eq!(
+            db.remove_active_peer(&peer).await.unwrap(),
+            Some(reservation)
+        );
+        assert!(!db
+            .renew_active_peer(&peer, now + chrono::Duration::hours(1))
+            .await
+            .unwrap());
+    }
+
     #[tokio::test]
     async fn save_and_retrieve_hundred_swaps() {
         let db = Database::new_test().unwrap();
@@ -434,6 +1418,223 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn balance_snapshots_are_retrieved_in_chronological_order() {
+        let db = Database::new_test().unwrap();
+        let snapshot = |taken_at: &str| BalanceSnapshot {
+            taken_at: taken_at.parse().unwrap(),
+            bitcoin_sat: Some(100_000),
+            dai_attodai: None,
+            ether_wei: Some("1000000000000000000".to_owned()),
+            mid_market_rate: Some(Rate::new(100)),
+        };
+        let later = snapshot("2020-07-10T09:00:00Z");
+        let earlier = snapshot("2020-07-10T08:00:00Z");
+
+        db.insert_balance_snapshot(&later).await.unwrap();
+        db.insert_balance_snapshot(&earlier).await.unwrap();
+
+        assert_eq!(db.balance_snapshots().unwrap(), vec![earlier, later]);
+        assert!(db.all_swaps().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn swap_failure_is_removed_with_the_swap() {
+        let db = Database::new_test().unwrap();
+        let swap = SwapKind::HbitHerc20(swap::SwapParams::static_stub());
+        let swap_id = swap.swap_id();
+        db.insert_swap(swap).await.unwrap();
+
+        db.insert_swap_failure(&swap_id, "first").await.unwrap();
+        db.insert_swap_failure(&swap_id, "second").await.unwrap();
+        assert_eq!(
+            db.swap_failure(&swap_id).unwrap(),
+            Some("second".to_owned())
+        );
+
+        db.remove_swap(&swap_id).await.unwrap();
+        assert_eq!(db.swap_failure(&swap_id).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn concurrent_write_to_the_swap_is_merged_into_the_update() {
+        let db = Database::new_test().unwrap();
+        let swap = SwapKind::HbitHerc20(swap::SwapParams::static_stub());
+        let swap_id = swap.swap_id();
+        db.insert_swap(swap).await.unwrap();
+        let mut attempts = 0;
+
+        update_swap(&db, swap_id, |swap| {
+            attempts += 1;
+            if attempts == 1 {
+                // Written to between our read and our write
+                let mut concurrent = db.get_swap(&swap_id)?;
+                concurrent.mid_market_rate = Some(crate::rate::rate(9_000.0));
+                db.db
+                    .insert(serialize(&swap_id)?, serialize(&concurrent)?)?;
+            }
+            swap.hbit_funded = Some(HbitFunded::from(swap::hbit::Funded {
+                asset: comit::asset::Bitcoin::from_sat(123_456),
+                location: comit::htlc_location::Bitcoin::default(),
+            }));
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        let stored = db.get_swap(&swap_id).unwrap();
+        assert_eq!(attempts, 2);
+        assert_eq!(stored.mid_market_rate, Some(crate::rate::rate(9_000.0)));
+        assert!(stored.hbit_funded.is_some());
+    }
+
+    #[tokio::test]
+    async fn swap_outcome_is_kept_after_the_swap_is_removed() {
+        let db = Database::new_test().unwrap();
+        let swap = SwapKind::HbitHerc20(swap::SwapParams::static_stub());
+        let swap_id = swap.swap_id();
+        db.insert_swap(swap).await.unwrap();
+        let outcome = SwapOutcome::AbortedBeforeFund {
+            reason: "Alice failed to fund.".to_owned(),
+        };
+
+        db.insert_swap_outcome(&swap_id, &outcome).await.unwrap();
+        db.remove_swap(&swap_id).await.unwrap();
+
+        assert_eq!(db.swap_outcome(&swap_id).unwrap(), Some(outcome.clone()));
+        assert_eq!(db.swap_outcomes().unwrap(), vec![outcome]);
+    }
+
+    #[test]
+    fn order_audit_entries_recorded_at_the_same_time_are_all_kept_in_order() {
+        let db = Database::new_test().unwrap();
+        let recorded_at: DateTime<Utc> = "2020-07-10T07:00:00Z".parse().unwrap();
+        let published = OrderAuditEntry {
+            recorded_at,
+            reason: OrderUpdateReason::RateUpdate,
+            action: OrderAction::Published(AuditedOrder {
+                position: "sell".to_owned(),
+                quantity_sat: 1_000_000,
+                quote_attodai: "100000000000000000000".to_owned(),
+                rate: Some(Rate::new(100_000_000_000_000)),
+            }),
+            mid_market_rate: Some(Rate::new(95_000_000_000_000)),
+        };
+        let cleared = OrderAuditEntry {
+            action: OrderAction::Cleared,
+            ..published.clone()
+        };
+
+        db.insert_order_audit_entry(&published).unwrap();
+        db.insert_order_audit_entry(&cleared).unwrap();
+
+        assert_eq!(db.order_audit_entries().unwrap(), vec![published, cleared]);
+        assert!(db.all_swaps().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn taker_is_listed_once_until_removed() {
+        let db = Database::new_test().unwrap();
+        let peer_id = PeerId::random();
+
+        db.insert_taker_listing(&peer_id, TakerListing::Allowed)
+            .await
+            .unwrap();
+        db.insert_taker_listing(&peer_id, TakerListing::Banned)
+            .await
+            .unwrap();
+        assert_eq!(db.taker_listings().unwrap(), vec![(
+            peer_id.clone(),
+            TakerListing::Banned
+        )]);
+
+        assert!(db.remove_taker_listing(&peer_id).await.unwrap());
+        assert!(!db.remove_taker_listing(&peer_id).await.unwrap());
+        assert!(db.taker_listings().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn peer_outcomes_are_counted_per_taker() {
+        let db = Database::new_test().unwrap();
+        let peer_id = PeerId::random();
+
+        assert!(db.reputation(&peer_id).unwrap().is_unknown());
+
+        db.record_peer_outcome(&peer_id, PeerOutcome::Aborted)
+            .await
+            .unwrap();
+        db.record_peer_outcome(&peer_id, PeerOutcome::Completed)
+            .await
+            .unwrap();
+        let reputation = db
+            .record_peer_outcome(&peer_id, PeerOutcome::Aborted)
+            .await
+            .unwrap();
+
+        assert_eq!(reputation, Reputation {
+            completed: 1,
+            aborted: 2,
+            refunds_caused: 0
+        });
+        assert_eq!(db.reputation(&peer_id).unwrap(), reputation);
+        assert_eq!(
+            db.reputation(&PeerId::random()).unwrap(),
+            Reputation::default()
+        );
+    }
+
+    #[tokio::test]
+    async fn sold_volumes_are_retrieved_since_the_given_time() {
+        let db = Database::new_test().unwrap();
+        let sold_volume = |sold_at: &str| SoldVolume {
+            sold_at: DateTime::from_str(sold_at).unwrap(),
+            swap_id: SwapId::default(),
+            bitcoin_sat: 100,
+            dai_attodai: "0".to_string(),
+        };
+
+        let old = sold_volume("2020-07-09T08:00:00Z");
+        let recent = sold_volume("2020-07-10T08:00:00Z");
+        let same_time = sold_volume("2020-07-10T08:00:00Z");
+        db.insert_sold_volume(&old).await.unwrap();
+        db.insert_sold_volume(&recent).await.unwrap();
+        db.insert_sold_volume(&same_time).await.unwrap();
+
+        let since = db
+            .sold_volumes_since(DateTime::from_str("2020-07-10T00:00:00Z").unwrap())
+            .unwrap();
+
+        assert_eq!(since.len(), 2);
+        assert!(!since.contains(&old));
+    }
+
+    #[tokio::test]
+    async fn sold_volume_is_removed_by_swap_id() {
+        let db = Database::new_test().unwrap();
+        let sold_volume = |sold_at: &str| SoldVolume {
+            sold_at: DateTime::from_str(sold_at).unwrap(),
+            swap_id: SwapId::default(),
+            bitcoin_sat: 100,
+            dai_attodai: "0".to_string(),
+        };
+
+        let kept = sold_volume("2020-07-10T08:00:00Z");
+        let removed = sold_volume("2020-07-10T07:00:00Z");
+        db.insert_sold_volume(&kept).await.unwrap();
+        db.insert_sold_volume(&removed).await.unwrap();
+
+        assert_eq!(
+            db.remove_sold_volume(&removed.swap_id).await.unwrap(),
+            Some(removed.clone())
+        );
+        assert_eq!(db.remove_sold_volume(&removed.swap_id).await.unwrap(), None);
+        assert_eq!(
+            db.sold_volumes_since(DateTime::from_str("2020-07-10T00:00:00Z").unwrap())
+                .unwrap(),
+            vec![kept]
+        );
+    }
+
     #[test]
     fn increment_bitcoin_transient_key_index() {
         let db = Database::new_test().unwrap();
@@ -1,12 +1,12 @@
 use crate::{
     swap::{
-        db::{serialize, Database, Load, Save},
+        db::{rollback, update_swap, Database, Load, Rollback, Save},
         hbit,
     },
     SwapId,
 };
 use ::bitcoin::secp256k1;
-use anyhow::{anyhow, Context};
+use anyhow::anyhow;
 use comit::{identity, Secret, SecretHash, Timestamp};
 use serde::{Deserialize, Serialize};
 
@@ -53,32 +53,14 @@ impl From<hbit::Funded> for HbitFunded {
 #[async_trait::async_trait]
 impl Save<hbit::Funded> for Database {
     async fn save(&self, event: hbit::Funded, swap_id: SwapId) -> anyhow::Result<()> {
-        let stored_swap = self.get_swap(&swap_id)?;
-
-        match stored_swap.hbit_funded {
+        update_swap(self, swap_id, |swap| match swap.hbit_funded {
             Some(_) => Err(anyhow!("Hbit Funded event is already stored")),
             None => {
-                let key = serialize(&swap_id)?;
-
-                let mut swap = stored_swap.clone();
                 swap.hbit_funded = Some(event.into());
-
-                let old_value =
-                    serialize(&stored_swap).context("Could not serialize old swap value")?;
-                let new_value = serialize(&swap).context("Could not serialize new swap value")?;
-
-                self.db
-                    .compare_and_swap(key, Some(old_value), Some(new_value))
-                    .context("Could not write in the DB")?
-                    .context("Stored swap somehow changed, aborting saving")?;
-
-                self.db
-                    .flush_async()
-                    .await
-                    .map(|_| ())
-                    .context("Could not flush db")
+                Ok(())
             }
-        }
+        })
+        .await
     }
 }
 
@@ -90,6 +72,13 @@ impl Load<hbit::Funded> for Database {
     }
 }
 
+#[async_trait::async_trait]
+impl Rollback<hbit::Funded> for Database {
+    async fn rollback(&self, swap_id: SwapId) -> anyhow::Result<()> {
+        rollback(self, swap_id, |swap| swap.hbit_funded = None).await
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct HbitRedeemed {
     pub transaction: comit::transaction::Bitcoin,
@@ -117,32 +106,14 @@ impl From<hbit::Redeemed> for HbitRedeemed {
 #[async_trait::async_trait]
 impl Save<hbit::Redeemed> for Database {
     async fn save(&self, event: hbit::Redeemed, swap_id: SwapId) -> anyhow::Result<()> {
-        let stored_swap = self.get_swap(&swap_id)?;
-
-        match stored_swap.hbit_redeemed {
+        update_swap(self, swap_id, |swap| match swap.hbit_redeemed {
             Some(_) => Err(anyhow!("Hbit Redeemed event is already stored")),
             None => {
-                let key = serialize(&swap_id)?;
-
-                let mut swap = stored_swap.clone();
-                swap.hbit_redeemed = Some(event.into());
-
-                let old_value =
-                    serialize(&stored_swap).context("Could not serialize old swap value")?;
-                let new_value = serialize(&swap).context("Could not serialize new swap value")?;
-
-                self.db
-                    .compare_and_swap(key, Some(old_value), Some(new_value))
-                    .context("Could not write in the DB")?
-                    .context("Stored swap somehow changed, aborting saving")?;
-
-                self.db
-                    .flush_async()
-                    .await
-                    .map(|_| ())
-                    .context("Could not flush db")
+                swap.hbit_redeemed = Some(event.clone().into());
+                Ok(())
             }
-        }
+        })
+        .await
     }
 }
 
@@ -178,31 +149,14 @@ impl From<hbit::Refunded> for HbitRefunded {
 #[async_trait::async_trait]
 impl Save<hbit::Refunded> for Database {
     async fn save(&self, event: hbit::Refunded, swap_id: SwapId) -> anyhow::Result<()> {
-        let stored_swap = self.get_swap(&swap_id)?;
-        match stored_swap.hbit_refunded {
+        update_swap(self, swap_id, |swap| match swap.hbit_refunded {
             Some(_) => Err(anyhow!("Hbit Refunded event is already stored")),
             None => {
-                let key = serialize(&swap_id)?;
-
-                let mut swap = stored_swap.clone();
-                swap.hbit_refunded = Some(event.into());
-
-                let old_value =
-                    serialize(&stored_swap).context("Could not serialize old swap value")?;
-                let new_value = serialize(&swap).context("Could not serialize new swap value")?;
-
-                self.db
-                    .compare_and_swap(key, Some(old_value), Some(new_value))
-                    .context("Could not write in the DB")?
-                    .context("Stored swap somehow changed, aborting saving")?;
-
-                self.db
-                    .flush_async()
-                    .await
-                    .map(|_| ())
-                    .context("Could not flush db")
+                swap.hbit_refunded = Some(event.clone().into());
+                Ok(())
             }
-        }
+        })
+        .await
     }
 }
 
@@ -1,11 +1,11 @@
 use crate::{
     swap::{
-        db::{serialize, Database, Load, Save},
+        db::{rollback, update_swap, Database, Load, Rollback, Save},
         herc20,
     },
     SwapId,
 };
-use anyhow::{anyhow, Context};
+use anyhow::anyhow;
 use comit::{
     asset::Erc20,
     ethereum,
@@ -42,32 +42,14 @@ impl From<herc20::Deployed> for Herc20Deployed {
 #[async_trait::async_trait]
 impl Save<herc20::Deployed> for Database {
     async fn save(&self, event: herc20::Deployed, swap_id: SwapId) -> anyhow::Result<()> {
-        let stored_swap = self.get_swap(&swap_id)?;
-
-        match stored_swap.herc20_deployed {
+        update_swap(self, swap_id, |swap| match swap.herc20_deployed {
             Some(_) => Err(anyhow!("Herc20 Deployed event is already stored")),
             None => {
-                let key = serialize(&swap_id)?;
-
-                let mut swap = stored_swap.clone();
-                swap.herc20_deployed = Some(event.into());
-
-                let old_value =
-                    serialize(&stored_swap).context("Could not serialize old swap value")?;
-                let new_value = serialize(&swap).context("Could not serialize new swap value")?;
-
-                self.db
-                    .compare_and_swap(key, Some(old_value), Some(new_value))
-                    .context("Could not write in the DB")?
-                    .context("Stored swap somehow changed, aborting saving")?;
-
-                self.db
-                    .flush_async()
-                    .await
-                    .map(|_| ())
-                    .context("Could not flush db")
+                swap.herc20_deployed = Some(event.clone().into());
+                Ok(())
             }
-        }
+        })
+        .await
     }
 }
 
@@ -79,6 +61,19 @@ impl Load<herc20::Deployed> for Database {
     }
 }
 
+/// The funding of the HTLC is rolled back with its deployment, it was sent to
+/// the orphaned contract.
+#[async_trait::async_trait]
+impl Rollback<herc20::Deployed> for Database {
+    async fn rollback(&self, swap_id: SwapId) -> anyhow::Result<()> {
+        rollback(self, swap_id, |swap| {
+            swap.herc20_deployed = None;
+            swap.herc20_funded = None;
+        })
+        .await
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Herc20Funded {
     pub transaction: EthereumTransaction,
@@ -106,32 +101,14 @@ impl From<herc20::Funded> for Herc20Funded {
 #[async_trait::async_trait]
 impl Save<herc20::Funded> for Database {
     async fn save(&self, event: herc20::Funded, swap_id: SwapId) -> anyhow::Result<()> {
-        let stored_swap = self.get_swap(&swap_id)?;
-
-        match stored_swap.herc20_funded {
+        update_swap(self, swap_id, |swap| match swap.herc20_funded {
             Some(_) => Err(anyhow!("Herc20 Funded event is already stored")),
             None => {
-                let key = serialize(&swap_id)?;
-
-                let mut swap = stored_swap.clone();
-                swap.herc20_funded = Some(event.into());
-
-                let old_value =
-                    serialize(&stored_swap).context("Could not serialize old swap value")?;
-                let new_value = serialize(&swap).context("Could not serialize new swap value")?;
-
-                self.db
-                    .compare_and_swap(key, Some(old_value), Some(new_value))
-                    .context("Could not write in the DB")?
-                    .context("Stored swap somehow changed, aborting saving")?;
-
-                self.db
-                    .flush_async()
-                    .await
-                    .map(|_| ())
-                    .context("Could not flush db")
+                swap.herc20_funded = Some(event.clone().into());
+                Ok(())
             }
-        }
+        })
+        .await
     }
 }
 
@@ -143,6 +120,13 @@ impl Load<herc20::Funded> for Database {
     }
 }
 
+#[async_trait::async_trait]
+impl Rollback<herc20::Funded> for Database {
+    async fn rollback(&self, swap_id: SwapId) -> anyhow::Result<()> {
+        rollback(self, swap_id, |swap| swap.herc20_funded = None).await
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Herc20Redeemed {
     pub transaction: EthereumTransaction,
@@ -170,32 +154,14 @@ impl From<herc20::Redeemed> for Herc20Redeemed {
 #[async_trait::async_trait]
 impl Save<herc20::Redeemed> for Database {
     async fn save(&self, event: herc20::Redeemed, swap_id: SwapId) -> anyhow::Result<()> {
-        let stored_swap = self.get_swap(&swap_id)?;
-
-        match stored_swap.herc20_redeemed {
+        update_swap(self, swap_id, |swap| match swap.herc20_redeemed {
             Some(_) => Err(anyhow!("Herc20 Redeemed event is already stored")),
             None => {
-                let key = serialize(&swap_id)?;
-
-                let mut swap = stored_swap.clone();
-                swap.herc20_redeemed = Some(event.into());
-
-                let old_value =
-                    serialize(&stored_swap).context("Could not serialize old swap value")?;
-                let new_value = serialize(&swap).context("Could not serialize new swap value")?;
-
-                self.db
-                    .compare_and_swap(key, Some(old_value), Some(new_value))
-                    .context("Could not write in the DB")?
-                    .context("Stored swap somehow changed, aborting saving")?;
-
-                self.db
-                    .flush_async()
-                    .await
-                    .map(|_| ())
-                    .context("Could not flush db")
+                swap.herc20_redeemed = Some(event.clone().into());
+                Ok(())
             }
-        }
+        })
+        .await
     }
 }
 
@@ -231,32 +197,14 @@ impl From<herc20::Refunded> for Herc20Refunded {
 #[async_trait::async_trait]
 impl Save<herc20::Refunded> for Database {
     async fn save(&self, event: herc20::Refunded, swap_id: SwapId) -> anyhow::Result<()> {
-        let stored_swap = self.get_swap(&swap_id)?;
-
-        match stored_swap.herc20_refunded {
+        update_swap(self, swap_id, |swap| match swap.herc20_refunded {
             Some(_) => Err(anyhow!("Herc20 Refunded event is already stored")),
             None => {
-                let key = serialize(&swap_id)?;
-
-                let mut swap = stored_swap.clone();
-                swap.herc20_refunded = Some(event.into());
-
-                let old_value =
-                    serialize(&stored_swap).context("Could not serialize old swap value")?;
-                let new_value = serialize(&swap).context("Could not serialize new swap value")?;
-
-                self.db
-                    .compare_and_swap(key, Some(old_value), Some(new_value))
-                    .context("Could not write in the DB")?
-                    .context("Stored swap somehow changed, aborting saving")?;
-
-                self.db
-                    .flush_async()
-                    .await
-                    .map(|_| ())
-                    .context("Could not flush db")
+                swap.herc20_refunded = Some(event.clone().into());
+                Ok(())
             }
-        }
+        })
+        .await
     }
 }
 
@@ -453,6 +401,44 @@ mod tests {
         assert_eq!(stored_event.asset, asset);
     }
 
+    #[tokio::test]
+    async fn rolling_back_herc20_deployed_also_rolls_back_funded() {
+        let db = Database::new_test().unwrap();
+        let swap = Swap::static_stub();
+        let swap_id = SwapId::default();
+        let deployed = herc20::Deployed {
+            transaction: comit::transaction::Ethereum::default(),
+            location: comit::htlc_location::Ethereum::random(),
+        };
+        let funded = herc20::Funded {
+            transaction: comit::transaction::Ethereum::default(),
+            asset: comit::asset::Erc20::new(
+                ethereum::Address::random(),
+                comit::asset::Erc20Quantity::from_wei_dec_str("1000").unwrap(),
+            ),
+        };
+
+        db.insert_swap(SwapKind::from((swap, swap_id)))
+            .await
+            .unwrap();
+        db.save(deployed.clone(), swap_id).await.unwrap();
+        db.save(funded, swap_id).await.unwrap();
+
+        Rollback::<herc20::Deployed>::rollback(&db, swap_id)
+            .await
+            .unwrap();
+
+        let stored_deployed: Option<herc20::Deployed> = db.load(swap_id).unwrap();
+        let stored_funded: Option<herc20::Funded> = db.load(swap_id).unwrap();
+        assert!(stored_deployed.is_none());
+        assert!(stored_funded.is_none());
+
+        // The event observed again once re-included can be saved
+        db.save(deployed, swap_id).await.unwrap();
+        let stored_deployed: Option<herc20::Deployed> = db.load(swap_id).unwrap();
+        assert!(stored_deployed.is_some());
+    }
+
     #[tokio::test]
     async fn save_and_load_herc20_redeemed() {
         let db = Database::new_test().unwrap();
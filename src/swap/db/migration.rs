@@ -0,0 +1,204 @@
+//! Versioning of the data stored in the database.
+//!
+//! The version of the database is stored under its own key, databases created
+//! before it was introduced are at version 0. When opening the database the
+//! migrations it is missing are applied in order, each of them upgrades the
+//! stored data by one version. Changing the serialized form of a stored struct,
+//! e.g. `EthereumTransaction`, requires a new migration and bumping
+//! `CURRENT_VERSION`.
+
+use super::{deserialize, serialize, Database, Expiring, Reservation};
+use crate::{network::ActivePeer, SwapId};
+use anyhow::Context;
+use chrono::Utc;
+use serde_cbor::Value;
+use std::{collections::BTreeMap, convert::TryFrom};
+
+pub const CURRENT_VERSION: u32 = 2;
+
+const VERSION_KEY: &str = "database_version";
+
+/// Upgrades the stored data from the version of its index in `MIGRATIONS` to
+/// the next one.
+type Migration = fn(&sled::Db) -> anyhow::Result<()>;
+
+const MIGRATIONS: &[Migration] = &[
+    record_missing_mid_market_rates,
+    move_active_peers_to_expiring_tree,
+];
+
+pub fn version(db: &sled::Db) -> anyhow::Result<u32> {
+    match db.get(serialize(&VERSION_KEY)?)? {
+        Some(version) => deserialize(&version),
+        None => Ok(0),
+    }
+}
+
+/// Apply the migrations the database is missing. A database written by a
+/// newer version of nectar is refused rather than misread.
+pub fn migrate(db: &sled::Db) -> anyhow::Result<()> {
+    let stored_version = version(db)?;
+    if stored_version > CURRENT_VERSION {
+        anyhow::bail!(
+            "The database is at version {} but at most version {} is supported, upgrade nectar",
+            stored_version,
+            CURRENT_VERSION
+        );
+    }
+
+    for version in stored_version..CURRENT_VERSION {
+        let migration = MIGRATIONS[usize::try_from(version)?];
+
+        tracing::info!("Migrating the database to version {}", version + 1);
+        migration(db).with_context(|| {
+            format!("Could not migrate the database to version {}", version + 1)
+        })?;
+        db.insert(serialize(&VERSION_KEY)?, serialize(&(version + 1))?)?;
+    }
+
+    db.flush()?;
+
+    Ok(())
+}
+
+/// Rewrite every stored swap with `update`, the swaps are handled as CBOR
+/// maps so that the structs of the previous version are not needed.
+fn update_swaps(
+    db: &sled::Db,
+    update: impl Fn(&mut BTreeMap<Value, Value>) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    for item in db.iter() {
+        let (key, value) = item?;
+        // Only the swaps are keyed by swap id
+        if deserialize::<SwapId>(&key).is_err() {
+            continue;
+        }
+
+        let mut swap = match deserialize::<Value>(&value)? {
+            Value::Map(swap) => swap,
+            _ => anyhow::bail!("Swap is not stored as a map"),
+        };
+        update(&mut swap)?;
+
+        db.insert(key, serialize(&Value::Map(swap))?)?;
+    }
+
+    Ok(())
+}
+
+/// Version 1: the swaps stored before the mid-market rate was recorded get an
+/// explicit `None`.
+fn record_missing_mid_market_rates(db: &sled::Db) -> anyhow::Result<()> {
+    update_swaps(db, |swap| {
+        swap.entry(Value::Text("mid_market_rate".to_owned()))
+            .or_insert(Value::Null);
+        Ok(())
+    })
+}
+
+/// The key the active peers were stored under before version 2.
+const LEGACY_ACTIVE_PEER_KEY: &str = "active_peer";
+
+/// Version 2: the active peers are stored with an expiry and the funds
+/// reserved for their swap. Those recorded before are unknown and expire
+/// right away, the swaps still stored record their peer again when resumed.
+fn move_active_peers_to_expiring_tree(db: &sled::Db) -> anyhow::Result<()> {
+    let peers = match db.remove(serialize(&LEGACY_ACTIVE_PEER_KEY)?)? {
+        Some(peers) => deserialize::<Vec<ActivePeer>>(&peers)?,
+        None => return Ok(()),
+    };
+
+    let tree = db.open_tree(Database::ACTIVE_PEERS_TREE)?;
+    for peer in peers {
+        let expiring = Expiring {
+            expires_at: Utc::now(),
+            value: Reservation::default(),
+        };
+        tree.insert(serialize(&peer)?, serialize(&expiring)?)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        swap::db::{Database, Swap},
+        StaticStub,
+    };
+
+    #[test]
+    fn there_is_a_migration_per_version() {
+        assert_eq!(usize::try_from(CURRENT_VERSION).unwrap(), MIGRATIONS.len());
+    }
+
+    #[test]
+    fn unversioned_swaps_are_migrated_to_the_current_version() {
+        let database = Database::new_test().unwrap();
+        let db = &database.db;
+        db.remove(serialize(&VERSION_KEY).unwrap()).unwrap();
+
+        let swap_id = SwapId::default();
+        let mut swap =
+            match deserialize::<Value>(&serialize(&Swap::static_stub()).unwrap()).unwrap() {
+                Value::Map(swap) => swap,
+                _ => panic!("swap is a map"),
+            };
+        swap.remove(&Value::Text("mid_market_rate".to_owned()));
+        db.insert(
+            serialize(&swap_id).unwrap(),
+            serialize(&Value::Map(swap)).unwrap(),
+        )
+        .unwrap();
+
+        migrate(db).unwrap();
+
+        assert_eq!(version(db).unwrap(), CURRENT_VERSION);
+        let stored = db.get(serialize(&swap_id).unwrap()).unwrap().unwrap();
+        let swap = match deserialize::<Value>(&stored).unwrap() {
+            Value::Map(swap) => swap,
+            _ => panic!("swap is a map"),
+        };
+        assert_eq!(
+            swap.get(&Value::Text("mid_market_rate".to_owned())),
+            Some(&Value::Null)
+        );
+        assert!(database.get_swap(&swap_id).is_ok());
+    }
+
+    #[test]
+    fn active_peers_are_moved_to_their_own_tree() {
+        let database = Database::new_test().unwrap();
+        let db = &database.db;
+        db.insert(serialize(&VERSION_KEY).unwrap(), serialize(&1u32).unwrap())
+            .unwrap();
+        let peer = ActivePeer::static_stub();
+        db.insert(
+            serialize(&LEGACY_ACTIVE_PEER_KEY).unwrap(),
+            serialize(&vec![peer.clone()]).unwrap(),
+        )
+        .unwrap();
+
+        migrate(db).unwrap();
+
+        assert!(db
+            .get(serialize(&LEGACY_ACTIVE_PEER_KEY).unwrap())
+            .unwrap()
+            .is_none());
+        assert!(database.contains_active_peer(&peer).unwrap());
+    }
+
+    #[test]
+    fn database_of_a_newer_version_is_refused() {
+        let database = Database::new_test().unwrap();
+        let db = &database.db;
+        db.insert(
+            serialize(&VERSION_KEY).unwrap(),
+            serialize(&(CURRENT_VERSION + 1)).unwrap(),
+        )
+        .unwrap();
+
+        assert!(migrate(db).is_err());
+    }
+}
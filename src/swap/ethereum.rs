@@ -1,4 +1,7 @@
-use crate::swap::{herc20, LedgerTime};
+use crate::swap::{
+    herc20::{self, WaitForConfirmations},
+    LedgerTime,
+};
 use chrono::{DateTime, Utc};
 use comit::{
     btsieve::{ethereum::Web3Connector, LatestBlock},
@@ -11,6 +14,9 @@ pub use comit::{
     Secret,
 };
 
+/// How often the chain is checked while waiting for confirmations.
+const CONFIRMATION_POLL_INTERVAL: Duration = Duration::from_secs(15);
+
 #[derive(Debug, Clone)]
 pub struct Wallet {
     pub inner: Arc<crate::ethereum::Wallet>,
@@ -100,6 +106,105 @@ impl herc20::ExecuteRefund for Wallet {
     }
 }
 
+/// Waits until the block the transaction was included in has the
+/// confirmations required on the chain, checking that it is still the block
+/// holding the transaction once it has them.
+#[async_trait::async_trait]
+impl herc20::WaitForConfirmations for Wallet {
+    async fn wait_for_confirmations(&self, transaction: Hash) -> anyhow::Result<herc20::Inclusion> {
+        let required = self.inner.required_confirmations();
+        if required <= 1 {
+            return Ok(herc20::Inclusion::Confirmed);
+        }
+
+        let mut included_in = match self.inner.get_transaction_block(transaction).await? {
+            Some(included_in) => included_in,
+            None => return Ok(herc20::Inclusion::Orphaned),
+        };
+
+        tracing::info!(
+            "Waiting for {} confirmations of Ethereum transaction {:?}",
+            required,
+            transaction
+        );
+        loop {
+            match self.inner.block_number().await {
+                Ok(latest) if latest + 1 >= included_in.block_number.low_u64() + required => {
+                    match self.inner.get_transaction_block(transaction).await? {
+                        Some(block) if block == included_in => {
+                            return Ok(herc20::Inclusion::Confirmed)
+                        }
+                        // Included again in another block after a reorganisation
+                        Some(block) => included_in = block,
+                        None => return Ok(herc20::Inclusion::Orphaned),
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => tracing::warn!("Could not get the latest block, retrying: {:#}", e),
+            }
+
+            tokio::time::delay_for(CONFIRMATION_POLL_INTERVAL).await;
+        }
+    }
+}
+
+/// Only returns the events of the HTLC deployed by the counterparty once they
+/// are confirmed, watching again for the ones that were orphaned.
+#[async_trait::async_trait]
+impl herc20::WatchForDeployed for Wallet {
+    async fn watch_for_deployed(
+        &self,
+        params: herc20::Params,
+        utc_start_of_swap: DateTime<Utc>,
+    ) -> anyhow::Result<herc20::Deployed> {
+        loop {
+            let deployed = herc20::watch_for_deployed(
+                self.connector.as_ref(),
+                params.clone(),
+                utc_start_of_swap,
+            )
+            .await?;
+
+            match self
+                .wait_for_confirmations(deployed.transaction.hash)
+                .await?
+            {
+                herc20::Inclusion::Confirmed => return Ok(deployed),
+                herc20::Inclusion::Orphaned => {
+                    tracing::warn!("Ethereum HTLC deployment was orphaned, watching again")
+                }
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl herc20::WatchForFunded for Wallet {
+    async fn watch_for_funded(
+        &self,
+        params: herc20::Params,
+        utc_start_of_swap: DateTime<Utc>,
+        deployed: herc20::Deployed,
+    ) -> anyhow::Result<herc20::Funded> {
+        loop {
+            let funded = herc20::watch_for_funded(
+                self.connector.as_ref(),
+                params.clone(),
+                utc_start_of_swap,
+                deployed.clone(),
+            )
+            .await?;
+
+            match self.wait_for_confirmations(funded.transaction.hash).await? {
+                herc20::Inclusion::Confirmed => return Ok(funded),
+                herc20::Inclusion::Orphaned => {
+                    tracing::warn!("Ethereum HTLC funding was orphaned, watching again")
+                }
+            }
+        }
+    }
+}
+
 #[async_trait::async_trait]
 impl LedgerTime for Web3Connector {
     async fn ledger_time(&self) -> anyhow::Result<Timestamp> {
@@ -107,6 +212,40 @@ impl LedgerTime for Web3Connector {
     }
 }
 
+#[async_trait::async_trait]
+impl herc20::WatchForDeployed for Web3Connector {
+    async fn watch_for_deployed(
+        &self,
+        params: herc20::Params,
+        utc_start_of_swap: DateTime<Utc>,
+    ) -> anyhow::Result<herc20::Deployed> {
+        herc20::watch_for_deployed(self, params, utc_start_of_swap).await
+    }
+}
+
+#[async_trait::async_trait]
+impl herc20::WatchForFunded for Web3Connector {
+    async fn watch_for_funded(
+        &self,
+        params: herc20::Params,
+        utc_start_of_swap: DateTime<Utc>,
+        deployed: herc20::Deployed,
+    ) -> anyhow::Result<herc20::Funded> {
+        herc20::watch_for_funded(self, params, utc_start_of_swap, deployed).await
+    }
+}
+
+#[async_trait::async_trait]
+impl herc20::WatchForRedeemed for Web3Connector {
+    async fn watch_for_redeemed(
+        &self,
+        utc_start_of_swap: DateTime<Utc>,
+        deployed: herc20::Deployed,
+    ) -> anyhow::Result<herc20::Redeemed> {
+        herc20::watch_for_redeemed(self, utc_start_of_swap, deployed).await
+    }
+}
+
 #[async_trait::async_trait]
 impl LedgerTime for Wallet {
     async fn ledger_time(&self) -> anyhow::Result<Timestamp> {
@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use std::fmt;
+use std::{fmt, str::FromStr};
 use uuid::Uuid;
 
 #[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
@@ -23,6 +23,14 @@ impl fmt::Display for SwapId {
     }
 }
 
+impl FromStr for SwapId {
+    type Err = uuid::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Uuid::from_str(s).map(SwapId)
+    }
+}
+
 #[cfg(test)]
 mod arbitrary {
     use super::*;
@@ -2,8 +2,16 @@
 pub mod bitcoin;
 #[cfg(feature = "test-docker")]
 pub mod ethereum;
+pub mod mock;
 
 /// A trait that provide a static stub value for testing purposes
 pub trait StaticStub {
     fn static_stub() -> Self;
 }
+
+/// The value of an environment variable configuring the harness, unset if
+/// empty.
+#[cfg(feature = "test-docker")]
+fn env_var(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
@@ -1,8 +1,26 @@
-use crate::bitcoin;
+use crate::{bitcoin, test_harness::env_var};
 use std::time::Duration;
 use testcontainers::{clients, images::coblox_bitcoincore::BitcoinCore, Container, Docker};
 use url::Url;
 
+/// The `coblox/bitcoin-core` tag run when `NECTAR_TEST_BITCOIND_TAG` is not
+/// set.
+pub const DEFAULT_TAG: &str = "0.19.1";
+
+/// The bitcoind version the harness runs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    pub tag: String,
+}
+
+impl Image {
+    pub fn from_env() -> Self {
+        Image {
+            tag: env_var("NECTAR_TEST_BITCOIND_TAG").unwrap_or_else(|| DEFAULT_TAG.to_owned()),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Blockchain<'c> {
     _container: Container<'c, clients::Cli, BitcoinCore>,
@@ -12,7 +30,11 @@ pub struct Blockchain<'c> {
 
 impl<'c> Blockchain<'c> {
     pub fn new(client: &'c clients::Cli) -> anyhow::Result<Self> {
-        let container = client.run(BitcoinCore::default().with_tag("0.19.1"));
+        Self::with_image(client, Image::from_env())
+    }
+
+    pub fn with_image(client: &'c clients::Cli, image: Image) -> anyhow::Result<Self> {
+        let container = client.run(BitcoinCore::default().with_tag(&image.tag));
         let port = container.get_host_port(18443);
 
         let auth = container.image().auth();
@@ -60,7 +82,14 @@ impl<'c> Blockchain<'c> {
         let bitcoind_client = bitcoin::Client::new(self.node_url.clone());
 
         bitcoind_client
-            .send_to_address(&self.wallet_name, address.clone(), amount)
+            .send_to_address(
+                &self.wallet_name,
+                address.clone(),
+                amount,
+                false,
+                false,
+                bitcoin::fee::CONFIRMATION_TARGET,
+            )
             .await?;
 
         Ok(())
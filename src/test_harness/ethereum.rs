@@ -1,4 +1,7 @@
-use crate::ethereum::{self, ether, Address, ChainId};
+use crate::{
+    ethereum::{self, ether, Address, ChainId},
+    test_harness::env_var,
+};
 use anyhow::Context;
 use clarity::PrivateKey;
 use comit::{
@@ -23,6 +26,61 @@ pub const GETH_HOST_KEYSTORE_DIR: &str = "./.geth_datadir";
 pub const GETH_DEV_ACCOUNT_PRIVATE_KEY: &str =
     "0x0bad9cdf7205a60039d5034b38cdadbbfc5e4f1c7436da011dd7d8c7684bcb1c";
 
+/// The geth image run when `NECTAR_TEST_GETH_IMAGE` is not set.
+pub const DEFAULT_IMAGE: &str = "ethereum/client-go:v1.9.13";
+
+/// The Ethereum node the harness runs.
+///
+/// The node must expose the JSON-RPC API on port 8545, mine a block every
+/// second and unlock the dev account of `GETH_HOST_KEYSTORE_DIR`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    /// Name and tag of the docker image
+    pub name: String,
+    /// Replace the arguments of the default geth dev node
+    pub args: Vec<String>,
+    /// Log message on stderr after which the node is ready
+    pub ready_message: String,
+}
+
+impl Default for Image {
+    fn default() -> Self {
+        Image {
+            name: DEFAULT_IMAGE.to_owned(),
+            args: vec![
+                String::from("--dev"),
+                String::from("--dev.period=1"),
+                String::from("--networkid=1337"),
+                String::from("--rpc"),
+                String::from("--rpcaddr=0.0.0.0"),
+                String::from("--rpcport=8545"),
+                String::from("--verbosity=5"),
+                String::from("--keystore=.ethereum"),
+                String::from("--rpcapi=db,eth,net,web3,personal"),
+            ],
+            ready_message: String::from("mined potential block"),
+        }
+    }
+}
+
+impl Image {
+    /// `NECTAR_TEST_GETH_IMAGE`, `NECTAR_TEST_GETH_ARGS` (whitespace
+    /// separated) and `NECTAR_TEST_GETH_READY_MESSAGE` override the default
+    /// image.
+    pub fn from_env() -> Self {
+        let default = Image::default();
+
+        Image {
+            name: env_var("NECTAR_TEST_GETH_IMAGE").unwrap_or(default.name),
+            args: env_var("NECTAR_TEST_GETH_ARGS")
+                .map(|args| args.split_whitespace().map(String::from).collect())
+                .unwrap_or(default.args),
+            ready_message: env_var("NECTAR_TEST_GETH_READY_MESSAGE")
+                .unwrap_or(default.ready_message),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Blockchain<'c> {
     _container: Container<'c, clients::Cli, GenericImage>,
@@ -32,22 +90,16 @@ pub struct Blockchain<'c> {
 
 impl<'c> Blockchain<'c> {
     pub fn new(client: &'c clients::Cli) -> anyhow::Result<Self> {
-        let geth_image = GenericImage::new("ethereum/client-go:v1.9.13")
+        Self::with_image(client, Image::from_env())
+    }
+
+    pub fn with_image(client: &'c clients::Cli, image: Image) -> anyhow::Result<Self> {
+        let geth_image = GenericImage::new(&image.name)
             .with_wait_for(WaitFor::LogMessage {
-                message: String::from("mined potential block"),
+                message: image.ready_message,
                 stream: Stream::StdErr,
             })
-            .with_args(vec![
-                String::from("--dev"),
-                String::from("--dev.period=1"),
-                String::from("--networkid=1337"),
-                String::from("--rpc"),
-                String::from("--rpcaddr=0.0.0.0"),
-                String::from("--rpcport=8545"),
-                String::from("--verbosity=5"),
-                String::from("--keystore=.ethereum"),
-                String::from("--rpcapi=db,eth,net,web3,personal"),
-            ])
+            .with_args(image.args)
             .with_volume(
                 std::fs::canonicalize(GETH_HOST_KEYSTORE_DIR)?
                     .to_str()
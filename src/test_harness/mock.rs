@@ -0,0 +1,606 @@
+//! In-process ledgers and wallets to run the swap protocols without docker.
+//!
+//! A `Ledger` only moves forward when a block is mined, each block being
+//! `BLOCK_INTERVAL` seconds after the previous one, hence the ledger time
+//! and the content of the blocks are deterministic. Every action of a
+//! wallet is mined in its own block. The ledger is also the connector the
+//! protocols watch through, the counterparty's behaviour is scripted by
+//! executing its actions with another wallet on the same ledger, or not.
+
+use crate::swap::{hbit, herc20, LedgerTime};
+use ::bitcoin::{OutPoint, Transaction, TxIn, TxOut};
+use chrono::{DateTime, Utc};
+use comit::{ethereum, Secret, Timestamp};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// Seconds between two blocks.
+const BLOCK_INTERVAL: u32 = 10;
+
+/// How often the watchers look for new blocks.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(Clone, Debug)]
+pub struct Ledger<E> {
+    blocks: Arc<Mutex<Vec<Block<E>>>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Block<E> {
+    pub height: u32,
+    pub timestamp: Timestamp,
+    pub event: Option<E>,
+}
+
+#[derive(Clone, Debug)]
+pub enum BitcoinEvent {
+    Funded {
+        params: hbit::SharedParams,
+        funded: hbit::Funded,
+    },
+    Redeemed {
+        location: OutPoint,
+        redeemed: hbit::Redeemed,
+    },
+    Refunded {
+        location: OutPoint,
+        refunded: hbit::Refunded,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub enum EthereumEvent {
+    Deployed {
+        params: herc20::Params,
+        deployed: herc20::Deployed,
+    },
+    Funded {
+        location: ethereum::Address,
+        funded: herc20::Funded,
+    },
+    Redeemed {
+        location: ethereum::Address,
+        redeemed: herc20::Redeemed,
+    },
+    Refunded {
+        location: ethereum::Address,
+        refunded: herc20::Refunded,
+    },
+}
+
+impl<E> Ledger<E>
+where
+    E: Clone,
+{
+    pub fn new(genesis: Timestamp) -> Self {
+        Ledger {
+            blocks: Arc::new(Mutex::new(vec![Block {
+                height: 0,
+                timestamp: genesis,
+                event: None,
+            }])),
+        }
+    }
+
+    pub fn latest_block(&self) -> Block<E> {
+        self.blocks
+            .lock()
+            .unwrap()
+            .last()
+            .cloned()
+            .expect("the genesis block is never removed")
+    }
+
+    /// Mine a block holding the event built from the height of the block.
+    pub fn mine(&self, event: impl FnOnce(u32) -> E) -> E {
+        let mut blocks = self.blocks.lock().unwrap();
+        let (height, timestamp) = match blocks.last() {
+            Some(latest) => (latest.height + 1, latest.timestamp.plus(BLOCK_INTERVAL)),
+            None => unreachable!("the genesis block is never removed"),
+        };
+        let event = event(height);
+
+        blocks.push(Block {
+            height,
+            timestamp,
+            event: Some(event.clone()),
+        });
+
+        event
+    }
+
+    /// Mine empty blocks until the ledger time reaches `timestamp`, e.g. to
+    /// let an HTLC expire.
+    pub fn mine_until(&self, timestamp: Timestamp) {
+        let mut blocks = self.blocks.lock().unwrap();
+
+        while let Some(latest) = blocks.last().cloned() {
+            if latest.timestamp >= timestamp {
+                break;
+            }
+
+            blocks.push(Block {
+                height: latest.height + 1,
+                timestamp: latest.timestamp.plus(BLOCK_INTERVAL),
+                event: None,
+            });
+        }
+    }
+
+    pub fn events(&self) -> Vec<E> {
+        self.blocks
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|block| block.event.clone())
+            .collect()
+    }
+
+    /// Wait until `find` returns a result for the blocks mined so far.
+    async fn watch<T, F>(&self, find: F) -> anyhow::Result<T>
+    where
+        F: Fn(&[Block<E>]) -> Option<anyhow::Result<T>>,
+    {
+        loop {
+            let found = {
+                let blocks = self.blocks.lock().unwrap();
+                find(&blocks)
+            };
+            if let Some(result) = found {
+                return result;
+            }
+
+            tokio::time::delay_for(POLL_INTERVAL).await;
+        }
+    }
+
+    async fn wait_until(&self, timestamp: Timestamp) {
+        while self.latest_block().timestamp < timestamp {
+            tokio::time::delay_for(POLL_INTERVAL).await;
+        }
+    }
+}
+
+fn events<E>(blocks: &[Block<E>]) -> impl Iterator<Item = &E> {
+    blocks.iter().filter_map(|block| block.event.as_ref())
+}
+
+fn has_expired<E>(blocks: &[Block<E>], expiry: Timestamp) -> bool {
+    blocks
+        .last()
+        .map_or(false, |latest| latest.timestamp >= expiry)
+}
+
+#[async_trait::async_trait]
+impl<E> LedgerTime for Ledger<E>
+where
+    E: Clone + Send,
+{
+    async fn ledger_time(&self) -> anyhow::Result<Timestamp> {
+        Ok(self.latest_block().timestamp)
+    }
+}
+
+#[async_trait::async_trait]
+impl hbit::WatchForFunded for Ledger<BitcoinEvent> {
+    async fn watch_for_funded(
+        &self,
+        params: &hbit::SharedParams,
+        _utc_start_of_swap: DateTime<Utc>,
+    ) -> anyhow::Result<hbit::Funded> {
+        self.watch(|blocks| {
+            events(blocks).find_map(|event| match event {
+                BitcoinEvent::Funded {
+                    params: funded_params,
+                    funded,
+                } if funded_params == params => Some(Ok(*funded)),
+                _ => None,
+            })
+        })
+        .await
+    }
+}
+
+/// Unlike on bitcoind, watching stops with an error once the HTLC expired so
+/// a counterparty never redeeming can be scripted.
+#[async_trait::async_trait]
+impl hbit::WatchForRedeemed for Ledger<BitcoinEvent> {
+    async fn watch_for_redeemed(
+        &self,
+        params: &hbit::SharedParams,
+        location: OutPoint,
+        _utc_start_of_swap: DateTime<Utc>,
+    ) -> anyhow::Result<hbit::Redeemed> {
+        self.watch(|blocks| {
+            let redeemed = events(blocks).find_map(|event| match event {
+                BitcoinEvent::Redeemed {
+                    location: redeemed_location,
+                    redeemed,
+                } if *redeemed_location == location => Some(Ok(redeemed.clone())),
+                _ => None,
+            });
+
+            redeemed.or_else(|| {
+                if has_expired(blocks, params.expiry) {
+                    Some(Err(anyhow::anyhow!("Bitcoin HTLC expired")))
+                } else {
+                    None
+                }
+            })
+        })
+        .await
+    }
+}
+
+#[async_trait::async_trait]
+impl herc20::WatchForDeployed for Ledger<EthereumEvent> {
+    async fn watch_for_deployed(
+        &self,
+        params: herc20::Params,
+        _utc_start_of_swap: DateTime<Utc>,
+    ) -> anyhow::Result<herc20::Deployed> {
+        self.watch(|blocks| {
+            events(blocks).find_map(|event| match event {
+                EthereumEvent::Deployed {
+                    params: deployed_params,
+                    deployed,
+                } if *deployed_params == params => Some(Ok(deployed.clone())),
+                _ => None,
+            })
+        })
+        .await
+    }
+}
+
+#[async_trait::async_trait]
+impl herc20::WatchForFunded for Ledger<EthereumEvent> {
+    async fn watch_for_funded(
+        &self,
+        _params: herc20::Params,
+        _utc_start_of_swap: DateTime<Utc>,
+        deployed: herc20::Deployed,
+    ) -> anyhow::Result<herc20::Funded> {
+        self.watch(|blocks| {
+            events(blocks).find_map(|event| match event {
+                EthereumEvent::Funded { location, funded } if *location == deployed.location => {
+                    Some(Ok(funded.clone()))
+                }
+                _ => None,
+            })
+        })
+        .await
+    }
+}
+
+/// Unlike on geth, watching stops with an error once the HTLC expired so a
+/// counterparty never redeeming can be scripted.
+#[async_trait::async_trait]
+impl herc20::WatchForRedeemed for Ledger<EthereumEvent> {
+    async fn watch_for_redeemed(
+        &self,
+        _utc_start_of_swap: DateTime<Utc>,
+        deployed: herc20::Deployed,
+    ) -> anyhow::Result<herc20::Redeemed> {
+        self.watch(|blocks| {
+            let mut expiry = None;
+            for event in events(blocks) {
+                match event {
+                    EthereumEvent::Deployed {
+                        params,
+                        deployed: htlc,
+                    } if htlc.location == deployed.location => expiry = Some(params.expiry),
+                    EthereumEvent::Redeemed { location, redeemed }
+                        if *location == deployed.location =>
+                    {
+                        return Some(Ok(redeemed.clone()))
+                    }
+                    _ => {}
+                }
+            }
+
+            match expiry {
+                Some(expiry) if has_expired(blocks, expiry) => {
+                    Some(Err(anyhow::anyhow!("Ethereum HTLC expired")))
+                }
+                _ => None,
+            }
+        })
+        .await
+    }
+}
+
+/// Executes our actions, or the scripted counterparty's, on a Bitcoin
+/// `Ledger`.
+#[derive(Clone, Debug)]
+pub struct BitcoinWallet {
+    pub ledger: Ledger<BitcoinEvent>,
+}
+
+#[async_trait::async_trait]
+impl hbit::ExecuteFund for BitcoinWallet {
+    async fn execute_fund(&self, params: &hbit::Params) -> anyhow::Result<hbit::Funded> {
+        let event = self.ledger.mine(|height| {
+            let transaction =
+                bitcoin_transaction(height, OutPoint::default(), params.shared.asset.as_sat());
+
+            BitcoinEvent::Funded {
+                params: params.shared,
+                funded: hbit::Funded {
+                    asset: params.shared.asset,
+                    location: OutPoint {
+                        txid: transaction.txid(),
+                        vout: 0,
+                    },
+                },
+            }
+        });
+
+        match event {
+            BitcoinEvent::Funded { funded, .. } => Ok(funded),
+            _ => unreachable!("a funded event was mined"),
+        }
+    }
+}
+
+/// A `Ledger` mines our funding right away, its fee never needs bumping.
+#[async_trait::async_trait]
+impl hbit::BumpFee for BitcoinWallet {
+    async fn bump_fee(
+        &self,
+        _params: &hbit::Params,
+        _fund_event: hbit::Funded,
+        _utc_start_of_swap: DateTime<Utc>,
+    ) -> anyhow::Result<Option<hbit::Funded>> {
+        Ok(None)
+    }
+}
+
+#[async_trait::async_trait]
+impl hbit::ExecuteRedeem for BitcoinWallet {
+    async fn execute_redeem(
+        &self,
+        _params: hbit::Params,
+        fund_event: hbit::Funded,
+        secret: Secret,
+    ) -> anyhow::Result<hbit::Redeemed> {
+        let event = self.ledger.mine(|height| BitcoinEvent::Redeemed {
+            location: fund_event.location,
+            redeemed: hbit::Redeemed {
+                transaction: bitcoin_transaction(
+                    height,
+                    fund_event.location,
+                    fund_event.asset.as_sat(),
+                ),
+                secret,
+            },
+        });
+
+        match event {
+            BitcoinEvent::Redeemed { redeemed, .. } => Ok(redeemed),
+            _ => unreachable!("a redeemed event was mined"),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl hbit::ExecuteRefund for BitcoinWallet {
+    async fn execute_refund(
+        &self,
+        params: hbit::Params,
+        fund_event: hbit::Funded,
+    ) -> anyhow::Result<hbit::Refunded> {
+        self.ledger.wait_until(params.shared.expiry).await;
+
+        let event = self.ledger.mine(|height| BitcoinEvent::Refunded {
+            location: fund_event.location,
+            refunded: hbit::Refunded {
+                transaction: bitcoin_transaction(
+                    height,
+                    fund_event.location,
+                    fund_event.asset.as_sat(),
+                ),
+            },
+        });
+
+        match event {
+            BitcoinEvent::Refunded { refunded, .. } => Ok(refunded),
+            _ => unreachable!("a refunded event was mined"),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl LedgerTime for BitcoinWallet {
+    async fn ledger_time(&self) -> anyhow::Result<Timestamp> {
+        self.ledger.ledger_time().await
+    }
+}
+
+/// Executes our actions, or the scripted counterparty's, on an Ethereum
+/// `Ledger`.
+#[derive(Clone, Debug)]
+pub struct EthereumWallet {
+    pub ledger: Ledger<EthereumEvent>,
+}
+
+#[async_trait::async_trait]
+impl herc20::ExecuteDeploy for EthereumWallet {
+    async fn execute_deploy(&self, params: herc20::Params) -> anyhow::Result<herc20::Deployed> {
+        let event = self.ledger.mine(|height| EthereumEvent::Deployed {
+            params: params.clone(),
+            deployed: herc20::Deployed {
+                transaction: ethereum_transaction(height, None),
+                location: contract_address(height),
+            },
+        });
+
+        match event {
+            EthereumEvent::Deployed { deployed, .. } => Ok(deployed),
+            _ => unreachable!("a deployed event was mined"),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl herc20::ExecuteFund for EthereumWallet {
+    async fn execute_fund(
+        &self,
+        params: herc20::Params,
+        deploy_event: herc20::Deployed,
+        _utc_start_of_swap: DateTime<Utc>,
+    ) -> anyhow::Result<herc20::Funded> {
+        let event = self.ledger.mine(|height| EthereumEvent::Funded {
+            location: deploy_event.location,
+            funded: herc20::Funded {
+                transaction: ethereum_transaction(height, Some(params.asset.token_contract)),
+                asset: params.asset.clone(),
+            },
+        });
+
+        match event {
+            EthereumEvent::Funded { funded, .. } => Ok(funded),
+            _ => unreachable!("a funded event was mined"),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl herc20::ExecuteRedeem for EthereumWallet {
+    async fn execute_redeem(
+        &self,
+        _params: herc20::Params,
+        secret: Secret,
+        deploy_event: herc20::Deployed,
+        _utc_start_of_swap: DateTime<Utc>,
+    ) -> anyhow::Result<herc20::Redeemed> {
+        let event = self.ledger.mine(|height| EthereumEvent::Redeemed {
+            location: deploy_event.location,
+            redeemed: herc20::Redeemed {
+                transaction: ethereum_transaction(height, Some(deploy_event.location)),
+                secret,
+            },
+        });
+
+        match event {
+            EthereumEvent::Redeemed { redeemed, .. } => Ok(redeemed),
+            _ => unreachable!("a redeemed event was mined"),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl herc20::ExecuteRefund for EthereumWallet {
+    async fn execute_refund(
+        &self,
+        params: herc20::Params,
+        deploy_event: herc20::Deployed,
+        _utc_start_of_swap: DateTime<Utc>,
+    ) -> anyhow::Result<herc20::Refunded> {
+        self.ledger.wait_until(params.expiry).await;
+
+        let event = self.ledger.mine(|height| EthereumEvent::Refunded {
+            location: deploy_event.location,
+            refunded: herc20::Refunded {
+                transaction: ethereum_transaction(height, Some(deploy_event.location)),
+            },
+        });
+
+        match event {
+            EthereumEvent::Refunded { refunded, .. } => Ok(refunded),
+            _ => unreachable!("a refunded event was mined"),
+        }
+    }
+}
+
+/// A `Ledger` is never reorganised, a transaction mined in it is confirmed.
+#[async_trait::async_trait]
+impl herc20::WaitForConfirmations for EthereumWallet {
+    async fn wait_for_confirmations(
+        &self,
+        transaction: ethereum::Hash,
+    ) -> anyhow::Result<herc20::Inclusion> {
+        let mined = self.ledger.events().iter().any(|event| {
+            let mined = match event {
+                EthereumEvent::Deployed { deployed, .. } => &deployed.transaction,
+                EthereumEvent::Funded { funded, .. } => &funded.transaction,
+                EthereumEvent::Redeemed { redeemed, .. } => &redeemed.transaction,
+                EthereumEvent::Refunded { refunded, .. } => &refunded.transaction,
+            };
+            mined.hash == transaction
+        });
+
+        if mined {
+            Ok(herc20::Inclusion::Confirmed)
+        } else {
+            Ok(herc20::Inclusion::Orphaned)
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl herc20::WatchForDeployed for EthereumWallet {
+    async fn watch_for_deployed(
+        &self,
+        params: herc20::Params,
+        utc_start_of_swap: DateTime<Utc>,
+    ) -> anyhow::Result<herc20::Deployed> {
+        herc20::WatchForDeployed::watch_for_deployed(&self.ledger, params, utc_start_of_swap).await
+    }
+}
+
+#[async_trait::async_trait]
+impl herc20::WatchForFunded for EthereumWallet {
+    async fn watch_for_funded(
+        &self,
+        params: herc20::Params,
+        utc_start_of_swap: DateTime<Utc>,
+        deployed: herc20::Deployed,
+    ) -> anyhow::Result<herc20::Funded> {
+        herc20::WatchForFunded::watch_for_funded(&self.ledger, params, utc_start_of_swap, deployed)
+            .await
+    }
+}
+
+#[async_trait::async_trait]
+impl LedgerTime for EthereumWallet {
+    async fn ledger_time(&self) -> anyhow::Result<Timestamp> {
+        self.ledger.ledger_time().await
+    }
+}
+
+/// A transaction unique to the block it is mined in.
+fn bitcoin_transaction(height: u32, previous_output: OutPoint, value: u64) -> Transaction {
+    Transaction {
+        version: 2,
+        lock_time: height,
+        input: vec![TxIn {
+            previous_output,
+            script_sig: Default::default(),
+            sequence: 0xFFFF_FFFF,
+            witness: vec![],
+        }],
+        output: vec![TxOut {
+            value,
+            script_pubkey: Default::default(),
+        }],
+    }
+}
+
+/// A transaction unique to the block it is mined in.
+fn ethereum_transaction(height: u32, to: Option<ethereum::Address>) -> ethereum::Transaction {
+    ethereum::Transaction {
+        hash: ethereum::Hash::from_low_u64_be(height.into()),
+        to,
+        input: height.to_be_bytes().to_vec(),
+        ..Default::default()
+    }
+}
+
+fn contract_address(height: u32) -> ethereum::Address {
+    let mut bytes = [0u8; 20];
+    bytes[16..].copy_from_slice(&height.to_be_bytes());
+
+    ethereum::Address::from(bytes)
+}
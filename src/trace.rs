@@ -1,24 +1,92 @@
+use crate::{
+    config::{file::Format, ErrorReporting, Telemetry},
+    error_report::{self, ErrorReportLayer},
+};
 use log::LevelFilter;
 use tracing::{info, subscriber, Level};
 use tracing_log::LogTracer;
-use tracing_subscriber::FmtSubscriber;
+use tracing_subscriber::{layer::SubscriberExt, FmtSubscriber};
 
-pub fn init_tracing(level: log::LevelFilter) -> anyhow::Result<()> {
+/// Keeps the OpenTelemetry pipeline alive, pending spans are exported when it
+/// is dropped.
+#[derive(Default)]
+#[allow(missing_debug_implementations, missing_copy_implementations)]
+pub struct TracingGuard {
+    #[cfg(feature = "otlp")]
+    _otlp: Option<opentelemetry_otlp::Uninstall>,
+}
+
+pub fn init_tracing(
+    level: log::LevelFilter,
+    format: Format,
+    telemetry: Option<&Telemetry>,
+    error_reporting: Option<&ErrorReporting>,
+) -> anyhow::Result<TracingGuard> {
     if level == LevelFilter::Off {
-        return Ok(());
+        return Ok(TracingGuard::default());
     }
 
     // We want upstream library log messages, just only at Info level.
     LogTracer::init_with_filter(LevelFilter::Info)?;
 
-    let subscriber = FmtSubscriber::builder()
-        .with_max_level(level_from_level_filter(level))
-        .finish();
+    let builder = FmtSubscriber::builder().with_max_level(level_from_level_filter(level));
 
-    subscriber::set_global_default(subscriber)?;
+    // Swap related events are emitted within spans carrying the `swap_id`,
+    // `peer_id`, `chain` and `action` fields, the JSON output includes them.
+    let error_report = ErrorReportLayer::new(error_reporting);
+    let guard = match (format, telemetry) {
+        (Format::Text, None) => {
+            subscriber::set_global_default(builder.finish().with(error_report))?;
+            TracingGuard::default()
+        }
+        (Format::Json, None) => {
+            subscriber::set_global_default(builder.json().finish().with(error_report))?;
+            TracingGuard::default()
+        }
+        (format, Some(telemetry)) => init_otlp(builder, format, telemetry, error_report)?,
+    };
+    if error_reporting.is_some() {
+        error_report::install_panic_hook();
+    }
     info!("Initialized tracing with level: {}", level);
 
-    Ok(())
+    Ok(guard)
+}
+
+#[cfg(feature = "otlp")]
+fn init_otlp(
+    builder: tracing_subscriber::fmt::SubscriberBuilder,
+    format: Format,
+    telemetry: &Telemetry,
+    error_report: ErrorReportLayer,
+) -> anyhow::Result<TracingGuard> {
+    let (tracer, uninstall) = opentelemetry_otlp::new_pipeline()
+        .with_endpoint(&telemetry.otlp_endpoint)
+        .install()?;
+    let otlp = tracing_opentelemetry::layer().with_tracer(tracer);
+
+    match format {
+        Format::Text => {
+            subscriber::set_global_default(builder.finish().with(otlp).with(error_report))?
+        }
+        Format::Json => {
+            subscriber::set_global_default(builder.json().finish().with(otlp).with(error_report))?
+        }
+    }
+
+    Ok(TracingGuard {
+        _otlp: Some(uninstall),
+    })
+}
+
+#[cfg(not(feature = "otlp"))]
+fn init_otlp(
+    _: tracing_subscriber::fmt::SubscriberBuilder,
+    _: Format,
+    _: &Telemetry,
+    _: ErrorReportLayer,
+) -> anyhow::Result<TracingGuard> {
+    anyhow::bail!("OTLP export is configured but nectar was built without the `otlp` feature")
 }
 
 fn level_from_level_filter(level: LevelFilter) -> Level {
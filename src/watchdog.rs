@@ -0,0 +1,104 @@
+//! Detect when the trade loop stops processing events.
+//!
+//! The trade loop ticks a heartbeat after each event, rate and balance
+//! updates alone are received every 15 seconds. The watchdog runs on its own
+//! thread so that it keeps checking if a handler blocks the runtime.
+
+use crate::{
+    alert::{Alert, Alerter},
+    config,
+};
+use std::{
+    sync::{Arc, Mutex, PoisonError},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How long to give the alert to be sent before exiting on stall.
+const EXIT_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Debug)]
+pub struct Heartbeat(Arc<Mutex<Instant>>);
+
+impl Heartbeat {
+    fn new() -> Self {
+        Heartbeat(Arc::new(Mutex::new(Instant::now())))
+    }
+
+    pub fn tick(&self) {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner) = Instant::now();
+    }
+
+    fn elapsed(&self) -> Duration {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .elapsed()
+    }
+}
+
+/// Start the watchdog, the trade loop must tick the returned heartbeat.
+///
+/// Must be called from within the runtime, alerts are sent on it.
+pub fn spawn(config: config::Watchdog, alerter: Alerter) -> anyhow::Result<Heartbeat> {
+    let heartbeat = Heartbeat::new();
+    let runtime = tokio::runtime::Handle::try_current()?;
+    let stall_timeout = Duration::from_secs(config.stall_timeout_secs);
+
+    let watched = heartbeat.clone();
+    thread::Builder::new()
+        .name("watchdog".to_owned())
+        .spawn(move || {
+            let mut stalled = false;
+
+            loop {
+                thread::sleep(stall_timeout / 4);
+
+                let elapsed = watched.elapsed();
+                match (elapsed >= stall_timeout, stalled) {
+                    (true, false) => {
+                        stalled = true;
+                        // Error events are reported on the runtime as well
+                        runtime.enter(|| {
+                            tracing::error!(
+                                "Trade loop has not processed any event for {} seconds",
+                                elapsed.as_secs()
+                            );
+                            alerter.notify(Alert::MainLoopStalled {
+                                stalled_for: elapsed,
+                            });
+                        });
+
+                        if config.exit_on_stall {
+                            thread::sleep(EXIT_GRACE_PERIOD);
+                            eprintln!("Exiting as the trade loop is stalled");
+                            std::process::exit(1);
+                        }
+                    }
+                    (false, true) => {
+                        stalled = false;
+                        tracing::info!("Trade loop is processing events again");
+                    }
+                    _ => (),
+                }
+            }
+        })?;
+
+    Ok(heartbeat)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tick_resets_the_elapsed_time() {
+        let heartbeat = Heartbeat::new();
+        thread::sleep(Duration::from_millis(50));
+        assert!(heartbeat.elapsed() >= Duration::from_millis(50));
+
+        heartbeat.clone().tick();
+
+        assert!(heartbeat.elapsed() < Duration::from_millis(50));
+    }
+}